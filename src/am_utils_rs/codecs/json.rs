@@ -376,14 +376,26 @@ pub fn hydrate_value<'a, D: ReadDoc>(
     obj: &ObjId,
     prop: autosurgeon::Prop<'a>,
 ) -> Result<serde_json::Value, HydrateError> {
-    let value = match doc.get(obj, &prop)? {
-        Some((automerge::Value::Scalar(scalar), _)) => ThroughJson::hydrate_scalar(scalar),
-        Some((automerge::Value::Object(obj_type), obj)) => match obj_type {
-            ObjType::Map | ObjType::Table => ThroughJson::hydrate_map(doc, &obj),
-            ObjType::List => ThroughJson::hydrate_seq(doc, &obj),
-            ObjType::Text => ThroughJson::hydrate_text(doc, &obj),
+    match doc.get(obj, &prop)? {
+        Some((value, id)) => hydrate_op_value(doc, &value, &id),
+        None => Ok(ThroughJson::hydrate_none()?.0),
+    }
+}
+
+/// Hydrate a value as returned by [`ReadDoc::get_all`], useful for reading
+/// the values that lost a conflict.
+pub fn hydrate_op_value<D: ReadDoc>(
+    doc: &D,
+    value: &automerge::Value<'_>,
+    id: &ObjId,
+) -> Result<serde_json::Value, HydrateError> {
+    let value = match value {
+        automerge::Value::Scalar(scalar) => ThroughJson::hydrate_scalar(scalar.clone()),
+        automerge::Value::Object(obj_type) => match obj_type {
+            ObjType::Map | ObjType::Table => ThroughJson::hydrate_map(doc, id),
+            ObjType::List => ThroughJson::hydrate_seq(doc, id),
+            ObjType::Text => ThroughJson::hydrate_text(doc, id),
         },
-        None => ThroughJson::hydrate_none(),
     }?;
     Ok(value.0)
 }
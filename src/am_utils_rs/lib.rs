@@ -33,6 +33,113 @@ pub fn serialize_commit_heads(heads: &[ChangeHash]) -> Vec<String> {
         .collect()
 }
 
+/// Copies the state of `doc` at `heads` into a fresh doc that has it in a
+/// single change, none of the history leading there comes along. The
+/// copy can't be merged back into `doc`, it's only good for reading.
+pub fn snapshot_at(doc: &automerge::Automerge, heads: &[ChangeHash]) -> Res<automerge::Automerge> {
+    let mut out = automerge::Automerge::new();
+    let mut tx = out.transaction();
+    copy_obj_at(
+        doc,
+        &automerge::ROOT,
+        automerge::ObjType::Map,
+        heads,
+        &mut tx,
+        &automerge::ROOT,
+    )?;
+    tx.commit();
+    Ok(out)
+}
+
+fn copy_obj_at(
+    src: &automerge::Automerge,
+    src_obj: &automerge::ObjId,
+    obj_type: automerge::ObjType,
+    heads: &[ChangeHash],
+    tx: &mut impl automerge::transaction::Transactable,
+    dst_obj: &automerge::ObjId,
+) -> Res<()> {
+    use automerge::{ObjType, ReadDoc, Value};
+    match obj_type {
+        ObjType::Map | ObjType::Table => {
+            let keys = src
+                .map_range_at(src_obj, .., heads)
+                .map(|item| item.key.to_string())
+                .collect::<Vec<_>>();
+            for key in keys {
+                match src.get_at(src_obj, key.as_str(), heads)? {
+                    Some((Value::Object(child_type), child)) => {
+                        let dst_child = tx.put_object(dst_obj, key.as_str(), child_type)?;
+                        copy_obj_at(src, &child, child_type, heads, tx, &dst_child)?;
+                    }
+                    Some((Value::Scalar(scalar), _)) => {
+                        tx.put(dst_obj, key.as_str(), scalar.into_owned())?;
+                    }
+                    None => {}
+                }
+            }
+        }
+        ObjType::List => {
+            for ii in 0..src.length_at(src_obj, heads) {
+                match src.get_at(src_obj, ii, heads)? {
+                    Some((Value::Object(child_type), child)) => {
+                        let dst_child = tx.insert_object(dst_obj, ii, child_type)?;
+                        copy_obj_at(src, &child, child_type, heads, tx, &dst_child)?;
+                    }
+                    Some((Value::Scalar(scalar), _)) => {
+                        tx.insert(dst_obj, ii, scalar.into_owned())?;
+                    }
+                    None => {}
+                }
+            }
+        }
+        ObjType::Text => {
+            let text = src.text_at(src_obj, heads)?;
+            tx.splice_text(dst_obj, 0, 0, &text)?;
+        }
+    }
+    Ok(())
+}
+
+#[test]
+fn snapshot_at_copies_state_without_history() -> Res<()> {
+    use automerge::transaction::Transactable;
+    use automerge::ReadDoc;
+
+    let mut doc = automerge::Automerge::new();
+    let mut tx = doc.transaction();
+    let map = tx.put_object(automerge::ROOT, "map", automerge::ObjType::Map)?;
+    tx.put(&map, "count", automerge::ScalarValue::counter(1))?;
+    let list = tx.put_object(&map, "list", automerge::ObjType::List)?;
+    tx.insert(&list, 0, "first")?;
+    let nested = tx.insert_object(&list, 1, automerge::ObjType::Map)?;
+    tx.put(&nested, "flag", true)?;
+    let text = tx.put_object(automerge::ROOT, "text", automerge::ObjType::Text)?;
+    tx.splice_text(&text, 0, 0, "hello")?;
+    tx.commit();
+    let heads = doc.get_heads();
+
+    let mut tx = doc.transaction();
+    tx.increment(&map, "count", 2)?;
+    tx.delete(&list, 0)?;
+    tx.splice_text(&text, 5, 0, " world")?;
+    tx.commit();
+
+    let old = snapshot_at(&doc, &heads)?;
+    assert_eq!(old.get_changes(&[]).len(), 1);
+    assert_eq!(
+        old.hydrate(automerge::ROOT, None)?,
+        doc.hydrate(automerge::ROOT, Some(&heads))?
+    );
+
+    let latest = snapshot_at(&doc, &doc.get_heads())?;
+    assert_eq!(
+        latest.hydrate(automerge::ROOT, None)?,
+        doc.hydrate(automerge::ROOT, None)?
+    );
+    Ok(())
+}
+
 #[test]
 fn play() -> Res<()> {
     use automerge::transaction::Transactable;
@@ -0,0 +1,173 @@
+//! Per-peer access checks shared by the repo rpc and the subduction sessions.
+//!
+//! A live subduction session lets the peer ask for any sedimentree it can
+//! name and push commits for any of them so the session is run with
+//! [`SessionPolicy`] which applies the same [`PartAccessPolicy`] the rpc
+//! does and refuses pushes from read-only peers.
+
+use crate::interlude::*;
+
+use big_sync::rpc::PartAccessPolicy;
+use future_form::{FutureForm, Sendable};
+use futures::future::BoxFuture;
+use sedimentree_core::id::SedimentreeId;
+
+use crate::{DocumentId, SharedPartStore};
+
+pub(crate) struct PeerAccess {
+    big_sync_store: SharedPartStore,
+    part_access: std::sync::RwLock<Option<Arc<dyn PartAccessPolicy>>>,
+    read_only_peers: std::sync::RwLock<HashSet<PeerId>>,
+}
+
+impl PeerAccess {
+    pub fn new(big_sync_store: SharedPartStore) -> Self {
+        Self {
+            big_sync_store,
+            part_access: default(),
+            read_only_peers: default(),
+        }
+    }
+
+    pub fn set_part_access(&self, policy: Option<Arc<dyn PartAccessPolicy>>) {
+        *self.part_access.write().expect(ERROR_MUTEX) = policy;
+    }
+
+    pub fn set_read_only_peers(&self, peers: HashSet<PeerId>) {
+        *self.read_only_peers.write().expect(ERROR_MUTEX) = peers;
+    }
+
+    pub fn can_write(&self, peer_id: PeerId) -> bool {
+        !self
+            .read_only_peers
+            .read()
+            .expect(ERROR_MUTEX)
+            .contains(&peer_id)
+    }
+
+    /// A doc is readable if the peer can read at least one of the
+    /// partitions it's a member of.
+    pub async fn can_read_doc(&self, peer_id: PeerId, doc_id: DocumentId) -> Res<bool> {
+        let Some(policy) = self.part_access.read().expect(ERROR_MUTEX).clone() else {
+            return Ok(true);
+        };
+        let part_ids = self.big_sync_store.obj_parts(doc_id).await?;
+        if part_ids.is_empty() {
+            return Ok(policy.can_read_unpartitioned(peer_id));
+        }
+        Ok(part_ids
+            .into_iter()
+            .any(|part_id| policy.can_read(peer_id, part_id)))
+    }
+}
+
+#[derive(Debug, thiserror::Error, displaydoc::Display)]
+pub enum SessionAccessError {
+    /// peer {peer_id} can't read doc {doc_id}
+    ReadDenied { peer_id: PeerId, doc_id: DocumentId },
+    /// peer {peer_id} is read-only
+    WriteDenied { peer_id: PeerId },
+}
+
+/// The subduction policy for the live sessions.
+#[derive(Clone, educe::Educe)]
+#[educe(Debug)]
+pub(crate) struct SessionPolicy {
+    pub local_peer_id: PeerId,
+    #[educe(Debug(ignore))]
+    pub access: Arc<PeerAccess>,
+}
+
+impl SessionPolicy {
+    async fn can_read(&self, peer_id: PeerId, sedimentree_id: SedimentreeId) -> bool {
+        if peer_id == self.local_peer_id {
+            return true;
+        }
+        let doc_id = DocumentId::new(*sedimentree_id.as_bytes());
+        match self.access.can_read_doc(peer_id, doc_id).await {
+            Ok(readable) => readable,
+            Err(err) => {
+                warn!(?err, %doc_id, %peer_id, "error checking doc access, denying");
+                false
+            }
+        }
+    }
+}
+
+fn peer_id_from_subduction(peer: subduction_core::peer::id::PeerId) -> PeerId {
+    PeerId::new(*peer.as_bytes())
+}
+
+impl subduction_core::policy::ConnectionPolicy<Sendable> for SessionPolicy {
+    type ConnectionDisallowed = std::convert::Infallible;
+
+    // who gets to connect at all is decided by the endpoint's allow-list
+    fn authorize_connect(
+        &self,
+        _peer: subduction_core::peer::id::PeerId,
+    ) -> BoxFuture<'_, Result<(), Self::ConnectionDisallowed>> {
+        Sendable::from_future(async { Ok(()) })
+    }
+}
+
+impl subduction_core::policy::StoragePolicy<Sendable> for SessionPolicy {
+    type FetchDisallowed = SessionAccessError;
+    type PutDisallowed = SessionAccessError;
+
+    fn authorize_fetch(
+        &self,
+        peer: subduction_core::peer::id::PeerId,
+        sedimentree_id: SedimentreeId,
+    ) -> BoxFuture<'_, Result<(), Self::FetchDisallowed>> {
+        Sendable::from_future(async move {
+            let peer_id = peer_id_from_subduction(peer);
+            if self.can_read(peer_id, sedimentree_id).await {
+                Ok(())
+            } else {
+                Err(SessionAccessError::ReadDenied {
+                    peer_id,
+                    doc_id: DocumentId::new(*sedimentree_id.as_bytes()),
+                })
+            }
+        })
+    }
+
+    fn authorize_put(
+        &self,
+        requestor: subduction_core::peer::id::PeerId,
+        _author: subduction_core::peer::id::PeerId,
+        sedimentree_id: SedimentreeId,
+    ) -> BoxFuture<'_, Result<(), Self::PutDisallowed>> {
+        Sendable::from_future(async move {
+            let peer_id = peer_id_from_subduction(requestor);
+            if !self.access.can_write(peer_id) {
+                return Err(SessionAccessError::WriteDenied { peer_id });
+            }
+            // pushing to a doc implies seeing it acknowledged
+            if !self.can_read(peer_id, sedimentree_id).await {
+                return Err(SessionAccessError::ReadDenied {
+                    peer_id,
+                    doc_id: DocumentId::new(*sedimentree_id.as_bytes()),
+                });
+            }
+            Ok(())
+        })
+    }
+
+    fn filter_authorized_fetch(
+        &self,
+        peer: subduction_core::peer::id::PeerId,
+        ids: Vec<SedimentreeId>,
+    ) -> BoxFuture<'_, Vec<SedimentreeId>> {
+        Sendable::from_future(async move {
+            let peer_id = peer_id_from_subduction(peer);
+            let mut out = Vec::with_capacity(ids.len());
+            for sedimentree_id in ids {
+                if self.can_read(peer_id, sedimentree_id).await {
+                    out.push(sedimentree_id);
+                }
+            }
+            out
+        })
+    }
+}
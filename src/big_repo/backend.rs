@@ -2,12 +2,94 @@ use crate::interlude::*;
 
 use crate::rpc::FullDoc;
 
+/// Decides whether a doc seen for the first time from a peer gets
+/// materialized locally. Docs that are already local always keep syncing.
+/// Rejected docs are reconsidered when their heads move or, through
+/// [`BigRepoSyncBackend::readmit_rejected`], when the filter changes.
+pub trait DocAdmissionFilter: Send + Sync + 'static {
+    fn admit(&self, doc_id: &crate::DocumentId, doc: &automerge::Automerge) -> bool;
+}
+
+/// Pulls docs that aren't available locally from peers.
+#[async_trait::async_trait]
+pub trait DocFetcher: Send + Sync + 'static {
+    /// Returns `None` if no peer could serve the doc.
+    async fn fetch(&self, doc_id: &crate::DocumentId) -> Res<Option<automerge::Automerge>>;
+    /// The doc's state at the peer's heads without its history. Returns
+    /// `None` if no peer could serve the doc.
+    async fn fetch_snapshot(&self, doc_id: &crate::DocumentId) -> Res<Option<crate::DocSnapshot>>;
+}
+
 #[derive(Clone)]
 pub struct BigRepoSyncBackend {
     repo: std::sync::Weak<crate::BigRepo>,
     repo_rpc_endpoint: iroh::Endpoint,
     remote_repo_clients:
-        Arc<surelock::mutex::Mutex<std::collections::HashMap<PeerId, Arc<RepoRpcClient>>>>,
+        Arc<surelock::mutex::Mutex<std::collections::HashMap<PeerId, Arc<RemoteDocSource>>>>,
+    admission_filter: Arc<surelock::mutex::Mutex<Option<Arc<dyn DocAdmissionFilter>>>>,
+    /// Docs the admission filter turned away.
+    rejected_docs:
+        Arc<surelock::mutex::Mutex<std::collections::HashMap<crate::DocumentId, RejectedDoc>>>,
+    /// Peers whose changes are never pulled in.
+    read_only_peers: Arc<surelock::mutex::Mutex<std::collections::HashSet<PeerId>>>,
+}
+
+struct RejectedDoc {
+    /// Where the doc was seen, readmission pulls it from there.
+    peer_id: PeerId,
+    /// The heads the filter saw.
+    heads: Arc<[automerge::ChangeHash]>,
+}
+
+enum RemoteDocSource {
+    Rpc(RepoRpcClient),
+    /// Saved docs of an offline peer read from a sync bundle.
+    Bundle(std::collections::HashMap<crate::DocumentId, Vec<u8>>),
+}
+
+impl RemoteDocSource {
+    async fn get_doc_save(&self, doc_id: crate::DocumentId) -> Res<Vec<u8>> {
+        match self {
+            Self::Rpc(client) => {
+                let doc_id = doc_id.to_string();
+                let Some(full_doc) = client
+                    .get_docs_full(vec![doc_id.clone()])
+                    .await?
+                    .into_iter()
+                    .find(|doc| doc.doc_id == doc_id)
+                else {
+                    eyre::bail!("missing on remote");
+                };
+                Ok(full_doc.automerge_save)
+            }
+            Self::Bundle(docs) => docs
+                .get(&doc_id)
+                .cloned()
+                .ok_or_else(|| ferr!("missing from bundle")),
+        }
+    }
+
+    async fn get_doc_snapshot(&self, doc_id: crate::DocumentId) -> Res<crate::DocSnapshot> {
+        if let Self::Rpc(client) = self {
+            let doc_id = doc_id.to_string();
+            match client.get_docs_snapshot(vec![doc_id.clone()]).await {
+                Ok(docs) => {
+                    let Some(doc) = docs.into_iter().find(|doc| doc.doc_id == doc_id) else {
+                        eyre::bail!("missing on remote");
+                    };
+                    return crate::DocSnapshot::from_wire(doc);
+                }
+                Err(err) => {
+                    // peers from before the snapshot rpc, compact it here
+                    debug!(%doc_id, ?err, "GetDocsSnapshot failed, falling back to the full doc");
+                }
+            }
+        }
+        let automerge_save = self.get_doc_save(doc_id).await?;
+        let loaded = automerge::Automerge::load(&automerge_save)
+            .wrap_err("invalid automerge payload from GetDocsFull")?;
+        crate::DocSnapshot::of(&loaded)
+    }
 }
 
 #[derive(Clone)]
@@ -39,6 +121,22 @@ impl RepoRpcClient {
             .wrap_err("GetDocsFull rejected")?;
         Ok(response.docs)
     }
+
+    async fn get_docs_snapshot(&self, doc_ids: Vec<String>) -> Res<Vec<crate::rpc::SnapshotDoc>> {
+        let client = irpc_iroh::client::<crate::rpc::RepoSyncRpc>(
+            self.endpoint.clone(),
+            self.endpoint_addr.clone(),
+            crate::rpc::REPO_SYNC_ALPN,
+        );
+        let response = client
+            .rpc(crate::rpc::GetDocsSnapshotRpcReq {
+                req: crate::rpc::GetDocsSnapshotRequest { doc_ids },
+            })
+            .await
+            .wrap_err("GetDocsSnapshot rpc failure")?
+            .wrap_err("GetDocsSnapshot rejected")?;
+        Ok(response.docs)
+    }
 }
 
 impl BigRepoSyncBackend {
@@ -50,6 +148,126 @@ impl BigRepoSyncBackend {
             repo,
             repo_rpc_endpoint: endpoint,
             remote_repo_clients: surelock::mutex::Mutex::new(default()).into(),
+            admission_filter: surelock::mutex::Mutex::new(None).into(),
+            rejected_docs: surelock::mutex::Mutex::new(default()).into(),
+            read_only_peers: surelock::mutex::Mutex::new(default()).into(),
+        })
+    }
+
+    pub fn set_admission_filter(&self, filter: Option<Arc<dyn DocAdmissionFilter>>) {
+        surelock::key::lock_scope(|key| {
+            let (mut admission_filter, _key) = key.lock(&self.admission_filter);
+            *admission_filter = filter;
+        })
+    }
+
+    fn admission_filter(&self) -> Option<Arc<dyn DocAdmissionFilter>> {
+        surelock::key::lock_scope(|key| {
+            let (admission_filter, _key) = key.lock(&self.admission_filter);
+            admission_filter.clone()
+        })
+    }
+
+    /// Runs the admission filter over a snapshot of the doc, recording the
+    /// doc if it's rejected.
+    fn admit(
+        &self,
+        peer_id: PeerId,
+        doc_id: crate::DocumentId,
+        snapshot: &crate::DocSnapshot,
+    ) -> bool {
+        let admitted = self
+            .admission_filter()
+            .is_none_or(|filter| filter.admit(&doc_id, &snapshot.doc));
+        surelock::key::lock_scope(|key| {
+            let (mut rejected_docs, _key) = key.lock(&self.rejected_docs);
+            if admitted {
+                rejected_docs.remove(&doc_id);
+            } else {
+                rejected_docs.insert(
+                    doc_id,
+                    RejectedDoc {
+                        peer_id,
+                        heads: Arc::clone(&snapshot.heads),
+                    },
+                );
+            }
+        });
+        admitted
+    }
+
+    /// Whether the doc was rejected at exactly these heads.
+    fn rejected_at(&self, doc_id: &crate::DocumentId, heads: &[automerge::ChangeHash]) -> bool {
+        surelock::key::lock_scope(|key| {
+            let (rejected_docs, _key) = key.lock(&self.rejected_docs);
+            rejected_docs
+                .get(doc_id)
+                .is_some_and(|rejected| rejected.heads.as_ref() == heads)
+        })
+    }
+
+    /// Re-runs admission over the docs the filter rejected so far and
+    /// materializes the ones the current filter admits. Meant to be called
+    /// after [`Self::set_admission_filter`]. Docs whose peer is gone stay
+    /// rejected until they're seen again.
+    pub async fn readmit_rejected(&self) -> Res<Vec<crate::DocumentId>> {
+        let repo: Arc<crate::BigRepo> = self
+            .repo
+            .upgrade()
+            .ok_or_else(|| eyre::eyre!("big repo dropped while sync backend was active"))?;
+        let rejected = surelock::key::lock_scope(|key| {
+            let (rejected_docs, _key) = key.lock(&self.rejected_docs);
+            rejected_docs
+                .iter()
+                .map(|(doc_id, rejected)| (*doc_id, rejected.peer_id))
+                .collect::<Vec<_>>()
+        });
+        let mut admitted = vec![];
+        for (doc_id, peer_id) in rejected {
+            let client = match self.remote_repo_client(peer_id) {
+                Ok(client) => client,
+                Err(err) => {
+                    debug!(%doc_id, %peer_id, ?err, "peer of rejected doc gone, skipping");
+                    continue;
+                }
+            };
+            let snapshot = match client.get_doc_snapshot(doc_id).await {
+                Ok(snapshot) => snapshot,
+                Err(err) => {
+                    debug!(%doc_id, %peer_id, ?err, "rejected doc unavailable, skipping");
+                    continue;
+                }
+            };
+            if !self.admit(peer_id, doc_id, &snapshot) {
+                continue;
+            }
+            let automerge_save = client.get_doc_save(doc_id).await?;
+            let doc = automerge::Automerge::load(&automerge_save)
+                .wrap_err("invalid automerge payload from GetDocsFull")?;
+            match repo.put_doc(doc_id, doc).await {
+                // synced in some other way meanwhile
+                Ok(_) | Err(crate::runtime::PutDocError::IdOccpuied { .. }) => {}
+                Err(err) => return Err(err).wrap_err("put_doc failed"),
+            }
+            debug!(%doc_id, "readmitted doc");
+            admitted.push(doc_id);
+        }
+        Ok(admitted)
+    }
+
+    /// Replaces the set of peers whose changes are rejected. Syncing an
+    /// object with a read-only peer completes without touching local state.
+    pub fn set_read_only_peers(&self, peers: std::collections::HashSet<PeerId>) {
+        surelock::key::lock_scope(|key| {
+            let (mut read_only_peers, _key) = key.lock(&self.read_only_peers);
+            *read_only_peers = peers;
+        })
+    }
+
+    fn is_read_only_peer(&self, peer_id: &PeerId) -> bool {
+        surelock::key::lock_scope(|key| {
+            let (read_only_peers, _key) = key.lock(&self.read_only_peers);
+            read_only_peers.contains(peer_id)
         })
     }
 
@@ -59,18 +277,34 @@ impl BigRepoSyncBackend {
             if endpoint_addr.addrs.is_empty()
                 && remote_repo_clients
                     .get(&peer_id)
-                    .is_some_and(|existing| !existing.endpoint_addr.addrs.is_empty())
+                    .is_some_and(|existing| match &**existing {
+                        RemoteDocSource::Rpc(client) => !client.endpoint_addr.addrs.is_empty(),
+                        RemoteDocSource::Bundle(_) => false,
+                    })
             {
                 return;
             }
-            let client = Arc::new(RepoRpcClient::new(
+            let client = Arc::new(RemoteDocSource::Rpc(RepoRpcClient::new(
                 self.repo_rpc_endpoint.clone(),
                 endpoint_addr,
-            ));
+            )));
             remote_repo_clients.insert(peer_id, client);
         })
     }
 
+    /// Serves docs for `peer_id` from the saves of a sync bundle instead of
+    /// over the network. Unregister with [`Self::unregister_remote_peer`].
+    pub fn register_bundle_peer(
+        &self,
+        peer_id: PeerId,
+        docs: std::collections::HashMap<crate::DocumentId, Vec<u8>>,
+    ) {
+        surelock::key::lock_scope(|key| {
+            let (mut remote_repo_clients, _key) = key.lock(&self.remote_repo_clients);
+            remote_repo_clients.insert(peer_id, Arc::new(RemoteDocSource::Bundle(docs)));
+        })
+    }
+
     pub fn unregister_remote_peer(&self, peer_id: PeerId) {
         surelock::key::lock_scope(|key| {
             let (mut remote_repo_clients, _key) = key.lock(&self.remote_repo_clients);
@@ -78,7 +312,7 @@ impl BigRepoSyncBackend {
         })
     }
 
-    fn remote_repo_client(&self, peer_id: PeerId) -> Res<Arc<RepoRpcClient>> {
+    fn remote_repo_client(&self, peer_id: PeerId) -> Res<Arc<RemoteDocSource>> {
         surelock::key::lock_scope(|key| {
             let (remote_repo_clients, _key) = key.lock(&self.remote_repo_clients);
             remote_repo_clients
@@ -89,6 +323,90 @@ impl BigRepoSyncBackend {
     }
 }
 
+impl BigRepoSyncBackend {
+    /// Merges the bundled save into the local doc. Returns false if the
+    /// bundle had nothing new.
+    async fn merge_bundle_doc(
+        &self,
+        repo: &Arc<crate::BigRepo>,
+        peer_id: PeerId,
+        obj_id: crate::DocumentId,
+        docs: &std::collections::HashMap<crate::DocumentId, Vec<u8>>,
+    ) -> Res<bool> {
+        let automerge_save = docs
+            .get(&obj_id)
+            .ok_or_else(|| ferr!("missing from bundle"))?;
+        let mut loaded = automerge::Automerge::load(automerge_save)
+            .wrap_err("invalid automerge payload in bundle")?;
+        let handle = repo
+            .get_doc(&obj_id)
+            .await?
+            .ok_or_else(|| eyre::eyre!("local doc missing while merging bundle"))?;
+        let before_heads = handle.with_document_read(|doc| doc.get_heads()).await;
+        let after_heads = handle
+            .with_document_with_origin(
+                |doc| {
+                    doc.merge(&mut loaded)?;
+                    eyre::Ok(doc.get_heads())
+                },
+                crate::BigRepoChangeOrigin::Remote { peer_id },
+            )
+            .await??;
+        Ok(before_heads != after_heads)
+    }
+}
+
+#[async_trait::async_trait]
+impl DocFetcher for BigRepoSyncBackend {
+    async fn fetch(&self, doc_id: &crate::DocumentId) -> Res<Option<automerge::Automerge>> {
+        let sources = surelock::key::lock_scope(|key| {
+            let (remote_repo_clients, _key) = key.lock(&self.remote_repo_clients);
+            remote_repo_clients
+                .iter()
+                .map(|(peer_id, source)| (*peer_id, Arc::clone(source)))
+                .collect::<Vec<_>>()
+        });
+        for (peer_id, source) in sources {
+            if self.is_read_only_peer(&peer_id) {
+                continue;
+            }
+            match source.get_doc_save(*doc_id).await {
+                Ok(automerge_save) => {
+                    let loaded = automerge::Automerge::load(&automerge_save)
+                        .wrap_err("invalid automerge payload from GetDocsFull")?;
+                    return Ok(Some(loaded));
+                }
+                Err(err) => {
+                    debug!(%doc_id, %peer_id, ?err, "peer couldn't serve doc, trying next");
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    async fn fetch_snapshot(&self, doc_id: &crate::DocumentId) -> Res<Option<crate::DocSnapshot>> {
+        let sources = surelock::key::lock_scope(|key| {
+            let (remote_repo_clients, _key) = key.lock(&self.remote_repo_clients);
+            remote_repo_clients
+                .iter()
+                .map(|(peer_id, source)| (*peer_id, Arc::clone(source)))
+                .collect::<Vec<_>>()
+        });
+        for (peer_id, source) in sources {
+            if self.is_read_only_peer(&peer_id) {
+                continue;
+            }
+            match source.get_doc_snapshot(*doc_id).await {
+                Ok(snapshot) => return Ok(Some(snapshot)),
+                Err(err) => {
+                    debug!(%doc_id, %peer_id, ?err, "peer couldn't serve snapshot, trying next");
+                }
+            }
+        }
+        Ok(None)
+    }
+}
+
 #[async_trait::async_trait]
 impl big_sync::SyncBackend for BigRepoSyncBackend {
     async fn sync_obj(
@@ -97,6 +415,15 @@ impl big_sync::SyncBackend for BigRepoSyncBackend {
         obj_id: crate::DocumentId,
         remote_payload: Option<big_sync::ObjPayload>,
     ) -> Res<big_sync::SyncTaskRunOutcome> {
+        if self.is_read_only_peer(&peer_id) {
+            debug!(%obj_id, %peer_id, "peer is read-only, not pulling its changes");
+            return Ok(big_sync::SyncTaskRunOutcome::Completion(
+                big_sync_core::SyncTaskCompletion {
+                    obj_id,
+                    deets: big_sync_core::SyncCompletionDeets::Noop,
+                },
+            ));
+        }
         let repo: Arc<crate::BigRepo> = self
             .repo
             .upgrade()
@@ -104,17 +431,35 @@ impl big_sync::SyncBackend for BigRepoSyncBackend {
 
         let local_heads = super::partition_doc_heads_payload(&repo.big_sync_store, obj_id).await?;
         if local_heads.is_none() {
+            // heads the filter already turned away, no need to fetch again
+            if let Some(remote_payload) = &remote_payload {
+                let remote_heads = super::doc_heads_from_payload(remote_payload.clone());
+                if self.rejected_at(&obj_id, &remote_heads) {
+                    return Ok(big_sync::SyncTaskRunOutcome::Completion(
+                        big_sync_core::SyncTaskCompletion {
+                            obj_id,
+                            deets: big_sync_core::SyncCompletionDeets::Noop,
+                        },
+                    ));
+                }
+            }
             let client = self.remote_repo_client(peer_id)?;
-            let doc_id = obj_id.to_string();
-            let Some(full_doc) = client
-                .get_docs_full(vec![doc_id.clone()])
-                .await?
-                .into_iter()
-                .find(|doc| doc.doc_id == doc_id)
-            else {
-                eyre::bail!("missing on remote");
-            };
-            let loaded = automerge::Automerge::load(&full_doc.automerge_save)
+            if self.admission_filter().is_some() {
+                // judged by the snapshot so turned away docs don't cost
+                // their history
+                let snapshot = client.get_doc_snapshot(obj_id).await?;
+                if !self.admit(peer_id, obj_id, &snapshot) {
+                    debug!(%obj_id, "doc rejected by admission filter, skipping");
+                    return Ok(big_sync::SyncTaskRunOutcome::Completion(
+                        big_sync_core::SyncTaskCompletion {
+                            obj_id,
+                            deets: big_sync_core::SyncCompletionDeets::Noop,
+                        },
+                    ));
+                }
+            }
+            let automerge_save = client.get_doc_save(obj_id).await?;
+            let loaded = automerge::Automerge::load(&automerge_save)
                 .wrap_err("invalid automerge payload from GetDocsFull")?;
             let put_outcome = repo.put_doc(obj_id, loaded).await;
             match put_outcome {
@@ -127,6 +472,15 @@ impl big_sync::SyncBackend for BigRepoSyncBackend {
                     ));
                 }
                 Err(crate::runtime::PutDocError::IdOccpuied { .. }) => {
+                    if let RemoteDocSource::Bundle(docs) = &*client {
+                        self.merge_bundle_doc(&repo, peer_id, obj_id, docs).await?;
+                        return Ok(big_sync::SyncTaskRunOutcome::Completion(
+                            big_sync_core::SyncTaskCompletion {
+                                obj_id,
+                                deets: big_sync_core::SyncCompletionDeets::AddedMember,
+                            },
+                        ));
+                    }
                     match repo
                         .runtime
                         .sync_doc_with_peer(obj_id, peer_id, Some(Duration::from_secs(10)))
@@ -169,6 +523,17 @@ impl big_sync::SyncBackend for BigRepoSyncBackend {
                 ));
             }
         }
+        if let RemoteDocSource::Bundle(docs) = &*self.remote_repo_client(peer_id)? {
+            let changed = self.merge_bundle_doc(&repo, peer_id, obj_id, docs).await?;
+            let deets = if changed {
+                big_sync_core::SyncCompletionDeets::ChangedObject
+            } else {
+                big_sync_core::SyncCompletionDeets::Noop
+            };
+            return Ok(big_sync::SyncTaskRunOutcome::Completion(
+                big_sync_core::SyncTaskCompletion { obj_id, deets },
+            ));
+        }
         match repo
             .runtime
             .sync_doc_with_peer(obj_id, peer_id, Some(Duration::from_secs(10)))
@@ -7,7 +7,7 @@ mod interlude {
 }
 
 use crate::interlude::*;
-use crate::rpc::FullDoc;
+use crate::rpc::{FullDoc, SnapshotDoc};
 
 use std::collections::BTreeSet;
 use std::str::FromStr;
@@ -19,16 +19,19 @@ use sedimentree_core::loose_commit::id::CommitId;
 
 // FIXME: properly test the changes impl and investigate
 // why it no longer has users
+mod access;
 mod backend;
 #[expect(unused)]
 mod changes;
 pub mod rpc;
 mod runtime;
-pub use runtime::{PutDocError, SyncDocError};
+mod sealed;
+pub use runtime::{DocGcReport, DocStorageStats, PutDocError, SyncDocError};
+pub use sealed::{seal_disk_storage, SealKey};
 #[cfg(test)]
 pub(crate) mod test;
 
-pub use backend::BigRepoSyncBackend;
+pub use backend::{BigRepoSyncBackend, DocAdmissionFilter, DocFetcher};
 
 pub use changes::{
     path_prefix_matches as big_repo_path_prefix_matches, BigRepoChangeNotification,
@@ -40,6 +43,40 @@ pub use changes::{
 pub type DocumentId = ObjId;
 pub type SharedPartStore = Arc<dyn big_sync::HostPartStore>;
 
+/// A doc's state at some heads without the history leading there. Lets
+/// shallow clones read docs they don't hold, anything that needs the
+/// history goes through [`BigRepo::get_or_fetch_doc`].
+#[derive(Debug, Clone)]
+pub struct DocSnapshot {
+    /// Heads of the doc the snapshot was taken at.
+    pub heads: Arc<[ChangeHash]>,
+    /// The state at `heads` as a single change of its own. Read it at its
+    /// own heads, it can't be merged with the doc it came from.
+    pub doc: Arc<automerge::Automerge>,
+}
+
+impl DocSnapshot {
+    /// Snapshots the doc at its current heads.
+    pub fn of(doc: &automerge::Automerge) -> Res<Self> {
+        let heads: Arc<[ChangeHash]> = doc.get_heads().into();
+        let snapshot = am_utils_rs::snapshot_at(doc, &heads)?;
+        Ok(Self {
+            heads,
+            doc: Arc::new(snapshot),
+        })
+    }
+
+    fn from_wire(doc: SnapshotDoc) -> Res<Self> {
+        Ok(Self {
+            heads: am_utils_rs::parse_commit_heads(&doc.heads)?,
+            doc: Arc::new(
+                automerge::Automerge::load(&doc.automerge_save)
+                    .wrap_err("invalid automerge payload from GetDocsSnapshot")?,
+            ),
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Config {
     pub peer_id: PeerId,
@@ -49,8 +86,16 @@ pub struct Config {
 
 #[derive(Debug, Clone)]
 pub enum StorageConfig {
-    Disk { path: PathBuf },
+    Disk {
+        path: PathBuf,
+    },
     Memory,
+    /// Docs are kept in memory and persisted sealed under `key`, see
+    /// [`seal_disk_storage`] for moving a disk store over.
+    Sealed {
+        path: PathBuf,
+        key: SealKey,
+    },
 }
 
 #[derive(educe::Educe)]
@@ -65,6 +110,15 @@ pub struct BigRepo {
     change_manager: Arc<changes::ChangeListenerManager>,
     #[educe(Debug(ignore))]
     change_manager_stop: std::sync::Mutex<Option<changes::ChangeListenerManagerStopToken>>,
+    #[educe(Debug(ignore))]
+    fetcher: std::sync::Mutex<Option<Arc<dyn DocFetcher>>>,
+    /// Snapshots of docs that aren't local, dropped once the full doc is.
+    #[educe(Debug(ignore))]
+    snapshots: std::sync::Mutex<HashMap<DocumentId, DocSnapshot>>,
+    #[educe(Debug(ignore))]
+    access: Arc<access::PeerAccess>,
+    #[educe(Debug(ignore))]
+    sealed: Option<Arc<sealed::SealedDocs>>,
 }
 
 pub type SharedBigRepo = Arc<BigRepo>;
@@ -84,12 +138,15 @@ impl BigRepo {
 
         let (change_manager, change_manager_stop) = changes::ChangeListenerManager::boot();
         let signer = subduction_crypto::signer::memory::MemorySigner::from_bytes(&secret_key_bytes);
+        let access = Arc::new(access::PeerAccess::new(Arc::clone(&big_sync_store)));
+        let mut sealed = None;
         let (runtime, runtime_stop) = match storage {
             StorageConfig::Memory => runtime::spawn_big_repo_runtime(
                 signer,
                 subduction_core::storage::memory::MemoryStorage::new(),
                 Arc::clone(&big_sync_store),
                 Arc::clone(&change_manager),
+                Arc::clone(&access),
             )?,
             StorageConfig::Disk { path } => {
                 let subduction_dir = path.join("subduction");
@@ -106,9 +163,38 @@ impl BigRepo {
                     fs_storage,
                     Arc::clone(&big_sync_store),
                     Arc::clone(&change_manager),
+                    Arc::clone(&access),
+                )?
+            }
+            StorageConfig::Sealed { path, key } => {
+                sealed = Some(Arc::new(sealed::SealedDocs::open(&path, &key)?));
+                runtime::spawn_big_repo_runtime(
+                    signer,
+                    subduction_core::storage::memory::MemoryStorage::new(),
+                    Arc::clone(&big_sync_store),
+                    Arc::clone(&change_manager),
+                    Arc::clone(&access),
                 )?
             }
         };
+        let sealed_stop = match &sealed {
+            Some(docs) => {
+                let restored = match docs.restore(&runtime).await {
+                    Ok(restored) => restored,
+                    Err(err) => {
+                        runtime_stop.stop().await?;
+                        change_manager_stop.stop().await?;
+                        return Err(err);
+                    }
+                };
+                debug!(restored, "restored sealed docs");
+                Some(
+                    sealed::spawn_persister(Arc::clone(docs), runtime.clone(), &change_manager)
+                        .await?,
+                )
+            }
+            None => None,
+        };
 
         let out = Arc::new(Self {
             local_peer_id: peer_id,
@@ -116,6 +202,10 @@ impl BigRepo {
             runtime,
             change_manager,
             change_manager_stop: std::sync::Mutex::new(Some(change_manager_stop)),
+            fetcher: std::sync::Mutex::new(None),
+            snapshots: default(),
+            access,
+            sealed,
         });
 
         let change_manager_stop = out
@@ -128,6 +218,7 @@ impl BigRepo {
         Ok((
             Arc::clone(&out),
             BigRepoStopToken {
+                sealed_stop,
                 runtime_stop,
                 change_manager_stop: Some(change_manager_stop),
             },
@@ -137,6 +228,25 @@ impl BigRepo {
     pub fn local_peer_id(&self) -> PeerId {
         self.local_peer_id
     }
+
+    /// Installs the source [`Self::get_or_fetch_doc`] pulls missing docs
+    /// from. `None` disables fetching.
+    pub fn set_fetcher(&self, fetcher: Option<Arc<dyn DocFetcher>>) {
+        *self.fetcher.lock().expect(ERROR_MUTEX) = fetcher;
+    }
+
+    /// Docs are only served to a peer, over the repo rpc or a live
+    /// session, if it can read at least one of the partitions they're
+    /// members of. `None` serves everything.
+    pub fn set_access_policy(&self, policy: Option<Arc<dyn big_sync::rpc::PartAccessPolicy>>) {
+        self.access.set_part_access(policy);
+    }
+
+    /// Replaces the set of peers whose pushes over live sessions are
+    /// refused.
+    pub fn set_read_only_peers(&self, peers: HashSet<PeerId>) {
+        self.access.set_read_only_peers(peers);
+    }
 }
 
 // main methods
@@ -157,6 +267,83 @@ impl BigRepo {
         Ok(out)
     }
 
+    /// Like [`Self::get_doc`] but pulls the doc through the installed
+    /// [`DocFetcher`] if it isn't local. The fetched doc comes with its full
+    /// history and is stored like any other local doc. Reads that only need
+    /// the latest state are cheaper off [`Self::get_or_fetch_snapshot`].
+    #[tracing::instrument(
+        skip_all,
+        fields(%document_id, %self.local_peer_id)
+    )]
+    pub async fn get_or_fetch_doc(
+        self: &Arc<Self>,
+        document_id: &DocumentId,
+    ) -> Res<Option<BigDocHandle>> {
+        if let Some(handle) = self.get_doc(document_id).await? {
+            return Ok(Some(handle));
+        }
+        let fetcher = self.fetcher.lock().expect(ERROR_MUTEX).clone();
+        let Some(fetcher) = fetcher else {
+            return Ok(None);
+        };
+        let Some(doc) = fetcher.fetch(document_id).await? else {
+            return Ok(None);
+        };
+        let out = match self.put_doc(*document_id, doc).await {
+            Ok(handle) => Some(handle),
+            // sync got to it first
+            Err(runtime::PutDocError::IdOccpuied { .. }) => self.get_doc(document_id).await?,
+            Err(err) => return Err(err).wrap_err("put_doc failed"),
+        };
+        self.snapshots
+            .lock()
+            .expect(ERROR_MUTEX)
+            .remove(document_id);
+        Ok(out)
+    }
+
+    /// The state of a doc that isn't local at the heads a peer has, pulled
+    /// through the installed [`DocFetcher`] without the history. `None` if
+    /// the doc is local, use [`Self::get_doc`] for those, or if no peer
+    /// could serve it. Snapshots are kept until the full doc is fetched.
+    #[tracing::instrument(
+        skip_all,
+        fields(%document_id, %self.local_peer_id)
+    )]
+    pub async fn get_or_fetch_snapshot(
+        self: &Arc<Self>,
+        document_id: &DocumentId,
+    ) -> Res<Option<DocSnapshot>> {
+        if self.get_doc(document_id).await?.is_some() {
+            self.snapshots
+                .lock()
+                .expect(ERROR_MUTEX)
+                .remove(document_id);
+            return Ok(None);
+        }
+        if let Some(snapshot) = self
+            .snapshots
+            .lock()
+            .expect(ERROR_MUTEX)
+            .get(document_id)
+            .cloned()
+        {
+            return Ok(Some(snapshot));
+        }
+        let fetcher = self.fetcher.lock().expect(ERROR_MUTEX).clone();
+        let Some(fetcher) = fetcher else {
+            return Ok(None);
+        };
+        let Some(snapshot) = fetcher.fetch_snapshot(document_id).await? else {
+            return Ok(None);
+        };
+        self.snapshots
+            .lock()
+            .expect(ERROR_MUTEX)
+            .insert(*document_id, snapshot.clone());
+        Ok(Some(snapshot))
+    }
+
     #[tracing::instrument(skip_all, fields(%document_id, %self.local_peer_id))]
     pub async fn put_doc(
         self: &Arc<Self>,
@@ -177,6 +364,35 @@ impl BigRepo {
     pub async fn export_doc(&self, doc_id: &DocumentId) -> Res<Option<Vec<u8>>> {
         self.runtime.export_doc_save(*doc_id).await
     }
+
+    /// Bundles the loose commits of the doc into fragments. `None` if the
+    /// doc isn't stored locally.
+    #[tracing::instrument(
+        skip_all,
+        fields(%doc_id, %self.local_peer_id)
+    )]
+    pub async fn compact_doc(
+        &self,
+        doc_id: &DocumentId,
+        dry_run: bool,
+    ) -> Res<Option<DocGcReport>> {
+        self.runtime.compact_doc(*doc_id, dry_run).await
+    }
+
+    /// Deletes everything stored for the doc. Peers that still have it can
+    /// sync it back, so only purge docs nobody is going to ask for again.
+    /// Fails if the doc has live handles.
+    #[tracing::instrument(
+        skip_all,
+        fields(%doc_id, %self.local_peer_id)
+    )]
+    pub async fn purge_doc(&self, doc_id: &DocumentId, dry_run: bool) -> Res<Option<DocGcReport>> {
+        let report = self.runtime.purge_doc(*doc_id, dry_run).await?;
+        if let (Some(docs), false) = (&self.sealed, dry_run) {
+            docs.remove(doc_id).await?;
+        }
+        Ok(report)
+    }
 }
 
 // iroh support
@@ -203,6 +419,11 @@ impl BigRepo {
         })
     }
 
+    /// Closes the live connection with the peer if there's one.
+    pub async fn close_peer_connection(&self, peer_id: PeerId) -> Res<()> {
+        self.runtime.close_peer_connection(peer_id).await
+    }
+
     #[tracing::instrument(
         skip_all,
         fields(%self.local_peer_id)
@@ -335,15 +556,47 @@ impl BigRepo {
         }
         Ok(out)
     }
+
+    /// Like [`Self::get_docs_full`] but each doc comes compacted to its
+    /// state at the current heads, see [`DocSnapshot`].
+    pub async fn get_docs_snapshot(&self, doc_ids: &[String]) -> Res<Vec<SnapshotDoc>> {
+        let docs = self.get_docs_full(doc_ids).await?;
+        tokio::task::spawn_blocking(move || {
+            docs.into_iter()
+                .map(
+                    |FullDoc {
+                         doc_id,
+                         automerge_save,
+                     }| {
+                        let doc = automerge::Automerge::load(&automerge_save)
+                            .wrap_err("invalid automerge payload in storage")?;
+                        let snapshot = DocSnapshot::of(&doc)?;
+                        Ok(SnapshotDoc {
+                            doc_id,
+                            heads: am_utils_rs::serialize_commit_heads(&snapshot.heads),
+                            automerge_save: snapshot.doc.save(),
+                        })
+                    },
+                )
+                .collect()
+        })
+        .await
+        .wrap_err("snapshot task panicked")?
+    }
 }
 
 pub struct BigRepoStopToken {
+    sealed_stop: Option<sealed::SealedPersisterStopToken>,
     runtime_stop: runtime::BigRepoRuntimeStopToken,
     change_manager_stop: Option<changes::ChangeListenerManagerStopToken>,
 }
 
 impl BigRepoStopToken {
     pub async fn stop(mut self) -> Res<()> {
+        // the last sealed writes export from the runtime
+        if let Some(stop_token) = self.sealed_stop.take() {
+            stop_token.stop().await?;
+        }
         self.runtime_stop.stop().await?;
         if let Some(stop_token) = self.change_manager_stop.take() {
             stop_token.stop().await?;
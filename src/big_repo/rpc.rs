@@ -1,12 +1,18 @@
 use crate::interlude::*;
 
-use crate::SharedBigRepo;
+use crate::{DocumentId, SharedBigRepo};
 use irpc::{channel, rpc_requests, WithChannels};
+use std::str::FromStr;
 use tokio::sync::mpsc;
 use tokio_util::sync::CancellationToken;
 
 pub const REPO_SYNC_ALPN: &[u8] = b"townframe/repo-sync/0";
 
+/// Bump on any wire change to [`RepoSyncRpc`] or the types it carries.
+pub const REPO_SYNC_RPC_VERSION: u32 = 2;
+/// Oldest [`REPO_SYNC_RPC_VERSION`] this build can still talk to.
+pub const REPO_SYNC_RPC_MIN_VERSION: u32 = 1;
+
 pub const MAX_GET_DOCS_FULL_DOC_IDS: usize = 256;
 
 #[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -30,6 +36,32 @@ pub struct GetDocsFullRpcReq {
     pub req: GetDocsFullRequest,
 }
 
+/// A doc compacted to its state at the current heads, see
+/// [`crate::DocSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct SnapshotDoc {
+    pub doc_id: String,
+    /// Heads of the doc the snapshot was taken at.
+    pub heads: Vec<String>,
+    pub automerge_save: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GetDocsSnapshotRequest {
+    pub doc_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct GetDocsSnapshotResponse {
+    pub docs: Vec<SnapshotDoc>,
+}
+
+/// Added in version 2 of the rpc.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct GetDocsSnapshotRpcReq {
+    pub req: GetDocsSnapshotRequest,
+}
+
 #[derive(
     Debug, thiserror::Error, displaydoc::Display, Clone, serde::Serialize, serde::Deserialize,
 )]
@@ -46,6 +78,8 @@ pub enum BigRepoRpcError {
 pub enum RepoSyncRpc {
     #[rpc(tx = channel::oneshot::Sender<Result<GetDocsFullResponse, BigRepoRpcError>>)]
     GetDocsFull(GetDocsFullRpcReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<GetDocsSnapshotResponse, BigRepoRpcError>>)]
+    GetDocsSnapshot(GetDocsSnapshotRpcReq),
 }
 
 pub struct RepoRpcHandle {
@@ -109,9 +143,9 @@ async fn handle_rpc_message(big_repo: &SharedBigRepo, peer: PeerId, msg: RepoSyn
         RepoSyncRpcMessage::GetDocsFull(req) => {
             let WithChannels { inner, tx, .. } = req;
             let out = (async {
-                ensure_known_peer(&peer).await?;
+                let doc_ids = readable_doc_ids(big_repo, peer, inner.req.doc_ids).await?;
                 let docs = big_repo
-                    .get_docs_full(&inner.req.doc_ids)
+                    .get_docs_full(&doc_ids)
                     .await
                     .map_err(map_repo_err)?;
                 Ok::<_, BigRepoRpcError>(GetDocsFullResponse { docs })
@@ -119,12 +153,45 @@ async fn handle_rpc_message(big_repo: &SharedBigRepo, peer: PeerId, msg: RepoSyn
             .await;
             tx.send(out).await.inspect_err(|_| warn!(ERROR_CALLER)).ok();
         }
+        RepoSyncRpcMessage::GetDocsSnapshot(req) => {
+            let WithChannels { inner, tx, .. } = req;
+            let out = (async {
+                let doc_ids = readable_doc_ids(big_repo, peer, inner.req.doc_ids).await?;
+                let docs = big_repo
+                    .get_docs_snapshot(&doc_ids)
+                    .await
+                    .map_err(map_repo_err)?;
+                Ok::<_, BigRepoRpcError>(GetDocsSnapshotResponse { docs })
+            })
+            .await;
+            tx.send(out).await.inspect_err(|_| warn!(ERROR_CALLER)).ok();
+        }
     }
 }
 
-async fn ensure_known_peer(_peer: &PeerId) -> Result<(), BigRepoRpcError> {
-    // TODO: permissioning
-    Ok(())
+/// Drops the docs the peer isn't allowed to read. Unreadable docs
+/// are left out of the response as if they were missing.
+async fn readable_doc_ids(
+    big_repo: &SharedBigRepo,
+    peer: PeerId,
+    doc_ids: Vec<String>,
+) -> Result<Vec<String>, BigRepoRpcError> {
+    let mut out = Vec::with_capacity(doc_ids.len());
+    for doc_id in doc_ids {
+        // unparseable ids are dropped by get_docs_full anyways
+        let Ok(obj_id) = DocumentId::from_str(&doc_id) else {
+            continue;
+        };
+        if big_repo
+            .access
+            .can_read_doc(peer, obj_id)
+            .await
+            .map_err(map_repo_err)?
+        {
+            out.push(doc_id);
+        }
+    }
+    Ok(out)
 }
 
 fn map_repo_err(err: eyre::Report) -> BigRepoRpcError {
@@ -18,6 +18,8 @@ use super::changes;
 use subduction_core::subduction::request::FragmentRequested;
 
 const DOC_WORKER_IDLE_TTL: Duration = Duration::from_secs(3);
+/// Docs with fewer loose commits than this aren't worth compacting.
+const COMPACT_MIN_LOOSE_COMMITS: usize = 16;
 type SharedPartitionStore = Arc<dyn big_sync::HostPartStore>;
 
 #[derive(Debug, thiserror::Error, displaydoc::Display)]
@@ -32,6 +34,29 @@ pub enum SyncDocError {
     Other(#[from] eyre::Report),
 }
 
+/// What a doc takes up in subduction storage. Only blob bytes are counted,
+/// the commit and fragment metadata next to them isn't.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocStorageStats {
+    pub loose_commits: usize,
+    pub fragments: usize,
+    pub bytes: u64,
+}
+
+/// Storage of a doc before and after a compaction or purge. On dry runs,
+/// `after` is what it would have been.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DocGcReport {
+    pub before: DocStorageStats,
+    pub after: DocStorageStats,
+}
+
+impl DocGcReport {
+    pub fn bytes_reclaimed(&self) -> u64 {
+        self.before.bytes.saturating_sub(self.after.bytes)
+    }
+}
+
 #[derive(educe::Educe)]
 #[educe(Debug)]
 pub struct LiveDocBundle {
@@ -119,6 +144,21 @@ enum RuntimeCmd {
     ReleaseDocLease {
         doc_id: DocumentId,
     },
+    CompactDoc {
+        doc_id: DocumentId,
+        dry_run: bool,
+        resp: oneshot::Sender<Res<Option<DocGcReport>>>,
+    },
+    PurgeDoc {
+        doc_id: DocumentId,
+        dry_run: bool,
+        resp: oneshot::Sender<Res<Option<DocGcReport>>>,
+    },
+    RestoreDoc {
+        doc_id: DocumentId,
+        doc: Box<automerge::Automerge>,
+        resp: oneshot::Sender<Res<()>>,
+    },
 }
 
 enum ConnTask {
@@ -294,6 +334,45 @@ impl BigRepoRuntimeHandle {
         rx.await.map_err(|_| eyre::eyre!(ERROR_CHANNEL))?
     }
 
+    pub async fn compact_doc(&self, doc_id: DocumentId, dry_run: bool) -> Res<Option<DocGcReport>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RuntimeCmd::CompactDoc {
+                doc_id,
+                dry_run,
+                resp: tx,
+            })
+            .map_err(|_| eyre::eyre!(ERROR_ACTOR))?;
+        rx.await.map_err(|_| eyre::eyre!(ERROR_CHANNEL))?
+    }
+
+    pub async fn purge_doc(&self, doc_id: DocumentId, dry_run: bool) -> Res<Option<DocGcReport>> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RuntimeCmd::PurgeDoc {
+                doc_id,
+                dry_run,
+                resp: tx,
+            })
+            .map_err(|_| eyre::eyre!(ERROR_ACTOR))?;
+        rx.await.map_err(|_| eyre::eyre!(ERROR_CHANNEL))?
+    }
+
+    /// Puts a doc persisted outside the storage back into it. Unlike
+    /// [`Self::put_doc`] this neither touches the partition store nor
+    /// notifies listeners, the doc is only being reloaded.
+    pub async fn restore_doc(&self, doc_id: DocumentId, doc: automerge::Automerge) -> Res<()> {
+        let (tx, rx) = oneshot::channel();
+        self.cmd_tx
+            .send(RuntimeCmd::RestoreDoc {
+                doc_id,
+                doc: doc.into(),
+                resp: tx,
+            })
+            .map_err(|_| eyre::eyre!(ERROR_ACTOR))?;
+        rx.await.map_err(|_| eyre::eyre!(ERROR_CHANNEL))?
+    }
+
     fn release_doc_lease(&self, doc_id: DocumentId) {
         if self
             .cmd_tx
@@ -336,7 +415,7 @@ type BigRepoSyncHandler<S> = subduction_core::handler::sync::SyncHandler<
     future_form::Sendable,
     S,
     BigRepoIrohTransport,
-    subduction_core::policy::open::OpenPolicy,
+    crate::access::SessionPolicy,
     sedimentree_core::depth::CountLeadingZeroBytes,
     256,
 >;
@@ -346,7 +425,7 @@ type BigRepoSubduction<S> = subduction_core::subduction::Subduction<
     S,
     BigRepoIrohTransport,
     BigRepoSyncHandler<S>,
-    subduction_core::policy::open::OpenPolicy,
+    crate::access::SessionPolicy,
     subduction_crypto::signer::memory::MemorySigner,
     subduction_websocket::tokio::TimeoutTokio,
     sedimentree_core::depth::CountLeadingZeroBytes,
@@ -392,17 +471,21 @@ pub fn spawn_big_repo_runtime<S>(
     storage: S,
     big_sync_store: SharedPartitionStore,
     change_manager: Arc<changes::ChangeListenerManager>,
+    access: Arc<crate::access::PeerAccess>,
 ) -> Res<(BigRepoRuntimeHandle, BigRepoRuntimeStopToken)>
 where
     S: BigRepoSubductionStorage,
 {
-    use subduction_core::{policy::open::OpenPolicy, subduction::Subduction};
+    use subduction_core::subduction::Subduction;
     use subduction_websocket::tokio::{TimeoutTokio, TokioSpawn};
 
-    let policy = Arc::new(OpenPolicy);
     let connect_signer = signer.clone();
     let local_peer_id =
         subduction_core::peer::id::PeerId::new(*connect_signer.verifying_key().as_bytes());
+    let policy = Arc::new(crate::access::SessionPolicy {
+        local_peer_id: PeerId::new(*local_peer_id.as_bytes()),
+        access,
+    });
     let nonce_cache = Arc::new(subduction_core::nonce_cache::NonceCache::new(
         Duration::from_secs(60),
     ));
@@ -673,6 +756,41 @@ where
                     .expect(ERROR_ACTOR);
             }
             RuntimeCmd::ReleaseDocLease { doc_id } => self.handle_release_doc_lease(doc_id).await,
+            RuntimeCmd::CompactDoc {
+                doc_id,
+                dry_run,
+                resp,
+            } => {
+                let worker = self.doc_worker_handle(doc_id).expect(ERROR_ACTOR);
+                if let Some(entry) = self.doc_workers.get_mut(&doc_id) {
+                    entry.transient_work += 1;
+                }
+                worker
+                    .send(DocWorkerMsg::CompactDoc { dry_run, resp })
+                    .expect(ERROR_ACTOR);
+            }
+            RuntimeCmd::PurgeDoc {
+                doc_id,
+                dry_run,
+                resp,
+            } => {
+                let worker = self.doc_worker_handle(doc_id).expect(ERROR_ACTOR);
+                if let Some(entry) = self.doc_workers.get_mut(&doc_id) {
+                    entry.transient_work += 1;
+                }
+                worker
+                    .send(DocWorkerMsg::PurgeDoc { dry_run, resp })
+                    .expect(ERROR_ACTOR);
+            }
+            RuntimeCmd::RestoreDoc { doc_id, doc, resp } => {
+                let worker = self.doc_worker_handle(doc_id).expect(ERROR_ACTOR);
+                if let Some(entry) = self.doc_workers.get_mut(&doc_id) {
+                    entry.transient_work += 1;
+                }
+                worker
+                    .send(DocWorkerMsg::RestoreDoc { doc, resp })
+                    .expect(ERROR_ACTOR);
+            }
         }
 
         Ok(())
@@ -1209,6 +1327,18 @@ enum DocWorkerMsg {
         done: oneshot::Sender<Result<(), SyncDocError>>,
     },
     ReleaseHandleLease,
+    CompactDoc {
+        dry_run: bool,
+        resp: oneshot::Sender<Res<Option<DocGcReport>>>,
+    },
+    PurgeDoc {
+        dry_run: bool,
+        resp: oneshot::Sender<Res<Option<DocGcReport>>>,
+    },
+    RestoreDoc {
+        doc: Box<automerge::Automerge>,
+        resp: oneshot::Sender<Res<()>>,
+    },
 }
 
 struct DocWorker<S>
@@ -1308,6 +1438,39 @@ where
                 self.handle_sync_with_peer(peer_id, timeout, done).await?;
             }
             DocWorkerMsg::ReleaseHandleLease => {}
+            DocWorkerMsg::CompactDoc {
+                dry_run,
+                resp: done,
+            } => {
+                let res = self.handle_compact_doc(dry_run).await;
+                self.runtime_evt_tx
+                    .send(RuntimeEvt::DocWorkerTransientFinished {
+                        doc_id: self.doc_id,
+                    })
+                    .wrap_err(ERROR_CHANNEL)?;
+                done.send(res).inspect_err(|_| warn!(ERROR_CALLER)).ok();
+            }
+            DocWorkerMsg::PurgeDoc {
+                dry_run,
+                resp: done,
+            } => {
+                let res = self.handle_purge_doc(dry_run).await;
+                self.runtime_evt_tx
+                    .send(RuntimeEvt::DocWorkerTransientFinished {
+                        doc_id: self.doc_id,
+                    })
+                    .wrap_err(ERROR_CHANNEL)?;
+                done.send(res).inspect_err(|_| warn!(ERROR_CALLER)).ok();
+            }
+            DocWorkerMsg::RestoreDoc { doc, resp: done } => {
+                let res = self.handle_restore_doc(doc).await;
+                self.runtime_evt_tx
+                    .send(RuntimeEvt::DocWorkerTransientFinished {
+                        doc_id: self.doc_id,
+                    })
+                    .wrap_err(ERROR_CHANNEL)?;
+                done.send(res).inspect_err(|_| warn!(ERROR_CALLER)).ok();
+            }
         }
         Ok(())
     }
@@ -1363,6 +1526,20 @@ where
         Ok(bundle)
     }
 
+    #[tracing::instrument(skip_all, fields(doc_id = %self.doc_id))]
+    async fn handle_restore_doc(&mut self, doc: Box<automerge::Automerge>) -> Res<()> {
+        if !matches!(self.state, DocWorkerDocState::Unloaded) {
+            eyre::bail!("document already loaded: {}", self.doc_id);
+        }
+        let sedimentree_id: SedimentreeId = self.doc_id_subduction;
+        let ingested = ingest_automerge(&doc, sedimentree_id);
+        self.subduction
+            .add_sedimentree(sedimentree_id, ingested.sedimentree, ingested.blobs)
+            .await
+            .map_err(|err| ferr!("failed add_sedimentree: {err}"))?;
+        Ok(())
+    }
+
     async fn handle_acquire_handle(&mut self) -> Res<Option<Arc<LiveDocBundle>>> {
         if let DocWorkerDocState::Live(bundle) = &self.state {
             if let Some(bundle) = bundle.upgrade() {
@@ -1689,6 +1866,160 @@ where
         process_fragment_requests(Arc::clone(&bundle), requests, Arc::clone(&self.subduction)).await
     }
 
+    async fn storage_stats(&self) -> Res<(DocStorageStats, BTreeSet<CommitId>)> {
+        let sedimentree_id = self.doc_id_subduction;
+        let loose_commits = self
+            .storage_for_reads
+            .load_loose_commits(sedimentree_id)
+            .await?;
+        let fragments = self
+            .storage_for_reads
+            .load_fragments(sedimentree_id)
+            .await?;
+        let bytes = loose_commits
+            .iter()
+            .map(|verified| verified.blob().as_slice().len() as u64)
+            .chain(
+                fragments
+                    .iter()
+                    .map(|verified| verified.blob().as_slice().len() as u64),
+            )
+            .sum();
+        let loose_heads = loose_commits
+            .iter()
+            .map(|verified| verified.payload().head())
+            .collect();
+        Ok((
+            DocStorageStats {
+                loose_commits: loose_commits.len(),
+                fragments: fragments.len(),
+                bytes,
+            },
+            loose_heads,
+        ))
+    }
+
+    /// Re-ingests the doc so that runs of loose commits get bundled into
+    /// fragments, then drops the loose commits the new tree doesn't need.
+    /// The new tree is stored before anything is deleted so that a crash
+    /// in between only leaves extra loose commits behind.
+    #[tracing::instrument(skip_all, fields(doc_id = %self.doc_id, dry_run))]
+    async fn handle_compact_doc(&mut self, dry_run: bool) -> Res<Option<DocGcReport>> {
+        let sedimentree_id = self.doc_id_subduction;
+        let (before, old_loose_heads) = self.storage_stats().await?;
+        if before.loose_commits == 0 && before.fragments == 0 {
+            return Ok(None);
+        }
+        if before.loose_commits < COMPACT_MIN_LOOSE_COMMITS {
+            return Ok(Some(DocGcReport {
+                before,
+                after: before,
+            }));
+        }
+        let live = match &self.state {
+            DocWorkerDocState::Live(bundle) => bundle.upgrade(),
+            _ => None,
+        };
+        let ingested = match live {
+            Some(bundle) => {
+                let doc = bundle.doc.lock().await;
+                ingest_automerge(&doc, sedimentree_id)
+            }
+            None => {
+                let Some(doc) = self.take_or_load_transient_doc().await? else {
+                    return Ok(None);
+                };
+                let ingested = ingest_automerge(&doc, sedimentree_id);
+                self.state = DocWorkerDocState::Transient(doc.into());
+                ingested
+            }
+        };
+        let new_loose_heads: BTreeSet<CommitId> = ingested
+            .sedimentree
+            .loose_commits()
+            .map(|commit| commit.head())
+            .collect();
+        let stale_loose_heads = old_loose_heads
+            .difference(&new_loose_heads)
+            .copied()
+            .collect::<Vec<_>>();
+        if stale_loose_heads.is_empty() {
+            return Ok(Some(DocGcReport {
+                before,
+                after: before,
+            }));
+        }
+        let after = DocStorageStats {
+            loose_commits: new_loose_heads.len(),
+            fragments: ingested.sedimentree.fragments().count(),
+            bytes: ingested
+                .blobs
+                .iter()
+                .map(|blob| blob.as_slice().len() as u64)
+                .sum(),
+        };
+        if dry_run {
+            return Ok(Some(DocGcReport { before, after }));
+        }
+
+        let tree = ingested.sedimentree.clone();
+        self.subduction
+            .add_sedimentree(sedimentree_id, ingested.sedimentree, ingested.blobs)
+            .await
+            .map_err(|err| ferr!("failed add_sedimentree: {err}"))?;
+        for head in stale_loose_heads {
+            self.storage_for_reads
+                .delete_loose_commit(sedimentree_id, head)
+                .await?;
+        }
+        // the cached tree still lists the loose commits we just dropped
+        self.sedimentrees.insert(sedimentree_id, tree).await;
+        let (after, _) = self.storage_stats().await?;
+        Ok(Some(DocGcReport { before, after }))
+    }
+
+    /// Drops everything stored for the doc and takes it out of its
+    /// partitions. Fails if the doc has live handles.
+    #[tracing::instrument(skip_all, fields(doc_id = %self.doc_id, dry_run))]
+    async fn handle_purge_doc(&mut self, dry_run: bool) -> Res<Option<DocGcReport>> {
+        let sedimentree_id = self.doc_id_subduction;
+        if let DocWorkerDocState::Live(bundle) = &self.state {
+            if bundle.upgrade().is_some() {
+                eyre::bail!("document is open: {}", self.doc_id);
+            }
+        }
+        let (before, _) = self.storage_stats().await?;
+        if before.loose_commits == 0 && before.fragments == 0 {
+            return Ok(None);
+        }
+        let report = DocGcReport {
+            before,
+            after: default(),
+        };
+        if dry_run {
+            return Ok(Some(report));
+        }
+
+        self.state = DocWorkerDocState::Unloaded;
+        self.pending_fragment_requests.clear();
+        self.storage_for_reads
+            .delete_loose_commits(sedimentree_id)
+            .await?;
+        self.storage_for_reads
+            .delete_fragments(sedimentree_id)
+            .await?;
+        self.storage_for_reads
+            .delete_sedimentree_id(sedimentree_id)
+            .await?;
+        self.sedimentrees.remove(&sedimentree_id).await;
+        for part_id in self.big_sync_store.obj_parts(self.doc_id).await? {
+            self.big_sync_store
+                .remove_obj_from_part(self.doc_id, part_id)
+                .await?;
+        }
+        Ok(Some(report))
+    }
+
     #[tracing::instrument(skip_all)]
     async fn take_or_load_transient_doc(&mut self) -> Res<Option<automerge::Automerge>> {
         let out = match std::mem::replace(&mut self.state, DocWorkerDocState::Unloaded) {
@@ -0,0 +1,323 @@
+//! Encrypted doc storage behind [`crate::StorageConfig::Sealed`].
+//!
+//! The sedimentree storage doesn't take a cipher so sealed repos keep it
+//! in memory and persist every doc as a single automerge save, sealed
+//! with XChaCha20Poly1305, under `sealed/`. The saves are put back into
+//! the storage at boot and rewritten shortly after the heads of a doc
+//! move. The price is that every doc of the repo is held in memory.
+
+use crate::interlude::*;
+
+use crate::changes::{
+    BigRepoHeadNotification, BigRepoLocalNotification, ChangeListenerManager, HeadFilter,
+    LocalFilter,
+};
+use crate::runtime::BigRepoRuntimeHandle;
+use crate::{DocumentId, SharedPartStore};
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use std::str::FromStr;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+
+const NONCE_LEN: usize = 24;
+const SEALED_DOC_EXTENSION: &str = "am";
+/// Doc writes within this window are coalesced into one.
+const FLUSH_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Key the docs of a [`crate::StorageConfig::Sealed`] store are sealed with.
+#[derive(Clone)]
+pub struct SealKey([u8; 32]);
+
+impl SealKey {
+    pub fn new(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+}
+
+impl std::fmt::Debug for SealKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str("SealKey(..)")
+    }
+}
+
+pub(crate) struct SealedDocs {
+    dir: PathBuf,
+    cipher: XChaCha20Poly1305,
+}
+
+impl SealedDocs {
+    pub(crate) fn open(path: &Path, key: &SealKey) -> Res<Self> {
+        let dir = path.join("sealed");
+        std::fs::create_dir_all(&dir).wrap_err_with(|| {
+            format!("Failed to create sealed doc directory: {}", dir.display())
+        })?;
+        Ok(Self {
+            dir,
+            cipher: XChaCha20Poly1305::new(&key.0.into()),
+        })
+    }
+
+    fn doc_path(&self, doc_id: &DocumentId) -> PathBuf {
+        self.dir.join(format!("{doc_id}.{SEALED_DOC_EXTENSION}"))
+    }
+
+    /// Puts every sealed doc back into the runtime's storage. Fails on
+    /// docs that don't open under the key.
+    pub(crate) async fn restore(&self, runtime: &BigRepoRuntimeHandle) -> Res<usize> {
+        let mut restored = 0;
+        let mut read_dir = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            // skips the temp files of interrupted writes too
+            if path.extension().and_then(|ext| ext.to_str()) != Some(SEALED_DOC_EXTENSION) {
+                continue;
+            }
+            let Some(doc_id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| DocumentId::from_str(stem).ok())
+            else {
+                warn!(path = %path.display(), "skipping sealed doc with unexpected name");
+                continue;
+            };
+            let save = self
+                .unseal(&doc_id, &tokio::fs::read(&path).await?)
+                .wrap_err_with(|| format!("error opening sealed doc {}", path.display()))?;
+            let doc = automerge::Automerge::load(&save)
+                .wrap_err_with(|| format!("invalid automerge save in {}", path.display()))?;
+            runtime.restore_doc(doc_id, doc).await?;
+            restored += 1;
+        }
+        Ok(restored)
+    }
+
+    pub(crate) async fn write(&self, doc_id: &DocumentId, save: &[u8]) -> Res<()> {
+        let sealed = self.seal(doc_id, save)?;
+        let path = self.doc_path(doc_id);
+        let temp = path.with_extension(format!("{SEALED_DOC_EXTENSION}.tmp"));
+        tokio::fs::write(&temp, sealed).await?;
+        tokio::fs::rename(&temp, &path).await?;
+        Ok(())
+    }
+
+    pub(crate) async fn remove(&self, doc_id: &DocumentId) -> Res<()> {
+        match tokio::fs::remove_file(self.doc_path(doc_id)).await {
+            Ok(()) => Ok(()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// The nonce followed by the ciphertext. The doc id goes in as
+    /// associated data so that files can't be swapped around.
+    fn seal(&self, doc_id: &DocumentId, save: &[u8]) -> Res<Vec<u8>> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let sealed = self
+            .cipher
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: save,
+                    aad: doc_id.as_bytes(),
+                },
+            )
+            .map_err(|_| ferr!("error sealing doc {doc_id}"))?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    fn unseal(&self, doc_id: &DocumentId, sealed: &[u8]) -> Res<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            eyre::bail!("sealed doc is truncated");
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        self.cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: doc_id.as_bytes(),
+                },
+            )
+            .map_err(|_| ferr!("sealed doc doesn't open under the key"))
+    }
+}
+
+pub(crate) struct SealedPersisterStopToken {
+    cancel_token: CancellationToken,
+    handle: JoinHandle<()>,
+}
+
+impl SealedPersisterStopToken {
+    /// Writes out the pending docs before returning. Has to run before
+    /// the runtime is stopped.
+    pub(crate) async fn stop(self) -> Res<()> {
+        self.cancel_token.cancel();
+        utils_rs::wait_on_handle_with_timeout(self.handle, Duration::from_secs(30))
+            .await
+            .map_err(eyre::Report::from)
+    }
+}
+
+/// Rewrites the sealed save of every doc whose heads moved. Local docs
+/// announce themselves on creation, synced ones through their heads.
+pub(crate) async fn spawn_persister(
+    docs: Arc<SealedDocs>,
+    runtime: BigRepoRuntimeHandle,
+    change_manager: &Arc<ChangeListenerManager>,
+) -> Res<SealedPersisterStopToken> {
+    let (head_registration, mut head_rx) = change_manager
+        .subscribe_head_listener(HeadFilter { doc_id: None })
+        .await?;
+    let (local_registration, mut local_rx) = change_manager
+        .subscribe_local_listener(LocalFilter { doc_id: None })
+        .await?;
+    let cancel_token = CancellationToken::new();
+    let fut = {
+        let cancel_token = cancel_token.clone();
+        async move {
+            let _registrations = (head_registration, local_registration);
+            let mut dirty = HashSet::new();
+            let mut flush_tick = tokio::time::interval(FLUSH_INTERVAL);
+            loop {
+                tokio::select! {
+                    biased;
+                    Some(batch) = head_rx.recv() => {
+                        dirty.extend(batch.into_iter().map(head_doc_id));
+                    }
+                    Some(batch) = local_rx.recv() => {
+                        dirty.extend(batch.into_iter().map(local_doc_id));
+                    }
+                    _ = cancel_token.cancelled() => break,
+                    _ = flush_tick.tick() => {
+                        flush(&docs, &runtime, &mut dirty).await;
+                    }
+                }
+            }
+            // lets the switchboard forward what was queued before the stop
+            tokio::task::yield_now().await;
+            while let Ok(batch) = head_rx.try_recv() {
+                dirty.extend(batch.into_iter().map(head_doc_id));
+            }
+            while let Ok(batch) = local_rx.try_recv() {
+                dirty.extend(batch.into_iter().map(local_doc_id));
+            }
+            flush(&docs, &runtime, &mut dirty).await;
+            if !dirty.is_empty() {
+                error!(
+                    docs = dirty.len(),
+                    "sealed docs left unwritten, their latest changes are lost"
+                );
+            }
+        }
+        .instrument(tracing::info_span!("BigRepo sealed doc persister"))
+    };
+    Ok(SealedPersisterStopToken {
+        cancel_token,
+        handle: tokio::spawn(fut),
+    })
+}
+
+/// Failed docs stay dirty for the next round.
+async fn flush(docs: &SealedDocs, runtime: &BigRepoRuntimeHandle, dirty: &mut HashSet<DocumentId>) {
+    for doc_id in std::mem::take(dirty) {
+        let res = async {
+            match runtime.export_doc_save(doc_id).await? {
+                Some(save) => docs.write(&doc_id, &save).await,
+                // purged since
+                None => docs.remove(&doc_id).await,
+            }
+        }
+        .await;
+        if let Err(err) = res {
+            warn!(?err, %doc_id, "error writing sealed doc");
+            dirty.insert(doc_id);
+        }
+    }
+}
+
+fn head_doc_id(notification: BigRepoHeadNotification) -> DocumentId {
+    match notification {
+        BigRepoHeadNotification::DocHeadsChanged { doc_id, .. } => doc_id,
+    }
+}
+
+fn local_doc_id(notification: BigRepoLocalNotification) -> DocumentId {
+    match notification {
+        BigRepoLocalNotification::DocCreated { doc_id, .. }
+        | BigRepoLocalNotification::DocImported { doc_id, .. }
+        | BigRepoLocalNotification::DocHeadsUpdated { doc_id, .. } => doc_id,
+    }
+}
+
+/// Seals every doc of the [`crate::StorageConfig::Disk`] storage at `path`
+/// into the sealed store next to it so that the repo can be booted with
+/// [`crate::StorageConfig::Sealed`] instead. The disk storage is left in
+/// place for the caller to delete once it has switched over while a
+/// sealed store left by an earlier attempt is replaced. `big_sync_store`
+/// is only read to find the docs. Returns the number of docs sealed.
+pub async fn seal_disk_storage(
+    path: &Path,
+    key: &SealKey,
+    big_sync_store: SharedPartStore,
+) -> Res<usize> {
+    let sealed_dir = path.join("sealed");
+    if tokio::fs::try_exists(&sealed_dir).await? {
+        tokio::fs::remove_dir_all(&sealed_dir).await?;
+    }
+    let docs = SealedDocs::open(path, key)?;
+    // nothing connects to this repo, any identity does
+    let secret_key_bytes: [u8; 32] = rand::random();
+    let signer = subduction_crypto::signer::memory::MemorySigner::from_bytes(&secret_key_bytes);
+    let (repo, stop) = crate::BigRepo::boot(
+        crate::Config {
+            peer_id: PeerId::new(*signer.verifying_key().as_bytes()),
+            secret_key_bytes,
+            storage: crate::StorageConfig::Disk {
+                path: path.to_path_buf(),
+            },
+        },
+        Arc::clone(&big_sync_store),
+    )
+    .await?;
+    let res = async {
+        let mut sealed = 0;
+        for obj_id in big_sync_store.list_objs().await? {
+            // blobs are objects of the store too
+            let Some(save) = repo.export_doc(&obj_id).await? else {
+                continue;
+            };
+            docs.write(&obj_id, &save).await?;
+            sealed += 1;
+        }
+        eyre::Ok(sealed)
+    }
+    .await;
+    stop.stop().await?;
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sealed_doc_only_opens_under_its_id_and_key() -> Res<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let docs = SealedDocs::open(temp_dir.path(), &SealKey::new([7; 32]))?;
+        let doc_id = DocumentId::random();
+        let sealed = docs.seal(&doc_id, b"automerge save")?;
+        assert!(!sealed
+            .windows(b"automerge".len())
+            .any(|window| window == b"automerge"));
+        assert_eq!(docs.unseal(&doc_id, &sealed)?, b"automerge save");
+        assert!(docs.unseal(&DocumentId::random(), &sealed).is_err());
+
+        let other = SealedDocs::open(temp_dir.path(), &SealKey::new([8; 32]))?;
+        assert!(other.unseal(&doc_id, &sealed).is_err());
+        Ok(())
+    }
+}
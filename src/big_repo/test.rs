@@ -102,6 +102,40 @@ pub async fn _boot_disk_repo(
     ))
 }
 
+/// Boots over a part store kept at `path` so that it survives reboots.
+async fn boot_repo_at(
+    path: &Path,
+    storage: StorageConfig,
+) -> Res<(
+    Arc<BigRepo>,
+    Arc<big_sync::Ctx>,
+    Box<dyn FnOnce() -> futures::future::BoxFuture<'static, Res<()>>>,
+)> {
+    let sqlite_url = format!("sqlite://{}", path.join("part_store.db").display());
+    let (big_sync_host, big_sync_stop) = boot_part_store(&sqlite_url).await?;
+    let (repo, stop) = BigRepo::boot(
+        Config {
+            peer_id: PeerId::new([7_u8; 32]),
+            secret_key_bytes: [7_u8; 32],
+            storage,
+        },
+        Arc::clone(&big_sync_host.store),
+    )
+    .await?;
+    Ok((
+        repo,
+        big_sync_host,
+        Box::new(move || {
+            async move {
+                stop.stop().await?;
+                big_sync_stop.stop().await?;
+                eyre::Ok(())
+            }
+            .boxed()
+        }),
+    ))
+}
+
 fn get_int_at_root(doc: &automerge::Automerge, key: &str) -> i64 {
     let value = doc
         .get(automerge::ROOT, key)
@@ -219,6 +253,126 @@ async fn with_document_roundtrip_rehydrates_from_storage() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn compact_then_purge_doc_reclaims_storage() -> Res<()> {
+    let (repo, _part_store, _stop_token) = boot_repo().await?;
+    let doc_id = random_doc_id();
+    let handle = repo.put_doc(doc_id, automerge::Automerge::new()).await?;
+    for ii in 0..64 {
+        handle
+            .with_document(|doc| {
+                doc.transact(|tx| tx.put(automerge::ROOT, "title", format!("edit {ii}")))
+                    .expect("failed mutating doc");
+            })
+            .await?;
+    }
+    drop(handle);
+
+    let dry = repo
+        .compact_doc(&doc_id, true)
+        .await?
+        .expect("doc should be stored");
+    let compacted = repo
+        .compact_doc(&doc_id, false)
+        .await?
+        .expect("doc should be stored");
+    assert_eq!(dry.before, compacted.before);
+    assert!(compacted.after.loose_commits < compacted.before.loose_commits);
+    let reloaded = repo.get_doc(&doc_id).await?.expect("doc should exist");
+    assert_eq!(
+        reloaded
+            .with_document_read(|doc| get_str_at_root(doc, "title"))
+            .await,
+        "edit 63"
+    );
+    assert!(repo.purge_doc(&doc_id, false).await.is_err());
+    drop(reloaded);
+
+    let purged = repo
+        .purge_doc(&doc_id, false)
+        .await?
+        .expect("doc should be stored");
+    assert_eq!(purged.bytes_reclaimed(), purged.before.bytes);
+    assert!(repo.get_doc(&doc_id).await?.is_none());
+    assert!(repo.purge_doc(&doc_id, false).await?.is_none());
+    Ok(())
+}
+
+#[tokio::test]
+async fn sealed_storage_takes_over_disk_docs_and_persists_across_reboots() -> Res<()> {
+    let temp_dir = tempfile::tempdir()?;
+    let path = temp_dir.path().to_path_buf();
+    let key = SealKey::new([9_u8; 32]);
+    let sealed = || StorageConfig::Sealed {
+        path: path.clone(),
+        key: key.clone(),
+    };
+
+    let disk_doc_id = random_doc_id();
+    let (repo, _part_store, stop) =
+        boot_repo_at(&path, StorageConfig::Disk { path: path.clone() }).await?;
+    let mut doc = automerge::Automerge::new();
+    doc.transact(|tx| tx.put(automerge::ROOT, "title", "on disk"))
+        .expect("failed seeding doc");
+    drop(repo.put_doc(disk_doc_id, doc).await?);
+    drop(repo);
+    stop().await?;
+
+    let (big_sync_host, big_sync_stop) = boot_part_store(&format!(
+        "sqlite://{}",
+        path.join("part_store.db").display()
+    ))
+    .await?;
+    let sealed_count =
+        crate::seal_disk_storage(&path, &key, Arc::clone(&big_sync_host.store)).await?;
+    big_sync_stop.stop().await?;
+    assert_eq!(sealed_count, 1);
+    std::fs::remove_dir_all(path.join("subduction"))?;
+
+    let new_doc_id = random_doc_id();
+    let (repo, _part_store, stop) = boot_repo_at(&path, sealed()).await?;
+    let handle = repo
+        .put_doc(new_doc_id, automerge::Automerge::new())
+        .await?;
+    handle
+        .with_document(|doc| {
+            doc.transact(|tx| tx.put(automerge::ROOT, "title", "sealed"))
+                .expect("failed mutating doc");
+        })
+        .await?;
+    drop(handle);
+    drop(repo);
+    stop().await?;
+
+    let (repo, _part_store, stop) = boot_repo_at(&path, sealed()).await?;
+    for (doc_id, title) in [(disk_doc_id, "on disk"), (new_doc_id, "sealed")] {
+        let handle = repo.get_doc(&doc_id).await?.expect("doc should exist");
+        assert_eq!(
+            handle
+                .with_document_read(|doc| get_str_at_root(doc, "title"))
+                .await,
+            title
+        );
+    }
+    repo.purge_doc(&disk_doc_id, false)
+        .await?
+        .expect("doc should be stored");
+    drop(repo);
+    stop().await?;
+
+    let wrong_key = StorageConfig::Sealed {
+        path: path.clone(),
+        key: SealKey::new([10_u8; 32]),
+    };
+    assert!(boot_repo_at(&path, wrong_key).await.is_err());
+    let (repo, _part_store, stop) = boot_repo_at(&path, sealed()).await?;
+    assert!(repo.get_doc(&disk_doc_id).await?.is_none());
+    assert!(repo.get_doc(&new_doc_id).await?.is_some());
+    drop(repo);
+    stop().await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn change_listener_doc_id_filter_only_receives_target_doc() -> Res<()> {
     let (repo, _part_store, _stop_token) = boot_repo().await?;
@@ -2329,6 +2483,208 @@ async fn big_repo_sync_backend_recovers_from_put_doc_conflict() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn big_repo_sync_backend_ignores_read_only_peers() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempdir()?;
+    let server = SyncRepoNode::boot(temp_root.path().join("server"), 141, true).await?;
+    let client = SyncRepoNode::boot(temp_root.path().join("client"), 142, false).await?;
+    let doc_id = random_doc_id();
+    let mut base_doc = automerge::Automerge::new();
+    write_sync_doc_value(
+        &mut base_doc,
+        &make_sync_doc_value("base", SYNC_DOC_ITEMS, SYNC_DOC_PAYLOAD_LEN),
+    );
+    server.repo.put_doc(doc_id, base_doc).await?;
+
+    let client_conn = connect_sync_pair(&client, &server).await?;
+    server.wait_for_accepts(1).await;
+    client
+        .sync_backend
+        .set_read_only_peers([client_conn.peer_id()].into());
+
+    let remote_payload = server.big_sync_store.obj_payload(doc_id).await?;
+    let outcome = timeout(
+        SYNC_CASE_TIMEOUT,
+        client
+            .sync_backend
+            .sync_obj(client_conn.peer_id(), doc_id, remote_payload),
+    )
+    .await
+    .expect("sync backend test timed out")?;
+    assert!(
+        matches!(
+            outcome,
+            big_sync::SyncTaskRunOutcome::Completion(big_sync_core::SyncTaskCompletion {
+                deets: SyncCompletionDeets::Noop,
+                ..
+            })
+        ),
+        "read-only peer sync should be a noop"
+    );
+    assert!(
+        client.repo.get_doc(&doc_id).await?.is_none(),
+        "doc from read-only peer was materialized"
+    );
+
+    client_conn.stop().await?;
+    server.shutdown().await?;
+    client.shutdown().await?;
+    Ok(())
+}
+
+struct FixedAdmission(bool);
+
+impl crate::DocAdmissionFilter for FixedAdmission {
+    fn admit(&self, _doc_id: &DocumentId, _doc: &automerge::Automerge) -> bool {
+        self.0
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn big_repo_sync_backend_readmits_rejected_docs_after_filter_change() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempdir()?;
+    let server = SyncRepoNode::boot(temp_root.path().join("server"), 143, true).await?;
+    let client = SyncRepoNode::boot(temp_root.path().join("client"), 144, false).await?;
+    let doc_id = random_doc_id();
+    let mut base_doc = automerge::Automerge::new();
+    write_sync_doc_value(
+        &mut base_doc,
+        &make_sync_doc_value("base", SYNC_DOC_ITEMS, SYNC_DOC_PAYLOAD_LEN),
+    );
+    server.repo.put_doc(doc_id, base_doc).await?;
+
+    let client_conn = connect_sync_pair(&client, &server).await?;
+    server.wait_for_accepts(1).await;
+    client.sync_backend.set_admission_filter(Some(
+        Arc::new(FixedAdmission(false)) as Arc<dyn crate::DocAdmissionFilter>
+    ));
+
+    let remote_payload = server.big_sync_store.obj_payload(doc_id).await?;
+    let outcome = timeout(
+        SYNC_CASE_TIMEOUT,
+        client
+            .sync_backend
+            .sync_obj(client_conn.peer_id(), doc_id, remote_payload),
+    )
+    .await
+    .expect("sync backend test timed out")?;
+    assert!(
+        matches!(
+            outcome,
+            big_sync::SyncTaskRunOutcome::Completion(big_sync_core::SyncTaskCompletion {
+                deets: SyncCompletionDeets::Noop,
+                ..
+            })
+        ),
+        "rejected doc sync should be a noop"
+    );
+    assert!(client.repo.get_doc(&doc_id).await?.is_none());
+
+    // the filter hasn't changed, nothing to readmit
+    let readmitted = timeout(SYNC_CASE_TIMEOUT, client.sync_backend.readmit_rejected())
+        .await
+        .expect("readmission timed out")?;
+    assert!(readmitted.is_empty());
+    assert!(client.repo.get_doc(&doc_id).await?.is_none());
+
+    client.sync_backend.set_admission_filter(Some(
+        Arc::new(FixedAdmission(true)) as Arc<dyn crate::DocAdmissionFilter>
+    ));
+    let readmitted = timeout(SYNC_CASE_TIMEOUT, client.sync_backend.readmit_rejected())
+        .await
+        .expect("readmission timed out")?;
+    assert_eq!(readmitted, vec![doc_id]);
+    let client_doc = client
+        .repo
+        .get_doc(&doc_id)
+        .await?
+        .ok_or_eyre("readmitted doc missing")?;
+    let server_doc = server
+        .repo
+        .get_doc(&doc_id)
+        .await?
+        .ok_or_eyre("server doc missing")?;
+    assert_eq!(
+        read_json_doc(&client_doc).await,
+        read_json_doc(&server_doc).await
+    );
+    // only once
+    assert!(client.sync_backend.readmit_rejected().await?.is_empty());
+
+    client_conn.stop().await?;
+    server.shutdown().await?;
+    client.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn big_repo_fetches_doc_snapshot_without_history() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempdir()?;
+    let server = SyncRepoNode::boot(temp_root.path().join("server"), 145, true).await?;
+    let client = SyncRepoNode::boot(temp_root.path().join("client"), 146, false).await?;
+    let doc_id = random_doc_id();
+    let mut doc_value = make_sync_doc_value("base", SYNC_DOC_ITEMS, SYNC_DOC_PAYLOAD_LEN);
+    let mut base_doc = automerge::Automerge::new();
+    write_sync_doc_value(&mut base_doc, &doc_value);
+    let server_doc = server.repo.put_doc(doc_id, base_doc).await?;
+    apply_sync_mutation(
+        &mut doc_value,
+        SyncMutation {
+            item_idx: 3,
+            note_key: "history",
+            side_label: "server",
+        },
+        SYNC_DOC_PAYLOAD_LEN,
+    );
+    server_doc
+        .with_document(|doc| write_sync_doc_value(doc, &doc_value))
+        .await?;
+    let server_heads = server_doc.with_document_read(|doc| doc.get_heads()).await;
+
+    let client_conn = connect_sync_pair(&client, &server).await?;
+    server.wait_for_accepts(1).await;
+    client.repo.set_fetcher(Some(
+        Arc::clone(&client.sync_backend) as Arc<dyn crate::DocFetcher>
+    ));
+
+    let snapshot = timeout(
+        SYNC_CASE_TIMEOUT,
+        client.repo.get_or_fetch_snapshot(&doc_id),
+    )
+    .await
+    .expect("snapshot fetch timed out")?
+    .ok_or_eyre("snapshot missing")?;
+    assert_eq!(&snapshot.heads[..], &server_heads[..]);
+    assert_eq!(snapshot.doc.get_changes(&[]).len(), 1);
+    assert_eq!(
+        autosurgeon::hydrate::<_, ThroughJson<serde_json::Value>>(&*snapshot.doc)?.0,
+        read_json_doc(&server_doc).await
+    );
+    // the snapshot isn't stored as the doc
+    assert!(client.repo.get_doc(&doc_id).await?.is_none());
+
+    let client_doc = timeout(SYNC_CASE_TIMEOUT, client.repo.get_or_fetch_doc(&doc_id))
+        .await
+        .expect("doc fetch timed out")?
+        .ok_or_eyre("fetched doc missing")?;
+    let (client_heads, client_changes) = client_doc
+        .with_document_read(|doc| (doc.get_heads(), doc.get_changes(&[]).len()))
+        .await;
+    assert_eq!(client_heads, server_heads);
+    assert!(client_changes > 1, "full fetch should carry the history");
+    // local docs aren't served from snapshots
+    assert!(client.repo.get_or_fetch_snapshot(&doc_id).await?.is_none());
+
+    client.repo.set_fetcher(None);
+    client_conn.stop().await?;
+    server.shutdown().await?;
+    client.shutdown().await?;
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn big_repo_payload_first_membership_late_reconnects_cleanly() -> Res<()> {
     timeout(SYNC_CASE_TIMEOUT, async {
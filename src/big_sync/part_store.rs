@@ -43,6 +43,9 @@ pub trait HostPartStore: Send + Sync {
 
     async fn obj_payload(&self, obj_id: ObjId) -> Res<Option<ObjPayload>>;
 
+    /// Every object the store has seen, members of a part or not.
+    async fn list_objs(&self) -> Res<Vec<ObjId>>;
+
     // async fn get_obj_lease(&self, obj_id: ObjId) -> Res<ObjStoreLease>;
 
     async fn add_obj_to_parts(&self, obj_id: ObjId, parts: Vec<PartId>) -> Res<()>;
@@ -405,6 +405,13 @@ impl HostPartStore for MemoryPartStore {
         })
     }
 
+    async fn list_objs(&self) -> Res<Vec<ObjId>> {
+        surelock::key::lock_scope(|key| {
+            let (guard, _key) = key.lock(&self.inner);
+            Ok(guard.objs.keys().copied().collect())
+        })
+    }
+
     async fn get_bucket_summary(&self, part_id: PartId, id: BuckId) -> Res<BucketSummary> {
         Ok(surelock::key::lock_scope(|key| {
             let (guard, _key) = key.lock(&self.inner);
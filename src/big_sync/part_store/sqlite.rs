@@ -600,6 +600,18 @@ impl HostPartStore for SqlitePartStore {
             .transpose()
     }
 
+    async fn list_objs(&self) -> Res<Vec<ObjId>> {
+        let rows: Vec<Vec<u8>> = sqlx::query_scalar(
+            "SELECT obj_id FROM big_sync_objs
+             WHERE scope_id = ?1
+             ORDER BY obj_id ASC",
+        )
+        .bind(self.scope_id)
+        .fetch_all(&self.sql.read_pool)
+        .await?;
+        Ok(rows.into_iter().map(Self::obj_from_blob).collect())
+    }
+
     async fn set_obj_payload(&self, obj_id: ObjId, payload: ObjPayload) -> Res<()> {
         let payload_json = serde_json::to_string(&payload).wrap_err(ERROR_JSON)?;
         let mut tx = self.sql.write_pool.begin_with("BEGIN IMMEDIATE").await?;
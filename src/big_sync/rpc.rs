@@ -4,14 +4,22 @@ use crate::part_store::HostPartStore;
 
 use big_sync_core::rpc::{
     BigSyncRpcResult, BucketSummary, GetChangedBucketsRequest, LeafBucketResult, LeafBucketsError,
-    LeafBucketsRequest, ListPartsError, PeerSummaryRequest, PeerSummaryResult, SubEvent,
-    SubPartsRequest,
+    LeafBucketsRequest, ListPartsError, PeerSummaryRequest, PeerSummaryResult, RpcServeLimits,
+    SubEvent, SubPartsRequest, TokenBucket,
 };
+use big_sync_core::{PartId, PeerId};
 use irpc::{channel, rpc_requests, WithChannels};
+use std::collections::VecDeque;
+use std::time::Instant;
 use tokio::sync::mpsc;
 
 pub const BIG_SYNC_RPC_ALPN: &[u8] = b"townframe/big-sync/0";
 
+/// Bump on any wire change to [`BigSyncIrpc`] or the types it carries.
+pub const BIG_SYNC_RPC_VERSION: u32 = 1;
+/// Oldest [`BIG_SYNC_RPC_VERSION`] this build can still talk to.
+pub const BIG_SYNC_RPC_MIN_VERSION: u32 = 1;
+
 #[async_trait]
 pub trait HostBigRpcClient: Send + Sync {
     async fn peer_summary(
@@ -48,9 +56,165 @@ pub enum BigSyncIrpc {
     LeafBuckets(LeafBucketsRequest),
 }
 
+/// Decides which partitions a remote peer gets to see over the rpc.
+/// Partitions a peer can't read are never listed, streamed or bucketed
+/// for it. Requests through [`BigSyncRpcHandle::local_sender`] aren't
+/// subject to the policy.
+pub trait PartAccessPolicy: Send + Sync + 'static {
+    fn can_read(&self, peer_id: PeerId, part_id: PartId) -> bool;
+
+    /// Whether the peer gets to see objects that aren't a member of any
+    /// partition. Denied unless the policy says otherwise.
+    fn can_read_unpartitioned(&self, _peer_id: PeerId) -> bool {
+        false
+    }
+}
+
+type SharedAccessPolicy = Arc<std::sync::RwLock<Option<Arc<dyn PartAccessPolicy>>>>;
+
+#[derive(Debug)]
+struct PeerBudget {
+    calls: TokenBucket,
+    bytes: TokenBucket,
+}
+
+/// Per-peer token buckets shared between the rpc worker and the
+/// subscription forwarders.
+#[derive(Clone, Default)]
+struct PeerBudgets {
+    limits: Arc<std::sync::RwLock<RpcServeLimits>>,
+    budgets: Arc<std::sync::Mutex<HashMap<PeerId, PeerBudget>>>,
+    /// Response bytes served to each peer since last taken.
+    bytes_served: Arc<std::sync::Mutex<HashMap<PeerId, u64>>>,
+}
+
+impl PeerBudgets {
+    fn limits(&self) -> RpcServeLimits {
+        self.limits.read().expect(ERROR_MUTEX).clone()
+    }
+
+    /// Peers start over with full buckets under the new limits.
+    fn set_limits(&self, limits: RpcServeLimits) {
+        *self.limits.write().expect(ERROR_MUTEX) = limits;
+        self.budgets.lock().expect(ERROR_MUTEX).clear();
+    }
+
+    fn with_budget<T>(
+        &self,
+        peer_id: PeerId,
+        now: Instant,
+        func: impl FnOnce(&mut PeerBudget) -> T,
+    ) -> T {
+        let mut budgets = self.budgets.lock().expect(ERROR_MUTEX);
+        let budget = budgets.entry(peer_id).or_insert_with(|| {
+            let limits = self.limits.read().expect(ERROR_MUTEX);
+            PeerBudget {
+                calls: TokenBucket::new(limits.calls_per_sec, limits.call_burst, now),
+                bytes: TokenBucket::new(limits.bytes_per_sec, limits.byte_burst, now),
+            }
+        });
+        func(budget)
+    }
+
+    /// Time until the peer can be served another call. Peers still in
+    /// debt for past responses have to wait that out too.
+    fn call_wait(&self, peer_id: PeerId, now: Instant) -> Duration {
+        self.with_budget(peer_id, now, |budget| {
+            budget
+                .calls
+                .wait_for(1.0, now)
+                .max(budget.bytes.wait_for(0.0, now))
+        })
+    }
+
+    fn charge_call(&self, peer_id: PeerId, now: Instant) {
+        self.with_budget(peer_id, now, |budget| budget.calls.charge(1.0, now));
+    }
+
+    /// Returns how long the peer is in debt for.
+    fn charge_bytes(&self, peer_id: PeerId, bytes: usize, now: Instant) -> Duration {
+        *self
+            .bytes_served
+            .lock()
+            .expect(ERROR_MUTEX)
+            .entry(peer_id)
+            .or_default() += bytes as u64;
+        self.with_budget(peer_id, now, |budget| {
+            budget.bytes.charge(bytes as f64, now);
+            budget.bytes.wait_for(0.0, now)
+        })
+    }
+}
+
+/// Round robin over the peers with queued requests where each peer
+/// only gets a turn when its budget allows. Local requests, the `None`
+/// peer, are never held back.
+struct PeerScheduler<T> {
+    budgets: PeerBudgets,
+    queues: HashMap<Option<PeerId>, VecDeque<T>>,
+    rotation: VecDeque<Option<PeerId>>,
+}
+
+impl<T> PeerScheduler<T> {
+    fn new(budgets: PeerBudgets) -> Self {
+        Self {
+            budgets,
+            queues: default(),
+            rotation: default(),
+        }
+    }
+
+    /// Hands the request back if the peer has too many waiting.
+    fn push(&mut self, peer_id: Option<PeerId>, item: T) -> Result<(), T> {
+        let queue = self.queues.entry(peer_id).or_default();
+        if peer_id.is_some() && queue.len() >= self.budgets.limits().max_queued_per_peer {
+            return Err(item);
+        }
+        if queue.is_empty() {
+            self.rotation.push_back(peer_id);
+        }
+        queue.push_back(item);
+        Ok(())
+    }
+
+    /// Takes the next request that's allowed to be served, or when there's
+    /// none, the earliest time one will be.
+    fn pop(&mut self, now: Instant) -> Result<(Option<PeerId>, T), Option<Instant>> {
+        let mut earliest: Option<Instant> = None;
+        for _ in 0..self.rotation.len() {
+            let peer_id = self.rotation.pop_front().expect(ERROR_IMPOSSIBLE);
+            let wait = match peer_id {
+                Some(peer_id) => self.budgets.call_wait(peer_id, now),
+                None => Duration::ZERO,
+            };
+            if !wait.is_zero() {
+                let ready_at = now + wait;
+                earliest = Some(earliest.map_or(ready_at, |at| at.min(ready_at)));
+                self.rotation.push_back(peer_id);
+                continue;
+            }
+            let queue = self.queues.get_mut(&peer_id).expect(ERROR_IMPOSSIBLE);
+            let item = queue.pop_front().expect(ERROR_IMPOSSIBLE);
+            if queue.is_empty() {
+                self.queues.remove(&peer_id);
+            } else {
+                self.rotation.push_back(peer_id);
+            }
+            if let Some(peer_id) = peer_id {
+                self.budgets.charge_call(peer_id, now);
+            }
+            return Ok((peer_id, item));
+        }
+        Err(earliest)
+    }
+}
+
 #[derive(Clone)]
 pub struct BigSyncRpcHandle {
     client: irpc::Client<BigSyncIrpc>,
+    peer_tx: mpsc::Sender<(PeerId, BigSyncRpcMessage)>,
+    access_policy: SharedAccessPolicy,
+    budgets: PeerBudgets,
 }
 
 impl BigSyncRpcHandle {
@@ -58,16 +222,53 @@ impl BigSyncRpcHandle {
         self.client.as_local().expect(ERROR_IMPOSSIBLE)
     }
 
+    /// Sender for requests made by a remote peer. These are checked
+    /// against the [`PartAccessPolicy`].
+    pub fn peer_sender(&self) -> mpsc::Sender<(PeerId, BigSyncRpcMessage)> {
+        self.peer_tx.clone()
+    }
+
+    pub fn set_access_policy(&self, policy: Option<Arc<dyn PartAccessPolicy>>) {
+        *self.access_policy.write().expect(ERROR_MUTEX) = policy;
+    }
+
+    pub fn set_limits(&self, limits: RpcServeLimits) {
+        self.budgets.set_limits(limits);
+    }
+
+    /// Response bytes served to each remote peer since the last call.
+    pub fn take_bytes_served(&self) -> HashMap<PeerId, u64> {
+        std::mem::take(&mut *self.budgets.bytes_served.lock().expect(ERROR_MUTEX))
+    }
+
+    /// Client whose requests are served as coming from `peer_id`
+    /// without going through iroh.
+    #[cfg(any(test, feature = "test-support"))]
+    pub fn peer_client(&self, peer_id: PeerId) -> IrohBigSyncRpcClient {
+        let (tx, mut rx) = mpsc::channel(1024);
+        let peer_tx = self.peer_sender();
+        tokio::spawn(async move {
+            while let Some(msg) = rx.recv().await {
+                if peer_tx.send((peer_id, msg)).await.is_err() {
+                    break;
+                }
+            }
+        });
+        IrohBigSyncRpcClient {
+            client: irpc::Client::<BigSyncIrpc>::local(tx),
+        }
+    }
+
     pub fn protocol_handler(&self) -> BigSyncRpcProtocolHandler {
         BigSyncRpcProtocolHandler {
-            tx: self.local_sender(),
+            tx: self.peer_sender(),
         }
     }
 }
 
 #[derive(Clone)]
 pub struct BigSyncRpcProtocolHandler {
-    tx: irpc::LocalSender<BigSyncIrpc>,
+    tx: mpsc::Sender<(PeerId, BigSyncRpcMessage)>,
 }
 
 impl std::fmt::Debug for BigSyncRpcProtocolHandler {
@@ -83,6 +284,7 @@ impl iroh::protocol::ProtocolHandler for BigSyncRpcProtocolHandler {
         &self,
         conn: iroh::endpoint::Connection,
     ) -> Result<(), iroh::protocol::AcceptError> {
+        let peer_id = PeerId::new(*conn.remote_id().as_bytes());
         loop {
             let msg = match irpc_iroh::read_request::<BigSyncIrpc>(&conn).await {
                 Ok(Some(msg)) => msg,
@@ -92,7 +294,7 @@ impl iroh::protocol::ProtocolHandler for BigSyncRpcProtocolHandler {
                     break;
                 }
             };
-            if self.tx.send_raw(msg).await.is_err() {
+            if self.tx.send((peer_id, msg)).await.is_err() {
                 break;
             }
         }
@@ -123,20 +325,50 @@ pub async fn spawn_big_sync_rpc(
     store: Arc<dyn HostPartStore>,
 ) -> Res<(BigSyncRpcHandle, BigSyncRpcStopToken)> {
     let (rpc_tx, mut rpc_rx) = mpsc::channel(1024);
+    let (peer_tx, mut peer_rx) = mpsc::channel(1024);
     let client = irpc::Client::<BigSyncIrpc>::local(rpc_tx);
+    let access_policy: SharedAccessPolicy = default();
+    let budgets = PeerBudgets::default();
 
     let cancel_token = CancellationToken::new();
     let subscription_tasks = Arc::new(utils_rs::AbortableJoinSet::new());
     let fut = {
         let cancel_token = cancel_token.clone();
         let subscription_tasks = Arc::clone(&subscription_tasks);
+        let mut scheduler = PeerScheduler::new(budgets.clone());
         let mut worker = BigSyncRpcWorker {
             store,
+            access_policy: Arc::clone(&access_policy),
+            budgets: budgets.clone(),
             cancel_token: cancel_token.clone(),
             subscription_tasks,
         };
         async move {
             loop {
+                if cancel_token.is_cancelled() {
+                    break;
+                }
+                // take in everything that's waiting so that every peer
+                // with requests is in the rotation
+                while let Ok(msg) = rpc_rx.try_recv() {
+                    enqueue(&mut scheduler, None, msg);
+                }
+                while let Ok((peer_id, msg)) = peer_rx.try_recv() {
+                    enqueue(&mut scheduler, Some(peer_id), msg);
+                }
+                let ready_at = match scheduler.pop(Instant::now()) {
+                    Ok((peer_id, msg)) => {
+                        worker.handle_rpc_message(peer_id, msg).await;
+                        continue;
+                    }
+                    Err(ready_at) => ready_at,
+                };
+                let next_ready = async {
+                    match ready_at {
+                        Some(ready_at) => tokio::time::sleep_until(ready_at.into()).await,
+                        None => std::future::pending().await,
+                    }
+                };
                 tokio::select! {
                     biased;
                     _ = cancel_token.cancelled() => break,
@@ -144,8 +376,15 @@ pub async fn spawn_big_sync_rpc(
                         let Some(msg) = msg else {
                             break;
                         };
-                        worker.handle_rpc_message(msg).await;
+                        enqueue(&mut scheduler, None, msg);
+                    }
+                    msg = peer_rx.recv() => {
+                        let Some((peer_id, msg)) = msg else {
+                            break;
+                        };
+                        enqueue(&mut scheduler, Some(peer_id), msg);
                     }
+                    _ = next_ready => {}
                 }
             }
             eyre::Ok(())
@@ -154,7 +393,12 @@ pub async fn spawn_big_sync_rpc(
     let join_handle = tokio::spawn(async { fut.await.unwrap() });
 
     Ok((
-        BigSyncRpcHandle { client },
+        BigSyncRpcHandle {
+            client,
+            peer_tx,
+            access_policy,
+            budgets,
+        },
         BigSyncRpcStopToken {
             cancel_token,
             subscription_tasks,
@@ -163,6 +407,21 @@ pub async fn spawn_big_sync_rpc(
     ))
 }
 
+/// Dropping the request closes its reply channel which the peer sees
+/// as a transport error and retries later.
+fn enqueue(
+    scheduler: &mut PeerScheduler<BigSyncRpcMessage>,
+    peer_id: Option<PeerId>,
+    msg: BigSyncRpcMessage,
+) {
+    if scheduler.push(peer_id, msg).is_err() {
+        warn!(
+            ?peer_id,
+            "dropping big sync rpc request, peer has too many queued"
+        );
+    }
+}
+
 #[derive(Clone)]
 pub struct IrohBigSyncRpcClient {
     client: irpc::Client<BigSyncIrpc>,
@@ -274,16 +533,41 @@ impl HostBigRpcClient for IrohBigSyncRpcClient {
 
 struct BigSyncRpcWorker {
     store: Arc<dyn HostPartStore>,
+    access_policy: SharedAccessPolicy,
+    budgets: PeerBudgets,
     cancel_token: CancellationToken,
     subscription_tasks: Arc<utils_rs::AbortableJoinSet>,
 }
 
 impl BigSyncRpcWorker {
+    /// `None` peers are local and can read everything.
+    fn can_read(&self, peer_id: Option<PeerId>, part_id: PartId) -> bool {
+        let Some(peer_id) = peer_id else {
+            return true;
+        };
+        match &*self.access_policy.read().expect(ERROR_MUTEX) {
+            Some(policy) => policy.can_read(peer_id, part_id),
+            None => true,
+        }
+    }
+
+    /// Counts a response against the peer's byte budget.
+    fn charge_response<T: serde::Serialize>(&self, peer_id: Option<PeerId>, response: &T) {
+        let Some(peer_id) = peer_id else {
+            return;
+        };
+        let len = postcard::experimental::serialized_size(response).unwrap_or_default();
+        self.budgets.charge_bytes(peer_id, len, Instant::now());
+    }
+
     #[tracing::instrument(skip(self, msg))]
-    async fn handle_rpc_message(&mut self, msg: BigSyncRpcMessage) {
+    async fn handle_rpc_message(&mut self, peer_id: Option<PeerId>, msg: BigSyncRpcMessage) {
         match msg {
             BigSyncRpcMessage::PeerSummary(req) => {
-                let WithChannels { inner, tx, .. } = req;
+                let WithChannels { mut inner, tx, .. } = req;
+                inner
+                    .parts
+                    .retain(|part_id| self.can_read(peer_id, *part_id));
                 let out = {
                     let parts = self.store.summarize_parts(inner.parts).await.unwrap();
                     parts.map(|parts| PeerSummaryResult {
@@ -291,19 +575,30 @@ impl BigSyncRpcWorker {
                         deepest_bucket_level: big_sync_core::BuckId::MAX_LEVEL,
                     })
                 };
+                self.charge_response(peer_id, &out);
                 tx.send(out).await.inspect_err(|_| warn!(ERROR_CALLER)).ok();
             }
             BigSyncRpcMessage::SubParts(req) => {
-                let WithChannels { inner, tx, .. } = req;
+                let WithChannels { mut inner, tx, .. } = req;
+                inner
+                    .parts
+                    .retain(|part| self.can_read(peer_id, part.part_id));
+                if inner.parts.is_empty() {
+                    debug!("sub_parts request for no readable parts");
+                    return;
+                }
                 let sub = self.store.subscribe(inner).await.unwrap();
                 let Ok(sub) = sub else {
                     warn!("sub_parts request for unknown parts");
                     return;
                 };
                 let child_token = self.cancel_token.child_token();
+                let budgets = self.budgets.clone();
+                let page_len = budgets.limits().sub_parts_page_len.max(1);
                 self.subscription_tasks
                     .spawn(async move {
                         let fut = async move {
+                            let mut forwarded = 0usize;
                             loop {
                                 tokio::select! {
                                     biased;
@@ -315,9 +610,28 @@ impl BigSyncRpcWorker {
                                                 break;
                                             }
                                         };
+                                        // replays of big parts get paced by
+                                        // the peer's byte budget one page
+                                        // at a time
+                                        let debt = peer_id.map(|peer_id| {
+                                            let len = postcard::experimental::serialized_size(&evt)
+                                                .unwrap_or_default();
+                                            budgets.charge_bytes(peer_id, len, Instant::now())
+                                        });
                                         if tx.send(evt).await.is_err() {
                                             break;
                                         }
+                                        forwarded += 1;
+                                        if !forwarded.is_multiple_of(page_len) {
+                                            continue;
+                                        }
+                                        if let Some(debt) = debt.filter(|debt| !debt.is_zero()) {
+                                            tokio::select! {
+                                                biased;
+                                                _ = child_token.cancelled() => break,
+                                                _ = tokio::time::sleep(debt) => {}
+                                            }
+                                        }
                                     }
                                 }
                             }
@@ -328,13 +642,36 @@ impl BigSyncRpcWorker {
                     .expect("failed spawning big sync rpc subscription forwarder");
             }
             BigSyncRpcMessage::GetChangedBuckets(req) => {
-                let WithChannels { inner, tx, .. } = req;
-                let out = self.store.get_changed_buckets(inner).await.unwrap();
+                let WithChannels { mut inner, tx, .. } = req;
+                inner.limit_hint = inner
+                    .limit_hint
+                    .min(self.budgets.limits().max_changed_buckets.max(1));
+                // unreadable parts look exactly like unknown ones
+                let out = if self.can_read(peer_id, inner.part_id) {
+                    self.store.get_changed_buckets(inner).await.unwrap()
+                } else {
+                    Err(ListPartsError::UnkownParts {
+                        unkown_parts: vec![inner.part_id],
+                    })
+                };
+                self.charge_response(peer_id, &out);
                 tx.send(out).await.inspect_err(|_| warn!(ERROR_CALLER)).ok();
             }
             BigSyncRpcMessage::LeafBuckets(req) => {
-                let WithChannels { inner, tx, .. } = req;
-                let out = self.store.leaf_buckets(inner).await.unwrap();
+                let WithChannels { mut inner, tx, .. } = req;
+                // dropping buckets would leave the client waiting on them
+                // so the total is capped by shrinking every page instead
+                let bucket_count = u32::try_from(inner.buckets.len())
+                    .unwrap_or(u32::MAX)
+                    .max(1);
+                let per_bucket = (self.budgets.limits().max_leaf_entries / bucket_count).max(1);
+                inner.limit_hint = inner.limit_hint.min(per_bucket);
+                let out = if self.can_read(peer_id, inner.part_id) {
+                    self.store.leaf_buckets(inner).await.unwrap()
+                } else {
+                    Err(LeafBucketsError::UnkownPart)
+                };
+                self.charge_response(peer_id, &out);
                 tx.send(out).await.inspect_err(|_| warn!(ERROR_CALLER)).ok();
             }
         }
@@ -520,4 +857,177 @@ mod tests {
         server_endpoint.close().await;
         Ok(())
     }
+
+    struct DenyAll;
+
+    impl PartAccessPolicy for DenyAll {
+        fn can_read(&self, _peer_id: PeerId, _part_id: PartId) -> bool {
+            false
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn access_policy_hides_unreadable_parts_from_remote_peers() -> Res<()> {
+        let part_id = test_part();
+        let store = Arc::new(MemoryPartStore::new());
+        seed_test_store(&store, part_id).await?;
+
+        let rpc_store: Arc<dyn HostPartStore> = Arc::<MemoryPartStore>::clone(&store);
+        let (rpc_handle, rpc_stop) = spawn_big_sync_rpc(rpc_store).await?;
+        rpc_handle.set_access_policy(Some(Arc::new(DenyAll)));
+
+        // local requests bypass the policy
+        let local_peer_summary = rpc_handle
+            .client
+            .rpc(PeerSummaryRequest {
+                parts: [part_id].into_iter().collect(),
+            })
+            .await??;
+        assert!(local_peer_summary.parts.contains_key(&part_id));
+
+        let server_endpoint = iroh::Endpoint::builder(iroh::endpoint::presets::Minimal)
+            .bind_addr((Ipv4Addr::LOCALHOST, 0))?
+            .relay_mode(iroh::RelayMode::Disabled)
+            .bind()
+            .await?;
+        let router = Router::builder(server_endpoint.clone())
+            .accept(BIG_SYNC_RPC_ALPN, rpc_handle.protocol_handler())
+            .spawn();
+        let client_endpoint = iroh::Endpoint::builder(iroh::endpoint::presets::Minimal)
+            .bind_addr((Ipv4Addr::LOCALHOST, 0))?
+            .relay_mode(iroh::RelayMode::Disabled)
+            .bind()
+            .await?;
+        let client = IrohBigSyncRpcClient::new(client_endpoint, router.endpoint().addr());
+
+        let peer_summary = client
+            .peer_summary(PeerSummaryRequest {
+                parts: [part_id].into_iter().collect(),
+            })
+            .await???;
+        assert!(peer_summary.parts.is_empty(), "unreadable part was listed");
+
+        let changed_buckets = client
+            .get_changed_buckets(GetChangedBucketsRequest {
+                part_id,
+                offset: BuckId::ROOT,
+                since: 0,
+                limit_hint: 16,
+            })
+            .await??;
+        assert_eq!(
+            changed_buckets,
+            Err(ListPartsError::UnkownParts {
+                unkown_parts: vec![part_id]
+            })
+        );
+
+        let leaf_buckets = client
+            .leaf_buckets(LeafBucketsRequest {
+                part_id,
+                since: 0,
+                buckets: vec![big_sync_core::rpc::LeafBucketRequest {
+                    buck_id: BuckId::ROOT,
+                    after: None,
+                }],
+                seed: FingerprintSeed::new(0xaaaa_bbbb, 0xcccc_dddd),
+                limit_hint: 16,
+            })
+            .await??;
+        assert_eq!(leaf_buckets, Err(LeafBucketsError::UnkownPart));
+
+        drop(client);
+        rpc_stop.stop().await?;
+        router.shutdown().await?;
+        server_endpoint.close().await;
+        Ok(())
+    }
+
+    fn test_peer(byte: u8) -> PeerId {
+        PeerId(Byte32Id::new([byte; 32]))
+    }
+
+    #[test]
+    fn scheduler_round_robins_between_peers() {
+        let budgets = PeerBudgets::default();
+        budgets.set_limits(RpcServeLimits {
+            max_queued_per_peer: 1024,
+            ..default()
+        });
+        let mut scheduler = PeerScheduler::new(budgets);
+        let greedy = test_peer(1);
+        let other = test_peer(2);
+        for ii in 0..100 {
+            scheduler.push(Some(greedy), ii).unwrap();
+        }
+        scheduler.push(Some(other), 1000).unwrap();
+        scheduler.push(None, 2000).unwrap();
+
+        let now = Instant::now();
+        let served = (0..3)
+            .map(|_| scheduler.pop(now).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            served,
+            [(Some(greedy), 0), (Some(other), 1000), (None, 2000)],
+            "every peer with requests gets a turn before the greedy one goes again"
+        );
+        let rest = std::iter::from_fn(|| scheduler.pop(now).ok()).count();
+        assert_eq!(rest, 99);
+    }
+
+    #[test]
+    fn scheduler_holds_back_peers_over_budget() {
+        let budgets = PeerBudgets::default();
+        budgets.set_limits(RpcServeLimits {
+            calls_per_sec: 10.0,
+            call_burst: 2.0,
+            max_queued_per_peer: 3,
+            ..default()
+        });
+        let mut scheduler = PeerScheduler::new(budgets.clone());
+        let peer = test_peer(1);
+        for ii in 0..3 {
+            scheduler.push(Some(peer), ii).unwrap();
+        }
+        assert_eq!(
+            scheduler.push(Some(peer), 3),
+            Err(3),
+            "queue cap wasn't applied"
+        );
+        // local requests aren't limited
+        for ii in 0..10 {
+            scheduler.push(None, 100 + ii).unwrap();
+        }
+
+        let now = Instant::now();
+        let mut served = Vec::new();
+        while let Ok((peer_id, _)) = scheduler.pop(now) {
+            served.push(peer_id);
+        }
+        assert_eq!(served.iter().filter(|peer_id| peer_id.is_some()).count(), 2);
+        assert_eq!(
+            served.iter().filter(|peer_id| peer_id.is_none()).count(),
+            10
+        );
+
+        let ready_at = scheduler
+            .pop(now)
+            .expect_err("peer is out of call tokens")
+            .expect("peer should become ready again");
+        assert!(ready_at > now && ready_at <= now + Duration::from_millis(100));
+        assert_eq!(scheduler.pop(ready_at).map(|(_, item)| item), Ok(2));
+
+        // responses put the peer in debt until the bytes are paid off
+        let later = ready_at + Duration::from_secs(10);
+        let limits = budgets.limits();
+        let debt = budgets.charge_bytes(
+            peer,
+            (limits.byte_burst + limits.bytes_per_sec) as usize,
+            later,
+        );
+        assert_eq!(debt, Duration::from_secs(1));
+        scheduler.push(Some(peer), 4).unwrap();
+        assert_eq!(scheduler.pop(later), Err(Some(later + debt)));
+    }
 }
@@ -581,3 +581,192 @@ pub async fn run_randomized_four_node_stress_with_settle_timeout<F: StressFixtur
 
     Ok(())
 }
+
+pub const CONTENTION_BULK_OBJS: usize = 4000;
+pub const CONTENTION_NEW_OBJS: usize = 8;
+
+#[derive(Debug)]
+pub struct GreedyPeerReport {
+    pub total_objs: usize,
+    /// Time the already synced peer took to pick up the new objects.
+    pub incremental_latency: Duration,
+    /// Objects the greedy peer had by the time the other one was done.
+    pub greedy_progress_at_incremental: usize,
+    pub greedy_latency: Duration,
+}
+
+/// Materializes objects straight out of the serving peer's store.
+struct ServingStoreBackend {
+    local: Arc<dyn crate::HostPartStore>,
+    serving: Arc<dyn crate::HostPartStore>,
+}
+
+#[async_trait]
+impl crate::SyncBackend for ServingStoreBackend {
+    async fn sync_obj(
+        &self,
+        _peer_id: PeerId,
+        obj_id: ObjId,
+        remote_payload: Option<serde_json::Value>,
+    ) -> Res<crate::SyncTaskRunOutcome> {
+        let remote_payload = match remote_payload {
+            Some(remote_payload) => remote_payload,
+            None => self
+                .serving
+                .obj_payload(obj_id)
+                .await?
+                .ok_or_eyre("missing on remote")?,
+        };
+        let deets = match self.local.obj_payload(obj_id).await? {
+            Some(local) if local == remote_payload => big_sync_core::SyncCompletionDeets::Noop,
+            Some(_) => big_sync_core::SyncCompletionDeets::ChangedObject,
+            None => big_sync_core::SyncCompletionDeets::AddedMember,
+        };
+        if !matches!(deets, big_sync_core::SyncCompletionDeets::Noop) {
+            self.local.set_obj_payload(obj_id, remote_payload).await?;
+        }
+        Ok(crate::SyncTaskRunOutcome::Completion(
+            big_sync_core::SyncTaskCompletion { obj_id, deets },
+        ))
+    }
+}
+
+async fn count_present(store: &dyn crate::HostPartStore, objs: &[ObjId]) -> Res<usize> {
+    let mut count = 0;
+    for obj_id in objs {
+        if store.obj_payload(*obj_id).await?.is_some() {
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+async fn wait_for_present(
+    store: &dyn crate::HostPartStore,
+    objs: &[ObjId],
+    timeout: Duration,
+    label: &str,
+) -> Res<()> {
+    let deadline = std::time::Instant::now() + utils_rs::scale_timeout(timeout);
+    loop {
+        let present = count_present(store, objs).await?;
+        if present == objs.len() {
+            return Ok(());
+        }
+        if std::time::Instant::now() >= deadline {
+            return Err(ferr!(
+                "timed out waiting for {label}: {present}/{} objects present",
+                objs.len()
+            ));
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+}
+
+/// One peer does a first full sync of a big part from a server while
+/// another peer that was already synced catches up on a handful of new
+/// objects from the same server. Both go through the server's rpc
+/// worker under `limits`.
+pub async fn run_greedy_peer_contention(
+    limits: big_sync_core::rpc::RpcServeLimits,
+) -> Res<GreedyPeerReport> {
+    utils_rs::testing::setup_tracing_once();
+
+    let part_id = test_part();
+    let backend_id: crate::BackendId = "ServingStoreBackend".into();
+    let server_peer = PeerId(Byte32Id::new([0xa0; 32]));
+    let greedy_peer = PeerId(Byte32Id::new([0xa1; 32]));
+    let incremental_peer = PeerId(Byte32Id::new([0xa2; 32]));
+    let mut rng = StdRng::seed_from_u64(DEFAULT_STRESS_SEED);
+
+    let serving: Arc<dyn crate::HostPartStore> = Arc::new(crate::MemoryPartStore::new());
+    let incremental: Arc<dyn crate::HostPartStore> = Arc::new(crate::MemoryPartStore::new());
+    let greedy: Arc<dyn crate::HostPartStore> = Arc::new(crate::MemoryPartStore::new());
+    for store in [&serving, &incremental, &greedy] {
+        store.ensure_part(part_id).await?;
+    }
+
+    let mut all_objs = Vec::with_capacity(CONTENTION_BULK_OBJS + CONTENTION_NEW_OBJS);
+    for ii in 0..CONTENTION_BULK_OBJS {
+        let obj_id = stress_obj(&mut rng);
+        let bulk_payload = payload(format!("bulk:{ii}"), ii as u64, server_peer);
+        for store in [&serving, &incremental] {
+            store.set_obj_payload(obj_id, bulk_payload.clone()).await?;
+            store.add_obj_to_parts(obj_id, vec![part_id]).await?;
+        }
+        all_objs.push(obj_id);
+    }
+    let mut new_objs = Vec::with_capacity(CONTENTION_NEW_OBJS);
+    for ii in 0..CONTENTION_NEW_OBJS {
+        let obj_id = stress_obj(&mut rng);
+        serving
+            .set_obj_payload(
+                obj_id,
+                payload(
+                    format!("new:{ii}"),
+                    (CONTENTION_BULK_OBJS + ii) as u64,
+                    server_peer,
+                ),
+            )
+            .await?;
+        serving.add_obj_to_parts(obj_id, vec![part_id]).await?;
+        new_objs.push(obj_id);
+        all_objs.push(obj_id);
+    }
+
+    let (rpc, rpc_stop) = crate::rpc::spawn_big_sync_rpc(Arc::clone(&serving)).await?;
+    rpc.set_limits(limits);
+
+    let started_at = std::time::Instant::now();
+    let mut workers = Vec::with_capacity(2);
+    // the greedy peer goes first so that its requests are already queued
+    for (peer_id, store) in [(greedy_peer, &greedy), (incremental_peer, &incremental)] {
+        let backend: Arc<dyn crate::SyncBackend> = Arc::new(ServingStoreBackend {
+            local: Arc::clone(store),
+            serving: Arc::clone(&serving),
+        });
+        let (worker, stop) = crate::spawn_big_sync_worker(
+            Arc::clone(store),
+            [(Arc::clone(&backend_id), backend)].into(),
+        )?;
+        worker
+            .set_peer(
+                server_peer,
+                Arc::new(rpc.peer_client(peer_id)),
+                [(part_id, Arc::clone(&backend_id))].into(),
+            )
+            .await?;
+        workers.push((worker, stop));
+    }
+
+    wait_for_present(
+        incremental.as_ref(),
+        &new_objs,
+        Duration::from_secs(30),
+        "incremental peer",
+    )
+    .await?;
+    let incremental_latency = started_at.elapsed();
+    let greedy_progress_at_incremental = count_present(greedy.as_ref(), &all_objs).await?;
+
+    wait_for_present(
+        greedy.as_ref(),
+        &all_objs,
+        Duration::from_secs(120),
+        "greedy peer",
+    )
+    .await?;
+    let greedy_latency = started_at.elapsed();
+
+    for (_worker, stop) in workers {
+        stop.stop().await?;
+    }
+    rpc_stop.stop().await?;
+
+    Ok(GreedyPeerReport {
+        total_objs: all_objs.len(),
+        incremental_latency,
+        greedy_progress_at_incremental,
+        greedy_latency,
+    })
+}
@@ -311,3 +311,21 @@ async fn sqlite_sync_randomized_four_node_stress_converges() -> Res<()> {
     )
     .await
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn greedy_peer_does_not_hold_back_incremental_sync() -> Res<()> {
+    let report = stress_support::run_greedy_peer_contention(big_sync_core::rpc::RpcServeLimits {
+        bytes_per_sec: 32.0 * 1024.0,
+        byte_burst: 32.0 * 1024.0,
+        max_leaf_entries: 256,
+        ..default()
+    })
+    .await?;
+    info!(?report, "greedy peer contention");
+    assert!(
+        report.greedy_progress_at_incremental < report.total_objs,
+        "incremental peer only caught up after the greedy one was done: {report:?}"
+    );
+    assert!(report.incremental_latency < report.greedy_latency);
+    Ok(())
+}
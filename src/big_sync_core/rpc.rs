@@ -1,4 +1,5 @@
-//! TODO: rate limiting
+//! Wire types of the big sync rpc along with the limits servers apply
+//! to the peers they serve.
 
 use serde::{Deserializer, Serializer};
 
@@ -30,14 +31,103 @@ pub trait BigSyncRpcClient<K: FutureForm> {
         req: GetChangedBucketsRequest,
     ) -> K::Future<'a, BigSyncRpcResult<Result<Vec<BucketSummary>, ListPartsError>>>;
 
-    /// WARN: this doesn't limit the number of returned results
-    /// It thus only accepts buckets that are of the level [`PeerSummaryResult::deepest_bucket_level`]
+    /// Only accepts buckets that are of the level [`PeerSummaryResult::deepest_bucket_level`].
+    ///
+    /// Servers may shrink [`LeafBucketsRequest::limit_hint`] to cap the total
+    /// entries of a response but every requested bucket gets a page. Pages
+    /// that aren't [`LeafBucketPage::done`] are continued from their
+    /// [`LeafBucketPage::next_after`].
     fn leaf_buckets<'a>(
         &'a self,
         req: LeafBucketsRequest,
     ) -> K::Future<'a, BigSyncRpcResult<Result<LeafBucketResult, LeafBucketsError>>>;
 }
 
+/// Limits a server applies to each remote peer. Local requests are
+/// paged the same but never rate limited.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcServeLimits {
+    /// Sustained request rate of a peer.
+    pub calls_per_sec: f64,
+    pub call_burst: f64,
+    /// Sustained response volume of a peer, subscription events included.
+    pub bytes_per_sec: f64,
+    pub byte_burst: f64,
+    /// Cap on the entries of one leaf_buckets response across all of
+    /// its buckets.
+    pub max_leaf_entries: u32,
+    /// Cap on [`GetChangedBucketsRequest::limit_hint`].
+    pub max_changed_buckets: u32,
+    /// Subscription events forwarded between checks of the byte budget.
+    pub sub_parts_page_len: usize,
+    /// Requests a peer can have waiting before new ones are dropped.
+    pub max_queued_per_peer: usize,
+}
+
+impl Default for RpcServeLimits {
+    fn default() -> Self {
+        Self {
+            calls_per_sec: 200.0,
+            call_burst: 400.0,
+            bytes_per_sec: (4 * 1024 * 1024) as f64,
+            byte_burst: (8 * 1024 * 1024) as f64,
+            max_leaf_entries: 4096,
+            max_changed_buckets: 256,
+            sub_parts_page_len: 64,
+            max_queued_per_peer: 256,
+        }
+    }
+}
+
+/// Token bucket that's allowed to go into debt.
+///
+/// Charges are never refused since the size of a response isn't known
+/// until after it's been computed. The debt instead pushes back the
+/// time the next tokens are available.
+#[derive(Debug, Clone)]
+pub struct TokenBucket {
+    rate_per_sec: f64,
+    burst: f64,
+    tokens: f64,
+    refilled_at: std::time::Instant,
+}
+
+impl TokenBucket {
+    /// Starts out full.
+    pub fn new(rate_per_sec: f64, burst: f64, now: std::time::Instant) -> Self {
+        assert!(rate_per_sec > 0.0, "token bucket rate must be positive");
+        Self {
+            rate_per_sec,
+            burst,
+            tokens: burst,
+            refilled_at: now,
+        }
+    }
+
+    fn refill(&mut self, now: std::time::Instant) {
+        if now <= self.refilled_at {
+            return;
+        }
+        let elapsed = now.duration_since(self.refilled_at).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate_per_sec).min(self.burst);
+        self.refilled_at = now;
+    }
+
+    pub fn charge(&mut self, cost: f64, now: std::time::Instant) {
+        self.refill(now);
+        self.tokens -= cost;
+    }
+
+    /// How long until `needed` tokens are available.
+    pub fn wait_for(&mut self, needed: f64, now: std::time::Instant) -> Duration {
+        self.refill(now);
+        if self.tokens >= needed {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64((needed - self.tokens) / self.rate_per_sec)
+    }
+}
+
 pub type BuckLevel = u8;
 pub type BucketFp = (u64, u64);
 
@@ -17,8 +17,13 @@ impl Config {
 pub type Ctx = daybook_core::repo::RepoCtx;
 pub type SharedCtx = Arc<Ctx>;
 
+/// Unlocks repos encrypted with a passphrase.
+pub const PASSPHRASE_ENV: &str = "DAYBOOK_PASSPHRASE";
+
 pub async fn open_repo_ctx(config: &Config, ensure_initialized: bool) -> Res<SharedCtx> {
-    let options = daybook_core::repo::RepoOpenOptions {};
+    let options = daybook_core::repo::RepoOpenOptions {
+        passphrase: std::env::var(PASSPHRASE_ENV).ok(),
+    };
     let local_device_name = format!("daybook-cli-{}", std::env::consts::ARCH);
     let rcx = if ensure_initialized
         && !daybook_core::repo::is_repo_initialized(&config.cli_config.repo_path).await?
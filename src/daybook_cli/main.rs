@@ -110,9 +110,10 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
     if let StaticCommands::Clone {
         source,
         destination,
+        shallow,
     } = &cli.command
     {
-        clone_repo_from_url(source, &std::path::PathBuf::from(destination)).await?;
+        clone_repo_from_url(source, &std::path::PathBuf::from(destination), *shallow).await?;
         return Ok(ExitCode::SUCCESS);
     }
 
@@ -123,6 +124,15 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
         );
         return Ok(ExitCode::FAILURE);
     }
+    // these rewrite the repo's files so they run without opening it
+    if let StaticCommands::Encryption { command } = &cli.command {
+        encryption_cli(&conf.cli_config.repo_path, command).await?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    // another process has the repo open, go through its daemon
+    if daybook_core::repo::is_repo_in_use(&conf.cli_config.repo_path)? {
+        return attached_cli(&conf.cli_config.repo_path, cli.command).await;
+    }
     // we only create init the Ctx after checking if the
     // configured repo is Initialized since `init`
     // initializes the repo
@@ -132,6 +142,7 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
     match cli.command {
         StaticCommands::Init {}
         | StaticCommands::Clone { .. }
+        | StaticCommands::Encryption { .. }
         | StaticCommands::Completions { .. } => unreachable!(),
         StaticCommands::Dump => {
             let mut drawer = ctx
@@ -186,7 +197,7 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
         StaticCommands::Ls => {
             let doc_entries = drawer_repo.list().await?;
             let mut docs = Vec::new();
-            for entry in &doc_entries {
+            for entry in doc_entries {
                 let Some(main_branch) = entry.main_branch_path() else {
                     warn!(doc_id = ?entry.doc_id,"no branches found on doc");
                     continue;
@@ -195,90 +206,29 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
                     .get_doc_with_facets_at_branch(&entry.doc_id, &main_branch, None)
                     .await?
                 {
-                    docs.push((entry.clone(), doc));
+                    docs.push((entry, (*doc).clone()));
                 }
             }
-
-            use comfy_table::presets::NOTHING;
-            use comfy_table::Table;
-            use daybook_types::doc::{WellKnownFacet, WellKnownFacetTag};
-
-            let mut table = Table::new();
-            table
-                .load_preset(NOTHING)
-                .set_header(vec!["ID", "Title", "Branches"]);
-
-            for (entry, doc) in docs {
-                let title = doc
-                    .facets
-                    .get(&WellKnownFacetTag::TitleGeneric.into())
-                    .map(|val| {
-                        match WellKnownFacet::from_json(
-                            val.clone(),
-                            WellKnownFacetTag::TitleGeneric,
-                        ) {
-                            Ok(WellKnownFacet::TitleGeneric(str)) => str.clone(),
-                            _ => panic!("tag - facet mismatch"),
-                        }
-                    })
-                    .unwrap_or_else(|| "<no title>".to_string());
-                table.add_row(vec![
-                    entry.doc_id,
-                    title,
-                    entry
-                        .branches
-                        .keys()
-                        .map(|key| key.as_str())
-                        .collect::<Vec<_>>()
-                        .join(","),
-                ]);
-            }
-            println!("{table}");
+            print_docs_table(&docs);
         }
         StaticCommands::Cat { id, branch } => {
             let Ok(Some(branches)) = drawer_repo.get_doc_branches(&id).await else {
                 error!("document not found: {id}");
                 return Ok(ExitCode::FAILURE);
             };
-            let branch_path = match &branch {
-                Some(val) => {
-                    if !branches.branches.contains_key(val) {
-                        error!("branch not found for doc: {id} - {val}");
-                        return Ok(ExitCode::FAILURE);
-                    }
-                    daybook_types::doc::BranchPathBuf::from(val.as_str())
-                }
-                None => {
-                    let Some(branch) = branches.main_branch_path() else {
-                        error!(doc_id = ?branches.doc_id,"no branches found on doc");
-                        return Ok(ExitCode::FAILURE);
-                    };
-                    branch
-                }
+            let Some(branch_path) = resolve_branch(&branches, branch.as_deref()) else {
+                return Ok(ExitCode::FAILURE);
             };
             let doc = drawer_repo
                 .get_doc_with_facets_at_branch(&id, &branch_path, None)
                 .await?
                 .expect("document from entry missing");
-            println!("{:#?}", &doc);
-            println!("{}", serde_json::to_string_pretty(&*doc)?);
+            print_doc(&doc)?;
         }
         StaticCommands::Touch => {
-            let doc = daybook_types::doc::AddDocArgs {
-                branch_path: daybook_types::doc::BranchPathBuf::from("main"),
-                facets: [
-                    //
-                    (
-                        daybook_types::doc::WellKnownFacetTag::TitleGeneric.into(),
-                        daybook_types::doc::WellKnownFacet::TitleGeneric("Untitled".into()).into(),
-                    ),
-                ]
-                .into(),
-                user_path: Some(daybook_types::doc::UserPathBuf::from(
-                    ctx.local_user_path.clone(),
-                )),
-            };
-            let id = drawer_repo.add(doc).await?;
+            let id = drawer_repo
+                .add(untitled_doc_args(ctx.local_user_path.clone()))
+                .await?;
             info!(id, "created document");
             println!("{id}");
         }
@@ -287,63 +237,166 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
                 error!("document not found: {id}");
                 return Ok(ExitCode::FAILURE);
             };
-            let branch_path = match &branch {
-                Some(val) => {
-                    if branches.branches.contains_key(val) {
-                        error!("branch not found for doc: {id} - {val}");
-                        return Ok(ExitCode::FAILURE);
-                    }
-                    daybook_types::doc::BranchPathBuf::from(val.as_str())
-                }
-                None => {
-                    let Some(branch) = branches.main_branch_path() else {
-                        error!(doc_id = ?branches.doc_id,"no branches found on doc");
-                        return Ok(ExitCode::FAILURE);
-                    };
-                    branch
-                }
+            let Some(branch_path) = resolve_branch(&branches, branch.as_deref()) else {
+                return Ok(ExitCode::FAILURE);
             };
             let Some((doc, heads)) = drawer_repo.get_with_heads(&id, &branch_path, None).await?
             else {
                 eyre::bail!("Document not found: {id}");
             };
-
-            let content = serde_json::to_string_pretty(&*doc)?;
-
-            // Create temporary file
-            // TODO: replace with tempfile crate usage
-            let tmp_dir = std::env::temp_dir();
-            let tmp_path = tmp_dir.join(format!("daybook-edit-{}.json", id));
-            tokio::fs::write(&tmp_path, &content).await?;
-
-            // Open editor
-            let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
-            let status = std::process::Command::new(editor).arg(&tmp_path).status()?;
-
-            if !status.success() {
-                eyre::bail!("Editor exited with failure");
-            }
-
-            // Read back and compare
-            let new_content = tokio::fs::read_to_string(&tmp_path).await?;
-            let new_doc: daybook_types::doc::Doc = serde_json::from_str(&new_content)
-                .wrap_err("Failed to parse modified document as JSON")?;
-
-            let mut patch = daybook_types::doc::Doc::diff(&doc, &new_doc);
-            if patch.is_empty() {
-                println!("No changes detected.");
-            } else {
-                patch.id = id.clone();
+            if let Some(patch) = edit_doc(&id, &doc).await? {
                 drawer_repo
                     .update_at_heads(patch, "main".into(), Some(heads))
                     .await?;
                 println!("Updated document: {id}");
             }
-
-            // Cleanup
-            tokio::fs::remove_file(&tmp_path).await?;
+        }
+        StaticCommands::Conflicts { id, branch } => {
+            let entries = match id {
+                Some(id) => {
+                    let Some(branches) = drawer_repo.get_doc_branches(&id).await? else {
+                        error!("document not found: {id}");
+                        return Ok(ExitCode::FAILURE);
+                    };
+                    vec![branches]
+                }
+                None => drawer_repo.list().await?,
+            };
+            let mut conflicts = Vec::new();
+            for entry in entries {
+                let Some(branch_path) = resolve_branch(&entry, branch.as_deref()) else {
+                    continue;
+                };
+                for conflict in drawer_repo
+                    .get_facet_conflicts(&entry.doc_id, &branch_path)
+                    .await?
+                {
+                    conflicts.push((entry.doc_id.clone(), branch_path.clone(), conflict));
+                }
+            }
+            print_conflicts_table(&conflicts)?;
+        }
+        StaticCommands::Resolve {
+            id,
+            facet,
+            value,
+            path,
+            branch,
+        } => {
+            let Some(branches) = drawer_repo.get_doc_branches(&id).await? else {
+                error!("document not found: {id}");
+                return Ok(ExitCode::FAILURE);
+            };
+            let Some(branch_path) = resolve_branch(&branches, branch.as_deref()) else {
+                return Ok(ExitCode::FAILURE);
+            };
+            let value: serde_json::Value =
+                serde_json::from_str(&value).wrap_err("value is not valid JSON")?;
+            drawer_repo
+                .resolve_facet_conflict(
+                    &id,
+                    &branch_path,
+                    &daybook_types::doc::FacetKey::from(facet.as_str()),
+                    &path,
+                    value,
+                    Some(&*ctx.local_user_path),
+                )
+                .await?;
+            println!("Resolved {facet}{path} on document: {id}");
+        }
+        StaticCommands::Gc { dry_run } => {
+            let gc_repo = daybook_core::gc::GcRepo::load(
+                Arc::clone(&ctx),
+                Arc::clone(&drawer_repo),
+                lazy::dispatch_repo().await?,
+            )
+            .await?;
+            let report = gc_repo.run(dry_run).await?;
+            let verb = if dry_run {
+                "Would reclaim"
+            } else {
+                "Reclaimed"
+            };
+            println!(
+                "{verb} {} bytes: {} branch docs purged, {} docs compacted, {} staging branches deleted",
+                report.bytes_reclaimed,
+                report.docs_purged,
+                report.docs_compacted,
+                report.staging_branches_deleted
+            );
+            if report.docs_awaiting_sync > 0 {
+                println!(
+                    "{} deleted branch docs are kept until every known device has synced",
+                    report.docs_awaiting_sync
+                );
+            }
+        }
+        StaticCommands::Doctor { repair } => {
+            let rt = Box::pin(lazy::daybook_rt()).await?;
+            let doctor = daybook_core::doctor::Doctor::load(rt).await?;
+            let report = doctor.run(repair).await?;
+            for finding in &report.findings {
+                let note = if finding.repairable() {
+                    ""
+                } else {
+                    " (not repairable)"
+                };
+                println!("{finding}{note}");
+            }
+            if report.findings.is_empty() {
+                println!("No inconsistencies found");
+            } else if repair {
+                println!(
+                    "Repaired {} of {} inconsistencies",
+                    report.repaired,
+                    report.findings.len()
+                );
+            } else {
+                println!(
+                    "Found {} inconsistencies, rerun with --repair to fix them",
+                    report.findings.len()
+                );
+            }
         }
         StaticCommands::Sync {
+            command: Some(command),
+            ..
+        } => {
+            let sync_repo = lazy::sync_repo().await?;
+            match command {
+                SyncCommands::ExportBundle { path, since } => {
+                    let report = sync_repo.export_bundle(&path, since).await?;
+                    println!(
+                        "Wrote {} events, {} docs and {} blobs to {}",
+                        report.event_count,
+                        report.doc_count,
+                        report.blob_count,
+                        path.display()
+                    );
+                    if report.skipped_count > 0 {
+                        warn!(
+                            skipped = report.skipped_count,
+                            "objects not held locally were left out of the bundle"
+                        );
+                    }
+                }
+                SyncCommands::ImportBundle { path } => {
+                    let report = sync_repo
+                        // TODO: parametrize timeout
+                        .import_bundle(&path, std::time::Duration::from_secs(300))
+                        .await?;
+                    println!(
+                        "Applied {} events, {} docs and {} blobs from {}",
+                        report.event_count,
+                        report.doc_count,
+                        report.blob_count,
+                        path.display()
+                    );
+                }
+            }
+        }
+        StaticCommands::Sync {
+            command: None,
             sync_urls,
             exit_when_synced,
         } => {
@@ -398,6 +451,9 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
             } else {
                 let listener = sync_repo.subscribe(daybook_core::repos::SubscribeOpts::new(512));
                 sync_repo.connect_known_devices_once().await?;
+                if let Err(err) = sync_repo.start_default_lan_discovery().await {
+                    warn!(?err, "lan discovery unavailable");
+                }
                 loop {
                     tokio::select! {
                         _ = tokio::signal::ctrl_c() => {
@@ -457,6 +513,38 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
                                         IrohSyncEvent::StalePeer { peer_key } => {
                                             warn!(%peer_key, "stale sync peer");
                                         }
+                                        IrohSyncEvent::PairingCandidateDiscovered {
+                                            peer_key,
+                                            device_name,
+                                        } => {
+                                            info!(
+                                                %peer_key,
+                                                %device_name,
+                                                "unknown device found on the local network"
+                                            );
+                                        }
+                                        IrohSyncEvent::UpdateRequired {
+                                            peer_key,
+                                            protocol,
+                                            local_needs_update,
+                                            remote_app_version,
+                                        } => {
+                                            if *local_needs_update {
+                                                warn!(
+                                                    %peer_key,
+                                                    %protocol,
+                                                    %remote_app_version,
+                                                    "peer runs a newer sync protocol, update daybook to sync with it"
+                                                );
+                                            } else {
+                                                warn!(
+                                                    %peer_key,
+                                                    %protocol,
+                                                    %remote_app_version,
+                                                    "peer runs an outdated sync protocol and needs updating"
+                                                );
+                                            }
+                                        }
                                     }
                                 }
                                 Err(err) => {
@@ -482,16 +570,104 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
                     let mut table = Table::new();
                     table
                         .load_preset(NOTHING)
-                        .set_header(vec!["Endpoint", "Name", "Added At"]);
+                        .set_header(vec!["Endpoint", "Name", "Added At", "Access"]);
                     for device in devices {
+                        let mut access = match &device.access.partitions {
+                            Some(partitions) => partitions.join(","),
+                            None => "all".to_string(),
+                        };
+                        if device.access.read_only {
+                            access.push_str(" (read-only)");
+                        }
                         table.add_row(vec![
                             utils_rs::hash::encode_base58_multibase(device.endpoint_id),
                             device.name,
                             device.added_at.to_string(),
+                            access,
                         ]);
                     }
                     println!("{table}");
                 }
+                DevicesCommands::Status => {
+                    use comfy_table::presets::NOTHING;
+                    use comfy_table::Table;
+
+                    let names = config_repo
+                        .list_known_sync_devices()
+                        .await?
+                        .into_iter()
+                        .map(|device| {
+                            (
+                                daybook_types::doc::format_peer_key(device.endpoint_id.as_bytes()),
+                                device.name,
+                            )
+                        })
+                        .collect::<HashMap<_, _>>();
+                    let statuses = daybook_core::sync::read_sync_status(&ctx).await?;
+                    let fmt_ts = |ts: Option<Timestamp>| {
+                        ts.map(|ts| ts.to_string())
+                            .unwrap_or_else(|| "never".into())
+                    };
+
+                    let mut table = Table::new();
+                    table.load_preset(NOTHING).set_header(vec![
+                        "Device",
+                        "Last Connected",
+                        "Last Full Sync",
+                        "Stale Since",
+                        "Received",
+                        "Sent",
+                        "Blob Backlog",
+                        "Last Error",
+                    ]);
+                    let mut parts_table = Table::new();
+                    parts_table.load_preset(NOTHING).set_header(vec![
+                        "Device",
+                        "Partition",
+                        "Last Full Sync",
+                        "Stale Since",
+                        "Cursor Lag",
+                    ]);
+                    for status in statuses {
+                        let device = names
+                            .get(&status.peer_key)
+                            .cloned()
+                            .unwrap_or_else(|| status.peer_key.to_string());
+                        table.add_row(vec![
+                            device.clone(),
+                            fmt_ts(status.last_connected_at),
+                            fmt_ts(status.last_full_sync_at),
+                            status
+                                .stale_since
+                                .map(|ts| ts.to_string())
+                                .unwrap_or_default(),
+                            format!("{} B", status.blob_bytes_received),
+                            format!("{} B", status.rpc_bytes_sent),
+                            status.blob_backlog.to_string(),
+                            match (status.last_error, status.last_error_at) {
+                                (Some(err), Some(at)) => format!("{at}: {err}"),
+                                (Some(err), None) => err,
+                                _ => String::new(),
+                            },
+                        ]);
+                        for part in status.partitions {
+                            parts_table.add_row(vec![
+                                device.clone(),
+                                part.partition,
+                                fmt_ts(part.last_full_sync_at),
+                                part.stale_since
+                                    .map(|ts| ts.to_string())
+                                    .unwrap_or_default(),
+                                part.cursor_lag
+                                    .map(|lag| lag.to_string())
+                                    .unwrap_or_else(|| "?".into()),
+                            ]);
+                        }
+                    }
+                    println!("{table}");
+                    println!();
+                    println!("{parts_table}");
+                }
                 DevicesCommands::Add {
                     iroh_ticket_url,
                     name,
@@ -526,22 +702,116 @@ async fn static_cli(cli: Cli) -> Res<ExitCode> {
                             name: device_name,
                             added_at: Timestamp::now(),
                             last_connected_at: None,
+                            access: default(),
+                            storage_peer: false,
+                        })
+                        .await?;
+                }
+                DevicesCommands::AddStorage { endpoint, name } => {
+                    let endpoint_id: iroh::EndpointId = endpoint
+                        .parse()
+                        .map_err(|err| ferr!("invalid endpoint id '{endpoint}': {err}"))?;
+                    config_repo
+                        .upsert_known_sync_device(daybook_core::repo::globals::SyncDeviceEntry {
+                            endpoint_id,
+                            name: name.unwrap_or_else(|| "storage".to_string()),
+                            added_at: Timestamp::now(),
+                            last_connected_at: None,
+                            access: default(),
+                            storage_peer: true,
                         })
                         .await?;
+                    if daybook_core::sync::read_vault_key(&ctx).await?.is_none() {
+                        println!("no vault key on this device yet, run `vault enable` first");
+                    }
+                }
+                DevicesCommands::Access {
+                    endpoint,
+                    read_only,
+                    partitions,
+                } => {
+                    let endpoint_id = resolve_device_endpoint(&config_repo, &endpoint).await?;
+                    let known = config_repo
+                        .set_known_sync_device_access(
+                            &endpoint_id,
+                            daybook_core::repo::globals::PeerAccess {
+                                read_only,
+                                partitions: (!partitions.is_empty()).then_some(partitions),
+                            },
+                        )
+                        .await?;
+                    if !known {
+                        eyre::bail!("no known device with endpoint {endpoint}");
+                    }
+                }
+                DevicesCommands::Revoke { endpoint } => {
+                    let endpoint_id = resolve_device_endpoint(&config_repo, &endpoint).await?;
+                    daybook_core::sync::revoke_device(&ctx, &config_repo, endpoint_id).await?;
+                    println!("revoked {endpoint}, other devices refuse it once they sync");
+                }
+                DevicesCommands::RotateIdentity => {
+                    let new_endpoint_id =
+                        daybook_core::sync::rotate_identity(&ctx, &config_repo).await?;
+                    println!(
+                        "new endpoint {} is used the next time the repo is opened",
+                        utils_rs::hash::encode_base58_multibase(new_endpoint_id)
+                    );
                 }
             }
         }
+        StaticCommands::Vault { command } => match command {
+            VaultCommands::Enable => {
+                let key = daybook_core::sync::enable_vault(&ctx).await?;
+                println!("{}", key.to_base58());
+                println!();
+                println!("import this key on your other devices with `vault import-key`");
+            }
+            VaultCommands::ExportKey => {
+                let Some(key) = daybook_core::sync::read_vault_key(&ctx).await? else {
+                    eyre::bail!("no vault key on this device, run `vault enable` first");
+                };
+                println!("{}", key.to_base58());
+            }
+            VaultCommands::ImportKey { key } => {
+                let key = daybook_core::sync::VaultKey::from_base58(&key)?;
+                daybook_core::sync::import_vault_key(&ctx, &key).await?;
+            }
+        },
     }
 
     Ok(ExitCode::SUCCESS)
 }
 
-async fn clone_repo_from_url(source_url: &str, destination: &std::path::Path) -> Res<()> {
+/// Matches `endpoint` against the known devices as listed by `devices ls`,
+/// falling back to parsing it as an endpoint id.
+async fn resolve_device_endpoint(
+    config_repo: &daybook_core::config::ConfigRepo,
+    endpoint: &str,
+) -> Res<iroh::EndpointId> {
+    let known = config_repo
+        .list_known_sync_devices()
+        .await?
+        .into_iter()
+        .find(|device| utils_rs::hash::encode_base58_multibase(device.endpoint_id) == endpoint);
+    if let Some(device) = known {
+        return Ok(device.endpoint_id);
+    }
+    endpoint
+        .parse()
+        .map_err(|err| ferr!("no known device with endpoint {endpoint}: {err}"))
+}
+
+async fn clone_repo_from_url(
+    source_url: &str,
+    destination: &std::path::Path,
+    shallow: bool,
+) -> Res<()> {
     let res = daybook_core::sync::clone_repo_init_from_url(
         source_url,
         destination,
         daybook_core::sync::CloneRepoInitOptions {
             timeout: std::time::Duration::from_secs(30),
+            shallow,
         },
     )
     .await?;
@@ -557,6 +827,310 @@ async fn clone_repo_from_url(source_url: &str, destination: &std::path::Path) ->
     Ok(())
 }
 
+async fn encryption_cli(repo_path: &std::path::Path, command: &EncryptionCommands) -> Res<()> {
+    use daybook_core::repo::encryption;
+    match command {
+        EncryptionCommands::Status => match encryption::read_header(repo_path).await? {
+            None => println!("not encrypted"),
+            Some(header) => match header.key_slot {
+                encryption::KeySlot::Keyring { .. } => {
+                    println!("encrypted, key kept in the OS keyring")
+                }
+                encryption::KeySlot::Passphrase { .. } => {
+                    println!("encrypted, key protected by a passphrase")
+                }
+            },
+        },
+        EncryptionCommands::Enable { passphrase } => {
+            let source = if *passphrase {
+                encryption::KeySource::Passphrase(read_new_passphrase()?)
+            } else {
+                encryption::KeySource::Keyring
+            };
+            encryption::enable(repo_path, source).await?;
+            println!("repo is now encrypted");
+            if *passphrase {
+                println!("set ${} to open the repo", crate::context::PASSPHRASE_ENV);
+            }
+        }
+        EncryptionCommands::ChangePassphrase => {
+            let old = read_passphrase("current passphrase: ")?;
+            let new = read_new_passphrase()?;
+            encryption::change_passphrase(repo_path, &old, &new).await?;
+            println!("passphrase changed");
+        }
+    }
+    Ok(())
+}
+
+// FIXME: input is echoed back
+fn read_passphrase(prompt: &str) -> Res<String> {
+    use std::io::Write;
+    eprint!("{prompt}");
+    std::io::stderr().flush()?;
+    let mut line = String::new();
+    std::io::stdin().read_line(&mut line)?;
+    let passphrase = line.trim_end_matches(['\r', '\n']).to_string();
+    if passphrase.is_empty() {
+        eyre::bail!("empty passphrase");
+    }
+    Ok(passphrase)
+}
+
+fn read_new_passphrase() -> Res<String> {
+    let passphrase = read_passphrase("new passphrase: ")?;
+    if read_passphrase("repeat passphrase: ")? != passphrase {
+        eyre::bail!("passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+/// Runs commands through the daemon of the process that has the repo open.
+/// Only the drawer commands are served.
+async fn attached_cli(repo_path: &std::path::Path, command: StaticCommands) -> Res<ExitCode> {
+    let Some(client) = daybook_core::daemon::DaemonClient::attach(repo_path).await? else {
+        eyre::bail!(
+            "repo is in use by another process that isn't serving it: {}",
+            repo_path.display()
+        );
+    };
+    info!(
+        pid = client.repo.pid,
+        "repo is in use, attached to the daemon of its process"
+    );
+    let res = async {
+        match command {
+            StaticCommands::Ls => {
+                let mut docs = Vec::new();
+                for entry in client.list().await? {
+                    let Some(main_branch) = entry.main_branch_path() else {
+                        warn!(doc_id = ?entry.doc_id,"no branches found on doc");
+                        continue;
+                    };
+                    if let Some((doc, _heads)) =
+                        client.get_with_heads(&entry.doc_id, &main_branch).await?
+                    {
+                        docs.push((entry, doc));
+                    }
+                }
+                print_docs_table(&docs);
+            }
+            StaticCommands::Cat { id, branch } => {
+                let Some(branches) = client.get_doc_branches(&id).await? else {
+                    error!("document not found: {id}");
+                    return Ok(ExitCode::FAILURE);
+                };
+                let Some(branch_path) = resolve_branch(&branches, branch.as_deref()) else {
+                    return Ok(ExitCode::FAILURE);
+                };
+                let (doc, _heads) = client
+                    .get_with_heads(&id, &branch_path)
+                    .await?
+                    .expect("document from entry missing");
+                print_doc(&doc)?;
+            }
+            StaticCommands::Touch => {
+                let id = client
+                    .add(untitled_doc_args(client.repo.local_user_path.clone()))
+                    .await?;
+                info!(id, "created document");
+                println!("{id}");
+            }
+            StaticCommands::Ed { id, branch } => {
+                let Some(branches) = client.get_doc_branches(&id).await? else {
+                    error!("document not found: {id}");
+                    return Ok(ExitCode::FAILURE);
+                };
+                let Some(branch_path) = resolve_branch(&branches, branch.as_deref()) else {
+                    return Ok(ExitCode::FAILURE);
+                };
+                let Some((doc, heads)) = client.get_with_heads(&id, &branch_path).await? else {
+                    eyre::bail!("Document not found: {id}");
+                };
+                if let Some(patch) = edit_doc(&id, &doc).await? {
+                    client
+                        .update_at_heads(patch, "main".into(), Some(heads))
+                        .await?;
+                    println!("Updated document: {id}");
+                }
+            }
+            command => {
+                eyre::bail!(
+                    "{command:?} needs the repo to itself, close the process with pid={} first",
+                    client.repo.pid
+                );
+            }
+        }
+        eyre::Ok(ExitCode::SUCCESS)
+    }
+    .await;
+    client.close().await;
+    res
+}
+
+fn untitled_doc_args(
+    local_user_path: daybook_types::doc::UserPathBuf,
+) -> daybook_types::doc::AddDocArgs {
+    daybook_types::doc::AddDocArgs {
+        branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+        facets: [
+            //
+            (
+                daybook_types::doc::WellKnownFacetTag::TitleGeneric.into(),
+                daybook_types::doc::WellKnownFacet::TitleGeneric("Untitled".into()).into(),
+            ),
+        ]
+        .into(),
+        user_path: Some(local_user_path),
+    }
+}
+
+/// Branch to show for `--branch`, the main branch if none is given.
+fn resolve_branch(
+    branches: &daybook_core::drawer::DocNBranches,
+    branch: Option<&str>,
+) -> Option<daybook_types::doc::BranchPathBuf> {
+    match branch {
+        Some(val) => {
+            if !branches.branches.contains_key(val) {
+                error!("branch not found for doc: {} - {val}", branches.doc_id);
+                return None;
+            }
+            Some(daybook_types::doc::BranchPathBuf::from(val))
+        }
+        None => {
+            let branch = branches.main_branch_path();
+            if branch.is_none() {
+                error!(doc_id = ?branches.doc_id,"no branches found on doc");
+            }
+            branch
+        }
+    }
+}
+
+fn print_docs_table(docs: &[(daybook_core::drawer::DocNBranches, daybook_types::doc::Doc)]) {
+    use comfy_table::presets::NOTHING;
+    use comfy_table::Table;
+    use daybook_types::doc::{WellKnownFacet, WellKnownFacetTag};
+
+    let mut table = Table::new();
+    table
+        .load_preset(NOTHING)
+        .set_header(vec!["ID", "Title", "Branches"]);
+
+    for (entry, doc) in docs {
+        let title = doc
+            .facets
+            .get(&WellKnownFacetTag::TitleGeneric.into())
+            .map(|val| {
+                match WellKnownFacet::from_json(val.clone(), WellKnownFacetTag::TitleGeneric) {
+                    Ok(WellKnownFacet::TitleGeneric(str)) => str.clone(),
+                    _ => panic!("tag - facet mismatch"),
+                }
+            })
+            .unwrap_or_else(|| "<no title>".to_string());
+        table.add_row(vec![
+            entry.doc_id.clone(),
+            title,
+            entry
+                .branches
+                .keys()
+                .map(|key| key.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+        ]);
+    }
+    println!("{table}");
+}
+
+fn print_conflicts_table(
+    conflicts: &[(
+        daybook_types::doc::DocId,
+        daybook_types::doc::BranchPathBuf,
+        daybook_core::drawer::FacetConflict,
+    )],
+) -> Res<()> {
+    use comfy_table::presets::NOTHING;
+    use comfy_table::Table;
+
+    let mut table = Table::new();
+    table.load_preset(NOTHING).set_header(vec![
+        "ID", "Branch", "Facet", "Path", "Kept", "User", "Updated", "Value",
+    ]);
+
+    for (doc_id, branch_path, conflict) in conflicts {
+        let values = [(true, &conflict.winner)]
+            .into_iter()
+            .chain(conflict.losers.iter().map(|value| (false, value)));
+        for (kept, value) in values {
+            table.add_row(vec![
+                doc_id.clone(),
+                branch_path.to_string(),
+                conflict.facet_key.to_string(),
+                conflict.path.clone(),
+                if kept { "*".into() } else { String::new() },
+                value
+                    .user_path
+                    .as_ref()
+                    .map(ToString::to_string)
+                    .unwrap_or_else(|| value.actor_id.clone()),
+                value
+                    .updated_at
+                    .map(|ts| ts.to_string())
+                    .unwrap_or_default(),
+                serde_json::to_string(&value.value)?,
+            ]);
+        }
+    }
+    println!("{table}");
+    Ok(())
+}
+
+fn print_doc(doc: &daybook_types::doc::Doc) -> Res<()> {
+    println!("{:#?}", doc);
+    println!("{}", serde_json::to_string_pretty(doc)?);
+    Ok(())
+}
+
+/// Opens the doc in `$EDITOR` and returns what was changed.
+async fn edit_doc(
+    id: &str,
+    doc: &daybook_types::doc::Doc,
+) -> Res<Option<daybook_types::doc::DocPatch>> {
+    let content = serde_json::to_string_pretty(doc)?;
+
+    // Create temporary file
+    // TODO: replace with tempfile crate usage
+    let tmp_dir = std::env::temp_dir();
+    let tmp_path = tmp_dir.join(format!("daybook-edit-{}.json", id));
+    tokio::fs::write(&tmp_path, &content).await?;
+
+    // Open editor
+    let editor = std::env::var("EDITOR").unwrap_or_else(|_| "vi".to_string());
+    let status = std::process::Command::new(editor).arg(&tmp_path).status()?;
+
+    if !status.success() {
+        eyre::bail!("Editor exited with failure");
+    }
+
+    // Read back and compare
+    let new_content = tokio::fs::read_to_string(&tmp_path).await?;
+    let new_doc: daybook_types::doc::Doc =
+        serde_json::from_str(&new_content).wrap_err("Failed to parse modified document as JSON")?;
+
+    let mut patch = daybook_types::doc::Doc::diff(doc, &new_doc);
+
+    // Cleanup
+    tokio::fs::remove_file(&tmp_path).await?;
+
+    if patch.is_empty() {
+        println!("No changes detected.");
+        return Ok(None);
+    }
+    patch.id = id.to_string();
+    Ok(Some(patch))
+}
+
 async fn dynamic_cli(static_res: StaticCliResult) -> Res<ExitCode> {
     let conf = lazy::config().await?;
 
@@ -651,7 +1225,13 @@ async fn dynamic_cli(static_res: StaticCliResult) -> Res<ExitCode> {
         | Ok(StaticCommands::Clone { .. })
         | Ok(StaticCommands::Cat { .. })
         | Ok(StaticCommands::Ed { .. })
+        | Ok(StaticCommands::Conflicts { .. })
+        | Ok(StaticCommands::Resolve { .. })
+        | Ok(StaticCommands::Gc { .. })
+        | Ok(StaticCommands::Doctor { .. })
         | Ok(StaticCommands::Devices { .. })
+        | Ok(StaticCommands::Vault { .. })
+        | Ok(StaticCommands::Encryption { .. })
         | Ok(StaticCommands::Sync { .. }) => {
             unreachable!("static_cli will prevent these");
         }
@@ -711,6 +1291,10 @@ enum StaticCommands {
         source: String,
         /// Destination directory path (must be empty or non-existent)
         destination: String,
+        /// Only pull the core docs, doc contents arrive with later syncs
+        /// or when opened
+        #[arg(long)]
+        shallow: bool,
     },
     /// Dump full automerge contents
     Dump,
@@ -730,8 +1314,43 @@ enum StaticCommands {
         #[arg(short, long)]
         branch: Option<String>,
     },
+    /// List facet fields that were edited concurrently
+    Conflicts {
+        /// Only look at this document
+        id: Option<String>,
+        #[arg(short, long)]
+        branch: Option<String>,
+    },
+    /// Keep a value for a facet field that was edited concurrently
+    Resolve {
+        id: String,
+        /// Facet key as listed by `conflicts`
+        facet: String,
+        /// JSON value to keep
+        value: String,
+        /// JSON pointer into the facet as listed by `conflicts`
+        #[arg(long, default_value = "")]
+        path: String,
+        #[arg(short, long)]
+        branch: Option<String>,
+    },
+    /// Reclaim storage from deleted docs and branches and compact the rest
+    Gc {
+        /// Only report what would be reclaimed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Check derived state against the drawer
+    Doctor {
+        /// Rebuild the derived state found inconsistent
+        #[arg(long)]
+        repair: bool,
+    },
     /// Run one-shot iroh sync session
+    #[command(args_conflicts_with_subcommands = true)]
     Sync {
+        #[clap(subcommand)]
+        command: Option<SyncCommands>,
         /// Additional sync URLs to connect to (not persisted)
         sync_urls: Vec<String>,
         /// Exit once the requested peers are synced
@@ -743,6 +1362,16 @@ enum StaticCommands {
         #[clap(subcommand)]
         command: DevicesCommands,
     },
+    /// Manage the key for end-to-end encrypted storage peers
+    Vault {
+        #[clap(subcommand)]
+        command: VaultCommands,
+    },
+    /// Manage encryption of the repo's databases at rest
+    Encryption {
+        #[clap(subcommand)]
+        command: EncryptionCommands,
+    },
     /// Generate shell completions
     Completions {
         #[clap(value_enum)]
@@ -750,10 +1379,29 @@ enum StaticCommands {
     },
 }
 
+#[derive(Debug, clap::Subcommand)]
+enum SyncCommands {
+    /// Write changes for a device that can't connect into a bundle file
+    ExportBundle {
+        /// Bundle file to write
+        path: std::path::PathBuf,
+        /// Cursor of this device the receiving device has synced up to
+        #[arg(long, default_value_t = 0)]
+        since: u64,
+    },
+    /// Apply a bundle exported by another device
+    ImportBundle {
+        /// Bundle file to read
+        path: std::path::PathBuf,
+    },
+}
+
 #[derive(Debug, clap::Subcommand)]
 enum DevicesCommands {
     /// List known devices
     Ls,
+    /// Show how syncing with each device has been going
+    Status,
     /// Add a device from a bootstrap URL
     Add {
         /// Clone URL: db+iroh-clone:<endpoint-ticket>
@@ -762,6 +1410,63 @@ enum DevicesCommands {
         #[arg(long)]
         name: Option<String>,
     },
+    /// Add a storage peer that only ever holds sealed objects
+    AddStorage {
+        /// Endpoint id logged by `daybook_server --sealed-storage`
+        endpoint: String,
+        /// Override display name
+        #[arg(long)]
+        name: Option<String>,
+    },
+    /// Set what a known device is allowed to sync
+    Access {
+        /// Endpoint id as shown by `devices ls`
+        endpoint: String,
+        /// Never pull changes made on the device
+        #[arg(long)]
+        read_only: bool,
+        /// Partition the device can see. Repeat to allow several.
+        /// Every partition is allowed if none are given.
+        #[arg(long = "partition")]
+        partitions: Vec<String>,
+    },
+    /// Revoke a lost or compromised device on every device of the repo
+    Revoke {
+        /// Endpoint id as shown by `devices ls`
+        endpoint: String,
+    },
+    /// Replace this device's identity. Takes effect the next time the repo
+    /// is opened, sync first so that other devices learn the new endpoint.
+    RotateIdentity,
+}
+
+#[derive(Debug, clap::Subcommand)]
+enum VaultCommands {
+    /// Create this device's vault key and print it. Prints the existing key
+    /// if there's one already.
+    Enable,
+    /// Print this device's vault key
+    ExportKey,
+    /// Use a vault key created on another device
+    ImportKey {
+        /// Key as printed by `vault export-key`
+        key: String,
+    },
+}
+#[derive(Debug, clap::Subcommand)]
+enum EncryptionCommands {
+    /// Show whether the repo is encrypted and where its key is kept
+    Status,
+    /// Encrypt the repo. The repo can't be open elsewhere while this runs.
+    Enable {
+        /// Protect the key with a passphrase instead of keeping it in the
+        /// OS keyring. The passphrase is then read from $DAYBOOK_PASSPHRASE
+        /// whenever the repo is opened.
+        #[arg(long)]
+        passphrase: bool,
+    },
+    /// Change the passphrase of a passphrase protected repo
+    ChangePassphrase,
 }
 enum StaticCliResult {
     ClapErr(clap::Error),
@@ -974,13 +1679,19 @@ mod tests {
     }
 
     async fn open_cli_sync_node(repo_root: &std::path::Path) -> Res<CliSyncNode> {
-        let ctx = RepoCtx::open(repo_root, RepoOpenOptions {}, "cli-test-device".into()).await?;
-        let blobs_repo = BlobsRepo::new(
+        let ctx = RepoCtx::open(
+            repo_root,
+            RepoOpenOptions::default(),
+            "cli-test-device".into(),
+        )
+        .await?;
+        let blobs_repo = BlobsRepo::new_keyed(
             ctx.layout.blobs_root.clone(),
             ctx.local_user_path.clone(),
             Arc::new(daybook_core::blobs::PartitionStoreMembershipWriter::new(
                 Arc::clone(&ctx.part_store),
             )),
+            ctx.data_key.clone(),
         )
         .await?;
         let (plugs_repo, plugs_stop) = PlugsRepo::load(
@@ -994,6 +1705,7 @@ mod tests {
             Arc::clone(&ctx.big_repo),
             Arc::clone(&ctx.part_store),
             ctx.doc_drawer.document_id(),
+            Some(ctx.doc_app.document_id()),
             ctx.local_user_path.clone(),
             ctx.sql.clone(),
             ctx.layout.repo_root.join("local_state"),
@@ -1014,8 +1726,14 @@ mod tests {
             ctx.sql.clone(),
         )
         .await?;
-        let (sqlite_local_state_repo, sqlite_local_state_stop) =
-            SqliteLocalStateRepo::boot(ctx.layout.repo_root.join("local_state")).await?;
+        drawer_repo
+            .track_device_revocations(Arc::clone(&config_repo))
+            .await;
+        let (sqlite_local_state_repo, sqlite_local_state_stop) = SqliteLocalStateRepo::boot(
+            ctx.layout.repo_root.join("local_state"),
+            ctx.data_key.clone(),
+        )
+        .await?;
         let (doc_blobs_index_repo, doc_blobs_index_stop) = DocBlobsIndexRepo::boot(
             Arc::clone(&drawer_repo),
             Arc::clone(&blobs_repo),
@@ -1060,7 +1778,7 @@ mod tests {
         tokio::fs::create_dir_all(&repo_a_path).await?;
         let init = RepoCtx::init(
             &repo_a_path,
-            RepoOpenOptions {},
+            RepoOpenOptions::default(),
             "cli-test-repo".into(),
             "cli-test-device".into(),
         )
@@ -1119,6 +1837,7 @@ mod lazy {
     use crate::context::*;
     use daybook_core::blobs::BlobsRepo;
     use daybook_core::config::ConfigRepo;
+    use daybook_core::daemon::RepoDaemon;
     use daybook_core::drawer::DrawerRepo;
     use daybook_core::index::DocBlobsIndexRepo;
     use daybook_core::local_state::SqliteLocalStateRepo;
@@ -1230,17 +1949,35 @@ mod lazy {
         }
     }
 
+    /// Serves the repo to other processes for as long as we hold it.
+    pub async fn daemon() -> Res<Arc<RepoDaemon>> {
+        static DAEMON: tokio::sync::OnceCell<Arc<RepoDaemon>> = tokio::sync::OnceCell::const_new();
+        match DAEMON
+            .get_or_try_init(|| async {
+                let ctx = repo_ctx().await?;
+                let (daemon, stop) = RepoDaemon::serve(&ctx).await?;
+                register_shutdown(move || async move { stop.stop().await });
+                Ok(daemon)
+            })
+            .await
+        {
+            Ok(daemon) => Ok(Arc::clone(daemon)),
+            Err(err) => Err(err),
+        }
+    }
+
     pub async fn blobs_repo() -> Res<Arc<BlobsRepo>> {
         static BLOBS: tokio::sync::OnceCell<Arc<BlobsRepo>> = tokio::sync::OnceCell::const_new();
         match BLOBS
             .get_or_try_init(|| async {
                 let ctx = repo_ctx().await?;
-                let blobs = BlobsRepo::new(
+                let blobs = BlobsRepo::new_keyed(
                     ctx.layout.blobs_root.clone(),
                     ctx.local_user_path.clone(),
                     Arc::new(daybook_core::blobs::PartitionStoreMembershipWriter::new(
                         Arc::clone(&ctx.part_store),
                     )),
+                    ctx.data_key.clone(),
                 )
                 .await?;
                 register_shutdown({
@@ -1271,6 +2008,7 @@ mod lazy {
                 .await?;
                 plugs.ensure_system_plugs().await?;
                 register_shutdown(move || async move { plugs_stop.stop().await });
+                daemon().await?.set_plugs(Arc::clone(&plugs));
                 Ok(plugs)
             })
             .await
@@ -1290,6 +2028,7 @@ mod lazy {
                     Arc::clone(&ctx.big_repo),
                     Arc::clone(&ctx.part_store),
                     ctx.doc_drawer.document_id(),
+                    Some(ctx.doc_app.document_id()),
                     ctx.local_user_path.clone(),
                     ctx.sql.clone(),
                     ctx.layout.repo_root.join("local_state"),
@@ -1303,6 +2042,8 @@ mod lazy {
                 )
                 .await?;
                 register_shutdown(move || async move { drawer_stop.stop().await });
+                drawer.track_device_revocations(config_repo().await?).await;
+                daemon().await?.set_drawer(Arc::clone(&drawer));
                 Ok(drawer)
             })
             .await
@@ -1366,8 +2107,11 @@ mod lazy {
         match SQLITE_LOCAL_STATE
             .get_or_try_init(|| async {
                 let ctx = repo_ctx().await?;
-                let (repo, stop) =
-                    SqliteLocalStateRepo::boot(ctx.layout.repo_root.join("local_state")).await?;
+                let (repo, stop) = SqliteLocalStateRepo::boot(
+                    ctx.layout.repo_root.join("local_state"),
+                    ctx.data_key.clone(),
+                )
+                .await?;
                 register_shutdown(move || async move { stop.stop().await });
                 Ok(repo)
             })
@@ -1437,6 +2181,7 @@ mod lazy {
                 )
                 .await?;
                 register_shutdown(move || async move { stop.stop().await });
+                daemon().await?.set_sync(Arc::clone(&repo));
                 Ok(repo)
             })
             .await
@@ -1488,6 +2233,7 @@ mod lazy {
                     daybook_core::rt::RtConfig {
                         device_id: "main_todo".into(),
                         startup_progress_task_id: None,
+                        processor_filter: None,
                     },
                     Arc::clone(&ctx),
                     Arc::clone(&drawer),
@@ -1501,6 +2247,7 @@ mod lazy {
                 )
                 .await?;
                 register_shutdown(move || async move { stop.stop().await });
+                daemon().await?.set_rt(Arc::clone(&rt));
                 Ok(rt)
             })
             .await
@@ -57,6 +57,8 @@ class DocEditorStoreViewModel(private val drawerRepo: DrawerRepoFfi) : ViewModel
                     }
 
                     is DrawerEvent.DocAdded -> {}
+
+                    is DrawerEvent.FacetConflicts -> {}
                 }
             }
         }
@@ -108,6 +108,8 @@ class DrawerViewModel(val drawerRepo: DrawerRepoFfi) : ViewModel() {
                                 _selectedDocBundle.value = null
                             }
                         }
+
+                        is DrawerEvent.FacetConflicts -> {}
                     }
                 }
             }
@@ -8,20 +8,29 @@ use std::path::PathBuf;
 #[derive(Debug, Clone)]
 pub struct SqlConfig {
     database_path: Option<PathBuf>,
+    key: Option<crate::repo::encryption::DataKey>,
 }
 
 impl SqlConfig {
     pub fn file(database_path: impl Into<PathBuf>) -> Self {
         Self {
             database_path: Some(database_path.into()),
+            key: None,
         }
     }
 
     pub fn memory() -> Self {
         Self {
             database_path: None,
+            key: None,
         }
     }
+
+    /// Opens the database with SQLCipher under `key`.
+    pub fn with_key(mut self, key: Option<crate::repo::encryption::DataKey>) -> Self {
+        self.key = key;
+        self
+    }
 }
 
 pub async fn open_sql_ctx(config: SqlConfig) -> Res<SqlCtx> {
@@ -33,7 +42,8 @@ pub async fn open_sql_ctx(config: SqlConfig) -> Res<SqlCtx> {
                 })?;
             }
             let database_url = format!("sqlite://{}", database_path.display());
-            SqlCtx::url(&database_url).await?
+            let key = config.key.as_ref().map(|key| key.sqlcipher_key());
+            SqlCtx::url_keyed(&database_url, key.as_deref()).await?
         }
         None => SqlCtx::memory().await?,
     };
@@ -143,6 +153,7 @@ pub mod version_updates {
     use autosurgeon::reconcile_prop;
 
     use crate::config::ConfigStore;
+    use crate::drawer::DrawerShardsStore;
     use crate::plugs::PlugsStore;
     use crate::rt::init::InitStore;
     use crate::tables::TablesStore;
@@ -177,6 +188,12 @@ pub mod version_updates {
             InitStore::prop().as_ref(),
             InitStore::default(),
         )?;
+        reconcile_prop(
+            &mut doc,
+            ROOT,
+            DrawerShardsStore::prop().as_ref(),
+            DrawerShardsStore::default(),
+        )?;
         Ok(doc.save_nocompress())
     }
 }
@@ -3,13 +3,19 @@ use crate::interlude::*;
 use big_repo::SharedPartStore;
 use iroh_blobs::api::blobs::{AddPathOptions, ImportMode};
 use iroh_blobs::store::fs::FsStore;
+use iroh_blobs::store::mem::MemStore;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::path::{Component, Path};
 use tokio::io::AsyncWriteExt;
 
+use crate::repo::encryption::DataKey;
+
+mod cache;
 pub mod sync;
 
+pub use cache::BlobFetcher;
+
 #[async_trait]
 pub trait PartitionMembershipWriter: Send + Sync {
     async fn upsert_item(
@@ -84,6 +90,9 @@ pub struct BlobsRepo {
     iroh_store: iroh_blobs::api::Store,
     hash_locks: Arc<std::sync::Mutex<HashMap<BlobId, Arc<tokio::sync::Mutex<()>>>>>,
     partition_writer: Arc<dyn PartitionMembershipWriter>,
+    cache: Arc<std::sync::Mutex<cache::BlobCacheState>>,
+    /// Set in encrypted repos, see [`Self::new_keyed`].
+    data_key: Option<DataKey>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -91,6 +100,8 @@ pub struct BlobsRepo {
 enum BlobMode {
     OwnedCopy,
     Reference,
+    /// Evicted from the local cache, fetched again on demand.
+    MetadataOnly,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -104,6 +115,8 @@ struct BlobMetaV1 {
     source_paths: Vec<String>,
     created_at_unix_secs: i64,
     iroh_ingested: bool,
+    #[serde(default)]
+    remote_holders: Vec<PeerId>,
 }
 
 struct ObjectPaths {
@@ -195,22 +208,65 @@ impl BlobsRepo {
         root: PathBuf,
         src_local_user_path: UserPathBuf,
         partition_writer: Arc<dyn PartitionMembershipWriter>,
+    ) -> Result<Arc<Self>, eyre::Report> {
+        Self::new_keyed(root, src_local_user_path, partition_writer, None).await
+    }
+
+    /// Like [`Self::new`] but for encrypted repos when `data_key` is set.
+    /// Object and metadata files are then sealed under the key and the
+    /// iroh store is kept in memory, loaded from the objects at boot.
+    /// Paths handed out point to plaintext copies under `staging/` which
+    /// is wiped on boot and on [`Self::shutdown`].
+    pub async fn new_keyed(
+        root: PathBuf,
+        src_local_user_path: UserPathBuf,
+        partition_writer: Arc<dyn PartitionMembershipWriter>,
+        data_key: Option<DataKey>,
     ) -> Result<Arc<Self>, eyre::Report> {
         let objects_root = root.join("objects");
         tokio::fs::create_dir_all(&objects_root).await?;
-        let iroh_root = root.join("iroh");
-        tokio::fs::create_dir_all(&iroh_root).await?;
-        let fs_store = FsStore::load(&iroh_root)
-            .await
-            .map_err(|err| eyre::eyre!("error loading iroh fs store: {err:?}"))?;
+        let iroh_store: iroh_blobs::api::Store = if data_key.is_some() {
+            MemStore::new().into()
+        } else {
+            let iroh_root = root.join("iroh");
+            tokio::fs::create_dir_all(&iroh_root).await?;
+            FsStore::load(&iroh_root)
+                .await
+                .map_err(|err| eyre::eyre!("error loading iroh fs store: {err:?}"))?
+                .into()
+        };
 
-        Ok(Arc::new(Self {
+        let repo = Arc::new(Self {
             root,
             src_local_user_path,
-            iroh_store: fs_store.into(),
+            iroh_store,
             hash_locks: Arc::new(std::sync::Mutex::new(HashMap::new())),
             partition_writer,
-        }))
+            cache: default(),
+            data_key,
+        });
+        if repo.data_key.is_some() {
+            repo.load_sealed_objects().await?;
+        }
+        Ok(repo)
+    }
+
+    async fn load_sealed_objects(&self) -> Res<()> {
+        use std::str::FromStr;
+
+        for path in list_object_files(&self.root.join("objects"), "blob").await? {
+            let Some(blob_id) = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .and_then(|stem| BlobId::from_str(stem).ok())
+            else {
+                warn!(path = %path.display(), "skipping blob object with unexpected name");
+                continue;
+            };
+            let data = self.read_file(&path).await?;
+            self.ingest_bytes_with_iroh(data, blob_id).await?;
+        }
+        Ok(())
     }
 
     pub async fn add_hash_to_scope(&self, scope: BlobScope, blob_id: BlobId) -> Res<()> {
@@ -240,20 +296,34 @@ impl BlobsRepo {
 
             tokio::fs::create_dir_all(&object_paths.dir).await?;
             if !tokio::fs::try_exists(&object_paths.blob).await? {
-                self.atomic_copy_file(&source_snapshot, &object_paths.blob)
-                    .await?;
+                match &self.data_key {
+                    None => {
+                        self.atomic_copy_file(&source_snapshot, &object_paths.blob)
+                            .await?
+                    }
+                    Some(_) => {
+                        let data = tokio::fs::read(&source_snapshot).await?;
+                        self.write_file(&object_paths.blob, &data).await?;
+                    }
+                }
             }
 
-            let blob_meta = tokio::fs::metadata(&object_paths.blob).await?;
+            let snapshot_meta = tokio::fs::metadata(&source_snapshot).await?;
             let mut meta = self.build_meta(
                 hash,
                 BlobMode::OwnedCopy,
-                blob_meta.len(),
+                snapshot_meta.len(),
                 Vec::new(),
                 false,
             );
             self.write_meta(&object_paths.meta, &meta).await?;
-            self.ingest_path_with_iroh(&object_paths.blob, hash).await?;
+            // the object file is sealed in encrypted repos
+            let ingest_path = if self.data_key.is_some() {
+                &source_snapshot
+            } else {
+                &object_paths.blob
+            };
+            self.ingest_path_with_iroh(ingest_path, hash).await?;
             meta.iroh_ingested = true;
             self.write_meta(&object_paths.meta, &meta).await?;
             self.publish_use_hints(hash, use_hints).await?;
@@ -326,19 +396,22 @@ impl BlobsRepo {
 
         tokio::fs::create_dir_all(&object_paths.dir).await?;
         if !tokio::fs::try_exists(&object_paths.blob).await? {
-            self.atomic_write(&object_paths.blob, data).await?;
+            self.write_file(&object_paths.blob, data).await?;
         }
 
-        let blob_meta = tokio::fs::metadata(&object_paths.blob).await?;
         let mut meta = self.build_meta(
             hash,
             BlobMode::OwnedCopy,
-            blob_meta.len(),
+            data.len() as u64,
             Vec::new(),
             false,
         );
         self.write_meta(&object_paths.meta, &meta).await?;
-        self.ingest_path_with_iroh(&object_paths.blob, hash).await?;
+        if self.data_key.is_some() {
+            self.ingest_bytes_with_iroh(data.to_vec(), hash).await?;
+        } else {
+            self.ingest_path_with_iroh(&object_paths.blob, hash).await?;
+        }
         meta.iroh_ingested = true;
         self.write_meta(&object_paths.meta, &meta).await?;
         self.publish_use_hints(hash, use_hints).await?;
@@ -349,8 +422,9 @@ impl BlobsRepo {
     pub async fn get_path(&self, blob_id: BlobId) -> Result<PathBuf, eyre::Report> {
         let object_paths = self.object_paths(blob_id)?;
         if tokio::fs::try_exists(&object_paths.blob).await? {
+            let plain_path = self.plain_object_path(blob_id, &object_paths.blob).await?;
             if self.read_meta(&object_paths.meta).await?.is_none() {
-                let blob_meta = tokio::fs::metadata(&object_paths.blob).await?;
+                let blob_meta = tokio::fs::metadata(&plain_path).await?;
                 let recovered = self.build_meta(
                     blob_id,
                     BlobMode::OwnedCopy,
@@ -360,15 +434,17 @@ impl BlobsRepo {
                 );
                 self.write_meta(&object_paths.meta, &recovered).await?;
             }
-            return Ok(object_paths.blob);
+            self.touch_cached(blob_id);
+            return Ok(plain_path);
         }
 
         let Some(meta) = self.read_meta(&object_paths.meta).await? else {
-            eyre::bail!("Blob not found: {blob_id}");
+            return self.fetch_on_demand(blob_id, None).await;
         };
 
         match meta.mode {
             BlobMode::OwnedCopy => eyre::bail!("Blob not found: {blob_id}"),
+            BlobMode::MetadataOnly => self.fetch_on_demand(blob_id, Some(meta)).await,
             BlobMode::Reference => {
                 if meta.source_paths.is_empty() {
                     eyre::bail!("reference metadata missing source_paths");
@@ -384,7 +460,7 @@ impl BlobsRepo {
                     if source_hash == meta.hash {
                         return Ok(source_path);
                     } else if tokio::fs::try_exists(&object_paths.blob).await? {
-                        return Ok(object_paths.blob);
+                        return self.plain_object_path(blob_id, &object_paths.blob).await;
                     } else if drift_error.is_none() {
                         drift_error = Some(format!(
                             "Referenced blob hash diverged for {}: expected={}, got={}",
@@ -411,11 +487,34 @@ impl BlobsRepo {
             .shutdown()
             .await
             .map_err(|err| eyre::eyre!("error shutting down iroh blob store: {err:?}"))?;
+        if self.data_key.is_some() {
+            // plaintext copies of sealed objects
+            self.cleanup_staging().await?;
+        }
         Ok(())
     }
 
+    /// Whether the blob is available locally. Doesn't fetch from peers.
     pub async fn has_hash(&self, blob_id: BlobId) -> Res<bool> {
-        Ok(self.get_path(blob_id).await.is_ok())
+        let object_paths = self.object_paths(blob_id)?;
+        if tokio::fs::try_exists(&object_paths.blob).await? {
+            return Ok(true);
+        }
+        match self.read_meta(&object_paths.meta).await? {
+            None => Ok(false),
+            Some(meta) if meta.mode == BlobMode::MetadataOnly => Ok(false),
+            Some(_) => Ok(self.get_path(blob_id).await.is_ok()),
+        }
+    }
+
+    /// Whether only the metadata of the blob is left after an eviction
+    /// from the local cache.
+    pub async fn is_evicted(&self, blob_id: BlobId) -> Res<bool> {
+        let object_paths = self.object_paths(blob_id)?;
+        Ok(matches!(
+            self.read_meta(&object_paths.meta).await?,
+            Some(meta) if meta.mode == BlobMode::MetadataOnly
+        ))
     }
 
     pub async fn cleanup_staging(&self) -> Res<()> {
@@ -433,8 +532,26 @@ impl BlobsRepo {
         request: BlobMaterializeRequest,
     ) -> Res<PathBuf> {
         let hash = blob_hash_from_id(blob_id);
-        self.ensure_local_object_no_meta_rewrite(blob_id).await?;
-        let source_path = self.object_paths(blob_id)?.blob;
+        let object_paths = self.object_paths(blob_id)?;
+        match self.read_meta(&object_paths.meta).await? {
+            Some(meta) if meta.mode == BlobMode::MetadataOnly => {
+                self.fetch_on_demand(blob_id, Some(meta)).await?;
+            }
+            None if !tokio::fs::try_exists(&object_paths.blob).await?
+                && !self
+                    .iroh_store
+                    .blobs()
+                    .has(blob_id_to_iroh_hash(blob_id))
+                    .await? =>
+            {
+                self.fetch_on_demand(blob_id, None).await?;
+            }
+            _ => {
+                self.ensure_local_object_no_meta_rewrite(blob_id).await?;
+                self.touch_cached(blob_id);
+            }
+        }
+        let source_path = self.plain_object_path(blob_id, &object_paths.blob).await?;
         let filename = match request {
             BlobMaterializeRequest::Filename(name) => Self::sanitize_requested_filename(&name)?,
             BlobMaterializeRequest::Extension(ext) => {
@@ -494,41 +611,112 @@ impl BlobsRepo {
         let object_paths = self.object_paths(blob_id)?;
         tokio::fs::create_dir_all(&object_paths.dir).await?;
 
-        if !tokio::fs::try_exists(&object_paths.blob).await? {
-            let iroh_hash = blob_id_to_iroh_hash(blob_id);
-            self.iroh_store
-                .blobs()
-                .export(iroh_hash, &object_paths.blob)
-                .await
-                .map_err(|err| eyre::eyre!("error exporting blob from iroh store: {err:?}"))?;
-        }
+        let size_bytes = if tokio::fs::try_exists(&object_paths.blob).await? {
+            self.object_size(blob_id, &object_paths.blob).await?
+        } else {
+            self.export_object(blob_id, &object_paths.blob).await?
+        };
 
-        let blob_meta = tokio::fs::metadata(&object_paths.blob).await?;
-        let meta = self.build_meta(
-            blob_id,
-            BlobMode::OwnedCopy,
-            blob_meta.len(),
-            Vec::new(),
-            true,
-        );
+        let meta = self.build_meta(blob_id, BlobMode::OwnedCopy, size_bytes, Vec::new(), true);
         self.write_meta(&object_paths.meta, &meta).await?;
         self.publish_use_hints(blob_id, use_hints).await?;
 
         Ok(blob_id)
     }
 
+    /// Adds the bytes to the iroh store only, leaving it to
+    /// [`Self::put_from_store`] to make the blob owned. Used to hand blobs
+    /// received out of band to the blob sync backend.
+    pub async fn stage_in_store(&self, blob_id: BlobId, data: Vec<u8>) -> Res<()> {
+        if BlobId::new(*blake3::hash(&data).as_bytes()) != blob_id {
+            eyre::bail!("blob bytes don't match hash: {blob_id}");
+        }
+        if self
+            .iroh_store
+            .blobs()
+            .has(blob_id_to_iroh_hash(blob_id))
+            .await?
+        {
+            return Ok(());
+        }
+        self.ingest_bytes_with_iroh(data, blob_id).await
+    }
+
     async fn ensure_local_object_no_meta_rewrite(&self, blob_id: BlobId) -> Res<()> {
         let object_paths = self.object_paths(blob_id)?;
         tokio::fs::create_dir_all(&object_paths.dir).await?;
         if !tokio::fs::try_exists(&object_paths.blob).await? {
-            let iroh_hash = blob_id_to_iroh_hash(blob_id);
+            self.export_object(blob_id, &object_paths.blob).await?;
+        }
+        Ok(())
+    }
+
+    /// Writes the blob from the iroh store out to its object file.
+    /// Returns the size of the blob.
+    async fn export_object(&self, blob_id: BlobId, object_path: &Path) -> Res<u64> {
+        let iroh_hash = blob_id_to_iroh_hash(blob_id);
+        if self.data_key.is_none() {
             self.iroh_store
                 .blobs()
-                .export(iroh_hash, &object_paths.blob)
+                .export(iroh_hash, object_path)
                 .await
                 .map_err(|err| eyre::eyre!("error exporting blob from iroh store: {err:?}"))?;
+            return Ok(tokio::fs::metadata(object_path).await?.len());
+        }
+        let data = self
+            .iroh_store
+            .blobs()
+            .get_bytes(iroh_hash)
+            .await
+            .map_err(|err| eyre::eyre!("error reading blob from iroh store: {err:?}"))?;
+        self.write_file(object_path, &data).await?;
+        Ok(data.len() as u64)
+    }
+
+    async fn object_size(&self, blob_id: BlobId, object_path: &Path) -> Res<u64> {
+        let plain_path = self.plain_object_path(blob_id, object_path).await?;
+        Ok(tokio::fs::metadata(plain_path).await?.len())
+    }
+
+    /// Where the plaintext of an object file can be read from. Encrypted
+    /// repos open it into `staging/`.
+    async fn plain_object_path(&self, blob_id: BlobId, object_path: &Path) -> Res<PathBuf> {
+        if self.data_key.is_none() {
+            return Ok(object_path.to_path_buf());
+        }
+        let hash = blob_hash_from_id(blob_id);
+        let plain_path = self
+            .root
+            .join("staging")
+            .join(&hash)
+            .join(format!("{hash}.blob"));
+        if !tokio::fs::try_exists(&plain_path).await? {
+            let data = self.read_file(object_path).await?;
+            self.atomic_write(&plain_path, &data).await?;
+        }
+        Ok(plain_path)
+    }
+
+    /// Reads an object or metadata file, opening it in encrypted repos.
+    async fn read_file(&self, path: &Path) -> Res<Vec<u8>> {
+        let raw = tokio::fs::read(path).await?;
+        match &self.data_key {
+            None => Ok(raw),
+            Some(key) => key
+                .open(&raw, &sealed_file_aad(path))
+                .wrap_err_with(|| format!("error opening sealed blob file {}", path.display())),
+        }
+    }
+
+    /// Writes an object or metadata file, sealing it in encrypted repos.
+    async fn write_file(&self, path: &Path, data: &[u8]) -> Res<()> {
+        match &self.data_key {
+            None => self.atomic_write(path, data).await,
+            Some(key) => {
+                self.atomic_write(path, &key.seal(data, &sealed_file_aad(path))?)
+                    .await
+            }
         }
-        Ok(())
     }
 
     fn object_paths(&self, blob_id: BlobId) -> Res<ObjectPaths> {
@@ -641,7 +829,7 @@ impl BlobsRepo {
         if !tokio::fs::try_exists(path).await? {
             return Ok(None);
         }
-        let raw = tokio::fs::read(path).await?;
+        let raw = self.read_file(path).await?;
         let meta = serde_json::from_slice::<BlobMetaV1>(&raw)
             .wrap_err_with(|| format!("invalid blob metadata json at {}", path.display()))?;
         Ok(Some(meta))
@@ -649,7 +837,7 @@ impl BlobsRepo {
 
     async fn write_meta(&self, path: &Path, meta: &BlobMetaV1) -> Res<()> {
         let data = serde_json::to_vec(meta)?;
-        self.atomic_write(path, &data).await
+        self.write_file(path, &data).await
     }
 
     fn build_meta(
@@ -673,6 +861,7 @@ impl BlobsRepo {
                 .expect("system time should be after unix epoch")
                 .as_secs() as i64,
             iroh_ingested,
+            remote_holders: Vec::new(),
         }
     }
 
@@ -690,6 +879,16 @@ impl BlobsRepo {
         Ok(())
     }
 
+    async fn ingest_bytes_with_iroh(&self, data: Vec<u8>, blob_id: BlobId) -> Res<()> {
+        self.iroh_store
+            .blobs()
+            .add_bytes(data)
+            .with_named_tag(blob_hash_from_id(blob_id).as_bytes())
+            .await
+            .map_err(|err| eyre::eyre!("error adding blob bytes to iroh store: {err:?}"))?;
+        Ok(())
+    }
+
     async fn publish_use_hints(&self, blob_id: BlobId, use_hints: BlobUseHints) -> Res<()> {
         for scope in use_hints.scopes() {
             self.add_hash_to_scope(*scope, blob_id).await?;
@@ -808,6 +1007,57 @@ impl BlobsRepo {
 //     Ok(blob_id_to_iroh_hash(blob_id))
 // }
 
+/// Object and metadata files are sealed against their name so that they
+/// can't be swapped for one another.
+fn sealed_file_aad(path: &Path) -> Vec<u8> {
+    path.file_name()
+        .map(|name| name.as_encoded_bytes().to_vec())
+        .unwrap_or_default()
+}
+
+/// The files under `objects/` with the given extension.
+pub(crate) async fn list_object_files(objects_root: &Path, extension: &str) -> Res<Vec<PathBuf>> {
+    let mut out = vec![];
+    let mut dirs = vec![objects_root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match tokio::fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().and_then(|ext| ext.to_str()) == Some(extension) {
+                out.push(path);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Writes a sealed copy of every object and metadata file under
+/// `objects_root` next to it for [`crate::repo::encryption::enable`].
+/// Returns the pairs of sealed copy and the file it replaces.
+pub(crate) async fn seal_object_files(
+    objects_root: &Path,
+    key: &DataKey,
+) -> Res<Vec<(PathBuf, PathBuf)>> {
+    let mut out = vec![];
+    for extension in ["blob", "meta"] {
+        for path in list_object_files(objects_root, extension).await? {
+            let sealed = key.seal(&tokio::fs::read(&path).await?, &sealed_file_aad(&path))?;
+            let mut sealed_path = path.clone().into_os_string();
+            sealed_path.push(".sealing");
+            let sealed_path = PathBuf::from(sealed_path);
+            tokio::fs::write(&sealed_path, sealed).await?;
+            out.push((sealed_path, path));
+        }
+    }
+    Ok(out)
+}
+
 pub(crate) fn blob_id_to_iroh_hash(blob_id: BlobId) -> iroh_blobs::Hash {
     iroh_blobs::Hash::from_bytes(*blob_id.as_bytes())
 }
@@ -912,6 +1162,47 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn keyed_repo_seals_objects_and_loads_them_back() -> Res<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let key = DataKey::generate();
+        let boot = || {
+            BlobsRepo::new_keyed(
+                temp_dir.path().to_path_buf(),
+                "/local/test-user".into(),
+                Arc::new(NoopPartitionMembershipWriter),
+                Some(key.clone()),
+            )
+        };
+        let data = b"sealed blob bytes";
+
+        let repo = boot().await?;
+        let hash = repo.put(data, BlobUseHints::Unknown).await?;
+        assert_eq!(tokio::fs::read(repo.get_path(hash).await?).await?, data);
+        let object_paths = repo.object_paths(hash)?;
+        let raw = tokio::fs::read(&object_paths.blob).await?;
+        assert!(!raw.windows(data.len()).any(|window| window == data));
+        let raw_meta = tokio::fs::read(&object_paths.meta).await?;
+        assert!(serde_json::from_slice::<BlobMetaV1>(&raw_meta).is_err());
+        repo.shutdown().await?;
+        let staging_dir = temp_dir
+            .path()
+            .join("staging")
+            .join(blob_hash_from_id(hash));
+        assert!(!tokio::fs::try_exists(&staging_dir).await?);
+
+        let repo = boot().await?;
+        assert!(
+            repo.iroh_store()
+                .blobs()
+                .has(blob_id_to_iroh_hash(hash))
+                .await?
+        );
+        assert_eq!(tokio::fs::read(repo.get_path(hash).await?).await?, data);
+        repo.shutdown().await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn put_path_reference_breaks_if_source_deleted() -> Res<()> {
         let (repo, temp) = setup().await;
@@ -951,6 +1242,7 @@ mod tests {
             source_paths: vec!["/tmp/does/not/exist".to_string()],
             created_at_unix_secs: 1,
             iroh_ingested: true,
+            remote_holders: vec![],
         };
         repo.write_meta(&object_paths.meta, &bogus_ref).await?;
 
@@ -1039,6 +1331,66 @@ mod tests {
         Ok(())
     }
 
+    struct StoreFetcher {
+        store: iroh_blobs::api::Store,
+        data: HashMap<BlobId, Vec<u8>>,
+        fetched: std::sync::Mutex<Vec<(BlobId, Vec<PeerId>)>>,
+    }
+
+    #[async_trait]
+    impl BlobFetcher for StoreFetcher {
+        async fn fetch(&self, blob_id: BlobId, known_holders: &[PeerId]) -> Res<()> {
+            let data = self.data.get(&blob_id).ok_or_eyre("no peer has blob")?;
+            self.store
+                .blobs()
+                .add_bytes(data.clone())
+                .await
+                .map_err(|err| eyre::eyre!("iroh add bytes failed: {err:?}"))?;
+            self.fetched
+                .lock()
+                .expect(ERROR_MUTEX)
+                .push((blob_id, known_holders.to_vec()));
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn cache_quota_evicts_only_blobs_held_by_peers() -> Res<()> {
+        let (repo, _temp) = setup().await;
+        let peer = PeerId::new([7; 32]);
+        repo.set_cache_quota(Some(20)).await?;
+
+        let pinned = repo.put(b"pinned-0123456", BlobUseHints::Unknown).await?;
+        let first = repo.put(b"first-01234567", BlobUseHints::Unknown).await?;
+        let second = repo.put(b"second-0123456", BlobUseHints::Unknown).await?;
+        repo.note_remote_holder(first, peer).await?;
+        assert!(!repo.is_metadata_only(first).await?);
+        repo.note_remote_holder(second, peer).await?;
+
+        assert!(repo.is_metadata_only(first).await?);
+        assert!(!repo.has_hash(first).await?);
+        assert!(repo.has_hash(second).await?);
+        assert!(repo.has_hash(pinned).await?);
+        assert!(repo.get_path(first).await.is_err());
+
+        let fetcher = Arc::new(StoreFetcher {
+            store: repo.iroh_store(),
+            data: [(first, b"first-01234567".to_vec())].into(),
+            fetched: default(),
+        });
+        repo.set_fetcher(Some(Arc::clone(&fetcher) as _));
+        let path = repo.get_path(first).await?;
+        assert_eq!(tokio::fs::read(path).await?, b"first-01234567");
+        assert_eq!(
+            fetcher.fetched.lock().expect(ERROR_MUTEX).clone(),
+            vec![(first, vec![peer])]
+        );
+        // fetching it back pushed out the other evictable blob
+        assert!(repo.is_metadata_only(second).await?);
+        assert!(repo.has_hash(pinned).await?);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn legacy_put_api_still_works() -> Res<()> {
         let (repo, _temp) = setup().await;
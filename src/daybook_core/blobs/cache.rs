@@ -0,0 +1,227 @@
+//! Quota managed local blob cache.
+//!
+//! Owned blobs that some peer is known to hold can be evicted back to a
+//! metadata-only state once the local quota is hit. Metadata-only blobs are
+//! fetched again on demand through the installed [`BlobFetcher`]. Blobs no
+//! peer is known to hold are never tracked by the pool and thus never evicted.
+//!
+//! A peer is only recorded as a holder once it served us the blob. Partition
+//! membership is replicated so it says nothing about who has the bytes. This
+//! means the device that added a blob never evicts it.
+
+use crate::interlude::*;
+
+use super::{blob_hash_from_id, blob_id_to_iroh_hash, BlobId, BlobMetaV1, BlobMode, BlobsRepo};
+use crate::drawer::lru::KeyedLruPool;
+
+/// Pulls blobs that aren't available locally from peers.
+#[async_trait]
+pub trait BlobFetcher: Send + Sync {
+    /// `known_holders` are the peers that advertised the blob before, they
+    /// should be tried first.
+    async fn fetch(&self, blob_id: BlobId, known_holders: &[PeerId]) -> Res<()>;
+}
+
+#[derive(Default)]
+pub(super) struct BlobCacheState {
+    fetcher: Option<Arc<dyn BlobFetcher>>,
+    pool: Option<KeyedLruPool<BlobId>>,
+}
+
+impl BlobsRepo {
+    pub fn set_fetcher(&self, fetcher: Option<Arc<dyn BlobFetcher>>) {
+        let mut cache = self.cache.lock().expect(ERROR_MUTEX);
+        cache.fetcher = fetcher;
+    }
+
+    /// Sets the quota for evictable blobs in bytes and evicts down to it.
+    /// `None` disables eviction.
+    pub async fn set_cache_quota(&self, quota_bytes: Option<u64>) -> Res<()> {
+        let Some(quota_bytes) = quota_bytes else {
+            self.cache.lock().expect(ERROR_MUTEX).pool = None;
+            return Ok(());
+        };
+        let mut evictable = self.list_evictable().await?;
+        // oldest first so that the pool ends up with the newest as most recent
+        evictable.sort_by_key(|(_, _, created_at)| *created_at);
+        let pruned = {
+            let mut pool = KeyedLruPool::new(usize::try_from(quota_bytes).unwrap_or(usize::MAX));
+            let mut pruned = vec![];
+            for (blob_id, size_bytes, _) in evictable {
+                pruned.extend(pool.insert_key(&blob_id, size_bytes as usize));
+            }
+            self.cache.lock().expect(ERROR_MUTEX).pool = Some(pool);
+            pruned
+        };
+        for blob_id in pruned {
+            self.evict(blob_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Records that `peer_id` holds the blob. Once a peer is known to hold it,
+    /// an owned blob becomes eligible for eviction. No-op for blobs we
+    /// don't have metadata for.
+    pub async fn note_remote_holder(&self, blob_id: BlobId, peer_id: PeerId) -> Res<()> {
+        let object_paths = self.object_paths(blob_id)?;
+        let size_bytes = {
+            let hash_lock = self.lock_for_hash(blob_id);
+            let _hash_guard = hash_lock.lock().await;
+            let Some(mut meta) = self.read_meta(&object_paths.meta).await? else {
+                return Ok(());
+            };
+            if meta.remote_holders.contains(&peer_id) {
+                return Ok(());
+            }
+            meta.remote_holders.push(peer_id);
+            self.write_meta(&object_paths.meta, &meta).await?;
+            if meta.mode != BlobMode::OwnedCopy {
+                return Ok(());
+            }
+            meta.size_bytes
+        };
+        self.track_cached(blob_id, size_bytes).await
+    }
+
+    /// Whether the blob was evicted and only its metadata remains.
+    pub async fn is_metadata_only(&self, blob_id: BlobId) -> Res<bool> {
+        let object_paths = self.object_paths(blob_id)?;
+        Ok(self
+            .read_meta(&object_paths.meta)
+            .await?
+            .is_some_and(|meta| meta.mode == BlobMode::MetadataOnly))
+    }
+
+    pub(super) fn touch_cached(&self, blob_id: BlobId) {
+        let mut cache = self.cache.lock().expect(ERROR_MUTEX);
+        if let Some(pool) = cache.pool.as_mut() {
+            pool.touch_key(&blob_id);
+        }
+    }
+
+    async fn track_cached(&self, blob_id: BlobId, size_bytes: u64) -> Res<()> {
+        let pruned = {
+            let mut cache = self.cache.lock().expect(ERROR_MUTEX);
+            let Some(pool) = cache.pool.as_mut() else {
+                return Ok(());
+            };
+            pool.insert_key(&blob_id, size_bytes as usize)
+        };
+        for pruned_id in pruned {
+            // a blob larger than the whole quota is kept until something
+            // else gets used so that callers can use the path we just gave them
+            if pruned_id == blob_id {
+                continue;
+            }
+            self.evict(pruned_id).await?;
+        }
+        Ok(())
+    }
+
+    /// Fetches a blob that isn't available locally from peers.
+    pub(super) async fn fetch_on_demand(
+        &self,
+        blob_id: BlobId,
+        meta: Option<BlobMetaV1>,
+    ) -> Res<PathBuf> {
+        let object_paths = self.object_paths(blob_id)?;
+        let fetcher = self.cache.lock().expect(ERROR_MUTEX).fetcher.clone();
+        let Some(fetcher) = fetcher else {
+            eyre::bail!("Blob not found: {blob_id}");
+        };
+        let size_bytes = {
+            let hash_lock = self.lock_for_hash(blob_id);
+            let _hash_guard = hash_lock.lock().await;
+            if tokio::fs::try_exists(&object_paths.blob).await? {
+                return self.plain_object_path(blob_id, &object_paths.blob).await;
+            }
+            let iroh_hash = blob_id_to_iroh_hash(blob_id);
+            if !self.iroh_store.blobs().has(iroh_hash).await? {
+                let known_holders = meta
+                    .as_ref()
+                    .map(|meta| meta.remote_holders.clone())
+                    .unwrap_or_default();
+                fetcher
+                    .fetch(blob_id, &known_holders)
+                    .await
+                    .wrap_err_with(|| format!("error fetching blob on demand: {blob_id}"))?;
+            }
+            tokio::fs::create_dir_all(&object_paths.dir).await?;
+            let size_bytes = self.export_object(blob_id, &object_paths.blob).await?;
+            let meta = match meta {
+                Some(meta) => BlobMetaV1 {
+                    mode: BlobMode::OwnedCopy,
+                    size_bytes,
+                    iroh_ingested: true,
+                    ..meta
+                },
+                None => self.build_meta(blob_id, BlobMode::OwnedCopy, size_bytes, Vec::new(), true),
+            };
+            self.write_meta(&object_paths.meta, &meta).await?;
+            if meta.remote_holders.is_empty() {
+                return self.plain_object_path(blob_id, &object_paths.blob).await;
+            }
+            size_bytes
+        };
+        self.track_cached(blob_id, size_bytes).await?;
+        self.plain_object_path(blob_id, &object_paths.blob).await
+    }
+
+    /// Drops the local copy of a blob, keeping its metadata around. Returns
+    /// false if the blob isn't eligible for eviction.
+    async fn evict(&self, blob_id: BlobId) -> Res<bool> {
+        let hash_lock = self.lock_for_hash(blob_id);
+        let _hash_guard = hash_lock.lock().await;
+        let object_paths = self.object_paths(blob_id)?;
+        let Some(mut meta) = self.read_meta(&object_paths.meta).await? else {
+            return Ok(false);
+        };
+        if meta.mode != BlobMode::OwnedCopy || meta.remote_holders.is_empty() {
+            return Ok(false);
+        }
+        let hash = blob_hash_from_id(blob_id);
+        self.iroh_store
+            .tags()
+            .delete(hash.as_bytes())
+            .await
+            .map_err(|err| eyre::eyre!("error deleting iroh blob tag: {err:?}"))?;
+        self.iroh_store
+            .blobs()
+            .delete([blob_id_to_iroh_hash(blob_id)])
+            .await
+            .map_err(|err| eyre::eyre!("error deleting blob from iroh store: {err:?}"))?;
+        match tokio::fs::remove_file(&object_paths.blob).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        meta.mode = BlobMode::MetadataOnly;
+        meta.iroh_ingested = false;
+        self.write_meta(&object_paths.meta, &meta).await?;
+        debug!(blob = %hash, size_bytes = meta.size_bytes, "evicted blob from local cache");
+        Ok(true)
+    }
+
+    /// Owned blobs that are present locally and held by some peer.
+    async fn list_evictable(&self) -> Res<Vec<(BlobId, u64, i64)>> {
+        let mut out = vec![];
+        for path in super::list_object_files(&self.root.join("objects"), "meta").await? {
+            let meta = match self.read_meta(&path).await {
+                Ok(Some(meta)) => meta,
+                Ok(None) => continue,
+                Err(err) => {
+                    warn!(?err, path = %path.display(), "skipping unreadable blob metadata");
+                    continue;
+                }
+            };
+            if meta.mode != BlobMode::OwnedCopy || meta.remote_holders.is_empty() {
+                continue;
+            }
+            if !tokio::fs::try_exists(&self.object_paths(meta.hash)?.blob).await? {
+                continue;
+            }
+            out.push((meta.hash, meta.size_bytes, meta.created_at_unix_secs));
+        }
+        Ok(out)
+    }
+}
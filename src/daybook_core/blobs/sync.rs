@@ -1,6 +1,13 @@
 use crate::interlude::*;
 
-use crate::blobs::{blob_hash_from_id, blob_id_to_iroh_hash, BlobId, BlobUseHints, BlobsRepo};
+use crate::blobs::{
+    blob_hash_from_id, blob_id_to_iroh_hash, BlobFetcher, BlobId, BlobUseHints, BlobsRepo,
+};
+use crate::progress::{
+    CreateProgressTaskArgs, ProgressFinalState, ProgressRepo, ProgressRetentionPolicy,
+    ProgressSeverity, ProgressUnit, ProgressUpdate, ProgressUpdateDeets,
+};
+use crate::sync::BlobSyncDecision;
 use big_repo::SharedPartStore;
 
 use big_sync::{SyncBackend, SyncTaskRunOutcome};
@@ -8,12 +15,42 @@ use big_sync_core::{ObjId, PeerId, SyncCompletionDeets, SyncTaskCompletion};
 use futures::StreamExt;
 use surelock::key::lock_scope;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BlobHints {
+    pub length_octets: u64,
+    pub mime: Option<String>,
+}
+
+/// Local knowledge about blobs we don't have yet, used to decide
+/// whether a [`crate::sync::SyncProfile`] wants them downloaded.
+#[async_trait]
+pub trait BlobHintsSource: Send + Sync {
+    async fn blob_hints(&self, blob_id: BlobId) -> Res<Option<BlobHints>>;
+}
+
+struct ProfileGate {
+    profile: surelock::mutex::Mutex<crate::sync::SyncProfile>,
+    hints: Arc<dyn BlobHintsSource>,
+}
+
+/// Per peer transfer counters shared by every backend made through
+/// [`BlobSyncBackend::profiled`].
+#[derive(Default)]
+struct PeerTransferStats {
+    /// Bytes downloaded since last taken.
+    bytes_received: HashMap<PeerId, u64>,
+    /// Blobs being fetched from the peer, failed ones stay until they land.
+    backlog: HashMap<PeerId, HashSet<BlobId>>,
+}
+
 #[derive(Clone)]
 pub struct BlobSyncBackend {
     blobs_repo: Arc<BlobsRepo>,
     part_store: SharedPartStore,
     endpoint: iroh::Endpoint,
     remote_peer_endpoints: Arc<surelock::mutex::Mutex<HashMap<PeerId, iroh::EndpointAddr>>>,
+    profile_gate: Option<Arc<ProfileGate>>,
+    transfer_stats: Arc<surelock::mutex::Mutex<PeerTransferStats>>,
 }
 
 impl BlobSyncBackend {
@@ -27,7 +64,47 @@ impl BlobSyncBackend {
             part_store,
             endpoint,
             remote_peer_endpoints: Arc::new(surelock::mutex::Mutex::new(default())),
+            profile_gate: None,
+            transfer_stats: Arc::new(surelock::mutex::Mutex::new(default())),
+        }
+    }
+
+    /// A backend sharing peer registrations with this one whose downloads
+    /// are gated by the profile set through [`Self::set_profile`].
+    pub fn profiled(&self, hints: Arc<dyn BlobHintsSource>) -> Self {
+        Self {
+            profile_gate: Some(Arc::new(ProfileGate {
+                profile: surelock::mutex::Mutex::new(default()),
+                hints,
+            })),
+            ..self.clone()
+        }
+    }
+
+    pub fn set_profile(&self, profile: crate::sync::SyncProfile) {
+        let Some(gate) = &self.profile_gate else {
+            warn!("set_profile called on a blob sync backend without a profile gate");
+            return;
+        };
+        lock_scope(|key| {
+            let (mut current, _key) = key.lock(&gate.profile);
+            *current = profile;
+        })
+    }
+
+    async fn profile_blob_decision(&self, blob_id: BlobId) -> Res<BlobSyncDecision> {
+        let Some(gate) = &self.profile_gate else {
+            return Ok(BlobSyncDecision::Download);
+        };
+        let profile = lock_scope(|key| {
+            let (current, _key) = key.lock(&gate.profile);
+            current.clone()
+        });
+        if profile.is_unrestricted() {
+            return Ok(BlobSyncDecision::Download);
         }
+        let hints = gate.hints.blob_hints(blob_id).await?;
+        Ok(profile.blob_decision(hints.as_ref()))
     }
 
     pub fn register_remote_peer(&self, peer_id: PeerId, endpoint_addr: iroh::EndpointAddr) {
@@ -44,13 +121,49 @@ impl BlobSyncBackend {
         })
     }
 
+    /// Bytes downloaded from each peer since the last call.
+    pub fn take_bytes_received(&self) -> HashMap<PeerId, u64> {
+        lock_scope(|key| {
+            let (mut stats, _key) = key.lock(&self.transfer_stats);
+            std::mem::take(&mut stats.bytes_received)
+        })
+    }
+
+    /// Number of blobs waiting to be downloaded from each peer.
+    pub fn blob_backlog(&self) -> HashMap<PeerId, u64> {
+        lock_scope(|key| {
+            let (stats, _key) = key.lock(&self.transfer_stats);
+            stats
+                .backlog
+                .iter()
+                .map(|(peer_id, blobs)| (*peer_id, blobs.len() as u64))
+                .collect()
+        })
+    }
+
+    fn note_backlog(&self, peer_id: PeerId, blob_id: BlobId, pending: bool) {
+        lock_scope(|key| {
+            let (mut stats, _key) = key.lock(&self.transfer_stats);
+            if pending {
+                stats.backlog.entry(peer_id).or_default().insert(blob_id);
+                return;
+            }
+            // once we have it, it's off every peer's backlog
+            stats.backlog.retain(|_, blobs| {
+                blobs.remove(&blob_id);
+                !blobs.is_empty()
+            });
+        })
+    }
+
     fn blob_id_from_obj_id(obj_id: ObjId) -> BlobId {
         BlobId::new(*obj_id.as_bytes())
     }
 
-    async fn ensure_local_blob(&self, peer_id: PeerId, blob_id: BlobId) -> Res<()> {
+    /// Returns [`BlobSyncDecision::Download`] once the blob is local.
+    async fn ensure_local_blob(&self, peer_id: PeerId, blob_id: BlobId) -> Res<BlobSyncDecision> {
         if self.blobs_repo.has_hash(blob_id).await? {
-            return Ok(());
+            return Ok(BlobSyncDecision::Download);
         }
 
         let iroh_hash = blob_id_to_iroh_hash(blob_id);
@@ -58,7 +171,20 @@ impl BlobSyncBackend {
             self.blobs_repo
                 .put_from_store(blob_id, BlobUseHints::Unknown)
                 .await?;
-            return Ok(());
+            return Ok(BlobSyncDecision::Download);
+        }
+
+        if self.blobs_repo.is_metadata_only(blob_id).await? {
+            // evicted from the local cache, it'll be fetched again on demand
+            return Ok(BlobSyncDecision::Skip);
+        }
+
+        match self.profile_blob_decision(blob_id).await? {
+            BlobSyncDecision::Download => {}
+            decision => {
+                debug!(blob = %blob_hash_from_id(blob_id), ?decision, "sync profile held back blob download");
+                return Ok(decision);
+            }
         }
 
         let has_peer = lock_scope(|key| {
@@ -69,17 +195,54 @@ impl BlobSyncBackend {
             eyre::bail!("missing registered peer {peer_id} for blob sync");
         }
 
+        self.note_backlog(peer_id, blob_id, true);
+        let mut received = 0;
+        let res = self
+            .download(blob_id, vec![peer_id], |done| received = done)
+            .await;
+        lock_scope(|key| {
+            let (mut stats, _key) = key.lock(&self.transfer_stats);
+            *stats.bytes_received.entry(peer_id).or_default() += received;
+        });
+        res?;
+        self.note_backlog(peer_id, blob_id, false);
+
+        self.blobs_repo
+            .put_from_store(blob_id, BlobUseHints::Unknown)
+            .await?;
+        // partition membership doesn't imply the peer has the bytes but
+        // having served them to us does, which makes our copy evictable
+        self.blobs_repo.note_remote_holder(blob_id, peer_id).await?;
+        Ok(BlobSyncDecision::Download)
+    }
+
+    /// Downloads the blob into the iroh store trying `providers` in order.
+    /// `on_progress` gets the number of bytes downloaded so far.
+    async fn download(
+        &self,
+        blob_id: BlobId,
+        providers: Vec<PeerId>,
+        mut on_progress: impl FnMut(u64) + Send,
+    ) -> Res<()> {
+        let iroh_hash = blob_id_to_iroh_hash(blob_id);
         let downloader = self.blobs_repo.iroh_store().downloader(&self.endpoint);
-        let provider = iroh::PublicKey::from_bytes(peer_id.as_bytes())
-            .expect("peer id must be a valid iroh public key");
-        let progress = downloader.download(iroh_hash, vec![provider]);
+        let providers = providers
+            .into_iter()
+            .map(|peer_id| {
+                iroh::PublicKey::from_bytes(peer_id.as_bytes())
+                    .expect("peer id must be a valid iroh public key")
+            })
+            .collect::<Vec<_>>();
+        let progress = downloader.download(iroh_hash, providers);
         let mut stream = progress.stream().await?;
 
         let mut saw_download_error = false;
         while let Some(item) = stream.next().await {
             match item {
                 iroh_blobs::api::downloader::DownloadProgressItem::TryProvider { .. } => {}
-                iroh_blobs::api::downloader::DownloadProgressItem::Progress(_) => {}
+                iroh_blobs::api::downloader::DownloadProgressItem::Progress(done) => {
+                    on_progress(done);
+                }
                 iroh_blobs::api::downloader::DownloadProgressItem::PartComplete { .. } => {}
                 iroh_blobs::api::downloader::DownloadProgressItem::ProviderFailed { .. } => {}
                 iroh_blobs::api::downloader::DownloadProgressItem::DownloadError
@@ -98,14 +261,131 @@ impl BlobSyncBackend {
         if !self.blobs_repo.iroh_store().blobs().has(iroh_hash).await? {
             eyre::bail!("download completed but blob missing from store");
         }
-
-        self.blobs_repo
-            .put_from_store(blob_id, BlobUseHints::Unknown)
-            .await?;
         Ok(())
     }
 }
 
+/// [`BlobFetcher`] that pulls blobs from connected peers, preferring the
+/// ones known to hold the blob.
+pub struct PeerBlobFetcher {
+    backend: Arc<BlobSyncBackend>,
+    /// Source of the expected size for the progress reports.
+    hints: Arc<dyn BlobHintsSource>,
+    progress_repo: Option<Arc<ProgressRepo>>,
+}
+
+impl PeerBlobFetcher {
+    pub fn new(
+        backend: Arc<BlobSyncBackend>,
+        hints: Arc<dyn BlobHintsSource>,
+        progress_repo: Option<Arc<ProgressRepo>>,
+    ) -> Self {
+        Self {
+            backend,
+            hints,
+            progress_repo,
+        }
+    }
+
+    async fn report(&self, task_id: &str, deets: ProgressUpdateDeets) {
+        let Some(progress_repo) = &self.progress_repo else {
+            return;
+        };
+        let res = progress_repo
+            .add_update(
+                task_id,
+                ProgressUpdate {
+                    at: jiff::Timestamp::now(),
+                    title: None,
+                    deets,
+                },
+            )
+            .await;
+        if let Err(err) = res {
+            warn!(?err, %task_id, "error reporting blob fetch progress");
+        }
+    }
+}
+
+#[async_trait]
+impl BlobFetcher for PeerBlobFetcher {
+    async fn fetch(&self, blob_id: BlobId, known_holders: &[PeerId]) -> Res<()> {
+        let connected = lock_scope(|key| {
+            let (remote_peer_endpoints, _key) = key.lock(&self.backend.remote_peer_endpoints);
+            remote_peer_endpoints.keys().copied().collect::<Vec<_>>()
+        });
+        let mut providers = known_holders
+            .iter()
+            .copied()
+            .filter(|peer_id| connected.contains(peer_id))
+            .collect::<Vec<_>>();
+        for peer_id in connected {
+            if !providers.contains(&peer_id) {
+                providers.push(peer_id);
+            }
+        }
+        if providers.is_empty() {
+            eyre::bail!(
+                "no connected peer to fetch blob {} from",
+                blob_hash_from_id(blob_id)
+            );
+        }
+
+        let hash = blob_hash_from_id(blob_id);
+        let task_id = format!("blobs/fetch/{hash}");
+        if let Some(progress_repo) = &self.progress_repo {
+            progress_repo
+                .upsert_task(CreateProgressTaskArgs {
+                    id: task_id.clone(),
+                    tags: vec!["/type/blob_fetch".to_string(), format!("/blobs/{hash}")],
+                    retention: ProgressRetentionPolicy::Ephemeral,
+                })
+                .await?;
+        }
+        // plug blobs and blobs of docs we haven't replicated have no hints
+        let total = self
+            .hints
+            .blob_hints(blob_id)
+            .await?
+            .map(|hints| hints.length_octets);
+        let amount = |done| ProgressUpdateDeets::Amount {
+            severity: ProgressSeverity::Info,
+            done,
+            total,
+            unit: ProgressUnit::Bytes,
+            message: None,
+        };
+        // small blobs tend to land before the first progress tick
+        self.report(&task_id, amount(0)).await;
+        let (progress_tx, mut progress_rx) = tokio::sync::watch::channel(0u64);
+        let download = self.backend.download(blob_id, providers, move |done| {
+            progress_tx.send_replace(done);
+        });
+        tokio::pin!(download);
+        let res = loop {
+            tokio::select! {
+                res = &mut download => break res,
+                Ok(()) = progress_rx.changed() => {
+                    let done = *progress_rx.borrow_and_update();
+                    self.report(&task_id, amount(done)).await;
+                }
+            }
+        };
+        let deets = match &res {
+            Ok(()) => ProgressUpdateDeets::Completed {
+                state: ProgressFinalState::Succeeded,
+                message: None,
+            },
+            Err(err) => ProgressUpdateDeets::Completed {
+                state: ProgressFinalState::Failed,
+                message: Some(format!("{err:#}")),
+            },
+        };
+        self.report(&task_id, deets).await;
+        res
+    }
+}
+
 #[async_trait]
 impl SyncBackend for BlobSyncBackend {
     async fn sync_obj(
@@ -135,7 +415,19 @@ impl SyncBackend for BlobSyncBackend {
             }
         }
 
-        self.ensure_local_blob(peer_id, blob_id).await?;
+        match self.ensure_local_blob(peer_id, blob_id).await? {
+            BlobSyncDecision::Download => {}
+            // skipped objects stay out of the local part so that we don't
+            // advertise blobs we don't hold
+            BlobSyncDecision::Skip => {
+                return Ok(SyncTaskRunOutcome::Completion(SyncTaskCompletion {
+                    obj_id,
+                    deets: SyncCompletionDeets::Noop,
+                }));
+            }
+            // retried with backoff until the doc referencing it shows up
+            BlobSyncDecision::AwaitHints => return Ok(SyncTaskRunOutcome::Stale),
+        }
         let payload = remote_payload
             .clone()
             .or_else(|| local_payload.clone())
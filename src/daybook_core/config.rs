@@ -13,6 +13,15 @@ pub struct ConfigStore {
     pub users: HashMap<String, Versioned<ThroughJson<UserMeta>>>,
     pub users_deleted: HashMap<String, Vec<VersionTag>>,
     pub mltools: Versioned<ThroughJson<mltools::Config>>,
+    /// Selective sync profiles keyed by device endpoint id.
+    #[autosurgeon(missing = "Default::default")]
+    pub sync_profiles: HashMap<String, Versioned<ThroughJson<crate::sync::SyncProfile>>>,
+    /// Signed device revocations keyed by the revoked endpoint id.
+    #[autosurgeon(missing = "Default::default")]
+    pub device_revocations: HashMap<String, Versioned<ThroughJson<crate::sync::DeviceRevocation>>>,
+    /// Signed identity rotations keyed by the retired endpoint id.
+    #[autosurgeon(missing = "Default::default")]
+    pub identity_rotations: HashMap<String, Versioned<ThroughJson<crate::sync::IdentityRotation>>>,
 }
 
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Reconcile, Hydrate)]
@@ -74,6 +83,9 @@ impl Default for ConfigStore {
                 }
                 .into(),
             },
+            sync_profiles: HashMap::new(),
+            device_revocations: HashMap::new(),
+            identity_rotations: HashMap::new(),
         }
     }
 }
@@ -330,6 +342,9 @@ impl ConfigRepo {
                         store.users = new_store.users;
                         store.users_deleted = new_store.users_deleted;
                         store.mltools = new_store.mltools;
+                        store.sync_profiles = new_store.sync_profiles;
+                        store.device_revocations = new_store.device_revocations;
+                        store.identity_rotations = new_store.identity_rotations;
                     })
                     .await?;
 
@@ -454,7 +469,15 @@ impl ConfigRepo {
                     live_origin,
                 );
 
-                if matches!(section_key.as_ref(), "facet_display" | "users" | "mltools") {
+                if matches!(
+                    section_key.as_ref(),
+                    "facet_display"
+                        | "users"
+                        | "mltools"
+                        | "sync_profiles"
+                        | "device_revocations"
+                        | "identity_rotations"
+                ) {
                     out.push(ConfigEvent::Changed {
                         heads,
                         origin: event_origin.clone(),
@@ -487,6 +510,9 @@ impl ConfigRepo {
                     "facet_display"
                         | "users"
                         | "mltools"
+                        | "sync_profiles"
+                        | "device_revocations"
+                        | "identity_rotations"
                         | "facet_display_deleted"
                         | "users_deleted"
                 ) {
@@ -506,7 +532,11 @@ impl ConfigRepo {
                 };
                 if matches!(
                     section_key.as_ref(),
-                    "facet_display_deleted" | "users_deleted"
+                    "facet_display_deleted"
+                        | "users_deleted"
+                        | "sync_profiles"
+                        | "device_revocations"
+                        | "identity_rotations"
                 ) {
                     out.push(ConfigEvent::Changed {
                         heads,
@@ -616,6 +646,131 @@ impl ConfigRepo {
         Ok(())
     }
 
+    pub async fn get_sync_profile(
+        &self,
+        endpoint_id: &iroh::EndpointId,
+    ) -> Option<crate::sync::SyncProfile> {
+        let key = endpoint_id.to_string();
+        self.store
+            .query_sync(move |store| store.sync_profiles.get(&key).map(|val| val.val.0.clone()))
+            .await
+    }
+
+    pub async fn set_sync_profile(
+        &self,
+        endpoint_id: &iroh::EndpointId,
+        profile: crate::sync::SyncProfile,
+    ) -> Res<()> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        let key = endpoint_id.to_string();
+        let (_, changed) = self
+            .store
+            .mutate_sync(move |store| {
+                let Some(old) = store.sync_profiles.get_mut(&key) else {
+                    store.sync_profiles.insert(
+                        key,
+                        Versioned::mint(self.local_actor_id.clone(), profile.into()),
+                    );
+                    return;
+                };
+                old.replace(self.local_actor_id.clone(), profile.into());
+            })
+            .await?;
+        if changed.is_some() {
+            self.registry.notify([ConfigEvent::Changed {
+                heads: ChangeHashSet(self.get_config_heads().await?),
+                origin: self.local_origin(),
+            }]);
+        }
+        Ok(())
+    }
+
+    /// Records a revocation in the config so that it reaches every device.
+    /// Also forgets the device locally.
+    pub async fn add_device_revocation(
+        &self,
+        revocation: crate::sync::DeviceRevocation,
+    ) -> Res<()> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        revocation.verify()?;
+        let endpoint_id = revocation.endpoint_id;
+        let key = endpoint_id.to_string();
+        let (_, changed) = self
+            .store
+            .mutate_sync(move |store| {
+                if store.device_revocations.contains_key(&key) {
+                    return;
+                }
+                store.device_revocations.insert(
+                    key,
+                    Versioned::mint(self.local_actor_id.clone(), revocation.into()),
+                );
+            })
+            .await?;
+        if changed.is_some() {
+            self.registry.notify([ConfigEvent::Changed {
+                heads: ChangeHashSet(self.get_config_heads().await?),
+                origin: self.local_origin(),
+            }]);
+        }
+        self.remove_known_sync_device(&endpoint_id).await?;
+        Ok(())
+    }
+
+    /// Announces the new endpoint of a device through the config. The
+    /// rotation must be signed with the retired key.
+    pub async fn add_identity_rotation(&self, rotation: crate::sync::IdentityRotation) -> Res<()> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        rotation.verify()?;
+        let key = rotation.old_endpoint_id.to_string();
+        let (_, changed) = self
+            .store
+            .mutate_sync(move |store| {
+                if store.identity_rotations.contains_key(&key) {
+                    return;
+                }
+                store.identity_rotations.insert(
+                    key,
+                    Versioned::mint(self.local_actor_id.clone(), rotation.into()),
+                );
+            })
+            .await?;
+        if changed.is_some() {
+            self.registry.notify([ConfigEvent::Changed {
+                heads: ChangeHashSet(self.get_config_heads().await?),
+                origin: self.local_origin(),
+            }]);
+        }
+        Ok(())
+    }
+
+    pub async fn get_device_trust(&self) -> crate::sync::DeviceTrust {
+        let (revocations, rotations) = self
+            .store
+            .query_sync(|store| {
+                (
+                    store
+                        .device_revocations
+                        .values()
+                        .map(|val| val.val.0.clone())
+                        .collect::<Vec<_>>(),
+                    store
+                        .identity_rotations
+                        .values()
+                        .map(|val| val.val.0.clone())
+                        .collect::<Vec<_>>(),
+                )
+            })
+            .await;
+        crate::sync::DeviceTrust::resolve(revocations, rotations)
+    }
+
     pub async fn get_actor_user_path(
         &self,
         actor_id: &automerge::ActorId,
@@ -661,6 +816,35 @@ impl ConfigRepo {
         Ok(())
     }
 
+    /// Returns false if the device isn't known.
+    pub async fn set_known_sync_device_access(
+        &self,
+        endpoint_id: &iroh::EndpointId,
+        access: crate::repo::globals::PeerAccess,
+    ) -> Res<bool> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        let _sync_config_guard = self.sync_config_lock.lock().await;
+        let mut config = crate::repo::globals::get_sync_config(&self.repo_sql).await?;
+        let Some(device) = config
+            .known_devices
+            .iter_mut()
+            .find(|entry| &entry.endpoint_id == endpoint_id)
+        else {
+            return Ok(false);
+        };
+        if device.access == access {
+            return Ok(true);
+        }
+        device.access = access;
+        crate::repo::globals::set_sync_config(&self.repo_sql, &config).await?;
+        self.registry.notify([ConfigEvent::SyncDevicesChanged {
+            origin: self.local_origin(),
+        }]);
+        Ok(true)
+    }
+
     pub async fn remove_known_sync_device(&self, endpoint_id: &iroh::EndpointId) -> Res<bool> {
         if self.cancel_token.is_cancelled() {
             eyre::bail!("repo is stopped");
@@ -705,6 +889,8 @@ impl ConfigRepo {
                 name: device_name.to_string(),
                 added_at: jiff::Timestamp::now(),
                 last_connected_at: None,
+                access: default(),
+                storage_peer: false,
             });
         crate::repo::globals::set_sync_config(&self.repo_sql, &config).await?;
         self.registry.notify([ConfigEvent::SyncDevicesChanged {
@@ -0,0 +1,943 @@
+//! Lets other local processes use a repo while it's open.
+//!
+//! [`crate::repo::RepoLockGuard`] keeps a repo to a single process. The
+//! process holding the lock serves the repositories it has loaded on a
+//! loopback-only iroh endpoint and advertises it in [`DAEMON_INFO_FILE`]
+//! at the repo root. Later openers that find the repo locked attach
+//! through [`DaemonClient`] instead of failing.
+//!
+//! The info file is only readable by its owner and carries a token that
+//! has to be presented before anything else is served. Endpoints that
+//! skip it get their connection closed.
+
+use crate::interlude::*;
+
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    EndpointId,
+};
+use irpc::{channel, rpc_requests, WithChannels};
+use tokio_util::sync::CancellationToken;
+
+use crate::drawer::{DocNBranches, DrawerEvent, DrawerRepo};
+use crate::plugs::PlugsRepo;
+use crate::repo::RepoCtx;
+use crate::repos::{RecvError, Repo, SubscribeOpts};
+use crate::rt::{DispatchArgs, Rt};
+use crate::sync::{IrohSyncRepo, PeerSyncStatus};
+
+use daybook_types::doc::{AddDocArgs, Doc, DocId, DocPatch};
+use daybook_types::manifest::PlugManifest;
+
+pub const DAEMON_ALPN: &[u8] = b"townframe/daybook-daemon/0";
+pub const DAEMON_INFO_FILE: &str = "daemon.json";
+
+const DRAWER_EVENTS_CAPACITY: usize = 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DaemonInfo {
+    pub pid: u32,
+    pub endpoint_addr: iroh::EndpointAddr,
+    pub token: String,
+}
+
+impl DaemonInfo {
+    pub async fn read(path: &Path) -> Res<Option<Self>> {
+        let json = match tokio::fs::read_to_string(path).await {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => {
+                return Err(err)
+                    .wrap_err_with(|| format!("error reading daemon info {}", path.display()))
+            }
+        };
+        let info = serde_json::from_str(&json)
+            .wrap_err_with(|| format!("error parsing daemon info {}", path.display()))?;
+        Ok(Some(info))
+    }
+
+    fn write(&self, path: &Path) -> Res<()> {
+        let json = serde_json::to_string(self)?;
+        let mut opts = std::fs::OpenOptions::new();
+        opts.create(true).write(true).truncate(true);
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::OpenOptionsExt;
+            opts.mode(0o600);
+        }
+        let mut file = opts
+            .open(path)
+            .wrap_err_with(|| format!("error writing daemon info {}", path.display()))?;
+        std::io::Write::write_all(&mut file, json.as_bytes())?;
+        std::io::Write::flush(&mut file)?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonRepoInfo {
+    pub pid: u32,
+    pub repo_id: String,
+    pub repo_name: String,
+    pub local_user_path: UserPathBuf,
+}
+
+/// [`DocNBranches`] with the heads in their serialized form.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonDocBranches {
+    pub doc_id: DocId,
+    pub branches: HashMap<String, Vec<String>>,
+}
+
+impl From<&DocNBranches> for DaemonDocBranches {
+    fn from(entry: &DocNBranches) -> Self {
+        Self {
+            doc_id: entry.doc_id.clone(),
+            branches: entry
+                .branches
+                .iter()
+                .map(|(name, heads)| (name.clone(), am_utils_rs::serialize_commit_heads(heads)))
+                .collect(),
+        }
+    }
+}
+
+impl DaemonDocBranches {
+    fn parse(self) -> Res<DocNBranches> {
+        Ok(DocNBranches {
+            doc_id: self.doc_id,
+            branches: self
+                .branches
+                .into_iter()
+                .map(|(name, heads)| {
+                    eyre::Ok((
+                        name,
+                        ChangeHashSet(am_utils_rs::parse_commit_heads(&heads)?),
+                    ))
+                })
+                .collect::<Res<_>>()?,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DaemonDoc {
+    pub doc: Doc,
+    pub heads: Vec<String>,
+}
+
+/// Drawer events stripped down to what changed. Clients that need the
+/// entries fetch them again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DaemonDrawerEvent {
+    DocAdded {
+        id: DocId,
+        drawer_heads: Vec<String>,
+    },
+    DocUpdated {
+        id: DocId,
+        drawer_heads: Vec<String>,
+    },
+    DocDeleted {
+        id: DocId,
+        drawer_heads: Vec<String>,
+    },
+    FacetConflicts {
+        id: DocId,
+        branch_path: BranchPathBuf,
+        conflicts: Vec<crate::drawer::FacetConflict>,
+        drawer_heads: Vec<String>,
+    },
+    /// The daemon fell behind and skipped events.
+    Dropped { dropped_count: u64 },
+}
+
+impl From<&DrawerEvent> for DaemonDrawerEvent {
+    fn from(event: &DrawerEvent) -> Self {
+        match event {
+            DrawerEvent::DocAdded {
+                id, drawer_heads, ..
+            } => Self::DocAdded {
+                id: id.clone(),
+                drawer_heads: am_utils_rs::serialize_commit_heads(drawer_heads),
+            },
+            DrawerEvent::DocUpdated {
+                id, drawer_heads, ..
+            } => Self::DocUpdated {
+                id: id.clone(),
+                drawer_heads: am_utils_rs::serialize_commit_heads(drawer_heads),
+            },
+            DrawerEvent::DocDeleted {
+                id, drawer_heads, ..
+            } => Self::DocDeleted {
+                id: id.clone(),
+                drawer_heads: am_utils_rs::serialize_commit_heads(drawer_heads),
+            },
+            DrawerEvent::FacetConflicts {
+                id,
+                branch_path,
+                conflicts,
+                drawer_heads,
+                ..
+            } => Self::FacetConflicts {
+                id: id.clone(),
+                branch_path: branch_path.clone(),
+                conflicts: conflicts.clone(),
+                drawer_heads: am_utils_rs::serialize_commit_heads(drawer_heads),
+            },
+        }
+    }
+}
+
+/// The subset of [`DispatchArgs`] that can be sent over.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum DaemonDispatchArgs {
+    DocInvoke {
+        doc_id: DocId,
+        branch_path: BranchPathBuf,
+        heads: Vec<String>,
+    },
+    DocFacet {
+        doc_id: DocId,
+        branch_path: BranchPathBuf,
+        heads: Vec<String>,
+        facet_key: Option<String>,
+    },
+}
+
+impl DaemonDispatchArgs {
+    fn parse(self) -> Res<DispatchArgs> {
+        Ok(match self {
+            Self::DocInvoke {
+                doc_id,
+                branch_path,
+                heads,
+            } => DispatchArgs::DocInvoke {
+                doc_id,
+                branch_path,
+                heads: ChangeHashSet(am_utils_rs::parse_commit_heads(&heads)?),
+            },
+            Self::DocFacet {
+                doc_id,
+                branch_path,
+                heads,
+                facet_key,
+            } => DispatchArgs::DocFacet {
+                doc_id,
+                branch_path,
+                heads: ChangeHashSet(am_utils_rs::parse_commit_heads(&heads)?),
+                facet_key,
+            },
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AttachReq {
+    pub token: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListDocsReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetDocBranchesReq {
+    pub doc_id: DocId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetDocReq {
+    pub doc_id: DocId,
+    pub branch_path: BranchPathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AddDocReq {
+    pub args: AddDocArgs,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UpdateDocReq {
+    pub patch: DocPatch,
+    pub branch_path: BranchPathBuf,
+    pub heads: Option<Vec<String>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DelDocReq {
+    pub doc_id: DocId,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscribeDrawerReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ListPlugsReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DispatchReq {
+    pub plug_id: String,
+    pub routine_name: String,
+    pub args: DaemonDispatchArgs,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SyncStatusReq {}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConnectUrlReq {
+    pub url: String,
+}
+
+#[rpc_requests(message = DaemonRpcMessage)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum DaemonRpc {
+    /// Has to be the first request of an endpoint.
+    #[rpc(tx = channel::oneshot::Sender<Result<DaemonRepoInfo, String>>)]
+    Attach(AttachReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<Vec<DaemonDocBranches>, String>>)]
+    ListDocs(ListDocsReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<Option<DaemonDocBranches>, String>>)]
+    GetDocBranches(GetDocBranchesReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<Option<DaemonDoc>, String>>)]
+    GetDoc(GetDocReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<DocId, String>>)]
+    AddDoc(AddDocReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<(), String>>)]
+    UpdateDoc(UpdateDocReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<bool, String>>)]
+    DelDoc(DelDocReq),
+    #[rpc(tx = channel::mpsc::Sender<DaemonDrawerEvent>)]
+    SubscribeDrawer(SubscribeDrawerReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<Vec<PlugManifest>, String>>)]
+    ListPlugs(ListPlugsReq),
+    /// Replies with the dispatch id.
+    #[rpc(tx = channel::oneshot::Sender<Result<String, String>>)]
+    Dispatch(DispatchReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<Vec<PeerSyncStatus>, String>>)]
+    SyncStatus(SyncStatusReq),
+    /// Replies with the id of the connected endpoint.
+    #[rpc(tx = channel::oneshot::Sender<Result<String, String>>)]
+    ConnectUrl(ConnectUrlReq),
+}
+
+/// Repositories the daemon process has loaded so far. Requests for the
+/// ones it hasn't are refused.
+#[derive(Default)]
+struct DaemonServices {
+    drawer: Option<Arc<DrawerRepo>>,
+    plugs: Option<Arc<PlugsRepo>>,
+    rt: Option<Arc<Rt>>,
+    sync: Option<Arc<IrohSyncRepo>>,
+}
+
+struct DaemonState {
+    token: String,
+    repo_info: DaemonRepoInfo,
+    attached: std::sync::RwLock<HashSet<EndpointId>>,
+    services: std::sync::RwLock<DaemonServices>,
+    cancel_token: CancellationToken,
+}
+
+impl DaemonState {
+    fn service<T>(
+        &self,
+        name: &str,
+        get: impl FnOnce(&DaemonServices) -> Option<&Arc<T>>,
+    ) -> Res<Arc<T>> {
+        let services = self.services.read().expect(ERROR_MUTEX);
+        get(&services)
+            .map(Arc::clone)
+            .ok_or_else(|| ferr!("the daemon process hasn't loaded the {name}"))
+    }
+
+    fn drawer(&self) -> Res<Arc<DrawerRepo>> {
+        self.service("drawer", |svc| svc.drawer.as_ref())
+    }
+
+    async fn list_docs(&self) -> Res<Vec<DaemonDocBranches>> {
+        let entries = self.drawer()?.list().await?;
+        Ok(entries.iter().map(DaemonDocBranches::from).collect())
+    }
+
+    async fn get_doc_branches(&self, req: GetDocBranchesReq) -> Res<Option<DaemonDocBranches>> {
+        let entry = self.drawer()?.get_doc_branches(&req.doc_id).await?;
+        Ok(entry.as_ref().map(DaemonDocBranches::from))
+    }
+
+    async fn get_doc(&self, req: GetDocReq) -> Res<Option<DaemonDoc>> {
+        let found = self
+            .drawer()?
+            .get_with_heads(&req.doc_id, &req.branch_path, None)
+            .await?;
+        Ok(found.map(|(doc, heads)| DaemonDoc {
+            doc: (*doc).clone(),
+            heads: am_utils_rs::serialize_commit_heads(&heads),
+        }))
+    }
+
+    async fn update_doc(&self, req: UpdateDocReq) -> Res<()> {
+        let heads = req
+            .heads
+            .map(|heads| am_utils_rs::parse_commit_heads(&heads).map(ChangeHashSet))
+            .transpose()?;
+        self.drawer()?
+            .update_at_heads(req.patch, &req.branch_path, heads)
+            .await?;
+        Ok(())
+    }
+
+    async fn list_plugs(&self) -> Res<Vec<PlugManifest>> {
+        let plugs = self.service("plugs", |svc| svc.plugs.as_ref())?;
+        Ok(plugs
+            .list_plugs()
+            .await
+            .iter()
+            .map(|man| (**man).clone())
+            .collect())
+    }
+
+    async fn dispatch(&self, req: DispatchReq) -> Res<String> {
+        let rt = self.service("runtime", |svc| svc.rt.as_ref())?;
+        rt.dispatch(&req.plug_id, &req.routine_name, req.args.parse()?)
+            .await
+    }
+
+    fn sync(&self) -> Res<Arc<IrohSyncRepo>> {
+        self.service("sync repo", |svc| svc.sync.as_ref())
+    }
+
+    async fn stream_drawer_events(&self, tx: channel::mpsc::Sender<DaemonDrawerEvent>) {
+        let drawer = match self.drawer() {
+            Ok(drawer) => drawer,
+            Err(err) => {
+                debug!(?err, "refusing drawer subscription");
+                return;
+            }
+        };
+        let listener = drawer.subscribe(SubscribeOpts::new(DRAWER_EVENTS_CAPACITY));
+        loop {
+            let event = tokio::select! {
+                biased;
+                _ = self.cancel_token.cancelled() => break,
+                event = listener.recv_async() => event,
+            };
+            let event = match event {
+                Ok(event) => DaemonDrawerEvent::from(&*event),
+                Err(RecvError::Dropped { dropped_count }) => {
+                    DaemonDrawerEvent::Dropped { dropped_count }
+                }
+                Err(RecvError::Closed) => break,
+            };
+            if tx.send(event).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn handle(self: Arc<Self>, msg: DaemonRpcMessage) {
+        fn to_wire<T>(res: Res<T>) -> Result<T, String> {
+            res.map_err(|err| format!("{err:#}"))
+        }
+        let sent = match msg {
+            DaemonRpcMessage::Attach(_) => unreachable!("attach is handled on the connection"),
+            DaemonRpcMessage::ListDocs(req) => {
+                let WithChannels { tx, .. } = req;
+                tx.send(to_wire(self.list_docs().await)).await
+            }
+            DaemonRpcMessage::GetDocBranches(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                tx.send(to_wire(self.get_doc_branches(inner).await)).await
+            }
+            DaemonRpcMessage::GetDoc(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                tx.send(to_wire(self.get_doc(inner).await)).await
+            }
+            DaemonRpcMessage::AddDoc(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                let out = match self.drawer() {
+                    Ok(drawer) => drawer.add(inner.args).await.map_err(eyre::Report::from),
+                    Err(err) => Err(err),
+                };
+                tx.send(to_wire(out)).await
+            }
+            DaemonRpcMessage::UpdateDoc(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                tx.send(to_wire(self.update_doc(inner).await)).await
+            }
+            DaemonRpcMessage::DelDoc(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                let out = match self.drawer() {
+                    Ok(drawer) => drawer.del(&inner.doc_id).await.map_err(eyre::Report::from),
+                    Err(err) => Err(err),
+                };
+                tx.send(to_wire(out)).await
+            }
+            DaemonRpcMessage::SubscribeDrawer(req) => {
+                let WithChannels { tx, .. } = req;
+                self.stream_drawer_events(tx).await;
+                Ok(())
+            }
+            DaemonRpcMessage::ListPlugs(req) => {
+                let WithChannels { tx, .. } = req;
+                tx.send(to_wire(self.list_plugs().await)).await
+            }
+            DaemonRpcMessage::Dispatch(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                tx.send(to_wire(self.dispatch(inner).await)).await
+            }
+            DaemonRpcMessage::SyncStatus(req) => {
+                let WithChannels { tx, .. } = req;
+                let out = match self.sync() {
+                    Ok(sync) => sync.sync_status().await,
+                    Err(err) => Err(err),
+                };
+                tx.send(to_wire(out)).await
+            }
+            DaemonRpcMessage::ConnectUrl(req) => {
+                let WithChannels { inner, tx, .. } = req;
+                let out = match self.sync() {
+                    Ok(sync) => sync
+                        .connect_url(&inner.url)
+                        .await
+                        .map(|addr| addr.id.to_string()),
+                    Err(err) => Err(err),
+                };
+                tx.send(to_wire(out)).await
+            }
+        };
+        if let Err(err) = sent {
+            debug!(?err, "daemon client went away before the reply");
+        }
+    }
+}
+
+#[derive(educe::Educe)]
+#[educe(Debug)]
+struct DaemonProtocol {
+    #[educe(Debug(ignore))]
+    state: Arc<DaemonState>,
+}
+
+impl ProtocolHandler for DaemonProtocol {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        let remote_id = conn.remote_id();
+        loop {
+            let msg = match irpc_iroh::read_request::<DaemonRpc>(&conn).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!(?err, "error reading daemon request");
+                    break;
+                }
+            };
+            match msg {
+                DaemonRpcMessage::Attach(req) => {
+                    let WithChannels { inner, tx, .. } = req;
+                    use subtle::ConstantTimeEq;
+                    let token_ok: bool = inner
+                        .token
+                        .as_bytes()
+                        .ct_eq(self.state.token.as_bytes())
+                        .into();
+                    let out = if token_ok {
+                        self.state
+                            .attached
+                            .write()
+                            .expect(ERROR_MUTEX)
+                            .insert(remote_id);
+                        Ok(self.state.repo_info.clone())
+                    } else {
+                        warn!(%remote_id, "refusing daemon client with a bad token");
+                        Err("bad daemon token".to_string())
+                    };
+                    let refused = out.is_err();
+                    if tx.send(out).await.is_err() || refused {
+                        break;
+                    }
+                }
+                msg => {
+                    let attached = self
+                        .state
+                        .attached
+                        .read()
+                        .expect(ERROR_MUTEX)
+                        .contains(&remote_id);
+                    if !attached {
+                        warn!(%remote_id, "daemon request from an endpoint that hasn't attached");
+                        conn.close(0u32.into(), b"not attached");
+                        break;
+                    }
+                    tokio::spawn(Arc::clone(&self.state).handle(msg));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Serves the repo of the process holding its lock. Repositories are
+/// handed over with the setters as the process loads them.
+pub struct RepoDaemon {
+    state: Arc<DaemonState>,
+    endpoint_addr: iroh::EndpointAddr,
+}
+
+pub struct RepoDaemonStopToken {
+    router: iroh::protocol::Router,
+    state: Arc<DaemonState>,
+    info_path: PathBuf,
+}
+
+impl RepoDaemonStopToken {
+    pub async fn stop(self) -> Res<()> {
+        self.state.cancel_token.cancel();
+        // let go of the repos so that they can shut down after us
+        *self.state.services.write().expect(ERROR_MUTEX) = default();
+        // NOTE: the lock is still held here so nobody else can have
+        // written the file in the meantime
+        if let Err(err) = tokio::fs::remove_file(&self.info_path).await {
+            if err.kind() != std::io::ErrorKind::NotFound {
+                warn!(?err, path = %self.info_path.display(), "error removing daemon info");
+            }
+        }
+        tokio::time::timeout(
+            utils_rs::scale_timeout(std::time::Duration::from_secs(10)),
+            self.router.shutdown(),
+        )
+        .await
+        .map_err(|_| eyre::eyre!("timeout waiting for router shutdown"))??;
+        Ok(())
+    }
+}
+
+impl RepoDaemon {
+    pub async fn serve(rcx: &RepoCtx) -> Res<(Arc<Self>, RepoDaemonStopToken)> {
+        let endpoint = bind_loopback_endpoint().await?;
+        let pid = std::process::id();
+        let state = Arc::new(DaemonState {
+            token: utils_rs::hash::encode_base58_multibase(rand::random::<[u8; 32]>()),
+            repo_info: DaemonRepoInfo {
+                pid,
+                repo_id: rcx.repo_id.clone(),
+                repo_name: rcx.repo_name.clone(),
+                local_user_path: rcx.local_user_path.clone(),
+            },
+            attached: default(),
+            services: default(),
+            cancel_token: CancellationToken::new(),
+        });
+        let endpoint_addr = endpoint.addr();
+        let router = iroh::protocol::Router::builder(endpoint)
+            .accept(
+                DAEMON_ALPN,
+                DaemonProtocol {
+                    state: Arc::clone(&state),
+                },
+            )
+            .spawn();
+        let info_path = rcx.layout.daemon_info_path.clone();
+        let info = DaemonInfo {
+            pid,
+            endpoint_addr: endpoint_addr.clone(),
+            token: state.token.clone(),
+        };
+        if let Err(err) = info.write(&info_path) {
+            router.shutdown().await.ok();
+            return Err(err);
+        }
+        info!(repo_root = %rcx.layout.repo_root.display(), "serving repo daemon");
+        Ok((
+            Arc::new(Self {
+                state: Arc::clone(&state),
+                endpoint_addr,
+            }),
+            RepoDaemonStopToken {
+                router,
+                state,
+                info_path,
+            },
+        ))
+    }
+
+    pub fn endpoint_addr(&self) -> &iroh::EndpointAddr {
+        &self.endpoint_addr
+    }
+
+    pub fn set_drawer(&self, drawer: Arc<DrawerRepo>) {
+        self.state.services.write().expect(ERROR_MUTEX).drawer = Some(drawer);
+    }
+
+    pub fn set_plugs(&self, plugs: Arc<PlugsRepo>) {
+        self.state.services.write().expect(ERROR_MUTEX).plugs = Some(plugs);
+    }
+
+    pub fn set_rt(&self, rt: Arc<Rt>) {
+        self.state.services.write().expect(ERROR_MUTEX).rt = Some(rt);
+    }
+
+    pub fn set_sync(&self, sync: Arc<IrohSyncRepo>) {
+        self.state.services.write().expect(ERROR_MUTEX).sync = Some(sync);
+    }
+}
+
+async fn bind_loopback_endpoint() -> Res<iroh::Endpoint> {
+    let endpoint = iroh::Endpoint::builder(iroh::endpoint::presets::Minimal)
+        .secret_key(iroh::SecretKey::from_bytes(&rand::random()))
+        .clear_ip_transports()
+        .bind_addr((std::net::Ipv4Addr::LOCALHOST, 0))?
+        .relay_mode(iroh::RelayMode::Disabled)
+        .bind()
+        .await?;
+    Ok(endpoint)
+}
+
+/// A process using a repo through the daemon of the process that holds it.
+pub struct DaemonClient {
+    endpoint: iroh::Endpoint,
+    client: irpc::Client<DaemonRpc>,
+    pub repo: DaemonRepoInfo,
+}
+
+impl DaemonClient {
+    /// Attaches to the daemon serving the repo. Returns `None` if nothing
+    /// is serving it.
+    pub async fn attach(repo_root: &Path) -> Res<Option<Self>> {
+        let layout = crate::repo::repo_layout(repo_root)?;
+        let Some(info) = DaemonInfo::read(&layout.daemon_info_path).await? else {
+            return Ok(None);
+        };
+        let endpoint = bind_loopback_endpoint().await?;
+        let client = irpc_iroh::client::<DaemonRpc>(
+            endpoint.clone(),
+            info.endpoint_addr.clone(),
+            DAEMON_ALPN,
+        );
+        let repo = tokio::time::timeout(
+            utils_rs::scale_timeout(std::time::Duration::from_secs(5)),
+            client.rpc(AttachReq {
+                token: info.token.clone(),
+            }),
+        )
+        .await
+        .map_err(|_| ferr!("timeout attaching to the daemon of pid={}", info.pid))?
+        .wrap_err_with(|| format!("error attaching to the daemon of pid={}", info.pid))?
+        .map_err(|err| ferr!("daemon of pid={} refused us: {err}", info.pid))?;
+        Ok(Some(Self {
+            endpoint,
+            client,
+            repo,
+        }))
+    }
+
+    pub async fn list(&self) -> Res<Vec<DocNBranches>> {
+        self.client
+            .rpc(ListDocsReq {})
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error listing docs: {err}"))?
+            .into_iter()
+            .map(DaemonDocBranches::parse)
+            .collect()
+    }
+
+    pub async fn get_doc_branches(&self, doc_id: &DocId) -> Res<Option<DocNBranches>> {
+        self.client
+            .rpc(GetDocBranchesReq {
+                doc_id: doc_id.clone(),
+            })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error getting doc branches: {err}"))?
+            .map(DaemonDocBranches::parse)
+            .transpose()
+    }
+
+    pub async fn get_with_heads(
+        &self,
+        doc_id: &DocId,
+        branch_path: &BranchPath,
+    ) -> Res<Option<(Doc, ChangeHashSet)>> {
+        let found = self
+            .client
+            .rpc(GetDocReq {
+                doc_id: doc_id.clone(),
+                branch_path: branch_path.to_owned(),
+            })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error getting doc: {err}"))?;
+        found
+            .map(|found| {
+                let heads = am_utils_rs::parse_commit_heads(&found.heads)?;
+                eyre::Ok((found.doc, ChangeHashSet(heads)))
+            })
+            .transpose()
+    }
+
+    pub async fn add(&self, args: AddDocArgs) -> Res<DocId> {
+        self.client
+            .rpc(AddDocReq { args })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error adding doc: {err}"))
+    }
+
+    pub async fn update_at_heads(
+        &self,
+        patch: DocPatch,
+        branch_path: &BranchPath,
+        heads: Option<ChangeHashSet>,
+    ) -> Res<()> {
+        self.client
+            .rpc(UpdateDocReq {
+                patch,
+                branch_path: branch_path.to_owned(),
+                heads: heads.map(|heads| am_utils_rs::serialize_commit_heads(&heads)),
+            })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error updating doc: {err}"))
+    }
+
+    pub async fn del(&self, doc_id: &DocId) -> Res<bool> {
+        self.client
+            .rpc(DelDocReq {
+                doc_id: doc_id.clone(),
+            })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error deleting doc: {err}"))
+    }
+
+    /// Stream of drawer changes. Ends when either side goes away.
+    pub async fn subscribe_drawer(&self) -> Res<channel::mpsc::Receiver<DaemonDrawerEvent>> {
+        self.client
+            .server_streaming(SubscribeDrawerReq {}, DRAWER_EVENTS_CAPACITY)
+            .await
+            .wrap_err("daemon rpc transport failed")
+    }
+
+    pub async fn list_plugs(&self) -> Res<Vec<PlugManifest>> {
+        self.client
+            .rpc(ListPlugsReq {})
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error listing plugs: {err}"))
+    }
+
+    pub async fn dispatch(
+        &self,
+        plug_id: &str,
+        routine_name: &str,
+        args: DaemonDispatchArgs,
+    ) -> Res<String> {
+        self.client
+            .rpc(DispatchReq {
+                plug_id: plug_id.into(),
+                routine_name: routine_name.into(),
+                args,
+            })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error dispatching: {err}"))
+    }
+
+    pub async fn sync_status(&self) -> Res<Vec<PeerSyncStatus>> {
+        self.client
+            .rpc(SyncStatusReq {})
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error getting sync status: {err}"))
+    }
+
+    pub async fn connect_url(&self, url: &str) -> Res<String> {
+        self.client
+            .rpc(ConnectUrlReq { url: url.into() })
+            .await
+            .wrap_err("daemon rpc transport failed")?
+            .map_err(|err| ferr!("daemon error connecting: {err}"))
+    }
+
+    pub async fn close(self) {
+        self.endpoint.close().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_locked_repo_is_served_to_attached_clients() -> Res<()> {
+        let test_cx = crate::test_support::test_cx("daemon_attach").await?;
+        let rcx = Arc::clone(&test_cx.rt.rcx);
+        let repo_root = rcx.layout.repo_root.clone();
+
+        assert!(
+            crate::repo::RepoLockGuard::try_acquire(&rcx.layout.lock_path)?.is_none(),
+            "repo should still be locked to the test process"
+        );
+        assert!(DaemonClient::attach(&repo_root).await?.is_none());
+
+        let (daemon, daemon_stop) = RepoDaemon::serve(&rcx).await?;
+        daemon.set_drawer(Arc::clone(&test_cx.drawer_repo));
+        daemon.set_plugs(Arc::clone(&test_cx.rt.plugs_repo));
+
+        let client = DaemonClient::attach(&repo_root)
+            .await?
+            .expect("daemon info should be written");
+        assert_eq!(client.repo.repo_id, rcx.repo_id);
+
+        let mut events = client.subscribe_drawer().await?;
+        let doc_id = client
+            .add(AddDocArgs {
+                branch_path: "main".into(),
+                facets: default(),
+                user_path: Some(client.repo.local_user_path.clone()),
+            })
+            .await?;
+        let event = tokio::time::timeout(
+            utils_rs::scale_timeout(std::time::Duration::from_secs(10)),
+            events.recv(),
+        )
+        .await??;
+        assert!(
+            matches!(&event, Some(DaemonDrawerEvent::DocAdded { id, .. }) if *id == doc_id),
+            "unexpected event: {event:?}"
+        );
+
+        let listed = client.list().await?;
+        assert!(listed.iter().any(|entry| entry.doc_id == doc_id));
+        let branches = client
+            .get_doc_branches(&doc_id)
+            .await?
+            .expect("added doc should have branches");
+        assert!(branches.branches.contains_key("main"));
+        assert!(client
+            .get_doc_branches(&"missing-doc".to_string())
+            .await?
+            .is_none());
+        let (doc, heads) = client
+            .get_with_heads(&doc_id, "main".into())
+            .await?
+            .expect("added doc should be found");
+        assert_eq!(doc.id, doc_id);
+        assert!(!heads.is_empty());
+        assert!(!client.list_plugs().await?.is_empty());
+        assert!(
+            client.sync_status().await.is_err(),
+            "sync wasn't handed to the daemon"
+        );
+
+        client.close().await;
+        daemon_stop.stop().await?;
+        assert!(DaemonClient::attach(&repo_root).await?.is_none());
+
+        drop(rcx);
+        test_cx.stop().await?;
+        Ok(())
+    }
+}
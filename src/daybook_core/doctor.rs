@@ -0,0 +1,602 @@
+//! Checks the state other subsystems derive from the drawer against it and
+//! rebuilds what drifted:
+//!
+//! - the doc blobs, facet ref and facet set indexes,
+//! - blob presence and the docs blob scope memberships,
+//! - the switch worker's partition cursor and doc states,
+//! - the wflow frontier of the dispatch repo and active dispatches,
+//! - the facet heads recovered from dmeta.
+//!
+//! The switch cursor and the wflow frontier are only read when the runtime
+//! boots so repairs to them take effect on the next boot. Missing blobs and
+//! unrecoverable facet heads are only reported, there's nothing derived to
+//! rebuild them from.
+
+use crate::interlude::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use big_sync::HostPartStore;
+use big_sync_core::rpc::ListPartsError;
+use daybook_types::doc::{DocId, WellKnownFacetTag};
+use wflow::wflow_core::partition::state::JobStatus;
+
+use crate::blobs::{BlobScope, BLOB_SCOPE_DOCS_PARTITION_ID};
+use crate::drawer::DocNBranches;
+use crate::rt::dispatch::{ActiveDispatchDeets, DispatchStatus};
+use crate::rt::switch::SwitchStore;
+use crate::rt::Rt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, displaydoc::Display)]
+#[serde(rename_all = "camelCase")]
+pub enum DoctorIndex {
+    /// doc blobs
+    DocBlobs,
+    /// facet ref
+    FacetRef,
+    /// facet set
+    FacetSet,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, displaydoc::Display)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum DoctorFinding {
+    /// {index} index has rows for deleted doc {doc_id}
+    IndexRowsForDeletedDoc { index: DoctorIndex, doc_id: DocId },
+    /// doc blobs index has rows for deleted branch {branch_path} of doc {doc_id}
+    BlobIndexRowsForDeletedBranch { doc_id: DocId, branch_path: String },
+    /// facet set index disagrees with the main branch of doc {doc_id}
+    FacetSetIndexStale { doc_id: DocId },
+    /// blob {hash} referenced by {doc_ids:?} is missing locally
+    BlobMissingLocally { hash: String, doc_ids: Vec<DocId> },
+    /// blob {hash} isn't in the docs blob scope
+    BlobMissingFromScope { hash: String },
+    /// switch still has deleted branch doc {branch_doc_id} of doc {doc_id} as present
+    SwitchDocStateStale {
+        branch_doc_id: String,
+        doc_id: DocId,
+    },
+    /// switch cursor {cursor} of partition {partition_id} is past its latest cursor {latest_cursor}
+    SwitchCursorAhead {
+        partition_id: String,
+        cursor: u64,
+        latest_cursor: u64,
+    },
+    /// wflow frontier {frontier} of partition {wflow_partition_id} is past its log at {latest_idx}
+    WflowFrontierAhead {
+        wflow_partition_id: String,
+        frontier: u64,
+        latest_idx: u64,
+    },
+    /// active dispatch {dispatch_id} points at unknown wflow job {wflow_job_id}
+    DispatchUnknownWflowJob {
+        dispatch_id: String,
+        wflow_job_id: String,
+    },
+    /// active dispatch {dispatch_id} outlived its wflow job {wflow_job_id}
+    DispatchWflowJobEnded {
+        dispatch_id: String,
+        wflow_job_id: String,
+    },
+    /// heads of facet {facet_key} on {branch_path} of doc {doc_id} can't be recovered: {reason}
+    FacetHeadsUnrecoverable {
+        doc_id: DocId,
+        branch_path: String,
+        facet_key: String,
+        reason: String,
+    },
+}
+
+impl DoctorFinding {
+    pub fn repairable(&self) -> bool {
+        !matches!(
+            self,
+            Self::BlobMissingLocally { .. } | Self::FacetHeadsUnrecoverable { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DoctorReport {
+    pub findings: Vec<DoctorFinding>,
+    /// How many of the findings were repaired, zero unless asked to repair.
+    pub repaired: usize,
+}
+
+pub struct Doctor {
+    rt: Arc<Rt>,
+    switch_store: SwitchStore,
+}
+
+impl Doctor {
+    pub async fn load(rt: Arc<Rt>) -> Res<Self> {
+        let switch_store = SwitchStore::load(rt.rcx.sql.clone()).await?;
+        Ok(Self { rt, switch_store })
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn run(&self, repair: bool) -> Res<DoctorReport> {
+        let docs: HashMap<DocId, DocNBranches> = self
+            .rt
+            .drawer
+            .list()
+            .await?
+            .into_iter()
+            .map(|doc| (doc.doc_id.clone(), doc))
+            .collect();
+
+        let mut report = DoctorReport::default();
+        self.check_doc_blobs(&docs, &mut report.findings).await?;
+        self.check_facet_ref_index(&docs, &mut report.findings)
+            .await?;
+        self.check_facet_set_index(&docs, &mut report.findings)
+            .await?;
+        self.check_switch_store(&mut report.findings).await?;
+        self.check_dispatches(&mut report.findings).await?;
+        self.check_facet_heads(&docs, &mut report.findings).await?;
+
+        if repair {
+            for finding in &report.findings {
+                if self.repair(finding).await? {
+                    debug!(%finding, "repaired");
+                    report.repaired += 1;
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    async fn check_doc_blobs(
+        &self,
+        docs: &HashMap<DocId, DocNBranches>,
+        findings: &mut Vec<DoctorFinding>,
+    ) -> Res<()> {
+        let mut indexed_branches: BTreeMap<DocId, BTreeSet<String>> = default();
+        let mut referencing_docs: BTreeMap<String, Vec<DocId>> = default();
+        for (doc_id, branch_path, hash, _length_octets) in
+            self.rt.doc_blobs_index_repo.list_all_memberships().await?
+        {
+            if docs.contains_key(&doc_id) {
+                let referencing = referencing_docs.entry(hash).or_default();
+                if !referencing.contains(&doc_id) {
+                    referencing.push(doc_id.clone());
+                }
+            }
+            indexed_branches
+                .entry(doc_id)
+                .or_default()
+                .insert(branch_path.to_string());
+        }
+
+        for (doc_id, branch_paths) in indexed_branches {
+            let Some(doc) = docs.get(&doc_id) else {
+                findings.push(DoctorFinding::IndexRowsForDeletedDoc {
+                    index: DoctorIndex::DocBlobs,
+                    doc_id,
+                });
+                continue;
+            };
+            for branch_path in branch_paths {
+                if !doc.branches.contains_key(&branch_path) {
+                    findings.push(DoctorFinding::BlobIndexRowsForDeletedBranch {
+                        doc_id: doc_id.clone(),
+                        branch_path,
+                    });
+                }
+            }
+        }
+
+        let docs_scope_part_id = crate::part_id_from_label(BLOB_SCOPE_DOCS_PARTITION_ID);
+        for (hash, doc_ids) in referencing_docs {
+            let blob_id = hash
+                .parse::<crate::blobs::BlobId>()
+                .wrap_err("invalid blob id in doc blobs index")?;
+            if !self.rt.blobs_repo.has_hash(blob_id).await?
+                && !self.rt.blobs_repo.is_evicted(blob_id).await?
+            {
+                findings.push(DoctorFinding::BlobMissingLocally {
+                    hash: hash.clone(),
+                    doc_ids,
+                });
+            }
+            if !self
+                .rt
+                .rcx
+                .part_store
+                .obj_parts(blob_id)
+                .await?
+                .contains(&docs_scope_part_id)
+            {
+                findings.push(DoctorFinding::BlobMissingFromScope { hash });
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_facet_ref_index(
+        &self,
+        docs: &HashMap<DocId, DocNBranches>,
+        findings: &mut Vec<DoctorFinding>,
+    ) -> Res<()> {
+        for doc_id in self.rt.doc_facet_ref_index_repo.list_all_doc_ids().await? {
+            if !docs.contains_key(&doc_id) {
+                findings.push(DoctorFinding::IndexRowsForDeletedDoc {
+                    index: DoctorIndex::FacetRef,
+                    doc_id,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_facet_set_index(
+        &self,
+        docs: &HashMap<DocId, DocNBranches>,
+        findings: &mut Vec<DoctorFinding>,
+    ) -> Res<()> {
+        let indexed: HashSet<DocId> = self
+            .rt
+            .doc_facet_set_index_repo
+            .list_all_doc_ids()
+            .await?
+            .into_iter()
+            .collect();
+        for doc_id in &indexed {
+            if !docs.contains_key(doc_id) {
+                findings.push(DoctorFinding::IndexRowsForDeletedDoc {
+                    index: DoctorIndex::FacetSet,
+                    doc_id: doc_id.clone(),
+                });
+            }
+        }
+        for doc in docs.values() {
+            let stale = match self.main_branch_facet_tags(doc).await? {
+                Some(expected) => {
+                    let actual: HashSet<String> = self
+                        .rt
+                        .doc_facet_set_index_repo
+                        .list_tags_for_doc(&doc.doc_id)
+                        .await?
+                        .into_iter()
+                        .collect();
+                    !indexed.contains(&doc.doc_id) || actual != expected
+                }
+                None => indexed.contains(&doc.doc_id),
+            };
+            if stale {
+                findings.push(DoctorFinding::FacetSetIndexStale {
+                    doc_id: doc.doc_id.clone(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// The tags the facet set index should have for the doc, `None` if it
+    /// shouldn't have the doc at all.
+    async fn main_branch_facet_tags(&self, doc: &DocNBranches) -> Res<Option<HashSet<String>>> {
+        let Some(heads) = doc.branches.get("main") else {
+            return Ok(None);
+        };
+        let Some(facet_keys) = self
+            .rt
+            .drawer
+            .facet_keys_at_branch_heads(&doc.doc_id, &BranchPathBuf::from("main"), heads)
+            .await?
+        else {
+            return Ok(None);
+        };
+        let mut tags: HashSet<String> = facet_keys
+            .iter()
+            .map(|facet_key| facet_key.tag.to_string())
+            .collect();
+        tags.remove(WellKnownFacetTag::Dmeta.as_str());
+        Ok(Some(tags))
+    }
+
+    async fn check_switch_store(&self, findings: &mut Vec<DoctorFinding>) -> Res<()> {
+        let live_branch_docs: HashSet<String> = self
+            .rt
+            .drawer
+            .live_branch_docs()
+            .await?
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        for (branch_doc_id, doc_id) in self.switch_store.list_present_doc_states().await? {
+            if !live_branch_docs.contains(&branch_doc_id) {
+                findings.push(DoctorFinding::SwitchDocStateStale {
+                    branch_doc_id,
+                    doc_id,
+                });
+            }
+        }
+
+        let part_id = self.rt.drawer.replicated_partition_id();
+        let partition_id = part_id.to_string();
+        let cursor = self
+            .switch_store
+            .get_partition_cursor(&partition_id)
+            .await?;
+        let latest_cursor = match self
+            .rt
+            .rcx
+            .part_store
+            .summarize_parts([part_id].into())
+            .await?
+        {
+            Ok(mut summaries) => summaries
+                .remove(&part_id)
+                .map(|summary| summary.latest_cursor)
+                .unwrap_or_default(),
+            Err(ListPartsError::UnkownParts { .. }) => 0,
+        };
+        if cursor > latest_cursor {
+            findings.push(DoctorFinding::SwitchCursorAhead {
+                partition_id,
+                cursor,
+                latest_cursor,
+            });
+        }
+        Ok(())
+    }
+
+    async fn check_dispatches(&self, findings: &mut Vec<DoctorFinding>) -> Res<()> {
+        let wflow_partition_id = self.rt.local_wflow_part_id().to_string();
+        let latest_idx = self.rt.wcx.logstore.latest_idx().await?;
+        let frontier = self
+            .rt
+            .dispatch_repo
+            .get_wflow_part_frontier(&wflow_partition_id)
+            .await
+            .unwrap_or_default();
+        if frontier > latest_idx {
+            findings.push(DoctorFinding::WflowFrontierAhead {
+                wflow_partition_id,
+                frontier,
+                latest_idx,
+            });
+        }
+
+        let last_applied = self
+            .rt
+            .wflow_part_state
+            .last_applied_entry_id
+            .load(std::sync::atomic::Ordering::SeqCst);
+        // ended jobs are only suspect once we've seen every entry the
+        // reducer applied, before that the completion might still be coming
+        let caught_up = frontier >= last_applied;
+        let dispatches = self.rt.dispatch_repo.list().await;
+        let jobs = self.rt.wflow_part_state.read_jobs().await;
+        for (dispatch_id, dispatch) in dispatches {
+            if dispatch.status != DispatchStatus::Active {
+                continue;
+            }
+            let ActiveDispatchDeets::Wflow {
+                wflow_job_id: Some(wflow_job_id),
+                entry_id: Some(entry_id),
+                ..
+            } = &dispatch.deets
+            else {
+                continue;
+            };
+            // not applied by the reducer yet
+            if *entry_id > last_applied {
+                continue;
+            }
+            match jobs.job_status(wflow_job_id) {
+                None => findings.push(DoctorFinding::DispatchUnknownWflowJob {
+                    dispatch_id,
+                    wflow_job_id: wflow_job_id.clone(),
+                }),
+                Some(JobStatus::Active | JobStatus::Cancelling) => {}
+                Some(_) if caught_up => findings.push(DoctorFinding::DispatchWflowJobEnded {
+                    dispatch_id,
+                    wflow_job_id: wflow_job_id.clone(),
+                }),
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    async fn check_facet_heads(
+        &self,
+        docs: &HashMap<DocId, DocNBranches>,
+        findings: &mut Vec<DoctorFinding>,
+    ) -> Res<()> {
+        for doc in docs.values() {
+            for (branch_name, heads) in &doc.branches {
+                let branch_path = BranchPathBuf::from(branch_name.as_str());
+                let Some(facet_keys) = self
+                    .rt
+                    .drawer
+                    .facet_keys_at_branch_heads(&doc.doc_id, &branch_path, heads)
+                    .await?
+                else {
+                    continue;
+                };
+                for facet_key in facet_keys {
+                    if facet_key.tag == WellKnownFacetTag::Dmeta.into() {
+                        continue;
+                    }
+                    let reason = match self
+                        .rt
+                        .drawer
+                        .get_facet_heads_at_branch_heads(
+                            &doc.doc_id,
+                            &branch_path,
+                            heads,
+                            &facet_key,
+                        )
+                        .await
+                    {
+                        Ok(facet_heads) if facet_heads.is_empty() => {
+                            "no updates recorded in dmeta".to_string()
+                        }
+                        Ok(_) => continue,
+                        Err(err) => err.to_string(),
+                    };
+                    findings.push(DoctorFinding::FacetHeadsUnrecoverable {
+                        doc_id: doc.doc_id.clone(),
+                        branch_path: branch_name.clone(),
+                        facet_key: facet_key.to_string(),
+                        reason,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns whether the finding could be repaired.
+    async fn repair(&self, finding: &DoctorFinding) -> Res<bool> {
+        match finding {
+            DoctorFinding::IndexRowsForDeletedDoc { index, doc_id } => match index {
+                DoctorIndex::DocBlobs => self.rt.doc_blobs_index_repo.delete_doc(doc_id).await?,
+                DoctorIndex::FacetRef => {
+                    self.rt.doc_facet_ref_index_repo.delete_doc(doc_id).await?
+                }
+                DoctorIndex::FacetSet => {
+                    self.rt.doc_facet_set_index_repo.delete_doc(doc_id).await?
+                }
+            },
+            DoctorFinding::BlobIndexRowsForDeletedBranch { doc_id, .. } => {
+                let branch_paths: Vec<BranchPathBuf> = self
+                    .rt
+                    .drawer
+                    .get_doc_branches(doc_id)
+                    .await?
+                    .map(|doc| {
+                        doc.branches
+                            .keys()
+                            .map(|name| BranchPathBuf::from(name.as_str()))
+                            .collect()
+                    })
+                    .unwrap_or_default();
+                self.rt
+                    .doc_blobs_index_repo
+                    .delete_doc_branches_not_in(doc_id, &branch_paths)
+                    .await?;
+            }
+            DoctorFinding::FacetSetIndexStale { doc_id } => {
+                let doc = self.rt.drawer.get_doc_branches(doc_id).await?;
+                let heads = doc.as_ref().and_then(|doc| doc.branches.get("main"));
+                let facet_keys = match heads {
+                    Some(heads) => {
+                        self.rt
+                            .drawer
+                            .facet_keys_at_branch_heads(doc_id, &BranchPathBuf::from("main"), heads)
+                            .await?
+                    }
+                    None => None,
+                };
+                match (heads, facet_keys) {
+                    (Some(heads), Some(facet_keys)) => {
+                        self.rt
+                            .doc_facet_set_index_repo
+                            .reindex_doc_with_keys(doc_id, heads, &facet_keys)
+                            .await?
+                    }
+                    _ => self.rt.doc_facet_set_index_repo.delete_doc(doc_id).await?,
+                }
+            }
+            DoctorFinding::BlobMissingFromScope { hash } => {
+                let blob_id = hash
+                    .parse::<crate::blobs::BlobId>()
+                    .wrap_err("invalid blob id in doc blobs index")?;
+                self.rt
+                    .blobs_repo
+                    .add_hash_to_scope(BlobScope::Docs, blob_id)
+                    .await?;
+            }
+            DoctorFinding::SwitchDocStateStale { branch_doc_id, .. } => {
+                self.switch_store
+                    .mark_doc_state_absent(branch_doc_id)
+                    .await?;
+            }
+            DoctorFinding::SwitchCursorAhead {
+                partition_id,
+                latest_cursor,
+                ..
+            } => {
+                // rather than from zero, that'd replay every doc to the sinks
+                self.switch_store
+                    .set_partition_cursor(partition_id, *latest_cursor)
+                    .await?;
+            }
+            DoctorFinding::WflowFrontierAhead {
+                wflow_partition_id,
+                latest_idx,
+                ..
+            } => {
+                self.rt
+                    .dispatch_repo
+                    .set_wflow_part_frontier(wflow_partition_id.clone(), *latest_idx)
+                    .await?;
+            }
+            DoctorFinding::DispatchUnknownWflowJob {
+                dispatch_id,
+                wflow_job_id,
+            } => {
+                self.rt
+                    .fail_stranded_dispatch(
+                        dispatch_id,
+                        &format!("wflow job {wflow_job_id} not found"),
+                    )
+                    .await?;
+            }
+            DoctorFinding::DispatchWflowJobEnded {
+                dispatch_id,
+                wflow_job_id,
+            } => {
+                self.rt
+                    .fail_stranded_dispatch(
+                        dispatch_id,
+                        &format!("wflow job {wflow_job_id} ended without completing dispatch"),
+                    )
+                    .await?;
+            }
+            DoctorFinding::BlobMissingLocally { .. }
+            | DoctorFinding::FacetHeadsUnrecoverable { .. } => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn doctor_repairs_index_rows_for_deleted_docs() -> Res<()> {
+        let test_cx = crate::test_support::test_cx("doctor_index_rows").await?;
+        let doctor = Doctor::load(Arc::clone(&test_cx.rt)).await?;
+
+        let doc_id = DocId::from("doctor-deleted-doc");
+        test_cx
+            .rt
+            .doc_facet_set_index_repo
+            .reindex_doc_with_keys(&doc_id, &ChangeHashSet::default(), &default())
+            .await?;
+        let expected = DoctorFinding::IndexRowsForDeletedDoc {
+            index: DoctorIndex::FacetSet,
+            doc_id: doc_id.clone(),
+        };
+
+        let report = doctor.run(false).await?;
+        assert!(report.findings.contains(&expected));
+        assert_eq!(report.repaired, 0);
+
+        let report = doctor.run(true).await?;
+        assert!(report.findings.contains(&expected));
+        assert!(report.repaired >= 1);
+
+        let report = doctor.run(false).await?;
+        assert!(!report.findings.contains(&expected));
+
+        test_cx.stop().await?;
+        Ok(())
+    }
+}
@@ -7,6 +7,7 @@ use crate::interlude::*;
 use crate::plugs::PlugsRepo;
 
 mod cache;
+mod conflicts;
 pub mod dmeta;
 mod events;
 mod facet_recovery;
@@ -14,11 +15,16 @@ pub mod lru;
 mod meta;
 mod mutations;
 mod queries;
+mod shards;
 #[cfg(test)]
 mod tests;
 pub mod types;
 
-pub use crate::drawer::types::{DocBundle, DocEntry, DocEntryDiff, DocNBranches, DrawerEvent};
+pub use crate::drawer::types::{
+    ConflictingValue, DocBundle, DocEntry, DocEntryDiff, DocNBranches, DrawerEvent, FacetConflict,
+    TombstonedBranchDoc,
+};
+pub use shards::{DrawerShardMeta, DrawerShardsStore};
 
 use big_repo::{SharedBigRepo, SharedPartStore};
 use cache::FacetCacheKey;
@@ -32,7 +38,9 @@ use daybook_types::url::{parse_facet_ref, FACET_SELF_DOC_ID};
 
 use tokio_util::sync::CancellationToken;
 
-const DRAWER_REPLICATED_PARTITION_PREFIX: &str = "drawer.replicated";
+pub(crate) const DRAWER_REPLICATED_PARTITION_PREFIX: &str = "drawer.replicated";
+/// How long opening a branch doc that isn't local waits on peers.
+const BRANCH_DOC_FETCH_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 enum BranchKind {
@@ -53,6 +61,8 @@ pub struct DrawerRepo {
     facet_schema_validators:
         surelock::mutex::Mutex<HashMap<(String, String), Arc<jsonschema::Validator>>>,
     branch_handles: surelock::mutex::Mutex<HashMap<DocumentId, big_repo::BigDocHandle>>,
+    // Op ids of the conflicts already announced, per doc branch
+    known_conflicts: surelock::mutex::Mutex<HashMap<(DocId, BranchPathBuf), HashSet<String>>>,
 
     // LRU Pools (Policy only)
     entry_pool: SharedKeyedLruPool<DocId>,
@@ -61,8 +71,16 @@ pub struct DrawerRepo {
     pub registry: Arc<crate::repos::ListenersRegistry>,
     cancel_token: CancellationToken,
     _change_listener_tickets: Vec<big_repo::BigRepoChangeListenerRegistration>,
-    current_heads: surelock::mutex::Mutex<ChangeHashSet>,
-    drawer_doc_handle: big_repo::BigDocHandle,
+    revoked_device_ids: surelock::mutex::Mutex<Arc<HashSet<String>>>,
+
+    // Sharding, the root shard is the drawer doc itself
+    app_doc_handle: Option<big_repo::BigDocHandle>,
+    shards: surelock::mutex::Mutex<HashMap<DocumentId, shards::DrawerShard>>,
+    shards_write_lock: tokio::sync::Mutex<()>,
+    max_shard_entries: std::sync::atomic::AtomicUsize,
+    shard_notif_tx: tokio::sync::mpsc::UnboundedSender<Vec<big_repo::BigRepoChangeNotification>>,
+    shard_listener_tickets:
+        surelock::mutex::Mutex<Vec<big_repo::BigRepoChangeListenerRegistration>>,
     meta_store_sql: SqlCtx,
     plugs_repo: Option<Arc<crate::plugs::PlugsRepo>>,
 }
@@ -95,11 +113,14 @@ impl DrawerRepo {
         &self.drawer_doc_id
     }
 
+    /// `app_doc_id` holds the shard list. Without it the drawer stays a
+    /// single doc.
     #[expect(clippy::too_many_arguments)]
     pub async fn load(
         big_repo: SharedBigRepo,
         partition_store: SharedPartStore,
         drawer_doc_id: DocumentId,
+        app_doc_id: Option<DocumentId>,
         local_user_path: daybook_types::doc::UserPathBuf,
         meta_db_pool: SqlCtx,
         _local_state_root: PathBuf,
@@ -120,14 +141,29 @@ impl DrawerRepo {
             .with_document_read(|doc| ChangeHashSet(doc.get_heads().into()))
             .await;
 
-        // Listen for changes to docs.map
-        let (ticket, notif_rx) = big_repo
-            .subscribe_change_listener(big_repo::BigRepoChangeFilter {
-                doc_id: Some(big_repo::BigRepoDocIdFilter::new(drawer_doc_id)),
-                path: vec!["docs".into(), "map".into()],
-                origin: None,
-            })
-            .await?;
+        let app_doc_handle = match app_doc_id {
+            Some(app_doc_id) => Some(
+                big_repo
+                    .get_doc(&app_doc_id)
+                    .await?
+                    .ok_or_eyre("app doc not found")?,
+            ),
+            None => None,
+        };
+        let mut change_listener_tickets = vec![];
+        let shards_notif_rx = match app_doc_id {
+            Some(app_doc_id) => {
+                let (ticket, notif_rx) =
+                    DrawerShardsStore::register_change_listener(&big_repo, &app_doc_id, vec![])
+                        .await?;
+                change_listener_tickets.push(ticket);
+                Some(notif_rx)
+            }
+            None => None,
+        };
+
+        // Listen for changes to docs.map, shard listeners all feed this
+        let (shard_notif_tx, notif_rx) = tokio::sync::mpsc::unbounded_channel();
 
         let main_cancel_token = CancellationToken::new();
         let repo = Arc::new(Self {
@@ -141,13 +177,27 @@ impl DrawerRepo {
             facet_cache: surelock::mutex::Mutex::new(FacetCacheState::new()),
             facet_schema_validators: surelock::mutex::Mutex::new(HashMap::new()),
             branch_handles: surelock::mutex::Mutex::new(HashMap::new()),
+            known_conflicts: surelock::mutex::Mutex::new(HashMap::new()),
             entry_pool,
             doc_pool,
             registry: crate::repos::ListenersRegistry::new(),
             cancel_token: main_cancel_token.child_token(),
-            _change_listener_tickets: vec![ticket],
-            current_heads: surelock::mutex::Mutex::new(initial_heads),
-            drawer_doc_handle: drawer_am_handle,
+            _change_listener_tickets: change_listener_tickets,
+            revoked_device_ids: surelock::mutex::Mutex::new(default()),
+            app_doc_handle,
+            shards: surelock::mutex::Mutex::new(
+                [(
+                    drawer_doc_id,
+                    shards::DrawerShard::loaded(drawer_am_handle, initial_heads),
+                )]
+                .into(),
+            ),
+            shards_write_lock: tokio::sync::Mutex::new(()),
+            max_shard_entries: std::sync::atomic::AtomicUsize::new(
+                shards::DEFAULT_MAX_SHARD_ENTRIES,
+            ),
+            shard_notif_tx,
+            shard_listener_tickets: surelock::mutex::Mutex::new(vec![]),
             meta_store_sql: meta_db_pool,
             #[cfg(not(test))]
             plugs_repo: Some(plugs_repo),
@@ -155,14 +205,23 @@ impl DrawerRepo {
             plugs_repo,
         });
         repo.ensure_local_branch_schema().await?;
+        repo.watch_shard(drawer_doc_id).await?;
+        repo.refresh_shards(false).await?;
 
         let worker_handle = tokio::spawn({
             let repo = Arc::clone(&repo);
             let cancel_token = main_cancel_token.clone();
             async move {
-                repo.notifs_loop(notif_rx, cancel_token)
-                    .await
-                    .expect("error handling notifs")
+                let notifs = repo.notifs_loop(notif_rx, cancel_token.clone());
+                match shards_notif_rx {
+                    Some(shards_notif_rx) => {
+                        let (notifs_res, shards_res) =
+                            tokio::join!(notifs, repo.shards_loop(shards_notif_rx, cancel_token));
+                        notifs_res.expect("error handling notifs");
+                        shards_res.expect("error maintaining drawer shards");
+                    }
+                    None => notifs.await.expect("error handling notifs"),
+                }
             }
         });
 
@@ -175,6 +234,70 @@ impl DrawerRepo {
         ))
     }
 
+    /// Actors of these devices get flagged as revoked in the Dmeta facet.
+    pub fn set_revoked_device_ids(&self, device_ids: HashSet<String>) {
+        surelock::key::lock_scope(|key| {
+            let (mut current, _key) = key.lock(&self.revoked_device_ids);
+            *current = Arc::new(device_ids);
+        });
+    }
+
+    /// Keeps the revoked devices in step with the revocations in the
+    /// config until the repo is stopped.
+    pub async fn track_device_revocations(
+        self: &Arc<Self>,
+        config_repo: Arc<crate::config::ConfigRepo>,
+    ) {
+        use crate::repos::Repo;
+        let listener = config_repo.subscribe(crate::repos::SubscribeOpts::new(16));
+        self.set_revoked_device_ids(config_repo.get_device_trust().await.revoked_device_ids());
+        let repo = Arc::downgrade(self);
+        let cancel_token = self.cancel_token.child_token();
+        tokio::spawn(async move {
+            loop {
+                let event = tokio::select! {
+                    biased;
+                    _ = cancel_token.cancelled() => break,
+                    val = listener.recv_lossy_async() => match val {
+                        Ok(event) => event,
+                        Err(_) => break,
+                    },
+                };
+                if !matches!(&*event, crate::config::ConfigEvent::Changed { .. }) {
+                    continue;
+                }
+                let Some(repo) = repo.upgrade() else {
+                    break;
+                };
+                repo.set_revoked_device_ids(
+                    config_repo.get_device_trust().await.revoked_device_ids(),
+                );
+            }
+        });
+    }
+
+    fn flag_revoked_actors(&self, facets: &mut HashMap<FacetKey, daybook_types::doc::ArcFacetRaw>) {
+        let revoked = surelock::key::lock_scope(|key| {
+            let (current, _key) = key.lock(&self.revoked_device_ids);
+            Arc::clone(&current)
+        });
+        if revoked.is_empty() {
+            return;
+        }
+        use daybook_types::doc::{WellKnownFacet, WellKnownFacetTag};
+        let dmeta_key = FacetKey::from(WellKnownFacetTag::Dmeta);
+        let Some(raw) = facets.get_mut(&dmeta_key) else {
+            return;
+        };
+        let Ok(WellKnownFacet::Dmeta(mut dmeta)) =
+            WellKnownFacet::from_json(raw.as_ref().clone(), WellKnownFacetTag::Dmeta)
+        else {
+            return;
+        };
+        dmeta.flag_revoked_actors(&revoked);
+        *raw = Arc::new(WellKnownFacet::Dmeta(dmeta).into());
+    }
+
     fn branch_kind_for_path(
         &self,
         branch_path: &daybook_types::doc::BranchPath,
@@ -251,6 +374,11 @@ impl DrawerRepo {
         &self,
         branch_doc_id: DocumentId,
     ) -> Res<Option<ChangeHashSet>> {
+        if self.local_branch_handle(branch_doc_id).await?.is_none() {
+            if let Some(snapshot) = self.branch_doc_snapshot(branch_doc_id).await? {
+                return Ok(Some(ChangeHashSet(snapshot.heads)));
+            }
+        }
         let Some(handle) = self.get_handle_by_branch_doc_id(branch_doc_id).await? else {
             return Ok(None);
         };
@@ -272,9 +400,35 @@ impl DrawerRepo {
             .await
     }
 
+    /// The handle of a branch doc, pulling it with its history from peers
+    /// if it's not local.
     async fn get_handle_by_branch_doc_id(
         &self,
         document_id: DocumentId,
+    ) -> Res<Option<big_repo::BigDocHandle>> {
+        if let Some(handle) = self.local_branch_handle(document_id).await? {
+            return Ok(Some(handle));
+        }
+        let Some(handle) = tokio::time::timeout(
+            BRANCH_DOC_FETCH_TIMEOUT,
+            self.big_repo.get_or_fetch_doc(&document_id),
+        )
+        .await
+        .map_err(|_| ferr!("timed out fetching branch doc {document_id} from peers"))??
+        else {
+            return Ok(None);
+        };
+        surelock::key::lock_scope(|key| {
+            let (mut handles, _key) = key.lock(&self.branch_handles);
+            handles.insert(document_id, handle.clone());
+        });
+        Ok(Some(handle))
+    }
+
+    /// The handle of a branch doc if it's local, never touches peers.
+    async fn local_branch_handle(
+        &self,
+        document_id: DocumentId,
     ) -> Res<Option<big_repo::BigDocHandle>> {
         if let Some(handle) = surelock::key::lock_scope(|key| {
             let (handles, _key) = key.lock(&self.branch_handles);
@@ -282,10 +436,6 @@ impl DrawerRepo {
         }) {
             return Ok(Some(handle));
         }
-        let has_local = self.big_repo.get_doc(&document_id).await?.is_some();
-        if !has_local {
-            return Ok(None);
-        }
         let Some(handle) = self.big_repo.get_doc(&document_id).await? else {
             return Ok(None);
         };
@@ -296,6 +446,20 @@ impl DrawerRepo {
         Ok(Some(handle))
     }
 
+    /// The latest state of a branch doc that isn't local, without its
+    /// history. Only shallow clones have a fetcher that serves these.
+    async fn branch_doc_snapshot(
+        &self,
+        document_id: DocumentId,
+    ) -> Res<Option<big_repo::DocSnapshot>> {
+        tokio::time::timeout(
+            BRANCH_DOC_FETCH_TIMEOUT,
+            self.big_repo.get_or_fetch_snapshot(&document_id),
+        )
+        .await
+        .map_err(|_| ferr!("timed out fetching branch doc snapshot {document_id} from peers"))?
+    }
+
     async fn resolve_handle_for_branch_heads(
         &self,
         doc_id: &DocId,
@@ -330,11 +494,11 @@ impl DrawerRepo {
 
     async fn latest_doc_delete_tombstone(
         &self,
+        shard: &big_repo::BigDocHandle,
         doc_id: &DocId,
         heads: &Arc<[automerge::ChangeHash]>,
     ) -> Res<Option<DocDeleteTombstone>> {
-        let Some(tags) = self
-            .drawer_doc_handle
+        let Some(tags) = shard
             .hydrate_path_at_heads::<Vec<DocDeleteTombstone>>(
                 heads,
                 automerge::ROOT,
@@ -357,11 +521,11 @@ impl DrawerRepo {
         snapshot: &BranchSnapshot,
     ) -> Res<HashSet<FacetKey>> {
         let branch_doc_id = snapshot.branch_doc_id;
-        let handle = self
-            .big_repo
-            .get_doc(&branch_doc_id)
-            .await?
-            .ok_or_eyre("branch doc handle missing for tombstoned branch")?;
+        // tombstoned branch docs get purged by gc once every peer has them
+        let Some(handle) = self.big_repo.get_doc(&branch_doc_id).await? else {
+            debug!(%branch_doc_id, "branch doc of tombstoned branch is gone");
+            return Ok(HashSet::new());
+        };
         let keys = handle
             .with_document_read(|am_doc| {
                 let facets_obj = match automerge::ReadDoc::get_at(
@@ -0,0 +1,360 @@
+//! Automerge settles concurrent writes to the same map key or list slot by
+//! picking a deterministic winner and keeping the other values around as
+//! conflicts. Readers only ever see the winner so we dig the rest out here,
+//! tag them with what Dmeta knows about the writers and let the user pick.
+
+use crate::interlude::*;
+
+use super::{dmeta, DrawerRepo};
+use crate::drawer::types::{ConflictingValue, DrawerError, DrawerEvent, FacetConflict};
+
+use automerge::transaction::Transactable;
+use automerge::ReadDoc;
+use daybook_types::doc::{DocId, FacetKey, UserMeta};
+
+impl DrawerRepo {
+    /// Conflicts currently present on the branch.
+    pub async fn get_facet_conflicts(
+        &self,
+        id: &DocId,
+        branch_path: &BranchPath,
+    ) -> Res<Vec<FacetConflict>> {
+        let Some(handle) = self.conflicts_handle(id, branch_path).await? else {
+            return Ok(vec![]);
+        };
+        handle.with_document_read(find_conflicts).await
+    }
+
+    /// Write `value` at `path` of the facet, superseding every value that
+    /// was there. The chosen value needn't be one of the conflicting ones.
+    #[tracing::instrument(level = "trace", skip(self, value, user_path))]
+    pub async fn resolve_facet_conflict(
+        &self,
+        id: &DocId,
+        branch_path: &BranchPath,
+        facet_key: &FacetKey,
+        path: &str,
+        value: serde_json::Value,
+        user_path: Option<&UserPath>,
+    ) -> Result<(), DrawerError> {
+        if self.cancel_token.is_cancelled() {
+            return Err(ferr!("repo is stopped"))?;
+        }
+        let branch_ref = self.get_branch_ref(id, branch_path).await?.ok_or_else(|| {
+            DrawerError::BranchNotFound {
+                name: branch_path.to_string(),
+            }
+        })?;
+        let handle = self
+            .get_handle_by_branch_doc_id(branch_ref.branch_doc_id)
+            .await?
+            .ok_or_else(|| DrawerError::DocNotFound { id: id.clone() })?;
+        let mutation_actor_id = self.content_actor_id(user_path, branch_ref.branch_doc_id);
+        let now = Timestamp::now();
+
+        let invalidated_uuids = handle
+            .with_document(|am_doc| {
+                am_doc.set_actor(mutation_actor_id.clone());
+                let mut tx = am_doc.transaction();
+                let facets_obj = match tx.get(automerge::ROOT, "facets")? {
+                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+                    _ => eyre::bail!("facets object not found in content doc"),
+                };
+                let key_str = facet_key.to_string();
+                let (obj, prop) = resolve_pointer(&tx, &facets_obj, &key_str, path)?;
+                // Reconcile skips values that didn't change so the winner
+                // wouldn't get rewritten, an explicit put is causally after
+                // all the conflicting ops and clears them.
+                tx.put(&obj, prop, automerge::ScalarValue::Null)?;
+
+                let mut facet = am_utils_rs::codecs::json::hydrate_value(
+                    &tx,
+                    &facets_obj,
+                    autosurgeon::Prop::Key(key_str.clone().into()),
+                )?;
+                if path.is_empty() {
+                    facet = value;
+                } else {
+                    *facet
+                        .pointer_mut(path)
+                        .ok_or_else(|| ferr!("conflict path not found in facet: {path}"))? = value;
+                }
+                autosurgeon::reconcile_prop(&mut tx, &facets_obj, &*key_str, ThroughJson(facet))?;
+
+                let invalidated_uuids = dmeta::apply_update(
+                    &mut tx,
+                    &facets_obj,
+                    std::slice::from_ref(facet_key),
+                    &[],
+                    now,
+                    user_path,
+                    &mutation_actor_id,
+                )?;
+                tx.commit();
+                eyre::Ok(invalidated_uuids)
+            })
+            .await??;
+
+        self.invalidate_entry_cache(id);
+        for uuid in invalidated_uuids {
+            self.invalidate_facet_cache_entry(id, &uuid);
+        }
+        self.track_facet_conflicts(id, branch_path, &handle).await?;
+        Ok(())
+    }
+
+    /// Rescan the branch and emit [`DrawerEvent::FacetConflicts`] if it has
+    /// conflicts we haven't announced yet.
+    pub(super) async fn track_facet_conflicts(
+        &self,
+        id: &DocId,
+        branch_path: &BranchPath,
+        handle: &big_repo::BigDocHandle,
+    ) -> Res<()> {
+        let conflicts = handle.with_document_read(find_conflicts).await?;
+        let op_ids: HashSet<String> = conflicts
+            .iter()
+            .flat_map(|conflict| conflict.losers.iter().chain([&conflict.winner]))
+            .map(|value| value.op_id.clone())
+            .collect();
+        let has_new = surelock::key::lock_scope(|key| {
+            let (mut known, _key) = key.lock(&self.known_conflicts);
+            let known_key = (id.clone(), branch_path.to_owned());
+            if op_ids.is_empty() {
+                known.remove(&known_key);
+                return false;
+            }
+            let previous = known.insert(known_key, op_ids.clone()).unwrap_or_default();
+            !op_ids.is_subset(&previous)
+        });
+        if has_new {
+            self.registry.notify([DrawerEvent::FacetConflicts {
+                id: id.clone(),
+                branch_path: branch_path.to_owned(),
+                conflicts,
+                drawer_heads: self.get_drawer_heads(),
+                origin: self.local_origin(),
+            }]);
+        }
+        Ok(())
+    }
+
+    async fn conflicts_handle(
+        &self,
+        id: &DocId,
+        branch_path: &BranchPath,
+    ) -> Res<Option<big_repo::BigDocHandle>> {
+        let Some(branch_ref) = self.get_branch_ref(id, branch_path).await? else {
+            return Ok(None);
+        };
+        self.get_handle_by_branch_doc_id(branch_ref.branch_doc_id)
+            .await
+    }
+}
+
+fn find_conflicts(doc: &automerge::Automerge) -> Res<Vec<FacetConflict>> {
+    let facets_obj = match doc.get(automerge::ROOT, "facets")? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+        _ => return Ok(vec![]),
+    };
+    let dmeta_key = dmeta::dmeta_key();
+    let actors = match doc.get(&facets_obj, &dmeta_key)? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), dmeta_obj)) => {
+            match autosurgeon::hydrate_prop::<
+                _,
+                Option<ThroughJson<HashMap<String, UserMeta>>>,
+                _,
+                _,
+            >(doc, &dmeta_obj, "actors")?
+            {
+                Some(ThroughJson(map)) => map,
+                None => HashMap::new(),
+            }
+        }
+        _ => HashMap::new(),
+    };
+
+    let mut out = vec![];
+    let keys: Vec<String> = doc.keys(&facets_obj).collect();
+    for key_str in keys {
+        // Dmeta is bookkeeping, concurrent touches are expected there.
+        if key_str == dmeta_key {
+            continue;
+        }
+        let facet_key = FacetKey::from(key_str.as_str());
+        let updated_at = facet_updated_at_by_actor(doc, &facet_key)?;
+        let mut walker = ConflictWalker {
+            doc,
+            facet_key: &facet_key,
+            actors: &actors,
+            updated_at: &updated_at,
+            out: &mut out,
+        };
+        walker.walk(
+            &facets_obj,
+            automerge::Prop::Map(key_str.clone()),
+            String::new(),
+        )?;
+    }
+    Ok(out)
+}
+
+/// Latest touch per actor. Touches replace the whole `updatedAt` list so
+/// only concurrent ones are left to find here.
+fn facet_updated_at_by_actor(
+    doc: &automerge::Automerge,
+    facet_key: &FacetKey,
+) -> Res<HashMap<ActorId, Timestamp>> {
+    let mut out = HashMap::new();
+    let Some(facet_meta_obj) = dmeta::facet_meta_obj(doc, facet_key)? else {
+        return Ok(out);
+    };
+    let list = match doc.get(&facet_meta_obj, "updatedAt")? {
+        Some((automerge::Value::Object(automerge::ObjType::List), id)) => id,
+        _ => return Ok(out),
+    };
+    for idx in 0..doc.length(&list) {
+        let Some((automerge::Value::Scalar(scalar), op_id)) = doc.get(&list, idx)? else {
+            continue;
+        };
+        let (automerge::ScalarValue::Timestamp(secs), Some(actor)) =
+            (scalar.as_ref(), op_actor(&op_id))
+        else {
+            continue;
+        };
+        let Ok(ts) = Timestamp::from_second(*secs) else {
+            continue;
+        };
+        out.entry(actor)
+            .and_modify(|prev: &mut Timestamp| *prev = (*prev).max(ts))
+            .or_insert(ts);
+    }
+    Ok(out)
+}
+
+fn op_actor(op_id: &automerge::ObjId) -> Option<ActorId> {
+    match op_id {
+        automerge::ObjId::Id(_, actor_id, _) => Some(actor_id.clone()),
+        automerge::ObjId::Root => None,
+    }
+}
+
+struct ConflictWalker<'a> {
+    doc: &'a automerge::Automerge,
+    facet_key: &'a FacetKey,
+    actors: &'a HashMap<String, UserMeta>,
+    updated_at: &'a HashMap<ActorId, Timestamp>,
+    out: &'a mut Vec<FacetConflict>,
+}
+
+impl ConflictWalker<'_> {
+    fn walk(&mut self, obj: &automerge::ObjId, prop: automerge::Prop, path: String) -> Res<()> {
+        let Some((winner_value, winner_id)) = self.doc.get(obj, prop.clone())? else {
+            return Ok(());
+        };
+        let values = self.doc.get_all(obj, prop)?;
+        let actor_count = values
+            .iter()
+            .filter_map(|(_, op_id)| op_actor(op_id))
+            .collect::<HashSet<_>>()
+            .len();
+        // The same actor can't race itself in a way worth surfacing.
+        if actor_count > 1 {
+            let mut winner = None;
+            let mut losers = vec![];
+            for (value, op_id) in &values {
+                let value = self.conflicting_value(value, op_id)?;
+                if *op_id == winner_id {
+                    winner = Some(value);
+                } else {
+                    losers.push(value);
+                }
+            }
+            self.out.push(FacetConflict {
+                facet_key: self.facet_key.clone(),
+                path: path.clone(),
+                winner: winner.ok_or_eyre("conflict winner missing from get_all")?,
+                losers,
+            });
+        }
+        match winner_value {
+            automerge::Value::Object(automerge::ObjType::Map | automerge::ObjType::Table) => {
+                let keys: Vec<String> = self.doc.keys(&winner_id).collect();
+                for key in keys {
+                    let child_path = format!("{path}/{}", escape_pointer_token(&key));
+                    self.walk(&winner_id, automerge::Prop::Map(key), child_path)?;
+                }
+            }
+            automerge::Value::Object(automerge::ObjType::List) => {
+                for idx in 0..self.doc.length(&winner_id) {
+                    self.walk(
+                        &winner_id,
+                        automerge::Prop::Seq(idx),
+                        format!("{path}/{idx}"),
+                    )?;
+                }
+            }
+            // Text merges character wise, scalars have no children
+            automerge::Value::Object(automerge::ObjType::Text) | automerge::Value::Scalar(_) => {}
+        }
+        Ok(())
+    }
+
+    fn conflicting_value(
+        &self,
+        value: &automerge::Value<'_>,
+        op_id: &automerge::ObjId,
+    ) -> Res<ConflictingValue> {
+        let actor = op_actor(op_id);
+        let actor_id = actor.as_ref().map(ToString::to_string).unwrap_or_default();
+        Ok(ConflictingValue {
+            op_id: op_id.to_string(),
+            value: am_utils_rs::codecs::json::hydrate_op_value(self.doc, value, op_id)?,
+            user_path: self
+                .actors
+                .get(&actor_id)
+                .map(|meta| meta.user_path.clone()),
+            updated_at: actor.and_then(|actor| self.updated_at.get(&actor).copied()),
+            actor_id,
+        })
+    }
+}
+
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+/// Walk the winners down a JSON pointer, returning the object and prop the
+/// pointer's last token addresses.
+fn resolve_pointer<D: ReadDoc>(
+    doc: &D,
+    facets_obj: &automerge::ObjId,
+    facet_key_str: &str,
+    path: &str,
+) -> Res<(automerge::ObjId, automerge::Prop)> {
+    let mut obj = facets_obj.clone();
+    let mut prop = automerge::Prop::Map(facet_key_str.to_string());
+    if path.is_empty() {
+        return Ok((obj, prop));
+    }
+    let Some(tokens) = path.strip_prefix('/') else {
+        eyre::bail!("conflict path is not a JSON pointer: {path}");
+    };
+    for token in tokens.split('/') {
+        let token = token.replace("~1", "/").replace("~0", "~");
+        obj = match doc.get(&obj, prop)? {
+            Some((automerge::Value::Object(_), id)) => id,
+            _ => eyre::bail!("conflict path not found in facet: {path}"),
+        };
+        prop = match doc.object_type(&obj)? {
+            automerge::ObjType::List => automerge::Prop::Seq(
+                token
+                    .parse()
+                    .wrap_err_with(|| format!("invalid list index in conflict path: {path}"))?,
+            ),
+            automerge::ObjType::Map | automerge::ObjType::Table => automerge::Prop::Map(token),
+            automerge::ObjType::Text => eyre::bail!("conflict path points into text: {path}"),
+        };
+    }
+    Ok((obj, prop))
+}
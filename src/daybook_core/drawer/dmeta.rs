@@ -13,7 +13,7 @@ use daybook_types::doc::{
     ChangeHashSet, FacetKey, FacetMeta, UserMeta, UserPath, WellKnownFacet, WellKnownFacetTag,
 };
 
-fn dmeta_key() -> String {
+pub(crate) fn dmeta_key() -> String {
     [WellKnownFacetTag::Dmeta.as_str(), "/main"].concat()
 }
 
@@ -241,6 +241,7 @@ fn set_user(
         actor_id.to_string(),
         UserMeta {
             user_path: user_path.to_owned(),
+            revoked: false,
         },
     );
     autosurgeon::reconcile_prop(tx, dmeta_obj, "actors", ThroughJson(actors))?;
@@ -274,6 +275,7 @@ pub fn ensure_for_add(
             actor_id.to_string(),
             UserMeta {
                 user_path: user_path.to_owned(),
+                revoked: false,
             },
         );
     }
@@ -1,5 +1,6 @@
 use crate::interlude::*;
 
+use super::shards::known_heads;
 use super::{BranchKind, DrawerRepo};
 
 use crate::drawer::types::{DocEntry, DrawerEvent};
@@ -46,22 +47,20 @@ impl DrawerRepo {
                 else {
                     continue;
                 };
-                if doc_id != self.drawer_doc_id {
+                let Some(shard) = self.loaded_shard(&doc_id) else {
                     eyre::bail!(
-                        "invariant break: drawer listener received change for wrong doc id: expected={} got={}",
-                        self.drawer_doc_id,
+                        "invariant break: drawer listener received change for unknown shard: got={}",
                         doc_id
                     );
-                }
-                let new_heads = ChangeHashSet(Arc::clone(&heads));
-                surelock::key::lock_scope(|key| {
-                    let (mut current_heads, _key) = key.lock(&self.current_heads);
-                    *current_heads = new_heads;
-                });
+                };
+                self.set_shard_heads(&doc_id, ChangeHashSet(Arc::clone(&heads)));
+                let drawer_heads = self.get_drawer_heads();
                 if let Err(err) = self
                     .events_for_patch(
+                        &shard,
                         &patch,
                         &heads,
+                        &drawer_heads,
                         &mut events,
                         Some(&origin),
                         Some(&self.local_peer_id),
@@ -75,25 +74,31 @@ impl DrawerRepo {
                 }
             }
 
-            if !events.is_empty() {
-                // Invalidate caches for updated docs
-                for event in &events {
-                    match event {
-                        DrawerEvent::DocUpdated { id, .. } | DrawerEvent::DocAdded { id, .. } => {
-                            self.invalidate_entry_cache(id);
-                            self.invalidate_facet_cache_doc(id);
-                        }
-                        DrawerEvent::DocDeleted { id, .. } => {
-                            self.invalidate_entry_cache(id);
-                            self.invalidate_facet_cache_doc(id);
-                        }
-                    }
-                }
+            self.notify_with_invalidation(&mut events);
+        }
+        Ok(())
+    }
 
-                self.registry.notify(events.drain(..));
+    pub(super) fn notify_with_invalidation(&self, events: &mut Vec<DrawerEvent>) {
+        if events.is_empty() {
+            return;
+        }
+        // Invalidate caches for updated docs
+        for event in events.iter() {
+            match event {
+                DrawerEvent::DocUpdated { id, .. } | DrawerEvent::DocAdded { id, .. } => {
+                    self.invalidate_entry_cache(id);
+                    self.invalidate_facet_cache_doc(id);
+                }
+                DrawerEvent::DocDeleted { id, .. } => {
+                    self.invalidate_entry_cache(id);
+                    self.invalidate_facet_cache_doc(id);
+                }
+                DrawerEvent::FacetConflicts { .. } => {}
             }
         }
-        Ok(())
+
+        self.registry.notify(events.drain(..));
     }
 
     pub async fn diff_events(
@@ -105,20 +110,44 @@ impl DrawerRepo {
             eyre::bail!("repo is stopped");
         }
 
-        let (patches, heads) = self
-            .drawer_doc_handle
-            .with_document_read(|am_doc| {
-                let heads = to.unwrap_or_else(|| ChangeHashSet(am_doc.get_heads().into()));
-                let patches = am_doc.diff_obj(&automerge::ROOT, &from, &heads, true)?;
-                eyre::Ok((patches, heads))
-            })
-            .await?;
-
+        let drawer_heads = to.clone().unwrap_or_else(|| self.get_drawer_heads());
         let mut events = vec![];
-        for patch in patches {
-            // Replay path: do not apply live-origin filtering.
-            self.events_for_patch(&patch, &heads.0, &mut events, None, None)
+        for (_shard_id, shard) in self.loaded_shards() {
+            // Each shard only gets the heads that belong to it. Shards
+            // created after `from` diff from the start.
+            let diff = shard
+                .with_document_read(|am_doc| {
+                    let from = known_heads(am_doc, &from);
+                    let heads = match &to {
+                        Some(to) => {
+                            let heads = known_heads(am_doc, to);
+                            if heads.is_empty() {
+                                return eyre::Ok(None);
+                            }
+                            heads
+                        }
+                        None => ChangeHashSet(am_doc.get_heads().into()),
+                    };
+                    let patches = am_doc.diff_obj(&automerge::ROOT, &from, &heads, true)?;
+                    eyre::Ok(Some((patches, heads)))
+                })
+                .await?;
+            let Some((patches, heads)) = diff else {
+                continue;
+            };
+            for patch in patches {
+                // Replay path: do not apply live-origin filtering.
+                self.events_for_patch(
+                    &shard,
+                    &patch,
+                    &heads.0,
+                    &drawer_heads,
+                    &mut events,
+                    None,
+                    None,
+                )
                 .await?;
+            }
         }
         Ok(events)
     }
@@ -183,10 +212,13 @@ impl DrawerRepo {
         Ok(events)
     }
 
+    #[expect(clippy::too_many_arguments)]
     async fn events_for_patch(
         &self,
+        shard: &big_repo::BigDocHandle,
         patch: &automerge::Patch,
         patch_heads: &Arc<[automerge::ChangeHash]>,
+        drawer_heads: &ChangeHashSet,
         out: &mut Vec<DrawerEvent>,
         live_origin: Option<&BigRepoChangeOrigin>,
         exclude_peer_id: Option<&PeerId>,
@@ -242,8 +274,7 @@ impl DrawerRepo {
                     "map".into(),
                     autosurgeon::Prop::Key(doc_id.to_string().into()),
                 ];
-                let new_entry = self
-                    .drawer_doc_handle
+                let new_entry = shard
                     .hydrate_path_at_heads::<DocEntry>(
                         patch_heads,
                         automerge::ROOT,
@@ -258,30 +289,17 @@ impl DrawerRepo {
                             patch_heads.len()
                         )
                     })?;
-                let drawer_heads = ChangeHashSet(Arc::clone(patch_heads));
+                let drawer_heads = drawer_heads.clone();
 
-                if new_entry.previous_version_heads.is_none() {
-                    let entry = self
-                        .current_doc_branches(&doc_id)
-                        .await?
-                        .ok_or_eyre("drawer doc added but branch state missing")?;
-                    out.push(DrawerEvent::DocAdded {
-                        id: doc_id,
-                        entry,
-                        drawer_heads: drawer_heads.clone(),
-                        origin: event_origin.clone(),
-                    });
-                } else {
-                    let previous_heads = new_entry
-                        .previous_version_heads
-                        .as_ref()
-                        .ok_or_eyre("doc update missing previous_version_heads")?;
-                    let old_entry = self
-                        .get_entry_at_heads(&doc_id, previous_heads)
-                        .await?
-                        .ok_or_eyre(
-                            "doc update previous entry not found at previous_version_heads",
-                        )?;
+                let old_entry = match &new_entry.previous_version_heads {
+                    Some(previous_heads) => {
+                        // The previous version can live in a shard that
+                        // hasn't synced yet, treat that as an add.
+                        self.get_entry_at_heads(&doc_id, previous_heads).await?
+                    }
+                    None => None,
+                };
+                if let Some(old_entry) = old_entry {
                     if old_entry.branches != new_entry.branches {
                         let entry = self
                             .current_doc_branches(&doc_id)
@@ -295,16 +313,31 @@ impl DrawerRepo {
                             origin: event_origin,
                         });
                     }
+                } else {
+                    let entry = self
+                        .current_doc_branches(&doc_id)
+                        .await?
+                        .ok_or_eyre("drawer doc added but branch state missing")?;
+                    out.push(DrawerEvent::DocAdded {
+                        id: doc_id,
+                        entry,
+                        drawer_heads: drawer_heads.clone(),
+                        origin: event_origin.clone(),
+                    });
                 }
             }
             automerge::PatchAction::DeleteMap { key, .. } if patch.path.len() == 2 => {
                 // docs.map.<doc_id> deleted
                 let doc_id = DocId::from(key.clone());
-                let drawer_heads = ChangeHashSet(Arc::clone(patch_heads));
+                let drawer_heads = drawer_heads.clone();
                 // Delete patches have no vtag; use docs.map_deleted actor evidence when replaying.
                 let tombstone = self
-                    .latest_doc_delete_tombstone(&doc_id, patch_heads)
+                    .latest_doc_delete_tombstone(shard, &doc_id, patch_heads)
                     .await?;
+                // Entries moved out of a deprecated shard leave no tombstone.
+                if tombstone.is_none() && self.entry_shard(&doc_id).await?.is_some() {
+                    return Ok(());
+                }
                 let event_origin = crate::repos::resolve_origin_for_delete(
                     &self.local_actor_id,
                     live_origin,
@@ -1,11 +1,11 @@
 use crate::interlude::*;
 
+use super::shards::known_heads;
 #[cfg(test)]
 use super::BranchStateRow;
 use super::{BranchKind, BranchRefRow, DrawerRepo};
 use crate::drawer::types::{DocEntry, DocNBranches, StoredBranchRef};
 use crate::stores::VersionTag;
-use automerge::ReadDoc;
 use daybook_types::doc::{ChangeHashSet, DocId};
 
 impl DrawerRepo {
@@ -123,6 +123,36 @@ impl DrawerRepo {
         .collect())
     }
 
+    /// Branch docs of the local branches of every doc.
+    pub(super) async fn list_all_local_branch_doc_ids(&self) -> Res<Vec<DocumentId>> {
+        Ok(
+            sqlx::query_scalar::<_, Vec<u8>>(
+                r#"SELECT branch_doc_id FROM "drawer_local_branches""#,
+            )
+            .fetch_all(&self.meta_store_sql.read_pool)
+            .await?
+            .into_iter()
+            .map(|id| DocumentId::new(id.try_into().expect(ERROR_IMPOSSIBLE)))
+            .collect(),
+        )
+    }
+
+    pub(super) async fn list_local_branch_tombstones(&self) -> Res<Vec<(DocId, DocumentId)>> {
+        Ok(sqlx::query_as::<_, (String, Vec<u8>)>(
+            r#"SELECT doc_id, branch_doc_id FROM "drawer_local_branches_deleted""#,
+        )
+        .fetch_all(&self.meta_store_sql.read_pool)
+        .await?
+        .into_iter()
+        .map(|(doc_id, id)| {
+            (
+                DocId::from(doc_id),
+                DocumentId::new(id.try_into().expect(ERROR_IMPOSSIBLE)),
+            )
+        })
+        .collect())
+    }
+
     pub(super) async fn delete_local_branch_ref_with_tombstone(
         &self,
         doc_id: &DocId,
@@ -288,32 +318,22 @@ impl DrawerRepo {
     pub(super) async fn current_drawer_entries(
         &self,
     ) -> Res<(ChangeHashSet, Vec<(DocId, DocEntry)>)> {
-        self.drawer_doc_handle
-            .with_document_read(|doc| {
-                let drawer_heads = ChangeHashSet(doc.get_heads().into());
-                let map_id = match doc.get(automerge::ROOT, "docs")? {
-                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => {
-                        match doc.get(&id, "map")? {
-                            Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
-                            _ => eyre::bail!("invalid drawer shape"),
-                        }
-                    }
-                    None => return eyre::Ok((drawer_heads, Vec::new())),
-                    _ => eyre::bail!("invalid drawer shape"),
-                };
-
-                let mut entries = Vec::new();
-                for item in doc.map_range(&map_id, ..) {
-                    let doc_id = DocId::from(item.key.clone());
-                    let entry: Option<DocEntry> =
-                        autosurgeon::hydrate_prop(doc, &map_id, item.key)?;
-                    if let Some(entry) = entry {
-                        entries.push((doc_id, entry));
-                    }
+        let mut drawer_heads = Vec::new();
+        let mut seen = HashSet::new();
+        let mut entries = Vec::new();
+        // Mid-move entries can be in two shards, the live one comes first.
+        for (_shard_id, shard) in self.loaded_shards() {
+            let (shard_heads, shard_entries) = self.shard_entries(&shard).await?;
+            drawer_heads.extend(shard_heads.iter().copied());
+            for (doc_id, entry) in shard_entries {
+                if seen.insert(doc_id.clone()) {
+                    entries.push((doc_id, entry));
                 }
-                eyre::Ok((drawer_heads, entries))
-            })
-            .await
+            }
+        }
+        drawer_heads.sort();
+        drawer_heads.dedup();
+        Ok((ChangeHashSet(drawer_heads.into()), entries))
     }
 
     #[tracing::instrument(skip_all)]
@@ -327,10 +347,20 @@ impl DrawerRepo {
             "map".into(),
             autosurgeon::Prop::Key(doc_id.to_string().into()),
         ];
-        let entry = self
-            .drawer_doc_handle
-            .hydrate_path_at_heads::<DocEntry>(heads, automerge::ROOT, path)
-            .await?;
-        Ok(entry)
+        for (_shard_id, shard) in self.loaded_shards() {
+            let shard_heads = shard
+                .with_document_read(|doc| known_heads(doc, heads))
+                .await;
+            if shard_heads.is_empty() {
+                continue;
+            }
+            if let Some(entry) = shard
+                .hydrate_path_at_heads::<DocEntry>(&shard_heads, automerge::ROOT, path.clone())
+                .await?
+            {
+                return Ok(Some(entry));
+            }
+        }
+        Ok(None)
     }
 }
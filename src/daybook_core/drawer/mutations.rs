@@ -1,5 +1,6 @@
 use crate::interlude::*;
 
+use super::shards::ensure_docs_map;
 use super::{BranchKind, DrawerRepo};
 
 use crate::drawer::{
@@ -117,19 +118,12 @@ impl DrawerRepo {
             prepared_docs.push(self.prepare_add_doc(args).await?);
         }
 
-        let drawer_heads = self
-            .drawer_doc_handle
+        let (shard_id, shard) = self.active_shard().await?;
+        let shard_heads = shard
             .with_document(|doc| {
                 doc.set_actor(self.local_actor_id.clone());
                 let mut tx = doc.transaction();
-                let docs_obj = match tx.get(automerge::ROOT, "docs")? {
-                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
-                    _ => tx.put_object(automerge::ROOT, "docs", automerge::ObjType::Map)?,
-                };
-                let map_id = match tx.get(&docs_obj, "map")? {
-                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
-                    _ => tx.put_object(&docs_obj, "map", automerge::ObjType::Map)?,
-                };
+                let map_id = ensure_docs_map(&mut tx)?;
                 for prepared in &prepared_docs {
                     autosurgeon::reconcile_prop(
                         &mut tx,
@@ -143,6 +137,8 @@ impl DrawerRepo {
                 eyre::Ok(ChangeHashSet(Arc::from([heads])))
             })
             .await??;
+        self.set_shard_heads(&shard_id, shard_heads);
+        let drawer_heads = self.get_drawer_heads();
 
         let mut doc_ids = Vec::with_capacity(prepared_docs.len());
         let mut events = Vec::with_capacity(prepared_docs.len());
@@ -186,11 +182,8 @@ impl DrawerRepo {
                 origin: self.local_origin(),
             });
         }
-        surelock::key::lock_scope(|key| {
-            let (mut heads, _key) = key.lock(&self.current_heads);
-            *heads = drawer_heads.clone();
-        });
         self.registry.notify(events);
+        self.check_shard_capacity(shard_id, &shard).await?;
 
         Ok(doc_ids)
     }
@@ -276,7 +269,7 @@ impl DrawerRepo {
             .await?;
 
         // 1. Update content doc
-        let (_new_heads, invalidated_uuids) = handle
+        let (_new_heads, invalidated_uuids, forked) = handle
             .with_document(|am_doc| {
                 am_doc.set_actor(mutation_actor_id.clone());
                 let mut tx = am_doc
@@ -314,7 +307,9 @@ impl DrawerRepo {
 
                 let (heads, _) = tx.commit();
                 let heads = heads.expect("commit failed");
-                eyre::Ok((ChangeHashSet(Arc::from([heads])), invalidated_uuids))
+                // Writing at stale heads leaves the doc with concurrent tips
+                let forked = am_doc.get_heads().len() > 1;
+                eyre::Ok((ChangeHashSet(Arc::from([heads])), invalidated_uuids, forked))
             })
             .await??;
 
@@ -327,9 +322,14 @@ impl DrawerRepo {
 
         surelock::key::lock_scope(|key| {
             let (mut handles, _key) = key.lock(&self.branch_handles);
-            handles.insert(handle.document_id(), handle);
+            handles.insert(handle.document_id(), handle.clone());
         });
 
+        if forked {
+            self.track_facet_conflicts(&patch.id, branch_path, &handle)
+                .await?;
+        }
+
         Ok(())
     }
 
@@ -466,10 +466,7 @@ impl DrawerRepo {
             self.invalidate_entry_cache(id);
             self.get_drawer_heads()
         } else {
-            let latest_drawer_heads = surelock::key::lock_scope(|key| {
-                let (heads, _key) = key.lock(&self.current_heads);
-                heads.clone()
-            });
+            let latest_drawer_heads = self.get_drawer_heads();
             let entry = self
                 .get_entry_at_heads(id, &latest_drawer_heads)
                 .await?
@@ -480,8 +477,11 @@ impl DrawerRepo {
                 .insert(to_branch.to_string(), StoredBranchRef { branch_doc_id });
             new_entry.vtag = VersionTag::update(self.local_actor_id.clone());
 
-            let drawer_heads = self
-                .drawer_doc_handle
+            let (shard_id, shard) = self
+                .entry_shard(id)
+                .await?
+                .ok_or_else(|| DrawerError::DocNotFound { id: id.clone() })?;
+            let shard_heads = shard
                 .with_document(|doc| {
                     let current_drawer_heads = ChangeHashSet(doc.get_heads().into());
                     new_entry.previous_version_heads = Some(current_drawer_heads);
@@ -508,11 +508,8 @@ impl DrawerRepo {
                 .await??;
 
             self.invalidate_entry_cache(id);
-            surelock::key::lock_scope(|key| {
-                let (mut heads, _key) = key.lock(&self.current_heads);
-                *heads = drawer_heads.clone();
-            });
-            drawer_heads
+            self.set_shard_heads(&shard_id, shard_heads);
+            self.get_drawer_heads()
         };
         let updated_entry = self
             .current_doc_branches(id)
@@ -746,11 +743,6 @@ impl DrawerRepo {
             self.invalidate_facet_cache_entry(id, &uuid);
         }
 
-        surelock::key::lock_scope(|key| {
-            let (mut heads, _key) = key.lock(&self.current_heads);
-            *heads = drawer_heads.clone();
-        });
-
         let updated_entry = self
             .current_doc_branches(id)
             .await?
@@ -767,6 +759,7 @@ impl DrawerRepo {
             drawer_heads,
             origin: self.local_origin(),
         }]);
+        self.track_facet_conflicts(id, to_branch, &handle).await?;
 
         Ok(())
     }
@@ -792,8 +785,10 @@ impl DrawerRepo {
         let mut deleted_facet_keys: Vec<FacetKey> = deleted_facet_keys_set.into_iter().collect();
         deleted_facet_keys.sort();
 
-        let res = self
-            .drawer_doc_handle
+        let Some((shard_id, shard)) = self.entry_shard(id).await? else {
+            return Ok(false);
+        };
+        let res = shard
             .with_document(|doc| {
                 let docs_id = match doc.get(automerge::ROOT, "docs")? {
                     Some((automerge::Value::Object(automerge::ObjType::Map), docs_id)) => docs_id,
@@ -839,7 +834,7 @@ impl DrawerRepo {
             })
             .await?;
 
-        let (existed, drawer_heads, entry) = res?;
+        let (existed, shard_heads, entry) = res?;
 
         if existed {
             let Some(entry) = &entry else {
@@ -888,10 +883,8 @@ impl DrawerRepo {
                 });
             }
             self.invalidate_facet_cache_doc(id);
-            surelock::key::lock_scope(|key| {
-                let (mut heads, _key) = key.lock(&self.current_heads);
-                *heads = drawer_heads.clone();
-            });
+            self.set_shard_heads(&shard_id, shard_heads);
+            let drawer_heads = self.get_drawer_heads();
             self.registry.notify([DrawerEvent::DocDeleted {
                 id: id.clone(),
                 entry: Some(entry.clone()),
@@ -1017,10 +1010,7 @@ impl DrawerRepo {
             return Ok(true);
         }
 
-        let latest_drawer_heads = surelock::key::lock_scope(|key| {
-            let (heads, _key) = key.lock(&self.current_heads);
-            heads.clone()
-        });
+        let latest_drawer_heads = self.get_drawer_heads();
         let entry = self
             .get_entry_at_heads(id, &latest_drawer_heads)
             .await?
@@ -1041,8 +1031,11 @@ impl DrawerRepo {
             });
         new_entry.vtag = VersionTag::update(self.local_actor_id.clone());
 
-        let drawer_heads = self
-            .drawer_doc_handle
+        let (shard_id, shard) = self
+            .entry_shard(id)
+            .await?
+            .ok_or_else(|| DrawerError::DocNotFound { id: id.clone() })?;
+        let shard_heads = shard
             .with_document(|doc| {
                 let current_drawer_heads = ChangeHashSet(doc.get_heads().into());
                 new_entry.previous_version_heads = Some(current_drawer_heads);
@@ -1071,10 +1064,8 @@ impl DrawerRepo {
         // Update caches and notify
         self.invalidate_entry_cache(id);
 
-        surelock::key::lock_scope(|key| {
-            let (mut heads, _key) = key.lock(&self.current_heads);
-            *heads = drawer_heads.clone();
-        });
+        self.set_shard_heads(&shard_id, shard_heads);
+        let drawer_heads = self.get_drawer_heads();
         let updated_entry = self
             .current_doc_branches(id)
             .await?
@@ -4,7 +4,7 @@ use super::DrawerRepo;
 
 use crate::drawer::{
     dmeta, facet_recovery,
-    types::{DocBundle, DocEntry, DocNBranches},
+    types::{DocBundle, DocDeleteTombstone, DocEntry, DocNBranches, TombstonedBranchDoc},
 };
 
 use automerge::ReadDoc;
@@ -12,11 +12,18 @@ use daybook_types::doc::{ChangeHashSet, Doc, DocId, FacetKey, FacetRaw, WellKnow
 
 // queries
 impl DrawerRepo {
+    /// Heads across every loaded shard.
     pub fn get_drawer_heads(&self) -> ChangeHashSet {
-        surelock::key::lock_scope(|key| {
-            let (heads, _key) = key.lock(&self.current_heads);
-            heads.clone()
-        })
+        let mut heads = surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            shards
+                .values()
+                .flat_map(|shard| shard.heads.iter().copied())
+                .collect::<Vec<_>>()
+        });
+        heads.sort();
+        heads.dedup();
+        ChangeHashSet(heads.into())
     }
 
     #[tracing::instrument(level = "trace", skip_all)]
@@ -49,6 +56,87 @@ impl DrawerRepo {
         Ok((drawer_heads, results))
     }
 
+    /// Branch docs backing the live branches, local ones included, of
+    /// every doc.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn live_branch_docs(&self) -> Res<Vec<DocumentId>> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        let (_drawer_heads, entries) = self.current_drawer_entries().await?;
+        let mut out = entries
+            .into_iter()
+            .flat_map(|(_doc_id, entry)| {
+                entry
+                    .branches
+                    .into_values()
+                    .map(|branch| branch.branch_doc_id)
+            })
+            .collect::<Vec<_>>();
+        out.extend(self.list_all_local_branch_doc_ids().await?);
+        Ok(out)
+    }
+
+    /// Branch docs only referenced from tombstones, those of deleted docs
+    /// and of deleted branches. Branch docs still backing a live branch are
+    /// left out.
+    #[tracing::instrument(level = "trace", skip_all)]
+    pub async fn tombstoned_branch_docs(&self) -> Res<Vec<TombstonedBranchDoc>> {
+        if self.cancel_token.is_cancelled() {
+            eyre::bail!("repo is stopped");
+        }
+        let live = self
+            .live_branch_docs()
+            .await?
+            .into_iter()
+            .collect::<HashSet<_>>();
+        let mut tombstoned = Vec::new();
+        for (_shard_id, shard) in self.loaded_shards() {
+            let (_shard_heads, entries) = self.shard_entries(&shard).await?;
+            for (doc_id, entry) in entries {
+                for tombstones in entry.branches_deleted.values() {
+                    tombstoned.extend(tombstones.iter().map(|tombstone| TombstonedBranchDoc {
+                        doc_id: doc_id.clone(),
+                        branch_doc_id: tombstone.branch_doc_id,
+                        local: false,
+                    }));
+                }
+            }
+            let Some((deleted, _heads)) = shard
+                .hydrate_path::<HashMap<String, Vec<DocDeleteTombstone>>>(
+                    automerge::ROOT,
+                    vec!["docs".into(), "map_deleted".into()],
+                )
+                .await?
+            else {
+                continue;
+            };
+            for (doc_id, tombstones) in deleted {
+                for tombstone in tombstones {
+                    tombstoned.extend(tombstone.branches.values().map(|snapshot| {
+                        TombstonedBranchDoc {
+                            doc_id: DocId::from(doc_id.clone()),
+                            branch_doc_id: snapshot.branch_doc_id,
+                            local: false,
+                        }
+                    }));
+                }
+            }
+        }
+        tombstoned.extend(self.list_local_branch_tombstones().await?.into_iter().map(
+            |(doc_id, branch_doc_id)| TombstonedBranchDoc {
+                doc_id,
+                branch_doc_id,
+                local: true,
+            },
+        ));
+        let mut seen = HashSet::new();
+        tombstoned.retain(|tombstoned| {
+            !live.contains(&tombstoned.branch_doc_id) && seen.insert(tombstoned.branch_doc_id)
+        });
+        Ok(tombstoned)
+    }
+
     #[tracing::instrument(level = "trace", skip_all)]
     pub async fn list(&self) -> Res<Vec<DocNBranches>> {
         if self.cancel_token.is_cancelled() {
@@ -74,11 +162,7 @@ impl DrawerRepo {
         if self.cancel_token.is_cancelled() {
             eyre::bail!("repo is stopped");
         }
-        let current_heads = surelock::key::lock_scope(|key| {
-            let (heads, _key) = key.lock(&self.current_heads);
-            heads.clone()
-        });
-        if heads == &current_heads {
+        if heads == &self.get_drawer_heads() {
             return self.get_entry(doc_id).await;
         }
         self.hydrate_entry_at_heads(doc_id, heads).await
@@ -104,10 +188,7 @@ impl DrawerRepo {
             return Ok(Some(cached));
         }
 
-        let heads = surelock::key::lock_scope(|key| {
-            let (heads, _key) = key.lock(&self.current_heads);
-            heads.clone()
-        });
+        let heads = self.get_drawer_heads();
         let entry = self.hydrate_entry_at_heads(doc_id, &heads).await?;
 
         if let Some(entry) = entry {
@@ -145,6 +226,13 @@ impl DrawerRepo {
             eyre::bail!("repo is stopped");
         }
 
+        if let Some(facets) = self
+            .get_at_branch_heads_from_snapshot(doc_id, branch_path, heads, facet_keys.as_deref())
+            .await?
+        {
+            return Ok(Some((facets, default())));
+        }
+
         let Some(handle) = self
             .resolve_handle_for_branch_heads(doc_id, branch_path, heads)
             .await?
@@ -152,7 +240,7 @@ impl DrawerRepo {
             return Ok(None);
         };
 
-        let (facets, facet_heads_by_key, to_cache) = handle
+        let (mut facets, facet_heads_by_key, to_cache) = handle
             .with_document_read(|am_doc| {
                 let mut facets = HashMap::new();
                 let mut facet_heads_by_key = HashMap::new();
@@ -212,10 +300,64 @@ impl DrawerRepo {
         for (uuid, heads, value) in to_cache {
             self.facet_cache_put(doc_id, uuid, heads, value);
         }
+        self.flag_revoked_actors(&mut facets);
 
         Ok(Some((facets, facet_heads_by_key)))
     }
 
+    /// Reads the facets off the snapshot of a branch doc that isn't local
+    /// when the snapshot is at `heads`. Facet heads need the history so
+    /// none are reported, and nothing goes into the facet cache. `None`
+    /// leaves it to the full doc.
+    async fn get_at_branch_heads_from_snapshot(
+        &self,
+        doc_id: &DocId,
+        branch_path: &daybook_types::doc::BranchPath,
+        heads: &ChangeHashSet,
+        facet_keys: Option<&[FacetKey]>,
+    ) -> Res<Option<HashMap<FacetKey, daybook_types::doc::ArcFacetRaw>>> {
+        let Some(branch_ref) = self.get_branch_ref(doc_id, branch_path).await? else {
+            return Ok(None);
+        };
+        if self
+            .local_branch_handle(branch_ref.branch_doc_id)
+            .await?
+            .is_some()
+        {
+            return Ok(None);
+        }
+        let Some(snapshot) = self.branch_doc_snapshot(branch_ref.branch_doc_id).await? else {
+            return Ok(None);
+        };
+        if snapshot.heads[..] != heads.0[..] {
+            return Ok(None);
+        }
+        let am_doc = &*snapshot.doc;
+        let facets_obj = match am_doc.get(automerge::ROOT, "facets")? {
+            Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+            _ if facet_keys.is_none() => return Ok(Some(default())),
+            _ => eyre::bail!("facets object not found in content doc"),
+        };
+        let selected_keys: Vec<FacetKey> = match facet_keys {
+            Some(keys) => keys.to_vec(),
+            None => am_doc
+                .map_range(&facets_obj, ..)
+                .map(|item| FacetKey::from(item.key.to_string().as_str()))
+                .collect(),
+        };
+        let mut facets = HashMap::new();
+        for key in selected_keys {
+            let key_str = key.to_string();
+            let value: Option<ThroughJson<FacetRaw>> =
+                autosurgeon::hydrate_prop(am_doc, &facets_obj, &*key_str)?;
+            if let Some(facet_value) = value {
+                facets.insert(key, Arc::new(facet_value.0));
+            }
+        }
+        self.flag_revoked_actors(&mut facets);
+        Ok(Some(facets))
+    }
+
     /// Get a doc at specific branch.
     #[tracing::instrument(level = "trace", skip_all, fields(%doc_id, %branch_path))]
     pub async fn get_doc_with_facets_at_branch(
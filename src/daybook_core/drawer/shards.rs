@@ -0,0 +1,672 @@
+//! Drawer sharding.
+//!
+//! Keeping `docs.map` for every entry in one automerge doc gets slow to
+//! load and sync once a drawer grows into the tens of thousands of docs.
+//! The drawer index is instead spread over shard docs that all share the
+//! `docs.{map, map_deleted}` shape. The original drawer doc is always the
+//! root shard, so existing repos keep working without a migration.
+//!
+//! The shard list lives in the app doc under `drawer_shards`. New entries
+//! land in the active shard: the root while it has room, otherwise the
+//! lowest-id shard that is neither full nor deprecated. A shard is marked
+//! full once it holds `max_shard_entries` entries.
+//!
+//! Two devices that fill the active shard at the same time will each
+//! create a fresh one. Once both shards have synced every device agrees on
+//! the same winner and marks the others deprecated. The shard task then
+//! moves entries out of deprecated shards into the active one. Moves don't
+//! write a delete tombstone, which is how listeners tell them apart from
+//! real deletes.
+
+use crate::interlude::*;
+
+use super::DrawerRepo;
+
+use crate::drawer::types::{DocEntry, DrawerEvent};
+
+use automerge::transaction::Transactable;
+use automerge::ReadDoc;
+use big_repo::BigRepoChangeNotification;
+use daybook_types::doc::{ChangeHashSet, DocId};
+use std::str::FromStr;
+use tokio::sync::mpsc::UnboundedReceiver;
+use tokio_util::sync::CancellationToken;
+
+pub(crate) const DEFAULT_MAX_SHARD_ENTRIES: usize = 4096;
+
+// Shard docs made on other devices can land after their entry in the
+// app doc, so the list also gets re-checked on a timer.
+const SHARDS_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Reconcile, Hydrate)]
+pub struct DrawerShardMeta {
+    pub full: bool,
+    pub deprecated: bool,
+}
+
+#[derive(Debug, Default, Reconcile, Hydrate)]
+pub struct DrawerShardsStore {
+    /// Keyed by shard doc id. The root drawer doc only shows up here once
+    /// it's been marked full.
+    pub shards: HashMap<String, DrawerShardMeta>,
+}
+
+#[async_trait]
+impl crate::stores::AmStore for DrawerShardsStore {
+    fn prop() -> Cow<'static, str> {
+        "drawer_shards".into()
+    }
+}
+
+pub(super) struct DrawerShard {
+    /// `None` until the shard doc has synced to this device.
+    pub handle: Option<big_repo::BigDocHandle>,
+    pub heads: ChangeHashSet,
+    pub meta: DrawerShardMeta,
+}
+
+impl DrawerShard {
+    pub fn loaded(handle: big_repo::BigDocHandle, heads: ChangeHashSet) -> Self {
+        Self {
+            handle: Some(handle),
+            heads,
+            meta: default(),
+        }
+    }
+
+    fn is_candidate(&self) -> bool {
+        !self.meta.full && !self.meta.deprecated
+    }
+}
+
+/// Reads the shard list out of the app doc.
+///
+/// Devices that create the list concurrently each put their own map so all
+/// the conflicting copies get merged here.
+fn read_shard_list(doc: &automerge::Automerge) -> Res<HashMap<DocumentId, DrawerShardMeta>> {
+    let mut out: HashMap<DocumentId, DrawerShardMeta> = HashMap::new();
+    for (store_val, store_id) in doc.get_all(automerge::ROOT, DrawerShardsStore::prop().as_ref())? {
+        if !matches!(store_val, automerge::Value::Object(automerge::ObjType::Map)) {
+            continue;
+        }
+        for (shards_val, shards_id) in doc.get_all(&store_id, "shards")? {
+            if !matches!(
+                shards_val,
+                automerge::Value::Object(automerge::ObjType::Map)
+            ) {
+                continue;
+            }
+            for item in doc.map_range(&shards_id, ..) {
+                let key = item.key.to_string();
+                let shard_id = DocumentId::from_str(&key)
+                    .wrap_err_with(|| format!("invalid drawer shard id '{key}'"))?;
+                let meta: Option<DrawerShardMeta> =
+                    autosurgeon::hydrate_prop(doc, &shards_id, &*key)?;
+                let Some(meta) = meta else {
+                    continue;
+                };
+                let merged = out.entry(shard_id).or_default();
+                merged.full |= meta.full;
+                merged.deprecated |= meta.deprecated;
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// `docs.map` of a drawer shard, if it's been created yet.
+pub(super) fn docs_map_id<D: ReadDoc>(doc: &D) -> Res<Option<automerge::ObjId>> {
+    let docs_id = match doc.get(automerge::ROOT, "docs")? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+        None => return Ok(None),
+        _ => eyre::bail!("invalid drawer shape"),
+    };
+    match doc.get(&docs_id, "map")? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), id)) => Ok(Some(id)),
+        None => Ok(None),
+        _ => eyre::bail!("invalid drawer shape"),
+    }
+}
+
+/// Like [`docs_map_id`] but creates `docs.map` if missing.
+pub(super) fn ensure_docs_map<T: Transactable + ReadDoc>(tx: &mut T) -> Res<automerge::ObjId> {
+    let docs_id = match tx.get(automerge::ROOT, "docs")? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+        _ => tx.put_object(automerge::ROOT, "docs", automerge::ObjType::Map)?,
+    };
+    let map_id = match tx.get(&docs_id, "map")? {
+        Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+        _ => tx.put_object(&docs_id, "map", automerge::ObjType::Map)?,
+    };
+    Ok(map_id)
+}
+
+/// The subset of `heads` that belong to the given shard doc.
+pub(super) fn known_heads(doc: &automerge::Automerge, heads: &ChangeHashSet) -> ChangeHashSet {
+    ChangeHashSet(
+        heads
+            .iter()
+            .filter(|head| doc.get_change_by_hash(head).is_some())
+            .copied()
+            .collect::<Vec<_>>()
+            .into(),
+    )
+}
+
+impl DrawerRepo {
+    #[cfg(test)]
+    pub(super) fn set_max_shard_entries(&self, max: usize) {
+        self.max_shard_entries
+            .store(max, std::sync::atomic::Ordering::Relaxed);
+    }
+
+    pub fn shard_ids(&self) -> Vec<DocumentId> {
+        self.loaded_shards()
+            .into_iter()
+            .map(|(shard_id, _)| shard_id)
+            .collect()
+    }
+
+    /// Loaded shards in lookup order: live shards before deprecated ones,
+    /// the root first, then by id.
+    pub(super) fn loaded_shards(&self) -> Vec<(DocumentId, big_repo::BigDocHandle)> {
+        let mut out = surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            shards
+                .iter()
+                .filter_map(|(shard_id, shard)| {
+                    shard
+                        .handle
+                        .clone()
+                        .map(|handle| (*shard_id, shard.meta.deprecated, handle))
+                })
+                .collect::<Vec<_>>()
+        });
+        out.sort_by_key(|(shard_id, deprecated, _)| {
+            (
+                *deprecated,
+                *shard_id != self.drawer_doc_id,
+                shard_id.to_string(),
+            )
+        });
+        out.into_iter()
+            .map(|(shard_id, _, handle)| (shard_id, handle))
+            .collect()
+    }
+
+    pub(super) fn loaded_shard(&self, shard_id: &DocumentId) -> Option<big_repo::BigDocHandle> {
+        surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            shards.get(shard_id).and_then(|shard| shard.handle.clone())
+        })
+    }
+
+    pub(super) fn set_shard_heads(&self, shard_id: &DocumentId, heads: ChangeHashSet) {
+        surelock::key::lock_scope(|key| {
+            let (mut shards, _key) = key.lock(&self.shards);
+            if let Some(shard) = shards.get_mut(shard_id) {
+                shard.heads = heads;
+            }
+        });
+    }
+
+    fn shard_winner<'a>(
+        &self,
+        candidates: impl Iterator<Item = &'a DocumentId>,
+    ) -> Option<DocumentId> {
+        candidates
+            .min_by_key(|shard_id| (**shard_id != self.drawer_doc_id, shard_id.to_string()))
+            .copied()
+    }
+
+    fn pick_active_shard(&self) -> Option<(DocumentId, big_repo::BigDocHandle)> {
+        surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            let winner = self.shard_winner(
+                shards
+                    .iter()
+                    .filter(|(_, shard)| shard.handle.is_some() && shard.is_candidate())
+                    .map(|(shard_id, _)| shard_id),
+            )?;
+            let handle = shards.get(&winner)?.handle.clone()?;
+            Some((winner, handle))
+        })
+    }
+
+    /// Shard that new entries go to, creating a fresh one if every known
+    /// shard is full.
+    pub(super) async fn active_shard(&self) -> Res<(DocumentId, big_repo::BigDocHandle)> {
+        if let Some(active) = self.pick_active_shard() {
+            return Ok(active);
+        }
+        let _guard = self.shards_write_lock.lock().await;
+        if let Some(active) = self.pick_active_shard() {
+            return Ok(active);
+        }
+        self.create_shard(DocumentId::random()).await
+    }
+
+    /// Shard currently holding the entry for `doc_id`.
+    pub(super) async fn entry_shard(
+        &self,
+        doc_id: &DocId,
+    ) -> Res<Option<(DocumentId, big_repo::BigDocHandle)>> {
+        for (shard_id, handle) in self.loaded_shards() {
+            let found = handle
+                .with_document_read(|doc| {
+                    let Some(map_id) = docs_map_id(doc)? else {
+                        return eyre::Ok(false);
+                    };
+                    Ok(doc.get(&map_id, &**doc_id)?.is_some())
+                })
+                .await?;
+            if found {
+                return Ok(Some((shard_id, handle)));
+            }
+        }
+        Ok(None)
+    }
+
+    pub(super) async fn create_shard(
+        &self,
+        shard_id: DocumentId,
+    ) -> Res<(DocumentId, big_repo::BigDocHandle)> {
+        if self.app_doc_handle.is_none() {
+            eyre::bail!("drawer was loaded without an app doc to record shards in");
+        }
+        let mut doc = automerge::Automerge::new();
+        doc.set_actor(self.local_actor_id.clone());
+        let mut tx = doc.transaction();
+        tx.put(automerge::ROOT, "version", "0")?;
+        let docs_id = tx.put_object(automerge::ROOT, "docs", automerge::ObjType::Map)?;
+        tx.put_object(&docs_id, "map", automerge::ObjType::Map)?;
+        tx.put_object(&docs_id, "map_deleted", automerge::ObjType::Map)?;
+        tx.commit();
+        let handle = match self.big_repo.put_doc(shard_id, doc).await {
+            Ok(val) => val,
+            Err(big_repo::PutDocError::IdOccpuied { .. }) => panic!("uuid conflict lol"),
+            Err(big_repo::PutDocError::Other(err)) => {
+                return Err(err).wrap_err("error putting drawer shard in big repo");
+            }
+        };
+        let heads = handle
+            .with_document_read(|doc| ChangeHashSet(doc.get_heads().into()))
+            .await;
+
+        let core_docs_part = crate::part_id_from_label(crate::sync::CORE_DOCS_PARTITION_ID);
+        self.partition_store.ensure_part(core_docs_part).await?;
+        self.partition_store
+            .add_obj_to_parts(shard_id, vec![core_docs_part])
+            .await?;
+
+        surelock::key::lock_scope(|key| {
+            let (mut shards, _key) = key.lock(&self.shards);
+            shards.insert(shard_id, DrawerShard::loaded(handle.clone(), heads));
+        });
+        self.watch_shard(shard_id).await?;
+        self.put_shard_meta(shard_id, &default()).await?;
+        info!(%shard_id, "created drawer shard");
+        Ok((shard_id, handle))
+    }
+
+    async fn put_shard_meta(&self, shard_id: DocumentId, meta: &DrawerShardMeta) -> Res<()> {
+        let Some(app_doc) = &self.app_doc_handle else {
+            return Ok(());
+        };
+        app_doc
+            .with_document(|doc| {
+                doc.set_actor(self.local_actor_id.clone());
+                let mut tx = doc.transaction();
+                let prop = DrawerShardsStore::prop();
+                let store_id = match tx.get(automerge::ROOT, prop.as_ref())? {
+                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+                    _ => tx.put_object(automerge::ROOT, prop.as_ref(), automerge::ObjType::Map)?,
+                };
+                let shards_id = match tx.get(&store_id, "shards")? {
+                    Some((automerge::Value::Object(automerge::ObjType::Map), id)) => id,
+                    _ => tx.put_object(&store_id, "shards", automerge::ObjType::Map)?,
+                };
+                autosurgeon::reconcile_prop(&mut tx, &shards_id, &*shard_id.to_string(), meta)?;
+                tx.commit();
+                eyre::Ok(())
+            })
+            .await??;
+        Ok(())
+    }
+
+    async fn mark_shard(
+        &self,
+        shard_id: DocumentId,
+        update: impl FnOnce(&mut DrawerShardMeta),
+    ) -> Res<()> {
+        let meta = surelock::key::lock_scope(|key| {
+            let (mut shards, _key) = key.lock(&self.shards);
+            let shard = shards.entry(shard_id).or_insert_with(|| DrawerShard {
+                handle: None,
+                heads: default(),
+                meta: default(),
+            });
+            update(&mut shard.meta);
+            shard.meta.clone()
+        });
+        self.put_shard_meta(shard_id, &meta).await
+    }
+
+    /// Marks the shard full once it's reached the entry limit.
+    pub(super) async fn check_shard_capacity(
+        &self,
+        shard_id: DocumentId,
+        handle: &big_repo::BigDocHandle,
+    ) -> Res<()> {
+        // Without an app doc there's nowhere to put a second shard.
+        if self.app_doc_handle.is_none() {
+            return Ok(());
+        }
+        let count = handle
+            .with_document_read(|doc| {
+                eyre::Ok(match docs_map_id(doc)? {
+                    Some(map_id) => doc.length(&map_id),
+                    None => 0,
+                })
+            })
+            .await?;
+        let max = self
+            .max_shard_entries
+            .load(std::sync::atomic::Ordering::Relaxed);
+        if count < max {
+            return Ok(());
+        }
+        let already_full = surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            shards
+                .get(&shard_id)
+                .map(|shard| shard.meta.full)
+                .unwrap_or_default()
+        });
+        if already_full {
+            return Ok(());
+        }
+        debug!(%shard_id, count, "drawer shard is full");
+        self.mark_shard(shard_id, |meta| meta.full = true).await
+    }
+
+    /// Forwards `docs.map` changes of the shard into the drawer notifs loop.
+    pub(super) async fn watch_shard(&self, shard_id: DocumentId) -> Res<()> {
+        let (ticket, mut notif_rx) = self
+            .big_repo
+            .subscribe_change_listener(big_repo::BigRepoChangeFilter {
+                doc_id: Some(big_repo::BigRepoDocIdFilter::new(shard_id)),
+                path: vec!["docs".into(), "map".into()],
+                origin: None,
+            })
+            .await?;
+        surelock::key::lock_scope(|key| {
+            let (mut tickets, _key) = key.lock(&self.shard_listener_tickets);
+            tickets.push(ticket);
+        });
+        let notif_tx = self.shard_notif_tx.clone();
+        let cancel_token = self.cancel_token.child_token();
+        tokio::spawn(async move {
+            loop {
+                let notifs = tokio::select! {
+                    biased;
+                    _ = cancel_token.cancelled() => break,
+                    msg = notif_rx.recv() => match msg {
+                        Some(notifs) => notifs,
+                        None => break,
+                    },
+                };
+                if notif_tx.send(notifs).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(())
+    }
+
+    /// Picks up changes to the shard list and opens shards that have synced
+    /// since the last check.
+    ///
+    /// With `emit_events`, entries of newly opened shards that aren't held
+    /// by any other shard are reported as added.
+    pub(super) async fn refresh_shards(&self, emit_events: bool) -> Res<Vec<DrawerEvent>> {
+        let Some(app_doc) = &self.app_doc_handle else {
+            return Ok(vec![]);
+        };
+        let listed = app_doc
+            .with_document_read(|doc| read_shard_list(doc))
+            .await?;
+        let pending = surelock::key::lock_scope(|key| {
+            let (mut shards, _key) = key.lock(&self.shards);
+            for (shard_id, meta) in listed {
+                let shard = shards.entry(shard_id).or_insert_with(|| DrawerShard {
+                    handle: None,
+                    heads: default(),
+                    meta: default(),
+                });
+                shard.meta.full |= meta.full;
+                shard.meta.deprecated |= meta.deprecated;
+            }
+            shards
+                .iter()
+                .filter(|(_, shard)| shard.handle.is_none())
+                .map(|(shard_id, _)| *shard_id)
+                .collect::<Vec<_>>()
+        });
+
+        let mut events = vec![];
+        for shard_id in pending {
+            let Some(handle) = self.big_repo.get_doc(&shard_id).await? else {
+                continue;
+            };
+            let heads = handle
+                .with_document_read(|doc| ChangeHashSet(doc.get_heads().into()))
+                .await;
+            surelock::key::lock_scope(|key| {
+                let (mut shards, _key) = key.lock(&self.shards);
+                if let Some(shard) = shards.get_mut(&shard_id) {
+                    shard.handle = Some(handle.clone());
+                    shard.heads = heads;
+                }
+            });
+            self.watch_shard(shard_id).await?;
+            debug!(%shard_id, "opened drawer shard");
+            if !emit_events {
+                continue;
+            }
+            let (_, entries) = self.shard_entries(&handle).await?;
+            let drawer_heads = self.get_drawer_heads();
+            for (doc_id, stored) in entries {
+                self.invalidate_entry_cache(&doc_id);
+                let Some((holder_id, _)) = self.entry_shard(&doc_id).await? else {
+                    continue;
+                };
+                if holder_id != shard_id {
+                    continue;
+                }
+                let Some(entry) = self.current_doc_branches(&doc_id).await? else {
+                    continue;
+                };
+                events.push(DrawerEvent::DocAdded {
+                    id: doc_id,
+                    entry,
+                    drawer_heads: drawer_heads.clone(),
+                    origin: crate::repos::resolve_origin_from_vtag_actor(
+                        &self.local_actor_id,
+                        &stored.vtag.actor_id,
+                        None,
+                    ),
+                });
+            }
+        }
+        Ok(events)
+    }
+
+    /// Deprecates all but one of the shards that are open for new entries.
+    async fn settle_active_shards(&self) -> Res<()> {
+        let losers = surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            let candidates = shards
+                .iter()
+                .filter(|(_, shard)| shard.is_candidate())
+                .map(|(shard_id, _)| *shard_id)
+                .collect::<Vec<_>>();
+            let Some(winner) = self.shard_winner(candidates.iter()) else {
+                return vec![];
+            };
+            candidates
+                .into_iter()
+                .filter(|shard_id| *shard_id != winner)
+                .collect::<Vec<_>>()
+        });
+        for shard_id in losers {
+            debug!(%shard_id, "deprecating concurrently created drawer shard");
+            self.mark_shard(shard_id, |meta| meta.deprecated = true)
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Moves entries out of deprecated shards into the active one.
+    async fn merge_deprecated_shards(&self) -> Res<usize> {
+        let deprecated = surelock::key::lock_scope(|key| {
+            let (shards, _key) = key.lock(&self.shards);
+            shards
+                .iter()
+                .filter(|(_, shard)| shard.meta.deprecated)
+                .filter_map(|(shard_id, shard)| {
+                    shard.handle.clone().map(|handle| (*shard_id, handle))
+                })
+                .collect::<Vec<_>>()
+        });
+        if deprecated.is_empty() {
+            return Ok(0);
+        }
+        // Creating shards is left to writers, try again next round.
+        let Some((target_id, target)) = self.pick_active_shard() else {
+            return Ok(0);
+        };
+
+        let mut moved = 0;
+        for (shard_id, shard) in deprecated {
+            let (shard_heads, entries) = self.shard_entries(&shard).await?;
+            if entries.is_empty() {
+                continue;
+            }
+            // The moved entry points back at the deprecated shard as its
+            // previous version so listeners see it as unchanged. Entries
+            // the target already has are left as they are.
+            let target_heads = target
+                .with_document(|doc| {
+                    doc.set_actor(self.local_actor_id.clone());
+                    let mut tx = doc.transaction();
+                    let map_id = ensure_docs_map(&mut tx)?;
+                    for (doc_id, entry) in &entries {
+                        if tx.get(&map_id, &**doc_id)?.is_some() {
+                            continue;
+                        }
+                        let mut entry = entry.clone();
+                        entry.previous_version_heads = Some(shard_heads.clone());
+                        autosurgeon::reconcile_prop(&mut tx, &map_id, &**doc_id, &entry)?;
+                    }
+                    tx.commit();
+                    eyre::Ok(ChangeHashSet(doc.get_heads().into()))
+                })
+                .await??;
+            self.set_shard_heads(&target_id, target_heads);
+
+            let shard_heads = shard
+                .with_document(|doc| {
+                    doc.set_actor(self.local_actor_id.clone());
+                    let mut tx = doc.transaction();
+                    let map_id = ensure_docs_map(&mut tx)?;
+                    for (doc_id, _) in &entries {
+                        if tx.get(&map_id, &**doc_id)?.is_some() {
+                            tx.delete(&map_id, &**doc_id)?;
+                        }
+                    }
+                    tx.commit();
+                    eyre::Ok(ChangeHashSet(doc.get_heads().into()))
+                })
+                .await??;
+            self.set_shard_heads(&shard_id, shard_heads);
+
+            for (doc_id, _) in &entries {
+                self.invalidate_entry_cache(doc_id);
+            }
+            debug!(%shard_id, %target_id, count = entries.len(), "merged deprecated drawer shard");
+            moved += entries.len();
+        }
+        if moved > 0 {
+            self.check_shard_capacity(target_id, &target).await?;
+        }
+        Ok(moved)
+    }
+
+    /// One round of shard upkeep: open newly synced shards, settle on a
+    /// single active shard and drain deprecated ones.
+    pub(super) async fn maintain_shards(&self) -> Res<()> {
+        let _guard = self.shards_write_lock.lock().await;
+        let mut events = self.refresh_shards(true).await?;
+        self.settle_active_shards().await?;
+        self.merge_deprecated_shards().await?;
+        self.notify_with_invalidation(&mut events);
+        Ok(())
+    }
+
+    #[tracing::instrument(skip(self, notif_rx, cancel_token))]
+    pub(super) async fn shards_loop(
+        &self,
+        mut notif_rx: UnboundedReceiver<Vec<BigRepoChangeNotification>>,
+        cancel_token: CancellationToken,
+    ) -> Res<()> {
+        let mut interval = tokio::time::interval(SHARDS_POLL_INTERVAL);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        loop {
+            tokio::select! {
+                biased;
+                _ = cancel_token.cancelled() => {
+                    debug!("cancel token lit");
+                    break
+                },
+                msg = notif_rx.recv() => {
+                    if msg.is_none() {
+                        break;
+                    }
+                }
+                _ = interval.tick() => {}
+            }
+            if let Err(err) = self.maintain_shards().await {
+                if cancel_token.is_cancelled() || self.cancel_token.is_cancelled() {
+                    return Ok(());
+                }
+                warn!(?err, "error maintaining drawer shards");
+            }
+        }
+        Ok(())
+    }
+
+    pub(super) async fn shard_entries(
+        &self,
+        handle: &big_repo::BigDocHandle,
+    ) -> Res<(ChangeHashSet, Vec<(DocId, DocEntry)>)> {
+        handle
+            .with_document_read(|doc| {
+                let heads = ChangeHashSet(doc.get_heads().into());
+                let Some(map_id) = docs_map_id(doc)? else {
+                    return eyre::Ok((heads, Vec::new()));
+                };
+                let mut entries = Vec::new();
+                for item in doc.map_range(&map_id, ..) {
+                    let doc_id = DocId::from(item.key.to_string());
+                    let entry: Option<DocEntry> =
+                        autosurgeon::hydrate_prop(doc, &map_id, item.key)?;
+                    if let Some(entry) = entry {
+                        entries.push((doc_id, entry));
+                    }
+                }
+                eyre::Ok((heads, entries))
+            })
+            .await
+    }
+}
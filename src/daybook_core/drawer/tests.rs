@@ -64,6 +64,7 @@ async fn test_v2_smoke() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -159,6 +160,7 @@ async fn test_partitions_track_non_tmp_branches() -> Res<()> {
         Arc::clone(&big_repo),
         Arc::clone(&big_sync_host.store),
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -278,6 +280,7 @@ async fn test_v2_batch_add_smoke() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -345,6 +348,7 @@ async fn test_v2_batch_add_emits_single_list_changed() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -446,6 +450,7 @@ async fn test_v2_merge() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -571,6 +576,176 @@ async fn test_v2_merge() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn test_concurrent_facet_edits_surface_conflicts() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let (big_repo, big_sync_host, acx_stop) = boot_repo().await?;
+
+    let drawer_doc_id = {
+        let mut doc = automerge::Automerge::new();
+        let mut tx = doc.transaction();
+        tx.put(automerge::ROOT, "version", "0")?;
+        tx.commit();
+        let handle = big_repo.put_doc(DocumentId::random(), doc).await?;
+        handle.document_id()
+    };
+
+    let entry_pool = Arc::new(surelock::mutex::Mutex::new(KeyedLruPool::new(1000)));
+    let doc_pool = Arc::new(surelock::mutex::Mutex::new(KeyedLruPool::new(1000)));
+    let (repo, stop_token) = DrawerRepo::load(
+        Arc::clone(&big_repo),
+        big_sync_host.store,
+        drawer_doc_id,
+        None,
+        daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
+        new_meta_store_sql().await?,
+        std::env::temp_dir().join(Uuid::new_v4().to_string()),
+        entry_pool,
+        doc_pool,
+        None,
+    )
+    .await?;
+
+    let facet_title = FacetKey::from(WellKnownFacetTag::TitleGeneric);
+    let doc_id = repo
+        .add(AddDocArgs {
+            branch_path: BranchPathBuf::from("main"),
+            facets: [(
+                facet_title.clone(),
+                WellKnownFacet::TitleGeneric("Base".into()).into(),
+            )]
+            .into(),
+            user_path: None,
+        })
+        .await?;
+    let entry = repo.get_doc_branches(&doc_id).await?.unwrap();
+    let main_heads = entry.branches.get("main").unwrap().clone();
+
+    // Both branches retitle the doc from the same heads
+    let user_a = UserPathBuf::from("/test-user/device-a");
+    for (branch, title, user_path) in [
+        ("branch-a", "A", Some(user_a.clone())),
+        ("branch-b", "B", None),
+    ] {
+        repo.create_branch_at_heads_from_branch(
+            &doc_id,
+            &local_branch(branch),
+            BranchPath::new("main"),
+            &main_heads,
+            None,
+        )
+        .await?;
+        repo.update_at_heads(
+            DocPatch {
+                id: doc_id.clone(),
+                facets_set: [(
+                    facet_title.clone(),
+                    WellKnownFacet::TitleGeneric(title.into()).into(),
+                )]
+                .into(),
+                facets_remove: vec![],
+                user_path,
+            },
+            &local_branch(branch),
+            Some(main_heads.clone()),
+        )
+        .await?;
+    }
+
+    let listener = repo.subscribe(crate::repos::SubscribeOpts::new(256));
+    for branch in ["branch-a", "branch-b"] {
+        repo.merge_from_branch(
+            &doc_id,
+            BranchPath::new("main"),
+            &local_branch(branch),
+            None,
+        )
+        .await?;
+    }
+
+    let conflicts = repo
+        .get_facet_conflicts(&doc_id, BranchPath::new("main"))
+        .await?;
+    assert_eq!(conflicts.len(), 1, "{conflicts:?}");
+    let conflict = &conflicts[0];
+    assert_eq!(conflict.facet_key, facet_title);
+    assert_eq!(conflict.path, "");
+    assert_eq!(conflict.losers.len(), 1);
+    let title_a = serde_json::Value::from(WellKnownFacet::TitleGeneric("A".into()));
+    let title_b = serde_json::Value::from(WellKnownFacet::TitleGeneric("B".into()));
+    let mut values = vec![
+        conflict.winner.value.clone(),
+        conflict.losers[0].value.clone(),
+    ];
+    values.sort_by_key(|value| value.to_string());
+    assert_eq!(values, vec![title_a.clone(), title_b]);
+    assert_ne!(conflict.winner.actor_id, conflict.losers[0].actor_id);
+    let value_a = [&conflict.winner, &conflict.losers[0]]
+        .into_iter()
+        .find(|value| value.value == title_a)
+        .unwrap();
+    assert_eq!(value_a.user_path.as_ref(), Some(&user_a));
+
+    // The doc reads as the winner
+    let doc = repo
+        .get_doc_with_facets_at_branch(&doc_id, BranchPath::new("main"), None)
+        .await?
+        .unwrap();
+    assert_eq!(doc.facets.get(&facet_title), Some(&conflict.winner.value));
+
+    let conflicts_event = loop {
+        let event = tokio::time::timeout(
+            std::time::Duration::from_secs(2),
+            listener.recv_lossy_async(),
+        )
+        .await
+        .wrap_err("timeout waiting for conflicts event")?
+        .map_err(|_| eyre::eyre!("listener closed"))?;
+        if let DrawerEvent::FacetConflicts {
+            id,
+            branch_path,
+            conflicts,
+            ..
+        } = &*event
+        {
+            break (id.clone(), branch_path.clone(), conflicts.clone());
+        }
+    };
+    assert_eq!(
+        conflicts_event,
+        (
+            doc_id.clone(),
+            BranchPathBuf::from("main"),
+            conflicts.clone()
+        )
+    );
+
+    // Resolving supersedes both values, including the winner
+    let title_c = serde_json::Value::from(WellKnownFacet::TitleGeneric("C".into()));
+    repo.resolve_facet_conflict(
+        &doc_id,
+        BranchPath::new("main"),
+        &facet_title,
+        "",
+        title_c.clone(),
+        None,
+    )
+    .await?;
+    assert!(repo
+        .get_facet_conflicts(&doc_id, BranchPath::new("main"))
+        .await?
+        .is_empty());
+    let doc = repo
+        .get_doc_with_facets_at_branch(&doc_id, BranchPath::new("main"), None)
+        .await?
+        .unwrap();
+    assert_eq!(doc.facets.get(&facet_title), Some(&title_c));
+
+    stop_token.stop().await?;
+    acx_stop().await?;
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn test_resolve_handle_for_heads_does_not_match_foreign_doc_heads() -> Res<()> {
     utils_rs::testing::setup_tracing_once();
@@ -591,6 +766,7 @@ async fn test_resolve_handle_for_heads_does_not_match_foreign_doc_heads() -> Res
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -665,6 +841,7 @@ async fn test_create_branch_at_stale_main_heads_after_intervening_merges() -> Re
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -997,6 +1174,7 @@ async fn test_v2_additional_apis() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1177,6 +1355,7 @@ async fn test_v2_metadata_maintenance() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1413,6 +1592,7 @@ async fn test_update_at_heads_uses_patch_user_path_actor() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1492,6 +1672,7 @@ async fn test_merge_from_heads_uses_user_path_actor() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1605,6 +1786,7 @@ async fn test_facet_keys_touched_by_local_actor_includes_user_path_scoped_actor(
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1783,6 +1965,7 @@ async fn test_v2_updated_at_merge() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -1990,6 +2173,7 @@ async fn test_v2_facet_blame_maintenance() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2148,6 +2332,7 @@ async fn test_v2_listener_is_scoped_to_drawer_doc() -> Res<()> {
         Arc::clone(&big_repo),
         Arc::clone(&big_sync_host.store),
         drawer_doc_id_a,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2160,6 +2345,7 @@ async fn test_v2_listener_is_scoped_to_drawer_doc() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id_b,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2220,6 +2406,7 @@ async fn test_v2_content_update_does_not_emit_drawer_membership_events() -> Res<
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2302,6 +2489,7 @@ async fn test_diff_events_delete_origin_uses_map_deleted_tombstone() -> Res<()>
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2376,6 +2564,7 @@ async fn test_add_rejects_unknown_facet_tag() -> Res<()> {
         big_repo,
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2422,6 +2611,7 @@ async fn test_add_rejects_self_reference_without_target_facet() -> Res<()> {
         big_repo,
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2478,6 +2668,7 @@ async fn test_add_accepts_body_self_reference_with_empty_fragment_for_present_ta
         big_repo,
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2653,6 +2844,7 @@ async fn perf_drawer_add_disk_baseline() -> Res<()> {
         Arc::clone(&big_repo),
         big_sync_host.store,
         drawer_doc_id,
+        None,
         daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
         new_meta_store_sql().await?,
         std::env::temp_dir().join(Uuid::new_v4().to_string()),
@@ -2704,3 +2896,157 @@ async fn perf_drawer_add_disk_baseline() -> Res<()> {
     let _ = std::fs::remove_dir_all(&storage_path);
     Ok(())
 }
+
+async fn load_sharded_drawer(
+    big_repo: &big_repo::SharedBigRepo,
+    part_store: big_repo::SharedPartStore,
+) -> Res<(Arc<DrawerRepo>, crate::repos::RepoStopToken)> {
+    let mut make_doc = |shape: &str| {
+        let mut doc = automerge::Automerge::new();
+        let mut tx = doc.transaction();
+        tx.put(automerge::ROOT, "version", "0")?;
+        tx.put(automerge::ROOT, "$schema", shape)?;
+        tx.commit();
+        eyre::Ok(doc)
+    };
+    let app_doc_id = big_repo
+        .put_doc(DocumentId::random(), make_doc("daybook.app")?)
+        .await?
+        .document_id();
+    let drawer_doc_id = big_repo
+        .put_doc(DocumentId::random(), make_doc("daybook.drawer")?)
+        .await?
+        .document_id();
+    DrawerRepo::load(
+        Arc::clone(big_repo),
+        part_store,
+        drawer_doc_id,
+        Some(app_doc_id),
+        daybook_types::doc::UserPathBuf::from("/duser-wip-localtest/ddev-wip-iroh-localtest"),
+        new_meta_store_sql().await?,
+        std::env::temp_dir().join(Uuid::new_v4().to_string()),
+        Arc::new(surelock::mutex::Mutex::new(KeyedLruPool::new(1000))),
+        Arc::new(surelock::mutex::Mutex::new(KeyedLruPool::new(1000))),
+        None,
+    )
+    .await
+}
+
+fn note_args(note: &str) -> AddDocArgs {
+    AddDocArgs {
+        branch_path: BranchPathBuf::from("main"),
+        facets: [(
+            FacetKey::from(WellKnownFacetTag::Note),
+            WellKnownFacet::Note(note.into()).into(),
+        )]
+        .into(),
+        user_path: None,
+    }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_drawer_spills_into_new_shards() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let (big_repo, big_sync_host, acx_stop) = boot_repo().await?;
+    let (repo, stop_token) =
+        load_sharded_drawer(&big_repo, Arc::clone(&big_sync_host.store)).await?;
+    repo.set_max_shard_entries(2);
+
+    let mut doc_ids = vec![];
+    for ii in 0..5 {
+        doc_ids.push(repo.add(note_args(&format!("note-{ii}"))).await?);
+    }
+    assert_eq!(repo.shard_ids().len(), 3);
+    assert_eq!(repo.shard_ids()[0], *repo.drawer_doc_id());
+
+    let (_, mut listed) = repo.list_just_ids().await?;
+    listed.sort();
+    let mut expected = doc_ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+    expected.sort();
+    assert_eq!(listed, expected);
+
+    // Writes land in whichever shard holds the entry.
+    let last = doc_ids.last().unwrap();
+    let main_heads = repo
+        .get_doc_branches(last)
+        .await?
+        .ok_or_eyre("missing doc branches after add")?
+        .branches
+        .get("main")
+        .ok_or_eyre("missing main branch")?
+        .clone();
+    repo.create_branch_at_heads_from_branch(
+        last,
+        &BranchPathBuf::from("/feature"),
+        BranchPath::new("main"),
+        &main_heads,
+        None,
+    )
+    .await?;
+    let branches = repo
+        .get_doc_branches(last)
+        .await?
+        .ok_or_eyre("missing doc branches after branching")?
+        .branches;
+    assert!(branches.contains_key("/feature"));
+
+    let events = repo.diff_events(ChangeHashSet::default(), None).await?;
+    let added = events
+        .iter()
+        .filter(|event| matches!(event, DrawerEvent::DocAdded { .. }))
+        .count();
+    assert_eq!(added, doc_ids.len());
+
+    // Diffing from the current heads across shards is empty.
+    let heads = repo.get_drawer_heads();
+    assert!(repo.diff_events(heads, None).await?.is_empty());
+
+    assert!(repo.del(&doc_ids[0]).await?);
+    assert!(repo.get_entry(&doc_ids[0]).await?.is_none());
+
+    stop_token.stop().await?;
+    acx_stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_drawer_merges_deprecated_shards() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let (big_repo, big_sync_host, acx_stop) = boot_repo().await?;
+    let (repo, stop_token) =
+        load_sharded_drawer(&big_repo, Arc::clone(&big_sync_host.store)).await?;
+    repo.set_max_shard_entries(2);
+
+    repo.add(note_args("root-1")).await?;
+    repo.add(note_args("root-2")).await?;
+
+    // Stand in for two devices that each created a shard once the root filled up.
+    let mut new_ids = [DocumentId::random(), DocumentId::random()];
+    new_ids.sort_by_key(|id| id.to_string());
+    let [winner_id, loser_id] = new_ids;
+    repo.create_shard(loser_id).await?;
+    let moved_doc_id = repo.add(note_args("in-loser")).await?;
+    assert_eq!(repo.entry_shard(&moved_doc_id).await?.unwrap().0, loser_id);
+
+    let listener = repo.subscribe(crate::repos::SubscribeOpts::new(128));
+    repo.create_shard(winner_id).await?;
+    repo.maintain_shards().await?;
+
+    assert_eq!(repo.entry_shard(&moved_doc_id).await?.unwrap().0, winner_id);
+    assert!(repo.get_entry(&moved_doc_id).await?.is_some());
+    assert_eq!(repo.list_just_ids().await?.1.len(), 3);
+    let maybe_event = tokio::time::timeout(
+        std::time::Duration::from_millis(300),
+        listener.recv_lossy_async(),
+    )
+    .await;
+    assert!(maybe_event.is_err(), "shard merge should not emit events");
+
+    // The winner takes new entries and fills up as usual.
+    let next_doc_id = repo.add(note_args("in-winner")).await?;
+    assert_eq!(repo.entry_shard(&next_doc_id).await?.unwrap().0, winner_id);
+
+    stop_token.stop().await?;
+    acx_stop().await?;
+    Ok(())
+}
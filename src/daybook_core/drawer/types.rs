@@ -64,6 +64,15 @@ pub struct DocDeleteTombstone {
     pub branches: HashMap<String, BranchSnapshot>,
 }
 
+/// A branch doc only referenced from tombstones.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TombstonedBranchDoc {
+    pub doc_id: DocId,
+    pub branch_doc_id: DocumentId,
+    /// The branch was local to this device, no peer ever had it.
+    pub local: bool,
+}
+
 #[derive(Debug, Clone, Reconcile, Hydrate)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
 pub struct DocEntry {
@@ -150,6 +159,32 @@ impl DocEntryDiff {
     }
 }
 
+/// One of the values written concurrently to the same place in a facet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct ConflictingValue {
+    /// Automerge op id of the write, stable across replicas.
+    pub op_id: String,
+    pub value: serde_json::Value,
+    pub actor_id: String,
+    /// From the Dmeta actors map, if the actor was recorded there.
+    pub user_path: Option<daybook_types::doc::UserPathBuf>,
+    /// From the Dmeta facet meta, if the actor's touch survived.
+    pub updated_at: Option<Timestamp>,
+}
+
+/// Concurrent writes from different actors to the same JSON path of a facet.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
+pub struct FacetConflict {
+    pub facet_key: FacetKey,
+    /// JSON pointer into the facet value, empty for the whole facet.
+    pub path: String,
+    /// The value automerge picked and the one readers currently see.
+    pub winner: ConflictingValue,
+    pub losers: Vec<ConflictingValue>,
+}
+
 #[derive(Debug, Clone)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Enum))]
 pub enum DrawerEvent {
@@ -173,4 +208,13 @@ pub enum DrawerEvent {
         entry: Option<DocEntry>,
         origin: crate::event_origin::SwitchEventOrigin,
     },
+    /// A branch picked up conflicts that weren't there before. Carries all
+    /// of the branch's current conflicts, not just the new ones.
+    FacetConflicts {
+        id: DocId,
+        branch_path: daybook_types::doc::BranchPathBuf,
+        conflicts: Vec<FacetConflict>,
+        drawer_heads: ChangeHashSet,
+        origin: crate::event_origin::SwitchEventOrigin,
+    },
 }
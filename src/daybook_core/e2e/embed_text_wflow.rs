@@ -85,7 +85,10 @@ async fn test_embed_text_workflow() -> Res<()> {
         eyre::bail!("embedding facet had unexpected type");
     };
 
-    assert_eq!(embedding.model_tag, "nomic-ai/nomic-embed-text-v1.5");
+    assert_eq!(
+        embedding.model_tag,
+        "fastembed/nomic-ai/nomic-embed-text-v1.5"
+    );
     assert_eq!(embedding.dtype, daybook_types::doc::EmbeddingDtype::F32);
     assert_eq!(embedding.compression, None);
     assert_eq!(embedding.dim, 768);
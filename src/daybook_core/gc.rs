@@ -0,0 +1,548 @@
+//! Reclaims local storage. A pass:
+//!
+//! - deletes staging branches that routines of this device are done with,
+//! - purges branch docs only referenced from tombstones, right away for
+//!   local branches and otherwise once every known device has fully synced
+//!   since the pass first saw them,
+//! - compacts the loose commits of the docs that are left into fragments.
+//!
+//! We've no record of what a peer holds, only of when we last fully synced
+//! with it (see [`crate::sync::PeerSyncStatus`]). A tombstoned branch doc is thus
+//! only purged after every known device, storage peers aside, has had a
+//! full sync that started after the tombstone was first seen here.
+
+use crate::interlude::*;
+
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+use crate::drawer::{DrawerRepo, TombstonedBranchDoc};
+use crate::rt::dispatch::DispatchRepo;
+
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// Prefix of the branches routines stage their changes on.
+const STAGING_BRANCH_PREFIX: &str = "/tmp/";
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GcReport {
+    pub dry_run: bool,
+    pub staging_branches_deleted: usize,
+    pub docs_purged: usize,
+    /// Tombstoned branch docs some known device hasn't caught up with.
+    pub docs_awaiting_sync: usize,
+    pub docs_compacted: usize,
+    pub bytes_reclaimed: u64,
+}
+
+pub struct GcRepo {
+    rcx: Arc<crate::repo::RepoCtx>,
+    drawer: Arc<DrawerRepo>,
+    dispatch: Arc<DispatchRepo>,
+    /// Passes are serialized, the maintenance task and callers share it.
+    pass_mutex: tokio::sync::Mutex<()>,
+}
+
+impl GcRepo {
+    pub async fn load(
+        rcx: Arc<crate::repo::RepoCtx>,
+        drawer: Arc<DrawerRepo>,
+        dispatch: Arc<DispatchRepo>,
+    ) -> Res<Arc<Self>> {
+        init_schema(&rcx.sql).await?;
+        Ok(Arc::new(Self {
+            rcx,
+            drawer,
+            dispatch,
+            pass_mutex: default(),
+        }))
+    }
+
+    /// Runs [`Self::run`] every hour until stopped.
+    pub fn spawn_maintenance(self: &Arc<Self>) -> crate::repos::RepoStopToken {
+        let cancel_token = CancellationToken::new();
+        let worker_cancel_token = cancel_token.child_token();
+        let this = Arc::clone(self);
+        let worker_handle = tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(MAINTENANCE_INTERVAL);
+            // the first tick is immediate, leave boot alone
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = worker_cancel_token.cancelled() => break,
+                    _ = ticker.tick() => {
+                        match this.run(false).await {
+                            Ok(report) => info!(?report, "gc pass done"),
+                            Err(err) => warn!(?err, "gc pass failed"),
+                        }
+                    }
+                }
+            }
+        });
+        crate::repos::RepoStopToken {
+            cancel_token,
+            worker_handle: Some(worker_handle),
+        }
+    }
+
+    #[tracing::instrument(skip(self))]
+    pub async fn run(&self, dry_run: bool) -> Res<GcReport> {
+        let _guard = self.pass_mutex.lock().await;
+        let mut report = GcReport {
+            dry_run,
+            ..default()
+        };
+        self.delete_finished_staging_branches(&mut report).await?;
+        self.purge_tombstoned_branch_docs(&mut report).await?;
+        self.compact_docs(&mut report).await?;
+        Ok(report)
+    }
+
+    async fn delete_finished_staging_branches(&self, report: &mut GcReport) -> Res<()> {
+        for (doc_id, staging_branch_path) in self.dispatch.finished_staging_branches().await {
+            if !staging_branch_path
+                .as_str()
+                .starts_with(STAGING_BRANCH_PREFIX)
+            {
+                continue;
+            }
+            let Some(branches) = self.drawer.get_doc_branches(&doc_id).await? else {
+                continue;
+            };
+            if !branches.branches.contains_key(staging_branch_path.as_str()) {
+                continue;
+            }
+            if !report.dry_run {
+                match self
+                    .drawer
+                    .delete_branch(&doc_id, &staging_branch_path, None)
+                    .await
+                {
+                    Ok(_) => {}
+                    // raced with the routine's own cleanup
+                    Err(crate::drawer::types::DrawerError::BranchNotFound { .. }) => continue,
+                    Err(err) => return Err(eyre::eyre!(err)),
+                }
+            }
+            debug!(%doc_id, %staging_branch_path, "deleted finished staging branch");
+            report.staging_branches_deleted += 1;
+        }
+        Ok(())
+    }
+
+    async fn purge_tombstoned_branch_docs(&self, report: &mut GcReport) -> Res<()> {
+        let now = Timestamp::now();
+        let tombstoned = self.drawer.tombstoned_branch_docs().await?;
+        let synced_at = self.synced_everywhere_at().await?;
+        let mut pending = HashSet::new();
+        for TombstonedBranchDoc {
+            doc_id,
+            branch_doc_id,
+            local,
+        } in tombstoned
+        {
+            pending.insert(branch_doc_id.to_string());
+            let first_seen_at = match self.tombstone_first_seen_at(&branch_doc_id).await? {
+                Some(first_seen_at) => first_seen_at,
+                None => {
+                    if !report.dry_run {
+                        self.record_tombstone_seen(&branch_doc_id, now).await?;
+                    }
+                    now
+                }
+            };
+            if !local && synced_at.is_none_or(|synced_at| synced_at < first_seen_at) {
+                report.docs_awaiting_sync += 1;
+                continue;
+            }
+            let res = match self
+                .rcx
+                .big_repo
+                .purge_doc(&branch_doc_id, report.dry_run)
+                .await
+            {
+                Ok(res) => res,
+                Err(err) => {
+                    // still open somewhere, next pass
+                    debug!(%doc_id, %branch_doc_id, ?err, "skipping tombstoned branch doc");
+                    continue;
+                }
+            };
+            if let Some(purged) = res {
+                debug!(%doc_id, %branch_doc_id, ?purged, "purged tombstoned branch doc");
+                report.docs_purged += 1;
+                report.bytes_reclaimed += purged.bytes_reclaimed();
+            }
+        }
+        if !report.dry_run {
+            self.forget_tombstones_except(&pending).await?;
+        }
+        Ok(())
+    }
+
+    async fn compact_docs(&self, report: &mut GcReport) -> Res<()> {
+        let mut doc_ids = vec![self.rcx.doc_app.document_id()];
+        doc_ids.extend(self.drawer.shard_ids());
+        doc_ids.extend(self.drawer.live_branch_docs().await?);
+        let mut seen = HashSet::new();
+        for doc_id in doc_ids {
+            if !seen.insert(doc_id) {
+                continue;
+            }
+            let Some(compacted) = self
+                .rcx
+                .big_repo
+                .compact_doc(&doc_id, report.dry_run)
+                .await?
+            else {
+                continue;
+            };
+            if compacted.before != compacted.after {
+                debug!(%doc_id, ?compacted, "compacted doc");
+                report.docs_compacted += 1;
+                report.bytes_reclaimed += compacted.bytes_reclaimed();
+            }
+        }
+        Ok(())
+    }
+
+    /// The oldest last full sync across known devices. `None` if a device
+    /// never fully synced or went stale since.
+    async fn synced_everywhere_at(&self) -> Res<Option<Timestamp>> {
+        let sync_config = crate::repo::globals::get_sync_config(&self.rcx.sql).await?;
+        let statuses = crate::sync::read_sync_status(&self.rcx).await?;
+        let mut oldest = Timestamp::MAX;
+        for device in sync_config.known_devices {
+            // storage peers only ever get the sealed vault
+            if device.storage_peer {
+                continue;
+            }
+            let peer_key = daybook_types::doc::format_peer_key(device.endpoint_id.as_bytes());
+            let Some(status) = statuses.iter().find(|status| status.peer_key == peer_key) else {
+                return Ok(None);
+            };
+            let (Some(last_full_sync_at), None) = (status.last_full_sync_at, status.stale_since)
+            else {
+                return Ok(None);
+            };
+            oldest = oldest.min(last_full_sync_at);
+        }
+        Ok(Some(oldest))
+    }
+
+    /// When a pass first saw the tombstoned branch doc.
+    async fn tombstone_first_seen_at(&self, branch_doc_id: &DocumentId) -> Res<Option<Timestamp>> {
+        let first_seen_at: Option<i64> = sqlx::query_scalar(
+            "SELECT first_seen_at_unix_secs FROM gc_tombstones WHERE branch_doc_id = ?1",
+        )
+        .bind(branch_doc_id.to_string())
+        .fetch_optional(&self.rcx.sql.read_pool)
+        .await?;
+        first_seen_at
+            .map(Timestamp::from_second)
+            .transpose()
+            .map_err(Into::into)
+    }
+
+    async fn record_tombstone_seen(&self, branch_doc_id: &DocumentId, at: Timestamp) -> Res<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO gc_tombstones (branch_doc_id, first_seen_at_unix_secs)
+            VALUES (?1, ?2)
+            ON CONFLICT(branch_doc_id) DO NOTHING
+            "#,
+        )
+        .bind(branch_doc_id.to_string())
+        .bind(at.as_second())
+        .execute(&self.rcx.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn forget_tombstones_except(&self, pending: &HashSet<String>) -> Res<()> {
+        let known: Vec<String> = sqlx::query_scalar("SELECT branch_doc_id FROM gc_tombstones")
+            .fetch_all(&self.rcx.sql.read_pool)
+            .await?;
+        for branch_doc_id in known {
+            if pending.contains(&branch_doc_id) {
+                continue;
+            }
+            sqlx::query("DELETE FROM gc_tombstones WHERE branch_doc_id = ?1")
+                .bind(&branch_doc_id)
+                .execute(&self.rcx.sql.write_pool)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+async fn init_schema(sql: &SqlCtx) -> Res<()> {
+    sqlx::query(
+        r#"
+        CREATE TABLE IF NOT EXISTS gc_tombstones (
+            branch_doc_id TEXT PRIMARY KEY,
+            first_seen_at_unix_secs INTEGER NOT NULL
+        )
+        "#,
+    )
+    .execute(&sql.write_pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::repo::globals::{SyncConfig, SyncDeviceEntry};
+    use crate::rt::dispatch::{
+        ActiveDispatch, ActiveDispatchArgs, ActiveDispatchDeets, DispatchStatus, DocFacetTokens,
+        FacetRoutineArgs, RoutineInvocation,
+    };
+    use daybook_types::doc::{AddDocArgs, BranchPath, BranchPathBuf};
+
+    fn known_device(name: &str, storage_peer: bool) -> SyncDeviceEntry {
+        SyncDeviceEntry {
+            endpoint_id: iroh::SecretKey::generate().public(),
+            name: name.into(),
+            added_at: Timestamp::now(),
+            last_connected_at: None,
+            access: default(),
+            storage_peer,
+        }
+    }
+
+    async fn add_and_delete_doc(drawer: &DrawerRepo) -> Res<DocumentId> {
+        let doc_id = drawer
+            .add(AddDocArgs {
+                branch_path: "main".into(),
+                facets: default(),
+                user_path: None,
+            })
+            .await?;
+        let branch_doc_id = drawer
+            .get_entry(&doc_id)
+            .await?
+            .ok_or_eyre("doc entry missing after add")?
+            .branches["main"]
+            .branch_doc_id;
+        assert!(drawer.del(&doc_id).await?);
+        Ok(branch_doc_id)
+    }
+
+    fn finished_dispatch(
+        doc_id: &str,
+        staging_branch_path: &str,
+        status: DispatchStatus,
+    ) -> Arc<ActiveDispatch> {
+        Arc::new(ActiveDispatch {
+            deets: ActiveDispatchDeets::Wflow {
+                wflow_partition_id: None,
+                entry_id: None,
+                plug_id: "@test/plug".into(),
+                routine_name: "routine".into(),
+                bundle_name: "bundle".into(),
+                wflow_key: "key".into(),
+                wflow_job_id: Some(staging_branch_path.trim_start_matches("/tmp/").into()),
+            },
+            args: ActiveDispatchArgs::FacetRoutine(FacetRoutineArgs {
+                doc_id: doc_id.into(),
+                branch_path: "main".into(),
+                staging_branch_path: staging_branch_path.into(),
+                heads: ChangeHashSet(Vec::new().into()),
+                invocation: RoutineInvocation::Command,
+                primary_doc: DocFacetTokens {
+                    doc_id: doc_id.into(),
+                    branch_path: "main".into(),
+                    staging_branch_path: staging_branch_path.into(),
+                    heads: ChangeHashSet(Vec::new().into()),
+                    facet_acl: vec![],
+                },
+                config_docs: vec![],
+                local_state_acl: vec![],
+                command_invoke_acl_snapshot: vec![],
+                wflow_args_json: None,
+            }),
+            status,
+            waiting_on_dispatch_ids: vec![],
+            on_success_hooks: vec![],
+        })
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gc_purges_deleted_docs_without_known_devices() -> Res<()> {
+        let test_cx = crate::test_support::test_cx("gc_purge").await?;
+        let rcx = Arc::clone(&test_cx.rt.rcx);
+        let gc_repo = GcRepo::load(
+            Arc::clone(&rcx),
+            Arc::clone(&test_cx.drawer_repo),
+            Arc::clone(&test_cx.dispatch_repo),
+        )
+        .await?;
+
+        let doc_id = test_cx
+            .drawer_repo
+            .add(AddDocArgs {
+                branch_path: "main".into(),
+                facets: default(),
+                user_path: None,
+            })
+            .await?;
+        let branch_doc_id = test_cx
+            .drawer_repo
+            .get_entry(&doc_id)
+            .await?
+            .ok_or_eyre("doc entry missing after add")?
+            .branches["main"]
+            .branch_doc_id;
+        assert!(test_cx.drawer_repo.del(&doc_id).await?);
+
+        let dry = gc_repo.run(true).await?;
+        assert!(dry.docs_purged >= 1);
+        assert!(rcx.big_repo.export_doc(&branch_doc_id).await?.is_some());
+
+        let report = gc_repo.run(false).await?;
+        assert!(report.docs_purged >= 1);
+        assert!(report.bytes_reclaimed > 0);
+        assert!(rcx.big_repo.export_doc(&branch_doc_id).await?.is_none());
+        assert_eq!(gc_repo.run(false).await?.docs_purged, 0);
+
+        test_cx.stop().await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gc_keeps_deleted_docs_until_every_known_device_synced() -> Res<()> {
+        let test_cx = crate::test_support::test_cx("gc_known_devices").await?;
+        let rcx = Arc::clone(&test_cx.rt.rcx);
+        let gc_repo = Arc::clone(&test_cx.rt.gc_repo);
+
+        let laptop = known_device("laptop", false);
+        let phone = known_device("phone", false);
+        crate::repo::globals::set_sync_config(
+            &rcx.sql,
+            &SyncConfig {
+                known_devices: vec![
+                    laptop.clone(),
+                    phone.clone(),
+                    // never syncs, mustn't hold anything back
+                    known_device("vault", true),
+                ],
+                ..default()
+            },
+        )
+        .await?;
+        let branch_doc_id = add_and_delete_doc(&test_cx.drawer_repo).await?;
+
+        let report = gc_repo.run(false).await?;
+        assert_eq!(report.docs_purged, 0);
+        assert!(report.docs_awaiting_sync >= 1);
+        assert!(rcx.big_repo.export_doc(&branch_doc_id).await?.is_some());
+
+        let status = crate::sync::SyncStatusStore::load(rcx.sql.clone()).await?;
+        let after_tombstone = Timestamp::now().checked_add(jiff::SignedDuration::from_secs(60))?;
+        status
+            .record_full_sync(
+                &daybook_types::doc::format_peer_key(laptop.endpoint_id.as_bytes()),
+                None,
+                after_tombstone,
+            )
+            .await?;
+        // a sync from before the tombstone was seen doesn't count
+        let before_tombstone = Timestamp::now().checked_sub(jiff::SignedDuration::from_secs(60))?;
+        status
+            .record_full_sync(
+                &daybook_types::doc::format_peer_key(phone.endpoint_id.as_bytes()),
+                None,
+                before_tombstone,
+            )
+            .await?;
+        let report = gc_repo.run(false).await?;
+        assert_eq!(report.docs_purged, 0);
+        assert!(rcx.big_repo.export_doc(&branch_doc_id).await?.is_some());
+
+        status
+            .record_full_sync(
+                &daybook_types::doc::format_peer_key(phone.endpoint_id.as_bytes()),
+                None,
+                after_tombstone,
+            )
+            .await?;
+        let report = gc_repo.run(false).await?;
+        assert!(report.docs_purged >= 1);
+        assert_eq!(report.docs_awaiting_sync, 0);
+        assert!(rcx.big_repo.export_doc(&branch_doc_id).await?.is_none());
+
+        test_cx.stop().await?;
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn gc_deletes_staging_branches_of_finished_dispatches() -> Res<()> {
+        let test_cx = crate::test_support::test_cx("gc_staging").await?;
+        let gc_repo = Arc::clone(&test_cx.rt.gc_repo);
+        let drawer = &test_cx.drawer_repo;
+
+        let doc_id = drawer
+            .add(AddDocArgs {
+                branch_path: "main".into(),
+                facets: default(),
+                user_path: None,
+            })
+            .await?;
+        let main_heads = drawer
+            .get_doc_branches(&doc_id)
+            .await?
+            .ok_or_eyre("missing doc branches after add")?
+            .branches
+            .get("main")
+            .ok_or_eyre("missing main branch")?
+            .clone();
+        for staging in ["/tmp/job-done", "/tmp/job-live"] {
+            drawer
+                .create_branch_at_heads_from_branch(
+                    &doc_id,
+                    &BranchPathBuf::from(staging),
+                    BranchPath::new("main"),
+                    &main_heads,
+                    None,
+                )
+                .await?;
+        }
+        test_cx
+            .dispatch_repo
+            .add(
+                "disp-done".into(),
+                finished_dispatch(&doc_id, "/tmp/job-done", DispatchStatus::Succeeded),
+            )
+            .await?;
+        test_cx
+            .dispatch_repo
+            .add(
+                "disp-live".into(),
+                finished_dispatch(&doc_id, "/tmp/job-live", DispatchStatus::Active),
+            )
+            .await?;
+
+        // boot-time init dispatches may have staging branches of their own
+        let dry = gc_repo.run(true).await?;
+        assert!(dry.staging_branches_deleted >= 1);
+        let branches = drawer
+            .get_doc_branches(&doc_id)
+            .await?
+            .ok_or_eyre("missing doc branches")?;
+        assert!(branches.branches.contains_key("/tmp/job-done"));
+
+        let report = gc_repo.run(false).await?;
+        assert!(report.staging_branches_deleted >= 1);
+        let branches = drawer
+            .get_doc_branches(&doc_id)
+            .await?
+            .ok_or_eyre("missing doc branches")?;
+        assert!(!branches.branches.contains_key("/tmp/job-done"));
+        assert!(branches.branches.contains_key("/tmp/job-live"));
+        assert!(branches.branches.contains_key("main"));
+
+        test_cx.stop().await?;
+        Ok(())
+    }
+}
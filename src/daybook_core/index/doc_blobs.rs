@@ -53,6 +53,16 @@ impl Repo for DocBlobsIndexRepo {
     }
 }
 
+#[async_trait]
+impl crate::blobs::sync::BlobHintsSource for DocBlobsIndexRepo {
+    async fn blob_hints(
+        &self,
+        blob_id: crate::blobs::BlobId,
+    ) -> Res<Option<crate::blobs::sync::BlobHints>> {
+        self.blob_hints_for_hash(&blob_id.to_string()).await
+    }
+}
+
 impl DocBlobsIndexRepo {
     pub async fn boot(
         drawer_repo: Arc<DrawerRepo>,
@@ -174,6 +184,17 @@ impl DocBlobsIndexRepo {
             tx.commit().await?;
         }
 
+        let has_mime_col: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM pragma_table_info('doc_blob_refs') WHERE name = 'mime'",
+        )
+        .fetch_optional(&sql.write_pool)
+        .await?;
+        if has_mime_col.is_none() {
+            sqlx::query("ALTER TABLE doc_blob_refs ADD COLUMN mime TEXT")
+                .execute(&sql.write_pool)
+                .await?;
+        }
+
         sqlx::query(
             "CREATE INDEX IF NOT EXISTS idx_doc_blob_refs_blob_hash ON doc_blob_refs(blob_hash)",
         )
@@ -261,7 +282,7 @@ impl DocBlobsIndexRepo {
             .map(|(facets, _)| facets)
             .ok_or_eyre("doc didn't match expectation")?;
 
-        let mut blobs = HashMap::<Arc<str>, u64>::new();
+        let mut blobs = HashMap::<Arc<str>, (u64, String)>::new();
         for (_facet_key, facet_raw) in facets {
             let facet =
                 match WellKnownFacet::from_json((*facet_raw).clone(), WellKnownFacetTag::Blob) {
@@ -284,8 +305,8 @@ impl DocBlobsIndexRepo {
                 for url in urls {
                     if let Some(hash) = parse_db_blob_hash(&url) {
                         let hash: Arc<str> = hash.into();
-                        if let Some(existing_len) =
-                            blobs.insert(Arc::clone(&hash), blob.length_octets)
+                        if let Some((existing_len, _)) =
+                            blobs.insert(Arc::clone(&hash), (blob.length_octets, blob.mime.clone()))
                         {
                             eyre::ensure!(
                                 existing_len == blob.length_octets,
@@ -307,7 +328,7 @@ impl DocBlobsIndexRepo {
         doc_id: &DocId,
         branch_path: &BranchPathBuf,
         heads: &ChangeHashSet,
-        blobs: &HashMap<Arc<str>, u64>,
+        blobs: &HashMap<Arc<str>, (u64, String)>,
     ) -> Res<ReindexDocOutcome> {
         let prev_hashes: HashSet<Arc<str>> = self
             .list_hashes_for_doc_branch(doc_id, branch_path)
@@ -347,8 +368,8 @@ impl DocBlobsIndexRepo {
             serde_json::to_string(&am_utils_rs::serialize_commit_heads(&heads.0))
                 .expect(ERROR_JSON);
 
-        let mut rows: Vec<(&str, i64)> = Vec::with_capacity(blobs.len());
-        for (hash, length_octets) in blobs {
+        let mut rows: Vec<(&str, i64, &str)> = Vec::with_capacity(blobs.len());
+        for (hash, (length_octets, mime)) in blobs {
             let length_octets_i64 = i64::try_from(*length_octets).map_err(|_| {
                 eyre::eyre!(
                     "blob length octets exceeds sqlite INTEGER range: doc_id={} branch={} length_octets={}",
@@ -357,21 +378,22 @@ impl DocBlobsIndexRepo {
                     length_octets
                 )
             })?;
-            rows.push((&hash[..], length_octets_i64));
+            rows.push((&hash[..], length_octets_i64, &mime[..]));
         }
 
         let mut query_builder = QueryBuilder::new(
-            "INSERT INTO doc_blob_refs (doc_id, branch_path, blob_hash, length_octets, origin_heads) ",
+            "INSERT INTO doc_blob_refs (doc_id, branch_path, blob_hash, length_octets, mime, origin_heads) ",
         );
-        query_builder.push_values(rows.iter(), |mut row, (hash, length_octets_i64)| {
+        query_builder.push_values(rows.iter(), |mut row, (hash, length_octets_i64, mime)| {
             row.push_bind(doc_id)
                 .push_bind(branch_path.as_str())
                 .push_bind(hash)
                 .push_bind(*length_octets_i64)
+                .push_bind(mime)
                 .push_bind(&serialized_heads);
         });
         query_builder.push(
-            " ON CONFLICT(doc_id, branch_path, blob_hash) DO UPDATE SET origin_heads = excluded.origin_heads, length_octets = excluded.length_octets",
+            " ON CONFLICT(doc_id, branch_path, blob_hash) DO UPDATE SET origin_heads = excluded.origin_heads, length_octets = excluded.length_octets, mime = excluded.mime",
         );
         query_builder.build().execute(&mut *tx).await?;
         tx.commit().await?;
@@ -670,6 +692,35 @@ impl DocBlobsIndexRepo {
             .collect()
     }
 
+    /// Size and mime of a blob as declared by the blob facets referencing it.
+    /// `None` if no indexed doc references the hash.
+    pub async fn blob_hints_for_hash(
+        &self,
+        hash: &str,
+    ) -> Res<Option<crate::blobs::sync::BlobHints>> {
+        let row: Option<(i64, Option<String>)> = sqlx::query_as(
+            r#"
+            SELECT length_octets, mime
+            FROM doc_blob_refs
+            WHERE blob_hash = ?1
+            ORDER BY doc_id ASC, branch_path ASC
+            LIMIT 1
+            "#,
+        )
+        .bind(hash)
+        .fetch_optional(&self.sql.read_pool)
+        .await?;
+        row.map(|(length_octets, mime)| {
+            let length_octets = u64::try_from(length_octets)
+                .map_err(|_| ferr!("invalid negative blob length for hash {hash}"))?;
+            Ok(crate::blobs::sync::BlobHints {
+                length_octets,
+                mime,
+            })
+        })
+        .transpose()
+    }
+
     pub async fn list_all_hashes(&self) -> Res<Vec<String>> {
         let hashes: Vec<String> = sqlx::query_scalar(
             r#"
@@ -753,7 +804,7 @@ impl DocBlobsIndexRepo {
         Ok(())
     }
 
-    async fn delete_doc_branches_not_in(
+    pub async fn delete_doc_branches_not_in(
         &self,
         doc_id: &DocId,
         branch_paths: &[BranchPathBuf],
@@ -851,6 +902,7 @@ impl crate::rt::switch::SwitchSink for DocBlobsTriageListener {
             return Ok(outcome);
         };
         match &**event {
+            crate::drawer::DrawerEvent::FacetConflicts { .. } => {}
             crate::drawer::DrawerEvent::DocDeleted { id, .. } => {
                 self.index_repo.enqueue_delete(id.clone())?;
             }
@@ -1047,6 +1099,7 @@ mod tests {
             Arc::clone(&big_repo),
             Arc::clone(&big_sync_host.store),
             drawer_doc_id,
+            None,
             local_user_path.clone(),
             crate::app::open_sql_ctx(crate::app::SqlConfig::memory()).await?,
             temp_dir.path().join("drawer-local-state"),
@@ -1060,8 +1113,11 @@ mod tests {
         )
         .await?;
         let (sqlite_local_state_repo, sqlite_local_state_stop) =
-            crate::local_state::SqliteLocalStateRepo::boot(temp_dir.path().join("local-state"))
-                .await?;
+            crate::local_state::SqliteLocalStateRepo::boot(
+                temp_dir.path().join("local-state"),
+                None,
+            )
+            .await?;
         let (repo, repo_stop) = DocBlobsIndexRepo::boot(
             Arc::clone(&drawer_repo),
             Arc::clone(&blobs_repo),
@@ -351,6 +351,21 @@ impl DocFacetRefIndexRepo {
         Ok(())
     }
 
+    /// Every doc showing up on either end of an edge.
+    pub async fn list_all_doc_ids(&self) -> Res<Vec<DocId>> {
+        let doc_ids: Vec<DocId> = sqlx::query_scalar(
+            r#"
+            SELECT origin_doc_id FROM facet_ref_edges
+            UNION
+            SELECT target_doc_id FROM facet_ref_edges
+            ORDER BY 1 ASC
+            "#,
+        )
+        .fetch_all(&self.sql.read_pool)
+        .await?;
+        Ok(doc_ids)
+    }
+
     pub async fn list_outgoing(&self, doc_id: &DocId) -> Res<Vec<DocFacetRefEdge>> {
         let rows: Vec<(String, String, String, String, String, String)> = sqlx::query_as(
             r#"
@@ -700,6 +715,7 @@ impl crate::rt::switch::SwitchSink for FacetRefTriageListener {
                 return Ok(outcome);
             }
             crate::rt::switch::SwitchEvent::Drawer(event) => match &**event {
+                crate::drawer::DrawerEvent::FacetConflicts { .. } => {}
                 crate::drawer::DrawerEvent::DocDeleted { id, .. } => {
                     self.index_repo.enqueue_delete(id.clone())?;
                 }
@@ -264,6 +264,14 @@ impl DocFacetSetIndexRepo {
         Ok(())
     }
 
+    pub async fn list_all_doc_ids(&self) -> Res<Vec<DocId>> {
+        let doc_ids: Vec<DocId> =
+            sqlx::query_scalar("SELECT doc_id FROM facet_set_docs ORDER BY doc_id ASC")
+                .fetch_all(&self.sql.read_pool)
+                .await?;
+        Ok(doc_ids)
+    }
+
     pub async fn list_tags_for_doc(&self, doc_id: &DocId) -> Res<Vec<String>> {
         let tags: Vec<String> = sqlx::query_scalar(
             r#"
@@ -395,6 +403,7 @@ impl crate::rt::switch::SwitchSink for FacetSetTriageListener {
             return Ok(outcome);
         };
         match &**event {
+            crate::drawer::DrawerEvent::FacetConflicts { .. } => {}
             crate::drawer::DrawerEvent::DocDeleted { id, .. } => {
                 self.index_repo.enqueue_delete(id.clone())?;
             }
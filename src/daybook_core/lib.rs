@@ -28,8 +28,11 @@ mod interlude {
 
 pub mod blobs;
 pub mod config;
+pub mod daemon;
+pub mod doctor;
 pub mod drawer;
 pub mod event_origin;
+pub mod gc;
 pub mod imgtools;
 pub mod index;
 pub mod local_state;
@@ -9,6 +9,8 @@ pub enum LocalStateEvent {
 
 pub struct SqliteLocalStateRepo {
     local_state_root: PathBuf,
+    /// See [`crate::repo::RepoCtx::data_key`].
+    data_key: Option<crate::repo::encryption::DataKey>,
     sqlite_ctxs: tokio::sync::RwLock<HashMap<String, sqlx_utils_rs::SqlCtx>>,
     pub registry: Arc<crate::repos::ListenersRegistry>,
     cancel_token: CancellationToken,
@@ -27,7 +29,10 @@ impl crate::repos::Repo for SqliteLocalStateRepo {
 }
 
 impl SqliteLocalStateRepo {
-    pub async fn boot(local_state_root: PathBuf) -> Res<(Arc<Self>, crate::repos::RepoStopToken)> {
+    pub async fn boot(
+        local_state_root: PathBuf,
+        data_key: Option<crate::repo::encryption::DataKey>,
+    ) -> Res<(Arc<Self>, crate::repos::RepoStopToken)> {
         let main_cancel_token = CancellationToken::new();
         tokio::fs::create_dir_all(&local_state_root)
             .await
@@ -40,6 +45,7 @@ impl SqliteLocalStateRepo {
 
         let repo = Arc::new(Self {
             local_state_root,
+            data_key,
             sqlite_ctxs: tokio::sync::RwLock::new(HashMap::new()),
             registry: crate::repos::ListenersRegistry::new(),
             cancel_token: main_cancel_token.child_token(),
@@ -103,7 +109,8 @@ impl SqliteLocalStateRepo {
         let sqlite_url = format!("sqlite://{}", sqlite_file_path.display());
 
         crate::init_sqlite_vec();
-        let sql = sqlx_utils_rs::SqlCtx::url(&sqlite_url)
+        let key = self.data_key.as_ref().map(|key| key.sqlcipher_key());
+        let sql = sqlx_utils_rs::SqlCtx::url_keyed(&sqlite_url, key.as_deref())
             .await
             .wrap_err("error initializing sqlite local state connection")?;
 
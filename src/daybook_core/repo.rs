@@ -8,6 +8,8 @@ use big_repo::SharedPartStore;
 use daybook_types::doc::{UserPath, UserPathBuf};
 use fs4::fs_std::FileExt;
 
+pub mod encryption;
+
 const REPO_MARKER_FILE: &str = "db.repo.txt";
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -18,10 +20,23 @@ pub struct RepoLayout {
     pub blobs_root: PathBuf,
     pub marker_path: PathBuf,
     pub lock_path: PathBuf,
+    /// See [`crate::daemon`].
+    pub daemon_info_path: PathBuf,
+}
+
+#[derive(Clone, Default)]
+pub struct RepoOpenOptions {
+    /// Unlocks repos encrypted with a passphrase, see [`encryption`].
+    pub passphrase: Option<String>,
 }
 
-#[derive(Debug, Clone, Default)]
-pub struct RepoOpenOptions {}
+impl std::fmt::Debug for RepoOpenOptions {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("RepoOpenOptions")
+            .field("passphrase", &self.passphrase.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
 
 #[derive(Debug, serde::Serialize, serde::Deserialize)]
 struct RepoLockInfo {
@@ -36,6 +51,24 @@ pub struct RepoLockGuard {
 
 impl RepoLockGuard {
     pub fn acquire(lock_path: &std::path::Path) -> Res<Self> {
+        match Self::try_acquire(lock_path)? {
+            Some(guard) => Ok(guard),
+            None => match Self::holder_pid(lock_path) {
+                Some(pid) => Err(eyre::eyre!(
+                    "repo is already in use by pid={} (lock file: {})",
+                    pid,
+                    lock_path.display()
+                )),
+                None => Err(eyre::eyre!(
+                    "repo is already in use (lock file: {})",
+                    lock_path.display()
+                )),
+            },
+        }
+    }
+
+    /// Returns `None` if another process holds the lock.
+    pub fn try_acquire(lock_path: &std::path::Path) -> Res<Option<Self>> {
         if let Some(parent) = lock_path.parent() {
             std::fs::create_dir_all(parent)?;
         }
@@ -48,24 +81,15 @@ impl RepoLockGuard {
             .wrap_err_with(|| format!("error opening repo lock file {}", lock_path.display()))?;
 
         // NOTE: lock is released when file is dropped
-        _file.try_lock_exclusive().map_err(|err| {
-            let holder = std::fs::read_to_string(lock_path)
-                .ok()
-                .and_then(|content| serde_json::from_str::<RepoLockInfo>(&content).ok());
-            if let Some(holder) = holder {
-                eyre::eyre!(
-                    "repo is already in use by pid={} (lock file: {})",
-                    holder.pid,
-                    lock_path.display()
-                )
-            } else {
-                eyre::eyre!(
-                    "repo is already in use (lock file: {}, cause: {})",
-                    lock_path.display(),
-                    err
-                )
+        match _file.try_lock_exclusive() {
+            Ok(true) => {}
+            Ok(false) => return Ok(None),
+            Err(err) => {
+                return Err(err).wrap_err_with(|| {
+                    format!("error locking repo lock file {}", lock_path.display())
+                })
             }
-        })?;
+        }
 
         let lock_info = RepoLockInfo {
             pid: std::process::id(),
@@ -75,10 +99,18 @@ impl RepoLockGuard {
         let json = serde_json::to_string(&lock_info)?;
         std::io::Write::write_all(&mut _file, json.as_bytes())?;
         std::io::Write::flush(&mut _file)?;
-        Ok(Self {
+        Ok(Some(Self {
             _file,
             _path: lock_path.to_path_buf(),
-        })
+        }))
+    }
+
+    /// Pid of the process holding the lock as it recorded it.
+    pub fn holder_pid(lock_path: &std::path::Path) -> Option<u32> {
+        std::fs::read_to_string(lock_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<RepoLockInfo>(&content).ok())
+            .map(|info| info.pid)
     }
 }
 
@@ -106,6 +138,8 @@ pub struct RepoCtx {
     pub iroh_public_key: String,
     pub iroh_secret_key: iroh::SecretKey,
     pub secret_repo: crate::secrets::SecretRepo,
+    /// Set for encrypted repos. Databases of the repo are keyed with it.
+    pub data_key: Option<encryption::DataKey>,
 }
 
 pub(crate) struct RepoCtxParts {
@@ -125,6 +159,7 @@ pub(crate) struct RepoCtxParts {
     pub iroh_public_key: String,
     pub iroh_secret_key: iroh::SecretKey,
     pub secret_repo: crate::secrets::SecretRepo,
+    pub data_key: Option<encryption::DataKey>,
 }
 
 impl RepoCtx {
@@ -152,6 +187,7 @@ impl RepoCtx {
             iroh_public_key: parts.iroh_public_key,
             iroh_secret_key: parts.iroh_secret_key,
             local_device_name: parts.local_device_name,
+            data_key: parts.data_key,
         })
     }
 
@@ -242,7 +278,7 @@ impl RepoCtx {
     async fn open_inner(
         layout: RepoLayout,
         lock_guard: RepoLockGuard,
-        _options: RepoOpenOptions,
+        options: RepoOpenOptions,
         local_device_name: String,
         initialize_repo: bool,
         repo_name: Option<String>,
@@ -255,14 +291,24 @@ impl RepoCtx {
         cleanup_blobs_staging_dir(&layout.blobs_root).await?;
         info!(repo_root = %layout.repo_root.display(), "repo open_inner: blobs staging cleaned");
 
-        let sql = crate::app::open_sql_ctx(SqlConfig::file(layout.sqlite_path.clone())).await?;
+        let secret_repo = crate::secrets::SecretRepo::boot().await?;
+        let data_key = encryption::unlock(
+            &layout.repo_root,
+            &secret_repo,
+            options.passphrase.as_deref(),
+        )
+        .await?;
+
+        let sql = crate::app::open_sql_ctx(
+            SqlConfig::file(layout.sqlite_path.clone()).with_key(data_key.clone()),
+        )
+        .await?;
         info!(
             repo_root = %layout.repo_root.display(),
             sqlite_path = %layout.sqlite_path.display(),
+            encrypted = data_key.is_some(),
             "repo open_inner: sqlite ready"
         );
-
-        let secret_repo = crate::secrets::SecretRepo::boot().await?;
         let repo_id = if initialize_repo {
             format!("repo-{}", Uuid::new_v4().simple())
         } else {
@@ -334,8 +380,13 @@ impl RepoCtx {
         let part_store: SharedPartStore = Arc::new(part_store) as _;
         info!(repo_root = %layout.repo_root.display(), "repo open_inner: partition store ready");
 
-        let (big_repo, big_repo_stop) =
-            boot_big_repo(&layout, &identity, Arc::clone(&part_store)).await?;
+        let (big_repo, big_repo_stop) = boot_big_repo(
+            &layout,
+            &identity,
+            Arc::clone(&part_store),
+            data_key.as_ref(),
+        )
+        .await?;
         info!(repo_root = %layout.repo_root.display(), "repo open_inner: big repo booted");
 
         let (doc_app, doc_drawer) = if initialize_repo {
@@ -392,6 +443,7 @@ impl RepoCtx {
             iroh_public_key: identity.iroh_public_key.to_string(),
             iroh_secret_key: identity.iroh_secret_key,
             secret_repo,
+            data_key,
         };
         Ok(RepoCtx::from_parts(parts, doc_app, doc_drawer))
     }
@@ -510,6 +562,7 @@ impl RepoCtx {
                 Arc::clone(big_repo),
                 Arc::clone(partition_store),
                 doc_drawer.document_id(),
+                Some(doc_app.document_id()),
                 UserPathBuf::from(local_user_path.to_string()),
                 sql.clone(),
                 blobs_root
@@ -615,12 +668,8 @@ fn compute_user_info(
     user_id: &str,
     identity: &crate::secrets::RepoIdentity,
 ) -> UserInfo {
-    let pkey_bs58 = utils_rs::hash::encode_base58_multibase(identity.iroh_public_key.as_bytes());
-    let device_id = format!(
-        "{}{}",
-        daybook_types::doc::user_path::DEVICE_ID_PREFIX,
-        pkey_bs58
-    );
+    let device_id =
+        daybook_types::doc::user_path::device_id_for_endpoint(identity.iroh_public_key.as_bytes());
     let local_user_path = UserPathBuf::new().join("/").join(user_id).join(device_id);
     let local_peer_key = daybook_types::doc::format_peer_key(identity.iroh_public_key.as_bytes());
     let local_actor_id =
@@ -636,11 +685,27 @@ async fn boot_big_repo(
     layout: &RepoLayout,
     identity: &crate::secrets::RepoIdentity,
     partition_store: SharedPartStore,
+    data_key: Option<&encryption::DataKey>,
 ) -> Res<(SharedBigRepo, big_repo::BigRepoStopToken)> {
-    let am_config = big_repo::Config {
-        storage: big_repo::StorageConfig::Disk {
+    let storage = match data_key {
+        None => big_repo::StorageConfig::Disk {
             path: layout.samod_root.clone(),
         },
+        Some(key) => {
+            // the plaintext storage encryption::enable sealed, left behind
+            // if it was interrupted before deleting it
+            let disk_storage = layout.samod_root.join("subduction");
+            if tokio::fs::try_exists(&disk_storage).await? {
+                tokio::fs::remove_dir_all(&disk_storage).await?;
+            }
+            big_repo::StorageConfig::Sealed {
+                path: layout.samod_root.clone(),
+                key: big_repo::SealKey::new(*key.as_bytes()),
+            }
+        }
+    };
+    let am_config = big_repo::Config {
+        storage,
         peer_id: PeerId::new(*identity.iroh_public_key.as_bytes()),
         secret_key_bytes: identity.iroh_secret_key.to_bytes(),
     };
@@ -718,7 +783,7 @@ pub(crate) async fn ensure_expected_partitions_for_docs(
         .await?;
     Ok(())
 }
-fn repo_layout(repo_root: &std::path::Path) -> Res<RepoLayout> {
+pub(crate) fn repo_layout(repo_root: &std::path::Path) -> Res<RepoLayout> {
     let repo_root = std::path::absolute(repo_root)
         .wrap_err_with(|| format!("error absolutizing repo root {}", repo_root.display()))?;
     Ok(RepoLayout {
@@ -728,6 +793,7 @@ fn repo_layout(repo_root: &std::path::Path) -> Res<RepoLayout> {
         blobs_root: repo_root.join("blobs"),
         marker_path: repo_root.join(REPO_MARKER_FILE),
         lock_path: repo_root.join("repo.lock"),
+        daemon_info_path: repo_root.join(crate::daemon::DAEMON_INFO_FILE),
     })
 }
 
@@ -744,6 +810,13 @@ pub async fn is_repo_initialized(repo_root: &std::path::Path) -> Res<bool> {
     Ok(utils_rs::file_exists(&layout.marker_path).await?)
 }
 
+/// Whether another process holds the repo. Such repos can only be used
+/// through [`crate::daemon::DaemonClient`].
+pub fn is_repo_in_use(repo_root: &std::path::Path) -> Res<bool> {
+    let layout = repo_layout(repo_root)?;
+    Ok(RepoLockGuard::try_acquire(&layout.lock_path)?.is_none())
+}
+
 pub async fn is_repo_bootstrapped(repo_root: &std::path::Path) -> Res<bool> {
     if !is_repo_initialized(repo_root).await? {
         return Ok(false);
@@ -752,6 +825,10 @@ pub async fn is_repo_bootstrapped(repo_root: &std::path::Path) -> Res<bool> {
     if !layout.sqlite_path.exists() {
         return Ok(false);
     }
+    // only bootstrapped repos can be encrypted
+    if encryption::read_header(repo_root).await?.is_some() {
+        return Ok(true);
+    }
     let sql = crate::app::open_sql_ctx(SqlConfig::file(layout.sqlite_path.clone()))
         .await
         .wrap_err_with(|| {
@@ -898,10 +975,40 @@ pub mod globals {
         .await?;
         Ok(())
     }
+    const BLOB_CACHE_CONFIG_KEY: &str = "global.blob_cache_config";
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+    pub struct BlobCacheConfig {
+        /// Bytes of blobs that can be fetched again from peers to keep
+        /// locally. `None` keeps everything.
+        pub quota_bytes: Option<u64>,
+    }
+
+    pub async fn get_blob_cache_config(sql: &SqlCtx) -> Res<BlobCacheConfig> {
+        let rec = sqlx::query_scalar::<_, String>("SELECT value FROM kvstore WHERE key = ?1")
+            .bind(BLOB_CACHE_CONFIG_KEY)
+            .fetch_optional(&sql.write_pool)
+            .await?;
+        let state = match rec {
+            Some(json) => serde_json::from_str::<BlobCacheConfig>(&json)?,
+            None => BlobCacheConfig::default(),
+        };
+        Ok(state)
+    }
+
+    pub async fn set_blob_cache_config(sql: &SqlCtx, state: &BlobCacheConfig) -> Res<()> {
+        let json = serde_json::to_string(state)?;
+        upsert_string_global(sql, BLOB_CACHE_CONFIG_KEY, &json).await
+    }
+
     const SYNC_CONFIG_KEY: &str = "global.sync_config";
     #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Default)]
     pub struct SyncConfig {
         pub known_devices: Vec<SyncDeviceEntry>,
+        /// Branch docs that aren't local yet get pulled from connected peers
+        /// when they're opened. Set on shallow clones, see
+        /// [`crate::sync::CloneRepoInitOptions::shallow`].
+        #[serde(default)]
+        pub fetch_docs_on_demand: bool,
     }
 
     #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq)]
@@ -910,7 +1017,36 @@ pub mod globals {
         pub name: String,
         pub added_at: Timestamp,
         pub last_connected_at: Option<Timestamp>,
+        #[serde(default)]
+        pub access: PeerAccess,
+        /// The device is a storage peer that only ever gets the sealed
+        /// vault partition. See [`crate::sync::StoragePeer`].
+        #[serde(default)]
+        pub storage_peer: bool,
     }
+
+    /// What a known device is allowed to do when syncing with this one.
+    /// The default grants full access.
+    #[derive(serde::Serialize, serde::Deserialize, Debug, Clone, PartialEq, Eq, Default)]
+    pub struct PeerAccess {
+        /// Changes made by the device are never pulled in.
+        #[serde(default)]
+        pub read_only: bool,
+        /// Partition labels the device can see. `None` allows every
+        /// partition.
+        #[serde(default)]
+        pub partitions: Option<Vec<String>>,
+    }
+
+    impl PeerAccess {
+        pub fn can_read(&self, partition_label: &str) -> bool {
+            match &self.partitions {
+                Some(partitions) => partitions.iter().any(|label| label == partition_label),
+                None => true,
+            }
+        }
+    }
+
     pub async fn get_sync_config(sql: &SqlCtx) -> Res<SyncConfig> {
         let rec = sqlx::query_scalar::<_, String>("SELECT value FROM kvstore WHERE key = ?1")
             .bind(SYNC_CONFIG_KEY)
@@ -0,0 +1,533 @@
+//! Opt-in encryption at rest.
+//!
+//! An encrypted repo has a random [`DataKey`] that keys its SQLite
+//! databases through SQLCipher: the repo database, `wflows.db` and the
+//! local state databases of plugs. The docs under `samod/` use the sealed
+//! storage of [`big_repo::StorageConfig::Sealed`] and the object files of
+//! the blob store are sealed by [`crate::blobs::BlobsRepo::new_keyed`],
+//! both with XChaCha20Poly1305 under the same key. The data key is either
+//! kept in the OS keyring or wrapped under a key derived from a
+//! passphrase with Argon2id. Which of the two is recorded in the
+//! [`ENCRYPTION_HEADER_FILE`] at the repo root, the only file that has to
+//! be readable before the repo is unlocked.
+
+use crate::interlude::*;
+
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+pub const ENCRYPTION_HEADER_FILE: &str = "encryption.json";
+const HEADER_VERSION: u32 = 1;
+const NONCE_LEN: usize = 24;
+const SALT_LEN: usize = 16;
+const WRAP_AAD: &[u8] = b"townframe daybook repo data key v1";
+
+/// Key the databases of an encrypted repo are keyed with.
+#[derive(Clone, PartialEq, Eq)]
+pub struct DataKey([u8; 32]);
+
+impl std::fmt::Debug for DataKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str("DataKey(..)")
+    }
+}
+
+impl DataKey {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_base58(&self) -> String {
+        utils_rs::hash::encode_base58_multibase(self.0)
+    }
+
+    pub fn from_base58(encoded: &str) -> Res<Self> {
+        let bytes = utils_rs::hash::decode_base58_multibase(encoded.trim())
+            .wrap_err("error decoding data key")?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| ferr!("data key has the wrong length"))?;
+        Ok(Self(bytes))
+    }
+
+    /// Value for SQLCipher's `key` pragma. Raw keys skip its KDF.
+    pub(crate) fn sqlcipher_key(&self) -> String {
+        let hex = self
+            .0
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect::<String>();
+        format!("\"x'{hex}'\"")
+    }
+
+    /// Seals the files SQLCipher doesn't cover: the nonce followed by the
+    /// ciphertext. `aad` binds the result to where it's stored.
+    pub(crate) fn seal(&self, plaintext: &[u8], aad: &[u8]) -> Res<Vec<u8>> {
+        let nonce: [u8; NONCE_LEN] = rand::random();
+        let sealed = XChaCha20Poly1305::new(&self.0.into())
+            .encrypt(
+                XNonce::from_slice(&nonce),
+                Payload {
+                    msg: plaintext,
+                    aad,
+                },
+            )
+            .map_err(|_| ferr!("error sealing with data key"))?;
+        let mut out = nonce.to_vec();
+        out.extend_from_slice(&sealed);
+        Ok(out)
+    }
+
+    pub(crate) fn open(&self, sealed: &[u8], aad: &[u8]) -> Res<Vec<u8>> {
+        if sealed.len() < NONCE_LEN {
+            eyre::bail!("sealed data is truncated");
+        }
+        let (nonce, sealed) = sealed.split_at(NONCE_LEN);
+        XChaCha20Poly1305::new(&self.0.into())
+            .decrypt(XNonce::from_slice(nonce), Payload { msg: sealed, aad })
+            .map_err(|_| ferr!("sealed data doesn't open under the data key"))
+    }
+}
+
+/// Where the data key of an encrypted repo is kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum KeySlot {
+    /// In the OS keyring under `key_id`.
+    Keyring { key_id: String },
+    /// Wrapped under a key derived from a passphrase.
+    Passphrase {
+        /// Argon2id salt, base58.
+        salt: String,
+        /// Nonce followed by the wrapped data key, base58.
+        wrapped_key: String,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptionHeader {
+    pub version: u32,
+    pub key_slot: KeySlot,
+}
+
+/// How [`enable`] keeps the data key.
+pub enum KeySource {
+    Keyring,
+    Passphrase(String),
+}
+
+fn header_path(repo_root: &Path) -> PathBuf {
+    repo_root.join(ENCRYPTION_HEADER_FILE)
+}
+
+/// `None` if the repo isn't encrypted.
+pub async fn read_header(repo_root: &Path) -> Res<Option<EncryptionHeader>> {
+    let path = header_path(repo_root);
+    match tokio::fs::read(&path).await {
+        Ok(bytes) => Ok(Some(
+            serde_json::from_slice(&bytes).wrap_err("error parsing encryption header")?,
+        )),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => {
+            Err(err).wrap_err_with(|| format!("error reading encryption header {}", path.display()))
+        }
+    }
+}
+
+async fn write_header(repo_root: &Path, header: &EncryptionHeader) -> Res<()> {
+    let path = header_path(repo_root);
+    let temp = path.with_extension("json.tmp");
+    tokio::fs::write(&temp, serde_json::to_vec_pretty(header).expect(ERROR_JSON)).await?;
+    tokio::fs::rename(&temp, &path).await?;
+    Ok(())
+}
+
+/// The data key of the repo at `repo_root`, `None` if it isn't encrypted.
+/// `passphrase` is only needed for passphrase protected repos.
+pub async fn unlock(
+    repo_root: &Path,
+    secret_repo: &crate::secrets::SecretRepo,
+    passphrase: Option<&str>,
+) -> Res<Option<DataKey>> {
+    let Some(header) = read_header(repo_root).await? else {
+        return Ok(None);
+    };
+    let key = match &header.key_slot {
+        KeySlot::Keyring { key_id } => secret_repo
+            .load_data_key(key_id)
+            .await?
+            .ok_or_eyre("repo data key missing from keyring")?,
+        KeySlot::Passphrase { .. } => {
+            let passphrase =
+                passphrase.ok_or_eyre("repo is encrypted with a passphrase but none was given")?;
+            unwrap_with_passphrase(&header.key_slot, passphrase)?
+        }
+    };
+    Ok(Some(key))
+}
+
+/// Encrypts the databases, docs and blobs of the repo at `repo_root`. The
+/// repo can't be open anywhere while this runs.
+pub async fn enable(repo_root: &Path, source: KeySource) -> Res<()> {
+    let layout = super::repo_layout(repo_root)?;
+    let _lock_guard = super::RepoLockGuard::acquire(&layout.lock_path)?;
+    if !super::is_repo_bootstrapped(repo_root).await? {
+        eyre::bail!("repo not initialized at path {}", repo_root.display());
+    }
+    if read_header(repo_root).await?.is_some() {
+        eyre::bail!("repo is already encrypted");
+    }
+
+    let key = DataKey::generate();
+    let key_slot = match source {
+        KeySource::Keyring => {
+            let key_id = format!("dkey_{}", Uuid::new_v4().bs58());
+            let secret_repo = crate::secrets::SecretRepo::boot().await?;
+            let res = secret_repo.set_data_key(&key_id, &key).await;
+            secret_repo.stop().await?;
+            res?;
+            KeySlot::Keyring { key_id }
+        }
+        KeySource::Passphrase(passphrase) => wrap_with_passphrase(&key, &passphrase)?,
+    };
+
+    // The encrypted copies are only swapped in once the header is in place
+    // so that a failed export leaves the repo as it was. Docs go first as
+    // finding them opens the repo database.
+    seal_docs(&layout, &key).await?;
+    let sealed_blob_files =
+        crate::blobs::seal_object_files(&layout.blobs_root.join("objects"), &key).await?;
+
+    let mut databases = vec![layout.sqlite_path.clone()];
+    let wflows_db_path = layout.repo_root.join("wflows.db");
+    if tokio::fs::try_exists(&wflows_db_path).await? {
+        databases.push(wflows_db_path);
+    }
+    databases.extend(local_state_databases(&layout.repo_root.join("local_state")).await?);
+    let mut encrypted = Vec::with_capacity(databases.len());
+    for path in &databases {
+        encrypted.push(export_encrypted(path, &key).await?);
+    }
+    write_header(
+        repo_root,
+        &EncryptionHeader {
+            version: HEADER_VERSION,
+            key_slot,
+        },
+    )
+    .await?;
+    for (path, encrypted_path) in databases.iter().zip(encrypted) {
+        for suffix in ["-wal", "-shm"] {
+            let mut sidecar = path.clone().into_os_string();
+            sidecar.push(suffix);
+            match tokio::fs::remove_file(&sidecar).await {
+                Ok(()) => {}
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        tokio::fs::rename(&encrypted_path, path).await?;
+    }
+    for (sealed_path, path) in sealed_blob_files {
+        tokio::fs::rename(&sealed_path, &path).await?;
+    }
+    // encrypted repos keep the iroh store in memory
+    for plaintext_dir in [
+        layout.samod_root.join("subduction"),
+        layout.blobs_root.join("iroh"),
+    ] {
+        match tokio::fs::remove_dir_all(&plaintext_dir).await {
+            Ok(()) => {}
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+/// Writes the sealed copy of every doc next to the plaintext storage.
+async fn seal_docs(layout: &super::RepoLayout, key: &DataKey) -> Res<()> {
+    let sql =
+        sqlx_utils_rs::SqlCtx::url(&format!("sqlite://{}", layout.sqlite_path.display())).await?;
+    let res = async {
+        let repo_id = super::globals::get_string_global(&sql, "global.repo_id")
+            .await?
+            .ok_or_eyre("global.repo_id missing in initialized repo")?;
+        let part_store =
+            big_sync::SqlitePartStore::new(sql.clone(), repo_id, big_sync_core::BuckId::MAX_LEVEL)
+                .await?;
+        let sealed = big_repo::seal_disk_storage(
+            &layout.samod_root,
+            &big_repo::SealKey::new(*key.as_bytes()),
+            Arc::new(part_store),
+        )
+        .await?;
+        debug!(sealed, "sealed repo docs");
+        eyre::Ok(())
+    }
+    .await;
+    sql.write_pool.close().await;
+    sql.read_pool.close().await;
+    res
+}
+
+/// Rewraps the data key of a passphrase protected repo. The databases
+/// aren't touched.
+pub async fn change_passphrase(repo_root: &Path, old: &str, new: &str) -> Res<()> {
+    let header = read_header(repo_root)
+        .await?
+        .ok_or_eyre("repo isn't encrypted")?;
+    let key = match &header.key_slot {
+        KeySlot::Passphrase { .. } => unwrap_with_passphrase(&header.key_slot, old)?,
+        KeySlot::Keyring { .. } => {
+            eyre::bail!("repo data key is kept in the keyring, it has no passphrase")
+        }
+    };
+    write_header(
+        repo_root,
+        &EncryptionHeader {
+            version: HEADER_VERSION,
+            key_slot: wrap_with_passphrase(&key, new)?,
+        },
+    )
+    .await
+}
+
+async fn local_state_databases(local_state_root: &Path) -> Res<Vec<PathBuf>> {
+    let mut out = vec![];
+    let mut dirs = vec![local_state_root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut read_dir = match tokio::fs::read_dir(&dir).await {
+            Ok(read_dir) => read_dir,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = read_dir.next_entry().await? {
+            let path = entry.path();
+            if entry.file_type().await?.is_dir() {
+                dirs.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "sqlite") {
+                out.push(path);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Writes an encrypted copy of the database next to it.
+async fn export_encrypted(path: &Path, key: &DataKey) -> Res<PathBuf> {
+    use sqlx::{ConnectOptions, Connection};
+
+    let mut encrypted_path = path.to_path_buf().into_os_string();
+    encrypted_path.push(".encrypting");
+    let encrypted_path = PathBuf::from(encrypted_path);
+    // leftover from an earlier attempt
+    match tokio::fs::remove_file(&encrypted_path).await {
+        Ok(()) => {}
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+        Err(err) => return Err(err.into()),
+    }
+
+    let mut conn = sqlx::sqlite::SqliteConnectOptions::new()
+        .filename(path)
+        .connect()
+        .await
+        .wrap_err_with(|| format!("error opening {}", path.display()))?;
+    let cipher_version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(&mut conn)
+        .await?;
+    if cipher_version.is_none() {
+        eyre::bail!("sqlite was built without SQLCipher, enable the sqlcipher feature");
+    }
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query(&format!(
+        "ATTACH DATABASE ?1 AS encrypted KEY {}",
+        key.sqlcipher_key()
+    ))
+    .bind(encrypted_path.to_string_lossy().into_owned())
+    .execute(&mut conn)
+    .await?;
+    sqlx::query("SELECT sqlcipher_export('encrypted')")
+        .execute(&mut conn)
+        .await
+        .wrap_err_with(|| format!("error encrypting {}", path.display()))?;
+    sqlx::query("DETACH DATABASE encrypted")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+    Ok(encrypted_path)
+}
+
+fn derive_wrapping_key(passphrase: &str, salt: &[u8]) -> Res<XChaCha20Poly1305> {
+    let mut key = [0_u8; 32];
+    argon2::Argon2::default()
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(|err| ferr!("error deriving key from passphrase: {err}"))?;
+    Ok(XChaCha20Poly1305::new(&key.into()))
+}
+
+fn wrap_with_passphrase(key: &DataKey, passphrase: &str) -> Res<KeySlot> {
+    let salt: [u8; SALT_LEN] = rand::random();
+    let nonce: [u8; NONCE_LEN] = rand::random();
+    let wrapped = derive_wrapping_key(passphrase, &salt)?
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: key.as_bytes(),
+                aad: WRAP_AAD,
+            },
+        )
+        .map_err(|_| ferr!("error wrapping data key"))?;
+    let mut wrapped_key = nonce.to_vec();
+    wrapped_key.extend_from_slice(&wrapped);
+    Ok(KeySlot::Passphrase {
+        salt: utils_rs::hash::encode_base58_multibase(salt),
+        wrapped_key: utils_rs::hash::encode_base58_multibase(wrapped_key),
+    })
+}
+
+fn unwrap_with_passphrase(slot: &KeySlot, passphrase: &str) -> Res<DataKey> {
+    let KeySlot::Passphrase { salt, wrapped_key } = slot else {
+        eyre::bail!("data key isn't wrapped under a passphrase");
+    };
+    let salt = utils_rs::hash::decode_base58_multibase(salt).wrap_err("error decoding salt")?;
+    let wrapped_key = utils_rs::hash::decode_base58_multibase(wrapped_key)
+        .wrap_err("error decoding wrapped data key")?;
+    if wrapped_key.len() < NONCE_LEN {
+        eyre::bail!("wrapped data key is truncated");
+    }
+    let (nonce, wrapped) = wrapped_key.split_at(NONCE_LEN);
+    let bytes = derive_wrapping_key(passphrase, &salt)?
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: wrapped,
+                aad: WRAP_AAD,
+            },
+        )
+        .map_err(|_| ferr!("wrong passphrase"))?;
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| ferr!("data key has the wrong length"))?;
+    Ok(DataKey(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passphrase_unwraps_data_key() -> Res<()> {
+        let key = DataKey::generate();
+        let slot = wrap_with_passphrase(&key, "correct horse")?;
+        assert_eq!(unwrap_with_passphrase(&slot, "correct horse")?, key);
+        assert!(unwrap_with_passphrase(&slot, "battery staple").is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn changed_passphrase_unlocks_same_key() -> Res<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let key = DataKey::generate();
+        write_header(
+            temp_dir.path(),
+            &EncryptionHeader {
+                version: HEADER_VERSION,
+                key_slot: wrap_with_passphrase(&key, "old")?,
+            },
+        )
+        .await?;
+        assert!(change_passphrase(temp_dir.path(), "wrong", "new")
+            .await
+            .is_err());
+        change_passphrase(temp_dir.path(), "old", "new").await?;
+
+        let secret_repo = crate::secrets::SecretRepo::boot().await?;
+        let unlocked = unlock(temp_dir.path(), &secret_repo, Some("new")).await;
+        let stale = unlock(temp_dir.path(), &secret_repo, Some("old")).await;
+        secret_repo.stop().await?;
+        assert_eq!(unlocked?, Some(key));
+        assert!(stale.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn enabled_repo_reopens_with_docs_and_blobs() -> Res<()> {
+        use crate::blobs::{BlobUseHints, BlobsRepo, PartitionStoreMembershipWriter};
+        use crate::repo::{RepoCtx, RepoOpenOptions};
+
+        utils_rs::testing::setup_tracing_once();
+        let temp_dir = tempfile::tempdir()?;
+        let device_name = "test-device".to_string();
+        let rcx = RepoCtx::init(
+            temp_dir.path(),
+            RepoOpenOptions::default(),
+            device_name.clone(),
+            device_name.clone(),
+        )
+        .await?;
+        let doc_app_id = rcx.doc_app.document_id();
+        let blobs = BlobsRepo::new(
+            rcx.layout.blobs_root.clone(),
+            rcx.local_user_path.clone(),
+            Arc::new(PartitionStoreMembershipWriter::new(Arc::clone(
+                &rcx.part_store,
+            ))),
+        )
+        .await?;
+        let blob_id = blobs.put(b"blob before enable", BlobUseHints::Docs).await?;
+        blobs.shutdown().await?;
+        let layout = rcx.layout.clone();
+        rcx.shutdown().await?;
+
+        enable(
+            temp_dir.path(),
+            KeySource::Passphrase("correct horse".into()),
+        )
+        .await?;
+        assert!(!tokio::fs::try_exists(layout.samod_root.join("subduction")).await?);
+        assert!(!tokio::fs::try_exists(layout.blobs_root.join("iroh")).await?);
+        assert!(
+            enable(temp_dir.path(), KeySource::Passphrase("again".into()))
+                .await
+                .is_err()
+        );
+
+        assert!(RepoCtx::open(
+            temp_dir.path(),
+            RepoOpenOptions::default(),
+            device_name.clone()
+        )
+        .await
+        .is_err());
+        let rcx = RepoCtx::open(
+            temp_dir.path(),
+            RepoOpenOptions {
+                passphrase: Some("correct horse".into()),
+            },
+            device_name,
+        )
+        .await?;
+        assert_eq!(rcx.doc_app.document_id(), doc_app_id);
+        let blobs = BlobsRepo::new_keyed(
+            rcx.layout.blobs_root.clone(),
+            rcx.local_user_path.clone(),
+            Arc::new(PartitionStoreMembershipWriter::new(Arc::clone(
+                &rcx.part_store,
+            ))),
+            rcx.data_key.clone(),
+        )
+        .await?;
+        let blob_path = blobs.get_path(blob_id).await?;
+        assert_eq!(tokio::fs::read(blob_path).await?, b"blob before enable");
+        blobs.shutdown().await?;
+        rcx.shutdown().await?;
+        Ok(())
+    }
+}
@@ -28,6 +28,17 @@ use wflow::{
     },
 };
 
+/// How long a dispatch id keeps resolving to the wflow job that claimed it.
+const DISPATCH_IDEMPOTENCY_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Submissions of the same dispatch collapse onto one wflow job.
+fn dispatch_job_idempotency(dispatch_id: &str) -> wflow::JobIdempotency {
+    wflow::JobIdempotency {
+        key: dispatch_id.into(),
+        ttl: DISPATCH_IDEMPOTENCY_TTL,
+    }
+}
+
 pub mod dispatch;
 pub mod init;
 pub mod switch;
@@ -69,6 +80,9 @@ struct ResolvedStatelessViewProvider {
 pub struct RtConfig {
     pub device_id: String,
     pub startup_progress_task_id: Option<String>,
+    /// Restricts triage to these `{plug_id}/{processor_name}` ids.
+    /// `None` runs every processor.
+    pub processor_filter: Option<HashSet<String>>,
 }
 
 pub struct Rt {
@@ -95,6 +109,7 @@ pub struct Rt {
     pub doc_facet_set_index_repo: Arc<DocFacetSetIndexRepo>,
     pub doc_facet_ref_index_repo: Arc<DocFacetRefIndexRepo>,
     pub sqlite_local_state_repo: Arc<SqliteLocalStateRepo>,
+    pub gc_repo: Arc<crate::gc::GcRepo>,
     local_wflow_part_id: String,
 }
 
@@ -106,6 +121,7 @@ pub struct RtStopToken {
     doc_blobs_index_stop: crate::repos::RepoStopToken,
     doc_facet_set_index_stop: crate::index::DocFacetSetIndexStopToken,
     doc_facet_ref_index_stop: crate::index::DocFacetRefIndexStopToken,
+    gc_stop: crate::repos::RepoStopToken,
 }
 
 impl RtStopToken {
@@ -120,6 +136,13 @@ impl RtStopToken {
             );
         }
 
+        if let Err(err) = self.gc_stop.stop().await {
+            warn!(
+                ?err,
+                "error stopping gc maintenance during shutdown - continuing"
+            );
+        }
+
         if let Err(err) = self.doc_facet_set_index_stop.stop().await {
             warn!(
                 ?err,
@@ -287,7 +310,12 @@ impl Rt {
         )
         .await?;
 
-        let wcx = wflow::Ctx::init(Some(rcx.layout.repo_root.join("wflows.db"))).await?;
+        let wflows_db_key = rcx.data_key.as_ref().map(|key| key.sqlcipher_key());
+        let wcx = wflow::Ctx::init_keyed(
+            Some(rcx.layout.repo_root.join("wflows.db")),
+            wflows_db_key.as_deref(),
+        )
+        .await?;
         Self::emit_startup_progress_status(
             &progress_repo,
             startup_progress_task_id.as_deref(),
@@ -446,12 +474,21 @@ impl Rt {
         )
         .await?;
         let part_log = PartitionLogRef::new(Arc::clone(&wcx.logstore));
-        let wflow_ingress = Arc::new(wflow::ingress::PartitionLogIngress::new(
-            part_log,
-            Arc::clone(&wcx.metastore),
-        ));
+        let wflow_ingress = Arc::new(
+            wflow::ingress::PartitionLogIngress::new(part_log, Arc::clone(&wcx.metastore))
+                .with_working_state(Arc::clone(&wflow_part_state))
+                .with_clock(Arc::clone(&wcx.clock)),
+        );
         let local_wflow_part_id = format!("{}/{part_idx}", config.device_id);
 
+        let gc_repo = crate::gc::GcRepo::load(
+            Arc::clone(&rcx),
+            Arc::clone(&drawer),
+            Arc::clone(&dispatch_repo),
+        )
+        .await?;
+        let gc_stop = gc_repo.spawn_maintenance();
+
         let rt = Arc::new(Self {
             config,
             local_wflow_part_id,
@@ -477,6 +514,7 @@ impl Rt {
             sqlite_local_state_repo,
             config_repo,
             wflow_part_state,
+            gc_repo,
         });
         rt.daybook_plugin.attach_rt(Arc::downgrade(&rt));
 
@@ -552,6 +590,7 @@ impl Rt {
                 doc_blobs_index_stop,
                 doc_facet_set_index_stop,
                 doc_facet_ref_index_stop,
+                gc_stop,
                 wflow_part_handle,
             },
         ))
@@ -1637,12 +1676,18 @@ impl Rt {
         self.dispatch_repo
             .activate_waiting(&dispatch_id, initial_deets)
             .await?;
-        let entry_id = match self
+        let (job_id, entry_id) = match self
             .wflow_ingress
-            .add_job(job_id.clone().into(), &key, job_args_json, None)
+            .add_job(
+                job_id.clone().into(),
+                &key,
+                job_args_json,
+                None,
+                Some(dispatch_job_idempotency(&dispatch_id)),
+            )
             .await
         {
-            Ok(value) => value,
+            Ok(reply) => (reply.job_id.to_string(), reply.entry_id),
             Err(err) => {
                 self.dispatch_repo
                     .complete(dispatch_id.clone(), dispatch::DispatchStatus::Failed)
@@ -1654,7 +1699,7 @@ impl Rt {
         };
         let deets = ActiveDispatchDeets::Wflow {
             wflow_partition_id: Some(self.local_wflow_part_id.clone()),
-            entry_id: Some(entry_id),
+            entry_id,
             plug_id: plug_id.clone(),
             routine_name: routine_name.clone(),
             bundle_name: bundle_name.clone(),
@@ -1669,7 +1714,7 @@ impl Rt {
             let _ = self
                 .wflow_ingress
                 .cancel_job(
-                    Arc::from(job_id.as_ref()),
+                    Arc::from(job_id.as_str()),
                     format!("rollback scheduling for dispatch {dispatch_id}"),
                 )
                 .await;
@@ -2073,26 +2118,38 @@ impl Rt {
                 return Err(ferr!("doc-invoke dispatch is not supported for routines"));
             }
         };
+        // A resubmission of a live dispatch keeps its job id so that the
+        // dispatch id, used as the wflow idempotency key, folds it into the
+        // job that's already queued. Anything else gets a fresh id.
+        let mut live_job = None;
         if let Some(existing) = self.dispatch_repo.get_any(&dispatch_id).await {
-            let can_reuse = serde_json::to_string(&existing.on_success_hooks).expect(ERROR_JSON)
+            let same_wiring = serde_json::to_string(&existing.on_success_hooks).expect(ERROR_JSON)
                 == serde_json::to_string(&on_success_hooks).expect(ERROR_JSON)
                 && existing.waiting_on_dispatch_ids == waiting_on_dispatch_ids;
-            let reuse_status_ok = matches!(
+            let live = matches!(
                 existing.status,
                 dispatch::DispatchStatus::Waiting | dispatch::DispatchStatus::Active
             );
-            if can_reuse && reuse_status_ok {
-                warn!(?dispatch_id, "dispatch already exists with same identity");
-                return Ok(dispatch_id);
-            }
-            if can_reuse && reuse_terminal_on_match {
-                debug!(
-                    ?dispatch_id,
-                    status = ?existing.status,
-                    "skipping terminal dispatch reuse without CommandInvokeReply replay"
-                );
+            let ActiveDispatchDeets::Wflow {
+                wflow_job_id,
+                entry_id,
+                ..
+            } = &existing.deets;
+            match wflow_job_id {
+                Some(wflow_job_id) if same_wiring && live => {
+                    live_job = Some((wflow_job_id.clone(), *entry_id));
+                }
+                _ => {
+                    if same_wiring && reuse_terminal_on_match {
+                        debug!(
+                            ?dispatch_id,
+                            status = ?existing.status,
+                            "skipping terminal dispatch reuse without CommandInvokeReply replay"
+                        );
+                    }
+                    dispatch_id = format!("{dispatch_id}-{}", Uuid::new_v4().bs58());
+                }
             }
-            dispatch_id = format!("{dispatch_id}-{}", Uuid::new_v4().bs58());
         }
 
         let is_waiting = !waiting_on_dispatch_ids.is_empty();
@@ -2103,7 +2160,10 @@ impl Rt {
                 bundle: bundle_name,
             } => {
                 // let fqk = format!("{workload_id}/{key}");
-                let job_id = format!("{dispatch_id}-{id}", id = Uuid::new_v4().bs58());
+                let job_id = match &live_job {
+                    Some((job_id, _)) => job_id.clone(),
+                    None => format!("{dispatch_id}-{id}", id = Uuid::new_v4().bs58()),
+                };
                 let staging_branch_path =
                     daybook_types::doc::BranchPathBuf::from(format!("/tmp/{}", job_id));
 
@@ -2232,17 +2292,27 @@ impl Rt {
                     .clone()
                     .unwrap_or_else(|| serde_json::to_string(&()).expect(ERROR_JSON))
             };
-            let entry_id = match self
+            let (job_id, entry_id) = match self
                 .wflow_ingress
                 .add_job(
                     job_id.clone().into(),
                     wflow_key.as_str(),
                     wflow_args_json,
                     None,
+                    Some(dispatch_job_idempotency(&dispatch_id)),
                 )
                 .await
             {
-                Ok(value) => value,
+                Ok(reply) if reply.deduped => {
+                    debug!(%dispatch_id, job_id = %reply.job_id, "dispatch resolved to existing job");
+                    // deduped before touching the log, keep the entry we
+                    // recorded when the job was first queued
+                    let entry_id = reply
+                        .entry_id
+                        .or_else(|| live_job.as_ref().and_then(|(_, entry_id)| *entry_id));
+                    (reply.job_id.to_string(), entry_id)
+                }
+                Ok(reply) => (reply.job_id.to_string(), reply.entry_id),
                 Err(err) => {
                     if let Err(cleanup_err) = self
                         .dispatch_repo
@@ -2262,7 +2332,7 @@ impl Rt {
             };
             let deets = ActiveDispatchDeets::Wflow {
                 wflow_partition_id: Some(self.local_wflow_part_id.clone()),
-                entry_id: Some(entry_id),
+                entry_id,
                 plug_id: plug_id.into(),
                 routine_name: routine_name.to_string(),
                 bundle_name: bundle_name.clone(),
@@ -2418,6 +2488,34 @@ impl Rt {
         Ok(())
     }
 
+    /// Fails an active dispatch whose wflow job is gone or ended without us
+    /// noticing. Its staging branch is left for gc.
+    pub(crate) async fn fail_stranded_dispatch(&self, dispatch_id: &str, reason: &str) -> Res<()> {
+        self.ensure_rt_live()?;
+        self.progress_repo
+            .add_update(
+                dispatch_id,
+                crate::progress::ProgressUpdate {
+                    at: jiff::Timestamp::now(),
+                    title: None,
+                    deets: crate::progress::ProgressUpdateDeets::Completed {
+                        state: crate::progress::ProgressFinalState::Failed,
+                        message: Some(reason.to_string()),
+                    },
+                },
+            )
+            .await?;
+        self.dispatch_repo
+            .complete(dispatch_id.into(), dispatch::DispatchStatus::Failed)
+            .await?;
+        self.release_waiting_dispatches(dispatch_id, false).await?;
+        Ok(())
+    }
+
+    pub(crate) fn local_wflow_part_id(&self) -> &str {
+        &self.local_wflow_part_id
+    }
+
     /// Wait until a log entry matches the provided condition
     /// The callback receives (entry_id, log_entry) and should return true when the condition is met
     pub async fn wait_for_dispatch_end(
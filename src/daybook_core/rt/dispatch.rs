@@ -509,6 +509,30 @@ impl DispatchRepo {
             .collect()
     }
 
+    /// Staging branches of dispatches that are done with them. Only the
+    /// dispatches of this device are known here so staging branches other
+    /// devices are still working on never show up.
+    pub async fn finished_staging_branches(
+        &self,
+    ) -> Vec<(daybook_types::doc::DocId, daybook_types::doc::BranchPathBuf)> {
+        self.state
+            .lock()
+            .await
+            .dispatches
+            .values()
+            .filter(|dispatch| {
+                matches!(
+                    dispatch.status,
+                    DispatchStatus::Succeeded | DispatchStatus::Failed | DispatchStatus::Cancelled
+                )
+            })
+            .map(|dispatch| {
+                let ActiveDispatchArgs::FacetRoutine(args) = &dispatch.args;
+                (args.doc_id.clone(), args.staging_branch_path.clone())
+            })
+            .collect()
+    }
+
     pub async fn mark_cancelled(&self, id: &str) -> Res<bool> {
         let _transition_guard = self.transition_mutex.lock().await;
         let state = self.state.lock().await;
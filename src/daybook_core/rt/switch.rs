@@ -38,12 +38,12 @@ pub struct SwitchStore {
 }
 
 impl SwitchStore {
-    async fn load(repo_sql: SqlCtx) -> Res<Self> {
+    pub(crate) async fn load(repo_sql: SqlCtx) -> Res<Self> {
         init_schema(&repo_sql).await?;
         Ok(Self { repo_sql })
     }
 
-    async fn get_partition_cursor(&self, partition_id: &str) -> Res<u64> {
+    pub(crate) async fn get_partition_cursor(&self, partition_id: &str) -> Res<u64> {
         let row = sqlx::query("SELECT cursor FROM switch_partition_cursor WHERE partition_id = ?1")
             .bind(partition_id)
             .fetch_optional(&self.repo_sql.write_pool)
@@ -113,6 +113,39 @@ impl SwitchStore {
         Ok(())
     }
 
+    /// Only read on boot, a running worker keeps its own cursor.
+    pub(crate) async fn set_partition_cursor(&self, partition_id: &str, cursor: u64) -> Res<()> {
+        sqlx::query(
+            "UPDATE switch_partition_cursor SET cursor = ?2, updated_at = unixepoch() WHERE partition_id = ?1",
+        )
+        .bind(partition_id)
+        .bind(i64::try_from(cursor).expect("cursor exceeds sqlite INTEGER range"))
+        .execute(&self.repo_sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Branch docs the worker last saw as present, with their doc ids.
+    pub(crate) async fn list_present_doc_states(&self) -> Res<Vec<(String, DocId)>> {
+        let rows: Vec<(String, DocId)> = sqlx::query_as(
+            "SELECT branch_doc_id, doc_id FROM switch_doc_state WHERE present = 1 ORDER BY branch_doc_id",
+        )
+        .fetch_all(&self.repo_sql.read_pool)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Makes the next partition event for the branch doc look like an add.
+    pub(crate) async fn mark_doc_state_absent(&self, branch_doc_id: &str) -> Res<()> {
+        sqlx::query(
+            "UPDATE switch_doc_state SET present = 0, last_heads_json = NULL, updated_at = unixepoch() WHERE branch_doc_id = ?1",
+        )
+        .bind(branch_doc_id)
+        .execute(&self.repo_sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
     async fn get_doc_state_by_branch_doc_id(
         &self,
         branch_doc_id: &str,
@@ -861,6 +894,8 @@ impl SwitchWorker {
             return Ok(true);
         };
         match &**event {
+            // Conflicts don't change which facets the doc has
+            DrawerEvent::FacetConflicts { .. } => Ok(false),
             DrawerEvent::DocDeleted {
                 id,
                 deleted_facet_keys,
@@ -1039,6 +1074,37 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_switch_store_doctor_helpers() -> Res<()> {
+        let store = test_switch_store().await?;
+        let partition_id = "drawer.replicated";
+        let state = SwitchDocState {
+            doc_id: DocId::from("doc-1"),
+            branch_name: "main".into(),
+            present: true,
+            last_heads: Some(ChangeHashSet::default()),
+        };
+        store
+            .commit_partition_event(partition_id, 42, Some("doc-branch-1"), Some(&state))
+            .await?;
+        assert_eq!(
+            store.list_present_doc_states().await?,
+            vec![("doc-branch-1".to_string(), state.doc_id.clone())]
+        );
+
+        store.set_partition_cursor(partition_id, 7).await?;
+        store.mark_doc_state_absent("doc-branch-1").await?;
+        assert_eq!(store.get_partition_cursor(partition_id).await?, 7);
+        assert!(store.list_present_doc_states().await?.is_empty());
+        let loaded = store
+            .get_doc_state_by_branch_doc_id("doc-branch-1")
+            .await?
+            .ok_or_eyre("missing stored doc state")?;
+        assert!(!loaded.present);
+        assert_eq!(loaded.last_heads, None);
+        Ok(())
+    }
+
     struct TestListener {
         name: String,
         calls: StdArc<Mutex<Vec<String>>>,
@@ -1090,7 +1156,8 @@ mod tests {
                 SwitchEvent::Drawer(event) => match &**event {
                     DrawerEvent::DocAdded { origin, .. }
                     | DrawerEvent::DocUpdated { origin, .. }
-                    | DrawerEvent::DocDeleted { origin, .. } => origin.clone(),
+                    | DrawerEvent::DocDeleted { origin, .. }
+                    | DrawerEvent::FacetConflicts { origin, .. } => origin.clone(),
                 },
                 SwitchEvent::Plugs(event) => match &**event {
                     PlugsEvent::PlugAdded { origin, .. }
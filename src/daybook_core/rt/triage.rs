@@ -82,6 +82,11 @@ impl DocProcessorTriageListener {
                     .extend(facet.references.iter().cloned());
             }
             for (processor_name, processor) in &plug.processors {
+                if let Some(filter) = &rt.config.processor_filter {
+                    if !filter.contains(&format!("{plug_id}/{processor_name}")) {
+                        continue;
+                    }
+                }
                 match &processor.deets {
                     ProcessorDeets::DocProcessor {
                         event_predicate,
@@ -379,6 +384,7 @@ impl SwitchSink for DocProcessorTriageListener {
                 DispatchEvent::DispatchAdded { .. } => {}
             },
             SwitchEvent::Drawer(event) => match &**event {
+                DrawerEvent::FacetConflicts { .. } => {}
                 DrawerEvent::DocDeleted {
                     id,
                     deleted_facet_keys,
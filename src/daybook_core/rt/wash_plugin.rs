@@ -355,6 +355,8 @@ pub struct DaybookPlugin {
     sqlite_local_state_repo: Arc<crate::local_state::SqliteLocalStateRepo>,
     config_repo: Arc<crate::config::ConfigRepo>,
     plugs_repo: Arc<crate::plugs::PlugsRepo>,
+    /// The mltools ctx is rebuilt per call, backend health outlives it here.
+    mltools_health: Arc<mltools::BackendHealth>,
     rt: RwLock<Option<std::sync::Weak<crate::rt::Rt>>>,
 }
 
@@ -374,6 +376,7 @@ impl DaybookPlugin {
             sqlite_local_state_repo,
             config_repo,
             plugs_repo,
+            mltools_health: default(),
             rt: default(),
         }
     }
@@ -1,3 +1,5 @@
+use crate::interlude::*;
+
 use wash_runtime::engine::ctx::SharedCtx as SharedWashCtx;
 
 use super::{
@@ -8,6 +10,15 @@ use super::{
 async fn mltools_ctx_from_config_repo(plugin: &DaybookPlugin) -> mltools::Ctx {
     mltools::Ctx {
         config: plugin.config_repo.get_mltools_config().await,
+        health: Arc::clone(&plugin.mltools_health),
+    }
+}
+
+fn call_constraints(
+    constraints: binds_guest::townframe::mltools::routing::CallConstraints,
+) -> mltools::CallConstraints {
+    mltools::CallConstraints {
+        local_only: constraints.local_only,
     }
 }
 
@@ -15,6 +26,7 @@ impl mltools_ocr::Host for SharedWashCtx {
     async fn ocr_image(
         &mut self,
         blob_facet: wasmtime::component::Resource<capabilities::FacetToken>,
+        constraints: binds_guest::townframe::mltools::routing::CallConstraints,
     ) -> wasmtime::Result<Result<mltools_ocr::OcrResult, String>> {
         let blob = match super::caps::get_blob_facet_from_token(self, &blob_facet).await? {
             Ok(value) => value,
@@ -29,7 +41,13 @@ impl mltools_ocr::Host for SharedWashCtx {
 
         let mltools_ctx = mltools_ctx_from_config_repo(&plugin).await;
 
-        let mut results = match mltools::ocr_image(&mltools_ctx, &[image_path]).await {
+        let mut results = match mltools::ocr_image_with(
+            &mltools_ctx,
+            &[image_path],
+            &call_constraints(constraints),
+        )
+        .await
+        {
             Ok(value) => value,
             Err(err) => return Ok(Err(err.to_string())),
         };
@@ -48,6 +66,7 @@ impl mltools_ocr::Host for SharedWashCtx {
                     confidence: region.confidence,
                 })
                 .collect(),
+            backend: result.backend,
         }))
     }
 }
@@ -56,25 +75,31 @@ impl mltools_embed::Host for SharedWashCtx {
     async fn embed_text(
         &mut self,
         text: String,
+        constraints: binds_guest::townframe::mltools::routing::CallConstraints,
     ) -> wasmtime::Result<Result<mltools_embed::EmbedResult, String>> {
         let plugin = DaybookPlugin::from_ctx(self);
         let mltools_ctx = mltools_ctx_from_config_repo(&plugin).await;
 
-        let result = match mltools::embed_text(&mltools_ctx, &text).await {
-            Ok(value) => value,
-            Err(err) => return Ok(Err(err.to_string())),
-        };
+        let result =
+            match mltools::embed_text_with(&mltools_ctx, &text, &call_constraints(constraints))
+                .await
+            {
+                Ok(value) => value,
+                Err(err) => return Ok(Err(err.to_string())),
+            };
 
         Ok(Ok(mltools_embed::EmbedResult {
             vector: result.vector,
             dimensions: result.dimensions,
             model_id: result.model_id,
+            backend: result.backend,
         }))
     }
 
     async fn embed_image(
         &mut self,
         blob_facet: wasmtime::component::Resource<capabilities::FacetToken>,
+        constraints: binds_guest::townframe::mltools::routing::CallConstraints,
     ) -> wasmtime::Result<Result<mltools_embed::EmbedResult, String>> {
         let blob = match super::caps::get_blob_facet_from_token(self, &blob_facet).await? {
             Ok(value) => value,
@@ -106,7 +131,13 @@ impl mltools_embed::Host for SharedWashCtx {
         };
         let mltools_ctx = mltools_ctx_from_config_repo(&plugin).await;
 
-        let result = match mltools::embed_image(&mltools_ctx, &image_path, blob.mime.as_str()).await
+        let result = match mltools::embed_image_with(
+            &mltools_ctx,
+            &image_path,
+            blob.mime.as_str(),
+            &call_constraints(constraints),
+        )
+        .await
         {
             Ok(value) => value,
             Err(err) => return Ok(Err(err.to_string())),
@@ -116,6 +147,7 @@ impl mltools_embed::Host for SharedWashCtx {
             vector: result.vector,
             dimensions: result.dimensions,
             model_id: result.model_id,
+            backend: result.backend,
         }))
     }
 }
@@ -143,15 +175,24 @@ fn extension_for_blob_mime(mime: &str) -> Result<&'static str, String> {
 }
 
 impl mltools_llm_chat::Host for SharedWashCtx {
-    async fn llm_chat(&mut self, text: String) -> wasmtime::Result<Result<String, String>> {
+    async fn llm_chat(
+        &mut self,
+        text: String,
+        constraints: binds_guest::townframe::mltools::routing::CallConstraints,
+    ) -> wasmtime::Result<Result<mltools_llm_chat::LlmChatResult, String>> {
         let plugin = DaybookPlugin::from_ctx(self);
         let mltools_ctx = mltools_ctx_from_config_repo(&plugin).await;
 
-        let result = match mltools::llm_chat(&mltools_ctx, &text).await {
-            Ok(value) => value,
-            Err(err) => return Ok(Err(err.to_string())),
-        };
-        Ok(Ok(result.text))
+        let result =
+            match mltools::llm_chat_with(&mltools_ctx, &text, &call_constraints(constraints)).await
+            {
+                Ok(value) => value,
+                Err(err) => return Ok(Err(err.to_string())),
+            };
+        Ok(Ok(mltools_llm_chat::LlmChatResult {
+            text: result.text,
+            backend: result.backend,
+        }))
     }
 
     async fn llm_chat_multimodal(
@@ -159,18 +200,27 @@ impl mltools_llm_chat::Host for SharedWashCtx {
         prompt: String,
         image_bytes: Vec<u8>,
         image_mime: String,
-    ) -> wasmtime::Result<Result<String, String>> {
+        constraints: binds_guest::townframe::mltools::routing::CallConstraints,
+    ) -> wasmtime::Result<Result<mltools_llm_chat::LlmChatResult, String>> {
         let plugin = DaybookPlugin::from_ctx(self);
         let mltools_ctx = mltools_ctx_from_config_repo(&plugin).await;
 
-        let result =
-            match mltools::llm_chat_multimodal(&mltools_ctx, &prompt, &image_bytes, &image_mime)
-                .await
-            {
-                Ok(value) => value,
-                Err(err) => return Ok(Err(err.to_string())),
-            };
-        Ok(Ok(result.text))
+        let result = match mltools::llm_chat_multimodal_with(
+            &mltools_ctx,
+            &prompt,
+            &image_bytes,
+            &image_mime,
+            &call_constraints(constraints),
+        )
+        .await
+        {
+            Ok(value) => value,
+            Err(err) => return Ok(Err(err.to_string())),
+        };
+        Ok(Ok(mltools_llm_chat::LlmChatResult {
+            text: result.text,
+            backend: result.backend,
+        }))
     }
 }
 
@@ -12,6 +12,8 @@ pub struct SecretRepo {
 
 impl SecretRepo {
     const KEYRING_USERNAME: &'static str = "iroh_secret_key_v1";
+    const VAULT_KEY_KEYRING_USERNAME: &'static str = "vault_key_v1";
+    const DATA_KEY_KEYRING_USERNAME: &'static str = "data_key_v1";
 
     fn spawn_drop_thread<T: Send + 'static>(value: T) -> std::thread::JoinHandle<()> {
         std::thread::spawn(move || drop(value))
@@ -141,6 +143,95 @@ impl SecretRepo {
         .expect(ERROR_TOKIO)
     }
 
+    /// The vault key this checkout seals with for storage peers.
+    pub async fn load_vault_key(&self, checkout_id: &str) -> Res<Option<crate::sync::VaultKey>> {
+        let store = Arc::clone(self.store.as_ref().expect(ERROR_IMPOSSIBLE));
+        let user = format!(
+            "daybook.checkout.{checkout_id}.{}",
+            Self::VAULT_KEY_KEYRING_USERNAME
+        );
+        tokio::task::spawn_blocking(move || {
+            let entry = store
+                .build("daybook", &user, None)
+                .wrap_err("failed to create keyring entry")?;
+            match entry.get_password() {
+                Err(keyring_core::Error::NoEntry) => Ok(None),
+                Err(err) => Err(eyre::eyre!(err)).wrap_err("failed reading vault key from keyring"),
+                Ok(secret) => crate::sync::VaultKey::from_base58(&secret).map(Some),
+            }
+        })
+        .await
+        .expect(ERROR_TOKIO)
+    }
+
+    pub async fn set_vault_key(&self, checkout_id: &str, key: &crate::sync::VaultKey) -> Res<()> {
+        let store = Arc::clone(self.store.as_ref().expect(ERROR_IMPOSSIBLE));
+        let user = format!(
+            "daybook.checkout.{checkout_id}.{}",
+            Self::VAULT_KEY_KEYRING_USERNAME
+        );
+        let secret = key.to_base58();
+        tokio::task::spawn_blocking(move || {
+            let entry = store
+                .build("daybook", &user, None)
+                .wrap_err("failed to create keyring entry")?;
+            entry
+                .set_password(&secret)
+                .wrap_err("failed setting vault key in keyring")
+        })
+        .await
+        .expect(ERROR_TOKIO)
+    }
+
+    /// Data key of an encrypted repo. Keyed by the id from the repo's
+    /// encryption header since the checkout id is only readable after
+    /// unlocking.
+    pub async fn load_data_key(
+        &self,
+        key_id: &str,
+    ) -> Res<Option<crate::repo::encryption::DataKey>> {
+        let store = Arc::clone(self.store.as_ref().expect(ERROR_IMPOSSIBLE));
+        let user = format!(
+            "daybook.repo_key.{key_id}.{}",
+            Self::DATA_KEY_KEYRING_USERNAME
+        );
+        tokio::task::spawn_blocking(move || {
+            let entry = store
+                .build("daybook", &user, None)
+                .wrap_err("failed to create keyring entry")?;
+            match entry.get_password() {
+                Err(keyring_core::Error::NoEntry) => Ok(None),
+                Err(err) => Err(eyre::eyre!(err)).wrap_err("failed reading data key from keyring"),
+                Ok(secret) => crate::repo::encryption::DataKey::from_base58(&secret).map(Some),
+            }
+        })
+        .await
+        .expect(ERROR_TOKIO)
+    }
+
+    pub async fn set_data_key(
+        &self,
+        key_id: &str,
+        key: &crate::repo::encryption::DataKey,
+    ) -> Res<()> {
+        let store = Arc::clone(self.store.as_ref().expect(ERROR_IMPOSSIBLE));
+        let user = format!(
+            "daybook.repo_key.{key_id}.{}",
+            Self::DATA_KEY_KEYRING_USERNAME
+        );
+        let secret = key.to_base58();
+        tokio::task::spawn_blocking(move || {
+            let entry = store
+                .build("daybook", &user, None)
+                .wrap_err("failed to create keyring entry")?;
+            entry
+                .set_password(&secret)
+                .wrap_err("failed setting data key in keyring")
+        })
+        .await
+        .expect(ERROR_TOKIO)
+    }
+
     pub async fn stop(mut self) -> Res<()> {
         let store = self.store.take().expect(ERROR_IMPOSSIBLE);
         tokio::task::spawn_blocking(move || Self::drop_off_runtime(store))
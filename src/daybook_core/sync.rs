@@ -18,8 +18,33 @@ use crate::index::DocBlobsIndexRepo;
 use crate::progress::ProgressRepo;
 use crate::repo::RepoCtx;
 
+mod access;
 mod bootstrap;
 pub use bootstrap::*;
+mod bundle;
+pub use bundle::SyncBundleReport;
+pub mod handshake;
+pub use handshake::{Incompatibility, PeerSession, SyncHello};
+mod lan;
+pub use lan::{
+    bind_lan_discovery_socket, LanDiscoveryOpts, PairingCandidate, LAN_DISCOVERY_MULTICAST_GROUP,
+    LAN_DISCOVERY_PORT,
+};
+mod profile;
+pub use profile::{BlobSyncDecision, BlobSyncPolicy, SyncProfile};
+mod revocation;
+mod status;
+pub use revocation::{
+    revoke_device, rotate_identity, DeviceRevocation, DeviceTrust, IdentityRotation,
+};
+#[cfg(test)]
+pub(crate) use status::SyncStatusStore;
+pub use status::{read_sync_status, LagSample, PartitionSyncStatus, PeerSyncStatus};
+mod vault;
+pub use vault::{
+    enable_vault, import_vault_key, read_vault_key, StoragePeer, StoragePeerOpts,
+    StoragePeerStopToken, VaultKey, SEALED_STORAGE_ALPN, VAULT_SEALED_PARTITION_ID,
+};
 #[cfg(test)]
 mod tests;
 
@@ -28,8 +53,13 @@ pub const IROH_CLONE_URL_SCHEME: &str = "db+iroh-clone";
 pub const PARTITION_SYNC_ALPN: &[u8] = b"townframe/partition-sync/0";
 pub const REPO_SYNC_ALPN: &[u8] = b"townframe/repo-sync/0";
 pub const CLONE_PROVISION_ALPN: &[u8] = b"townframe/clone-provision/0";
+/// Bump on any wire change to [`CloneProvisionRpc`] or the types it carries.
+pub const CLONE_PROVISION_RPC_VERSION: u32 = 1;
+/// Oldest [`CLONE_PROVISION_RPC_VERSION`] this build can still talk to.
+pub const CLONE_PROVISION_RPC_MIN_VERSION: u32 = 1;
 pub const CORE_DOCS_PARTITION_ID: &str = "core.docs";
 pub(crate) const BLOBS_BACKEND_ID: &str = "blobs";
+pub(crate) const DOC_BLOBS_BACKEND_ID: &str = "blobs.docs";
 
 pub type PeerKey = Arc<str>;
 
@@ -94,9 +124,70 @@ where
     }
 }
 
+type IncomingAllowList = Arc<std::sync::RwLock<Option<HashSet<EndpointId>>>>;
+type RefusedEndpoints = Arc<std::sync::RwLock<HashSet<EndpointId>>>;
+
+/// Rejects connections from revoked endpoints, from endpoints outside
+/// the allow-list and from endpoints that failed the version handshake
+/// before handing them to the wrapped protocol.
+#[derive(educe::Educe)]
+#[educe(Debug)]
+struct PeerGate<P> {
+    inner: P,
+    #[educe(Debug(ignore))]
+    allow_list: IncomingAllowList,
+    #[educe(Debug(ignore))]
+    refused: RefusedEndpoints,
+    /// `None` for the handshake itself so that peers get to retry it
+    /// once updated.
+    #[educe(Debug(ignore))]
+    sessions: Option<handshake::PeerSessions>,
+}
+
+impl<P: ProtocolHandler> ProtocolHandler for PeerGate<P> {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        let remote_id = conn.remote_id();
+        if self.refused.read().expect(ERROR_MUTEX).contains(&remote_id) {
+            debug!(%remote_id, "rejecting connection from revoked endpoint");
+            conn.close(0u32.into(), b"revoked");
+            return Err(AcceptError::from_boxed(
+                ferr!("endpoint {remote_id} has been revoked").into(),
+            ));
+        }
+        let allowed = match &*self.allow_list.read().expect(ERROR_MUTEX) {
+            Some(allow_list) => allow_list.contains(&remote_id),
+            None => true,
+        };
+        if !allowed {
+            debug!(%remote_id, "rejecting connection from endpoint outside allow-list");
+            conn.close(0u32.into(), b"not allowed");
+            return Err(AcceptError::from_boxed(
+                ferr!("endpoint {remote_id} is not in the allow-list").into(),
+            ));
+        }
+        if let Some(sessions) = &self.sessions {
+            if handshake::is_incompatible(sessions, &remote_id) {
+                debug!(%remote_id, "rejecting connection from incompatible endpoint");
+                conn.close(0u32.into(), b"update required");
+                return Err(AcceptError::from_boxed(
+                    ferr!("endpoint {remote_id} speaks an incompatible protocol version").into(),
+                ));
+            }
+        }
+        self.inner.accept(conn).await
+    }
+
+    async fn shutdown(&self) {
+        self.inner.shutdown().await
+    }
+}
+
 enum ActivePeerState {
     Connecting,
-    Connected { peer_key: PeerKey },
+    Connected {
+        peer_key: PeerKey,
+        big_sync_rpc_client: Arc<big_sync::rpc::IrohBigSyncRpcClient>,
+    },
 }
 
 pub struct IrohSyncRepo {
@@ -107,7 +198,11 @@ pub struct IrohSyncRepo {
     router: iroh::protocol::Router,
 
     config_repo: Arc<crate::config::ConfigRepo>,
+    blobs_repo: Arc<BlobsRepo>,
     blobs_sync_backend: Arc<crate::blobs::sync::BlobSyncBackend>,
+    doc_blobs_sync_backend: Arc<crate::blobs::sync::BlobSyncBackend>,
+    sealed_store: Arc<vault::SealedStore>,
+    sealed_sync_backend: Arc<vault::SealedSyncBackend>,
     _doc_blobs_index_repo: Arc<DocBlobsIndexRepo>,
     progress_repo: Option<Arc<ProgressRepo>>,
 
@@ -115,9 +210,21 @@ pub struct IrohSyncRepo {
     active_peers: tokio::sync::RwLock<HashMap<PeerId, ActivePeerState>>,
     // sync_store: am_utils_rs::sync::store::SyncStoreHandle,
     reconnect_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    lan_discovery_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    status_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    vault_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    pairing_candidates: std::sync::Mutex<HashMap<EndpointId, PairingCandidate>>,
     big_sync_worker: big_sync::BigSyncWorkerHandle,
-    _big_sync_rpc: big_sync::rpc::BigSyncRpcHandle,
+    big_sync_rpc: big_sync::rpc::BigSyncRpcHandle,
     repo_sync_backend: Arc<big_repo::BigRepoSyncBackend>,
+    sync_profile: std::sync::Mutex<SyncProfile>,
+    incoming_allow_list: IncomingAllowList,
+    refused_endpoints: RefusedEndpoints,
+    peer_sessions: handshake::PeerSessions,
+    peer_access: Arc<access::PeerAccessPolicy>,
+    /// Objects synced with each peer since it was last fully synced.
+    synced_since_full_sync: std::sync::Mutex<HashMap<PeerId, usize>>,
+    sync_status: status::SyncStatusStore,
 }
 
 #[derive(Debug, Clone)]
@@ -162,23 +269,45 @@ pub enum IrohSyncEvent {
     StalePeer {
         peer_key: PeerKey,
     },
+    /// An unknown device of this repo announced itself on the local network.
+    PairingCandidateDiscovered {
+        peer_key: PeerKey,
+        device_name: String,
+    },
+    /// The peer speaks a sync protocol version we can't talk to. Nothing
+    /// is synced with it until one side updates.
+    UpdateRequired {
+        peer_key: PeerKey,
+        protocol: String,
+        /// Set when it's this device that's out of date.
+        local_needs_update: bool,
+        remote_app_version: String,
+    },
 }
 
 pub struct IrohSyncRepoStopToken {
     cancel_token: CancellationToken,
     worker_handle: JoinHandle<()>,
     reconnect_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    lan_discovery_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    status_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
+    vault_task: Arc<std::sync::Mutex<Option<JoinHandle<()>>>>,
     router: iroh::protocol::Router,
     // partition_sync_stop_token: am_utils_rs::sync::node::SyncNodeStopToken,
     big_repo_rpc_stop_token: big_repo::rpc::BigRepoRpcStopToken,
     big_sync_rpc_stop: big_sync::rpc::BigSyncRpcStopToken,
     big_sync_worker_stop: big_sync::StopToken,
+    blobs_repo: Arc<BlobsRepo>,
+    big_repo: SharedBigRepo,
     // partition_sync_store_stop_token: am_utils_rs::sync::store::SyncStoreStopToken,
 }
 
 impl IrohSyncRepoStopToken {
     pub async fn stop(self) -> Res<()> {
         self.cancel_token.cancel();
+        // the fetcher holds on to the blob sync backend which holds the repo
+        self.blobs_repo.set_fetcher(None);
+        self.big_repo.set_fetcher(None);
         let reconnect_handle = self.reconnect_task.lock().expect(ERROR_MUTEX).take();
         if let Some(handle) = reconnect_handle {
             utils_rs::wait_on_handle_with_timeout(
@@ -187,6 +316,30 @@ impl IrohSyncRepoStopToken {
             )
             .await?;
         }
+        let lan_discovery_handle = self.lan_discovery_task.lock().expect(ERROR_MUTEX).take();
+        if let Some(handle) = lan_discovery_handle {
+            utils_rs::wait_on_handle_with_timeout(
+                handle,
+                utils_rs::scale_timeout(Duration::from_secs(10)),
+            )
+            .await?;
+        }
+        let status_handle = self.status_task.lock().expect(ERROR_MUTEX).take();
+        if let Some(handle) = status_handle {
+            utils_rs::wait_on_handle_with_timeout(
+                handle,
+                utils_rs::scale_timeout(Duration::from_secs(10)),
+            )
+            .await?;
+        }
+        let vault_handle = self.vault_task.lock().expect(ERROR_MUTEX).take();
+        if let Some(handle) = vault_handle {
+            utils_rs::wait_on_handle_with_timeout(
+                handle,
+                utils_rs::scale_timeout(Duration::from_secs(30)),
+            )
+            .await?;
+        }
         // pre light the stop signal to the full worker
         // Worker shutdown drains active repo connections; each connection stop can wait up to 5s.
         utils_rs::wait_on_handle_with_timeout(
@@ -236,6 +389,17 @@ impl IrohSyncRepo {
             Arc::clone(&rcx.part_store),
             endpoint.clone(),
         ));
+        let doc_blobs_sync_backend =
+            Arc::new(blobs_sync_backend.profiled(Arc::clone(&doc_blobs_index_repo) as _));
+        blobs_repo.set_fetcher(Some(Arc::new(crate::blobs::sync::PeerBlobFetcher::new(
+            Arc::clone(&blobs_sync_backend),
+            Arc::clone(&doc_blobs_index_repo) as _,
+            progress_repo.clone(),
+        ))));
+        let blob_cache_config = crate::repo::globals::get_blob_cache_config(&rcx.sql).await?;
+        blobs_repo
+            .set_cache_quota(blob_cache_config.quota_bytes)
+            .await?;
 
         let cancel_token = CancellationToken::new();
 
@@ -251,56 +415,137 @@ impl IrohSyncRepo {
                 .await
                 .wrap_err("failed booting big repo sync backend")?,
         );
+        if crate::repo::globals::get_sync_config(&rcx.sql)
+            .await?
+            .fetch_docs_on_demand
+        {
+            rcx.big_repo
+                .set_fetcher(Some(Arc::clone(&repo_sync_backend) as _));
+        }
+        let sealed_store =
+            Arc::new(vault::SealedStore::load(rcx.sql.clone(), Arc::clone(&rcx.part_store)).await?);
+        let sealed_sync_backend = Arc::new(vault::SealedSyncBackend::new(
+            Arc::clone(&sealed_store),
+            endpoint.clone(),
+        ));
         let blob_sync_backend: Arc<dyn big_sync::SyncBackend> =
             Arc::clone(&blobs_sync_backend) as _;
         let mut sync_backends = std::collections::HashMap::new();
         sync_backends.insert(BLOBS_BACKEND_ID.into(), blob_sync_backend);
+        sync_backends.insert(
+            DOC_BLOBS_BACKEND_ID.into(),
+            Arc::clone(&doc_blobs_sync_backend) as _,
+        );
         sync_backends.insert(
             big_repo::BigRepo::BACKEND_ID.into(),
             Arc::clone(&repo_sync_backend) as _,
         );
+        sync_backends.insert(
+            vault::SEALED_BACKEND_ID.into(),
+            Arc::clone(&sealed_sync_backend) as _,
+        );
         let (big_sync_worker, big_sync_worker_stop) =
             big_sync::spawn_big_sync_worker(Arc::clone(&rcx.part_store), sync_backends)?;
 
         let (big_sync_rpc, big_sync_rpc_stop) =
             big_sync::rpc::spawn_big_sync_rpc(Arc::clone(&rcx.part_store)).await?;
 
+        let peer_access = Arc::new(access::PeerAccessPolicy::default());
+        big_sync_rpc.set_access_policy(Some(Arc::clone(&peer_access) as _));
+        rcx.big_repo
+            .set_access_policy(Some(Arc::clone(&peer_access) as _));
+
+        let incoming_allow_list: IncomingAllowList = default();
+        let refused_endpoints: RefusedEndpoints = default();
+        let peer_sessions: handshake::PeerSessions = default();
+        let registry = crate::repos::ListenersRegistry::new();
         let router = iroh::protocol::Router::builder(endpoint.clone())
+            .accept(
+                handshake::SYNC_HANDSHAKE_ALPN,
+                PeerGate {
+                    inner: handshake::HandshakeProtocol {
+                        sessions: Arc::clone(&peer_sessions),
+                        registry: Arc::clone(&registry),
+                    },
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: None,
+                },
+            )
             .accept(
                 SUBDUCTION_ALPN,
-                SubductionProtocolHandler {
-                    big_repo: Arc::clone(&rcx.big_repo),
-                    incoming_conn_tx,
-                    end_signal_tx: conn_end_tx.clone(),
+                PeerGate {
+                    inner: SubductionProtocolHandler {
+                        big_repo: Arc::clone(&rcx.big_repo),
+                        incoming_conn_tx,
+                        end_signal_tx: conn_end_tx.clone(),
+                    },
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
                 },
             )
             .accept(
                 big_sync::rpc::BIG_SYNC_RPC_ALPN,
-                irpc_iroh::IrohProtocol::<big_sync::rpc::BigSyncIrpc>::with_sender(
-                    big_sync_rpc.local_sender(),
-                ),
+                PeerGate {
+                    inner: big_sync_rpc.protocol_handler(),
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
+                },
             )
             .accept(
                 big_repo::rpc::REPO_SYNC_ALPN,
-                AuthenticatedIrohProtocol::<big_repo::rpc::RepoSyncRpc, PeerId> {
-                    tx: big_repo_rpc.local_sender(),
-                    peer_key_fn: Arc::new(|endpoint_id| PeerId::new(*endpoint_id.as_bytes())),
+                PeerGate {
+                    inner: AuthenticatedIrohProtocol::<big_repo::rpc::RepoSyncRpc, PeerId> {
+                        tx: big_repo_rpc.local_sender(),
+                        peer_key_fn: Arc::new(|endpoint_id| PeerId::new(*endpoint_id.as_bytes())),
+                    },
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
                 },
             )
             .accept(
                 CLONE_PROVISION_ALPN,
-                // NOTE: we don't use 0Rtt since CloneProvisionRpc requests are not idempotetnt
-                // safe
-                irpc_iroh::IrohProtocol::<bootstrap::CloneProvisionRpc>::with_sender(
-                    clone_rpc_tx.clone(),
-                ),
+                PeerGate {
+                    // NOTE: we don't use 0Rtt since CloneProvisionRpc requests are not idempotetnt
+                    // safe
+                    inner: irpc_iroh::IrohProtocol::<bootstrap::CloneProvisionRpc>::with_sender(
+                        clone_rpc_tx.clone(),
+                    ),
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
+                },
             )
             .accept(
                 iroh_blobs::ALPN,
-                iroh_blobs::BlobsProtocol::new(&blobs, None),
+                PeerGate {
+                    inner: iroh_blobs::BlobsProtocol::new(&blobs, None),
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
+                },
+            )
+            .accept(
+                iroh_docs::ALPN,
+                PeerGate {
+                    inner: docs.clone(),
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
+                },
+            )
+            .accept(
+                iroh_gossip::ALPN,
+                PeerGate {
+                    inner: gossip.clone(),
+                    allow_list: Arc::clone(&incoming_allow_list),
+                    refused: Arc::clone(&refused_endpoints),
+                    sessions: Some(Arc::clone(&peer_sessions)),
+                },
             )
-            .accept(iroh_docs::ALPN, docs.clone())
-            .accept(iroh_gossip::ALPN, gossip.clone())
             .spawn();
 
         config_repo
@@ -308,24 +553,47 @@ impl IrohSyncRepo {
             .await?;
 
         let big_sync_rx = big_sync_worker.subscribe_stats();
+        let sync_status = status::SyncStatusStore::load(rcx.sql.clone()).await?;
 
         let reconnect_task = default();
+        let lan_discovery_task = default();
+        let status_task = default();
+        let vault_task = default();
         let repo = Arc::new(Self {
             rcx,
             router: router.clone(),
             config_repo,
+            blobs_repo: Arc::clone(&blobs_repo),
             blobs_sync_backend,
+            doc_blobs_sync_backend,
+            sealed_store,
+            sealed_sync_backend,
             _doc_blobs_index_repo: doc_blobs_index_repo,
             progress_repo,
             cancel_token: cancel_token.clone(),
-            registry: crate::repos::ListenersRegistry::new(),
+            registry,
             active_peers: default(),
             conn_end_signal_tx: conn_end_tx,
             reconnect_task: Arc::clone(&reconnect_task),
+            lan_discovery_task: Arc::clone(&lan_discovery_task),
+            status_task: Arc::clone(&status_task),
+            vault_task: Arc::clone(&vault_task),
+            pairing_candidates: default(),
             big_sync_worker,
             repo_sync_backend,
-            _big_sync_rpc: big_sync_rpc, // active_endpoint_ids: tokio::sync::RwLock::new(HashMap::new()),
+            sync_profile: default(),
+            incoming_allow_list,
+            refused_endpoints,
+            peer_sessions,
+            peer_access,
+            synced_since_full_sync: default(),
+            sync_status,
+            big_sync_rpc, // active_endpoint_ids: tokio::sync::RwLock::new(HashMap::new()),
         });
+        repo.apply_sync_profile().await?;
+        repo.apply_device_trust().await?;
+        repo.apply_peer_access().await?;
+        repo.apply_vault_key().await?;
         #[cfg(test)]
         bootstrap::register_test_clone_rpc_sender(router.endpoint().id(), clone_rpc_tx.clone())
             .await;
@@ -352,10 +620,15 @@ impl IrohSyncRepo {
                 cancel_token,
                 worker_handle,
                 reconnect_task,
+                lan_discovery_task,
+                status_task,
+                vault_task,
                 router,
                 big_repo_rpc_stop_token: repo_rpc_stop_token,
                 big_sync_rpc_stop,
                 big_sync_worker_stop,
+                blobs_repo,
+                big_repo: Arc::clone(&rcx.big_repo),
             },
         ))
     }
@@ -367,34 +640,162 @@ impl IrohSyncRepo {
         Ok(())
     }
 
-    fn peer_partition_ids(&self, _peer_key: &str) -> HashMap<PartId, BackendId> {
-        let repo_backend_id = big_repo::BigRepo::BACKEND_ID.into();
-        let blob_backend_id = BLOBS_BACKEND_ID.into();
-        [
+    /// Every partition this repo syncs along with its label and backend.
+    fn known_partitions(&self) -> Vec<(&'static str, PartId, BackendId)> {
+        let repo_backend_id: BackendId = big_repo::BigRepo::BACKEND_ID.into();
+        vec![
             (
+                CORE_DOCS_PARTITION_ID,
                 crate::part_id_from_label(CORE_DOCS_PARTITION_ID),
                 Arc::clone(&repo_backend_id),
             ),
             (
+                crate::drawer::DRAWER_REPLICATED_PARTITION_PREFIX,
                 crate::drawer::DrawerRepo::replicated_partition_id_for_drawer(
                     &self.rcx.doc_drawer.document_id(),
                 ),
-                Arc::clone(&repo_backend_id),
+                repo_backend_id,
             ),
             // (
+            //     crate::rt::PROCESSOR_RUNLOG_PARTITION_ID,
             //     crate::part_id_from_label(crate::rt::PROCESSOR_RUNLOG_PARTITION_ID),
             //     repo_backend_id,
             // ),
             (
+                crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID,
                 crate::part_id_from_label(crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID),
-                Arc::clone(&blob_backend_id),
+                DOC_BLOBS_BACKEND_ID.into(),
             ),
             (
+                crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID,
                 crate::part_id_from_label(crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID),
-                blob_backend_id,
+                BLOBS_BACKEND_ID.into(),
+            ),
+            (
+                VAULT_SEALED_PARTITION_ID,
+                self.sealed_store.part_id(),
+                vault::SEALED_BACKEND_ID.into(),
             ),
         ]
-        .into()
+    }
+
+    fn partition_label(&self, part_id: PartId) -> String {
+        self.known_partitions()
+            .into_iter()
+            .find(|(_, known, _)| *known == part_id)
+            .map(|(label, ..)| label.to_string())
+            .unwrap_or_else(|| part_id.to_string())
+    }
+
+    fn current_sync_profile(&self) -> SyncProfile {
+        self.sync_profile.lock().expect(ERROR_MUTEX).clone()
+    }
+
+    fn peer_partition_ids(&self, _peer_key: &str) -> HashMap<PartId, BackendId> {
+        let profile = self.current_sync_profile();
+        self.known_partitions()
+            .into_iter()
+            // only synced with storage peers
+            .filter(|(label, ..)| *label != VAULT_SEALED_PARTITION_ID)
+            .filter(|(label, ..)| profile.subscribes_to(label))
+            .map(|(_, part_id, backend_id)| (part_id, backend_id))
+            .collect()
+    }
+
+    /// The subscribed partitions narrowed down to what the peer is
+    /// allowed to see.
+    fn partition_ids_for_peer(&self, peer_id: PeerId) -> HashMap<PartId, BackendId> {
+        use big_sync::rpc::PartAccessPolicy;
+        if self.peer_access.is_storage_peer(peer_id) {
+            // without the vault key there's nothing to seal or open
+            if self.sealed_sync_backend.vault().is_none() {
+                return default();
+            }
+            return [(self.sealed_store.part_id(), vault::SEALED_BACKEND_ID.into())].into();
+        }
+        let mut partition_ids = self.peer_partition_ids("");
+        partition_ids.retain(|part_id, _| self.peer_access.can_read(peer_id, *part_id));
+        partition_ids
+    }
+
+    /// Loads the known devices' access from the config and pushes it to
+    /// the rpc handlers and the sync backend.
+    async fn apply_peer_access(&self) -> Res<()> {
+        let devices = self.config_repo.list_known_sync_devices().await?;
+        let part_labels = self
+            .known_partitions()
+            .into_iter()
+            .map(|(label, part_id, _)| (part_id, label))
+            .collect();
+        let read_only = self.peer_access.update(part_labels, &devices);
+        self.rcx.big_repo.set_read_only_peers(read_only.clone());
+        self.repo_sync_backend.set_read_only_peers(read_only);
+        Ok(())
+    }
+
+    /// Loads the local device's profile from the config and pushes it to the
+    /// sync backends. Returns true if the profile changed.
+    async fn apply_sync_profile(&self) -> Res<bool> {
+        let profile = self
+            .config_repo
+            .get_sync_profile(&self.router.endpoint().id())
+            .await
+            .unwrap_or_default();
+        {
+            let mut current = self.sync_profile.lock().expect(ERROR_MUTEX);
+            let unchanged = serde_json::to_value(&*current).expect(ERROR_JSON)
+                == serde_json::to_value(&profile).expect(ERROR_JSON);
+            if unchanged {
+                return Ok(false);
+            }
+            *current = profile.clone();
+        }
+        self.repo_sync_backend
+            .set_admission_filter(profile.doc_predicate.is_some().then(|| {
+                Arc::new(profile::ProfileDocAdmissionFilter {
+                    profile: profile.clone(),
+                }) as Arc<dyn big_repo::DocAdmissionFilter>
+            }));
+        self.doc_blobs_sync_backend.set_profile(profile);
+        Ok(true)
+    }
+
+    async fn refresh_sync_profile(&self) -> Res<()> {
+        if self.apply_sync_profile().await? {
+            self.resubscribe_active_peers().await?;
+            let readmitted = self.repo_sync_backend.readmit_rejected().await?;
+            if !readmitted.is_empty() {
+                debug!(
+                    count = readmitted.len(),
+                    "readmitted docs after profile change"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-subscribes connected peers to the partitions of the current profile.
+    async fn resubscribe_active_peers(&self) -> Res<()> {
+        let peers = self
+            .active_peers
+            .read()
+            .await
+            .iter()
+            .filter_map(|(peer_id, state)| match state {
+                ActivePeerState::Connected {
+                    big_sync_rpc_client,
+                    ..
+                } => Some((*peer_id, Arc::clone(big_sync_rpc_client))),
+                ActivePeerState::Connecting => None,
+            })
+            .collect::<Vec<_>>();
+        for (peer_id, big_sync_rpc_client) in peers {
+            let partition_ids = self.partition_ids_for_peer(peer_id);
+            self.big_sync_worker
+                .set_peer(peer_id, big_sync_rpc_client as _, partition_ids)
+                .await?;
+        }
+        Ok(())
     }
 
     async fn spawn_connect_known_devices_once(self: &Arc<Self>, trigger: &'static str) {
@@ -430,6 +831,161 @@ impl IrohSyncRepo {
         *reconnect_task = Some(handle);
     }
 
+    /// Failing to keep the status up to date shouldn't get in the way of
+    /// syncing itself.
+    fn note_status_res(&self, res: Res<()>) {
+        if let Err(err) = res {
+            warn!(?err, "failed recording sync status");
+        }
+    }
+
+    fn spawn_sample_sync_status(self: &Arc<Self>) {
+        let Ok(mut status_task) = self.status_task.try_lock() else {
+            return;
+        };
+        if let Some(existing) = status_task.as_ref() {
+            if !existing.is_finished() {
+                return;
+            }
+        }
+        let repo = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            let _ = repo
+                .cancel_token
+                .clone()
+                .run_until_cancelled(async move {
+                    if let Err(err) = repo.sample_sync_status().await {
+                        if !repo.cancel_token.is_cancelled() {
+                            warn!(?err, "sync status sampling failed");
+                        }
+                    }
+                })
+                .await;
+        });
+        *status_task = Some(handle);
+    }
+
+    fn spawn_sync_vault(self: &Arc<Self>) {
+        if self.sealed_sync_backend.vault().is_none() {
+            return;
+        }
+        let Ok(mut vault_task) = self.vault_task.try_lock() else {
+            return;
+        };
+        if let Some(existing) = vault_task.as_ref() {
+            if !existing.is_finished() {
+                return;
+            }
+        }
+        let repo = Arc::clone(self);
+        let handle = tokio::spawn(async move {
+            let _ = repo
+                .cancel_token
+                .clone()
+                .run_until_cancelled(async move {
+                    if let Err(err) = repo.sync_vault().await {
+                        if !repo.cancel_token.is_cancelled() {
+                            warn!(?err, "vault sync failed");
+                        }
+                    }
+                })
+                .await;
+        });
+        *vault_task = Some(handle);
+    }
+
+    /// Flushes the transfer counters and measures how far behind each
+    /// connected peer we are on every partition.
+    async fn sample_sync_status(&self) -> Res<()> {
+        let now = Timestamp::now();
+        let mut bytes: HashMap<PeerId, (u64, u64)> = default();
+        for (peer_id, received) in self.blobs_sync_backend.take_bytes_received() {
+            bytes.entry(peer_id).or_default().0 += received;
+        }
+        for (peer_id, sent) in self.big_sync_rpc.take_bytes_served() {
+            bytes.entry(peer_id).or_default().1 += sent;
+        }
+        for (peer_id, (received, sent)) in bytes {
+            let peer_key = daybook_types::doc::format_peer_key(peer_id.as_bytes());
+            self.sync_status
+                .add_bytes(&peer_key, received, sent)
+                .await?;
+        }
+
+        let connected = self
+            .active_peers
+            .read()
+            .await
+            .iter()
+            .filter_map(|(peer_id, state)| match state {
+                ActivePeerState::Connected {
+                    peer_key,
+                    big_sync_rpc_client,
+                } => Some((
+                    *peer_id,
+                    Arc::clone(peer_key),
+                    Arc::clone(big_sync_rpc_client),
+                )),
+                ActivePeerState::Connecting => None,
+            })
+            .collect::<Vec<_>>();
+
+        let mut backlog = self.blobs_sync_backend.blob_backlog();
+        for (peer_id, ..) in &connected {
+            backlog.entry(*peer_id).or_default();
+        }
+        for (peer_id, count) in backlog {
+            let peer_key = daybook_types::doc::format_peer_key(peer_id.as_bytes());
+            self.sync_status.set_blob_backlog(&peer_key, count).await?;
+        }
+
+        for (peer_id, peer_key, client) in connected {
+            if let Err(err) = self.sample_peer_lag(peer_id, &peer_key, &client, now).await {
+                debug!(?err, %peer_key, "failed sampling cursor lag");
+            }
+        }
+        Ok(())
+    }
+
+    async fn sample_peer_lag(
+        &self,
+        peer_id: PeerId,
+        peer_key: &str,
+        client: &big_sync::rpc::IrohBigSyncRpcClient,
+        now: Timestamp,
+    ) -> Res<()> {
+        use big_sync::rpc::HostBigRpcClient;
+        // asked for one by one so that partitions the peer doesn't have
+        // don't keep us from sampling the rest
+        for part_id in self.partition_ids_for_peer(peer_id).into_keys() {
+            let summary = tokio::time::timeout(
+                utils_rs::scale_timeout(Duration::from_secs(10)),
+                client.peer_summary(big_sync_core::rpc::PeerSummaryRequest {
+                    parts: [part_id].into(),
+                }),
+            )
+            .await
+            .map_err(|_| ferr!("timed out asking peer for its summary"))???;
+            let Some(part) = summary.ok().and_then(|mut out| out.parts.remove(&part_id)) else {
+                continue;
+            };
+            let synced = self
+                .rcx
+                .part_store
+                .get_peer_part_cursor(peer_id, part_id)
+                .await?;
+            self.sync_status
+                .record_lag(
+                    peer_key,
+                    &self.partition_label(part_id),
+                    part.latest_cursor.saturating_sub(synced),
+                    now,
+                )
+                .await?;
+        }
+        Ok(())
+    }
+
     async fn machine_loop(
         self: &Arc<Self>,
         mut big_sync_rx: tokio::sync::broadcast::Receiver<big_sync_core::SyncStatEvent>,
@@ -501,17 +1057,32 @@ impl IrohSyncRepo {
                 }
                 _ = reconnect_tick.tick() => {
                     self.spawn_connect_known_devices_once("periodic").await;
+                    self.spawn_sample_sync_status();
+                    self.spawn_sync_vault();
                 }
                 val = config_listener.recv_async() => {
                     match val {
-                        Ok(event) => {
-                            if matches!(
-                                &*event,
-                                crate::config::ConfigEvent::SyncDevicesChanged { .. }
-                            ) {
+                        Ok(event) => match &*event {
+                            crate::config::ConfigEvent::SyncDevicesChanged { .. } => {
+                                let res = async {
+                                    self.apply_peer_access().await?;
+                                    self.resubscribe_active_peers().await
+                                }
+                                .await;
+                                if let Err(err) = res {
+                                    warn!(?err, "failed applying peer access");
+                                }
                                 self.spawn_connect_known_devices_once("config-change").await;
                             }
-                        }
+                            crate::config::ConfigEvent::Changed { .. } => {
+                                if let Err(err) = self.apply_device_trust().await {
+                                    warn!(?err, "failed applying device revocations");
+                                }
+                                if let Err(err) = self.refresh_sync_profile().await {
+                                    warn!(?err, "failed applying sync profile");
+                                }
+                            }
+                        },
                         Err(crate::repos::RecvError::Closed) => {
                             warn!("config listener closed; re-subscribing");
                             config_listener = self
@@ -536,6 +1107,7 @@ impl IrohSyncRepo {
         for peer_id in active_peers {
             self.repo_sync_backend.unregister_remote_peer(peer_id);
             self.blobs_sync_backend.unregister_remote_peer(peer_id);
+            self.sealed_sync_backend.unregister_remote_peer(peer_id);
             self.big_sync_worker.remove_peer(peer_id).await.ok();
         }
         self.active_peers.write().await.clear();
@@ -555,7 +1127,7 @@ impl IrohSyncRepo {
             let events = [IrohSyncEvent::IncomingConnection {
                 peer_key: Arc::clone(&peer_key),
             }];
-            let partition_ids = self.peer_partition_ids(&peer_key);
+            let partition_ids = self.partition_ids_for_peer(peer_id);
             let endpoint = self.router.endpoint().clone();
             let remote_info = endpoint
                 .remote_info(
@@ -576,16 +1148,30 @@ impl IrohSyncRepo {
             self.repo_sync_backend
                 .register_remote_peer(conn.peer_id, addr.clone());
             self.big_sync_worker
-                .set_peer(conn.peer_id, big_sync_rpc_client, partition_ids)
+                .set_peer(
+                    conn.peer_id,
+                    Arc::clone(&big_sync_rpc_client) as _,
+                    partition_ids,
+                )
                 .await?;
 
-            let old = self
-                .active_peers
-                .write()
-                .await
-                .insert(peer_id, ActivePeerState::Connected { peer_key });
+            let old = self.active_peers.write().await.insert(
+                peer_id,
+                ActivePeerState::Connected {
+                    peer_key,
+                    big_sync_rpc_client,
+                },
+            );
             assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy");
 
+            self.note_status_res(
+                self.sync_status
+                    .record_connected(
+                        &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                        Timestamp::now(),
+                    )
+                    .await,
+            );
             self.registry.notify(events);
             eyre::Ok(())
         }
@@ -607,17 +1193,22 @@ impl IrohSyncRepo {
         self.blobs_sync_backend
             .unregister_remote_peer(signal.peer_id);
         self.big_sync_worker.remove_peer(signal.peer_id).await?;
-        let Some(ActivePeerState::Connected { peer_key }) =
+        let Some(ActivePeerState::Connected { peer_key, .. }) =
             self.active_peers.write().await.remove(&signal.peer_id)
         else {
             eyre::bail!("unkown connection disconnected");
         };
+        let conn_err = signal.err.map(|err| format!("conn error: {err}"));
+        if let Some(err) = &conn_err {
+            self.note_status_res(
+                self.sync_status
+                    .record_error(&peer_key, err, Timestamp::now())
+                    .await,
+            );
+        }
         let events = [IrohSyncEvent::ConnectionClosed {
             peer_key,
-            reason: signal
-                .err
-                .map(|err| format!("conn error: {err}"))
-                .unwrap_or_else(|| "natural disconnect".into()),
+            reason: conn_err.unwrap_or_else(|| "natural disconnect".into()),
         }];
 
         self.registry.notify(events);
@@ -663,14 +1254,133 @@ impl IrohSyncRepo {
     }
 
     /// Allow a peer to connect to this node by their endpoint ID.
-    /// Useful for test setups where nodes need to connect in a mesh topology.
-    #[cfg(test)]
+    /// Pairing candidates found on the local network are added to the known
+    /// sync devices and connected to.
     pub async fn allow_peer_by_endpoint_id(&self, endpoint_id: EndpointId) -> Res<()> {
         let _peer_key = daybook_types::doc::format_peer_key(endpoint_id.as_bytes());
         // self.sync_store.allow_peer(peer_key).await
+        let Some(candidate) = self.take_pairing_candidate(&endpoint_id) else {
+            return Ok(());
+        };
+        self.config_repo
+            .upsert_known_sync_device(crate::repo::globals::SyncDeviceEntry {
+                endpoint_id,
+                name: candidate.device_name,
+                added_at: Timestamp::now(),
+                last_connected_at: None,
+                access: default(),
+                storage_peer: false,
+            })
+            .await?;
+        self.connect_endpoint_addr(candidate.endpoint_addr).await
+    }
+
+    /// Restricts incoming connections on every protocol to the given
+    /// endpoints. `None` accepts any endpoint.
+    /// Already established connections are left alone.
+    pub fn set_incoming_allow_list(&self, allow_list: Option<HashSet<EndpointId>>) {
+        *self.incoming_allow_list.write().expect(ERROR_MUTEX) = allow_list;
+    }
+
+    /// Revokes a device for the whole repo and drops any connection to it.
+    pub async fn revoke_device(&self, endpoint_id: EndpointId) -> Res<DeviceRevocation> {
+        self.ensure_repo_live()?;
+        let revocation = revoke_device(&self.rcx, &self.config_repo, endpoint_id).await?;
+        self.apply_device_trust().await?;
+        Ok(revocation)
+    }
+
+    /// See [`rotate_identity`].
+    pub async fn rotate_identity(&self) -> Res<EndpointId> {
+        self.ensure_repo_live()?;
+        rotate_identity(&self.rcx, &self.config_repo).await
+    }
+
+    /// Loads the revocations and rotations from the config, refuses the
+    /// affected endpoints and updates the known devices to match.
+    async fn apply_device_trust(&self) -> Res<()> {
+        let trust = self.config_repo.get_device_trust().await;
+        let local_endpoint_id = self.router.endpoint().id();
+        if trust.revoked.contains_key(&local_endpoint_id) {
+            warn!("this device has been revoked by another device");
+        }
+        let mut refused = trust.refused_endpoints();
+        // we keep running under a rotated key until the repo is reopened
+        refused.remove(&local_endpoint_id);
+        {
+            let mut current = self.refused_endpoints.write().expect(ERROR_MUTEX);
+            if *current == refused {
+                return Ok(());
+            }
+            *current = refused.clone();
+        }
+
+        let known_devices = self.config_repo.list_known_sync_devices().await?;
+        for device in known_devices {
+            if device.endpoint_id == local_endpoint_id || !refused.contains(&device.endpoint_id) {
+                continue;
+            }
+            if !trust.revoked.contains_key(&device.endpoint_id) {
+                let new_endpoint_id = trust.current_endpoint(device.endpoint_id);
+                if !refused.contains(&new_endpoint_id) {
+                    self.config_repo
+                        .upsert_known_sync_device(crate::repo::globals::SyncDeviceEntry {
+                            endpoint_id: new_endpoint_id,
+                            last_connected_at: None,
+                            ..device.clone()
+                        })
+                        .await?;
+                }
+            }
+            self.config_repo
+                .remove_known_sync_device(&device.endpoint_id)
+                .await?;
+        }
+
+        let connected = self
+            .active_peers
+            .read()
+            .await
+            .keys()
+            .copied()
+            .filter(|peer_id| {
+                EndpointId::from_bytes(peer_id.as_bytes())
+                    .is_ok_and(|endpoint_id| refused.contains(&endpoint_id))
+            })
+            .collect::<Vec<_>>();
+        for peer_id in connected {
+            self.rcx.big_repo.close_peer_connection(peer_id).await?;
+        }
         Ok(())
     }
 
+    /// Peers with an established connection.
+    pub async fn connected_peer_keys(&self) -> Vec<PeerKey> {
+        self.active_peers
+            .read()
+            .await
+            .values()
+            .filter_map(|state| match state {
+                ActivePeerState::Connected { peer_key, .. } => Some(Arc::clone(peer_key)),
+                ActivePeerState::Connecting => None,
+            })
+            .collect()
+    }
+
+    /// What was negotiated in the last handshake with the endpoint.
+    /// `None` if it hasn't handshaken with us or was incompatible.
+    pub fn peer_session(&self, endpoint_id: &EndpointId) -> Option<PeerSession> {
+        match self
+            .peer_sessions
+            .read()
+            .expect(ERROR_MUTEX)
+            .get(endpoint_id)
+        {
+            Some(handshake::PeerCompat::Compatible(session)) => Some(session.clone()),
+            Some(handshake::PeerCompat::Incompatible(_)) | None => None,
+        }
+    }
+
     async fn reserve_endpoint_connection(&self, peer_id: PeerId) -> bool {
         let mut active_peers = self.active_peers.write().await;
         if active_peers.contains_key(&peer_id) {
@@ -683,22 +1393,76 @@ impl IrohSyncRepo {
     async fn handle_big_sync_evt(&self, evt: big_sync_core::SyncStatEvent) -> Res<()> {
         match evt {
             big_sync_core::SyncStatEvent::ObjectSynced { peer_id, obj_id } => {
+                *self
+                    .synced_since_full_sync
+                    .lock()
+                    .expect(ERROR_MUTEX)
+                    .entry(peer_id)
+                    .or_default() += 1;
                 self.registry.notify([IrohSyncEvent::DocSyncedWithPeer {
                     peer_key: daybook_types::doc::format_peer_key(peer_id.as_bytes()),
                     doc_id: obj_id,
                 }]);
             }
             big_sync_core::SyncStatEvent::PeerPartFullySynced { peer_id, part_id } => {
+                self.note_status_res(
+                    self.sync_status
+                        .record_full_sync(
+                            &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                            Some(&self.partition_label(part_id)),
+                            Timestamp::now(),
+                        )
+                        .await,
+                );
                 self.registry.notify([IrohSyncEvent::PartitionFullySynced {
                     peer_key: daybook_types::doc::format_peer_key(peer_id.as_bytes()),
                     partition: part_id.to_string(),
                 }]);
             }
-            big_sync_core::SyncStatEvent::PeerPartStale { .. } => {}
+            big_sync_core::SyncStatEvent::PeerPartStale { peer_id, part_id } => {
+                self.note_status_res(
+                    self.sync_status
+                        .record_stale(
+                            &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                            Some(&self.partition_label(part_id)),
+                            Timestamp::now(),
+                        )
+                        .await,
+                );
+            }
             big_sync_core::SyncStatEvent::PartFullySynced { .. } => {}
             big_sync_core::SyncStatEvent::PartStale { .. } => {}
-            big_sync_core::SyncStatEvent::PeerFullySynced { .. } => {}
+            big_sync_core::SyncStatEvent::PeerFullySynced { peer_id } => {
+                let doc_count = self
+                    .synced_since_full_sync
+                    .lock()
+                    .expect(ERROR_MUTEX)
+                    .remove(&peer_id)
+                    .unwrap_or_default();
+                self.note_status_res(
+                    self.sync_status
+                        .record_full_sync(
+                            &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                            None,
+                            Timestamp::now(),
+                        )
+                        .await,
+                );
+                self.registry.notify([IrohSyncEvent::PeerFullySynced {
+                    peer_key: daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                    doc_count,
+                }]);
+            }
             big_sync_core::SyncStatEvent::PeerStale { peer_id } => {
+                self.note_status_res(
+                    self.sync_status
+                        .record_stale(
+                            &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                            None,
+                            Timestamp::now(),
+                        )
+                        .await,
+                );
                 self.registry.notify([IrohSyncEvent::StalePeer {
                     peer_key: daybook_types::doc::format_peer_key(peer_id.as_bytes()),
                 }]);
@@ -715,6 +1479,14 @@ impl IrohSyncRepo {
             eyre::bail!("connecting to ourself is not supported");
         }
         let endpoint_id = endpoint_addr.id;
+        if self
+            .refused_endpoints
+            .read()
+            .expect(ERROR_MUTEX)
+            .contains(&endpoint_id)
+        {
+            eyre::bail!("endpoint {endpoint_id} has been revoked");
+        }
         let peer_id = PeerId::new(*endpoint_id.as_bytes());
 
         let endpoint = self.router.endpoint().clone();
@@ -728,7 +1500,10 @@ impl IrohSyncRepo {
                 peer_key: Arc::clone(&peer_key),
             }];
 
-            let partition_ids = self.peer_partition_ids(&peer_key);
+            let outcome = handshake::exchange_hello(&endpoint, endpoint_addr.clone()).await?;
+            handshake::record_outcome(&self.peer_sessions, &self.registry, endpoint_id, outcome)?;
+
+            let partition_ids = self.partition_ids_for_peer(peer_id);
             let conn = self
                 .rcx
                 .big_repo
@@ -748,22 +1523,45 @@ impl IrohSyncRepo {
             self.repo_sync_backend
                 .register_remote_peer(conn.peer_id, endpoint_addr.clone());
             self.big_sync_worker
-                .set_peer(conn.peer_id, big_sync_rpc_client, partition_ids)
+                .set_peer(
+                    conn.peer_id,
+                    Arc::clone(&big_sync_rpc_client) as _,
+                    partition_ids,
+                )
                 .await?;
 
-            let old = self
-                .active_peers
-                .write()
-                .await
-                .insert(peer_id, ActivePeerState::Connected { peer_key });
+            let old = self.active_peers.write().await.insert(
+                peer_id,
+                ActivePeerState::Connected {
+                    peer_key,
+                    big_sync_rpc_client,
+                },
+            );
             assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy");
+            self.note_status_res(
+                self.sync_status
+                    .record_connected(
+                        &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                        Timestamp::now(),
+                    )
+                    .await,
+            );
             self.registry.notify(events);
             eyre::Ok(())
         }
         .await;
-        if res.is_err() {
+        if let Err(err) = &res {
             let old = self.active_peers.write().await.remove(&peer_id);
-            assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy")
+            assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy");
+            self.note_status_res(
+                self.sync_status
+                    .record_error(
+                        &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                        &format!("connect failed: {err:#}"),
+                        Timestamp::now(),
+                    )
+                    .await,
+            );
         }
 
         Ok(())
@@ -776,6 +1574,106 @@ impl IrohSyncRepo {
         Ok(endpoint_addr)
     }
 
+    /// Connects to a known storage peer. Only the sealed vault partition
+    /// is synced with it and only once the vault is enabled.
+    pub async fn connect_storage_peer(&self, endpoint_addr: iroh::EndpointAddr) -> Res<()> {
+        self.ensure_repo_live()?;
+        let endpoint_id = endpoint_addr.id;
+        if self
+            .refused_endpoints
+            .read()
+            .expect(ERROR_MUTEX)
+            .contains(&endpoint_id)
+        {
+            eyre::bail!("endpoint {endpoint_id} has been revoked");
+        }
+        let peer_id = PeerId::new(*endpoint_id.as_bytes());
+        // the device might have just been added
+        self.apply_peer_access().await?;
+        if !self.peer_access.is_storage_peer(peer_id) {
+            eyre::bail!("endpoint {endpoint_id} is not a known storage peer");
+        }
+        if !self.reserve_endpoint_connection(peer_id).await {
+            return Ok(());
+        }
+        let endpoint = self.router.endpoint().clone();
+        let res = async {
+            let peer_key = daybook_types::doc::format_peer_key(endpoint_id.as_bytes());
+            let outcome = handshake::exchange_hello(&endpoint, endpoint_addr.clone()).await?;
+            let session = handshake::record_outcome(
+                &self.peer_sessions,
+                &self.registry,
+                endpoint_id,
+                outcome,
+            )?;
+            if !session.supports(handshake::features::SEALED_STORAGE) {
+                eyre::bail!("endpoint {endpoint_id} doesn't serve sealed storage");
+            }
+            let big_sync_rpc_client = Arc::new(big_sync::rpc::IrohBigSyncRpcClient::new(
+                endpoint,
+                endpoint_addr.clone(),
+            ));
+            self.sealed_sync_backend
+                .register_remote_peer(peer_id, endpoint_addr);
+            self.big_sync_worker
+                .set_peer(
+                    peer_id,
+                    Arc::clone(&big_sync_rpc_client) as _,
+                    self.partition_ids_for_peer(peer_id),
+                )
+                .await?;
+            let old = self.active_peers.write().await.insert(
+                peer_id,
+                ActivePeerState::Connected {
+                    peer_key: Arc::clone(&peer_key),
+                    big_sync_rpc_client,
+                },
+            );
+            assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy");
+            self.note_status_res(
+                self.sync_status
+                    .record_connected(&peer_key, Timestamp::now())
+                    .await,
+            );
+            self.registry
+                .notify([IrohSyncEvent::OutgoingConnection { peer_key }]);
+            eyre::Ok(())
+        }
+        .await;
+        if let Err(err) = &res {
+            self.sealed_sync_backend.unregister_remote_peer(peer_id);
+            let old = self.active_peers.write().await.remove(&peer_id);
+            assert!(matches!(old, Some(ActivePeerState::Connecting)), "fishy");
+            self.note_status_res(
+                self.sync_status
+                    .record_error(
+                        &daybook_types::doc::format_peer_key(peer_id.as_bytes()),
+                        &format!("connect failed: {err:#}"),
+                        Timestamp::now(),
+                    )
+                    .await,
+            );
+        }
+        res
+    }
+
+    /// Storage peers have no connection of their own that signals its
+    /// end so they're dropped once they stop answering and picked up
+    /// again by the reconnects.
+    async fn drop_storage_peer(&self, peer_id: PeerId) -> Res<()> {
+        self.sealed_sync_backend.unregister_remote_peer(peer_id);
+        self.big_sync_worker.remove_peer(peer_id).await?;
+        if let Some(ActivePeerState::Connected { peer_key, .. }) =
+            self.active_peers.write().await.remove(&peer_id)
+        {
+            self.registry.notify([IrohSyncEvent::ConnectionClosed {
+                peer_key,
+                reason: "storage peer unreachable".into(),
+            }]);
+        }
+        Ok(())
+    }
+
     pub async fn connect_known_devices_once(&self) -> Res<()> {
         self.ensure_repo_live()?;
         #[cfg(not(test))]
@@ -786,10 +1684,13 @@ impl IrohSyncRepo {
                 if device.endpoint_id == local_endpoint_id {
                     continue;
                 }
-                if let Err(err) = self
-                    .connect_endpoint_addr(iroh::EndpointAddr::new(device.endpoint_id))
-                    .await
-                {
+                let endpoint_addr = iroh::EndpointAddr::new(device.endpoint_id);
+                let res = if device.storage_peer {
+                    self.connect_storage_peer(endpoint_addr).await
+                } else {
+                    self.connect_endpoint_addr(endpoint_addr).await
+                };
+                if let Err(err) = res {
                     warn!(
                         ?err,
                         endpoint_id = %device.endpoint_id,
@@ -801,6 +1702,137 @@ impl IrohSyncRepo {
         Ok(())
     }
 
+    /// Sync status of every peer we've ever connected to.
+    pub async fn sync_status(&self) -> Res<Vec<PeerSyncStatus>> {
+        self.sync_status.list().await
+    }
+
+    /// Cursor lag samples of the peer taken at or after `since`.
+    pub async fn sync_lag_history(&self, peer_key: &str, since: Timestamp) -> Res<Vec<LagSample>> {
+        self.sync_status.lag_history(peer_key, since).await
+    }
+
+    /// Samples the transfer counters and cursor lag now instead of
+    /// waiting for the next periodic sample.
+    pub async fn refresh_sync_status(&self) -> Res<()> {
+        self.ensure_repo_live()?;
+        self.sample_sync_status().await
+    }
+
+    /// Loads the vault key from the keyring and starts sealing with it.
+    async fn apply_vault_key(&self) -> Res<()> {
+        let Some(key) = read_vault_key(&self.rcx).await? else {
+            return Ok(());
+        };
+        let vault = vault::Vault::load(
+            &key,
+            &self.rcx,
+            Arc::clone(&self.sealed_store),
+            Arc::clone(&self.blobs_repo),
+        )
+        .await?;
+        self.sealed_sync_backend.set_vault(Some(Arc::new(vault)));
+        self.resubscribe_active_peers().await
+    }
+
+    /// See [`enable_vault`]. Connected storage peers get synced with
+    /// right away.
+    pub async fn enable_vault(&self) -> Res<VaultKey> {
+        self.ensure_repo_live()?;
+        let key = enable_vault(&self.rcx).await?;
+        self.apply_vault_key().await?;
+        Ok(key)
+    }
+
+    /// See [`import_vault_key`].
+    pub async fn import_vault_key(&self, key: &VaultKey) -> Res<()> {
+        self.ensure_repo_live()?;
+        import_vault_key(&self.rcx, key).await?;
+        self.apply_vault_key().await
+    }
+
+    /// Seals what changed since the last call and pushes it to the
+    /// connected storage peers.
+    async fn sync_vault(&self) -> Res<()> {
+        let Some(vault) = self.sealed_sync_backend.vault() else {
+            return Ok(());
+        };
+        let repo_backend_id = big_repo::BigRepo::BACKEND_ID;
+        let sources = self
+            .known_partitions()
+            .into_iter()
+            .filter_map(|(label, part_id, backend_id)| {
+                if &*backend_id == repo_backend_id {
+                    return Some((part_id, vault::SealSource::Docs));
+                }
+                crate::blobs::BlobScope::from_partition_id(label)
+                    .map(|scope| (part_id, vault::SealSource::Blobs(scope)))
+            })
+            .collect::<Vec<_>>();
+        let sealed = vault.seal_new(&sources).await?;
+        if sealed > 0 {
+            debug!(sealed, "sealed new vault objects");
+        }
+
+        let storage_peers = self
+            .active_peers
+            .read()
+            .await
+            .iter()
+            .filter_map(|(peer_id, state)| match state {
+                ActivePeerState::Connected { peer_key, .. }
+                    if self.peer_access.is_storage_peer(*peer_id) =>
+                {
+                    Some((*peer_id, Arc::clone(peer_key)))
+                }
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        for (peer_id, peer_key) in storage_peers {
+            let Some(client) = self.sealed_sync_backend.remote_peer(peer_id) else {
+                continue;
+            };
+            if let Err(err) = vault.push(&peer_key, &client).await {
+                warn!(?err, %peer_key, "failed pushing to storage peer");
+                self.note_status_res(
+                    self.sync_status
+                        .record_error(
+                            &peer_key,
+                            &format!("push failed: {err:#}"),
+                            Timestamp::now(),
+                        )
+                        .await,
+                );
+                self.drop_storage_peer(peer_id).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Seals and pushes to the storage peers now instead of waiting for
+    /// the next periodic run. A no-op unless the vault is enabled.
+    pub async fn sync_vault_now(&self) -> Res<()> {
+        self.ensure_repo_live()?;
+        self.sync_vault().await
+    }
+
+    pub async fn blob_cache_quota(&self) -> Res<Option<u64>> {
+        Ok(crate::repo::globals::get_blob_cache_config(&self.rcx.sql)
+            .await?
+            .quota_bytes)
+    }
+
+    /// Persists the local blob cache quota and evicts down to it.
+    pub async fn set_blob_cache_quota(&self, quota_bytes: Option<u64>) -> Res<()> {
+        self.ensure_repo_live()?;
+        crate::repo::globals::set_blob_cache_config(
+            &self.rcx.sql,
+            &crate::repo::globals::BlobCacheConfig { quota_bytes },
+        )
+        .await?;
+        self.blobs_repo.set_cache_quota(quota_bytes).await
+    }
+
     #[tracing::instrument(skip(self))]
     pub async fn wait_for_full_sync(
         &self,
@@ -815,12 +1847,27 @@ impl IrohSyncRepo {
         if peer_ids.is_empty() {
             return Ok(());
         }
+        // partitions the local sync profile opts out of are never going to
+        // sync so they don't count towards being fully synced
+        let profile = self.current_sync_profile();
+        let unsubscribed = self
+            .known_partitions()
+            .into_iter()
+            .filter(|(label, ..)| {
+                !profile.subscribes_to(label)
+                    || (*label == crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID
+                        && !profile.doc_blobs_can_settle())
+            })
+            .map(|(_, part_id, _)| part_id)
+            .collect::<HashSet<_>>();
+        let required_partitions = required_partitions
+            .iter()
+            .copied()
+            .filter(|part_id| !unsubscribed.contains(part_id))
+            .collect::<Vec<_>>();
         let timeout_outcome = tokio::time::timeout(timeout, async {
             self.big_sync_worker
-                .wait_for_full_sync(
-                    peer_ids.iter().copied(),
-                    required_partitions.iter().copied(),
-                )
+                .wait_for_full_sync(peer_ids.iter().copied(), required_partitions)
                 .await?;
             eyre::Ok(())
         })
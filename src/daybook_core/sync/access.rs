@@ -0,0 +1,100 @@
+//! Per-device sync permissions.
+//!
+//! Each known device carries a [`PeerAccess`] in the local sync config.
+//! Read-only devices get to pull from us but their changes are never
+//! pulled in and partition-scoped devices are only ever shown the
+//! partitions they've been granted. Devices we don't have an entry for
+//! aren't restricted here, the incoming allow-list takes care of those.
+//! The sealed vault partition is only ever shown to storage peers and
+//! it's the only one they get to see.
+
+use crate::interlude::*;
+
+use crate::repo::globals::{PeerAccess, SyncDeviceEntry};
+
+#[derive(Debug, Default)]
+pub(super) struct PeerAccessPolicy {
+    part_labels: std::sync::RwLock<HashMap<PartId, &'static str>>,
+    access: std::sync::RwLock<HashMap<PeerId, PeerAccess>>,
+    storage_peers: std::sync::RwLock<HashSet<PeerId>>,
+}
+
+impl PeerAccessPolicy {
+    /// Replaces the policy state. Returns the peers that are read-only.
+    pub fn update(
+        &self,
+        part_labels: HashMap<PartId, &'static str>,
+        devices: &[SyncDeviceEntry],
+    ) -> HashSet<PeerId> {
+        let access = devices
+            .iter()
+            .map(|device| {
+                (
+                    PeerId::new(*device.endpoint_id.as_bytes()),
+                    device.access.clone(),
+                )
+            })
+            .collect::<HashMap<_, _>>();
+        let read_only = access
+            .iter()
+            .filter(|(_, access)| access.read_only)
+            .map(|(peer_id, _)| *peer_id)
+            .collect();
+        let storage_peers = devices
+            .iter()
+            .filter(|device| device.storage_peer)
+            .map(|device| PeerId::new(*device.endpoint_id.as_bytes()))
+            .collect();
+        *self.part_labels.write().expect(ERROR_MUTEX) = part_labels;
+        *self.storage_peers.write().expect(ERROR_MUTEX) = storage_peers;
+        *self.access.write().expect(ERROR_MUTEX) = access;
+        read_only
+    }
+
+    pub fn is_storage_peer(&self, peer_id: PeerId) -> bool {
+        self.storage_peers
+            .read()
+            .expect(ERROR_MUTEX)
+            .contains(&peer_id)
+    }
+}
+
+impl big_sync::rpc::PartAccessPolicy for PeerAccessPolicy {
+    fn can_read(&self, peer_id: PeerId, part_id: PartId) -> bool {
+        let label = self
+            .part_labels
+            .read()
+            .expect(ERROR_MUTEX)
+            .get(&part_id)
+            .copied();
+        let is_sealed = label == Some(super::VAULT_SEALED_PARTITION_ID);
+        let is_storage_peer = self.is_storage_peer(peer_id);
+        if is_sealed || is_storage_peer {
+            return is_sealed && is_storage_peer;
+        }
+        let access = self.access.read().expect(ERROR_MUTEX);
+        let Some(access) = access.get(&peer_id) else {
+            return true;
+        };
+        if access.partitions.is_none() {
+            return true;
+        }
+        match label {
+            // the config lives in the core docs so every device needs it
+            Some(label) if label == super::CORE_DOCS_PARTITION_ID => true,
+            Some(label) => access.can_read(label),
+            None => false,
+        }
+    }
+
+    fn can_read_unpartitioned(&self, peer_id: PeerId) -> bool {
+        if self.is_storage_peer(peer_id) {
+            return false;
+        }
+        self.access
+            .read()
+            .expect(ERROR_MUTEX)
+            .get(&peer_id)
+            .is_none_or(|access| access.partitions.is_none())
+    }
+}
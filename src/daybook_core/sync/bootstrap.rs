@@ -25,12 +25,20 @@ pub struct SyncBootstrapState {
 #[derive(Debug, Clone)]
 pub struct CloneRepoInitOptions {
     pub timeout: std::time::Duration,
+    /// Only pull the core docs (app doc, drawer and its shards) before the
+    /// clone completes. Branch docs that aren't local yet are read from a
+    /// compacted snapshot at the peer's heads, their history is only pulled
+    /// when a history or diff view, a read at older heads or a write needs
+    /// it. Combine with a [`super::SyncProfile`] to keep parts of the
+    /// drawer off the device.
+    pub shallow: bool,
 }
 
 impl Default for CloneRepoInitOptions {
     fn default() -> Self {
         Self {
             timeout: std::time::Duration::from_secs(30),
+            shallow: false,
         }
     }
 }
@@ -150,16 +158,21 @@ pub async fn resolve_clone_info_from_url(source_url: &str) -> Res<CloneInfoRespo
     let endpoint = iroh::Endpoint::builder(iroh::endpoint::presets::Minimal)
         .bind()
         .await?;
-    let client = irpc_iroh::client::<CloneProvisionRpc>(
-        endpoint.clone(),
-        endpoint_addr,
-        CLONE_PROVISION_ALPN,
-    );
-    let response_res = client.rpc(req).await;
+    let response_res = async {
+        super::handshake::ensure_compatible(&endpoint, endpoint_addr.clone()).await?;
+        let client = irpc_iroh::client::<CloneProvisionRpc>(
+            endpoint.clone(),
+            endpoint_addr,
+            CLONE_PROVISION_ALPN,
+        );
+        client
+            .rpc(req)
+            .await
+            .wrap_err("clone info rpc transport failed")
+    }
+    .await;
     endpoint.close().await;
-    let response = response_res
-        .wrap_err("clone info rpc transport failed")?
-        .map_err(|err| eyre::eyre!("clone info rpc failed: {err}"))?;
+    let response = response_res?.map_err(|err| eyre::eyre!("clone info rpc failed: {err}"))?;
     Ok(response)
 }
 
@@ -183,16 +196,21 @@ pub async fn request_clone_provision_from_url(
     let endpoint = iroh::Endpoint::builder(iroh::endpoint::presets::Minimal)
         .bind()
         .await?;
-    let client = irpc_iroh::client::<CloneProvisionRpc>(
-        endpoint.clone(),
-        endpoint_addr,
-        CLONE_PROVISION_ALPN,
-    );
-    let response_res = client.rpc(req).await;
+    let response_res = async {
+        super::handshake::ensure_compatible(&endpoint, endpoint_addr.clone()).await?;
+        let client = irpc_iroh::client::<CloneProvisionRpc>(
+            endpoint.clone(),
+            endpoint_addr,
+            CLONE_PROVISION_ALPN,
+        );
+        client
+            .rpc(req)
+            .await
+            .wrap_err("clone provision rpc transport failed")
+    }
+    .await;
     endpoint.close().await;
-    let response = response_res
-        .wrap_err("clone provision rpc transport failed")?
-        .map_err(|err| eyre::eyre!("clone provision rpc failed: {err}"))?;
+    let response = response_res?.map_err(|err| eyre::eyre!("clone provision rpc failed: {err}"))?;
     Ok(response)
 }
 
@@ -211,6 +229,7 @@ pub async fn connect_and_pull_required_partitions_once(
     iroh_secret_key: iroh::SecretKey,
     bootstrap: &SyncBootstrapState,
     timeout: std::time::Duration,
+    shallow: bool,
 ) -> Res<()> {
     let endpoint_builder =
         iroh::Endpoint::builder(iroh::endpoint::presets::Minimal).secret_key(iroh_secret_key);
@@ -227,6 +246,7 @@ pub async fn connect_and_pull_required_partitions_once(
         &endpoint,
         bootstrap,
         timeout,
+        shallow,
     )
     .await;
     endpoint.close().await;
@@ -241,7 +261,10 @@ async fn pull_required_partitions_via_big_sync_worker(
     endpoint: &iroh::Endpoint,
     bootstrap: &SyncBootstrapState,
     timeout: std::time::Duration,
+    shallow: bool,
 ) -> Res<()> {
+    super::handshake::ensure_compatible(endpoint, bootstrap.endpoint_addr.clone()).await?;
+
     let core_docs_partition_id = crate::part_id_from_label(CORE_DOCS_PARTITION_ID);
     let drawer_partition_id =
         crate::drawer::DrawerRepo::replicated_partition_id_for_drawer(&bootstrap.drawer_doc_id);
@@ -301,15 +324,19 @@ async fn pull_required_partitions_via_big_sync_worker(
         big_sync::rpc::IrohBigSyncRpcClient::new(endpoint.clone(), bootstrap.endpoint_addr.clone());
     let big_sync_rpc_client = Arc::new(big_sync_rpc_client);
 
-    let initial_partitions: HashMap<PartId, big_sync::BackendId> = [
-        (core_docs_partition_id, Arc::clone(&repo_backend_id)),
-        (drawer_partition_id, Arc::clone(&repo_backend_id)),
-        // The blob-scope partitions are populated lazily once the repo has fully
-        // booted and loaded the blob stores; they are not part of the initial clone
-        // barrier.
-    ]
-    .into_iter()
-    .collect();
+    // The blob-scope partitions are populated lazily once the repo has fully
+    // booted and loaded the blob stores; they are not part of the initial clone
+    // barrier. Shallow clones leave the branch docs of the drawer partition
+    // for later as well.
+    let required_partitions = if shallow {
+        vec![core_docs_partition_id]
+    } else {
+        vec![core_docs_partition_id, drawer_partition_id]
+    };
+    let initial_partitions: HashMap<PartId, big_sync::BackendId> = required_partitions
+        .iter()
+        .map(|part_id| (*part_id, Arc::clone(&repo_backend_id)))
+        .collect();
 
     big_sync_worker
         .set_peer(peer_id, big_sync_rpc_client, initial_partitions.clone())
@@ -323,7 +350,6 @@ async fn pull_required_partitions_via_big_sync_worker(
         // Blob-scope partitions are populated lazily as blobs/plugs appear after the repo
         // finishes booting, so requiring them here would make clone bootstrap race normal
         // repo initialization.
-        let required_partitions = [core_docs_partition_id, drawer_partition_id];
         big_sync_worker
             .wait_for_full_sync(vec![peer_id], required_partitions)
             .await
@@ -383,6 +409,7 @@ pub async fn clone_repo_init_from_url(
         blobs_root: destination.join("blobs"),
         marker_path: destination.join("db.repo.txt"),
         lock_path: destination.join("repo.lock"),
+        daemon_info_path: destination.join(crate::daemon::DAEMON_INFO_FILE),
     };
     let lock_guard = crate::repo::RepoLockGuard::acquire(&staging.join("repo.lock"))?;
 
@@ -459,9 +486,12 @@ pub async fn clone_repo_init_from_url(
                         .unwrap_or_else(|| bootstrap.endpoint_id.to_string()),
                     added_at: jiff::Timestamp::now(),
                     last_connected_at: None,
+                    access: default(),
+                    storage_peer: false,
                 });
-            crate::repo::globals::set_sync_config(&sql, &sync_config).await?;
         }
+        sync_config.fetch_docs_on_demand = options.shallow;
+        crate::repo::globals::set_sync_config(&sql, &sync_config).await?;
 
         let part_store = big_sync::SqlitePartStore::new(
             sql.clone(),
@@ -501,6 +531,7 @@ pub async fn clone_repo_init_from_url(
             local_secret.clone(),
             &bootstrap,
             options.timeout,
+            options.shallow,
         )
         .await?;
 
@@ -533,6 +564,7 @@ pub async fn clone_repo_init_from_url(
                 iroh_public_key: identity.iroh_public_key.to_string(),
                 iroh_secret_key: identity.iroh_secret_key,
                 secret_repo,
+                data_key: None,
             },
             staging.join("blobs"),
         )
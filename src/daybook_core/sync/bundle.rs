@@ -0,0 +1,475 @@
+//! Offline sync bundles.
+//!
+//! A bundle captures the part events a device has past some cursor along with
+//! the automerge saves and blob bytes they reference. Importing replays it
+//! through the big_sync worker as if the exporting device was connected so
+//! that partition membership and peer cursors end up where a live sync would
+//! leave them. Replaying a bundle that was already applied is a no-op.
+//!
+//! The file is a zstd stream of a magic, a length prefixed JSON manifest and
+//! then the length prefixed doc saves and blobs in manifest order. Imports
+//! hold the whole bundle in memory.
+
+use crate::interlude::*;
+
+use std::io::{Read, Write};
+
+use big_sync::HostPartStore;
+use big_sync_core::part_store::CursorIndex;
+use big_sync_core::rpc::{
+    BigSyncRpcResult, BucketSummary, GetChangedBucketsRequest, LeafBucketResult, LeafBucketsError,
+    LeafBucketsRequest, ListPartsError, PartEvent, PartSummary, PeerSummaryRequest,
+    PeerSummaryResult, SubEvent, SubPartsRequest,
+};
+
+use super::IrohSyncRepo;
+use crate::blobs::BlobId;
+
+const BUNDLE_MAGIC: &[u8; 8] = b"DBSYNCB\x01";
+const BUNDLE_VERSION: u32 = 1;
+const LIST_EVENTS_PAGE_LIMIT: u32 = 512;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundleManifest {
+    version: u32,
+    repo_id: String,
+    source_peer_id: PeerId,
+    exported_at: Timestamp,
+    parts: Vec<BundlePart>,
+    docs: Vec<(DocumentId, u64)>,
+    blobs: Vec<(BlobId, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BundlePart {
+    part_id: PartId,
+    since: CursorIndex,
+    summary: PartSummary,
+    events: Vec<PartEvent>,
+}
+
+struct SyncBundle {
+    manifest: BundleManifest,
+    docs: HashMap<DocumentId, Vec<u8>>,
+    blobs: Vec<(BlobId, Vec<u8>)>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyncBundleReport {
+    pub source_peer_id: PeerId,
+    pub part_count: usize,
+    pub event_count: usize,
+    pub doc_count: usize,
+    pub blob_count: usize,
+    /// Objects left out of the bundle since their content isn't held locally,
+    /// e.g. evicted blobs.
+    pub skipped_count: usize,
+}
+
+impl IrohSyncRepo {
+    /// Writes the part events past `since` and everything they reference to
+    /// `path`. `since` is the cursor of this device that the receiving device
+    /// last synced up to, 0 for everything.
+    pub async fn export_bundle(&self, path: &Path, since: CursorIndex) -> Res<SyncBundleReport> {
+        self.ensure_repo_live()?;
+        let part_store = &self.rcx.part_store;
+        let repo_backend_id: big_sync::BackendId = big_repo::BigRepo::BACKEND_ID.into();
+
+        let mut parts = vec![];
+        let mut doc_saves = vec![];
+        let mut blob_paths: Vec<(BlobId, PathBuf, u64)> = vec![];
+        let mut seen_objs = HashSet::new();
+        let mut skipped_count = 0;
+        for (_, part_id, backend_id) in self.known_partitions() {
+            let summary = match part_store.summarize_parts([part_id].into()).await? {
+                Ok(mut summaries) => summaries.remove(&part_id).expect(ERROR_IMPOSSIBLE),
+                // not created locally yet, nothing to export
+                Err(ListPartsError::UnkownParts { .. }) => continue,
+            };
+            let mut events = vec![];
+            let mut cursor = since;
+            loop {
+                let mut pages = part_store
+                    .list_events([part_id].into(), cursor, LIST_EVENTS_PAGE_LIMIT)
+                    .await?
+                    .map_err(|err| ferr!("error listing part events: {err}"))?;
+                let page = pages.remove(&part_id).unwrap_or_default();
+                events.extend(page.events);
+                match page.next_cursor {
+                    // next_cursor is the first event left out
+                    Some(next) => cursor = next.saturating_sub(1),
+                    None => break,
+                }
+            }
+            let mut kept = Vec::with_capacity(events.len());
+            for event in events {
+                let obj_id = match &event {
+                    PartEvent::Changed(inner) => inner.obj_id,
+                    PartEvent::Added(inner) => inner.obj_id,
+                    PartEvent::Removed(_) => {
+                        kept.push(event);
+                        continue;
+                    }
+                };
+                let available = if backend_id == repo_backend_id {
+                    if seen_objs.insert(obj_id) {
+                        match self.rcx.big_repo.export_doc(&obj_id).await? {
+                            Some(save) => {
+                                doc_saves.push((obj_id, save));
+                                true
+                            }
+                            None => {
+                                seen_objs.remove(&obj_id);
+                                false
+                            }
+                        }
+                    } else {
+                        true
+                    }
+                } else if seen_objs.contains(&obj_id) {
+                    true
+                } else if self.blobs_repo.has_hash(obj_id).await? {
+                    let blob_path = self.blobs_repo.get_path(obj_id).await?;
+                    let len = tokio::fs::metadata(&blob_path).await?.len();
+                    seen_objs.insert(obj_id);
+                    blob_paths.push((obj_id, blob_path, len));
+                    true
+                } else {
+                    false
+                };
+                if available {
+                    kept.push(event);
+                } else {
+                    debug!(%obj_id, %part_id, "content not held locally, leaving out of bundle");
+                    skipped_count += 1;
+                }
+            }
+            parts.push(BundlePart {
+                part_id,
+                since,
+                summary,
+                events: kept,
+            });
+        }
+
+        let manifest = BundleManifest {
+            version: BUNDLE_VERSION,
+            repo_id: self.rcx.repo_id.clone(),
+            source_peer_id: PeerId::new(*self.router.endpoint().id().as_bytes()),
+            exported_at: Timestamp::now(),
+            parts,
+            docs: doc_saves
+                .iter()
+                .map(|(doc_id, save)| (*doc_id, save.len() as u64))
+                .collect(),
+            blobs: blob_paths
+                .iter()
+                .map(|(blob_id, _, len)| (*blob_id, *len))
+                .collect(),
+        };
+        let report = SyncBundleReport::new(&manifest, skipped_count);
+
+        let path = path.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            write_bundle(&path, &manifest, &doc_saves, &blob_paths)
+                .wrap_err_with(|| format!("error writing bundle to {}", path.display()))
+        })
+        .await
+        .wrap_err(ERROR_TOKIO)??;
+        Ok(report)
+    }
+
+    /// Applies a bundle written by [`Self::export_bundle`] on another device.
+    /// The exporting device must not be connected while importing.
+    pub async fn import_bundle(&self, path: &Path, timeout: Duration) -> Res<SyncBundleReport> {
+        self.ensure_repo_live()?;
+        let bundle = {
+            let path = path.to_path_buf();
+            tokio::task::spawn_blocking(move || {
+                read_bundle(&path)
+                    .wrap_err_with(|| format!("error reading bundle from {}", path.display()))
+            })
+            .await
+            .wrap_err(ERROR_TOKIO)??
+        };
+        let SyncBundle {
+            manifest,
+            docs,
+            blobs,
+        } = bundle;
+        if manifest.repo_id != self.rcx.repo_id {
+            eyre::bail!(
+                "bundle is for a different repo (local={}, bundle={})",
+                self.rcx.repo_id,
+                manifest.repo_id
+            );
+        }
+        let peer_id = manifest.source_peer_id;
+        if peer_id == PeerId::new(*self.router.endpoint().id().as_bytes()) {
+            eyre::bail!("bundle was exported by this device");
+        }
+        if self.active_peers.read().await.contains_key(&peer_id) {
+            eyre::bail!("bundle source device is connected, sync with it directly");
+        }
+        let report = SyncBundleReport::new(&manifest, 0);
+
+        let subscribed = self.peer_partition_ids("");
+        let mut partition_ids = HashMap::new();
+        for part in &manifest.parts {
+            let Some(backend_id) = subscribed.get(&part.part_id) else {
+                continue;
+            };
+            let last_cursor = self
+                .rcx
+                .part_store
+                .get_peer_part_cursor(peer_id, part.part_id)
+                .await?;
+            if part.since > last_cursor {
+                eyre::bail!(
+                    "bundle starts at cursor {} but only {} was synced from its device, \
+                     export again with --since {}",
+                    part.since,
+                    last_cursor,
+                    last_cursor
+                );
+            }
+            partition_ids.insert(part.part_id, Arc::clone(backend_id));
+        }
+
+        for (blob_id, data) in blobs {
+            self.blobs_repo.stage_in_store(blob_id, data).await?;
+        }
+        self.repo_sync_backend.register_bundle_peer(peer_id, docs);
+        let client = Arc::new(BundleRpcClient::new(manifest));
+        let res = async {
+            self.big_sync_worker
+                .set_peer(peer_id, client as _, partition_ids.clone())
+                .await?;
+            tokio::time::timeout(
+                timeout,
+                self.big_sync_worker
+                    .wait_for_full_sync([peer_id], partition_ids.into_keys()),
+            )
+            .await
+            .map_err(|_| ferr!("timed out applying bundle"))??;
+            eyre::Ok(())
+        }
+        .await;
+        // the device might have connected for real in the mean time
+        if !self.active_peers.read().await.contains_key(&peer_id) {
+            self.big_sync_worker.remove_peer(peer_id).await?;
+            self.repo_sync_backend.unregister_remote_peer(peer_id);
+        }
+        res?;
+        Ok(report)
+    }
+}
+
+impl SyncBundleReport {
+    fn new(manifest: &BundleManifest, skipped_count: usize) -> Self {
+        Self {
+            source_peer_id: manifest.source_peer_id,
+            part_count: manifest.parts.len(),
+            event_count: manifest.parts.iter().map(|part| part.events.len()).sum(),
+            doc_count: manifest.docs.len(),
+            blob_count: manifest.blobs.len(),
+            skipped_count,
+        }
+    }
+}
+
+fn write_bundle(
+    path: &Path,
+    manifest: &BundleManifest,
+    docs: &[(DocumentId, Vec<u8>)],
+    blobs: &[(BlobId, PathBuf, u64)],
+) -> Res<()> {
+    let tmp_path = path.with_extension("partial");
+    let file = std::fs::File::create(&tmp_path)?;
+    let mut out = zstd::stream::write::Encoder::new(std::io::BufWriter::new(file), 3)?;
+    out.write_all(BUNDLE_MAGIC)?;
+    let manifest = serde_json::to_vec(manifest).expect(ERROR_JSON);
+    write_frame_len(&mut out, manifest.len() as u64)?;
+    out.write_all(&manifest)?;
+    for (_, save) in docs {
+        write_frame_len(&mut out, save.len() as u64)?;
+        out.write_all(save)?;
+    }
+    for (blob_id, blob_path, len) in blobs {
+        write_frame_len(&mut out, *len)?;
+        let copied = std::io::copy(&mut std::fs::File::open(blob_path)?, &mut out)?;
+        if copied != *len {
+            eyre::bail!("blob changed size while exporting: {blob_id}");
+        }
+    }
+    out.finish()?
+        .into_inner()
+        .map_err(|err| err.into_error())?
+        .sync_all()?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn read_bundle(path: &Path) -> Res<SyncBundle> {
+    let file = std::fs::File::open(path)?;
+    let mut input = zstd::stream::read::Decoder::new(std::io::BufReader::new(file))?;
+    let mut magic = [0u8; 8];
+    input.read_exact(&mut magic)?;
+    if &magic != BUNDLE_MAGIC {
+        eyre::bail!("not a sync bundle");
+    }
+    let manifest: BundleManifest = serde_json::from_slice(&read_frame(&mut input)?)?;
+    if manifest.version != BUNDLE_VERSION {
+        eyre::bail!("unsupported bundle version {}", manifest.version);
+    }
+    let mut docs = HashMap::new();
+    for (doc_id, len) in &manifest.docs {
+        let save = read_frame(&mut input)?;
+        if save.len() as u64 != *len {
+            eyre::bail!("truncated doc in bundle: {doc_id}");
+        }
+        docs.insert(*doc_id, save);
+    }
+    let mut blobs = vec![];
+    for (blob_id, len) in &manifest.blobs {
+        let data = read_frame(&mut input)?;
+        if data.len() as u64 != *len {
+            eyre::bail!("truncated blob in bundle: {blob_id}");
+        }
+        blobs.push((*blob_id, data));
+    }
+    Ok(SyncBundle {
+        manifest,
+        docs,
+        blobs,
+    })
+}
+
+fn write_frame_len(out: &mut impl Write, len: u64) -> std::io::Result<()> {
+    out.write_all(&len.to_le_bytes())
+}
+
+fn read_frame(input: &mut impl Read) -> Res<Vec<u8>> {
+    let mut len = [0u8; 8];
+    input.read_exact(&mut len)?;
+    let len = usize::try_from(u64::from_le_bytes(len))?;
+    let mut buf = vec![];
+    input.take(len as u64).read_to_end(&mut buf)?;
+    if buf.len() != len {
+        eyre::bail!("unexpected end of bundle");
+    }
+    Ok(buf)
+}
+
+/// Serves the bundle to the big_sync worker as if it was the exporting peer.
+struct BundleRpcClient {
+    manifest: BundleManifest,
+    /// Subscriptions stay open after replay like they would on a live peer.
+    subscriptions: std::sync::Mutex<Vec<big_sync_core::mpsc::Sender<SubEvent>>>,
+}
+
+impl BundleRpcClient {
+    fn new(manifest: BundleManifest) -> Self {
+        Self {
+            manifest,
+            subscriptions: default(),
+        }
+    }
+
+    fn unknown_parts<'a>(&self, parts: impl Iterator<Item = &'a PartId>) -> Vec<PartId> {
+        parts
+            .filter(|part_id| {
+                !self
+                    .manifest
+                    .parts
+                    .iter()
+                    .any(|part| part.part_id == **part_id)
+            })
+            .copied()
+            .collect()
+    }
+}
+
+#[async_trait]
+impl big_sync::rpc::HostBigRpcClient for BundleRpcClient {
+    async fn peer_summary(
+        &self,
+        req: PeerSummaryRequest,
+    ) -> Res<BigSyncRpcResult<Result<PeerSummaryResult, ListPartsError>>> {
+        let parts = self
+            .manifest
+            .parts
+            .iter()
+            .filter(|part| req.parts.contains(&part.part_id))
+            .map(|part| (part.part_id, part.summary.clone()))
+            .collect();
+        Ok(Ok(Ok(PeerSummaryResult {
+            parts,
+            deepest_bucket_level: 0,
+        })))
+    }
+
+    async fn sub_parts(
+        &self,
+        req: SubPartsRequest,
+    ) -> Res<BigSyncRpcResult<Result<big_sync_core::mpsc::Receiver<SubEvent>, ListPartsError>>>
+    {
+        let unkown_parts = self.unknown_parts(req.parts.iter().map(|part| &part.part_id));
+        if !unkown_parts.is_empty() {
+            return Ok(Ok(Err(ListPartsError::UnkownParts { unkown_parts })));
+        }
+        let mut events = vec![];
+        for part_req in &req.parts {
+            let part = self
+                .manifest
+                .parts
+                .iter()
+                .find(|part| part.part_id == part_req.part_id)
+                .expect(ERROR_IMPOSSIBLE);
+            for event in &part.events {
+                let cursor = match event {
+                    PartEvent::Changed(inner) => inner.cursor,
+                    PartEvent::Added(inner) => inner.cursor,
+                    PartEvent::Removed(inner) => inner.cursor,
+                };
+                if cursor > part_req.cursor {
+                    events.push((cursor, event.clone()));
+                }
+            }
+        }
+        events.sort_by_key(|(cursor, _)| *cursor);
+        events.dedup();
+        let (tx, rx) = big_sync_core::mpsc::unbounded("SyncBundle".into(), "caller".into());
+        for (_, event) in events {
+            let evt = match event {
+                PartEvent::Changed(inner) => SubEvent::Changed(inner),
+                PartEvent::Added(inner) => SubEvent::Added(inner),
+                PartEvent::Removed(inner) => SubEvent::Removed(inner),
+            };
+            tx.send(evt).await.wrap_err(ERROR_CHANNEL)?;
+        }
+        tx.send(SubEvent::ReplayComplete)
+            .await
+            .wrap_err(ERROR_CHANNEL)?;
+        self.subscriptions.lock().expect(ERROR_MUTEX).push(tx);
+        Ok(Ok(Ok(rx)))
+    }
+
+    async fn get_changed_buckets(
+        &self,
+        _req: GetChangedBucketsRequest,
+    ) -> Res<BigSyncRpcResult<Result<Vec<BucketSummary>, ListPartsError>>> {
+        // no buckets steers the worker to replay the cursor stream which
+        // is all a bundle has
+        Ok(Ok(Ok(vec![])))
+    }
+
+    async fn leaf_buckets(
+        &self,
+        _req: LeafBucketsRequest,
+    ) -> Res<BigSyncRpcResult<Result<LeafBucketResult, LeafBucketsError>>> {
+        Ok(Err(big_sync_core::rpc::RpcError::TransportError))
+    }
+}
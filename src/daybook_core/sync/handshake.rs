@@ -0,0 +1,296 @@
+//! Version handshake run before any of the sync protocols.
+//!
+//! Each side sends a [`SyncHello`] listing the range of versions it speaks
+//! for every rpc protocol along with the optional features it supports.
+//! Peers are only synced with if every protocol both sides know of has an
+//! overlapping range. Endpoints found to be incompatible are refused on the
+//! other ALPNs until they handshake again with a compatible build.
+//!
+//! NOTE: the hello can't change shape without breaking the very check it's
+//! there for. New information goes into `features`.
+
+use crate::interlude::*;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    EndpointId,
+};
+use irpc::{channel, rpc_requests, WithChannels};
+
+use super::IrohSyncEvent;
+
+pub const SYNC_HANDSHAKE_ALPN: &[u8] = b"townframe/sync-handshake/0";
+
+pub const BIG_SYNC_PROTOCOL: &str = "big-sync";
+pub const REPO_SYNC_PROTOCOL: &str = "repo-sync";
+pub const CLONE_PROVISION_PROTOCOL: &str = "clone-provision";
+
+/// Optional behaviours advertised in the hello. Features a peer
+/// doesn't know of are ignored.
+pub mod features {
+    pub const PARTITION_ACCESS: &str = "partition-access";
+    pub const DEVICE_REVOCATION: &str = "device-revocation";
+    pub const LAN_PAIRING: &str = "lan-pairing";
+    /// Serves [`crate::sync::SEALED_STORAGE_ALPN`] when run as a storage peer.
+    pub const SEALED_STORAGE: &str = "sealed-storage";
+
+    pub const LOCAL: &[&str] = &[
+        PARTITION_ACCESS,
+        DEVICE_REVOCATION,
+        LAN_PAIRING,
+        SEALED_STORAGE,
+    ];
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProtocolVersionRange {
+    pub current: u32,
+    pub min_compatible: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncHello {
+    pub app_version: String,
+    pub protocols: BTreeMap<String, ProtocolVersionRange>,
+    pub features: BTreeSet<String>,
+}
+
+impl SyncHello {
+    pub fn local() -> Self {
+        let protocols = [
+            (
+                BIG_SYNC_PROTOCOL,
+                big_sync::rpc::BIG_SYNC_RPC_VERSION,
+                big_sync::rpc::BIG_SYNC_RPC_MIN_VERSION,
+            ),
+            (
+                REPO_SYNC_PROTOCOL,
+                big_repo::rpc::REPO_SYNC_RPC_VERSION,
+                big_repo::rpc::REPO_SYNC_RPC_MIN_VERSION,
+            ),
+            (
+                CLONE_PROVISION_PROTOCOL,
+                super::CLONE_PROVISION_RPC_VERSION,
+                super::CLONE_PROVISION_RPC_MIN_VERSION,
+            ),
+        ]
+        .into_iter()
+        .map(|(name, current, min_compatible)| {
+            (
+                name.to_string(),
+                ProtocolVersionRange {
+                    current,
+                    min_compatible,
+                },
+            )
+        })
+        .collect();
+        Self {
+            app_version: env!("CARGO_PKG_VERSION").into(),
+            protocols,
+            features: features::LOCAL
+                .iter()
+                .map(|feat| feat.to_string())
+                .collect(),
+        }
+    }
+}
+
+/// What was agreed on with a compatible peer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PeerSession {
+    pub app_version: String,
+    /// Version of each protocol to speak with the peer.
+    pub protocols: BTreeMap<String, u32>,
+    /// Features both sides support.
+    pub features: BTreeSet<String>,
+}
+
+impl PeerSession {
+    pub fn supports(&self, feature: &str) -> bool {
+        self.features.contains(feature)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error, displaydoc::Display)]
+/// {protocol} is not compatible with the peer running {remote_app_version}
+pub struct Incompatibility {
+    pub protocol: String,
+    /// Set when it's this device that's out of date.
+    pub local_needs_update: bool,
+    pub remote_app_version: String,
+}
+
+pub fn negotiate(local: &SyncHello, remote: &SyncHello) -> Result<PeerSession, Incompatibility> {
+    let incompatible = |protocol: &str, local_needs_update| Incompatibility {
+        protocol: protocol.to_string(),
+        local_needs_update,
+        remote_app_version: remote.app_version.clone(),
+    };
+    let mut protocols = BTreeMap::new();
+    for (name, local_range) in &local.protocols {
+        // peers from before a protocol existed are older builds
+        let Some(remote_range) = remote.protocols.get(name) else {
+            return Err(incompatible(name, false));
+        };
+        if local_range.current < remote_range.min_compatible {
+            return Err(incompatible(name, true));
+        }
+        if remote_range.current < local_range.min_compatible {
+            return Err(incompatible(name, false));
+        }
+        protocols.insert(name.clone(), local_range.current.min(remote_range.current));
+    }
+    // protocols only the remote knows of mean it's the newer one and
+    // it's up to its own ranges to decide if we're still usable
+    Ok(PeerSession {
+        app_version: remote.app_version.clone(),
+        protocols,
+        features: local
+            .features
+            .intersection(&remote.features)
+            .cloned()
+            .collect(),
+    })
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct HelloRpcReq {
+    pub hello: SyncHello,
+}
+
+#[rpc_requests(message = HandshakeRpcMessage)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum HandshakeRpc {
+    /// Replies with the responder's own hello regardless of compatibility
+    /// so that both sides get to tell which of them needs updating.
+    #[rpc(tx = channel::oneshot::Sender<SyncHello>)]
+    Hello(HelloRpcReq),
+}
+
+#[derive(Debug, Clone)]
+pub enum PeerCompat {
+    Compatible(PeerSession),
+    Incompatible(Incompatibility),
+}
+
+pub(super) type PeerSessions = Arc<std::sync::RwLock<HashMap<EndpointId, PeerCompat>>>;
+
+/// Records the outcome of a handshake, notifying an update requirement
+/// when the peer is incompatible.
+pub(super) fn record_outcome(
+    sessions: &PeerSessions,
+    registry: &crate::repos::ListenersRegistry,
+    endpoint_id: EndpointId,
+    outcome: Result<PeerSession, Incompatibility>,
+) -> Result<PeerSession, Incompatibility> {
+    let compat = match &outcome {
+        Ok(session) => PeerCompat::Compatible(session.clone()),
+        Err(incompat) => {
+            warn!(
+                %endpoint_id,
+                protocol = %incompat.protocol,
+                local_needs_update = incompat.local_needs_update,
+                remote_app_version = %incompat.remote_app_version,
+                "peer speaks an incompatible sync protocol version"
+            );
+            registry.notify([IrohSyncEvent::UpdateRequired {
+                peer_key: daybook_types::doc::format_peer_key(endpoint_id.as_bytes()),
+                protocol: incompat.protocol.clone(),
+                local_needs_update: incompat.local_needs_update,
+                remote_app_version: incompat.remote_app_version.clone(),
+            }]);
+            PeerCompat::Incompatible(incompat.clone())
+        }
+    };
+    sessions
+        .write()
+        .expect(ERROR_MUTEX)
+        .insert(endpoint_id, compat);
+    outcome
+}
+
+pub(super) fn is_incompatible(sessions: &PeerSessions, endpoint_id: &EndpointId) -> bool {
+    matches!(
+        sessions.read().expect(ERROR_MUTEX).get(endpoint_id),
+        Some(PeerCompat::Incompatible(_))
+    )
+}
+
+#[derive(educe::Educe)]
+#[educe(Debug)]
+pub(super) struct HandshakeProtocol {
+    #[educe(Debug(ignore))]
+    pub sessions: PeerSessions,
+    #[educe(Debug(ignore))]
+    pub registry: Arc<crate::repos::ListenersRegistry>,
+}
+
+impl ProtocolHandler for HandshakeProtocol {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        let remote_id = conn.remote_id();
+        loop {
+            let msg = match irpc_iroh::read_request::<HandshakeRpc>(&conn).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!(?err, "error reading handshake request");
+                    break;
+                }
+            };
+            match msg {
+                HandshakeRpcMessage::Hello(req) => {
+                    let WithChannels { inner, tx, .. } = req;
+                    let local = SyncHello::local();
+                    record_outcome(
+                        &self.sessions,
+                        &self.registry,
+                        remote_id,
+                        negotiate(&local, &inner.hello),
+                    )
+                    .ok();
+                    if tx.send(local).await.is_err() {
+                        break;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Sends our hello to the peer and negotiates against its reply.
+///
+/// NOTE: builds from before the handshake don't serve its ALPN and show
+/// up here as a transport error.
+pub async fn exchange_hello(
+    endpoint: &iroh::Endpoint,
+    endpoint_addr: iroh::EndpointAddr,
+) -> Res<Result<PeerSession, Incompatibility>> {
+    let client =
+        irpc_iroh::client::<HandshakeRpc>(endpoint.clone(), endpoint_addr, SYNC_HANDSHAKE_ALPN);
+    let local = SyncHello::local();
+    let remote = client
+        .rpc(HelloRpcReq {
+            hello: local.clone(),
+        })
+        .await
+        .wrap_err("sync handshake rpc transport failed")?;
+    Ok(negotiate(&local, &remote))
+}
+
+/// [`exchange_hello`] for one-off connections that have nobody to
+/// notify. Incompatibility is turned into an error.
+pub async fn ensure_compatible(
+    endpoint: &iroh::Endpoint,
+    endpoint_addr: iroh::EndpointAddr,
+) -> Res<PeerSession> {
+    match exchange_hello(endpoint, endpoint_addr).await? {
+        Ok(session) => Ok(session),
+        Err(incompat) if incompat.local_needs_update => Err(ferr!("update required: {incompat}")),
+        Err(incompat) => Err(ferr!("the peer needs updating: {incompat}")),
+    }
+}
@@ -0,0 +1,257 @@
+//! Local network peer discovery.
+//!
+//! Devices periodically announce their endpoint over UDP (multicast by
+//! default) tagged with a digest of the repo id. Announcements from known
+//! sync devices trigger a connection while unknown announcers are kept as
+//! pairing candidates until approved through
+//! [`IrohSyncRepo::allow_peer_by_endpoint_id`].
+
+use crate::interlude::*;
+
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+
+use iroh::EndpointId;
+use tokio::net::UdpSocket;
+
+use super::{IrohSyncEvent, IrohSyncRepo};
+
+pub const LAN_DISCOVERY_PORT: u16 = 47_321;
+pub const LAN_DISCOVERY_MULTICAST_GROUP: Ipv4Addr = Ipv4Addr::new(239, 255, 77, 77);
+const LAN_ANNOUNCEMENT_VERSION: u32 = 1;
+const MAX_ANNOUNCEMENT_BYTES: usize = 16 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct LanDiscoveryOpts {
+    pub announce_to: Vec<SocketAddr>,
+    pub interval: Duration,
+}
+
+impl Default for LanDiscoveryOpts {
+    fn default() -> Self {
+        Self {
+            announce_to: vec![SocketAddr::V4(SocketAddrV4::new(
+                LAN_DISCOVERY_MULTICAST_GROUP,
+                LAN_DISCOVERY_PORT,
+            ))],
+            interval: Duration::from_secs(5),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct LanAnnouncement {
+    version: u32,
+    /// Digest of the repo id so that we don't broadcast it in the clear.
+    repo_tag: String,
+    device_name: String,
+    endpoint_addr: iroh::EndpointAddr,
+}
+
+#[derive(Debug, Clone)]
+pub struct PairingCandidate {
+    pub endpoint_addr: iroh::EndpointAddr,
+    pub device_name: String,
+    pub last_seen_at: Timestamp,
+}
+
+fn repo_tag(repo_id: &str) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"daybook/lan-discovery/");
+    hasher.update(repo_id.as_bytes());
+    utils_rs::hash::encode_base58_multibase_blake3(*hasher.finalize().as_bytes())
+}
+
+/// Binds the discovery socket, joining the multicast group when bound to
+/// the unspecified address.
+pub async fn bind_lan_discovery_socket(bind_addr: SocketAddr) -> Res<UdpSocket> {
+    let socket = UdpSocket::bind(bind_addr)
+        .await
+        .wrap_err_with(|| format!("error binding lan discovery socket on {bind_addr}"))?;
+    if let SocketAddr::V4(addr) = bind_addr {
+        if addr.ip().is_unspecified() {
+            socket.join_multicast_v4(LAN_DISCOVERY_MULTICAST_GROUP, Ipv4Addr::UNSPECIFIED)?;
+            // other repos on the same machine should see us too
+            socket.set_multicast_loop_v4(true)?;
+        }
+    }
+    Ok(socket)
+}
+
+impl IrohSyncRepo {
+    /// Starts discovery on the default port and multicast group.
+    pub async fn start_default_lan_discovery(self: &Arc<Self>) -> Res<()> {
+        let socket = bind_lan_discovery_socket(SocketAddr::V4(SocketAddrV4::new(
+            Ipv4Addr::UNSPECIFIED,
+            LAN_DISCOVERY_PORT,
+        )))
+        .await?;
+        self.start_lan_discovery(socket, LanDiscoveryOpts::default())
+            .await
+    }
+
+    pub async fn start_lan_discovery(
+        self: &Arc<Self>,
+        socket: UdpSocket,
+        opts: LanDiscoveryOpts,
+    ) -> Res<()> {
+        self.ensure_repo_live()?;
+        let mut lan_task = self.lan_discovery_task.lock().expect(ERROR_MUTEX);
+        if lan_task
+            .as_ref()
+            .is_some_and(|handle| !handle.is_finished())
+        {
+            eyre::bail!("lan discovery already running");
+        }
+        let repo = Arc::clone(self);
+        let handle = tokio::spawn(
+            async move {
+                let cancel_token = repo.cancel_token.clone();
+                let res = cancel_token
+                    .run_until_cancelled(repo.lan_discovery_loop(socket, opts))
+                    .await;
+                if let Some(Err(err)) = res {
+                    warn!(?err, "lan discovery stopped");
+                }
+            }
+            .instrument(tracing::info_span!("IrohSyncRepo lan discovery task")),
+        );
+        *lan_task = Some(handle);
+        Ok(())
+    }
+
+    pub fn list_pairing_candidates(&self) -> Vec<PairingCandidate> {
+        self.pairing_candidates
+            .lock()
+            .expect(ERROR_MUTEX)
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    /// Removes and returns the pairing candidate for the endpoint, if any.
+    pub(super) fn take_pairing_candidate(
+        &self,
+        endpoint_id: &EndpointId,
+    ) -> Option<PairingCandidate> {
+        self.pairing_candidates
+            .lock()
+            .expect(ERROR_MUTEX)
+            .remove(endpoint_id)
+    }
+
+    async fn lan_discovery_loop(&self, socket: UdpSocket, opts: LanDiscoveryOpts) -> Res<()> {
+        let repo_tag = repo_tag(&self.rcx.repo_id);
+        let mut announce_tick = tokio::time::interval(opts.interval);
+        announce_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+        let mut buf = vec![0u8; MAX_ANNOUNCEMENT_BYTES];
+        loop {
+            tokio::select! {
+                _ = announce_tick.tick() => {
+                    let announcement = LanAnnouncement {
+                        version: LAN_ANNOUNCEMENT_VERSION,
+                        repo_tag: repo_tag.clone(),
+                        device_name: self.rcx.local_device_name.clone(),
+                        endpoint_addr: self.router.endpoint().addr(),
+                    };
+                    let payload = serde_json::to_vec(&announcement).expect(ERROR_JSON);
+                    for target in &opts.announce_to {
+                        if let Err(err) = socket.send_to(&payload, target).await {
+                            debug!(?err, %target, "error sending lan announcement");
+                        }
+                    }
+                }
+                res = socket.recv_from(&mut buf) => {
+                    let (len, from) = match res {
+                        Ok(val) => val,
+                        Err(err) => {
+                            debug!(?err, "error receiving lan announcement");
+                            continue;
+                        }
+                    };
+                    let announcement = match serde_json::from_slice::<LanAnnouncement>(&buf[..len]) {
+                        Ok(val) => val,
+                        Err(err) => {
+                            debug!(?err, %from, "ignoring malformed lan announcement");
+                            continue;
+                        }
+                    };
+                    if announcement.version != LAN_ANNOUNCEMENT_VERSION
+                        || announcement.repo_tag != repo_tag
+                    {
+                        continue;
+                    }
+                    self.handle_lan_announcement(announcement).await?;
+                }
+            }
+        }
+    }
+
+    async fn handle_lan_announcement(&self, announcement: LanAnnouncement) -> Res<()> {
+        let endpoint_id = announcement.endpoint_addr.id;
+        if endpoint_id == self.router.endpoint().id() {
+            return Ok(());
+        }
+        let is_known = self
+            .config_repo
+            .list_known_sync_devices()
+            .await?
+            .iter()
+            .any(|device| device.endpoint_id == endpoint_id);
+        if is_known {
+            let peer_id = PeerId::new(*endpoint_id.as_bytes());
+            if self.active_peers.read().await.contains_key(&peer_id) {
+                return Ok(());
+            }
+            debug!(%endpoint_id, "connecting to known device found on lan");
+            if let Err(err) = self.connect_endpoint_addr(announcement.endpoint_addr).await {
+                warn!(?err, %endpoint_id, "failed connecting to known device found on lan");
+            }
+            return Ok(());
+        }
+        let is_new = self
+            .pairing_candidates
+            .lock()
+            .expect(ERROR_MUTEX)
+            .insert(
+                endpoint_id,
+                PairingCandidate {
+                    endpoint_addr: announcement.endpoint_addr,
+                    device_name: announcement.device_name.clone(),
+                    last_seen_at: Timestamp::now(),
+                },
+            )
+            .is_none();
+        if is_new {
+            self.registry
+                .notify([IrohSyncEvent::PairingCandidateDiscovered {
+                    peer_key: daybook_types::doc::format_peer_key(endpoint_id.as_bytes()),
+                    device_name: announcement.device_name,
+                }]);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn announcements_round_trip_and_hide_repo_id() -> Res<()> {
+        let secret = iroh::SecretKey::generate();
+        let announcement = LanAnnouncement {
+            version: LAN_ANNOUNCEMENT_VERSION,
+            repo_tag: repo_tag("repo-123"),
+            device_name: "laptop".into(),
+            endpoint_addr: iroh::EndpointAddr::new(secret.public()),
+        };
+        let raw = serde_json::to_string(&announcement)?;
+        assert!(!raw.contains("repo-123"));
+        let parsed: LanAnnouncement = serde_json::from_str(&raw)?;
+        assert_eq!(parsed.repo_tag, repo_tag("repo-123"));
+        assert_ne!(parsed.repo_tag, repo_tag("repo-456"));
+        assert_eq!(parsed.endpoint_addr.id, secret.public());
+        Ok(())
+    }
+}
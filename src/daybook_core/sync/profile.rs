@@ -0,0 +1,344 @@
+//! Per-device selective sync profiles.
+//!
+//! A profile lives in the config doc keyed by the device's endpoint id so
+//! that it can be edited from any device. It narrows what the device pulls
+//! from its peers: which partitions it subscribes to, which docs from the
+//! replicated drawer partition it admits and which doc blobs it downloads.
+
+use crate::interlude::*;
+
+use automerge::ReadDoc;
+use daybook_types::doc::{Doc, FacetKey, FacetRaw};
+use daybook_types::manifest::{
+    DocPredicateClause, DocPredicateEvalMode, DocPredicateEvalRequirement, DocPredicateEvalResolved,
+};
+
+use crate::blobs::sync::BlobHints;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncProfile {
+    /// Partition labels to subscribe to. `None` subscribes to every
+    /// partition the repo knows about. The core docs partition is
+    /// always subscribed since the config itself lives there.
+    #[serde(default)]
+    pub partitions: Option<Vec<String>>,
+    /// Docs from the replicated drawer partition that don't match are
+    /// not materialized on this device.
+    #[serde(default)]
+    pub doc_predicate: Option<DocPredicateClause>,
+    #[serde(default)]
+    pub blob_policy: BlobSyncPolicy,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum BlobSyncPolicy {
+    /// Download every doc blob.
+    #[default]
+    All,
+    /// Never download doc blobs.
+    None,
+    /// Only download blobs smaller than `max_bytes`.
+    UnderBytes { max_bytes: u64 },
+    /// Only download blobs whose mime matches one of the patterns.
+    /// Patterns are either exact (`image/png`) or type wildcards (`image/*`).
+    ByMime { mimes: Vec<String> },
+    /// Don't subscribe to doc blobs at all, they're fetched when needed.
+    OnDemand,
+}
+
+/// What a [`SyncProfile`] makes of a doc blob offered by a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlobSyncDecision {
+    Download,
+    Skip,
+    /// The policy depends on the blob's size or mime but no local doc
+    /// references it yet. Ask again once its doc has been replicated.
+    AwaitHints,
+}
+
+impl SyncProfile {
+    pub fn subscribes_to(&self, partition_label: &str) -> bool {
+        if partition_label == super::CORE_DOCS_PARTITION_ID {
+            return true;
+        }
+        if partition_label == crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID
+            && matches!(
+                self.blob_policy,
+                BlobSyncPolicy::None | BlobSyncPolicy::OnDemand
+            )
+        {
+            return false;
+        }
+        match &self.partitions {
+            Some(partitions) => partitions.iter().any(|label| label == partition_label),
+            None => true,
+        }
+    }
+
+    /// Whether a doc blob should be downloaded eagerly. `hints` come from the
+    /// blob facets of local docs and are `None` if no replicated doc
+    /// references the blob (yet).
+    pub fn blob_decision(&self, hints: Option<&BlobHints>) -> BlobSyncDecision {
+        let wants = match (&self.blob_policy, hints) {
+            (BlobSyncPolicy::None | BlobSyncPolicy::OnDemand, _) => false,
+            (BlobSyncPolicy::All, _) if self.doc_predicate.is_none() => true,
+            // the blob's doc might not have been replicated yet, or it's
+            // one the doc predicate skips in which case it never will be
+            (_, None) => return BlobSyncDecision::AwaitHints,
+            (BlobSyncPolicy::All, Some(_)) => true,
+            (BlobSyncPolicy::UnderBytes { max_bytes }, Some(hints)) => {
+                hints.length_octets < *max_bytes
+            }
+            (BlobSyncPolicy::ByMime { mimes }, Some(hints)) => hints
+                .mime
+                .as_deref()
+                .is_some_and(|mime| mimes.iter().any(|pattern| mime_matches(pattern, mime))),
+        };
+        if wants {
+            BlobSyncDecision::Download
+        } else {
+            BlobSyncDecision::Skip
+        }
+    }
+
+    /// Whether every blob of the doc blob partition eventually gets a
+    /// decision. Blobs of docs the doc predicate skips keep waiting on
+    /// hints so the partition never counts as fully synced.
+    pub fn doc_blobs_can_settle(&self) -> bool {
+        self.doc_predicate.is_none()
+            || matches!(
+                self.blob_policy,
+                BlobSyncPolicy::None | BlobSyncPolicy::OnDemand
+            )
+    }
+
+    /// Evaluates the doc predicate in approximate mode. Facet field matches are
+    /// resolved against the doc itself while manifest-dependent clauses fall
+    /// back to tag presence which errs on the side of replicating.
+    pub fn admits_doc(&self, doc: &Doc) -> bool {
+        let Some(predicate) = &self.doc_predicate else {
+            return true;
+        };
+        let mut requirements = HashSet::new();
+        predicate.append_requirements(&mut requirements);
+        let mut resolved = HashMap::new();
+        for requirement in requirements {
+            if let DocPredicateEvalRequirement::FacetsOfTag(tag) = &requirement {
+                let facets = doc
+                    .facets
+                    .iter()
+                    .filter(|(key, _)| key.tag.to_string() == tag.0)
+                    .map(|(key, raw)| (key.clone(), raw.clone()))
+                    .collect();
+                resolved.insert(requirement, DocPredicateEvalResolved::FacetsOfTag(facets));
+            }
+        }
+        predicate.evaluate(doc, DocPredicateEvalMode::ApproxInterest, &resolved)
+    }
+
+    pub fn is_unrestricted(&self) -> bool {
+        self.partitions.is_none()
+            && self.doc_predicate.is_none()
+            && self.blob_policy == BlobSyncPolicy::All
+    }
+}
+
+fn mime_matches(pattern: &str, mime: &str) -> bool {
+    let mime = mime.split(';').next().unwrap_or(mime).trim();
+    match pattern.strip_suffix("/*") {
+        Some(kind) => mime
+            .split_once('/')
+            .is_some_and(|(mime_kind, _)| mime_kind.eq_ignore_ascii_case(kind)),
+        None => pattern.eq_ignore_ascii_case(mime),
+    }
+}
+
+/// Reads the facets out of a drawer branch doc. Returns `None` for
+/// automerge docs that aren't daybook docs.
+pub(crate) fn doc_from_branch_doc(am_doc: &automerge::Automerge) -> Res<Option<Doc>> {
+    if root_str(am_doc, "$schema")?.as_deref() != Some("daybook.doc") {
+        return Ok(None);
+    }
+    let Some(id) = root_str(am_doc, "id")? else {
+        return Ok(None);
+    };
+    let mut facets = HashMap::new();
+    if let Some((automerge::Value::Object(automerge::ObjType::Map), facets_obj)) =
+        am_doc.get(automerge::ROOT, "facets")?
+    {
+        let keys = am_doc
+            .map_range(&facets_obj, ..)
+            .map(|item| item.key.to_string())
+            .collect::<Vec<_>>();
+        for key in keys {
+            let value: Option<ThroughJson<FacetRaw>> =
+                autosurgeon::hydrate_prop(am_doc, &facets_obj, &*key)?;
+            if let Some(value) = value {
+                facets.insert(FacetKey::from(key.as_str()), value.0);
+            }
+        }
+    }
+    Ok(Some(Doc { id, facets }))
+}
+
+fn root_str(am_doc: &automerge::Automerge, key: &str) -> Res<Option<String>> {
+    match am_doc.get(automerge::ROOT, key)? {
+        Some((automerge::Value::Scalar(scalar), _)) => match &*scalar {
+            automerge::ScalarValue::Str(val) => Ok(Some(val.to_string())),
+            _ => Ok(None),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Admission filter for the repo sync backend that applies the doc predicate
+/// of a profile to drawer docs. Other automerge docs are always admitted.
+pub(crate) struct ProfileDocAdmissionFilter {
+    pub profile: SyncProfile,
+}
+
+impl big_repo::DocAdmissionFilter for ProfileDocAdmissionFilter {
+    fn admit(&self, doc_id: &DocumentId, am_doc: &automerge::Automerge) -> bool {
+        match doc_from_branch_doc(am_doc) {
+            Ok(Some(doc)) => self.profile.admits_doc(&doc),
+            Ok(None) => true,
+            Err(err) => {
+                warn!(%doc_id, ?err, "error reading doc for sync profile, admitting");
+                true
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use automerge::transaction::Transactable;
+
+    fn branch_doc(facets: &[(&str, serde_json::Value)]) -> Res<automerge::Automerge> {
+        let mut doc = automerge::AutoCommit::new();
+        doc.put(automerge::ROOT, "$schema", "daybook.doc")?;
+        doc.put(automerge::ROOT, "id", "doc-1")?;
+        let facets_obj = doc.put_object(automerge::ROOT, "facets", automerge::ObjType::Map)?;
+        for (key, value) in facets {
+            autosurgeon::reconcile_prop(&mut doc, &facets_obj, *key, ThroughJson(value.clone()))?;
+        }
+        Ok(automerge::Automerge::load(&doc.save())?)
+    }
+
+    #[test]
+    fn default_profile_subscribes_everything() {
+        let profile = SyncProfile::default();
+        assert!(profile.is_unrestricted());
+        for label in [
+            super::super::CORE_DOCS_PARTITION_ID,
+            crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID,
+            crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID,
+        ] {
+            assert!(profile.subscribes_to(label), "{label}");
+        }
+        assert_eq!(profile.blob_decision(None), BlobSyncDecision::Download);
+    }
+
+    #[test]
+    fn partitions_always_keep_core_docs() {
+        let profile = SyncProfile {
+            partitions: Some(vec![crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID.into()]),
+            ..default()
+        };
+        assert!(profile.subscribes_to(super::super::CORE_DOCS_PARTITION_ID));
+        assert!(profile.subscribes_to(crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID));
+        assert!(!profile.subscribes_to(crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID));
+    }
+
+    #[test]
+    fn on_demand_blobs_drop_doc_blob_partition() {
+        let profile = SyncProfile {
+            blob_policy: BlobSyncPolicy::OnDemand,
+            ..default()
+        };
+        assert!(!profile.subscribes_to(crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID));
+        assert!(profile.subscribes_to(crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID));
+    }
+
+    #[test]
+    fn blob_policy_decisions() {
+        let png = BlobHints {
+            length_octets: 4 * 1024 * 1024,
+            mime: Some("image/png".into()),
+        };
+        let note = BlobHints {
+            length_octets: 512,
+            mime: Some("text/markdown; charset=utf-8".into()),
+        };
+        let under = SyncProfile {
+            blob_policy: BlobSyncPolicy::UnderBytes {
+                max_bytes: 1024 * 1024,
+            },
+            ..default()
+        };
+        assert_eq!(under.blob_decision(Some(&png)), BlobSyncDecision::Skip);
+        assert_eq!(under.blob_decision(Some(&note)), BlobSyncDecision::Download);
+        assert_eq!(under.blob_decision(None), BlobSyncDecision::AwaitHints);
+
+        let by_mime = SyncProfile {
+            blob_policy: BlobSyncPolicy::ByMime {
+                mimes: vec!["text/*".into()],
+            },
+            ..default()
+        };
+        assert_eq!(by_mime.blob_decision(Some(&png)), BlobSyncDecision::Skip);
+        assert_eq!(
+            by_mime.blob_decision(Some(&note)),
+            BlobSyncDecision::Download
+        );
+
+        let filtered = SyncProfile {
+            doc_predicate: Some(DocPredicateClause::HasTag("org.example.note".into())),
+            ..default()
+        };
+        assert_eq!(
+            filtered.blob_decision(Some(&png)),
+            BlobSyncDecision::Download
+        );
+        assert_eq!(filtered.blob_decision(None), BlobSyncDecision::AwaitHints);
+        assert!(!filtered.doc_blobs_can_settle());
+
+        let no_blobs = SyncProfile {
+            blob_policy: BlobSyncPolicy::None,
+            ..default()
+        };
+        assert_eq!(no_blobs.blob_decision(None), BlobSyncDecision::Skip);
+    }
+
+    #[test]
+    fn doc_predicate_filters_branch_docs() -> Res<()> {
+        let profile = SyncProfile {
+            doc_predicate: Some(DocPredicateClause::HasTag("org.example.note".into())),
+            ..default()
+        };
+        let filter = ProfileDocAdmissionFilter { profile };
+        let doc_id = DocumentId::random();
+
+        let note = branch_doc(&[(
+            "org.example.note/main",
+            serde_json::json!({ "content": "hi" }),
+        )])?;
+        assert!(big_repo::DocAdmissionFilter::admit(&filter, &doc_id, &note));
+
+        let photo = branch_doc(&[("org.example.photo/main", serde_json::json!({ "width": 10 }))])?;
+        assert!(!big_repo::DocAdmissionFilter::admit(
+            &filter, &doc_id, &photo
+        ));
+
+        // non daybook docs like the drawer and app docs always pass
+        let other = automerge::Automerge::new();
+        assert!(big_repo::DocAdmissionFilter::admit(
+            &filter, &doc_id, &other
+        ));
+        Ok(())
+    }
+}
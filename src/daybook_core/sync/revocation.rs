@@ -0,0 +1,267 @@
+//! Device revocation and identity rotation.
+//!
+//! Both are signed records replicated through the config doc so that
+//! every device can check them no matter which peer relayed them.
+//! A revocation is signed by the device that issued it and a rotation
+//! by the old key of the device that rotated, vouching for its new
+//! endpoint. Revoked and retired endpoints are refused by every device.
+
+use crate::interlude::*;
+
+use iroh::EndpointId;
+
+use crate::config::ConfigRepo;
+use crate::repo::RepoCtx;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRevocation {
+    pub endpoint_id: EndpointId,
+    pub revoked_by: EndpointId,
+    pub revoked_at: Timestamp,
+    /// Base58 ed25519 signature by `revoked_by`.
+    pub signature: String,
+}
+
+impl DeviceRevocation {
+    pub fn sign(secret_key: &iroh::SecretKey, endpoint_id: EndpointId) -> Self {
+        let mut out = Self {
+            endpoint_id,
+            revoked_by: secret_key.public(),
+            revoked_at: Timestamp::now(),
+            signature: String::new(),
+        };
+        out.signature = encode_signature(secret_key, &out.signing_payload());
+        out
+    }
+
+    fn signing_payload(&self) -> Vec<u8> {
+        signing_payload(
+            b"daybook/device-revocation/v1",
+            &self.endpoint_id,
+            &self.revoked_by,
+            self.revoked_at,
+        )
+    }
+
+    pub fn verify(&self) -> Res<()> {
+        verify_signature(&self.revoked_by, &self.signing_payload(), &self.signature)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct IdentityRotation {
+    pub old_endpoint_id: EndpointId,
+    pub new_endpoint_id: EndpointId,
+    pub rotated_at: Timestamp,
+    /// Base58 ed25519 signature by `old_endpoint_id`.
+    pub signature: String,
+}
+
+impl IdentityRotation {
+    pub fn sign(old_secret_key: &iroh::SecretKey, new_endpoint_id: EndpointId) -> Self {
+        let mut out = Self {
+            old_endpoint_id: old_secret_key.public(),
+            new_endpoint_id,
+            rotated_at: Timestamp::now(),
+            signature: String::new(),
+        };
+        out.signature = encode_signature(old_secret_key, &out.signing_payload());
+        out
+    }
+
+    fn signing_payload(&self) -> Vec<u8> {
+        signing_payload(
+            b"daybook/identity-rotation/v1",
+            &self.old_endpoint_id,
+            &self.new_endpoint_id,
+            self.rotated_at,
+        )
+    }
+
+    pub fn verify(&self) -> Res<()> {
+        verify_signature(
+            &self.old_endpoint_id,
+            &self.signing_payload(),
+            &self.signature,
+        )
+    }
+}
+
+fn signing_payload(
+    domain: &[u8],
+    subject: &EndpointId,
+    issuer: &EndpointId,
+    at: Timestamp,
+) -> Vec<u8> {
+    let mut out = Vec::with_capacity(domain.len() + 32 + 32 + 8);
+    out.extend_from_slice(domain);
+    out.extend_from_slice(subject.as_bytes());
+    out.extend_from_slice(issuer.as_bytes());
+    out.extend_from_slice(&at.as_second().to_be_bytes());
+    out
+}
+
+fn encode_signature(secret_key: &iroh::SecretKey, payload: &[u8]) -> String {
+    utils_rs::hash::encode_base58_multibase(secret_key.sign(payload).to_bytes())
+}
+
+fn verify_signature(signer: &EndpointId, payload: &[u8], signature: &str) -> Res<()> {
+    let bytes =
+        utils_rs::hash::decode_base58_multibase(signature).wrap_err("error decoding signature")?;
+    let bytes: [u8; 64] = bytes
+        .try_into()
+        .map_err(|_| ferr!("signature has bad length"))?;
+    signer
+        .verify(payload, &iroh::Signature::from_bytes(&bytes))
+        .map_err(|err| ferr!("invalid signature from {signer}: {err}"))
+}
+
+/// The revocations and rotations that hold up after checking signatures
+/// and issuers.
+#[derive(Debug, Clone, Default)]
+pub struct DeviceTrust {
+    pub revoked: HashMap<EndpointId, DeviceRevocation>,
+    /// Retired endpoints mapped to the endpoint that replaced them.
+    pub rotated: HashMap<EndpointId, EndpointId>,
+}
+
+impl DeviceTrust {
+    /// Records are applied in timestamp order and records issued by an
+    /// endpoint that was already revoked or retired are ignored.
+    pub fn resolve(
+        revocations: impl IntoIterator<Item = DeviceRevocation>,
+        rotations: impl IntoIterator<Item = IdentityRotation>,
+    ) -> Self {
+        enum Record {
+            Revocation(DeviceRevocation),
+            Rotation(IdentityRotation),
+        }
+        let mut records = revocations
+            .into_iter()
+            .map(|rec| (rec.revoked_at, Record::Revocation(rec)))
+            .chain(
+                rotations
+                    .into_iter()
+                    .map(|rec| (rec.rotated_at, Record::Rotation(rec))),
+            )
+            .collect::<Vec<_>>();
+        records.sort_by_key(|(at, _)| *at);
+
+        let mut out = Self::default();
+        for (_, record) in records {
+            match record {
+                Record::Revocation(rec) => {
+                    if rec.endpoint_id == rec.revoked_by || out.is_refused(&rec.revoked_by) {
+                        continue;
+                    }
+                    if let Err(err) = rec.verify() {
+                        warn!(?err, endpoint_id = %rec.endpoint_id, "ignoring bad revocation");
+                        continue;
+                    }
+                    out.revoked.insert(rec.endpoint_id, rec);
+                }
+                Record::Rotation(rec) => {
+                    if rec.old_endpoint_id == rec.new_endpoint_id
+                        || out.is_refused(&rec.old_endpoint_id)
+                    {
+                        continue;
+                    }
+                    if let Err(err) = rec.verify() {
+                        warn!(?err, endpoint_id = %rec.old_endpoint_id, "ignoring bad rotation");
+                        continue;
+                    }
+                    out.rotated.insert(rec.old_endpoint_id, rec.new_endpoint_id);
+                }
+            }
+        }
+        out
+    }
+
+    pub fn is_refused(&self, endpoint_id: &EndpointId) -> bool {
+        self.revoked.contains_key(endpoint_id) || self.rotated.contains_key(endpoint_id)
+    }
+
+    pub fn refused_endpoints(&self) -> HashSet<EndpointId> {
+        self.revoked
+            .keys()
+            .chain(self.rotated.keys())
+            .copied()
+            .collect()
+    }
+
+    /// Follows rotations to the endpoint the device currently uses.
+    pub fn current_endpoint(&self, endpoint_id: EndpointId) -> EndpointId {
+        let mut current = endpoint_id;
+        // bounded in case of a cycle
+        for _ in 0..=self.rotated.len() {
+            match self.rotated.get(&current) {
+                Some(next) => current = *next,
+                None => break,
+            }
+        }
+        current
+    }
+
+    /// User path device ids of the revoked endpoints.
+    pub fn revoked_device_ids(&self) -> HashSet<String> {
+        self.revoked
+            .keys()
+            .map(|endpoint_id| {
+                daybook_types::doc::user_path::device_id_for_endpoint(endpoint_id.as_bytes())
+            })
+            .collect()
+    }
+}
+
+/// Revokes a device for the whole repo. Every device that receives the
+/// revocation refuses connections from the endpoint and forgets it.
+pub async fn revoke_device(
+    rcx: &RepoCtx,
+    config_repo: &ConfigRepo,
+    endpoint_id: EndpointId,
+) -> Res<DeviceRevocation> {
+    if endpoint_id == rcx.iroh_secret_key.public() {
+        eyre::bail!("revoking the local device is not supported, rotate its identity instead");
+    }
+    let revocation = DeviceRevocation::sign(&rcx.iroh_secret_key, endpoint_id);
+    config_repo
+        .add_device_revocation(revocation.clone())
+        .await?;
+    Ok(revocation)
+}
+
+/// Replaces the local device's iroh identity with a fresh one and
+/// announces the new endpoint to the other devices under the current
+/// key. The new identity is used the next time the repo is opened.
+/// Until then the device stays reachable under the current key so that
+/// the announcement gets to replicate.
+pub async fn rotate_identity(rcx: &RepoCtx, config_repo: &ConfigRepo) -> Res<EndpointId> {
+    let old_secret = rcx.iroh_secret_key.clone();
+    let old_endpoint_id = old_secret.public();
+    let new_secret = iroh::SecretKey::generate();
+    let new_endpoint_id = new_secret.public();
+    rcx.secret_repo
+        .set_identity(&rcx.checkout_id, new_secret)
+        .await?;
+    let res = async {
+        if let Some(profile) = config_repo.get_sync_profile(&old_endpoint_id).await {
+            config_repo
+                .set_sync_profile(&new_endpoint_id, profile)
+                .await?;
+        }
+        config_repo
+            .add_identity_rotation(IdentityRotation::sign(&old_secret, new_endpoint_id))
+            .await
+    }
+    .await;
+    if let Err(err) = res {
+        // keep the old key so that we're not locked out
+        rcx.secret_repo
+            .set_identity(&rcx.checkout_id, old_secret)
+            .await?;
+        return Err(err);
+    }
+    Ok(new_endpoint_id)
+}
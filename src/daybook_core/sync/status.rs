@@ -0,0 +1,440 @@
+//! Per peer and per partition sync status kept in the repo SQL so that it
+//! outlives the connections and events it's derived from.
+
+use crate::interlude::*;
+
+use sqlx_utils_rs::SqlCtx;
+
+use super::PeerKey;
+
+/// Lag samples older than this are pruned.
+const LAG_HISTORY_RETENTION_SECS: i64 = 7 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PeerSyncStatus {
+    pub peer_key: PeerKey,
+    pub last_connected_at: Option<Timestamp>,
+    pub last_full_sync_at: Option<Timestamp>,
+    /// Set while the peer has changes we haven't caught up with.
+    pub stale_since: Option<Timestamp>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<Timestamp>,
+    pub blob_bytes_received: u64,
+    pub rpc_bytes_sent: u64,
+    /// Blobs fetched from the peer that haven't landed yet.
+    pub blob_backlog: u64,
+    pub partitions: Vec<PartitionSyncStatus>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartitionSyncStatus {
+    pub partition: String,
+    pub last_full_sync_at: Option<Timestamp>,
+    pub stale_since: Option<Timestamp>,
+    /// How many cursor positions the peer is ahead of what we've pulled
+    /// as of `lag_sampled_at`.
+    pub cursor_lag: Option<u64>,
+    pub lag_sampled_at: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LagSample {
+    pub partition: String,
+    pub at: Timestamp,
+    pub cursor_lag: u64,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct SyncStatusStore {
+    sql: SqlCtx,
+}
+
+fn opt_ts(unix_secs: Option<i64>) -> Res<Option<Timestamp>> {
+    unix_secs
+        .map(Timestamp::from_second)
+        .transpose()
+        .map_err(Into::into)
+}
+
+impl SyncStatusStore {
+    pub async fn load(sql: SqlCtx) -> Res<Self> {
+        let this = Self { sql };
+        this.init_schema().await?;
+        Ok(this)
+    }
+
+    async fn init_schema(&self) -> Res<()> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_peer_status (
+                peer_key TEXT PRIMARY KEY,
+                last_connected_at_unix_secs INTEGER,
+                last_full_sync_at_unix_secs INTEGER,
+                stale_since_unix_secs INTEGER,
+                last_error TEXT,
+                last_error_at_unix_secs INTEGER,
+                blob_bytes_received INTEGER NOT NULL DEFAULT 0,
+                rpc_bytes_sent INTEGER NOT NULL DEFAULT 0,
+                blob_backlog INTEGER NOT NULL DEFAULT 0
+            ) STRICT
+            "#,
+        )
+        .execute(&self.sql.write_pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_peer_part_status (
+                peer_key TEXT NOT NULL,
+                partition TEXT NOT NULL,
+                last_full_sync_at_unix_secs INTEGER,
+                stale_since_unix_secs INTEGER,
+                cursor_lag INTEGER,
+                lag_sampled_at_unix_secs INTEGER,
+                PRIMARY KEY(peer_key, partition)
+            ) STRICT
+            "#,
+        )
+        .execute(&self.sql.write_pool)
+        .await?;
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS sync_peer_lag_history (
+                peer_key TEXT NOT NULL,
+                partition TEXT NOT NULL,
+                at_unix_secs INTEGER NOT NULL,
+                cursor_lag INTEGER NOT NULL
+            ) STRICT
+            "#,
+        )
+        .execute(&self.sql.write_pool)
+        .await?;
+        sqlx::query(
+            "CREATE INDEX IF NOT EXISTS idx_sync_peer_lag_history_peer_at ON sync_peer_lag_history(peer_key, at_unix_secs)",
+        )
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn ensure_peer(&self, peer_key: &str) -> Res<()> {
+        sqlx::query("INSERT INTO sync_peer_status(peer_key) VALUES (?1) ON CONFLICT DO NOTHING")
+            .bind(peer_key)
+            .execute(&self.sql.write_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn ensure_part(&self, peer_key: &str, partition: &str) -> Res<()> {
+        self.ensure_peer(peer_key).await?;
+        sqlx::query(
+            "INSERT INTO sync_peer_part_status(peer_key, partition) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+        )
+        .bind(peer_key)
+        .bind(partition)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn record_connected(&self, peer_key: &str, at: Timestamp) -> Res<()> {
+        self.ensure_peer(peer_key).await?;
+        sqlx::query(
+            "UPDATE sync_peer_status SET last_connected_at_unix_secs = ?2 WHERE peer_key = ?1",
+        )
+        .bind(peer_key)
+        .bind(at.as_second())
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    /// Records a full sync of the whole peer if `partition` is `None`.
+    pub async fn record_full_sync(
+        &self,
+        peer_key: &str,
+        partition: Option<&str>,
+        at: Timestamp,
+    ) -> Res<()> {
+        match partition {
+            Some(partition) => {
+                self.ensure_part(peer_key, partition).await?;
+                sqlx::query(
+                    r#"
+                    UPDATE sync_peer_part_status
+                    SET last_full_sync_at_unix_secs = ?3, stale_since_unix_secs = NULL
+                    WHERE peer_key = ?1 AND partition = ?2
+                    "#,
+                )
+                .bind(peer_key)
+                .bind(partition)
+                .bind(at.as_second())
+                .execute(&self.sql.write_pool)
+                .await?;
+            }
+            None => {
+                self.ensure_peer(peer_key).await?;
+                sqlx::query(
+                    r#"
+                    UPDATE sync_peer_status
+                    SET last_full_sync_at_unix_secs = ?2, stale_since_unix_secs = NULL
+                    WHERE peer_key = ?1
+                    "#,
+                )
+                .bind(peer_key)
+                .bind(at.as_second())
+                .execute(&self.sql.write_pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Marks the peer, or one of its partitions, stale. Already stale
+    /// entries keep the time they went stale.
+    pub async fn record_stale(
+        &self,
+        peer_key: &str,
+        partition: Option<&str>,
+        at: Timestamp,
+    ) -> Res<()> {
+        match partition {
+            Some(partition) => {
+                self.ensure_part(peer_key, partition).await?;
+                sqlx::query(
+                    r#"
+                    UPDATE sync_peer_part_status
+                    SET stale_since_unix_secs = COALESCE(stale_since_unix_secs, ?3)
+                    WHERE peer_key = ?1 AND partition = ?2
+                    "#,
+                )
+                .bind(peer_key)
+                .bind(partition)
+                .bind(at.as_second())
+                .execute(&self.sql.write_pool)
+                .await?;
+            }
+            None => {
+                self.ensure_peer(peer_key).await?;
+                sqlx::query(
+                    r#"
+                    UPDATE sync_peer_status
+                    SET stale_since_unix_secs = COALESCE(stale_since_unix_secs, ?2)
+                    WHERE peer_key = ?1
+                    "#,
+                )
+                .bind(peer_key)
+                .bind(at.as_second())
+                .execute(&self.sql.write_pool)
+                .await?;
+            }
+        }
+        Ok(())
+    }
+
+    pub async fn record_error(&self, peer_key: &str, error: &str, at: Timestamp) -> Res<()> {
+        self.ensure_peer(peer_key).await?;
+        sqlx::query(
+            "UPDATE sync_peer_status SET last_error = ?2, last_error_at_unix_secs = ?3 WHERE peer_key = ?1",
+        )
+        .bind(peer_key)
+        .bind(error)
+        .bind(at.as_second())
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn add_bytes(
+        &self,
+        peer_key: &str,
+        blob_bytes_received: u64,
+        rpc_bytes_sent: u64,
+    ) -> Res<()> {
+        if blob_bytes_received == 0 && rpc_bytes_sent == 0 {
+            return Ok(());
+        }
+        self.ensure_peer(peer_key).await?;
+        sqlx::query(
+            r#"
+            UPDATE sync_peer_status
+            SET blob_bytes_received = blob_bytes_received + ?2,
+                rpc_bytes_sent = rpc_bytes_sent + ?3
+            WHERE peer_key = ?1
+            "#,
+        )
+        .bind(peer_key)
+        .bind(blob_bytes_received as i64)
+        .bind(rpc_bytes_sent as i64)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    pub async fn set_blob_backlog(&self, peer_key: &str, blob_backlog: u64) -> Res<()> {
+        self.ensure_peer(peer_key).await?;
+        sqlx::query("UPDATE sync_peer_status SET blob_backlog = ?2 WHERE peer_key = ?1")
+            .bind(peer_key)
+            .bind(blob_backlog as i64)
+            .execute(&self.sql.write_pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Updates the partition's current lag and appends it to the history.
+    pub async fn record_lag(
+        &self,
+        peer_key: &str,
+        partition: &str,
+        cursor_lag: u64,
+        at: Timestamp,
+    ) -> Res<()> {
+        self.ensure_part(peer_key, partition).await?;
+        let mut tx = self.sql.write_pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE sync_peer_part_status
+            SET cursor_lag = ?3, lag_sampled_at_unix_secs = ?4
+            WHERE peer_key = ?1 AND partition = ?2
+            "#,
+        )
+        .bind(peer_key)
+        .bind(partition)
+        .bind(cursor_lag as i64)
+        .bind(at.as_second())
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query(
+            "INSERT INTO sync_peer_lag_history(peer_key, partition, at_unix_secs, cursor_lag) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(peer_key)
+        .bind(partition)
+        .bind(at.as_second())
+        .bind(cursor_lag as i64)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM sync_peer_lag_history WHERE at_unix_secs < ?1")
+            .bind(at.as_second() - LAG_HISTORY_RETENTION_SECS)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    pub async fn list(&self) -> Res<Vec<PeerSyncStatus>> {
+        type PeerRow = (
+            String,
+            Option<i64>,
+            Option<i64>,
+            Option<i64>,
+            Option<String>,
+            Option<i64>,
+            i64,
+            i64,
+            i64,
+        );
+        type PartRow = (
+            String,
+            String,
+            Option<i64>,
+            Option<i64>,
+            Option<i64>,
+            Option<i64>,
+        );
+        let peer_rows: Vec<PeerRow> = sqlx::query_as(
+            r#"
+            SELECT peer_key, last_connected_at_unix_secs, last_full_sync_at_unix_secs,
+                   stale_since_unix_secs, last_error, last_error_at_unix_secs,
+                   blob_bytes_received, rpc_bytes_sent, blob_backlog
+            FROM sync_peer_status ORDER BY peer_key
+            "#,
+        )
+        .fetch_all(&self.sql.read_pool)
+        .await?;
+        let part_rows: Vec<PartRow> = sqlx::query_as(
+            r#"
+            SELECT peer_key, partition, last_full_sync_at_unix_secs, stale_since_unix_secs,
+                   cursor_lag, lag_sampled_at_unix_secs
+            FROM sync_peer_part_status ORDER BY peer_key, partition
+            "#,
+        )
+        .fetch_all(&self.sql.read_pool)
+        .await?;
+
+        let mut partitions: HashMap<String, Vec<PartitionSyncStatus>> = default();
+        for (peer_key, partition, full_sync_at, stale_since, cursor_lag, lag_sampled_at) in
+            part_rows
+        {
+            partitions
+                .entry(peer_key)
+                .or_default()
+                .push(PartitionSyncStatus {
+                    partition,
+                    last_full_sync_at: opt_ts(full_sync_at)?,
+                    stale_since: opt_ts(stale_since)?,
+                    cursor_lag: cursor_lag.map(|lag| lag as u64),
+                    lag_sampled_at: opt_ts(lag_sampled_at)?,
+                });
+        }
+        peer_rows
+            .into_iter()
+            .map(
+                |(
+                    peer_key,
+                    connected_at,
+                    full_sync_at,
+                    stale_since,
+                    last_error,
+                    last_error_at,
+                    blob_bytes_received,
+                    rpc_bytes_sent,
+                    blob_backlog,
+                )| {
+                    Ok(PeerSyncStatus {
+                        partitions: partitions.remove(&peer_key).unwrap_or_default(),
+                        peer_key: peer_key.into(),
+                        last_connected_at: opt_ts(connected_at)?,
+                        last_full_sync_at: opt_ts(full_sync_at)?,
+                        stale_since: opt_ts(stale_since)?,
+                        last_error,
+                        last_error_at: opt_ts(last_error_at)?,
+                        blob_bytes_received: blob_bytes_received as u64,
+                        rpc_bytes_sent: rpc_bytes_sent as u64,
+                        blob_backlog: blob_backlog as u64,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Lag samples of the peer taken at or after `since`, oldest first.
+    pub async fn lag_history(&self, peer_key: &str, since: Timestamp) -> Res<Vec<LagSample>> {
+        let rows: Vec<(String, i64, i64)> = sqlx::query_as(
+            r#"
+            SELECT partition, at_unix_secs, cursor_lag FROM sync_peer_lag_history
+            WHERE peer_key = ?1 AND at_unix_secs >= ?2
+            ORDER BY at_unix_secs, rowid
+            "#,
+        )
+        .bind(peer_key)
+        .bind(since.as_second())
+        .fetch_all(&self.sql.read_pool)
+        .await?;
+        rows.into_iter()
+            .map(|(partition, at, cursor_lag)| {
+                Ok(LagSample {
+                    partition,
+                    at: Timestamp::from_second(at)?,
+                    cursor_lag: cursor_lag as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Reads the persisted status without booting an [`super::IrohSyncRepo`].
+pub async fn read_sync_status(rcx: &crate::repo::RepoCtx) -> Res<Vec<PeerSyncStatus>> {
+    SyncStatusStore::load(rcx.sql.clone()).await?.list().await
+}
@@ -1,5 +1,6 @@
 use super::*;
 mod ladder;
+mod rpc_fixtures;
 mod stress;
 
 use crate::blobs::{BlobId, BlobsRepo};
@@ -26,6 +27,7 @@ struct SyncTestNode {
     _plugs_repo: Arc<PlugsRepo>,
     plugs_stop: crate::repos::RepoStopToken,
     config_stop: crate::repos::RepoStopToken,
+    doc_blobs_index_repo: Arc<DocBlobsIndexRepo>,
     doc_blobs_index_stop: crate::repos::RepoStopToken,
     sqlite_local_state_stop: crate::repos::RepoStopToken,
     doc_blobs_bridge_cancel: CancellationToken,
@@ -46,6 +48,7 @@ impl SyncTestNode {
             _plugs_repo,
             plugs_stop,
             config_stop,
+            doc_blobs_index_repo: _doc_blobs_index_repo,
             doc_blobs_index_stop,
             sqlite_local_state_stop,
             doc_blobs_bridge_cancel,
@@ -169,6 +172,474 @@ async fn iroh_sync_between_copied_repos() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_status_is_recorded_per_peer_and_partition() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+    let since = Timestamp::now();
+
+    let sync_url = node_a.sync_repo.get_clone_ticket_url().await?;
+    let endpoint_addr = node_b.sync_repo.connect_url(&sync_url).await?;
+    wait_for_sync_convergence(&node_a, &node_b, endpoint_addr.id, Duration::from_secs(20)).await?;
+    let peer_key_a = daybook_types::doc::format_peer_key(endpoint_addr.id.as_bytes());
+
+    // the full sync event is recorded by the sync loop after the waiter
+    // gets released
+    let status = tokio::time::timeout(utils_rs::scale_timeout(Duration::from_secs(10)), async {
+        loop {
+            let status = node_b
+                .sync_repo
+                .sync_status()
+                .await?
+                .into_iter()
+                .find(|status| status.peer_key == peer_key_a);
+            if let Some(status) = status.filter(|status| status.last_full_sync_at.is_some()) {
+                return eyre::Ok(status);
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .map_err(|_| ferr!("full sync was never recorded"))??;
+    assert!(status.last_connected_at.is_some());
+    assert_eq!(status.stale_since, None);
+    assert_eq!(status.last_error, None);
+
+    node_b.sync_repo.refresh_sync_status().await?;
+    let status = read_sync_status(&node_b.ctx)
+        .await?
+        .into_iter()
+        .find(|status| status.peer_key == peer_key_a)
+        .expect("peer status not persisted");
+    let core_docs = status
+        .partitions
+        .iter()
+        .find(|part| part.partition == CORE_DOCS_PARTITION_ID)
+        .expect("core docs partition status missing");
+    assert_eq!(core_docs.cursor_lag, Some(0), "converged peer still lags");
+    let history = node_b
+        .sync_repo
+        .sync_lag_history(&peer_key_a, since)
+        .await?;
+    assert!(
+        history
+            .iter()
+            .any(|sample| sample.partition == CORE_DOCS_PARTITION_ID),
+        "lag sample missing from history: {history:?}"
+    );
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn incoming_allow_list_rejects_unlisted_endpoints() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+
+    node_a.sync_repo.set_incoming_allow_list(Some(default()));
+    let sync_url = node_a.sync_repo.get_clone_ticket_url().await?;
+    node_b.sync_repo.connect_url(&sync_url).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        node_a.sync_repo.connected_peer_keys().await.is_empty(),
+        "unlisted endpoint was accepted"
+    );
+    assert!(
+        node_b.sync_repo.connected_peer_keys().await.is_empty(),
+        "connection to a gated endpoint was kept"
+    );
+
+    node_a
+        .sync_repo
+        .set_incoming_allow_list(Some([endpoint_b].into()));
+    let endpoint_addr = node_b.sync_repo.connect_url(&sync_url).await?;
+    wait_for_sync_convergence(&node_a, &node_b, endpoint_addr.id, Duration::from_secs(20)).await?;
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn peer_access_scopes_partitions_per_device() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+    let peer_b = PeerId::new(*endpoint_b.as_bytes());
+    let all_partitions = node_a.sync_repo.peer_partition_ids("");
+    assert_eq!(
+        node_a.sync_repo.partition_ids_for_peer(peer_b),
+        all_partitions
+    );
+
+    node_a
+        .sync_repo
+        .config_repo
+        .upsert_known_sync_device(crate::repo::globals::SyncDeviceEntry {
+            endpoint_id: endpoint_b,
+            name: "repo-b".into(),
+            added_at: Timestamp::now(),
+            last_connected_at: None,
+            access: crate::repo::globals::PeerAccess {
+                read_only: true,
+                partitions: Some(vec![crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID.into()]),
+            },
+            storage_peer: false,
+        })
+        .await?;
+    node_a.sync_repo.apply_peer_access().await?;
+
+    let scoped = node_a.sync_repo.partition_ids_for_peer(peer_b);
+    let expected = [
+        CORE_DOCS_PARTITION_ID,
+        crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID,
+    ]
+    .into_iter()
+    .map(crate::part_id_from_label)
+    .collect::<HashSet<_>>();
+    assert_eq!(scoped.keys().copied().collect::<HashSet<_>>(), expected);
+    {
+        use big_sync::rpc::PartAccessPolicy;
+        // docs outside of any partition aren't served to scoped devices
+        assert!(!node_a.sync_repo.peer_access.can_read_unpartitioned(peer_b));
+    }
+
+    // devices without an entry keep full access
+    let stranger = PeerId::new([7; 32]);
+    assert_eq!(
+        node_a.sync_repo.partition_ids_for_peer(stranger),
+        all_partitions
+    );
+    {
+        use big_sync::rpc::PartAccessPolicy;
+        assert!(node_a
+            .sync_repo
+            .peer_access
+            .can_read_unpartitioned(stranger));
+    }
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn storage_peer_relays_sealed_docs_between_devices() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let (storage, storage_stop) = StoragePeer::boot(StoragePeerOpts {
+        root: temp_root.path().join("storage"),
+        allow_list: None,
+    })
+    .await?;
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+
+    let new_doc_id = node_a
+        .drawer
+        .add(AddDocArgs {
+            branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+            facets: default(),
+            user_path: Some(daybook_types::doc::UserPathBuf::from(
+                node_a.ctx.local_user_path.clone(),
+            )),
+        })
+        .await?;
+
+    let key = node_a.sync_repo.enable_vault().await?;
+    node_b.sync_repo.import_vault_key(&key).await?;
+    for node in [&node_a, &node_b] {
+        node.sync_repo
+            .config_repo
+            .upsert_known_sync_device(crate::repo::globals::SyncDeviceEntry {
+                endpoint_id: storage.endpoint_id(),
+                name: "storage".into(),
+                added_at: Timestamp::now(),
+                last_connected_at: None,
+                access: default(),
+                storage_peer: true,
+            })
+            .await?;
+        node.sync_repo
+            .connect_storage_peer(storage.endpoint_addr())
+            .await?;
+    }
+    let storage_peer = PeerId::new(*storage.endpoint_id().as_bytes());
+    assert_eq!(
+        node_b
+            .sync_repo
+            .partition_ids_for_peer(storage_peer)
+            .into_keys()
+            .collect::<Vec<_>>(),
+        [crate::part_id_from_label(VAULT_SEALED_PARTITION_ID)],
+        "storage peers only get the sealed partition"
+    );
+    let peer_b = PeerId::new(*node_b.sync_repo.endpoint_addr().id.as_bytes());
+    assert!(!node_a
+        .sync_repo
+        .partition_ids_for_peer(peer_b)
+        .contains_key(&crate::part_id_from_label(VAULT_SEALED_PARTITION_ID)));
+
+    // the devices never talk to each other directly
+    node_a.sync_repo.sync_vault_now().await?;
+    wait_for_doc_presence_with_activity(&node_b, &new_doc_id, Duration::from_secs(60)).await?;
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    storage_stop.stop().await?;
+    Ok(())
+}
+
+#[test]
+fn handshake_negotiates_overlapping_versions() {
+    let local = SyncHello::local();
+    let session = handshake::negotiate(&local, &local).expect("self should be compatible");
+    assert!(session.supports(handshake::features::DEVICE_REVOCATION));
+
+    let mut newer = local.clone();
+    newer.app_version = "99.0.0".into();
+    newer.features.insert("from-the-future".into());
+    newer.protocols.insert(
+        "from-the-future".into(),
+        handshake::ProtocolVersionRange {
+            current: 1,
+            min_compatible: 1,
+        },
+    );
+    let big_sync = newer
+        .protocols
+        .get_mut(handshake::BIG_SYNC_PROTOCOL)
+        .expect(ERROR_IMPOSSIBLE);
+    big_sync.current += 1;
+    let session = handshake::negotiate(&local, &newer).expect("newer peer still speaks ours");
+    assert_eq!(
+        session.protocols[handshake::BIG_SYNC_PROTOCOL],
+        big_sync::rpc::BIG_SYNC_RPC_VERSION
+    );
+    assert!(!session.supports("from-the-future"));
+
+    let big_sync = newer
+        .protocols
+        .get_mut(handshake::BIG_SYNC_PROTOCOL)
+        .expect(ERROR_IMPOSSIBLE);
+    big_sync.min_compatible = big_sync.current;
+    let incompat = handshake::negotiate(&local, &newer).expect_err("we're too old");
+    assert!(incompat.local_needs_update);
+    assert_eq!(incompat.protocol, handshake::BIG_SYNC_PROTOCOL);
+    let incompat = handshake::negotiate(&newer, &local).expect_err("peer is too old");
+    assert!(!incompat.local_needs_update);
+
+    let mut older = local.clone();
+    older.protocols.remove(handshake::REPO_SYNC_PROTOCOL);
+    let incompat = handshake::negotiate(&local, &older).expect_err("peer lacks a protocol");
+    assert!(!incompat.local_needs_update);
+    assert_eq!(incompat.protocol, handshake::REPO_SYNC_PROTOCOL);
+}
+
+#[tokio::test]
+async fn incompatible_handshake_notifies_and_gates_peer() -> Res<()> {
+    let registry = crate::repos::ListenersRegistry::new();
+    let listener = registry.subscribe::<IrohSyncEvent>(SubscribeOpts::new(8));
+    let sessions: handshake::PeerSessions = default();
+    let endpoint_id = iroh::SecretKey::generate().public();
+
+    let local = SyncHello::local();
+    let mut newer = local.clone();
+    for range in newer.protocols.values_mut() {
+        range.current += 1;
+        range.min_compatible = range.current;
+    }
+    let res = handshake::record_outcome(
+        &sessions,
+        &registry,
+        endpoint_id,
+        handshake::negotiate(&local, &newer),
+    );
+    assert!(res.is_err());
+    assert!(handshake::is_incompatible(&sessions, &endpoint_id));
+    let event = listener.recv_async().await.expect("listener closed");
+    assert!(matches!(
+        &*event,
+        IrohSyncEvent::UpdateRequired {
+            local_needs_update: true,
+            ..
+        }
+    ));
+
+    // retrying after an update clears the gate
+    handshake::record_outcome(
+        &sessions,
+        &registry,
+        endpoint_id,
+        handshake::negotiate(&local, &local),
+    )?;
+    assert!(!handshake::is_incompatible(&sessions, &endpoint_id));
+    Ok(())
+}
+
+#[test]
+fn device_trust_ignores_records_from_refused_issuers() {
+    let key_a = iroh::SecretKey::generate();
+    let key_b = iroh::SecretKey::generate();
+    let key_c = iroh::SecretKey::generate();
+    let key_c2 = iroh::SecretKey::generate();
+
+    let revoke_b = DeviceRevocation::sign(&key_a, key_b.public());
+    let late_revoke_a = DeviceRevocation::sign(&key_b, key_a.public());
+    let rotate_c = IdentityRotation::sign(&key_c, key_c2.public());
+    let mut forged = DeviceRevocation::sign(&key_c, key_a.public());
+    forged.revoked_by = key_b.public();
+
+    let trust = DeviceTrust::resolve([revoke_b, late_revoke_a, forged], [rotate_c]);
+    assert!(trust.is_refused(&key_b.public()));
+    assert!(
+        !trust.is_refused(&key_a.public()),
+        "revoked or forged issuers shouldn't be able to revoke"
+    );
+    assert!(trust.is_refused(&key_c.public()));
+    assert!(!trust.is_refused(&key_c2.public()));
+    assert_eq!(trust.current_endpoint(key_c.public()), key_c2.public());
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn revoked_devices_are_refused() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+
+    node_a.sync_repo.revoke_device(endpoint_b).await?;
+    assert!(node_a
+        .sync_repo
+        .config_repo
+        .list_known_sync_devices()
+        .await?
+        .iter()
+        .all(|device| device.endpoint_id != endpoint_b));
+    assert!(node_a
+        .sync_repo
+        .connect_endpoint_addr(iroh::EndpointAddr::new(endpoint_b))
+        .await
+        .is_err());
+
+    let sync_url = node_a.sync_repo.get_clone_ticket_url().await?;
+    node_b.sync_repo.connect_url(&sync_url).await?;
+    tokio::time::sleep(Duration::from_millis(500)).await;
+    assert!(
+        node_a.sync_repo.connected_peer_keys().await.is_empty(),
+        "revoked endpoint was accepted"
+    );
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn lan_discovery_pairs_unknown_devices_on_approval() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+    let endpoint_a = node_a.sync_repo.router.endpoint().id();
+    node_b
+        .sync_repo
+        .config_repo
+        .remove_known_sync_device(&endpoint_a)
+        .await?;
+
+    let socket_a = bind_lan_discovery_socket((std::net::Ipv4Addr::LOCALHOST, 0).into()).await?;
+    let socket_b = bind_lan_discovery_socket((std::net::Ipv4Addr::LOCALHOST, 0).into()).await?;
+    let opts_for = |socket: &tokio::net::UdpSocket| -> Res<LanDiscoveryOpts> {
+        Ok(LanDiscoveryOpts {
+            announce_to: vec![socket.local_addr()?],
+            interval: Duration::from_millis(100),
+        })
+    };
+    let opts_a = opts_for(&socket_b)?;
+    let opts_b = opts_for(&socket_a)?;
+    node_a
+        .sync_repo
+        .start_lan_discovery(socket_a, opts_a)
+        .await?;
+    node_b
+        .sync_repo
+        .start_lan_discovery(socket_b, opts_b)
+        .await?;
+
+    tokio::time::timeout(utils_rs::scale_timeout(Duration::from_secs(10)), async {
+        while !node_b
+            .sync_repo
+            .list_pairing_candidates()
+            .iter()
+            .any(|candidate| candidate.endpoint_addr.id == endpoint_a)
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+    })
+    .await
+    .wrap_err("timed out waiting for pairing candidate")?;
+    // unknown announcers are not connected to without approval
+    assert!(!node_b
+        .sync_repo
+        .active_peers
+        .read()
+        .await
+        .contains_key(&PeerId::new(*endpoint_a.as_bytes())));
+
+    node_b
+        .sync_repo
+        .allow_peer_by_endpoint_id(endpoint_a)
+        .await?;
+    assert!(node_b
+        .sync_repo
+        .config_repo
+        .list_known_sync_devices()
+        .await?
+        .iter()
+        .any(|device| device.endpoint_id == endpoint_a));
+    assert!(node_b.sync_repo.list_pairing_candidates().is_empty());
+    wait_for_sync_convergence(&node_a, &node_b, endpoint_a, Duration::from_secs(20)).await?;
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn iroh_live_sync_bidirectional_after_clone() -> Res<()> {
     utils_rs::testing::setup_tracing_once();
@@ -230,7 +701,7 @@ async fn iroh_live_sync_propagates_repeated_doc_updates() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -314,7 +785,7 @@ async fn cloned_repo_registers_core_docs_partition_on_open() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -369,7 +840,7 @@ async fn bootstrap_ticket_in_tests_omits_relay_addresses() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -496,6 +967,79 @@ async fn iroh_blob_sync_validates_bytes() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_bundle_import_converges_and_is_idempotent() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+
+    let payload = b"sneakernet-blob".to_vec();
+    let hash = node_a
+        .blobs_repo
+        .put(&payload, crate::blobs::BlobUseHints::Docs)
+        .await?;
+    let doc_id = node_a
+        .drawer
+        .add(AddDocArgs {
+            branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+            facets: [(
+                FacetKey::from(WellKnownFacetTag::Blob),
+                FacetRaw::from(WellKnownFacet::Blob(daybook_types::doc::Blob {
+                    mime: "application/octet-stream".to_string(),
+                    length_octets: payload.len() as u64,
+                    digest: crate::blobs::blob_id_to_digest_str(hash),
+                    inline: None,
+                    urls: Some(vec![format!("db+blob:///{hash}")]),
+                })),
+            )]
+            .into(),
+            user_path: Some(daybook_types::doc::UserPathBuf::from(
+                node_a.ctx.local_user_path.clone(),
+            )),
+        })
+        .await?;
+
+    let bundle_path = temp_root.path().join("a.dbbundle");
+    let exported = node_a.sync_repo.export_bundle(&bundle_path, 0).await?;
+    assert!(exported.doc_count > 0);
+    assert_eq!(exported.blob_count, 1);
+
+    node_b
+        .sync_repo
+        .import_bundle(&bundle_path, Duration::from_secs(60))
+        .await?;
+    wait_for_doc_presence_with_activity(&node_b, &doc_id, Duration::from_secs(30)).await?;
+    let got = wait_for_blob_bytes(&node_b.blobs_repo, hash, Duration::from_secs(30)).await?;
+    assert_eq!(got, payload);
+    let main = daybook_types::doc::BranchPathBuf::from("main");
+    wait_for_doc_head_parity(&node_a, &node_b, &doc_id, &main, Duration::from_secs(30)).await?;
+
+    let heads_before = node_b.drawer.get_with_heads(&doc_id, &main, None).await?;
+    node_b
+        .sync_repo
+        .import_bundle(&bundle_path, Duration::from_secs(60))
+        .await?;
+    let heads_after = node_b.drawer.get_with_heads(&doc_id, &main, None).await?;
+    assert_eq!(
+        heads_before.map(|(_, heads)| heads),
+        heads_after.map(|(_, heads)| heads),
+        "re-importing a bundle must not change anything"
+    );
+    assert_eq!(
+        list_doc_ids(&node_a.drawer).await?,
+        list_doc_ids(&node_b.drawer).await?
+    );
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
 #[tokio::test(flavor = "multi_thread")]
 async fn iroh_sync_after_bootstrap_clone_converges() -> Res<()> {
     utils_rs::testing::setup_tracing_once();
@@ -507,7 +1051,7 @@ async fn iroh_sync_after_bootstrap_clone_converges() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -555,6 +1099,73 @@ async fn iroh_sync_after_bootstrap_clone_converges() -> Res<()> {
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn shallow_clone_lists_docs_and_pulls_contents_later() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+
+    tokio::fs::create_dir_all(&repo_a_path).await?;
+    let device_name = "test-device".to_string();
+    let rtx = RepoCtx::init(
+        &repo_a_path,
+        RepoOpenOptions::default(),
+        device_name.clone(),
+        device_name,
+    )
+    .await?;
+    rtx.shutdown().await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let mut created_doc_ids = Vec::new();
+    for _ in 0..4 {
+        let new_doc_id = node_a
+            .drawer
+            .add(daybook_types::doc::AddDocArgs {
+                branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+                facets: default(),
+                user_path: Some(daybook_types::doc::UserPathBuf::from(
+                    node_a.ctx.local_user_path.clone(),
+                )),
+            })
+            .await?;
+        created_doc_ids.push(new_doc_id);
+    }
+
+    let sync_url = node_a.sync_repo.get_clone_ticket_url().await?;
+    crate::sync::clone_repo_init_from_url(
+        &sync_url,
+        &repo_b_path,
+        crate::sync::CloneRepoInitOptions {
+            timeout: Duration::from_secs(30),
+            shallow: true,
+        },
+    )
+    .await?;
+
+    let node_b = open_sync_node(&repo_b_path).await?;
+    assert!(
+        crate::repo::globals::get_sync_config(&node_b.ctx.sql)
+            .await?
+            .fetch_docs_on_demand
+    );
+    // the listing lives in the core docs so it's there before any sync
+    assert_eq!(
+        list_doc_ids(&node_a.drawer).await?,
+        list_doc_ids(&node_b.drawer).await?,
+    );
+
+    node_b.sync_repo.connect_url(&sync_url).await?;
+    for doc_id in &created_doc_ids {
+        wait_for_doc_presence_with_activity(&node_b, doc_id, Duration::from_secs(60)).await?;
+    }
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
 async fn init_and_copy_repo_pair(
     repo_a_path: &std::path::Path,
     repo_b_path: &std::path::Path,
@@ -563,7 +1174,7 @@ async fn init_and_copy_repo_pair(
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -578,7 +1189,12 @@ async fn init_and_copy_repo_pair(
         let ticket = seed_node.sync_repo.get_clone_ticket_url().await?;
         bootstrap_clone_repo_from_url_for_tests(&ticket, repo_b_path).await?;
 
-        let ctx = RepoCtx::open(repo_b_path, RepoOpenOptions {}, "test-device".into()).await?;
+        let ctx = RepoCtx::open(
+            repo_b_path,
+            RepoOpenOptions::default(),
+            "test-device".into(),
+        )
+        .await?;
         if ctx.repo_id != source_repo_id {
             eyre::bail!(
                 "init repo_id mismatch after clone (source={}, cloned={})",
@@ -616,6 +1232,7 @@ async fn bootstrap_clone_repo_from_url_for_tests(
         destination,
         crate::sync::CloneRepoInitOptions {
             timeout: Duration::from_secs(30),
+            shallow: false,
         },
     )
     .await?;
@@ -623,7 +1240,7 @@ async fn bootstrap_clone_repo_from_url_for_tests(
 }
 
 async fn open_sync_node(repo_root: &std::path::Path) -> Res<SyncTestNode> {
-    let rtx = RepoCtx::open(repo_root, RepoOpenOptions {}, "test-device".into()).await?;
+    let rtx = RepoCtx::open(repo_root, RepoOpenOptions::default(), "test-device".into()).await?;
     let blobs_repo = BlobsRepo::new(
         rtx.layout.blobs_root.clone(),
         rtx.local_user_path.clone(),
@@ -643,6 +1260,7 @@ async fn open_sync_node(repo_root: &std::path::Path) -> Res<SyncTestNode> {
         Arc::clone(&rtx.big_repo),
         Arc::clone(&rtx.part_store),
         rtx.doc_drawer.document_id(),
+        Some(rtx.doc_app.document_id()),
         daybook_types::doc::UserPathBuf::from(rtx.local_user_path.clone()),
         rtx.sql.clone(),
         rtx.layout.repo_root.join("local_state"),
@@ -663,8 +1281,11 @@ async fn open_sync_node(repo_root: &std::path::Path) -> Res<SyncTestNode> {
         rtx.sql.clone(),
     )
     .await?;
-    let (sqlite_local_state_repo, sqlite_local_state_stop) =
-        SqliteLocalStateRepo::boot(rtx.layout.repo_root.join("local_state")).await?;
+    let (sqlite_local_state_repo, sqlite_local_state_stop) = SqliteLocalStateRepo::boot(
+        rtx.layout.repo_root.join("local_state"),
+        rtx.data_key.clone(),
+    )
+    .await?;
     let (doc_blobs_index_repo, doc_blobs_index_stop) = DocBlobsIndexRepo::boot(
         Arc::clone(&drawer_repo),
         Arc::clone(&blobs_repo),
@@ -695,6 +1316,7 @@ async fn open_sync_node(repo_root: &std::path::Path) -> Res<SyncTestNode> {
         _plugs_repo: plugs_repo,
         plugs_stop,
         config_stop,
+        doc_blobs_index_repo,
         doc_blobs_index_stop,
         sqlite_local_state_stop,
         doc_blobs_bridge_cancel,
@@ -719,6 +1341,7 @@ fn spawn_doc_blobs_index_bridge_for_tests(
                 evt = drawer_listener.recv_async() => {
                     match evt {
                         Ok(evt) => match evt.as_ref() {
+                            crate::drawer::DrawerEvent::FacetConflicts { .. } => {}
                             crate::drawer::DrawerEvent::DocDeleted { id, .. } => {
                                 doc_blobs_index_repo.enqueue_delete(id.clone()).unwrap_or_log();
                             }
@@ -808,12 +1431,14 @@ async fn wait_for_doc_presence_with_activity(
                     match evt.as_ref() {
                         crate::drawer::DrawerEvent::DocAdded { id, .. }
                         | crate::drawer::DrawerEvent::DocUpdated { id, .. }
-                        | crate::drawer::DrawerEvent::DocDeleted { id, .. } if id == doc_id => {
+                        | crate::drawer::DrawerEvent::DocDeleted { id, .. }
+                        | crate::drawer::DrawerEvent::FacetConflicts { id, .. } if id == doc_id => {
                             *last_activity_for_wait.lock().expect(ERROR_MUTEX) = std::time::Instant::now();
                         }
                         crate::drawer::DrawerEvent::DocAdded { .. }
                         | crate::drawer::DrawerEvent::DocUpdated { .. }
-                        | crate::drawer::DrawerEvent::DocDeleted { .. } => {
+                        | crate::drawer::DrawerEvent::DocDeleted { .. }
+                        | crate::drawer::DrawerEvent::FacetConflicts { .. } => {
                             *last_activity_for_wait.lock().expect(ERROR_MUTEX) = std::time::Instant::now();
                         }
                     }
@@ -926,6 +1551,274 @@ async fn wait_for_full_sync_succeeds_after_event_was_already_emitted() -> Res<()
     Ok(())
 }
 
+#[tokio::test(flavor = "multi_thread")]
+async fn sync_profile_narrows_peer_subscriptions() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+
+    let ticket_a = node_a.sync_repo.get_clone_ticket_url().await?;
+    let endpoint_addr_ba = node_b.sync_repo.connect_url(&ticket_a).await?;
+    let peer_a = PeerId::new(*endpoint_addr_ba.id.as_bytes());
+    wait_for_sync_convergence(
+        &node_a,
+        &node_b,
+        endpoint_addr_ba.id,
+        Duration::from_secs(20),
+    )
+    .await?;
+
+    let doc_blobs_part = crate::part_id_from_label(crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID);
+    let plugs_blobs_part = crate::part_id_from_label(crate::blobs::BLOB_SCOPE_PLUGS_PARTITION_ID);
+    let snapshot = node_b.sync_repo.big_sync_worker.snapshot().await?;
+    let subscribed = &snapshot.peer_parts[&peer_a];
+    assert!(subscribed.contains_key(&doc_blobs_part));
+    assert!(subscribed.contains_key(&plugs_blobs_part));
+
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+    node_b
+        .sync_repo
+        .config_repo
+        .set_sync_profile(
+            &endpoint_b,
+            SyncProfile {
+                partitions: Some(vec![crate::blobs::BLOB_SCOPE_DOCS_PARTITION_ID.into()]),
+                blob_policy: BlobSyncPolicy::OnDemand,
+                ..default()
+            },
+        )
+        .await?;
+    node_b.sync_repo.refresh_sync_profile().await?;
+
+    let snapshot = node_b.sync_repo.big_sync_worker.snapshot().await?;
+    let subscribed = snapshot.peer_parts[&peer_a]
+        .keys()
+        .copied()
+        .collect::<HashSet<_>>();
+    // on-demand blobs drop the doc blob partition even though it's listed
+    // and the core docs partition is always kept
+    assert_eq!(
+        subscribed,
+        [crate::part_id_from_label(CORE_DOCS_PARTITION_ID)].into()
+    );
+
+    // the narrowed subscription counts as fully synced
+    let required_partitions = node_b
+        .sync_repo
+        .known_partitions()
+        .into_iter()
+        .filter(|(label, ..)| *label != VAULT_SEALED_PARTITION_ID)
+        .map(|(_, part_id, _)| part_id)
+        .collect::<Vec<_>>();
+    node_b
+        .sync_repo
+        .wait_for_full_sync(
+            std::slice::from_ref(&peer_a),
+            &required_partitions,
+            Duration::from_secs(20),
+        )
+        .await?;
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn wait_for_full_sync_under_blob_size_profile() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+    node_b
+        .sync_repo
+        .config_repo
+        .set_sync_profile(
+            &endpoint_b,
+            SyncProfile {
+                blob_policy: BlobSyncPolicy::UnderBytes { max_bytes: 256 },
+                ..default()
+            },
+        )
+        .await?;
+    node_b.sync_repo.refresh_sync_profile().await?;
+
+    let small = b"small blob under the profile limit".to_vec();
+    let large = vec![b'x'; 4096];
+    let mut args_batch = Vec::new();
+    let mut hashes = Vec::new();
+    for payload in [&small, &large] {
+        let hash = node_a
+            .blobs_repo
+            .put(payload, crate::blobs::BlobUseHints::Docs)
+            .await?;
+        hashes.push(hash);
+        args_batch.push(AddDocArgs {
+            branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+            facets: [(
+                FacetKey::from(WellKnownFacetTag::Blob),
+                FacetRaw::from(WellKnownFacet::Blob(daybook_types::doc::Blob {
+                    mime: "application/octet-stream".to_string(),
+                    length_octets: payload.len() as u64,
+                    digest: crate::blobs::blob_id_to_digest_str(hash),
+                    inline: None,
+                    urls: Some(vec![format!("db+blob:///{hash}")]),
+                })),
+            )]
+            .into(),
+            user_path: Some(daybook_types::doc::UserPathBuf::from(
+                node_a.ctx.local_user_path.clone(),
+            )),
+        });
+    }
+    node_a.drawer.batch_add(args_batch).await?;
+
+    let ticket_a = node_a.sync_repo.get_clone_ticket_url().await?;
+    let endpoint_addr_ba = node_b.sync_repo.connect_url(&ticket_a).await?;
+    let peer_a = PeerId::new(*endpoint_addr_ba.id.as_bytes());
+    let required_partitions = node_b
+        .sync_repo
+        .peer_partition_ids("")
+        .into_keys()
+        .collect::<Vec<_>>();
+    // blobs offered before their docs got replicated are held back until
+    // the hints show up rather than being skipped for good
+    node_b
+        .sync_repo
+        .wait_for_full_sync(
+            std::slice::from_ref(&peer_a),
+            &required_partitions,
+            Duration::from_secs(60),
+        )
+        .await?;
+
+    let got = wait_for_blob_bytes(&node_b.blobs_repo, hashes[0], Duration::from_secs(5)).await?;
+    assert_eq!(got, small);
+    assert!(!node_b.blobs_repo.has_hash(hashes[1]).await?);
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn on_demand_fetch_reports_expected_size() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let temp_root = tempfile::tempdir()?;
+    let repo_a_path = temp_root.path().join("repo-a");
+    let repo_b_path = temp_root.path().join("repo-b");
+    init_and_copy_repo_pair(&repo_a_path, &repo_b_path).await?;
+
+    let node_a = open_sync_node(&repo_a_path).await?;
+    let node_b = open_sync_node(&repo_b_path).await?;
+
+    let endpoint_b = node_b.sync_repo.endpoint_addr().id;
+    node_b
+        .sync_repo
+        .config_repo
+        .set_sync_profile(
+            &endpoint_b,
+            SyncProfile {
+                blob_policy: BlobSyncPolicy::OnDemand,
+                ..default()
+            },
+        )
+        .await?;
+    node_b.sync_repo.refresh_sync_profile().await?;
+
+    let payload = vec![b'z'; 2048];
+    let hash = node_a
+        .blobs_repo
+        .put(&payload, crate::blobs::BlobUseHints::Docs)
+        .await?;
+    node_a
+        .drawer
+        .batch_add(vec![AddDocArgs {
+            branch_path: daybook_types::doc::BranchPathBuf::from("main"),
+            facets: [(
+                FacetKey::from(WellKnownFacetTag::Blob),
+                FacetRaw::from(WellKnownFacet::Blob(daybook_types::doc::Blob {
+                    mime: "application/octet-stream".to_string(),
+                    length_octets: payload.len() as u64,
+                    digest: crate::blobs::blob_id_to_digest_str(hash),
+                    inline: None,
+                    urls: Some(vec![format!("db+blob:///{hash}")]),
+                })),
+            )]
+            .into(),
+            user_path: Some(daybook_types::doc::UserPathBuf::from(
+                node_a.ctx.local_user_path.clone(),
+            )),
+        }])
+        .await?;
+
+    let ticket_a = node_a.sync_repo.get_clone_ticket_url().await?;
+    let endpoint_addr_ba = node_b.sync_repo.connect_url(&ticket_a).await?;
+    let peer_a = PeerId::new(*endpoint_addr_ba.id.as_bytes());
+    let required_partitions = node_b
+        .sync_repo
+        .peer_partition_ids("")
+        .into_keys()
+        .collect::<Vec<_>>();
+    node_b
+        .sync_repo
+        .wait_for_full_sync(
+            std::slice::from_ref(&peer_a),
+            &required_partitions,
+            Duration::from_secs(30),
+        )
+        .await?;
+    assert!(!node_b.blobs_repo.has_hash(hash).await?);
+
+    tokio::time::timeout(Duration::from_secs(10), async {
+        while node_b
+            .doc_blobs_index_repo
+            .blob_hints_for_hash(&hash.to_string())
+            .await?
+            .is_none()
+        {
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        eyre::Ok(())
+    })
+    .await
+    .map_err(|_| eyre::eyre!("timeout waiting for blob hints to be indexed"))??;
+
+    let path = node_b.blobs_repo.get_path(hash).await?;
+    assert_eq!(tokio::fs::read(&path).await?, payload);
+
+    let task_id = format!("blobs/fetch/{}", crate::blobs::blob_hash_from_id(hash));
+    let updates = node_b.progress_repo.list_updates(&task_id).await?;
+    assert!(
+        updates.iter().any(|entry| matches!(
+            entry.update.deets,
+            crate::progress::ProgressUpdateDeets::Amount {
+                total: Some(total),
+                ..
+            } if total == payload.len() as u64
+        )),
+        "fetch progress is missing the expected size: {updates:?}"
+    );
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
+
 async fn wait_for_doc_set_parity(
     left: &DrawerRepo,
     right: &DrawerRepo,
@@ -12,7 +12,7 @@ async fn boot_connected_sync_pair(
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -244,7 +244,7 @@ async fn iroh_sync_single_doc_created_before_connect_replicates() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -315,7 +315,7 @@ async fn iroh_sync_single_blob_created_before_connect_replicates() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -407,7 +407,7 @@ async fn iroh_sync_single_doc_created_while_connected_replicates() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -480,7 +480,7 @@ async fn iroh_sync_single_blob_created_while_connected_replicates() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -968,7 +968,7 @@ async fn clone_bootstrap_populates_all_globals_and_can_open() -> Res<()> {
     let device_name = "test-device".to_string();
     let rtx = RepoCtx::init(
         &repo_a_path,
-        RepoOpenOptions {},
+        RepoOpenOptions::default(),
         device_name.clone(),
         device_name,
     )
@@ -984,8 +984,12 @@ async fn clone_bootstrap_populates_all_globals_and_can_open() -> Res<()> {
     bootstrap_clone_repo_from_url_for_tests(&ticket, &repo_b_path).await?;
     node_a.stop().await?;
 
-    let cloned =
-        RepoCtx::open(&repo_b_path, RepoOpenOptions {}, "clone-device".to_string()).await?;
+    let cloned = RepoCtx::open(
+        &repo_b_path,
+        RepoOpenOptions::default(),
+        "clone-device".to_string(),
+    )
+    .await?;
 
     assert_eq!(
         cloned.repo_id, source_repo_id,
@@ -1028,3 +1032,103 @@ async fn clone_bootstrap_populates_all_globals_and_can_open() -> Res<()> {
     cloned.shutdown().await?;
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn iroh_sync_read_only_peer_live_edits_are_not_applied() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let (_temp_root, node_a, node_b, _) = boot_connected_sync_pair().await?;
+    {
+        let title_key = FacetKey::from(WellKnownFacetTag::TitleGeneric);
+        let branch = BranchPathBuf::from("main");
+        let doc_id = node_a
+            .drawer
+            .add(daybook_types::doc::AddDocArgs {
+                branch_path: branch.clone(),
+                facets: [(
+                    title_key.clone(),
+                    WellKnownFacet::TitleGeneric("Base title".into()).into(),
+                )]
+                .into(),
+                user_path: Some(daybook_types::doc::UserPathBuf::from(
+                    node_a.ctx.local_user_path.clone(),
+                )),
+            })
+            .await?;
+        wait_for_doc_presence_with_activity(&node_b, &doc_id, Duration::from_secs(60)).await?;
+        assert_title_synced(&node_a, &node_b, &doc_id, "Base title").await?;
+
+        node_a
+            .sync_repo
+            .config_repo
+            .upsert_known_sync_device(crate::repo::globals::SyncDeviceEntry {
+                endpoint_id: node_b.sync_repo.endpoint_addr().id,
+                name: "repo-b".into(),
+                added_at: Timestamp::now(),
+                last_connected_at: None,
+                access: crate::repo::globals::PeerAccess {
+                    read_only: true,
+                    partitions: None,
+                },
+                storage_peer: false,
+            })
+            .await?;
+        node_a.sync_repo.apply_peer_access().await?;
+        let (_, heads_before) = node_a
+            .drawer
+            .get_with_heads(&doc_id, &branch, None)
+            .await?
+            .ok_or_eyre("node_a lost the doc")?;
+
+        // the edit goes out over the live session right away
+        update_title_at_main_branch(&node_b, &doc_id, "B edit").await?;
+        tokio::time::sleep(utils_rs::scale_timeout(Duration::from_secs(5))).await;
+        node_a
+            .sync_repo
+            .big_sync_worker
+            .wait_for_idle(Duration::from_secs(30))
+            .await?;
+
+        let (doc_on_a, heads_after) = node_a
+            .drawer
+            .get_with_heads(&doc_id, &branch, None)
+            .await?
+            .ok_or_eyre("node_a lost the doc")?;
+        assert_eq!(heads_before, heads_after);
+        assert_eq!(
+            doc_on_a.facets.get(&title_key),
+            Some(&serde_json::Value::from(WellKnownFacet::TitleGeneric(
+                "Base title".into()
+            ))),
+        );
+
+        // the read-only peer still gets our edits
+        update_title_at_main_branch(&node_a, &doc_id, "A edit").await?;
+        let (_, heads_on_a) = node_a
+            .drawer
+            .get_with_heads(&doc_id, &branch, None)
+            .await?
+            .ok_or_eyre("node_a lost the doc")?;
+        tokio::time::timeout(Duration::from_secs(30), async {
+            loop {
+                let (_, heads_on_b) = node_b
+                    .drawer
+                    .get_with_heads(&doc_id, &branch, None)
+                    .await?
+                    .ok_or_eyre("node_b lost the doc")?;
+                // b keeps its own rejected edit next to ours
+                if heads_on_a.0.iter().all(|head| heads_on_b.0.contains(head)) {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(200)).await;
+            }
+            eyre::Ok(())
+        })
+        .await
+        .map_err(|_| eyre::eyre!("timeout waiting for the read-only peer to get our edit"))??;
+    }
+
+    node_b.stop().await?;
+    node_a.stop().await?;
+    Ok(())
+}
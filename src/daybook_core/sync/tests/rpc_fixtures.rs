@@ -0,0 +1,343 @@
+//! Pinned postcard encodings of every message sent over the sync rpcs.
+//!
+//! A failure here means the wire format changed. Bump the protocol's
+//! version (and its min version if old peers can't decode the new
+//! format) in the same change before updating the fixture.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use big_repo::rpc::{
+    BigRepoRpcError, FullDoc, GetDocsFullRequest, GetDocsFullResponse, RepoSyncRpc,
+};
+use big_sync::rpc::BigSyncIrpc;
+use big_sync_core::rpc::*;
+use big_sync_core::{BuckId, Fingerprint, FingerprintSeed, ObjId};
+
+use super::*;
+use crate::sync::handshake::{HandshakeRpc, HelloRpcReq, ProtocolVersionRange};
+
+const PART_ID: PartId = PartId::new([1; 32]);
+const OBJ_ID: ObjId = ObjId::new([2; 32]);
+const SEED: FingerprintSeed = FingerprintSeed::new(1, 2);
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+fn assert_fixture<T>(name: &str, value: &T, fixture: &str)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let bytes = postcard::to_stdvec(value).expect(ERROR_IMPOSSIBLE);
+    assert_eq!(
+        to_hex(&bytes),
+        fixture,
+        "{name} changed on the wire, bump its protocol version"
+    );
+    let decoded: T = postcard::from_bytes(&bytes).expect("fixture doesn't decode");
+    assert_eq!(
+        postcard::to_stdvec(&decoded).expect(ERROR_IMPOSSIBLE),
+        bytes,
+        "{name} doesn't round trip"
+    );
+}
+
+#[test]
+fn protocol_versions_are_pinned() {
+    let hello = SyncHello::local();
+    let versions = hello
+        .protocols
+        .iter()
+        .map(|(name, range)| (name.as_str(), (range.current, range.min_compatible)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        versions,
+        [
+            ("big-sync", (1, 1)),
+            ("clone-provision", (1, 1)),
+            ("repo-sync", (1, 1)),
+        ],
+        "update the fixtures below along with the version bump"
+    );
+}
+
+#[test]
+fn big_sync_rpc_fixtures() {
+    assert_fixture(
+        "PeerSummary",
+        &BigSyncIrpc::PeerSummary(PeerSummaryRequest {
+            parts: [PART_ID].into(),
+        }),
+        "0001200101010101010101010101010101010101010101010101010101010101010101",
+    );
+    assert_fixture(
+        "SubParts",
+        &BigSyncIrpc::SubParts(SubPartsRequest {
+            parts: vec![PartStreamCursorRequest {
+                part_id: PART_ID,
+                cursor: 7,
+            }],
+        }),
+        "010120010101010101010101010101010101010101010101010101010101010101010107",
+    );
+    assert_fixture(
+        "GetChangedBuckets",
+        &BigSyncIrpc::GetChangedBuckets(GetChangedBucketsRequest {
+            part_id: PART_ID,
+            offset: BuckId::ROOT,
+            since: 7,
+            limit_hint: 64,
+        }),
+        "02200101010101010101010101010101010101010101010101010101010101010101000740",
+    );
+    assert_fixture(
+        "LeafBuckets",
+        &BigSyncIrpc::LeafBuckets(LeafBucketsRequest {
+            part_id: PART_ID,
+            since: 7,
+            buckets: vec![LeafBucketRequest {
+                buck_id: BuckId::new(1, 2),
+                after: Some(OBJ_ID),
+            }],
+            seed: SEED,
+            limit_hint: 64,
+        }),
+        "032001010101010101010101010101010101010101010101010101010101010101010701828004012002020202\
+         02020202020202020202020202020202020202020202020202020202021000000000000000010000000000000002\
+         40",
+    );
+
+    assert_fixture(
+        "PeerSummary response",
+        &Result::<_, ListPartsError>::Ok(PeerSummaryResult {
+            parts: [(
+                PART_ID,
+                PartSummary {
+                    latest_cursor: 7,
+                    member_count: 3,
+                },
+            )]
+            .into(),
+            deepest_bucket_level: 2,
+        }),
+        "0001200101010101010101010101010101010101010101010101010101010101010101070302",
+    );
+    assert_fixture(
+        "ListPartsError",
+        &Result::<PeerSummaryResult, _>::Err(ListPartsError::UnkownParts {
+            unkown_parts: vec![PART_ID],
+        }),
+        "010001200101010101010101010101010101010101010101010101010101010101010101",
+    );
+    assert_fixture(
+        "SubEvent::Changed",
+        &SubEvent::Changed(ObjChanged {
+            cursor: 7,
+            part_ids: vec![PART_ID],
+            obj_id: OBJ_ID,
+            payload: serde_json::json!({ "k": 1 }),
+        }),
+        "000701200101010101010101010101010101010101010101010101010101010101010101200202020202020202\
+         020202020202020202020202020202020202020202020202077b226b223a317d",
+    );
+    assert_fixture(
+        "SubEvent::Added",
+        &SubEvent::Added(ObjAddedToPart {
+            cursor: 8,
+            part_id: PART_ID,
+            obj_id: OBJ_ID,
+            payload: None,
+        }),
+        "010820010101010101010101010101010101010101010101010101010101010101010120020202020202020202\
+         020202020202020202020202020202020202020202020200",
+    );
+    assert_fixture(
+        "SubEvent::Removed",
+        &SubEvent::Removed(ObjRemovedFromPart {
+            cursor: 9,
+            part_id: PART_ID,
+            obj_id: OBJ_ID,
+        }),
+        "020920010101010101010101010101010101010101010101010101010101010101010120020202020202020202\
+         0202020202020202020202020202020202020202020202",
+    );
+    assert_fixture("SubEvent::ReplayComplete", &SubEvent::ReplayComplete, "03");
+    assert_fixture(
+        "GetChangedBuckets response",
+        &Result::<_, ListPartsError>::Ok(vec![BucketSummary {
+            id: BuckId::ROOT,
+            len: 3,
+            live_count: 2,
+            fp: (5, 6),
+            changed_at: 7,
+        }]),
+        "0001000302050607",
+    );
+    assert_fixture(
+        "LeafBuckets response",
+        &Result::<_, LeafBucketsError>::Ok(LeafBucketResult {
+            seed: SEED,
+            bucks: [(
+                BuckId::new(1, 2),
+                LeafBucketPage {
+                    entries: vec![BucketObjPageEntry {
+                        obj_id: OBJ_ID,
+                        dead: false,
+                        fp: Fingerprint::from_u64(300),
+                    }],
+                    next_after: None,
+                    done: true,
+                },
+            )]
+            .into(),
+        }),
+        "001000000000000000010000000000000002018280040120020202020202020202020202020202020202020202\
+         020202020202020202020200ac020001",
+    );
+    assert_fixture(
+        "LeafBucketsError",
+        &Result::<LeafBucketResult, _>::Err(LeafBucketsError::ShallowBucket {
+            buck_id: BuckId::new(1, 2),
+        }),
+        "0101828004",
+    );
+}
+
+#[test]
+fn repo_sync_rpc_fixtures() {
+    assert_fixture(
+        "GetDocsFull",
+        &RepoSyncRpc::GetDocsFull(big_repo::rpc::GetDocsFullRpcReq {
+            req: GetDocsFullRequest {
+                doc_ids: vec!["doc-a".into()],
+            },
+        }),
+        "000105646f632d61",
+    );
+    assert_fixture(
+        "GetDocsFull response",
+        &Result::<_, BigRepoRpcError>::Ok(GetDocsFullResponse {
+            docs: vec![FullDoc {
+                doc_id: "doc-a".into(),
+                automerge_save: vec![1, 2, 3],
+            }],
+        }),
+        "000105646f632d6103010203",
+    );
+    assert_fixture(
+        "BigRepoRpcError",
+        &Result::<GetDocsFullResponse, _>::Err(BigRepoRpcError::Internal {
+            message: "boom".into(),
+        }),
+        "010004626f6f6d",
+    );
+}
+
+#[test]
+fn clone_provision_rpc_fixtures() {
+    assert_fixture(
+        "ResolveCloneInfo",
+        &CloneProvisionRpc::ResolveCloneInfo(ResolveCloneInfoRpcReq {
+            req: CloneInfoRequest {
+                requested_device_name: Some("laptop".into()),
+            },
+        }),
+        "0001066c6170746f70",
+    );
+    assert_fixture(
+        "RequestCloneProvision",
+        &CloneProvisionRpc::RequestCloneProvision(RequestCloneProvisionRpcReq {
+            req: RequestCloneProvisionReq {
+                requested_device_name: None,
+                requester_endpoint_id: "ep".into(),
+            },
+        }),
+        "0100026570",
+    );
+    assert_fixture(
+        "ResolveCloneInfo response",
+        &Result::<_, String>::Ok(CloneInfoResponse {
+            repo_name: "notes".into(),
+            device_name: Some("laptop".into()),
+        }),
+        "00056e6f74657301066c6170746f70",
+    );
+    // the endpoint addr encoding is iroh's business, only our own
+    // fields around it are pinned
+    let endpoint_addr = iroh::EndpointAddr::new(iroh::SecretKey::from_bytes(&[7; 32]).public());
+    let endpoint_addr_hex = to_hex(&postcard::to_stdvec(&endpoint_addr).expect(ERROR_IMPOSSIBLE));
+    assert_fixture(
+        "RequestCloneProvision response",
+        &Result::<_, String>::Ok(CloneProvisionResponse {
+            endpoint_addr,
+            repo_id: "repo-1".into(),
+            repo_name: "notes".into(),
+            app_doc_id: "app".into(),
+            drawer_doc_id: "drawer".into(),
+            device_name: None,
+        }),
+        &format!("00{endpoint_addr_hex}067265706f2d31056e6f746573036170700664726177657200"),
+    );
+    assert_fixture(
+        "CloneProvisionRpc error",
+        &Result::<CloneInfoResponse, _>::Err("nope".to_string()),
+        "01046e6f7065",
+    );
+}
+
+#[test]
+fn handshake_rpc_fixtures() {
+    let hello = SyncHello {
+        app_version: "0.1.0".into(),
+        protocols: BTreeMap::from([(
+            "big-sync".to_string(),
+            ProtocolVersionRange {
+                current: 2,
+                min_compatible: 1,
+            },
+        )]),
+        features: BTreeSet::from(["lan-pairing".to_string()]),
+    };
+    assert_fixture(
+        "Hello",
+        &HandshakeRpc::Hello(HelloRpcReq {
+            hello: hello.clone(),
+        }),
+        "0005302e312e3001086269672d73796e630201010b6c616e2d70616972696e67",
+    );
+    assert_fixture(
+        "Hello response",
+        &hello,
+        "05302e312e3001086269672d73796e630201010b6c616e2d70616972696e67",
+    );
+}
+
+#[test]
+fn sealed_storage_rpc_fixtures() {
+    use crate::sync::vault::{GetSealedReq, PutSealedReq, SealedObj, SealedStorageRpc};
+
+    let obj = SealedObj {
+        obj_id: OBJ_ID,
+        ciphertext: vec![9, 9, 9],
+    };
+    assert_fixture(
+        "GetSealed",
+        &SealedStorageRpc::GetSealed(GetSealedReq {
+            obj_ids: vec![OBJ_ID],
+        }),
+        "0001200202020202020202020202020202020202020202020202020202020202020202",
+    );
+    assert_fixture(
+        "PutSealed",
+        &SealedStorageRpc::PutSealed(PutSealedReq {
+            objs: vec![obj.clone()],
+        }),
+        "010120020202020202020202020202020202020202020202020202020202020202020203090909",
+    );
+    assert_fixture(
+        "GetSealed response",
+        &Result::<_, String>::Ok(vec![obj]),
+        "000120020202020202020202020202020202020202020202020202020202020202020203090909",
+    );
+    assert_fixture("PutSealed response", &Result::<(), String>::Ok(()), "00");
+}
@@ -0,0 +1,1159 @@
+//! End-to-end encrypted storage peers.
+//!
+//! A storage peer is a server that holds the repo's history for devices
+//! that want an always-on copy without trusting the host with the
+//! content. Devices of the repo share a vault key that the storage peer
+//! never sees. Each device seals the commits of its docs into fragments
+//! and its blobs under that key and files the ciphertexts in the
+//! [`VAULT_SEALED_PARTITION_ID`] partition where the payload is only the
+//! ciphertext digest. The storage peer keeps that single partition and
+//! serves it over the regular big_sync rpc so the bucket fingerprints
+//! are computed over ciphertext digests alone.
+//!
+//! Devices push what they sealed with [`SealedStorageRpc::PutSealed`]
+//! and pull what the others sealed through big_sync, fetching the
+//! ciphertexts with [`SealedStorageRpc::GetSealed`]. Sealing is
+//! deterministic so that devices sealing the same content end up with
+//! the same object. The storage peer gets to tell equal content apart
+//! but nothing else about it.
+
+use crate::interlude::*;
+
+use big_repo::{BigRepo, SharedPartStore};
+use big_sync::{SyncBackend, SyncTaskRunOutcome};
+use big_sync_core::part_store::{CursorIndex, ObjPayload};
+use big_sync_core::rpc::PartEvent;
+use big_sync_core::{SyncCompletionDeets, SyncTaskCompletion};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+use iroh::{
+    endpoint::Connection,
+    protocol::{AcceptError, ProtocolHandler},
+    EndpointId,
+};
+use irpc::{channel, rpc_requests, WithChannels};
+
+use crate::blobs::{BlobId, BlobScope, BlobUseHints, BlobsRepo};
+
+use super::{handshake, IncomingAllowList, PeerGate, RefusedEndpoints};
+
+pub const VAULT_SEALED_PARTITION_ID: &str = "vault.sealed";
+pub(crate) const SEALED_BACKEND_ID: &str = "vault.sealed";
+/// Bump the suffix on any wire change to [`SealedStorageRpc`] or the
+/// types it carries.
+pub const SEALED_STORAGE_ALPN: &[u8] = b"townframe/sealed-storage/0";
+
+/// Most objects sent in a single [`SealedStorageRpc`] request.
+const SEALED_BATCH_LIMIT: usize = 64;
+/// Most changes sealed into a single fragment.
+const FRAGMENT_MAX_CHANGES: usize = 256;
+// FIXME: larger blobs need to be chunked before they can be sealed
+const MAX_SEALED_BLOB_BYTES: u64 = 16 * 1024 * 1024;
+const NONCE_LEN: usize = 24;
+
+const ID_KEY_CONTEXT: &str = "townframe daybook vault 2025-01 object id";
+const NONCE_KEY_CONTEXT: &str = "townframe daybook vault 2025-01 nonce";
+const ENC_KEY_CONTEXT: &str = "townframe daybook vault 2025-01 encryption";
+
+/// Symmetric key shared by the trusted devices of a repo.
+#[derive(Clone, PartialEq, Eq)]
+pub struct VaultKey([u8; 32]);
+
+impl std::fmt::Debug for VaultKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.write_str("VaultKey(..)")
+    }
+}
+
+impl VaultKey {
+    pub fn generate() -> Self {
+        Self(rand::random())
+    }
+
+    pub fn from_bytes(bytes: [u8; 32]) -> Self {
+        Self(bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_base58(&self) -> String {
+        utils_rs::hash::encode_base58_multibase(self.0)
+    }
+
+    pub fn from_base58(encoded: &str) -> Res<Self> {
+        let bytes = utils_rs::hash::decode_base58_multibase(encoded.trim())
+            .wrap_err("error decoding vault key")?;
+        let bytes: [u8; 32] = bytes
+            .try_into()
+            .map_err(|_| ferr!("vault key has the wrong length"))?;
+        Ok(Self(bytes))
+    }
+}
+
+/// The vault key of this device, `None` if the vault isn't enabled.
+pub async fn read_vault_key(rcx: &crate::repo::RepoCtx) -> Res<Option<VaultKey>> {
+    rcx.secret_repo.load_vault_key(&rcx.checkout_id).await
+}
+
+/// Generates a vault key for the repo unless this device has one
+/// already. The key needs to be imported on every other device that's
+/// to sync through storage peers.
+pub async fn enable_vault(rcx: &crate::repo::RepoCtx) -> Res<VaultKey> {
+    if let Some(key) = read_vault_key(rcx).await? {
+        return Ok(key);
+    }
+    let key = VaultKey::generate();
+    rcx.secret_repo
+        .set_vault_key(&rcx.checkout_id, &key)
+        .await?;
+    Ok(key)
+}
+
+/// Uses the vault key exported from another device of the repo.
+pub async fn import_vault_key(rcx: &crate::repo::RepoCtx, key: &VaultKey) -> Res<()> {
+    if let Some(existing) = read_vault_key(rcx).await? {
+        if existing != *key {
+            eyre::bail!("this device already has a different vault key");
+        }
+        return Ok(());
+    }
+    rcx.secret_repo.set_vault_key(&rcx.checkout_id, key).await
+}
+
+/// What gets sealed. Never leaves the trusted devices in the clear.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) enum SealedItem {
+    /// Raw automerge changes of a doc along with their hashes.
+    Fragment {
+        doc_id: DocumentId,
+        part_id: PartId,
+        changes: Vec<([u8; 32], Vec<u8>)>,
+    },
+    Blob {
+        blob_id: BlobId,
+        scope: String,
+        bytes: Vec<u8>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SealedObj {
+    pub obj_id: ObjId,
+    /// The nonce followed by the AEAD output.
+    pub ciphertext: Vec<u8>,
+}
+
+/// The partition payload of a sealed object.
+pub(crate) fn sealed_payload(ciphertext: &[u8]) -> ObjPayload {
+    serde_json::json!({
+        "digest": blake3::hash(ciphertext).to_hex().to_string(),
+    })
+}
+
+pub(crate) struct VaultCipher {
+    id_key: [u8; 32],
+    nonce_key: [u8; 32],
+    cipher: XChaCha20Poly1305,
+}
+
+impl VaultCipher {
+    pub fn new(key: &VaultKey) -> Self {
+        let enc_key = blake3::derive_key(ENC_KEY_CONTEXT, key.as_bytes());
+        Self {
+            id_key: blake3::derive_key(ID_KEY_CONTEXT, key.as_bytes()),
+            nonce_key: blake3::derive_key(NONCE_KEY_CONTEXT, key.as_bytes()),
+            cipher: XChaCha20Poly1305::new(&enc_key.into()),
+        }
+    }
+
+    /// The object id and nonce are keyed hashes of the plaintext so that
+    /// the same item always seals to the same object.
+    pub fn seal(&self, item: &SealedItem) -> Res<SealedObj> {
+        let plaintext = postcard::to_stdvec(item).expect(ERROR_IMPOSSIBLE);
+        let obj_id = ObjId::new(*blake3::keyed_hash(&self.id_key, &plaintext).as_bytes());
+        let nonce = blake3::keyed_hash(&self.nonce_key, &plaintext);
+        let nonce = XNonce::from_slice(&nonce.as_bytes()[..NONCE_LEN]);
+        let sealed = self
+            .cipher
+            .encrypt(
+                nonce,
+                Payload {
+                    msg: &plaintext,
+                    aad: obj_id.as_bytes(),
+                },
+            )
+            .map_err(|_| ferr!("error sealing vault item"))?;
+        let mut ciphertext = Vec::with_capacity(NONCE_LEN + sealed.len());
+        ciphertext.extend_from_slice(nonce.as_slice());
+        ciphertext.extend_from_slice(&sealed);
+        Ok(SealedObj { obj_id, ciphertext })
+    }
+
+    pub fn open(&self, obj: &SealedObj) -> Res<SealedItem> {
+        if obj.ciphertext.len() < NONCE_LEN {
+            eyre::bail!("sealed object {} is truncated", obj.obj_id);
+        }
+        let (nonce, sealed) = obj.ciphertext.split_at(NONCE_LEN);
+        let plaintext = self
+            .cipher
+            .decrypt(
+                XNonce::from_slice(nonce),
+                Payload {
+                    msg: sealed,
+                    aad: obj.obj_id.as_bytes(),
+                },
+            )
+            .map_err(|_| ferr!("sealed object {} failed to open", obj.obj_id))?;
+        if blake3::keyed_hash(&self.id_key, &plaintext).as_bytes() != obj.obj_id.as_bytes() {
+            eyre::bail!("sealed object {} doesn't match its id", obj.obj_id);
+        }
+        postcard::from_bytes(&plaintext).wrap_err("error decoding sealed item")
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct GetSealedReq {
+    pub obj_ids: Vec<ObjId>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PutSealedReq {
+    pub objs: Vec<SealedObj>,
+}
+
+#[rpc_requests(message = SealedStorageRpcMessage)]
+#[derive(Debug, Serialize, Deserialize)]
+pub enum SealedStorageRpc {
+    /// Objects the storage peer doesn't hold are left out of the reply.
+    #[rpc(tx = channel::oneshot::Sender<Result<Vec<SealedObj>, String>>)]
+    GetSealed(GetSealedReq),
+    #[rpc(tx = channel::oneshot::Sender<Result<(), String>>)]
+    PutSealed(PutSealedReq),
+}
+
+fn obj_id_from_row(bytes: Vec<u8>) -> Res<ObjId> {
+    let bytes: [u8; 32] = bytes
+        .try_into()
+        .map_err(|_| ferr!("corrupt object id in vault store"))?;
+    Ok(ObjId::new(bytes))
+}
+
+/// Ciphertexts of the sealed partition. Used by devices and storage
+/// peers alike.
+pub(crate) struct SealedStore {
+    sql: SqlCtx,
+    part_store: SharedPartStore,
+    part_id: PartId,
+}
+
+impl SealedStore {
+    pub async fn load(sql: SqlCtx, part_store: SharedPartStore) -> Res<Self> {
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_sealed_objs (
+                obj_id BLOB PRIMARY KEY,
+                ciphertext BLOB NOT NULL
+            ) STRICT
+            "#,
+        )
+        .execute(&sql.write_pool)
+        .await?;
+        let part_id = crate::part_id_from_label(VAULT_SEALED_PARTITION_ID);
+        part_store.ensure_part(part_id).await?;
+        Ok(Self {
+            sql,
+            part_store,
+            part_id,
+        })
+    }
+
+    pub fn part_id(&self) -> PartId {
+        self.part_id
+    }
+
+    pub async fn has(&self, obj_id: ObjId) -> Res<bool> {
+        let found: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM vault_sealed_objs WHERE obj_id = ?1")
+                .bind(obj_id.as_bytes().as_slice())
+                .fetch_optional(&self.sql.read_pool)
+                .await?;
+        Ok(found.is_some())
+    }
+
+    /// Stores the ciphertext without touching the partition. Returns
+    /// false if it was already stored.
+    pub async fn insert(&self, obj: &SealedObj) -> Res<bool> {
+        let res = sqlx::query(
+            "INSERT INTO vault_sealed_objs(obj_id, ciphertext) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+        )
+        .bind(obj.obj_id.as_bytes().as_slice())
+        .bind(&obj.ciphertext)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(res.rows_affected() > 0)
+    }
+
+    /// Stores the ciphertext and files it in the sealed partition.
+    pub async fn put(&self, obj: &SealedObj) -> Res<bool> {
+        if !self.insert(obj).await? {
+            return Ok(false);
+        }
+        self.part_store
+            .set_obj_payload(obj.obj_id, sealed_payload(&obj.ciphertext))
+            .await?;
+        self.part_store
+            .add_obj_to_parts(obj.obj_id, vec![self.part_id])
+            .await?;
+        Ok(true)
+    }
+
+    pub async fn get(&self, obj_ids: &[ObjId]) -> Res<Vec<SealedObj>> {
+        let mut out = Vec::with_capacity(obj_ids.len());
+        for obj_id in obj_ids {
+            let ciphertext: Option<Vec<u8>> =
+                sqlx::query_scalar("SELECT ciphertext FROM vault_sealed_objs WHERE obj_id = ?1")
+                    .bind(obj_id.as_bytes().as_slice())
+                    .fetch_optional(&self.sql.read_pool)
+                    .await?;
+            if let Some(ciphertext) = ciphertext {
+                out.push(SealedObj {
+                    obj_id: *obj_id,
+                    ciphertext,
+                });
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Objects added to or changed in the partition after the cursor along
+/// with the cursor to continue from.
+async fn part_objs_after(
+    part_store: &SharedPartStore,
+    part_id: PartId,
+    cursor: CursorIndex,
+    limit: usize,
+) -> Res<(Vec<ObjId>, CursorIndex)> {
+    let mut pages = part_store
+        .list_events([part_id].into(), cursor, limit as u32)
+        .await??;
+    let Some(page) = pages.remove(&part_id) else {
+        return Ok((vec![], cursor));
+    };
+    let mut next_cursor = cursor;
+    let mut obj_ids = Vec::with_capacity(page.events.len());
+    for event in page.events {
+        match event {
+            PartEvent::Changed(evt) => {
+                next_cursor = next_cursor.max(evt.cursor);
+                obj_ids.push(evt.obj_id);
+            }
+            PartEvent::Added(evt) => {
+                next_cursor = next_cursor.max(evt.cursor);
+                obj_ids.push(evt.obj_id);
+            }
+            // sealed objects are never taken back
+            PartEvent::Removed(evt) => {
+                next_cursor = next_cursor.max(evt.cursor);
+            }
+        }
+    }
+    Ok((obj_ids, next_cursor))
+}
+
+/// Where the sealer picks up from on each device.
+struct VaultLedger {
+    sql: SqlCtx,
+}
+
+impl VaultLedger {
+    async fn load(sql: SqlCtx) -> Res<Self> {
+        for stmt in [
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_seal_cursors (
+                part_id BLOB PRIMARY KEY,
+                cursor INTEGER NOT NULL
+            ) STRICT
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_sealed_heads (
+                doc_id BLOB PRIMARY KEY,
+                heads TEXT NOT NULL
+            ) STRICT
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_sealed_blobs (
+                blob_id BLOB PRIMARY KEY
+            ) STRICT
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_push_cursors (
+                peer_key TEXT PRIMARY KEY,
+                cursor INTEGER NOT NULL
+            ) STRICT
+            "#,
+            r#"
+            CREATE TABLE IF NOT EXISTS vault_pending_fragments (
+                obj_id BLOB PRIMARY KEY,
+                doc_id BLOB NOT NULL
+            ) STRICT
+            "#,
+        ] {
+            sqlx::query(stmt).execute(&sql.write_pool).await?;
+        }
+        Ok(Self { sql })
+    }
+
+    async fn seal_cursor(&self, part_id: PartId) -> Res<CursorIndex> {
+        let cursor: Option<i64> =
+            sqlx::query_scalar("SELECT cursor FROM vault_seal_cursors WHERE part_id = ?1")
+                .bind(part_id.as_bytes().as_slice())
+                .fetch_optional(&self.sql.read_pool)
+                .await?;
+        Ok(cursor.unwrap_or_default() as CursorIndex)
+    }
+
+    async fn set_seal_cursor(&self, part_id: PartId, cursor: CursorIndex) -> Res<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO vault_seal_cursors(part_id, cursor) VALUES (?1, ?2)
+            ON CONFLICT(part_id) DO UPDATE SET cursor = excluded.cursor
+            "#,
+        )
+        .bind(part_id.as_bytes().as_slice())
+        .bind(cursor as i64)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn sealed_heads(&self, doc_id: DocumentId) -> Res<Vec<automerge::ChangeHash>> {
+        let heads: Option<String> =
+            sqlx::query_scalar("SELECT heads FROM vault_sealed_heads WHERE doc_id = ?1")
+                .bind(doc_id.as_bytes().as_slice())
+                .fetch_optional(&self.sql.read_pool)
+                .await?;
+        let Some(heads) = heads else {
+            return Ok(vec![]);
+        };
+        let heads: Vec<String> = serde_json::from_str(&heads)?;
+        Ok(am_utils_rs::parse_commit_heads(&heads)?.to_vec())
+    }
+
+    async fn set_sealed_heads(
+        &self,
+        doc_id: DocumentId,
+        heads: &[automerge::ChangeHash],
+    ) -> Res<()> {
+        let heads =
+            serde_json::to_string(&am_utils_rs::serialize_commit_heads(heads)).expect(ERROR_JSON);
+        sqlx::query(
+            r#"
+            INSERT INTO vault_sealed_heads(doc_id, heads) VALUES (?1, ?2)
+            ON CONFLICT(doc_id) DO UPDATE SET heads = excluded.heads
+            "#,
+        )
+        .bind(doc_id.as_bytes().as_slice())
+        .bind(heads)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn is_blob_sealed(&self, blob_id: BlobId) -> Res<bool> {
+        let found: Option<i64> =
+            sqlx::query_scalar("SELECT 1 FROM vault_sealed_blobs WHERE blob_id = ?1")
+                .bind(blob_id.as_bytes().as_slice())
+                .fetch_optional(&self.sql.read_pool)
+                .await?;
+        Ok(found.is_some())
+    }
+
+    async fn mark_blob_sealed(&self, blob_id: BlobId) -> Res<()> {
+        sqlx::query("INSERT INTO vault_sealed_blobs(blob_id) VALUES (?1) ON CONFLICT DO NOTHING")
+            .bind(blob_id.as_bytes().as_slice())
+            .execute(&self.sql.write_pool)
+            .await?;
+        Ok(())
+    }
+
+    async fn push_cursor(&self, peer_key: &str) -> Res<CursorIndex> {
+        let cursor: Option<i64> =
+            sqlx::query_scalar("SELECT cursor FROM vault_push_cursors WHERE peer_key = ?1")
+                .bind(peer_key)
+                .fetch_optional(&self.sql.read_pool)
+                .await?;
+        Ok(cursor.unwrap_or_default() as CursorIndex)
+    }
+
+    async fn set_push_cursor(&self, peer_key: &str, cursor: CursorIndex) -> Res<()> {
+        sqlx::query(
+            r#"
+            INSERT INTO vault_push_cursors(peer_key, cursor) VALUES (?1, ?2)
+            ON CONFLICT(peer_key) DO UPDATE SET cursor = excluded.cursor
+            "#,
+        )
+        .bind(peer_key)
+        .bind(cursor as i64)
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn add_pending(&self, obj_id: ObjId, doc_id: DocumentId) -> Res<()> {
+        sqlx::query(
+            "INSERT INTO vault_pending_fragments(obj_id, doc_id) VALUES (?1, ?2) ON CONFLICT DO NOTHING",
+        )
+        .bind(obj_id.as_bytes().as_slice())
+        .bind(doc_id.as_bytes().as_slice())
+        .execute(&self.sql.write_pool)
+        .await?;
+        Ok(())
+    }
+
+    async fn pending_for(&self, doc_id: DocumentId) -> Res<Vec<ObjId>> {
+        let rows: Vec<Vec<u8>> =
+            sqlx::query_scalar("SELECT obj_id FROM vault_pending_fragments WHERE doc_id = ?1")
+                .bind(doc_id.as_bytes().as_slice())
+                .fetch_all(&self.sql.read_pool)
+                .await?;
+        rows.into_iter().map(obj_id_from_row).collect()
+    }
+
+    async fn remove_pending(&self, obj_id: ObjId) -> Res<()> {
+        sqlx::query("DELETE FROM vault_pending_fragments WHERE obj_id = ?1")
+            .bind(obj_id.as_bytes().as_slice())
+            .execute(&self.sql.write_pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Partitions whose members get sealed.
+#[derive(Debug, Clone, Copy)]
+pub(super) enum SealSource {
+    Docs,
+    Blobs(BlobScope),
+}
+
+/// The trusted device side of the vault.
+pub(super) struct Vault {
+    cipher: VaultCipher,
+    store: Arc<SealedStore>,
+    ledger: VaultLedger,
+    part_store: SharedPartStore,
+    big_repo: std::sync::Weak<BigRepo>,
+    blobs_repo: Arc<BlobsRepo>,
+}
+
+impl Vault {
+    pub async fn load(
+        key: &VaultKey,
+        rcx: &crate::repo::RepoCtx,
+        store: Arc<SealedStore>,
+        blobs_repo: Arc<BlobsRepo>,
+    ) -> Res<Self> {
+        Ok(Self {
+            cipher: VaultCipher::new(key),
+            store,
+            ledger: VaultLedger::load(rcx.sql.clone()).await?,
+            part_store: Arc::clone(&rcx.part_store),
+            big_repo: Arc::downgrade(&rcx.big_repo),
+            blobs_repo,
+        })
+    }
+
+    fn big_repo(&self) -> Res<Arc<BigRepo>> {
+        self.big_repo.upgrade().ok_or_eyre("big repo is gone")
+    }
+
+    /// Seals whatever was added to the source partitions since the last
+    /// call.
+    pub async fn seal_new(&self, sources: &[(PartId, SealSource)]) -> Res<usize> {
+        let mut sealed = 0;
+        for (part_id, source) in sources {
+            let mut cursor = self.ledger.seal_cursor(*part_id).await?;
+            loop {
+                let (obj_ids, next_cursor) =
+                    part_objs_after(&self.part_store, *part_id, cursor, 256).await?;
+                if obj_ids.is_empty() && next_cursor == cursor {
+                    break;
+                }
+                for obj_id in obj_ids {
+                    sealed += match source {
+                        SealSource::Docs => self.seal_doc(obj_id, *part_id).await?,
+                        SealSource::Blobs(scope) => self.seal_blob(obj_id, *scope).await?,
+                    };
+                }
+                self.ledger.set_seal_cursor(*part_id, next_cursor).await?;
+                cursor = next_cursor;
+            }
+        }
+        Ok(sealed)
+    }
+
+    async fn seal_doc(&self, doc_id: DocumentId, part_id: PartId) -> Res<usize> {
+        let Some(handle) = self.big_repo()?.get_doc(&doc_id).await? else {
+            return Ok(0);
+        };
+        let sealed_heads = self.ledger.sealed_heads(doc_id).await?;
+        let (changes, heads) = handle
+            .with_document_read(|doc| {
+                let changes = doc
+                    .get_changes(&sealed_heads)
+                    .into_iter()
+                    .map(|change| (change.hash().0, change.raw_bytes().to_vec()))
+                    .collect::<Vec<_>>();
+                (changes, doc.get_heads())
+            })
+            .await;
+        let mut sealed = 0;
+        for chunk in changes.chunks(FRAGMENT_MAX_CHANGES) {
+            let obj = self.cipher.seal(&SealedItem::Fragment {
+                doc_id,
+                part_id,
+                changes: chunk.to_vec(),
+            })?;
+            if self.store.put(&obj).await? {
+                sealed += 1;
+            }
+        }
+        self.ledger.set_sealed_heads(doc_id, &heads).await?;
+        Ok(sealed)
+    }
+
+    async fn seal_blob(&self, blob_id: BlobId, scope: BlobScope) -> Res<usize> {
+        if self.ledger.is_blob_sealed(blob_id).await? {
+            return Ok(0);
+        }
+        // blobs we only know of get sealed by the devices holding them
+        if !self.blobs_repo.has_hash(blob_id).await? {
+            return Ok(0);
+        }
+        let path = self.blobs_repo.get_path(blob_id).await?;
+        if tokio::fs::metadata(&path).await?.len() > MAX_SEALED_BLOB_BYTES {
+            debug!(%blob_id, "blob too large to seal, skipping");
+            return Ok(0);
+        }
+        let bytes = tokio::fs::read(&path).await?;
+        let obj = self.cipher.seal(&SealedItem::Blob {
+            blob_id,
+            scope: scope.partition_id().into(),
+            bytes,
+        })?;
+        let sealed = self.store.put(&obj).await?;
+        self.ledger.mark_blob_sealed(blob_id).await?;
+        Ok(sealed as usize)
+    }
+
+    /// Sends the storage peer everything in the sealed partition it
+    /// hasn't been sent yet.
+    ///
+    /// NOTE: objects pulled from the storage peer get sent back once,
+    /// it drops them as duplicates.
+    pub async fn push(
+        &self,
+        peer_key: &str,
+        client: &irpc::Client<SealedStorageRpc>,
+    ) -> Res<usize> {
+        let mut pushed = 0;
+        let mut cursor = self.ledger.push_cursor(peer_key).await?;
+        loop {
+            let (obj_ids, next_cursor) = part_objs_after(
+                &self.part_store,
+                self.store.part_id(),
+                cursor,
+                SEALED_BATCH_LIMIT,
+            )
+            .await?;
+            if obj_ids.is_empty() && next_cursor == cursor {
+                break;
+            }
+            let objs = self.store.get(&obj_ids).await?;
+            if !objs.is_empty() {
+                pushed += objs.len();
+                client
+                    .rpc(PutSealedReq { objs })
+                    .await
+                    .wrap_err("sealed storage rpc transport failed")?
+                    .map_err(|err| ferr!("storage peer refused sealed objects: {err}"))?;
+            }
+            self.ledger.set_push_cursor(peer_key, next_cursor).await?;
+            cursor = next_cursor;
+        }
+        Ok(pushed)
+    }
+
+    /// Decrypts a pulled object and applies it. Objects that fail to
+    /// open are kept sealed and otherwise ignored.
+    async fn apply(&self, peer_id: PeerId, obj: &SealedObj) -> Res<()> {
+        let item = match self.cipher.open(obj) {
+            Ok(item) => item,
+            Err(err) => {
+                warn!(
+                    ?err,
+                    "ignoring sealed object that doesn't open under the vault key"
+                );
+                return Ok(());
+            }
+        };
+        match item {
+            SealedItem::Fragment { doc_id, .. } => {
+                self.ledger.add_pending(obj.obj_id, doc_id).await?;
+                self.apply_pending(peer_id, doc_id).await
+            }
+            SealedItem::Blob {
+                blob_id,
+                scope,
+                bytes,
+            } => {
+                if *blake3::hash(&bytes).as_bytes() != *blob_id.as_bytes() {
+                    eyre::bail!("sealed blob doesn't match its id {blob_id}");
+                }
+                if self.blobs_repo.has_hash(blob_id).await? {
+                    return Ok(());
+                }
+                let use_hints = match BlobScope::from_partition_id(&scope) {
+                    Some(BlobScope::Docs) => BlobUseHints::Docs,
+                    Some(BlobScope::Plugs) => BlobUseHints::Plugs,
+                    None => BlobUseHints::Unknown,
+                };
+                self.blobs_repo.put(&bytes, use_hints).await?;
+                // it's in the vault already
+                self.ledger.mark_blob_sealed(blob_id).await
+            }
+        }
+    }
+
+    /// Loads the pulled fragments of the doc into it. Fragments whose
+    /// dependencies haven't arrived yet stay pending.
+    // FIXME: every pending fragment of the doc is retried on each arrival
+    async fn apply_pending(&self, peer_id: PeerId, doc_id: DocumentId) -> Res<()> {
+        let pending = self.ledger.pending_for(doc_id).await?;
+        let mut fragments = vec![];
+        for obj in self.store.get(&pending).await? {
+            if let SealedItem::Fragment {
+                part_id, changes, ..
+            } = self.cipher.open(&obj)?
+            {
+                fragments.push((obj.obj_id, part_id, changes));
+            }
+        }
+        let all_present = |doc: &automerge::Automerge, changes: &[([u8; 32], Vec<u8>)]| {
+            changes.iter().all(|(hash, _)| {
+                doc.get_change_by_hash(&automerge::ChangeHash(*hash))
+                    .is_some()
+            })
+        };
+        let big_repo = self.big_repo()?;
+        let applied = match big_repo.get_doc(&doc_id).await? {
+            Some(handle) => {
+                handle
+                    .with_document_with_origin(
+                        |doc| {
+                            for (_, _, changes) in &fragments {
+                                for (_, change) in changes {
+                                    doc.load_incremental(change)?;
+                                }
+                            }
+                            eyre::Ok(
+                                fragments
+                                    .iter()
+                                    .filter(|(_, _, changes)| all_present(doc, changes))
+                                    .map(|(obj_id, ..)| *obj_id)
+                                    .collect::<Vec<_>>(),
+                            )
+                        },
+                        big_repo::BigRepoChangeOrigin::Remote { peer_id },
+                    )
+                    .await??
+            }
+            None => {
+                let mut doc = automerge::Automerge::new();
+                for (_, _, changes) in &fragments {
+                    for (_, change) in changes {
+                        doc.load_incremental(change)?;
+                    }
+                }
+                if doc.get_heads().is_empty() {
+                    return Ok(());
+                }
+                let applied = fragments
+                    .iter()
+                    .filter(|(_, _, changes)| all_present(&doc, changes))
+                    .map(|(obj_id, ..)| *obj_id)
+                    .collect::<Vec<_>>();
+                match big_repo.put_doc(doc_id, doc).await {
+                    Ok(_) => {}
+                    // raced with another arrival, the next one retries
+                    Err(big_repo::PutDocError::IdOccpuied { .. }) => return Ok(()),
+                    Err(big_repo::PutDocError::Other(err)) => return Err(err),
+                }
+                let part_ids = fragments
+                    .iter()
+                    .map(|(_, part_id, _)| *part_id)
+                    .collect::<HashSet<_>>();
+                self.part_store
+                    .add_obj_to_parts(doc_id, part_ids.into_iter().collect())
+                    .await?;
+                applied
+            }
+        };
+        for obj_id in applied {
+            self.ledger.remove_pending(obj_id).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Pulls sealed objects from storage peers, opening them when the
+/// vault is enabled on this device.
+pub(super) struct SealedSyncBackend {
+    store: Arc<SealedStore>,
+    endpoint: iroh::Endpoint,
+    peers: std::sync::RwLock<HashMap<PeerId, irpc::Client<SealedStorageRpc>>>,
+    vault: std::sync::RwLock<Option<Arc<Vault>>>,
+}
+
+impl SealedSyncBackend {
+    pub fn new(store: Arc<SealedStore>, endpoint: iroh::Endpoint) -> Self {
+        Self {
+            store,
+            endpoint,
+            peers: default(),
+            vault: default(),
+        }
+    }
+
+    pub fn vault(&self) -> Option<Arc<Vault>> {
+        self.vault.read().expect(ERROR_MUTEX).clone()
+    }
+
+    pub fn set_vault(&self, vault: Option<Arc<Vault>>) {
+        *self.vault.write().expect(ERROR_MUTEX) = vault;
+    }
+
+    pub fn register_remote_peer(&self, peer_id: PeerId, endpoint_addr: iroh::EndpointAddr) {
+        let client = irpc_iroh::client::<SealedStorageRpc>(
+            self.endpoint.clone(),
+            endpoint_addr,
+            SEALED_STORAGE_ALPN,
+        );
+        self.peers
+            .write()
+            .expect(ERROR_MUTEX)
+            .insert(peer_id, client);
+    }
+
+    pub fn unregister_remote_peer(&self, peer_id: PeerId) {
+        self.peers.write().expect(ERROR_MUTEX).remove(&peer_id);
+    }
+
+    pub fn remote_peer(&self, peer_id: PeerId) -> Option<irpc::Client<SealedStorageRpc>> {
+        self.peers.read().expect(ERROR_MUTEX).get(&peer_id).cloned()
+    }
+}
+
+#[async_trait]
+impl SyncBackend for SealedSyncBackend {
+    async fn sync_obj(
+        &self,
+        peer_id: PeerId,
+        obj_id: ObjId,
+        remote_payload: Option<ObjPayload>,
+    ) -> Res<SyncTaskRunOutcome> {
+        let noop = Ok(SyncTaskRunOutcome::Completion(SyncTaskCompletion {
+            obj_id,
+            deets: SyncCompletionDeets::Noop,
+        }));
+        // sealed objects never change so holding one is enough
+        if self.store.has(obj_id).await? {
+            return noop;
+        }
+        let Some(remote_payload) = remote_payload else {
+            return noop;
+        };
+        let Some(client) = self.remote_peer(peer_id) else {
+            eyre::bail!("no sealed storage client registered for peer {peer_id}");
+        };
+        let obj = client
+            .rpc(GetSealedReq {
+                obj_ids: vec![obj_id],
+            })
+            .await
+            .wrap_err("sealed storage rpc transport failed")?
+            .map_err(|err| ferr!("storage peer failed serving sealed object: {err}"))?
+            .into_iter()
+            .find(|obj| obj.obj_id == obj_id)
+            .ok_or_else(|| ferr!("storage peer doesn't hold sealed object {obj_id}"))?;
+        if sealed_payload(&obj.ciphertext) != remote_payload {
+            eyre::bail!("sealed object {obj_id} doesn't match its digest");
+        }
+        self.store.insert(&obj).await?;
+        self.store
+            .part_store
+            .set_obj_payload(obj_id, remote_payload)
+            .await?;
+        if let Some(vault) = self.vault() {
+            vault.apply(peer_id, &obj).await?;
+        }
+        Ok(SyncTaskRunOutcome::Completion(SyncTaskCompletion {
+            obj_id,
+            deets: SyncCompletionDeets::AddedMember,
+        }))
+    }
+}
+
+/// Serves the sealed store to the repo's devices.
+#[derive(Clone)]
+struct SealedStorageProtocol {
+    store: Arc<SealedStore>,
+}
+
+impl std::fmt::Debug for SealedStorageProtocol {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        fmt.debug_struct("SealedStorageProtocol").finish()
+    }
+}
+
+impl SealedStorageProtocol {
+    async fn put(&self, objs: Vec<SealedObj>) -> Res<()> {
+        if objs.len() > SEALED_BATCH_LIMIT {
+            eyre::bail!("too many objects in one request");
+        }
+        for obj in &objs {
+            self.store.put(obj).await?;
+        }
+        Ok(())
+    }
+}
+
+impl ProtocolHandler for SealedStorageProtocol {
+    async fn accept(&self, conn: Connection) -> Result<(), AcceptError> {
+        loop {
+            let msg = match irpc_iroh::read_request::<SealedStorageRpc>(&conn).await {
+                Ok(Some(msg)) => msg,
+                Ok(None) => break,
+                Err(err) => {
+                    warn!(?err, "error reading sealed storage request");
+                    break;
+                }
+            };
+            let sent = match msg {
+                SealedStorageRpcMessage::GetSealed(req) => {
+                    let WithChannels { inner, tx, .. } = req;
+                    let out = if inner.obj_ids.len() > SEALED_BATCH_LIMIT {
+                        Err(ferr!("too many objects in one request"))
+                    } else {
+                        self.store.get(&inner.obj_ids).await
+                    };
+                    tx.send(out.map_err(|err| format!("{err:#}"))).await
+                }
+                SealedStorageRpcMessage::PutSealed(req) => {
+                    let WithChannels { inner, tx, .. } = req;
+                    let out = self.put(inner.objs).await;
+                    tx.send(out.map_err(|err| format!("{err:#}"))).await
+                }
+            };
+            if sent.is_err() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StoragePeerOpts {
+    /// Where the storage peer keeps its identity and the ciphertexts.
+    pub root: PathBuf,
+    /// Endpoints allowed to connect. `None` accepts any endpoint.
+    pub allow_list: Option<HashSet<EndpointId>>,
+}
+
+/// A node that stores and relays the sealed partition of repos whose
+/// vault key it doesn't hold. It doesn't open a repo of its own.
+pub struct StoragePeer {
+    router: iroh::protocol::Router,
+    allow_list: IncomingAllowList,
+}
+
+pub struct StoragePeerStopToken {
+    router: iroh::protocol::Router,
+    big_sync_rpc_stop: big_sync::rpc::BigSyncRpcStopToken,
+}
+
+impl StoragePeerStopToken {
+    pub async fn stop(self) -> Res<()> {
+        self.big_sync_rpc_stop.stop().await?;
+        tokio::time::timeout(
+            utils_rs::scale_timeout(Duration::from_secs(10)),
+            self.router.shutdown(),
+        )
+        .await
+        .map_err(|_| eyre::eyre!("timeout waiting for router shutdown"))??;
+        Ok(())
+    }
+}
+
+impl StoragePeer {
+    const SECRET_KEY_FILE: &'static str = "storage_peer.key";
+
+    pub async fn boot(opts: StoragePeerOpts) -> Res<(Self, StoragePeerStopToken)> {
+        tokio::fs::create_dir_all(&opts.root).await?;
+        let secret_key = Self::load_or_create_secret_key(&opts.root).await?;
+        let sql =
+            crate::app::open_sql_ctx(crate::app::SqlConfig::file(opts.root.join("sealed.db")))
+                .await?;
+        let part_store: SharedPartStore = Arc::new(
+            big_sync::SqlitePartStore::new(
+                sql.clone(),
+                "sealed-storage",
+                big_sync_core::BuckId::MAX_LEVEL,
+            )
+            .await?,
+        );
+        let store = Arc::new(SealedStore::load(sql, Arc::clone(&part_store)).await?);
+        let (big_sync_rpc, big_sync_rpc_stop) =
+            big_sync::rpc::spawn_big_sync_rpc(Arc::clone(&part_store)).await?;
+
+        let endpoint_builder =
+            iroh::Endpoint::builder(iroh::endpoint::presets::Minimal).secret_key(secret_key);
+        #[cfg(test)]
+        let endpoint_builder = endpoint_builder
+            .clear_ip_transports()
+            .bind_addr((std::net::Ipv4Addr::LOCALHOST, 0))?
+            .relay_mode(iroh::RelayMode::Disabled);
+        let endpoint = endpoint_builder.bind().await?;
+
+        let allow_list: IncomingAllowList = Arc::new(std::sync::RwLock::new(opts.allow_list));
+        let refused: RefusedEndpoints = default();
+        let sessions: handshake::PeerSessions = default();
+        let router = iroh::protocol::Router::builder(endpoint)
+            .accept(
+                handshake::SYNC_HANDSHAKE_ALPN,
+                PeerGate {
+                    inner: handshake::HandshakeProtocol {
+                        sessions: Arc::clone(&sessions),
+                        registry: crate::repos::ListenersRegistry::new(),
+                    },
+                    allow_list: Arc::clone(&allow_list),
+                    refused: Arc::clone(&refused),
+                    sessions: None,
+                },
+            )
+            .accept(
+                big_sync::rpc::BIG_SYNC_RPC_ALPN,
+                PeerGate {
+                    inner: big_sync_rpc.protocol_handler(),
+                    allow_list: Arc::clone(&allow_list),
+                    refused: Arc::clone(&refused),
+                    sessions: Some(Arc::clone(&sessions)),
+                },
+            )
+            .accept(
+                SEALED_STORAGE_ALPN,
+                PeerGate {
+                    inner: SealedStorageProtocol { store },
+                    allow_list: Arc::clone(&allow_list),
+                    refused,
+                    sessions: Some(sessions),
+                },
+            )
+            .spawn();
+        Ok((
+            Self {
+                router: router.clone(),
+                allow_list,
+            },
+            StoragePeerStopToken {
+                router,
+                big_sync_rpc_stop,
+            },
+        ))
+    }
+
+    async fn load_or_create_secret_key(root: &Path) -> Res<iroh::SecretKey> {
+        let path = root.join(Self::SECRET_KEY_FILE);
+        if tokio::fs::try_exists(&path).await? {
+            let encoded = tokio::fs::read_to_string(&path).await?;
+            let bytes = utils_rs::hash::decode_base58_multibase(encoded.trim())
+                .wrap_err("error decoding storage peer key")?;
+            let bytes: [u8; 32] = bytes
+                .try_into()
+                .map_err(|_| ferr!("storage peer key has the wrong length"))?;
+            return Ok(iroh::SecretKey::from_bytes(&bytes));
+        }
+        let secret_key = iroh::SecretKey::from_bytes(&rand::random());
+        tokio::fs::write(
+            &path,
+            utils_rs::hash::encode_base58_multibase(secret_key.to_bytes()),
+        )
+        .await?;
+        Ok(secret_key)
+    }
+
+    pub fn endpoint_id(&self) -> EndpointId {
+        self.router.endpoint().id()
+    }
+
+    pub fn endpoint_addr(&self) -> iroh::EndpointAddr {
+        self.router.endpoint().addr()
+    }
+
+    /// See [`StoragePeerOpts::allow_list`].
+    pub fn set_allow_list(&self, allow_list: Option<HashSet<EndpointId>>) {
+        *self.allow_list.write().expect(ERROR_MUTEX) = allow_list;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fragment() -> SealedItem {
+        SealedItem::Fragment {
+            doc_id: DocumentId::new([1; 32]),
+            part_id: PartId::new([2; 32]),
+            changes: vec![([3; 32], vec![4, 5, 6])],
+        }
+    }
+
+    #[test]
+    fn sealing_is_deterministic_and_opens() -> Res<()> {
+        let cipher = VaultCipher::new(&VaultKey::generate());
+        let sealed = cipher.seal(&fragment())?;
+        assert_eq!(cipher.seal(&fragment())?, sealed);
+        assert_eq!(cipher.open(&sealed)?, fragment());
+        Ok(())
+    }
+
+    #[test]
+    fn sealed_objects_dont_open_when_tampered_or_under_other_keys() -> Res<()> {
+        let cipher = VaultCipher::new(&VaultKey::generate());
+        let sealed = cipher.seal(&fragment())?;
+        assert!(VaultCipher::new(&VaultKey::generate())
+            .open(&sealed)
+            .is_err());
+
+        let mut flipped = sealed.clone();
+        *flipped.ciphertext.last_mut().unwrap() ^= 1;
+        assert!(cipher.open(&flipped).is_err());
+
+        let moved = SealedObj {
+            obj_id: ObjId::new([9; 32]),
+            ..sealed
+        };
+        assert!(cipher.open(&moved).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn vault_key_round_trips_through_base58() -> Res<()> {
+        let key = VaultKey::generate();
+        assert_eq!(VaultKey::from_base58(&key.to_base58())?, key);
+        Ok(())
+    }
+}
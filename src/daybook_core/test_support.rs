@@ -251,6 +251,7 @@ pub async fn test_cx_with_options(
         Arc::clone(&big_repo),
         Arc::clone(&part_store),
         drawer_doc_id,
+        Some(app_doc_id),
         local_user_path.clone(),
         sql_ctx.clone(),
         temp_dir.path().join("local_state"),
@@ -294,6 +295,7 @@ pub async fn test_cx_with_options(
         blobs_root: repo_root.join("blobs"),
         marker_path: repo_root.join("db.repo.txt"),
         lock_path: repo_root.join("repo.lock"),
+        daemon_info_path: repo_root.join(crate::daemon::DAEMON_INFO_FILE),
     };
     let lock_guard = crate::repo::RepoLockGuard::acquire(&layout.lock_path)?;
     let secret_repo = crate::secrets::SecretRepo::boot().await?;
@@ -319,6 +321,7 @@ pub async fn test_cx_with_options(
             iroh_public_key: peer_id.to_string(),
             iroh_secret_key,
             secret_repo,
+            data_key: None,
         },
         big_repo
             .get_doc(&app_doc_id)
@@ -347,12 +350,14 @@ pub async fn test_cx_with_options(
         .await?;
 
     let (sqlite_local_state_repo, sqlite_local_state_stop) =
-        crate::local_state::SqliteLocalStateRepo::boot(temp_dir.path().join("local_state")).await?;
+        crate::local_state::SqliteLocalStateRepo::boot(temp_dir.path().join("local_state"), None)
+            .await?;
 
     let (rt, rt_stop) = crate::rt::Rt::boot(
         crate::rt::RtConfig {
             device_id: device_id.clone(),
             startup_progress_task_id: None,
+            processor_filter: None,
         },
         rcx,
         Arc::clone(&drawer_repo),
@@ -30,6 +30,9 @@ pub struct FfiCtx {
     #[expect(unused)]
     pub acx: Arc<AppCtx>,
     pub rcx: Arc<daybook_core::repo::RepoCtx>,
+    /// Lets other processes like the CLI use the repo while the app has it.
+    pub daemon: Arc<daybook_core::daemon::RepoDaemon>,
+    _daemon_stop: daybook_core::daemon::RepoDaemonStopToken,
 }
 pub type SharedFfiCtx = Arc<FfiCtx>;
 
@@ -183,32 +186,40 @@ impl FfiCtx {
 
         let repo_root_for_init = std::path::PathBuf::from(repo_root);
 
-        let (rcx, acx) = do_on_rt(&rt, async move {
+        let (rcx, acx, (daemon, _daemon_stop)) = do_on_rt(&rt, async move {
             let device_name = format!("daybook-ffi-{}", std::env::consts::ARCH);
             let rcx = if daybook_core::repo::is_repo_initialized(&repo_root_for_init).await? {
                 acx.open_repo(
                     &repo_root_for_init,
-                    daybook_core::repo::RepoOpenOptions {},
+                    daybook_core::repo::RepoOpenOptions::default(),
                     device_name.clone(),
                 )
                 .await?
             } else {
                 acx.init_repo(
                     &repo_root_for_init,
-                    daybook_core::repo::RepoOpenOptions {},
+                    daybook_core::repo::RepoOpenOptions::default(),
                     device_name.clone(),
                     device_name,
                 )
                 .await?
             };
 
-            eyre::Ok((rcx, acx))
+            let daemon = daybook_core::daemon::RepoDaemon::serve(&rcx).await?;
+
+            eyre::Ok((rcx, acx, daemon))
         })
         .await
         .wrap_err("error initializing main Ctx")
         .inspect_err(|err| tracing::error!(?err))?;
 
-        Ok(Arc::new(Self { rcx, acx, rt }))
+        Ok(Arc::new(Self {
+            rcx,
+            acx,
+            rt,
+            daemon,
+            _daemon_stop,
+        }))
     }
 }
 
@@ -279,7 +290,7 @@ impl AppFfiCtx {
                 .inner
                 .open_repo(
                     &repo_root,
-                    daybook_core::repo::RepoOpenOptions {},
+                    daybook_core::repo::RepoOpenOptions::default(),
                     device_name,
                 )
                 .await?;
@@ -371,7 +382,7 @@ impl AppFfiCtx {
                 .inner
                 .open_repo(
                     &out.repo_path,
-                    daybook_core::repo::RepoOpenOptions {},
+                    daybook_core::repo::RepoOpenOptions::default(),
                     device_name,
                 )
                 .await?;
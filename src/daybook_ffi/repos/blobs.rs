@@ -15,12 +15,13 @@ impl BlobsRepoFfi {
     #[tracing::instrument(err, skip(fcx))]
     pub async fn load(fcx: SharedFfiCtx) -> Result<Arc<Self>, FfiError> {
         let repo = fcx
-            .do_on_rt(daybook_core::blobs::BlobsRepo::new(
+            .do_on_rt(daybook_core::blobs::BlobsRepo::new_keyed(
                 fcx.rcx.layout.blobs_root.to_path_buf(),
                 fcx.rcx.local_user_path.clone(),
                 Arc::new(daybook_core::blobs::PartitionStoreMembershipWriter::new(
                     Arc::clone(&fcx.rcx.part_store),
                 )),
+                fcx.rcx.data_key.clone(),
             ))
             .await?;
         Ok(Arc::new(Self { fcx, repo }))
@@ -4,8 +4,10 @@ use crate::ffi::{FfiError, SharedFfiCtx};
 use crate::repos::plugs::PlugsRepoFfi;
 
 use daybook_core::drawer::types::UpdateDocArgsV2 as UpdateDocArgs;
-use daybook_core::drawer::{DocBundle, DocEntry, DocNBranches, DrawerEvent, DrawerRepo};
-use daybook_types::doc::{AddDocArgs, ChangeHashSet, Doc, DocId, DocPatch};
+use daybook_core::drawer::{
+    DocBundle, DocEntry, DocNBranches, DrawerEvent, DrawerRepo, FacetConflict,
+};
+use daybook_types::doc::{AddDocArgs, ChangeHashSet, Doc, DocId, DocPatch, FacetKey};
 
 #[derive(uniffi::Object)]
 pub struct DrawerRepoFfi {
@@ -38,6 +40,7 @@ impl DrawerRepoFfi {
                 Arc::clone(&fcx.rcx.big_repo),
                 Arc::clone(&fcx.rcx.part_store),
                 fcx.rcx.doc_drawer.document_id(),
+                Some(fcx.rcx.doc_app.document_id()),
                 fcx.rcx.local_user_path.clone(),
                 fcx.rcx.sql.clone(),
                 fcx.rcx.layout.repo_root.join("local_state"),
@@ -51,6 +54,7 @@ impl DrawerRepoFfi {
             ))
             .await
             .inspect_err(|err| tracing::error!(?err))?;
+        fcx.daemon.set_drawer(Arc::clone(&repo));
         Ok(Arc::new(Self {
             fcx,
             repo,
@@ -71,6 +75,22 @@ impl DrawerRepoFfi {
             .await
     }
 
+    /// Flags the actors of revoked devices in the Dmeta facet of docs
+    /// read from this repo.
+    async fn track_device_revocations(
+        self: Arc<Self>,
+        config_repo: Arc<crate::repos::config::ConfigRepoFfi>,
+    ) {
+        let this = Arc::clone(&self);
+        self.fcx
+            .do_on_rt(async move {
+                this.repo
+                    .track_device_revocations(Arc::clone(&config_repo.repo))
+                    .await
+            })
+            .await
+    }
+
     #[tracing::instrument(skip(self))]
     async fn list(self: Arc<Self>) -> Vec<DocNBranches> {
         let this = Arc::clone(&self);
@@ -177,6 +197,50 @@ impl DrawerRepoFfi {
             .await?)
     }
 
+    #[tracing::instrument(err, skip(self))]
+    async fn get_facet_conflicts(
+        self: Arc<Self>,
+        id: DocId,
+        branch_path: String,
+    ) -> Result<Vec<FacetConflict>, FfiError> {
+        let this = Arc::clone(&self);
+        let branch_path = daybook_types::doc::BranchPathBuf::from(branch_path);
+        Ok(self
+            .fcx
+            .do_on_rt(async move { this.repo.get_facet_conflicts(&id, &branch_path).await })
+            .await?)
+    }
+
+    #[tracing::instrument(err, skip(self, value))]
+    async fn resolve_facet_conflict(
+        self: Arc<Self>,
+        id: DocId,
+        branch_path: String,
+        facet_key: FacetKey,
+        path: String,
+        value: serde_json::Value,
+        user_path: Option<daybook_types::doc::UserPathBuf>,
+    ) -> Result<(), FfiError> {
+        let this = Arc::clone(&self);
+        let branch_path = daybook_types::doc::BranchPathBuf::from(branch_path);
+        Ok(self
+            .fcx
+            .do_on_rt(async move {
+                this.repo
+                    .resolve_facet_conflict(
+                        &id,
+                        &branch_path,
+                        &facet_key,
+                        &path,
+                        value,
+                        user_path.as_deref(),
+                    )
+                    .await
+                    .wrap_err("error resolving conflict")
+            })
+            .await?)
+    }
+
     #[tracing::instrument(err, skip(self))]
     async fn update_batch(self: Arc<Self>, patches: Vec<UpdateDocArgs>) -> Result<(), FfiError> {
         let this = Arc::clone(&self);
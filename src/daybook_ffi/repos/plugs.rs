@@ -56,6 +56,7 @@ impl PlugsRepoFfi {
             ))
             .await
             .inspect_err(|err| tracing::error!(?err))?;
+        fcx.daemon.set_plugs(Arc::clone(&repo));
         Ok(Arc::new(Self {
             fcx,
             repo,
@@ -32,6 +32,7 @@ impl SqliteLocalStateRepoFfi {
         let (repo, stop_token) = fcx
             .do_on_rt(SqliteLocalStateRepo::boot(
                 fcx.rcx.layout.repo_root.join("local_state"),
+                fcx.rcx.data_key.clone(),
             ))
             .await
             .inspect_err(|err| tracing::error!(?err))?;
@@ -12,6 +12,63 @@ use daybook_core::repos::RepoStopToken;
 use daybook_core::sync::{IrohSyncRepo, IrohSyncRepoStopToken};
 use qrcode::QrCode;
 
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PairingCandidate {
+    pub endpoint_id: String,
+    pub device_name: String,
+    pub last_seen_at: Timestamp,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PartitionSyncStatus {
+    pub partition: String,
+    pub last_full_sync_at: Option<Timestamp>,
+    pub stale_since: Option<Timestamp>,
+    pub cursor_lag: Option<u64>,
+    pub lag_sampled_at: Option<Timestamp>,
+}
+
+#[derive(Debug, Clone, uniffi::Record)]
+pub struct PeerSyncStatus {
+    pub peer_key: String,
+    pub last_connected_at: Option<Timestamp>,
+    pub last_full_sync_at: Option<Timestamp>,
+    pub stale_since: Option<Timestamp>,
+    pub last_error: Option<String>,
+    pub last_error_at: Option<Timestamp>,
+    pub blob_bytes_received: u64,
+    pub rpc_bytes_sent: u64,
+    pub blob_backlog: u64,
+    pub partitions: Vec<PartitionSyncStatus>,
+}
+
+impl From<daybook_core::sync::PeerSyncStatus> for PeerSyncStatus {
+    fn from(status: daybook_core::sync::PeerSyncStatus) -> Self {
+        Self {
+            peer_key: status.peer_key.to_string(),
+            last_connected_at: status.last_connected_at,
+            last_full_sync_at: status.last_full_sync_at,
+            stale_since: status.stale_since,
+            last_error: status.last_error,
+            last_error_at: status.last_error_at,
+            blob_bytes_received: status.blob_bytes_received,
+            rpc_bytes_sent: status.rpc_bytes_sent,
+            blob_backlog: status.blob_backlog,
+            partitions: status
+                .partitions
+                .into_iter()
+                .map(|part| PartitionSyncStatus {
+                    partition: part.partition,
+                    last_full_sync_at: part.last_full_sync_at,
+                    stale_since: part.stale_since,
+                    cursor_lag: part.cursor_lag,
+                    lag_sampled_at: part.lag_sampled_at,
+                })
+                .collect(),
+        }
+    }
+}
+
 #[derive(uniffi::Object)]
 pub struct SyncRepoFfi {
     fcx: SharedFfiCtx,
@@ -35,6 +92,7 @@ impl SyncRepoFfi {
         let (sqlite_local_state_repo, sqlite_local_state_stop_token) = fcx
             .do_on_rt(SqliteLocalStateRepo::boot(
                 fcx.rcx.layout.repo_root.join("local_state"),
+                fcx.rcx.data_key.clone(),
             ))
             .await?;
         let (doc_blobs_index_repo, doc_blobs_index_stop_token) = fcx
@@ -54,6 +112,7 @@ impl SyncRepoFfi {
                 Some(Arc::clone(&progress_repo.repo)),
             ))
             .await?;
+        fcx.daemon.set_sync(Arc::clone(&repo));
 
         Ok(Arc::new(Self {
             fcx,
@@ -169,4 +228,68 @@ impl SyncRepoFfi {
             })
             .await
     }
+
+    async fn start_lan_discovery(self: Arc<Self>) -> Result<(), FfiError> {
+        let this = Arc::clone(&self);
+        self.fcx
+            .do_on_rt(async move {
+                this.repo
+                    .start_default_lan_discovery()
+                    .await
+                    .map_err(FfiError::from)
+            })
+            .await
+    }
+
+    async fn sync_status(self: Arc<Self>) -> Result<Vec<PeerSyncStatus>, FfiError> {
+        let this = Arc::clone(&self);
+        self.fcx
+            .do_on_rt(async move {
+                let status = this.repo.sync_status().await?;
+                Ok::<_, FfiError>(status.into_iter().map(PeerSyncStatus::from).collect())
+            })
+            .await
+    }
+
+    async fn refresh_sync_status(self: Arc<Self>) -> Result<(), FfiError> {
+        let this = Arc::clone(&self);
+        self.fcx
+            .do_on_rt(async move {
+                this.repo
+                    .refresh_sync_status()
+                    .await
+                    .map_err(FfiError::from)
+            })
+            .await
+    }
+
+    fn list_pairing_candidates(&self) -> Vec<PairingCandidate> {
+        self.repo
+            .list_pairing_candidates()
+            .into_iter()
+            .map(|candidate| PairingCandidate {
+                endpoint_id: candidate.endpoint_addr.id.to_string(),
+                device_name: candidate.device_name,
+                last_seen_at: candidate.last_seen_at,
+            })
+            .collect()
+    }
+
+    async fn allow_pairing_candidate(self: Arc<Self>, endpoint_id: String) -> Result<(), FfiError> {
+        let this = Arc::clone(&self);
+        self.fcx
+            .do_on_rt(async move {
+                let candidate = this
+                    .repo
+                    .list_pairing_candidates()
+                    .into_iter()
+                    .find(|candidate| candidate.endpoint_addr.id.to_string() == endpoint_id)
+                    .ok_or_else(|| eyre::eyre!("no pairing candidate found for {endpoint_id}"))?;
+                this.repo
+                    .allow_peer_by_endpoint_id(candidate.endpoint_addr.id)
+                    .await?;
+                Ok::<(), FfiError>(())
+            })
+            .await
+    }
 }
@@ -69,6 +69,7 @@ impl RtFfi {
         let rt_config = RtConfig {
             device_id,
             startup_progress_task_id,
+            processor_filter: None,
         };
         let (rt, stop_token) = fcx
             .do_on_rt(Rt::boot(
@@ -85,6 +86,7 @@ impl RtFfi {
             ))
             .await
             .inspect_err(|err| tracing::error!(?err))?;
+        fcx.daemon.set_rt(Arc::clone(&rt));
 
         Ok(Arc::new(Self {
             fcx,
@@ -515,6 +515,7 @@ mod tests {
                 Arc::clone(&repo_ctx.big_repo),
                 Arc::clone(&repo_ctx.part_store),
                 repo_ctx.doc_drawer.document_id().clone(),
+                Some(repo_ctx.doc_app.document_id()),
                 local_user_path,
                 repo_ctx.sql.clone(),
                 temp_dir.path().join("local_state"),
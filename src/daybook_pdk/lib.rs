@@ -144,13 +144,14 @@ where
         &InvokeCommandRequest,
     ) -> Result<wflow_sdk::Json<InvokeCommandAccepted>, wflow_sdk::JobErrorX>,
 {
-    cx.effect(|| invoke(request))
+    cx.effect_named("invoke-command", || invoke(request))
 }
 
 pub fn wait_command_reply(
     cx: &mut wflow_sdk::WflowCtx,
 ) -> Result<InvokeCommandReply, wflow_sdk::JobErrorX> {
-    let wflow_sdk::Json(reply) = cx.recv::<wflow_sdk::Json<InvokeCommandReply>>()?;
+    let wflow_sdk::Json(reply) =
+        cx.recv_named::<wflow_sdk::Json<InvokeCommandReply>>("command-reply")?;
     Ok(reply)
 }
 
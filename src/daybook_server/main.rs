@@ -1,248 +1,93 @@
-#[expect(unused)]
-mod interlude {
-    pub use api_utils_rs::{api, prelude::*};
-
-    pub use autosurgeon::{Hydrate, Reconcile};
+//! Headless, always-on sync peer.
+//!
+//! Keeps a repo open and syncing with its known devices so that
+//! there's always one peer around holding the latest state and every blob.
 
-    pub(crate) use crate::Ctx;
-    pub use generational_box::{GenerationalBox, Storage};
+mod interlude {
+    pub use utils_rs::prelude::*;
 }
 
-mod gen;
-
-use std::collections::HashSet;
-
 use crate::interlude::*;
 
-use axum::Router;
-use generational_box::SyncStorage;
-use samod::{DocumentId, PeerId};
-use tokio::{sync::mpsc, task::JoinHandle};
-use tower_http::ServiceBuilderExt;
+use std::process::ExitCode;
+
+use clap::Parser;
+
+mod metrics;
+mod node;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "Headless daybook sync and backup node")]
+pub struct Args {
+    /// Path to an initialized repo. Clone one with `daybook_cli clone` first.
+    /// With `--sealed-storage`, the directory to keep sealed objects in instead.
+    #[arg(long, env = "DAYBOOK_REPO_PATH")]
+    pub repo_path: PathBuf,
+    /// Device name used when registering this node in the repo.
+    #[arg(long, env = "DAYBOOK_DEVICE_NAME")]
+    pub device_name: Option<String>,
+    /// Endpoint ids allowed to connect. Defaults to the repo's known sync devices.
+    #[arg(long = "allow", value_name = "ENDPOINT_ID")]
+    pub allow: Vec<String>,
+    /// Accept connections from any endpoint.
+    #[arg(long, conflicts_with = "allow")]
+    pub allow_any: bool,
+    /// Plug processors to run on this node, as `{plug_id}/{processor_name}`.
+    /// No processors run if none are given.
+    #[arg(long = "processor", value_name = "PROCESSOR_ID")]
+    pub processors: Vec<String>,
+    /// Address to serve `/health` and `/metrics` on.
+    #[arg(long, env = "DAYBOOK_HTTP_ADDR", default_value = "127.0.0.1:8090")]
+    pub http_addr: std::net::SocketAddr,
+    /// Run as a storage peer that only holds end-to-end encrypted objects
+    /// and never sees the repo's contents. Needs `--allow` or `--allow-any`.
+    #[arg(long, conflicts_with = "processors")]
+    pub sealed_storage: bool,
+}
 
-fn main() -> Res<()> {
+fn main() -> Res<ExitCode> {
     utils_rs::setup_tracing()?;
+    let args = Args::parse();
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()
         .wrap_err("error building tokio rt")?;
-    rt.block_on(app_main())
-}
-
-async fn app_main() -> Res<()> {
-    let config = Config {};
-    let cx = Ctx::init(config).await?;
-
-    let app = Router::new()
-        .route("/", axum::routing::any(connect))
-        .with_state(cx)
-        // tracing layer
-        .layer(
-            tower::ServiceBuilder::new()
-                .sensitive_headers(vec![http::header::AUTHORIZATION, http::header::COOKIE])
-                .layer(
-                    tower_http::trace::TraceLayer::new_for_http()
-                        .on_response(
-                            tower_http::trace::DefaultOnResponse::new()
-                                .level(tracing::Level::INFO)
-                                .latency_unit(tower_http::LatencyUnit::Micros),
-                        )
-                        .on_failure(
-                            tower_http::trace::DefaultOnFailure::new()
-                                .level(tracing::Level::ERROR)
-                                .latency_unit(tower_http::LatencyUnit::Micros),
-                        )
-                        .make_span_with(
-                            tower_http::trace::DefaultMakeSpan::new().include_headers(true),
-                        ),
-                ),
-        );
-
-    let addr = std::net::SocketAddr::from((
-        std::net::Ipv4Addr::UNSPECIFIED,
-        utils_rs::get_env_var("PORT")
-            .and_then(|str| {
-                str.parse()
-                    .map_err(|err| ferr!("error parsing port env var ({str}): {err}"))
-            })
-            .unwrap_or(8090),
-    ));
-
-    // run our app with hyper
-    // `axum::Server` is a re-export of `hyper::Server`
-    tracing::info!(%addr, "server going online");
-    let listener = tokio::net::TcpListener::bind(&addr).await?;
-
-    axum::serve(
-        listener,
-        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
-    )
-    .await?;
-
-    Ok(())
-}
-
-struct Config {}
-
-struct Ctx {
-    _config: Config,
-    big_repo: am_utils_rs::repo::SharedBigRepo,
-    _peer_docs: Arc<DHashMap<PeerId, HashSet<DocumentId>>>,
-    _doc_peers: Arc<DHashMap<DocumentId, PeerId>>,
-    _gen_store: generational_box::Owner<SyncStorage>,
+    rt.block_on(app_main(args))
 }
 
-type SharedCtx = Arc<Ctx>;
-
-impl Ctx {
-    async fn init(config: Config) -> Res<SharedCtx> {
-        let peer_docs: Arc<DHashMap<PeerId, HashSet<DocumentId>>> = default();
-        let doc_peers: Arc<DHashMap<DocumentId, PeerId>> = default();
-
-        let (_doc_setup_req_tx, mut doc_setup_req_rx) =
-            mpsc::unbounded_channel::<DocSetupRequest>();
-        let (big_repo, _big_repo_stop) = am_utils_rs::BigRepo::boot(am_utils_rs::repo::Config {
-            peer_id: "daybook_sync".to_string(),
-            secret_key_bytes: [0u8; 32],
-            storage: am_utils_rs::repo::StorageConfig::Disk {
-                path: "/tmp/samod-sync".into(),
-                big_repo_sqlite_url: None,
-            },
-        })
-        .await?;
-
-        use generational_box::AnyStorage;
-        let cx = Arc::new(Self {
-            big_repo,
-            _config: config,
-            _peer_docs: peer_docs,
-            _doc_peers: doc_peers,
-            _gen_store: SyncStorage::owner(),
-        });
-        let doc_setup_worker = spawn_doc_setup_worker(cx.clone());
-        tokio::spawn(async move {
-            while let Some(req) = doc_setup_req_rx.recv().await {
-                doc_setup_worker.request_tx.send(req).expect(ERROR_CHANNEL)
-            }
-        });
-        Ok(cx)
+async fn app_main(args: Args) -> Res<ExitCode> {
+    if args.sealed_storage {
+        return run_storage_peer(args).await;
     }
-}
-
-#[derive(Debug)]
-struct DocSetupRequest {
-    _peer_id: PeerId,
-    doc_id: DocumentId,
-    last_attempt_backoff_ms: u64,
-}
-
-impl DocSetupRequest {
-    fn new(peer_id: PeerId, doc_id: DocumentId) -> Self {
-        Self {
-            _peer_id: peer_id,
-            doc_id,
-            last_attempt_backoff_ms: 1000,
-        }
+    if !daybook_core::repo::is_repo_initialized(&args.repo_path).await? {
+        error!(repo_path = ?args.repo_path, "no initialized repo found");
+        return Ok(ExitCode::FAILURE);
     }
+    let node = node::Node::boot(&args).await?;
+    let res = node.run(args.http_addr).await;
+    node.shutdown().await?;
+    res?;
+    Ok(ExitCode::SUCCESS)
 }
 
-struct DocSetupWorker {
-    request_tx: mpsc::UnboundedSender<DocSetupRequest>,
-    _handle: JoinHandle<()>,
-}
-
-fn spawn_doc_setup_worker(cx: SharedCtx) -> DocSetupWorker {
-    let (request_tx, mut request_rx) = mpsc::unbounded_channel::<DocSetupRequest>();
-    let fut = {
-        let request_tx = request_tx.clone();
-        async move {
-            let mut listen_list = std::collections::HashSet::new();
-            // let mut drawer_change_workers = std::collections::HashMap::new();
-
-            // with exponential backoff
-            let retry = |task: DocSetupRequest| {
-                tokio::spawn({
-                    let doc_id_tx = request_tx.clone();
-                    async move {
-                        let new_backoff =
-                            utils_rs::backoff(task.last_attempt_backoff_ms, 60 * 1000).await;
-                        doc_id_tx
-                            .send(DocSetupRequest {
-                                last_attempt_backoff_ms: new_backoff,
-                                ..task
-                            })
-                            .expect(ERROR_CHANNEL);
-                    }
-                });
-            };
-
-            while let Some(request) = request_rx.recv().await {
-                if listen_list.contains(&request.doc_id) {
-                    continue;
-                }
-                match cx.big_repo.find_doc_handle(&request.doc_id).await {
-                    Err(err) => {
-                        warn!(?request, "error looking up doc_id: {err}");
-                        retry(request);
-                        continue;
-                    }
-                    Ok(None) => {
-                        warn!(?request, "doc not found in repo during setup");
-                        retry(request);
-                        continue;
-                    }
-                    Ok(Some(_val)) => {
-                        // noop
-                    }
-                };
-
-                // inspect schema and attach schema-specific listeners
-
-                let schema_opt = cx
-                    .big_repo
-                    .hydrate_path::<String>(
-                        &request.doc_id,
-                        automerge::ROOT,
-                        vec!["$schema".into()],
-                    )
-                    .await
-                    .wrap_err("error hydrating added docment")?;
-
-                if let Some((schema, _heads)) = schema_opt {
-                    if schema == "daybook.drawer" {
-                        // TODO: spawn doc changes worker
-                    }
-                }
-
-                listen_list.insert(request.doc_id);
-            }
-            eyre::Ok(())
-        }
-    };
-
-    let handle = tokio::spawn({
-        async move {
-            fut.await.unwrap_or_log();
-        }
-    });
-    DocSetupWorker {
-        request_tx,
-        _handle: handle,
+async fn run_storage_peer(args: Args) -> Res<ExitCode> {
+    let allow_list = node::parse_allow_list(&args.allow)?;
+    if allow_list.is_none() && !args.allow_any {
+        error!("storage peers have no known devices, pass --allow or --allow-any");
+        return Ok(ExitCode::FAILURE);
     }
-}
-
-#[tracing::instrument(skip(cx))]
-async fn connect(
-    axum::extract::State(cx): axum::extract::State<SharedCtx>,
-    ws: axum::extract::WebSocketUpgrade,
-) -> axum::response::Response {
-    ws.on_upgrade(move |socket| handle_socket(cx, socket))
-}
-
-#[tracing::instrument(skip(cx))]
-async fn handle_socket(cx: SharedCtx, socket: axum::extract::ws::WebSocket) {
-    let _handle = tokio::spawn(async move {
-        let fin = cx.big_repo.samod_repo().accept_axum(socket).await;
-        info!(?fin, "connection finsihed");
-    });
+    let (storage, stop_token) =
+        daybook_core::sync::StoragePeer::boot(daybook_core::sync::StoragePeerOpts {
+            root: args.repo_path.clone(),
+            allow_list,
+        })
+        .await?;
+    info!(endpoint_id = %storage.endpoint_id(), "storage peer listening");
+    let res = tokio::signal::ctrl_c()
+        .await
+        .wrap_err("error waiting for ctrl-c");
+    info!("shutting down storage peer");
+    stop_token.stop().await?;
+    res?;
+    Ok(ExitCode::SUCCESS)
 }
@@ -0,0 +1,198 @@
+use crate::interlude::*;
+
+use std::fmt::Write;
+
+use daybook_core::sync::{IrohSyncEvent, PeerKey};
+
+#[derive(Debug, Default, Clone)]
+struct PeerStats {
+    connected: bool,
+    last_connected_at: Option<Timestamp>,
+    last_synced_at: Option<Timestamp>,
+    /// Set when the peer is known to have changes we haven't caught up on.
+    behind_since: Option<Timestamp>,
+    docs_synced: u64,
+}
+
+/// Sync state of the node as observed through [`IrohSyncEvent`]s.
+#[derive(Debug)]
+pub struct SyncMetrics {
+    started_at: Timestamp,
+    peers: std::sync::Mutex<HashMap<PeerKey, PeerStats>>,
+    blobs_synced: std::sync::atomic::AtomicU64,
+    blob_downloads_failed: std::sync::atomic::AtomicU64,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthReport {
+    pub ok: bool,
+    pub endpoint_id: String,
+    pub uptime_secs: i64,
+    pub connected_peers: usize,
+    pub max_sync_lag_secs: i64,
+}
+
+impl SyncMetrics {
+    pub fn new() -> Self {
+        Self {
+            started_at: Timestamp::now(),
+            peers: default(),
+            blobs_synced: default(),
+            blob_downloads_failed: default(),
+        }
+    }
+
+    pub fn observe(&self, event: &IrohSyncEvent) {
+        use std::sync::atomic::Ordering;
+        let now = Timestamp::now();
+        let mut peers = self.peers.lock().expect(ERROR_MUTEX);
+        match event {
+            IrohSyncEvent::IncomingConnection { peer_key }
+            | IrohSyncEvent::OutgoingConnection { peer_key } => {
+                let stats = peers.entry(Arc::clone(peer_key)).or_default();
+                stats.connected = true;
+                stats.last_connected_at = Some(now);
+                // we don't know what the peer has until the first full sync
+                stats.behind_since.get_or_insert(now);
+            }
+            IrohSyncEvent::ConnectionClosed { peer_key, .. } => {
+                if let Some(stats) = peers.get_mut(peer_key) {
+                    stats.connected = false;
+                }
+            }
+            IrohSyncEvent::StalePeer { peer_key } => {
+                peers
+                    .entry(Arc::clone(peer_key))
+                    .or_default()
+                    .behind_since
+                    .get_or_insert(now);
+            }
+            IrohSyncEvent::PeerFullySynced { peer_key, .. } => {
+                let stats = peers.entry(Arc::clone(peer_key)).or_default();
+                stats.last_synced_at = Some(now);
+                stats.behind_since = None;
+            }
+            IrohSyncEvent::DocSyncedWithPeer { peer_key, .. } => {
+                peers.entry(Arc::clone(peer_key)).or_default().docs_synced += 1;
+            }
+            IrohSyncEvent::BlobSynced { .. } => {
+                self.blobs_synced.fetch_add(1, Ordering::Relaxed);
+            }
+            IrohSyncEvent::BlobDownloadFinished { success, .. } => {
+                if !success {
+                    self.blob_downloads_failed.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            IrohSyncEvent::PartitionFullySynced { .. }
+            | IrohSyncEvent::BlobDownloadStarted { .. }
+            | IrohSyncEvent::BlobSyncBackoff { .. }
+            | IrohSyncEvent::PairingCandidateDiscovered { .. }
+            | IrohSyncEvent::UpdateRequired { .. } => {}
+        }
+    }
+
+    fn lag_secs(stats: &PeerStats, now: Timestamp) -> i64 {
+        stats
+            .behind_since
+            .map(|since| now.as_second() - since.as_second())
+            .unwrap_or(0)
+    }
+
+    pub fn health(&self, endpoint_id: String, ok: bool) -> HealthReport {
+        let now = Timestamp::now();
+        let peers = self.peers.lock().expect(ERROR_MUTEX);
+        HealthReport {
+            ok,
+            endpoint_id,
+            uptime_secs: now.as_second() - self.started_at.as_second(),
+            connected_peers: peers.values().filter(|stats| stats.connected).count(),
+            max_sync_lag_secs: peers
+                .values()
+                .filter(|stats| stats.connected)
+                .map(|stats| Self::lag_secs(stats, now))
+                .max()
+                .unwrap_or(0),
+        }
+    }
+
+    /// Renders the metrics in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        use std::sync::atomic::Ordering;
+        let now = Timestamp::now();
+        let peers = self.peers.lock().expect(ERROR_MUTEX);
+        let mut out = String::new();
+        let mut gauge = |name: &str, help: &str, samples: Vec<(String, i64)>| {
+            writeln!(out, "# HELP {name} {help}").expect(ERROR_IMPOSSIBLE);
+            writeln!(out, "# TYPE {name} gauge").expect(ERROR_IMPOSSIBLE);
+            for (labels, value) in samples {
+                writeln!(out, "{name}{labels} {value}").expect(ERROR_IMPOSSIBLE);
+            }
+        };
+        let peer_samples = |value: &dyn Fn(&PeerStats) -> Option<i64>| {
+            let mut samples = peers
+                .iter()
+                .filter_map(|(peer_key, stats)| {
+                    value(stats).map(|val| (format!("{{peer=\"{peer_key}\"}}"), val))
+                })
+                .collect::<Vec<_>>();
+            samples.sort();
+            samples
+        };
+        gauge(
+            "daybook_node_uptime_seconds",
+            "Seconds since the node started.",
+            vec![(String::new(), now.as_second() - self.started_at.as_second())],
+        );
+        gauge(
+            "daybook_node_connected_peers",
+            "Number of peers with an open connection.",
+            vec![(
+                String::new(),
+                peers.values().filter(|stats| stats.connected).count() as i64,
+            )],
+        );
+        gauge(
+            "daybook_node_blobs_synced",
+            "Blobs synced since the node started.",
+            vec![(
+                String::new(),
+                self.blobs_synced.load(Ordering::Relaxed) as i64,
+            )],
+        );
+        gauge(
+            "daybook_node_blob_downloads_failed",
+            "Failed blob downloads since the node started.",
+            vec![(
+                String::new(),
+                self.blob_downloads_failed.load(Ordering::Relaxed) as i64,
+            )],
+        );
+        gauge(
+            "daybook_node_peer_connected",
+            "Whether the peer currently has an open connection.",
+            peer_samples(&|stats| Some(stats.connected as i64)),
+        );
+        gauge(
+            "daybook_node_peer_sync_lag_seconds",
+            "Seconds the node has been behind the peer. Zero when fully synced.",
+            peer_samples(&|stats| Some(Self::lag_secs(stats, now))),
+        );
+        gauge(
+            "daybook_node_peer_last_synced_timestamp_seconds",
+            "Unix time of the last full sync with the peer.",
+            peer_samples(&|stats| stats.last_synced_at.map(|ts| ts.as_second())),
+        );
+        gauge(
+            "daybook_node_peer_last_connected_timestamp_seconds",
+            "Unix time of the last connection with the peer.",
+            peer_samples(&|stats| stats.last_connected_at.map(|ts| ts.as_second())),
+        );
+        gauge(
+            "daybook_node_peer_docs_synced",
+            "Docs synced with the peer since the node started.",
+            peer_samples(&|stats| Some(stats.docs_synced as i64)),
+        );
+        out
+    }
+}
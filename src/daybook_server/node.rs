@@ -0,0 +1,394 @@
+use crate::interlude::*;
+
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use daybook_core::blobs::BlobsRepo;
+use daybook_core::config::{ConfigEvent, ConfigRepo};
+use daybook_core::repo::RepoCtx;
+use daybook_core::repos::Repo;
+use daybook_core::sync::IrohSyncRepo;
+
+use crate::metrics::SyncMetrics;
+use crate::Args;
+
+type ShutdownFn = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = Res<()>> + Send>> + Send>;
+
+pub struct Node {
+    rcx: Arc<RepoCtx>,
+    config_repo: Arc<ConfigRepo>,
+    sync_repo: Arc<IrohSyncRepo>,
+    rt: Option<Arc<daybook_core::rt::Rt>>,
+    /// `None` when the allow-list tracks the repo's known sync devices.
+    static_allow_list: Option<HashSet<iroh::EndpointId>>,
+    allow_any: bool,
+    metrics: Arc<SyncMetrics>,
+    stop_callbacks: Vec<ShutdownFn>,
+}
+
+#[derive(Clone)]
+struct HttpState {
+    sync_repo: Arc<IrohSyncRepo>,
+    metrics: Arc<SyncMetrics>,
+    cancel_token: tokio_util::sync::CancellationToken,
+}
+
+/// `None` when no endpoint ids were given.
+pub fn parse_allow_list(ids: &[String]) -> Res<Option<HashSet<iroh::EndpointId>>> {
+    if ids.is_empty() {
+        return Ok(None);
+    }
+    let allow_list = ids
+        .iter()
+        .map(|id| {
+            iroh::EndpointId::from_str(id).map_err(|err| ferr!("invalid endpoint id '{id}': {err}"))
+        })
+        .collect::<Res<HashSet<_>>>()?;
+    Ok(Some(allow_list))
+}
+
+impl Node {
+    pub async fn boot(args: &Args) -> Res<Self> {
+        let static_allow_list = parse_allow_list(&args.allow)?;
+
+        let device_name = args
+            .device_name
+            .clone()
+            .unwrap_or_else(|| format!("daybook-server-{}", std::env::consts::ARCH));
+        let rcx = RepoCtx::open(
+            &args.repo_path,
+            daybook_core::repo::RepoOpenOptions::default(),
+            device_name,
+        )
+        .await?;
+        let mut stop_callbacks: Vec<ShutdownFn> = vec![];
+        macro_rules! on_shutdown {
+            ($stop:expr) => {{
+                let stop = $stop;
+                stop_callbacks.push(Box::new(move || Box::pin(async move { stop.stop().await })));
+            }};
+        }
+
+        let blobs_repo = BlobsRepo::new_keyed(
+            rcx.layout.blobs_root.clone(),
+            rcx.local_user_path.clone(),
+            Arc::new(daybook_core::blobs::PartitionStoreMembershipWriter::new(
+                Arc::clone(&rcx.part_store),
+            )),
+            rcx.data_key.clone(),
+        )
+        .await?;
+        stop_callbacks.push(Box::new({
+            let blobs_repo = Arc::clone(&blobs_repo);
+            move || Box::pin(async move { blobs_repo.shutdown().await })
+        }));
+        let (plugs_repo, plugs_stop) = daybook_core::plugs::PlugsRepo::load(
+            Arc::clone(&rcx.big_repo),
+            Arc::clone(&blobs_repo),
+            rcx.doc_app.document_id(),
+            daybook_types::doc::UserPathBuf::from(rcx.local_user_path.clone()),
+        )
+        .await?;
+        on_shutdown!(plugs_stop);
+        let (drawer_repo, drawer_stop) = daybook_core::drawer::DrawerRepo::load(
+            Arc::clone(&rcx.big_repo),
+            Arc::clone(&rcx.part_store),
+            rcx.doc_drawer.document_id(),
+            Some(rcx.doc_app.document_id()),
+            rcx.local_user_path.clone(),
+            rcx.sql.clone(),
+            rcx.layout.repo_root.join("local_state"),
+            Arc::new(surelock::mutex::Mutex::new(
+                daybook_core::drawer::lru::KeyedLruPool::new(1000),
+            )),
+            Arc::new(surelock::mutex::Mutex::new(
+                daybook_core::drawer::lru::KeyedLruPool::new(1000),
+            )),
+            Arc::clone(&plugs_repo),
+        )
+        .await?;
+        on_shutdown!(drawer_stop);
+        let (config_repo, config_stop) = ConfigRepo::load(
+            Arc::clone(&rcx.big_repo),
+            rcx.doc_app.document_id(),
+            Arc::clone(&plugs_repo),
+            daybook_types::doc::UserPathBuf::from(rcx.local_user_path.clone()),
+            rcx.sql.clone(),
+        )
+        .await?;
+        on_shutdown!(config_stop);
+        drawer_repo
+            .track_device_revocations(Arc::clone(&config_repo))
+            .await;
+        let (local_state_repo, local_state_stop) =
+            daybook_core::local_state::SqliteLocalStateRepo::boot(
+                rcx.layout.repo_root.join("local_state"),
+                rcx.data_key.clone(),
+            )
+            .await?;
+        on_shutdown!(local_state_stop);
+        let (doc_blobs_index_repo, doc_blobs_index_stop) =
+            daybook_core::index::DocBlobsIndexRepo::boot(
+                Arc::clone(&drawer_repo),
+                Arc::clone(&blobs_repo),
+                Arc::clone(&local_state_repo),
+            )
+            .await?;
+        on_shutdown!(doc_blobs_index_stop);
+        let (progress_repo, progress_stop) =
+            daybook_core::progress::ProgressRepo::boot(rcx.sql.clone()).await?;
+        on_shutdown!(progress_stop);
+        let (sync_repo, sync_stop) = IrohSyncRepo::boot(
+            Arc::clone(&rcx),
+            Arc::clone(&config_repo),
+            Arc::clone(&blobs_repo),
+            Arc::clone(&doc_blobs_index_repo),
+            Some(Arc::clone(&progress_repo)),
+        )
+        .await?;
+        on_shutdown!(sync_stop);
+
+        // a backup node is only useful if it holds everything
+        sync_repo.set_blob_cache_quota(None).await?;
+        let local_endpoint_id = sync_repo.endpoint_addr().id;
+        let profile = config_repo
+            .get_sync_profile(&local_endpoint_id)
+            .await
+            .unwrap_or_default();
+        if !profile.is_unrestricted() {
+            warn!("resetting restricted sync profile of this node");
+            config_repo
+                .set_sync_profile(&local_endpoint_id, default())
+                .await?;
+        }
+
+        let (dispatch_repo, dispatch_stop) = daybook_core::rt::dispatch::DispatchRepo::load(
+            Arc::clone(&rcx.big_repo),
+            rcx.doc_app.document_id(),
+            daybook_types::doc::UserPathBuf::from(rcx.local_user_path.clone()),
+            rcx.sql.clone(),
+        )
+        .await?;
+        on_shutdown!(dispatch_stop);
+
+        let rt = if args.processors.is_empty() {
+            // the rt runs gc maintenance, without one it's on us
+            let gc_repo = daybook_core::gc::GcRepo::load(
+                Arc::clone(&rcx),
+                Arc::clone(&drawer_repo),
+                Arc::clone(&dispatch_repo),
+            )
+            .await?;
+            on_shutdown!(gc_repo.spawn_maintenance());
+            None
+        } else {
+            let (init_repo, init_stop) = daybook_core::rt::init::InitRepo::load(
+                Arc::clone(&rcx.big_repo),
+                rcx.doc_app.document_id(),
+                rcx.local_user_path.clone(),
+                rcx.sql.clone(),
+                Arc::clone(&progress_repo),
+                None,
+            )
+            .await?;
+            on_shutdown!(init_stop);
+            let (rt, rt_stop) = daybook_core::rt::Rt::boot(
+                daybook_core::rt::RtConfig {
+                    device_id: local_endpoint_id.to_string(),
+                    startup_progress_task_id: None,
+                    processor_filter: Some(args.processors.iter().cloned().collect()),
+                },
+                Arc::clone(&rcx),
+                Arc::clone(&drawer_repo),
+                Arc::clone(&plugs_repo),
+                dispatch_repo,
+                progress_repo,
+                blobs_repo,
+                Arc::clone(&config_repo),
+                init_repo,
+                local_state_repo,
+            )
+            .await?;
+            on_shutdown!(rt_stop);
+            Some(rt)
+        };
+
+        // served last so that it's the first to go on shutdown
+        let (daemon, daemon_stop) = daybook_core::daemon::RepoDaemon::serve(&rcx).await?;
+        daemon.set_plugs(Arc::clone(&plugs_repo));
+        daemon.set_drawer(Arc::clone(&drawer_repo));
+        daemon.set_sync(Arc::clone(&sync_repo));
+        if let Some(rt) = &rt {
+            daemon.set_rt(Arc::clone(rt));
+        }
+        on_shutdown!(daemon_stop);
+
+        Ok(Self {
+            rcx,
+            config_repo,
+            sync_repo,
+            rt,
+            static_allow_list,
+            allow_any: args.allow_any,
+            metrics: Arc::new(SyncMetrics::new()),
+            stop_callbacks,
+        })
+    }
+
+    async fn refresh_allow_list(&self) -> Res<()> {
+        if self.allow_any {
+            self.sync_repo.set_incoming_allow_list(None);
+            return Ok(());
+        }
+        let allow_list = match &self.static_allow_list {
+            Some(allow_list) => allow_list.clone(),
+            None => self
+                .config_repo
+                .list_known_sync_devices()
+                .await?
+                .into_iter()
+                .map(|device| device.endpoint_id)
+                .collect(),
+        };
+        debug!(count = allow_list.len(), "updated incoming allow-list");
+        self.sync_repo.set_incoming_allow_list(Some(allow_list));
+        Ok(())
+    }
+
+    /// Serves http and keeps syncing until ctrl-c.
+    pub async fn run(&self, http_addr: std::net::SocketAddr) -> Res<()> {
+        self.refresh_allow_list().await?;
+        let sync_listener = self
+            .sync_repo
+            .subscribe(daybook_core::repos::SubscribeOpts::new(1024));
+        let mut config_listener = self
+            .config_repo
+            .subscribe(daybook_core::repos::SubscribeOpts::new(64));
+
+        self.sync_repo.connect_known_devices_once().await?;
+        if let Err(err) = self.sync_repo.start_default_lan_discovery().await {
+            warn!(?err, "lan discovery unavailable");
+        }
+
+        let cancel_token = tokio_util::sync::CancellationToken::new();
+        let http_state = HttpState {
+            sync_repo: Arc::clone(&self.sync_repo),
+            metrics: Arc::clone(&self.metrics),
+            cancel_token: cancel_token.clone(),
+        };
+        let app = axum::Router::new()
+            .route("/health", axum::routing::get(health))
+            .route("/metrics", axum::routing::get(metrics))
+            .with_state(http_state);
+        let listener = tokio::net::TcpListener::bind(&http_addr)
+            .await
+            .wrap_err_with(|| format!("error binding http listener on {http_addr}"))?;
+        info!(
+            %http_addr,
+            endpoint_id = %self.sync_repo.endpoint_addr().id,
+            processors = self.rt.is_some(),
+            "sync node online"
+        );
+        let http_handle = tokio::spawn({
+            let cancel_token = cancel_token.clone();
+            async move {
+                axum::serve(listener, app)
+                    .with_graceful_shutdown(cancel_token.cancelled_owned())
+                    .await
+            }
+        });
+
+        let res = async {
+            loop {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {
+                        info!("ctrl-c received, shutting down");
+                        break;
+                    }
+                    event = sync_listener.recv_lossy_async() => {
+                        let event = event.map_err(|err| ferr!("sync listener closed: {err:?}"))?;
+                        self.metrics.observe(&event);
+                    }
+                    event = config_listener.recv_async() => match event {
+                        Ok(event) => {
+                            if let ConfigEvent::SyncDevicesChanged { .. } = &*event {
+                                self.refresh_allow_list().await?;
+                            }
+                        }
+                        Err(daybook_core::repos::RecvError::Closed) => {
+                            warn!("config listener closed; re-subscribing");
+                            config_listener = self
+                                .config_repo
+                                .subscribe(daybook_core::repos::SubscribeOpts::new(64));
+                        }
+                        Err(daybook_core::repos::RecvError::Dropped { dropped_count }) => {
+                            warn!(dropped_count, "config listener dropped events");
+                            self.refresh_allow_list().await?;
+                        }
+                    }
+                }
+            }
+            eyre::Ok(())
+        }
+        .await;
+        cancel_token.cancel();
+        utils_rs::wait_on_handle_with_timeout(http_handle, Duration::from_secs(5))
+            .await?
+            .wrap_err("error serving http")?;
+        res
+    }
+
+    pub async fn shutdown(self) -> Res<()> {
+        let Self {
+            rcx,
+            config_repo,
+            sync_repo,
+            rt,
+            metrics,
+            stop_callbacks,
+            ..
+        } = self;
+        // the repo ctx can only shut down once nothing else holds on to it
+        drop((config_repo, sync_repo, rt, metrics));
+        let mut first_err: Option<eyre::Report> = None;
+        for callback in stop_callbacks.into_iter().rev() {
+            if let Err(err) = callback().await {
+                if first_err.is_none() {
+                    first_err = Some(err);
+                } else {
+                    warn!(?err, "shutdown callback failed after first error");
+                }
+            }
+        }
+        if let Err(err) = rcx.shutdown().await {
+            first_err.get_or_insert(err);
+        }
+        if let Some(err) = first_err {
+            return Err(err);
+        }
+        Ok(())
+    }
+}
+
+async fn health(
+    axum::extract::State(state): axum::extract::State<HttpState>,
+) -> (
+    axum::http::StatusCode,
+    axum::Json<crate::metrics::HealthReport>,
+) {
+    let ok = !state.cancel_token.is_cancelled();
+    let report = state
+        .metrics
+        .health(state.sync_repo.endpoint_addr().id.to_string(), ok);
+    let status = if ok {
+        axum::http::StatusCode::OK
+    } else {
+        axum::http::StatusCode::SERVICE_UNAVAILABLE
+    };
+    (status, axum::Json(report))
+}
+
+async fn metrics(axum::extract::State(state): axum::extract::State<HttpState>) -> String {
+    state.metrics.render_prometheus()
+}
@@ -12,6 +12,9 @@ pub type MimeType = String;
 pub struct UserMeta {
     #[cfg_attr(feature = "schemars", schemars(with = "String"))]
     pub user_path: UserPathBuf,
+    /// Set on read when the actor's device has been revoked. Never stored.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub revoked: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -176,6 +179,16 @@ pub struct NoteMimeOption {
     pub description: String,
 }
 
+impl Dmeta {
+    /// Marks the actors whose device is in `revoked_device_ids`.
+    pub fn flag_revoked_actors(&mut self, revoked_device_ids: &HashSet<String>) {
+        for meta in self.actors.values_mut() {
+            meta.revoked = user_path::device_id(&meta.user_path)
+                .is_some_and(|device_id| revoked_device_ids.contains(device_id));
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 #[cfg_attr(feature = "uniffi", derive(uniffi::Record))]
 #[serde(rename_all = "camelCase")]
@@ -387,6 +400,22 @@ pub mod user_path {
         path
     }
 
+    pub fn device_id_for_endpoint(pubkey: &[u8; 32]) -> String {
+        format!(
+            "{DEVICE_ID_PREFIX}{}",
+            utils_rs::hash::encode_base58_multibase(pubkey)
+        )
+    }
+
+    /// The device segment of a `/{user_id}/{device_id}/...` path.
+    pub fn device_id(path: &UserPath) -> Option<&str> {
+        path.as_str()
+            .trim_start_matches('/')
+            .split('/')
+            .nth(1)
+            .filter(|segment| segment.starts_with(DEVICE_ID_PREFIX))
+    }
+
     pub fn to_actor_id(path: &UserPathBuf) -> automerge::ActorId {
         let hash = blake3::hash(path.as_str().as_bytes());
         let mut bytes = [0u8; 16];
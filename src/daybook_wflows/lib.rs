@@ -30,6 +30,7 @@ mod wit {
 
             "townframe:mltools/ocr": generate,
             "townframe:mltools/embed": generate,
+            "townframe:mltools/routing": generate,
             "townframe:sql/types": generate,
 
             "townframe:daybook-types/doc": generate,
@@ -6,6 +6,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
     use crate::wit::townframe::daybook::capabilities::FacetRights;
     use crate::wit::townframe::daybook::facet_routine;
     use crate::wit::townframe::daybook::mltools_embed;
+    use crate::wit::townframe::mltools::routing::CallConstraints;
     use daybook_types::doc::{WellKnownFacet, WellKnownFacetTag};
 
     let args = facet_routine::get_args();
@@ -54,8 +55,9 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
     let blob_embed_token = blob_facet_token.clone(None).map_err(|err| {
         JobErrorX::Terminal(ferr!("access error cloning blob facet token: {err:?}"))
     })?;
-    let embed_result = mltools_embed::embed_image(blob_embed_token)
-        .map_err(|err| JobErrorX::Terminal(ferr!("error running embed-image: {err}")))?;
+    let embed_result =
+        mltools_embed::embed_image(blob_embed_token, CallConstraints { local_only: false })
+            .map_err(|err| JobErrorX::Terminal(ferr!("error running embed-image: {err}")))?;
 
     let heads = am_utils_rs::parse_commit_heads(&args.heads)
         .map_err(|err| JobErrorX::Terminal(ferr!("invalid heads from facet-routine: {err}")))?;
@@ -76,7 +78,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
             WellKnownFacet::Embedding(daybook_types::doc::Embedding {
                 facet_ref: facet_ref.clone(),
                 ref_heads: daybook_types::doc::ChangeHashSet(Arc::clone(&heads)),
-                model_tag: embed_result.model_id.clone(),
+                model_tag: embed_result.backend.clone(),
                 vector: vector_bytes.clone(),
                 dim: embed_result.dimensions,
                 dtype: daybook_types::doc::EmbeddingDtype::F32,
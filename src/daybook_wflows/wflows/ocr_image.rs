@@ -6,6 +6,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
     use crate::wit::townframe::daybook::capabilities::FacetRights;
     use crate::wit::townframe::daybook::facet_routine;
     use crate::wit::townframe::daybook::mltools_ocr;
+    use crate::wit::townframe::mltools::routing::CallConstraints;
     use daybook_types::doc::{WellKnownFacet, WellKnownFacetTag};
 
     let args = facet_routine::get_args();
@@ -36,7 +37,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
     let blob_ocr_token = blob_facet_token.clone(None).map_err(|err| {
         JobErrorX::Terminal(ferr!("access error cloning blob facet token: {err:?}"))
     })?;
-    let ocr_result = mltools_ocr::ocr_image(blob_ocr_token)
+    let ocr_result = mltools_ocr::ocr_image(blob_ocr_token, CallConstraints { local_only: false })
         .map_err(|err| JobErrorX::Terminal(ferr!("error running OCR: {err}")))?;
 
     cx.effect(|| {
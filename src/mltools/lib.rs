@@ -112,10 +112,26 @@ pub enum EmbedBackendConfig {
         url: String,
         model: String,
         auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
     },
     CloudGemini {
         model: String,
         auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    /// Servers speaking the OpenAI API, like llama.cpp, vLLM or LM Studio.
+    OpenAiCompatible {
+        /// Including the version segment, e.g. `http://localhost:8080/v1`.
+        base_url: String,
+        model: String,
+        auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
     },
 }
 
@@ -142,10 +158,26 @@ pub enum LlmBackendConfig {
         url: String,
         model: String,
         auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
     },
     CloudGemini {
         model: String,
         auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
+    },
+    /// Servers speaking the OpenAI API, like llama.cpp, vLLM or LM Studio.
+    OpenAiCompatible {
+        /// Including the version segment, e.g. `http://localhost:8080/v1`.
+        base_url: String,
+        model: String,
+        auth: Option<CloudAuth>,
+        /// Limit for a single attempt, defaults to five minutes.
+        #[serde(default)]
+        timeout_secs: Option<u64>,
     },
 }
 
@@ -158,14 +190,24 @@ pub enum CloudAuth {
 
 pub struct Ctx {
     pub config: Config,
+    /// Outlives the ctx when shared, hosts rebuilding the ctx per call
+    /// should hold on to it.
+    pub health: Arc<BackendHealth>,
 }
 
 impl Ctx {
     pub async fn new(config: Config) -> Arc<Self> {
-        Self { config }.into()
+        Self {
+            config,
+            health: default(),
+        }
+        .into()
     }
 }
 
+use router::RoutedBackend;
+pub use router::{BackendHealth, CallConstraints};
+
 #[cfg(feature = "hf_hub")]
 pub mod models;
 
@@ -173,42 +215,79 @@ pub struct EmbedResult {
     pub vector: Vec<f32>,
     pub dimensions: u32,
     pub model_id: String,
+    /// Tag of the backend that served the call, e.g. `ollama/embeddinggemma`.
+    pub backend: String,
 }
 
 pub async fn embed_text(ctx: &Ctx, text: &str) -> Res<EmbedResult> {
+    embed_text_with(ctx, text, &default()).await
+}
+
+pub async fn embed_text_with(
+    ctx: &Ctx,
+    text: &str,
+    constraints: &CallConstraints,
+) -> Res<EmbedResult> {
     if text.trim().is_empty() {
         eyre::bail!("empty input text");
     }
 
-    let Some(backend_config) = ctx.config.embed.backends.first() else {
-        eyre::bail!("no embed backend configured");
-    };
-    match backend_config {
-        EmbedBackendConfig::LocalFastembed { .. } => local::embed_text(backend_config, text).await,
-        EmbedBackendConfig::CloudOllama { .. } | EmbedBackendConfig::CloudGemini { .. } => {
-            cloud::embed_text(backend_config, text).await
-        }
-    }
+    router::route(
+        &ctx.health,
+        "embed",
+        &ctx.config.embed.backends,
+        constraints,
+        |backend_config| async move {
+            match backend_config {
+                EmbedBackendConfig::LocalFastembed { .. } => {
+                    local::embed_text(backend_config, text).await
+                }
+                EmbedBackendConfig::CloudOllama { .. }
+                | EmbedBackendConfig::CloudGemini { .. }
+                | EmbedBackendConfig::OpenAiCompatible { .. } => {
+                    cloud::embed_text(backend_config, text).await
+                }
+            }
+        },
+    )
+    .await
 }
 
 pub async fn embed_image(ctx: &Ctx, image: &Path, mime: &str) -> Res<EmbedResult> {
+    embed_image_with(ctx, image, mime, &default()).await
+}
+
+pub async fn embed_image_with(
+    ctx: &Ctx,
+    image: &Path,
+    mime: &str,
+    constraints: &CallConstraints,
+) -> Res<EmbedResult> {
     if mime.trim().is_empty() {
         eyre::bail!("empty image mime");
     }
-    let Some(backend_config) = ctx.config.image_embed.backends.first() else {
-        eyre::bail!("no image embed backend configured");
-    };
 
-    match backend_config {
-        ImageEmbedBackendConfig::LocalFastembed { .. } => {
-            local::embed_image(backend_config, image, mime).await
-        }
-    }
+    router::route(
+        &ctx.health,
+        "image embed",
+        &ctx.config.image_embed.backends,
+        constraints,
+        |backend_config| async move {
+            match backend_config {
+                ImageEmbedBackendConfig::LocalFastembed { .. } => {
+                    local::embed_image(backend_config, image, mime).await
+                }
+            }
+        },
+    )
+    .await
 }
 
 pub struct OcrResult {
     pub text: String,
     pub regions: Vec<TextRegion>,
+    /// Tag of the backend that served the call.
+    pub backend: String,
 }
 pub struct TextRegion {
     /// x,y pairs
@@ -218,30 +297,59 @@ pub struct TextRegion {
 }
 
 pub async fn ocr_image(ctx: &Ctx, images: &[PathBuf]) -> Res<Vec<OcrResult>> {
-    let Some(backend_config) = ctx.config.ocr.backends.first() else {
-        eyre::bail!("no ocr backend configured");
-    };
-    local::ocr_image(backend_config, images).await
+    ocr_image_with(ctx, images, &default()).await
+}
+
+pub async fn ocr_image_with(
+    ctx: &Ctx,
+    images: &[PathBuf],
+    constraints: &CallConstraints,
+) -> Res<Vec<OcrResult>> {
+    router::route(
+        &ctx.health,
+        "ocr",
+        &ctx.config.ocr.backends,
+        constraints,
+        |backend_config| local::ocr_image(backend_config, images),
+    )
+    .await
 }
 
 pub struct LlmChatResult {
     pub text: String,
+    /// Tag of the backend that served the call, e.g. `gemini/gemini-flash-latest`.
+    pub backend: String,
 }
 
 pub async fn llm_chat(ctx: &Ctx, text: &str) -> Res<LlmChatResult> {
+    llm_chat_with(ctx, text, &default()).await
+}
+
+pub async fn llm_chat_with(
+    ctx: &Ctx,
+    text: &str,
+    constraints: &CallConstraints,
+) -> Res<LlmChatResult> {
     if text.trim().is_empty() {
         eyre::bail!("empty llm input text");
     }
 
-    let Some(backend_config) = ctx.config.llm.backends.first() else {
-        eyre::bail!("no llm backend configured");
-    };
-
-    match backend_config {
-        LlmBackendConfig::CloudOllama { .. } | LlmBackendConfig::CloudGemini { .. } => {
-            cloud::llm_chat(backend_config, text).await
-        }
-    }
+    router::route(
+        &ctx.health,
+        "llm",
+        &ctx.config.llm.backends,
+        constraints,
+        |backend_config| async move {
+            match backend_config {
+                LlmBackendConfig::CloudOllama { .. }
+                | LlmBackendConfig::CloudGemini { .. }
+                | LlmBackendConfig::OpenAiCompatible { .. } => {
+                    cloud::llm_chat(backend_config, text).await
+                }
+            }
+        },
+    )
+    .await
 }
 
 pub async fn llm_chat_multimodal(
@@ -249,6 +357,16 @@ pub async fn llm_chat_multimodal(
     prompt: &str,
     image_bytes: &[u8],
     image_mime: &str,
+) -> Res<LlmChatResult> {
+    llm_chat_multimodal_with(ctx, prompt, image_bytes, image_mime, &default()).await
+}
+
+pub async fn llm_chat_multimodal_with(
+    ctx: &Ctx,
+    prompt: &str,
+    image_bytes: &[u8],
+    image_mime: &str,
+    constraints: &CallConstraints,
 ) -> Res<LlmChatResult> {
     if prompt.trim().is_empty() {
         eyre::bail!("empty llm input prompt");
@@ -260,11 +378,16 @@ pub async fn llm_chat_multimodal(
         eyre::bail!("empty llm input image mime");
     }
 
-    let Some(backend_config) = ctx.config.llm.backends.first() else {
-        eyre::bail!("no llm backend configured");
-    };
-
-    cloud::llm_chat_multimodal(backend_config, prompt, image_bytes, image_mime).await
+    router::route(
+        &ctx.health,
+        "llm",
+        &ctx.config.llm.backends,
+        constraints,
+        |backend_config| {
+            cloud::llm_chat_multimodal(backend_config, prompt, image_bytes, image_mime)
+        },
+    )
+    .await
 }
 
 /// local execution of ML tools.
@@ -327,7 +450,9 @@ mod local {
                 tokenizer_config_path.clone(),
                 model_id.clone(),
             ),
-            EmbedBackendConfig::CloudOllama { .. } | EmbedBackendConfig::CloudGemini { .. } => {
+            EmbedBackendConfig::CloudOllama { .. }
+            | EmbedBackendConfig::CloudGemini { .. }
+            | EmbedBackendConfig::OpenAiCompatible { .. } => {
                 eyre::bail!("cloud backend is not supported in local::embed_text")
             }
         };
@@ -345,6 +470,7 @@ mod local {
         }
 
         let input_text = text.to_string();
+        let backend = backend_config.tag();
         tokio::task::spawn_blocking(move || -> Res<EmbedResult> {
             use fastembed::{
                 InitOptionsUserDefined, Pooling, QuantizationMode, TextEmbedding, TokenizerFiles,
@@ -393,6 +519,7 @@ mod local {
                 vector,
                 dimensions,
                 model_id,
+                backend,
             })
         })
         .await
@@ -437,6 +564,7 @@ mod local {
         use oar_ocr::utils::load_image;
 
         let image_paths = images.to_vec();
+        let backend = backend_config.tag();
 
         tokio::task::spawn_blocking(move || -> Res<Vec<OcrResult>> {
             let mut builder = OAROCRBuilder::new(
@@ -486,7 +614,11 @@ mod local {
                     .collect::<Vec<_>>()
                     .join("\n");
 
-                results.push(OcrResult { text, regions });
+                results.push(OcrResult {
+                    text,
+                    regions,
+                    backend: backend.clone(),
+                });
             }
 
             Ok(results)
@@ -537,6 +669,7 @@ mod local {
             }
         }
 
+        let backend = backend_config.tag();
         tokio::task::spawn_blocking(move || -> Res<EmbedResult> {
             use fastembed::{
                 ImageEmbedding, ImageInitOptionsUserDefined, UserDefinedImageEmbeddingModel,
@@ -602,6 +735,7 @@ mod local {
                 vector,
                 dimensions,
                 model_id,
+                backend,
             })
         })
         .await
@@ -613,9 +747,9 @@ mod local {
 mod cloud {
     use super::*;
 
-    fn normalize_genai_service_url(url: &str) -> Res<String> {
-        let mut parsed = url::Url::parse(url)
-            .wrap_err_with(|| format!("invalid service url for genai client: {url}"))?;
+    fn normalize_service_url(url: &str) -> Res<String> {
+        let mut parsed =
+            url::Url::parse(url).wrap_err_with(|| format!("invalid service url: {url}"))?;
         let path = parsed.path();
         if path.is_empty() || path == "/" {
             parsed.set_path("/");
@@ -633,7 +767,7 @@ mod cloud {
         let mut builder = genai::Client::builder();
 
         if let Some(service_url) = service_url {
-            let service_url = normalize_genai_service_url(service_url)?;
+            let service_url = normalize_service_url(service_url)?;
             let target_resolver = genai::resolver::ServiceTargetResolver::from_resolver_fn(
                 move |mut service_target: genai::ServiceTarget| {
                     service_target.endpoint =
@@ -706,9 +840,23 @@ mod cloud {
             EmbedBackendConfig::CloudOllama { model, auth, .. } => {
                 (model, auth, genai::adapter::AdapterKind::Ollama)
             }
-            EmbedBackendConfig::CloudGemini { model, auth } => {
+            EmbedBackendConfig::CloudGemini { model, auth, .. } => {
                 (model, auth, genai::adapter::AdapterKind::Gemini)
             }
+            EmbedBackendConfig::OpenAiCompatible {
+                base_url,
+                model,
+                auth,
+                ..
+            } => {
+                let vector = openai_embed_text(base_url, model, auth.as_ref(), text).await?;
+                return Ok(EmbedResult {
+                    dimensions: vector.len() as u32,
+                    vector,
+                    model_id: model.to_string(),
+                    backend: backend_config.tag(),
+                });
+            }
             _ => eyre::bail!("unsupported cloud embed backend"),
         };
 
@@ -751,6 +899,7 @@ mod cloud {
             dimensions: embedding.dimensions as u32,
             vector: embedding.vector,
             model_id: model.to_string(),
+            backend: backend_config.tag(),
         })
     }
 
@@ -759,9 +908,21 @@ mod cloud {
             LlmBackendConfig::CloudOllama { model, auth, .. } => {
                 (model, auth, genai::adapter::AdapterKind::Ollama)
             }
-            LlmBackendConfig::CloudGemini { model, auth } => {
+            LlmBackendConfig::CloudGemini { model, auth, .. } => {
                 (model, auth, genai::adapter::AdapterKind::Gemini)
             }
+            LlmBackendConfig::OpenAiCompatible {
+                base_url,
+                model,
+                auth,
+                ..
+            } => {
+                let text = openai_chat(base_url, model, auth.as_ref(), json!(text)).await?;
+                return Ok(LlmChatResult {
+                    text,
+                    backend: backend_config.tag(),
+                });
+            }
         };
 
         let mut chat_options = genai::chat::ChatOptions::default();
@@ -782,7 +943,8 @@ mod cloud {
             provider,
             match backend_config {
                 LlmBackendConfig::CloudOllama { url, .. } => Some(url.as_str()),
-                LlmBackendConfig::CloudGemini { .. } => None,
+                LlmBackendConfig::CloudGemini { .. }
+                | LlmBackendConfig::OpenAiCompatible { .. } => None,
             },
             auth.as_ref(),
         )?;
@@ -798,6 +960,7 @@ mod cloud {
 
         Ok(LlmChatResult {
             text: response.first_text().unwrap_or_default().to_string(),
+            backend: backend_config.tag(),
         })
     }
 
@@ -808,8 +971,10 @@ mod cloud {
         image_mime: &str,
     ) -> Res<LlmChatResult> {
         match backend_config {
-            LlmBackendConfig::CloudOllama { url, model, auth } => {
-                llm_chat_ollama_multimodal(
+            LlmBackendConfig::CloudOllama {
+                url, model, auth, ..
+            } => {
+                let text = llm_chat_ollama_multimodal(
                     url,
                     model,
                     prompt,
@@ -817,9 +982,13 @@ mod cloud {
                     image_mime,
                     auth.as_ref(),
                 )
-                .await
+                .await?;
+                Ok(LlmChatResult {
+                    text,
+                    backend: backend_config.tag(),
+                })
             }
-            LlmBackendConfig::CloudGemini { model, auth } => {
+            LlmBackendConfig::CloudGemini { model, auth, .. } => {
                 if let Some(auth) = auth {
                     match auth {
                         CloudAuth::Basic { .. } => {
@@ -851,6 +1020,27 @@ mod cloud {
 
                 Ok(LlmChatResult {
                     text: response.first_text().unwrap_or_default().to_string(),
+                    backend: backend_config.tag(),
+                })
+            }
+            LlmBackendConfig::OpenAiCompatible {
+                base_url,
+                model,
+                auth,
+                ..
+            } => {
+                let image_b64 = data_encoding::BASE64.encode(image_bytes);
+                let content = json!([
+                    { "type": "text", "text": prompt },
+                    {
+                        "type": "image_url",
+                        "image_url": { "url": format!("data:{image_mime};base64,{image_b64}") },
+                    },
+                ]);
+                let text = openai_chat(base_url, model, auth.as_ref(), content).await?;
+                Ok(LlmChatResult {
+                    text,
+                    backend: backend_config.tag(),
                 })
             }
         }
@@ -863,7 +1053,7 @@ mod cloud {
         image_bytes: &[u8],
         _image_mime: &str,
         auth: Option<&CloudAuth>,
-    ) -> Res<LlmChatResult> {
+    ) -> Res<String> {
         #[derive(serde::Deserialize)]
         struct ChatMessage {
             content: String,
@@ -898,9 +1088,7 @@ mod cloud {
             .json(&payload)
             .send()
             .await
-            .map_err(|error| {
-                eyre::eyre!("ollama multimodal request failed for model '{model}': {error:?}")
-            })?;
+            .wrap_err_with(|| format!("ollama multimodal request failed for model '{model}'"))?;
         let status = response.status();
         if !status.is_success() {
             let body = response
@@ -913,12 +1101,121 @@ mod cloud {
             .json()
             .await
             .wrap_err("error parsing ollama multimodal response")?;
-        let text = body
-            .message
+        body.message
             .map(|message| message.content)
             .or(body.response)
-            .ok_or_eyre("ollama multimodal response missing message content")?;
-        Ok(LlmChatResult { text })
+            .ok_or_eyre("ollama multimodal response missing message content")
+    }
+
+    fn openai_http_client(auth: Option<&CloudAuth>) -> Res<reqwest::Client> {
+        let mut headers = reqwest::header::HeaderMap::new();
+        if let Some(auth) = auth {
+            let token = match auth {
+                CloudAuth::ApiKey { key } => format!("Bearer {key}"),
+                CloudAuth::Basic { username, password } => {
+                    let mut token = "Basic ".to_string();
+                    data_encoding::BASE64
+                        .encode_append(format!("{username}:{password}").as_bytes(), &mut token);
+                    token
+                }
+            };
+            let mut token: reqwest::header::HeaderValue =
+                token.parse().wrap_err("error formatting Auth header")?;
+            token.set_sensitive(true);
+            headers.insert(reqwest::header::AUTHORIZATION, token);
+        }
+        reqwest::Client::builder()
+            .default_headers(headers)
+            .build()
+            .map_err(Into::into)
+    }
+
+    async fn openai_post<T: serde::de::DeserializeOwned>(
+        base_url: &str,
+        path: &str,
+        auth: Option<&CloudAuth>,
+        payload: &serde_json::Value,
+    ) -> Res<T> {
+        let endpoint = url::Url::parse(&normalize_service_url(base_url)?)?
+            .join(path)
+            .wrap_err_with(|| format!("error joining OpenAI-compatible {path} endpoint"))?;
+        let response = openai_http_client(auth)?
+            .post(endpoint)
+            .json(payload)
+            .send()
+            .await
+            .wrap_err_with(|| format!("OpenAI-compatible {path} request failed"))?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response
+                .text()
+                .await
+                .unwrap_or_else(|_| "<failed to read body>".to_string());
+            eyre::bail!("OpenAI-compatible {path} error {status}: {body}");
+        }
+        response
+            .json()
+            .await
+            .wrap_err_with(|| format!("error parsing OpenAI-compatible {path} response"))
+    }
+
+    /// `content` is either a string or an array of content parts.
+    async fn openai_chat(
+        base_url: &str,
+        model: &str,
+        auth: Option<&CloudAuth>,
+        content: serde_json::Value,
+    ) -> Res<String> {
+        #[derive(serde::Deserialize)]
+        struct ChatMessage {
+            content: Option<String>,
+        }
+        #[derive(serde::Deserialize)]
+        struct ChatChoice {
+            message: ChatMessage,
+        }
+        #[derive(serde::Deserialize)]
+        struct ChatResponse {
+            choices: Vec<ChatChoice>,
+        }
+
+        let payload = json!({
+            "model": model,
+            "stream": false,
+            "messages": [{ "role": "user", "content": content }],
+        });
+        let body: ChatResponse = openai_post(base_url, "chat/completions", auth, &payload).await?;
+        let choice = body
+            .choices
+            .into_iter()
+            .next()
+            .ok_or_eyre("OpenAI-compatible chat response has no choices")?;
+        Ok(choice.message.content.unwrap_or_default())
+    }
+
+    async fn openai_embed_text(
+        base_url: &str,
+        model: &str,
+        auth: Option<&CloudAuth>,
+        text: &str,
+    ) -> Res<Vec<f32>> {
+        #[derive(serde::Deserialize)]
+        struct Embedding {
+            embedding: Vec<f32>,
+        }
+        #[derive(serde::Deserialize)]
+        struct EmbeddingsResponse {
+            data: Vec<Embedding>,
+        }
+
+        let payload = json!({ "model": model, "input": text });
+        let body: EmbeddingsResponse = openai_post(base_url, "embeddings", auth, &payload).await?;
+        let embedding = body
+            .data
+            .into_iter()
+            .next()
+            .ok_or_eyre("no embeddings returned")?;
+        Ok(embedding.embedding)
     }
 }
 
@@ -927,7 +1224,293 @@ mod gateway {}
 /// mltools_local but for servers.
 mod server {}
 /// routes to mltools_local, mltools_client or mltools_cloud depending on config.
-mod router {}
+mod router {
+    use super::*;
+
+    use std::future::Future;
+    use std::time::Instant;
+
+    /// How long a backend that turned out unreachable or out of quota is skipped.
+    pub const UNHEALTHY_COOLDOWN: Duration = Duration::from_secs(60);
+    /// For cloud backends without a `timeout_secs`.
+    const DEFAULT_CLOUD_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+    /// Matched against errors that only reach us as text, mostly the ones
+    /// genai flattens into its own.
+    const FALL_THROUGH_NEEDLES: &[&str] = &[
+        "error sending request",
+        "connection refused",
+        "connection reset",
+        "dns error",
+        "timed out",
+        "too many requests",
+        "resource_exhausted",
+        "quota",
+        "rate limit",
+        "bad gateway",
+        "service unavailable",
+        "gateway timeout",
+        "status: 429",
+        "status: 502",
+        "status: 503",
+        "status: 504",
+    ];
+
+    /// Restrictions on which backends a single call may use.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct CallConstraints {
+        /// Keep the input on this device, for privacy sensitive callers.
+        pub local_only: bool,
+    }
+
+    /// Backends that recently failed to answer, shared across calls.
+    #[derive(Debug, Default)]
+    pub struct BackendHealth {
+        unhealthy_until: DHashMap<String, Instant>,
+    }
+
+    impl BackendHealth {
+        pub fn is_healthy(&self, health_key: &str) -> bool {
+            let Some(until) = self.unhealthy_until.get(health_key).map(|until| *until) else {
+                return true;
+            };
+            if until > Instant::now() {
+                return false;
+            }
+            self.unhealthy_until.remove(health_key);
+            true
+        }
+
+        pub fn mark_unhealthy(&self, health_key: &str) {
+            self.unhealthy_until
+                .insert(health_key.to_string(), Instant::now() + UNHEALTHY_COOLDOWN);
+        }
+
+        pub fn mark_healthy(&self, health_key: &str) {
+            self.unhealthy_until.remove(health_key);
+        }
+    }
+
+    pub trait RoutedBackend {
+        /// Reported back as the backend that served a call.
+        fn tag(&self) -> String;
+        /// Tells apart backends with the same tag, say one model on two servers.
+        fn health_key(&self) -> String {
+            self.tag()
+        }
+        fn is_local(&self) -> bool;
+        /// `None` for local backends, their blocking work can't be
+        /// cancelled anyways.
+        fn timeout(&self) -> Option<Duration>;
+    }
+
+    fn cloud_timeout(timeout_secs: &Option<u64>) -> Option<Duration> {
+        Some(timeout_secs.map_or(DEFAULT_CLOUD_TIMEOUT, Duration::from_secs))
+    }
+
+    impl RoutedBackend for OcrBackendConfig {
+        fn tag(&self) -> String {
+            match self {
+                OcrBackendConfig::LocalOnnx {
+                    text_recognition_onnx_path,
+                    ..
+                } => format!(
+                    "oar-ocr/{}",
+                    text_recognition_onnx_path
+                        .file_stem()
+                        .unwrap_or_default()
+                        .to_string_lossy()
+                ),
+            }
+        }
+
+        fn health_key(&self) -> String {
+            match self {
+                OcrBackendConfig::LocalOnnx {
+                    text_recognition_onnx_path,
+                    ..
+                } => format!("oar-ocr@{}", text_recognition_onnx_path.display()),
+            }
+        }
+
+        fn is_local(&self) -> bool {
+            true
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    impl RoutedBackend for EmbedBackendConfig {
+        fn tag(&self) -> String {
+            match self {
+                EmbedBackendConfig::LocalFastembed { model_id, .. } => {
+                    format!("fastembed/{model_id}")
+                }
+                EmbedBackendConfig::CloudOllama { model, .. } => format!("ollama/{model}"),
+                EmbedBackendConfig::CloudGemini { model, .. } => format!("gemini/{model}"),
+                EmbedBackendConfig::OpenAiCompatible { model, .. } => format!("openai/{model}"),
+            }
+        }
+
+        fn health_key(&self) -> String {
+            match self {
+                EmbedBackendConfig::CloudOllama { url, model, .. } => {
+                    format!("ollama/{model}@{url}")
+                }
+                EmbedBackendConfig::OpenAiCompatible {
+                    base_url, model, ..
+                } => format!("openai/{model}@{base_url}"),
+                _ => self.tag(),
+            }
+        }
+
+        fn is_local(&self) -> bool {
+            matches!(self, EmbedBackendConfig::LocalFastembed { .. })
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            match self {
+                EmbedBackendConfig::LocalFastembed { .. } => None,
+                EmbedBackendConfig::CloudOllama { timeout_secs, .. }
+                | EmbedBackendConfig::CloudGemini { timeout_secs, .. }
+                | EmbedBackendConfig::OpenAiCompatible { timeout_secs, .. } => {
+                    cloud_timeout(timeout_secs)
+                }
+            }
+        }
+    }
+
+    impl RoutedBackend for ImageEmbedBackendConfig {
+        fn tag(&self) -> String {
+            match self {
+                ImageEmbedBackendConfig::LocalFastembed { model_id, .. } => {
+                    format!("fastembed/{model_id}")
+                }
+            }
+        }
+
+        fn is_local(&self) -> bool {
+            true
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            None
+        }
+    }
+
+    impl RoutedBackend for LlmBackendConfig {
+        fn tag(&self) -> String {
+            match self {
+                LlmBackendConfig::CloudOllama { model, .. } => format!("ollama/{model}"),
+                LlmBackendConfig::CloudGemini { model, .. } => format!("gemini/{model}"),
+                LlmBackendConfig::OpenAiCompatible { model, .. } => format!("openai/{model}"),
+            }
+        }
+
+        fn health_key(&self) -> String {
+            match self {
+                LlmBackendConfig::CloudOllama { url, model, .. } => {
+                    format!("ollama/{model}@{url}")
+                }
+                LlmBackendConfig::OpenAiCompatible {
+                    base_url, model, ..
+                } => format!("openai/{model}@{base_url}"),
+                LlmBackendConfig::CloudGemini { .. } => self.tag(),
+            }
+        }
+
+        fn is_local(&self) -> bool {
+            false
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            match self {
+                LlmBackendConfig::CloudOllama { timeout_secs, .. }
+                | LlmBackendConfig::CloudGemini { timeout_secs, .. }
+                | LlmBackendConfig::OpenAiCompatible { timeout_secs, .. } => {
+                    cloud_timeout(timeout_secs)
+                }
+            }
+        }
+    }
+
+    /// Whether the next backend deserves a go. Only for failures of the
+    /// backend itself, anything else would likely fail the same way there.
+    pub fn should_fall_through(err: &eyre::Report) -> bool {
+        for cause in err.chain() {
+            let Some(err) = cause.downcast_ref::<reqwest::Error>() else {
+                continue;
+            };
+            if err.is_connect() || err.is_timeout() {
+                return true;
+            }
+            if let Some(status) = err.status() {
+                return status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                    || status.is_server_error();
+            }
+        }
+        let text = format!("{err:#}").to_ascii_lowercase();
+        FALL_THROUGH_NEEDLES
+            .iter()
+            .any(|needle| text.contains(needle))
+    }
+
+    /// Tries the backends in order, skipping the ones ruled out by the
+    /// constraints or cooling down, until one serves the call.
+    pub async fn route<'a, B, T, F, Fut>(
+        health: &BackendHealth,
+        kind: &str,
+        backends: &'a [B],
+        constraints: &CallConstraints,
+        mut call: F,
+    ) -> Res<T>
+    where
+        B: RoutedBackend,
+        F: FnMut(&'a B) -> Fut,
+        Fut: Future<Output = Res<T>>,
+    {
+        if backends.is_empty() {
+            eyre::bail!("no {kind} backend configured");
+        }
+        let mut skipped = Vec::new();
+        for backend in backends {
+            let tag = backend.tag();
+            if constraints.local_only && !backend.is_local() {
+                skipped.push(format!("{tag}: not local"));
+                continue;
+            }
+            let health_key = backend.health_key();
+            if !health.is_healthy(&health_key) {
+                skipped.push(format!("{tag}: cooling down"));
+                continue;
+            }
+            let res = match backend.timeout() {
+                Some(timeout) => tokio::time::timeout(timeout, call(backend))
+                    .await
+                    .unwrap_or_else(|_| Err(ferr!("timed out after {timeout:?}"))),
+                None => call(backend).await,
+            };
+            match res {
+                Ok(value) => {
+                    health.mark_healthy(&health_key);
+                    return Ok(value);
+                }
+                Err(err) if should_fall_through(&err) => {
+                    warn!(%tag, ?err, "{kind} backend unavailable, trying the next one");
+                    health.mark_unhealthy(&health_key);
+                    skipped.push(format!("{tag}: {err:#}"));
+                }
+                Err(err) => return Err(err),
+            }
+        }
+        eyre::bail!(
+            "no {kind} backend could serve the call: {}",
+            skipped.join("; ")
+        )
+    }
+}
 
 #[cfg(test)]
 mod tests {
@@ -970,9 +1553,60 @@ mod tests {
                     backends: llm_backends,
                 },
             },
+            health: default(),
+        }
+    }
+
+    struct FakeBackend {
+        tag: &'static str,
+        local: bool,
+        timeout: Option<Duration>,
+    }
+
+    impl FakeBackend {
+        fn cloud(tag: &'static str) -> Self {
+            Self {
+                tag,
+                local: false,
+                timeout: None,
+            }
+        }
+
+        fn local(tag: &'static str) -> Self {
+            Self {
+                tag,
+                local: true,
+                timeout: None,
+            }
         }
     }
 
+    impl RoutedBackend for FakeBackend {
+        fn tag(&self) -> String {
+            self.tag.to_string()
+        }
+
+        fn is_local(&self) -> bool {
+            self.local
+        }
+
+        fn timeout(&self) -> Option<Duration> {
+            self.timeout
+        }
+    }
+
+    fn serve_unless_down(
+        down: &'static [&'static str],
+        attempts: &std::sync::Mutex<Vec<&'static str>>,
+        backend: &FakeBackend,
+    ) -> Res<&'static str> {
+        attempts.lock().expect(ERROR_MUTEX).push(backend.tag);
+        if down.contains(&backend.tag) {
+            eyre::bail!("error sending request for url (http://{}/)", backend.tag);
+        }
+        Ok(backend.tag)
+    }
+
     fn expect_error_message_contains(result: Res<impl Sized>, expected_fragment: &str) {
         let error = match result {
             Ok(_) => panic!("expected error"),
@@ -1134,6 +1768,277 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn test_router_falls_through_and_cools_down() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let health = BackendHealth::default();
+            let backends = [FakeBackend::cloud("down"), FakeBackend::cloud("up")];
+            let attempts = std::sync::Mutex::new(vec![]);
+            let call =
+                |backend| std::future::ready(serve_unless_down(&["down"], &attempts, backend));
+
+            let served = router::route(&health, "test", &backends, &default(), call).await?;
+            assert_eq!(served, "up");
+            let served = router::route(&health, "test", &backends, &default(), call).await?;
+            assert_eq!(served, "up");
+            // the second call skipped the backend that's cooling down
+            assert_eq!(*attempts.lock().expect(ERROR_MUTEX), ["down", "up", "up"]);
+
+            health.mark_healthy("down");
+            let res = router::route(&health, "test", &backends[..1], &default(), call).await;
+            expect_error_message_contains(res, "no test backend could serve the call");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_router_honours_local_only() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let health = BackendHealth::default();
+            let constraints = CallConstraints { local_only: true };
+            let attempts = std::sync::Mutex::new(vec![]);
+            let call = |backend| std::future::ready(serve_unless_down(&[], &attempts, backend));
+
+            let backends = [FakeBackend::cloud("cloud"), FakeBackend::local("local")];
+            let served = router::route(&health, "test", &backends, &constraints, call).await?;
+            assert_eq!(served, "local");
+            assert_eq!(*attempts.lock().expect(ERROR_MUTEX), ["local"]);
+
+            let res = router::route(&health, "test", &backends[..1], &constraints, call).await;
+            expect_error_message_contains(res, "cloud: not local");
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_router_stops_on_other_errors() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let health = BackendHealth::default();
+            let backends = [FakeBackend::cloud("first"), FakeBackend::cloud("second")];
+            let res = router::route(&health, "test", &backends, &default(), |_| async {
+                Res::<()>::Err(ferr!("invalid argument"))
+            })
+            .await;
+            expect_error_message_contains(res, "invalid argument");
+            assert!(health.is_healthy("first"));
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_router_times_out_slow_backends() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let health = BackendHealth::default();
+            let backends = [
+                FakeBackend {
+                    timeout: Some(Duration::from_millis(10)),
+                    ..FakeBackend::cloud("slow")
+                },
+                FakeBackend::cloud("fast"),
+            ];
+            let served = router::route(
+                &health,
+                "test",
+                &backends,
+                &default(),
+                |backend| async move {
+                    if backend.tag == "slow" {
+                        tokio::time::sleep(Duration::from_secs(60)).await;
+                    }
+                    Ok(backend.tag)
+                },
+            )
+            .await?;
+            assert_eq!(served, "fast");
+            assert!(!health.is_healthy("slow"));
+            Ok(())
+        })
+    }
+
+    #[derive(Debug, Clone)]
+    struct MockRequest {
+        path: &'static str,
+        authorization: Option<String>,
+        body: serde_json::Value,
+    }
+
+    type MockRequests = Arc<std::sync::Mutex<Vec<MockRequest>>>;
+
+    /// Answers like an OpenAI-compatible server under `/v1` and with 429s
+    /// under `/limited/v1`. Returns the server's address.
+    async fn spawn_mock_openai_server(requests: MockRequests) -> Res<std::net::SocketAddr> {
+        use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Json};
+
+        fn record(
+            requests: &MockRequests,
+            path: &'static str,
+            headers: &HeaderMap,
+            body: serde_json::Value,
+        ) {
+            requests.lock().expect(ERROR_MUTEX).push(MockRequest {
+                path,
+                authorization: headers
+                    .get("authorization")
+                    .and_then(|value| value.to_str().ok())
+                    .map(str::to_string),
+                body,
+            });
+        }
+
+        async fn chat_completions(
+            State(requests): State<MockRequests>,
+            headers: HeaderMap,
+            Json(body): Json<serde_json::Value>,
+        ) -> Json<serde_json::Value> {
+            record(&requests, "chat/completions", &headers, body);
+            Json(json!({
+                "object": "chat.completion",
+                "choices": [{
+                    "index": 0,
+                    "message": { "role": "assistant", "content": "pong" },
+                    "finish_reason": "stop",
+                }],
+            }))
+        }
+
+        async fn embeddings(
+            State(requests): State<MockRequests>,
+            headers: HeaderMap,
+            Json(body): Json<serde_json::Value>,
+        ) -> Json<serde_json::Value> {
+            record(&requests, "embeddings", &headers, body);
+            Json(json!({
+                "object": "list",
+                "data": [{ "object": "embedding", "index": 0, "embedding": [0.5, 0.25, 0.25] }],
+            }))
+        }
+
+        async fn rate_limited() -> (StatusCode, &'static str) {
+            (StatusCode::TOO_MANY_REQUESTS, "slow down")
+        }
+
+        let app = axum::Router::new()
+            .route("/v1/chat/completions", post(chat_completions))
+            .route("/v1/embeddings", post(embeddings))
+            .route("/limited/v1/chat/completions", post(rate_limited))
+            .with_state(requests);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+        let addr = listener.local_addr()?;
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        Ok(addr)
+    }
+
+    #[test]
+    fn test_openai_compatible_mock_roundtrip() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let requests: MockRequests = default();
+            let addr = spawn_mock_openai_server(Arc::clone(&requests)).await?;
+            let base_url = format!("http://{addr}/v1");
+            let auth = Some(crate::CloudAuth::ApiKey {
+                key: "sk-test".to_string(),
+            });
+            let context = context_with(
+                vec![],
+                vec![EmbedBackendConfig::OpenAiCompatible {
+                    base_url: base_url.clone(),
+                    model: "mock-embed".to_string(),
+                    auth: auth.clone(),
+                    timeout_secs: None,
+                }],
+                vec![LlmBackendConfig::OpenAiCompatible {
+                    base_url,
+                    model: "mock-chat".to_string(),
+                    auth,
+                    timeout_secs: None,
+                }],
+            );
+
+            let embedded = embed_text(&context, "hello").await?;
+            assert_eq!(embedded.vector, [0.5, 0.25, 0.25]);
+            assert_eq!(embedded.dimensions, 3);
+            assert_eq!(embedded.model_id, "mock-embed");
+            assert_eq!(embedded.backend, "openai/mock-embed");
+
+            let chat = llm_chat(&context, "ping").await?;
+            assert_eq!(chat.text, "pong");
+            assert_eq!(chat.backend, "openai/mock-chat");
+
+            let chat = llm_chat_multimodal(&context, "describe", &[1, 2, 3], "image/png").await?;
+            assert_eq!(chat.text, "pong");
+
+            let requests = requests.lock().expect(ERROR_MUTEX).clone();
+            assert_eq!(
+                requests.iter().map(|req| req.path).collect::<Vec<_>>(),
+                ["embeddings", "chat/completions", "chat/completions"]
+            );
+            for req in &requests {
+                assert_eq!(req.authorization.as_deref(), Some("Bearer sk-test"));
+            }
+            assert_eq!(requests[0].body["model"], "mock-embed");
+            assert_eq!(requests[0].body["input"], "hello");
+            assert_eq!(requests[1].body["model"], "mock-chat");
+            assert_eq!(requests[1].body["messages"][0]["content"], "ping");
+            let parts = &requests[2].body["messages"][0]["content"];
+            assert_eq!(parts[0]["text"], "describe");
+            assert_eq!(parts[1]["image_url"]["url"], "data:image/png;base64,AQID");
+
+            Ok(())
+        })
+    }
+
+    #[test]
+    fn test_openai_compatible_rate_limit_falls_through() -> Res<()> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        runtime.block_on(async {
+            let addr = spawn_mock_openai_server(default()).await?;
+            let limited_url = format!("http://{addr}/limited/v1");
+            let context = context_with(
+                vec![],
+                vec![],
+                vec![
+                    LlmBackendConfig::OpenAiCompatible {
+                        base_url: limited_url.clone(),
+                        model: "limited".to_string(),
+                        auth: None,
+                        timeout_secs: None,
+                    },
+                    LlmBackendConfig::OpenAiCompatible {
+                        base_url: format!("http://{addr}/v1"),
+                        model: "mock-chat".to_string(),
+                        auth: None,
+                        timeout_secs: None,
+                    },
+                ],
+            );
+
+            let chat = llm_chat(&context, "ping").await?;
+            assert_eq!(chat.text, "pong");
+            assert_eq!(chat.backend, "openai/mock-chat");
+            assert!(!context
+                .health
+                .is_healthy(&format!("openai/limited@{limited_url}")));
+
+            Ok(())
+        })
+    }
+
     #[test]
     fn test_embed_text_cloud_router_roundtrip() -> Res<()> {
         let embed_model_name =
@@ -1151,6 +2056,7 @@ mod tests {
                         username: test_ollama_username(),
                         password: test_ollama_password(),
                     }),
+                    timeout_secs: None,
                 }],
                 vec![],
             );
@@ -1183,6 +2089,7 @@ mod tests {
                         username: test_ollama_username(),
                         password: test_ollama_password(),
                     }),
+                    timeout_secs: None,
                 }],
             );
 
@@ -1205,6 +2112,7 @@ mod tests {
                 vec![EmbedBackendConfig::CloudGemini {
                     model: "gemini-embedding-001".to_string(),
                     auth: Some(crate::CloudAuth::ApiKey { key }),
+                    timeout_secs: None,
                 }],
                 vec![],
             );
@@ -1232,6 +2140,7 @@ mod tests {
                 vec![LlmBackendConfig::CloudGemini {
                     model: "gemini-flash-latest".to_string(),
                     auth: Some(crate::CloudAuth::ApiKey { key }),
+                    timeout_secs: None,
                 }],
             );
 
@@ -1258,6 +2167,7 @@ mod tests {
                 vec![LlmBackendConfig::CloudGemini {
                     model,
                     auth: Some(crate::CloudAuth::ApiKey { key }),
+                    timeout_secs: None,
                 }],
             );
 
@@ -392,12 +392,14 @@ pub async fn mobile_default_with_observer(
                 username: OLLAMA_USERNAME.to_string(),
                 password: OLLAMA_PASSWORD.to_string(),
             }),
+            timeout_secs: None,
         },
         EmbedBackendConfig::CloudGemini {
             model: "gemini-embedding-001".to_string(),
             auth: gemini_api_key
                 .clone()
                 .map(|key| crate::CloudAuth::ApiKey { key }),
+            timeout_secs: None,
         },
     ];
 
@@ -409,12 +411,14 @@ pub async fn mobile_default_with_observer(
                 username: OLLAMA_USERNAME.to_string(),
                 password: OLLAMA_PASSWORD.to_string(),
             }),
+            timeout_secs: None,
         },
         LlmBackendConfig::CloudGemini {
             model: "gemini-flash-latest".to_string(),
             auth: gemini_api_key
                 .clone()
                 .map(|key| crate::CloudAuth::ApiKey { key }),
+            timeout_secs: None,
         },
     ];
 
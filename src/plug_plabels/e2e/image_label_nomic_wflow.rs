@@ -73,7 +73,10 @@ async fn test_image_label_fallback_nomic_pipeline() -> Res<()> {
     let WellKnownFacet::Embedding(embedding) = embedding_facet else {
         eyre::bail!("embedding facet had unexpected type");
     };
-    assert_eq!(embedding.model_tag, "nomic-ai/nomic-embed-vision-v1.5");
+    assert_eq!(
+        embedding.model_tag,
+        "fastembed/nomic-ai/nomic-embed-vision-v1.5"
+    );
     assert_eq!(embedding.dim, 768);
     assert_eq!(embedding.dtype, daybook_types::doc::EmbeddingDtype::F32);
     assert_eq!(embedding.compression, None);
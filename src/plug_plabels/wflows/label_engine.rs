@@ -161,6 +161,7 @@ pub fn default_label_set() -> PseudoLabelCandidatesFacet {
 
 pub fn apply_labeling(req: LabelRequest<'_>) -> Result<(), JobErrorX> {
     use crate::wit::townframe::daybook::mltools_embed;
+    use crate::wit::townframe::mltools::routing::CallConstraints;
     use crate::wit::townframe::sql::types::SqlValue;
 
     #[derive(Default)]
@@ -320,8 +321,11 @@ pub fn apply_labeling(req: LabelRequest<'_>) -> Result<(), JobErrorX> {
             std::collections::HashMap::new();
         for cache_row in cache_rows {
             let query_text = format!("search_query: {}", cache_row.description);
-            let embed_result = mltools_embed::embed_text(&query_text)
-                .map_err(|err| JobErrorX::Terminal(ferr!("error embedding seed text: {err}")))?;
+            let embed_result =
+                mltools_embed::embed_text(&query_text, CallConstraints { local_only: false })
+                    .map_err(|err| {
+                        JobErrorX::Terminal(ferr!("error embedding seed text: {err}"))
+                    })?;
             if !embed_result
                 .model_id
                 .eq_ignore_ascii_case(NOMIC_TEXT_MODEL_ID)
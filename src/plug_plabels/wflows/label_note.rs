@@ -87,10 +87,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), wflow_sdk::JobErrorX> {
     {
         return Ok(());
     }
-    if embedding.dim != 768
-        || !embedding
-            .model_tag
-            .eq_ignore_ascii_case(NOMIC_TEXT_MODEL_ID)
+    if embedding.dim != 768 || !super::model_tag_matches(&embedding.model_tag, NOMIC_TEXT_MODEL_ID)
     {
         return Ok(());
     }
@@ -85,9 +85,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
         return Ok(());
     }
     if embedding.dim != 768
-        || !embedding
-            .model_tag
-            .eq_ignore_ascii_case(NOMIC_VISION_MODEL_ID)
+        || !super::model_tag_matches(&embedding.model_tag, NOMIC_VISION_MODEL_ID)
     {
         return Ok(());
     }
@@ -126,6 +124,7 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
 
     cx.effect(|| {
         use crate::wit::townframe::daybook::{mltools_image_tools, mltools_llm_chat};
+        use crate::wit::townframe::mltools::routing::CallConstraints;
 
         let mut proposal_set = load_or_init_proposal_set(rw_config_token, ro_config_token)?;
         ensure_embedding_cache_schema(sqlite_connection)?;
@@ -143,8 +142,10 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
             &build_multimodal_prompt(),
             &downsized.bytes,
             &downsized.mime,
+            CallConstraints { local_only: false },
         )
-        .map_err(|err| JobErrorX::Terminal(ferr!("error calling multimodal llm: {err}")))?;
+        .map_err(|err| JobErrorX::Terminal(ferr!("error calling multimodal llm: {err}")))?
+        .text;
 
         let Some(parsed_proposal) = parse_llm_answer(&llm_text) else {
             return Ok(Json(()));
@@ -276,6 +277,7 @@ fn get_or_compute_text_embedding(
     query_text: &str,
 ) -> Result<Vec<f32>, JobErrorX> {
     use crate::wit::townframe::daybook::mltools_embed;
+    use crate::wit::townframe::mltools::routing::CallConstraints;
     use crate::wit::townframe::sql::types::SqlValue;
 
     let cache_rows = sqlite_connection
@@ -314,7 +316,7 @@ fn get_or_compute_text_embedding(
         }
     }
 
-    let embed_result = mltools_embed::embed_text(query_text)
+    let embed_result = mltools_embed::embed_text(query_text, CallConstraints { local_only: false })
         .map_err(|err| JobErrorX::Terminal(ferr!("error embedding prompt text: {err}")))?;
     if !embed_result
         .model_id
@@ -90,13 +90,18 @@ pub fn run(cx: &mut WflowCtx) -> Result<(), JobErrorX> {
 
     cx.effect(|| {
         use crate::wit::townframe::daybook::mltools_llm_chat;
+        use crate::wit::townframe::mltools::routing::CallConstraints;
 
         let mut proposal_set = load_or_init_proposal_set(rw_config_token, ro_config_token)?;
         ensure_embedding_cache_schema(sqlite_connection)?;
 
         let note_snippet = note_prompt_snippet(&note.content);
-        let llm_text = mltools_llm_chat::llm_chat(&build_note_prompt(&note_snippet))
-            .map_err(|err| JobErrorX::Terminal(ferr!("error calling note llm: {err}")))?;
+        let llm_text = mltools_llm_chat::llm_chat(
+            &build_note_prompt(&note_snippet),
+            CallConstraints { local_only: false },
+        )
+        .map_err(|err| JobErrorX::Terminal(ferr!("error calling note llm: {err}")))?
+        .text;
 
         let Some(parsed_proposal) = parse_llm_answer(&llm_text) else {
             return Ok(Json(()));
@@ -242,6 +247,7 @@ fn get_or_compute_text_embedding(
     query_text: &str,
 ) -> Result<Vec<f32>, JobErrorX> {
     use crate::wit::townframe::daybook::mltools_embed;
+    use crate::wit::townframe::mltools::routing::CallConstraints;
     use crate::wit::townframe::sql::types::SqlValue;
 
     let cache_rows = sqlite_connection
@@ -280,7 +286,7 @@ fn get_or_compute_text_embedding(
         }
     }
 
-    let embed_result = mltools_embed::embed_text(query_text)
+    let embed_result = mltools_embed::embed_text(query_text, CallConstraints { local_only: false })
         .map_err(|err| JobErrorX::Terminal(ferr!("error embedding prompt text: {err}")))?;
     if !embed_result
         .model_id
@@ -71,6 +71,16 @@ pub(crate) fn resolve_facet_write_target<'a>(
     )))
 }
 
+/// Embedding facets are tagged with the backend that made them, e.g.
+/// `fastembed/nomic-ai/nomic-embed-text-v1.5`. Older ones carry just the
+/// model id.
+pub(crate) fn model_tag_matches(model_tag: &str, model_id: &str) -> bool {
+    model_tag.eq_ignore_ascii_case(model_id)
+        || model_tag
+            .split_once('/')
+            .is_some_and(|(_, tag_model_id)| tag_model_id.eq_ignore_ascii_case(model_id))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -80,4 +90,18 @@ mod tests {
         assert_eq!(facet_key_id("org.example.note/custom"), "custom");
         assert_eq!(facet_key_id("org.example.note"), "main");
     }
+
+    #[test]
+    fn model_tag_matches_backend_tags_and_bare_model_ids() {
+        let model_id = "nomic-ai/nomic-embed-text-v1.5";
+        assert!(model_tag_matches(
+            "fastembed/nomic-ai/nomic-embed-text-v1.5",
+            model_id
+        ));
+        assert!(model_tag_matches(
+            "nomic-ai/nomic-embed-text-v1.5",
+            model_id
+        ));
+        assert!(!model_tag_matches("ollama/embeddinggemma", model_id));
+    }
 }
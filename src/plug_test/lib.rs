@@ -41,6 +41,7 @@ mod wit {
             "townframe:wflow/bundle": generate,
             "townframe:mltools/ocr": generate,
             "townframe:mltools/embed": generate,
+            "townframe:mltools/routing": generate,
             "townframe:sql/types": generate,
 
             "townframe:daybook-types/doc": generate,
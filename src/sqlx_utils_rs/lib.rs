@@ -26,16 +26,29 @@ impl SqlCtx {
     }
 
     pub async fn url(url: &str) -> Res<Self> {
+        Self::url_keyed(url, None).await
+    }
+
+    /// Like [`Self::url`] but keys every connection through SQLCipher's
+    /// `key` pragma. `key` is the pragma value, e.g. `"x'2DD2...'"` for a
+    /// raw key. Errors if a key is given but sqlite was built without
+    /// SQLCipher, the database would be written in the clear otherwise.
+    pub async fn url_keyed(url: &str, key: Option<&str>) -> Res<Self> {
         if is_memory_url(url) {
             return Self::memory().await;
         }
 
-        let connect_options = SqliteConnectOptions::from_str(url)
+        let mut connect_options = SqliteConnectOptions::from_str(url)
             .wrap_err_with(|| format!("failed parsing sqlite url: {url}"))?
             .create_if_missing(true)
             .journal_mode(SqliteJournalMode::Wal)
             //.busy_timeout(std::time::Duration::from_secs(90))
             .disable_statement_logging();
+        if let Some(key) = key {
+            ensure_sqlcipher().await?;
+            // sqlx issues the key pragma before any other
+            connect_options = connect_options.pragma("key", key.to_owned());
+        }
 
         let read_pool = SqlitePoolOptions::new()
             .max_connections(4)
@@ -55,6 +68,23 @@ impl SqlCtx {
     }
 }
 
+/// Sqlite ignores the `key` pragma if it wasn't built with SQLCipher,
+/// checked on a scratch connection so that nothing gets created in the
+/// clear.
+async fn ensure_sqlcipher() -> Res<()> {
+    let mut conn = SqliteConnectOptions::from_str("sqlite::memory:")?
+        .connect()
+        .await
+        .wrap_err("failed opening sqlite memory connection")?;
+    let cipher_version: Option<String> = sqlx::query_scalar("PRAGMA cipher_version")
+        .fetch_optional(&mut conn)
+        .await?;
+    if cipher_version.is_none() {
+        color_eyre::eyre::bail!("sqlite was built without SQLCipher, enable the sqlcipher feature");
+    }
+    Ok(())
+}
+
 fn is_memory_url(url: &str) -> bool {
     url.contains(":memory:")
 }
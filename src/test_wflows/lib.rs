@@ -49,6 +49,8 @@ impl wit::exports::townframe::wflow::bundle::Guest for Component {
             "recv_message" => |cx, args: RecvMessageArgs| recv_message(cx, args),
             "recv_message_then_effect" => |cx, args: RecvMessageThenEffectArgs| recv_message_then_effect(cx, args),
             "sleep_then_effect" => |cx, args: SleepThenEffectArgs| sleep_then_effect(cx, args),
+            "replay_divergence" => |cx, args: ReplayDivergenceArgs| replay_divergence(cx, args),
+            "compensated_reservation" => |cx, args: CompensatedReservationArgs| compensated_reservation(cx, args),
         })
     }
 }
@@ -190,3 +192,74 @@ fn sleep_then_effect(cx: &mut WflowCtx, args: SleepThenEffectArgs) -> Result<(),
     cx.effect(|| Ok(Json(serde_json::json!({"slept": true}))))?;
     Ok(())
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReplayDivergenceArgs {
+    key: String,
+}
+
+/// Picks its first step based on a keyvalue read made outside of any step,
+/// standing in for a routine that was edited while it had jobs in flight.
+fn replay_divergence(cx: &mut WflowCtx, args: ReplayDivergenceArgs) -> Result<(), JobErrorX> {
+    use api_utils_rs::wit::wasi::keyvalue::store;
+
+    let bucket = store::open("default")
+        .map_err(|err| JobErrorX::Terminal(ferr!("error opening bucket: {:?}", err)))?;
+    let diverge = bucket
+        .get(&args.key)
+        .map_err(|err| JobErrorX::Terminal(ferr!("error getting keyvalue: {:?}", err)))?
+        .is_some();
+    if diverge {
+        cx.sleep(Duration::from_millis(1))?;
+    } else {
+        cx.effect(|| Ok(Json(())))?;
+    }
+    Ok(())
+}
+
+#[derive(serde::Deserialize)]
+struct CompensatedReservationArgs {
+    key: String,
+    #[serde(default)]
+    fail: bool,
+    #[serde(default)]
+    wait_for_message: bool,
+}
+
+/// Reserves a keyvalue entry with an undo that releases it, then either
+/// fails terminally or waits for a message so it can be cancelled.
+fn compensated_reservation(
+    cx: &mut WflowCtx,
+    args: CompensatedReservationArgs,
+) -> Result<(), JobErrorX> {
+    use api_utils_rs::wit::wasi::keyvalue::store;
+
+    let key = args.key.clone();
+    cx.effect_with_compensation(
+        || {
+            let bucket = store::open("default")
+                .map_err(|err| JobErrorX::Terminal(ferr!("error opening bucket: {:?}", err)))?;
+            bucket
+                .set(&key, &[1])
+                .map_err(|err| JobErrorX::Terminal(ferr!("error setting keyvalue: {:?}", err)))?;
+            Ok(Json(key))
+        },
+        |key| {
+            let bucket = store::open("default")
+                .map_err(|err| JobErrorX::Terminal(ferr!("error opening bucket: {:?}", err)))?;
+            bucket
+                .delete(key)
+                .map_err(|err| JobErrorX::Terminal(ferr!("error deleting keyvalue: {:?}", err)))?;
+            Ok(())
+        },
+    )?;
+    if args.wait_for_message {
+        let Json(()) = cx.recv()?;
+    }
+    if args.fail {
+        cx.effect(|| -> Result<Json<()>, _> {
+            Err(JobErrorX::Terminal(ferr!("reservation rejected")))
+        })?;
+    }
+    Ok(())
+}
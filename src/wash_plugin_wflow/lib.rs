@@ -65,6 +65,22 @@ struct ActiveJobCtx {
     cur_step: AtomicU64,
     active_step: std::sync::Mutex<Option<ActiveStepCtx>>,
     journal: std::sync::Mutex<state::JobState>,
+    /// Only serve steps from the journal, see [`WflowPlugin::replay_job`]
+    replay_only: bool,
+}
+
+impl ActiveJobCtx {
+    /// Move the routine past the given step.
+    fn advance_step(&self, step_id: u64) {
+        self.cur_step
+            .compare_exchange(
+                step_id,
+                step_id + 1,
+                std::sync::atomic::Ordering::SeqCst,
+                std::sync::atomic::Ordering::Relaxed,
+            )
+            .expect("impossible: wasm is single threaded");
+    }
 }
 
 #[derive(Debug)]
@@ -72,6 +88,7 @@ struct ActiveStepCtx {
     attempt_id: u64,
     step_id: u64,
     start_at: Timestamp,
+    key: job_events::StepKey,
 }
 
 enum JobTrap {
@@ -81,12 +98,14 @@ enum JobTrap {
         end_at: Timestamp,
         value_json: Arc<str>,
         attempt_id: u64,
+        key: job_events::StepKey,
     },
     WaitStep {
         step_id: u64,
         attempt_id: u64,
         start_at: Timestamp,
         deets: WaitTrapDeets,
+        key: job_events::StepKey,
     },
     RunComplete(Result<String, types::JobError>),
 }
@@ -107,6 +126,32 @@ fn wasmtime_err(msg: impl std::fmt::Display) -> wasmtime::Error {
     wasmtime::Error::msg(msg.to_string())
 }
 
+fn step_key_from_wit(key: host::StepKey) -> job_events::StepKey {
+    job_events::StepKey {
+        kind: match key.kind {
+            host::StepKind::Effect => job_events::StepKind::Effect,
+            host::StepKind::CompensableEffect => job_events::StepKind::CompensableEffect,
+            host::StepKind::Compensate => job_events::StepKind::Compensate,
+            host::StepKind::Sleep => job_events::StepKind::Sleep,
+            host::StepKind::Recv => job_events::StepKind::Recv,
+        },
+        name: key.name.into(),
+    }
+}
+
+fn step_key_to_wit(key: &job_events::StepKey) -> host::StepKey {
+    host::StepKey {
+        kind: match key.kind {
+            job_events::StepKind::Effect => host::StepKind::Effect,
+            job_events::StepKind::CompensableEffect => host::StepKind::CompensableEffect,
+            job_events::StepKind::Compensate => host::StepKind::Compensate,
+            job_events::StepKind::Sleep => host::StepKind::Sleep,
+            job_events::StepKind::Recv => host::StepKind::Recv,
+        },
+        name: key.name.to_string(),
+    }
+}
+
 fn wait_id_for(step_id: u64, attempt_id: u64) -> u64 {
     let lo = step_id & 0xFFFF_FFFF;
     let hi = (attempt_id & 0xFFFF_FFFF) << 32;
@@ -147,7 +192,8 @@ impl host::Host for SharedWashCtx {
     async fn next_step(
         &mut self,
         job_id: partition_host::JobId,
-    ) -> wasmtime::Result<Result<host::StepState, String>> {
+        key: host::StepKey,
+    ) -> wasmtime::Result<Result<host::StepState, host::NextStepError>> {
         let plugin = WflowPlugin::from_ctx(self);
         let Some(job) = plugin
             .active_jobs
@@ -158,6 +204,7 @@ impl host::Host for SharedWashCtx {
         else {
             return Err(wasmtime_err("job not active"));
         };
+        let key = step_key_from_wit(key);
         let mut active_step = job.active_step.lock().expect(ERROR_MUTEX);
         if let Some(stale_active) = active_step.as_ref() {
             let stale_step_id = stale_active.step_id;
@@ -174,21 +221,30 @@ impl host::Host for SharedWashCtx {
             drop(journal);
             if let Some(value_json) = stale_completed {
                 active_step.take();
-                job.cur_step
-                    .compare_exchange(
-                        stale_step_id,
-                        stale_step_id + 1,
-                        std::sync::atomic::Ordering::SeqCst,
-                        std::sync::atomic::Ordering::Relaxed,
-                    )
-                    .expect("impossible: wasm is single threaded");
+                job.advance_step(stale_step_id);
                 let _ = value_json;
+            } else if key.kind == job_events::StepKind::Compensate {
+                // the routine failed mid-effect and is now unwinding,
+                // the abandoned step keeps its id and the compensation
+                // gets the next one
+                active_step.take();
+                job.advance_step(stale_step_id);
             } else {
                 return Err(wasmtime_err("concurrent steps not allowed"));
             }
         }
         let step_id = job.cur_step.load(std::sync::atomic::Ordering::Relaxed);
         let journal = job.journal.lock().expect(ERROR_MUTEX);
+        if let Err(err) = journal.check_step_key(step_id, &key) {
+            warn!(?job_id, %err, "routine diverged from journal");
+            return Ok(Err(host::NextStepError::NonDeterminism(
+                host::NonDeterminismError {
+                    step_id: err.step_id,
+                    recorded: step_key_to_wit(&err.recorded),
+                    requested: step_key_to_wit(&err.requested),
+                },
+            )));
+        }
         let attempt_id = if let Some(state) = journal.steps.get(step_id as usize) {
             use wflow_core::partition::job_events::JobEffectResultDeets;
             use wflow_core::partition::state::JobStepState;
@@ -197,14 +253,7 @@ impl host::Host for SharedWashCtx {
                     if let Some(attempt) = attempts.last() {
                         match &attempt.deets {
                             JobEffectResultDeets::Success { value_json } => {
-                                job.cur_step
-                                    .compare_exchange(
-                                        step_id,
-                                        step_id + 1,
-                                        std::sync::atomic::Ordering::SeqCst,
-                                        std::sync::atomic::Ordering::Relaxed,
-                                    )
-                                    .expect("impossible: wasm is single threaded");
+                                job.advance_step(step_id);
                                 return Ok(Ok(host::StepState::Completed(
                                     host::CompletedStepState {
                                         id: step_id,
@@ -222,12 +271,34 @@ impl host::Host for SharedWashCtx {
         } else {
             0
         };
+        let compensating = journal.compensating;
+        let abandoned = journal.is_abandoned_step(step_id);
         drop(journal);
-        let start_at = Timestamp::now();
+        // a compensating job only gets to run its compensations. The refused
+        // step keeps its id so that the compensations never land on a
+        // position the routine will ask for again on replay.
+        if key.kind != job_events::StepKind::Compensate && (compensating || abandoned) {
+            job.advance_step(step_id);
+            return Ok(Err(host::NextStepError::Cancelled));
+        }
+        if job.replay_only {
+            if abandoned {
+                // a compensation that failed before it was persisted
+                job.advance_step(step_id);
+                return Ok(Err(host::NextStepError::Other(format!(
+                    "step {step_id} was abandoned in the recorded run"
+                ))));
+            }
+            return Ok(Err(host::NextStepError::Other(format!(
+                "replay reached the end of the journal at step {step_id} requesting {key}"
+            ))));
+        }
+        let start_at = plugin.clock.now();
         active_step.replace(ActiveStepCtx {
             attempt_id: attempt_id as u64,
             step_id,
             start_at,
+            key,
         });
         Ok(Ok(host::StepState::Active(host::ActiveStepState {
             id: step_id,
@@ -258,24 +329,18 @@ impl host::Host for SharedWashCtx {
             if active_step.step_id != step_id {
                 return Err(wasmtime_err("given step_id is not active"));
             }
-            let end_at = Timestamp::now();
+            let end_at = plugin.clock.now();
             JobTrap::PersistStep {
                 step_id,
                 value_json: value_json.into(),
                 attempt_id: active_step.attempt_id,
                 start_at: active_step.start_at,
                 end_at,
+                key: active_step.key,
             }
         };
 
-        job.cur_step
-            .compare_exchange(
-                step_id,
-                step_id + 1,
-                std::sync::atomic::Ordering::SeqCst,
-                std::sync::atomic::Ordering::Relaxed,
-            )
-            .expect("impossible: wasm is single threaded");
+        job.advance_step(step_id);
 
         if job.yield_tx.send(trap).is_err() {
             return Err(wasmtime_err("session parent dropped"));
@@ -310,7 +375,7 @@ impl host::Host for SharedWashCtx {
         else {
             return Err(wasmtime_err("job not active"));
         };
-        let (attempt_id, start_at) = {
+        let (attempt_id, start_at, key) = {
             let active_step = job.active_step.lock().expect(ERROR_MUTEX);
             let Some(active_step) = active_step.as_ref() else {
                 return Err(wasmtime_err("step not active"));
@@ -318,7 +383,11 @@ impl host::Host for SharedWashCtx {
             if active_step.step_id != step_id {
                 return Err(wasmtime_err("given step_id is not active"));
             }
-            (active_step.attempt_id, active_step.start_at)
+            (
+                active_step.attempt_id,
+                active_step.start_at,
+                active_step.key.clone(),
+            )
         };
 
         let fire_at = start_at
@@ -332,6 +401,7 @@ impl host::Host for SharedWashCtx {
                 wait_id: wait_id_for(step_id, attempt_id),
                 fire_at,
             },
+            key,
         };
 
         if job.yield_tx.send(trap).is_err() {
@@ -366,7 +436,7 @@ impl host::Host for SharedWashCtx {
         else {
             return Err(wasmtime_err("job not active"));
         };
-        let (attempt_id, start_at) = {
+        let (attempt_id, start_at, key) = {
             let active_step = job.active_step.lock().expect(ERROR_MUTEX);
             let Some(active_step) = active_step.as_ref() else {
                 return Err(wasmtime_err("step not active"));
@@ -374,7 +444,11 @@ impl host::Host for SharedWashCtx {
             if active_step.step_id != step_id {
                 return Err(wasmtime_err("given step_id is not active"));
             }
-            (active_step.attempt_id, active_step.start_at)
+            (
+                active_step.attempt_id,
+                active_step.start_at,
+                active_step.key.clone(),
+            )
         };
 
         let trap = JobTrap::WaitStep {
@@ -384,6 +458,7 @@ impl host::Host for SharedWashCtx {
             deets: WaitTrapDeets::Message {
                 wait_id: wait_id_for(step_id, attempt_id),
             },
+            key,
         };
 
         if job.yield_tx.send(trap).is_err() {
@@ -485,6 +560,7 @@ pub struct WflowPlugin {
     // ctx id -> job id
     active_contexts: DHashMap<Arc<str>, Arc<str>>,
     metastore: Arc<dyn MetdataStore>,
+    clock: Arc<dyn wflow_tokio::clock::Clock>,
 }
 
 impl WflowPlugin {
@@ -496,9 +572,17 @@ impl WflowPlugin {
             active_jobs: default(),
             active_contexts: default(),
             metastore,
+            clock: Arc::new(wflow_tokio::clock::SystemClock),
         }
     }
 
+    /// Stamp steps and timers with the given clock, should match the
+    /// partition's.
+    pub fn with_clock(mut self, clock: Arc<dyn wflow_tokio::clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     const ID: &str = "townframe:wflow";
 
     pub fn try_from_ctx(wcx: &SharedWashCtx) -> Option<Arc<Self>> {
@@ -553,6 +637,7 @@ impl WflowPlugin {
                 start_at,
                 end_at,
                 attempt_id,
+                key,
             } => Ok(job_events::JobRunResult::StepEffect(
                 job_events::JobEffectResult {
                     step_id,
@@ -560,6 +645,7 @@ impl WflowPlugin {
                     start_at,
                     end_at,
                     deets: job_events::JobEffectResultDeets::Success { value_json },
+                    key: Some(key),
                 },
             )),
             JobTrap::WaitStep {
@@ -567,11 +653,13 @@ impl WflowPlugin {
                 attempt_id,
                 start_at,
                 deets,
+                key,
             } => Ok(job_events::JobRunResult::StepWait(
                 job_events::JobWaitResult {
                     step_id,
                     attempt_id,
                     start_at,
+                    key: Some(key),
                     deets: match deets {
                         WaitTrapDeets::Timer { wait_id, fire_at } => {
                             job_events::JobWaitResultDeets::Timer { wait_id, fire_at }
@@ -593,6 +681,7 @@ impl WflowPlugin {
         workload: &Arc<WflowWorkload>,
         job_id: Arc<str>,
         journal: state::JobState,
+        replay_only: bool,
     ) -> Result<SessionHandle, job_events::JobRunResult> {
         let mut store = workload
             .resolved_handle
@@ -628,6 +717,7 @@ impl WflowPlugin {
                 journal: std::sync::Mutex::new(journal),
                 cur_step: default(),
                 active_step: None.into(),
+                replay_only,
             }
             .into(),
         );
@@ -689,6 +779,58 @@ impl WflowPlugin {
         };
         Self::trap_to_result(trap)
     }
+
+    /// Re-run a recorded job purely from its journal.
+    ///
+    /// Every step must be served from the journal: a routine that asks for
+    /// a step past its end is stopped before running it. Used by test harnesses
+    /// to check that routines still produce the same step sequence.
+    pub async fn replay_job(
+        &self,
+        job_id: Arc<str>,
+        journal: state::JobState,
+        workload_id: &str,
+    ) -> Res<JobReplayReport> {
+        let Some(workload) = self
+            .active_workloads
+            .read()
+            .expect(ERROR_MUTEX)
+            .get(workload_id)
+            .cloned()
+        else {
+            eyre::bail!("workload not found: {workload_id}");
+        };
+        let mut session = self
+            .start_session(&workload, Arc::clone(&job_id), journal, true)
+            .await
+            .map_err(|result| ferr!("error starting replay session: {result:?}"))?;
+        let result = match self
+            .wait_for_session_yield(&mut session, &CancellationToken::new())
+            .await
+        {
+            Ok(val) | Err(val) => val,
+        };
+        let replayed_steps = self
+            .active_jobs
+            .read()
+            .expect(ERROR_MUTEX)
+            .get(&job_id)
+            .map(|job| job.cur_step.load(std::sync::atomic::Ordering::Relaxed))
+            .unwrap_or_default();
+        self.drop_session_handle(session);
+        Ok(JobReplayReport {
+            replayed_steps,
+            result,
+        })
+    }
+}
+
+/// Outcome of [`WflowPlugin::replay_job`].
+#[derive(Debug)]
+pub struct JobReplayReport {
+    /// Number of journal steps the routine consumed
+    pub replayed_steps: u64,
+    pub result: job_events::JobRunResult,
 }
 
 #[derive(educe::Educe)]
@@ -906,7 +1048,7 @@ impl service::WflowServiceHost for WflowPlugin {
             };
         };
         let start_session = |journal| async {
-            self.start_session(&workload, Arc::clone(&job_id), journal)
+            self.start_session(&workload, Arc::clone(&job_id), journal, false)
                 .await
         };
         let mut session = if let Some(mut session_box) = session.take() {
@@ -1,10 +1,35 @@
 use crate::interlude::*;
 
 use wflow_core::metastore;
-use wflow_core::partition::job_events::{JobCancelEvent, JobInitEvent, JobMessageEvent};
+use wflow_core::partition::job_events::{
+    JobCancelEvent, JobIdempotencyKey, JobInitEvent, JobMessageEvent,
+};
 use wflow_core::partition::log::PartitionLogEntry;
+use wflow_core::partition::state::JobStatus;
+use wflow_tokio::partition::state::PartitionWorkingState;
 use wflow_tokio::partition::PartitionLogRef;
 
+/// Dedupe options for [`WflowIngress::add_job`]
+#[derive(Debug, Clone)]
+pub struct JobIdempotency {
+    /// Identifies the logical work, independent of the job id
+    pub key: Arc<str>,
+    /// How long the key keeps resolving to the job that claimed it
+    pub ttl: Duration,
+}
+
+#[derive(Debug, Clone)]
+pub struct AddJobReply {
+    /// The job the submission resolved to
+    pub job_id: Arc<str>,
+    /// The appended JobInit entry, None if deduped before touching the log
+    pub entry_id: Option<u64>,
+    /// Whether an existing job had already claimed the idempotency key
+    pub deduped: bool,
+    /// None when the ingress can't observe the partition state
+    pub status: Option<JobStatus>,
+}
+
 /// Trait for scheduling workflow jobs
 ///
 /// Implementations can schedule workflows through different mechanisms:
@@ -19,13 +44,15 @@ pub trait WflowIngress: Send + Sync {
     /// * `wflow_key` - The workflow key to execute
     /// * `args_json` - JSON arguments for the workflow
     /// * `retry_policy` - Optional retry policy override
+    /// * `idempotency` - Optional key resolving resubmissions to the existing job
     async fn add_job(
         &self,
         job_id: Arc<str>,
         wflow_key: &str,
         args_json: String,
         retry_policy: Option<wflow_core::partition::RetryPolicy>,
-    ) -> Res<u64>;
+        idempotency: Option<JobIdempotency>,
+    ) -> Res<AddJobReply>;
 
     /// Request cancellation of a job. Appends JobCancel to partition log.
     async fn cancel_job(&self, job_id: Arc<str>, reason: String) -> Res<u64>;
@@ -43,11 +70,32 @@ pub trait WflowIngress: Send + Sync {
 pub struct PartitionLogIngress {
     log: PartitionLogRef,
     metastore: Arc<dyn metastore::MetdataStore>,
+    working_state: Option<Arc<PartitionWorkingState>>,
+    clock: Arc<dyn wflow_tokio::clock::Clock>,
 }
 
 impl PartitionLogIngress {
     pub fn new(log: PartitionLogRef, metastore: Arc<dyn metastore::MetdataStore>) -> Self {
-        Self { log, metastore }
+        Self {
+            log,
+            metastore,
+            working_state: None,
+            clock: Arc::new(wflow_tokio::clock::SystemClock),
+        }
+    }
+
+    /// Stamp submitted events and idempotency expiries from the
+    /// partition's clock.
+    pub fn with_clock(mut self, clock: Arc<dyn wflow_tokio::clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
+    /// Observe the partition's reducer state. Needed for idempotent
+    /// submissions to report which job they resolved to.
+    pub fn with_working_state(mut self, working_state: Arc<PartitionWorkingState>) -> Self {
+        self.working_state = Some(working_state);
+        self
     }
 }
 
@@ -59,7 +107,23 @@ impl WflowIngress for PartitionLogIngress {
         wflow_key: &str,
         args_json: String,
         retry_policy: Option<wflow_core::partition::RetryPolicy>,
-    ) -> Res<u64> {
+        idempotency: Option<JobIdempotency>,
+    ) -> Res<AddJobReply> {
+        let now = self.clock.now();
+
+        // Short-circuit resubmissions we can already see
+        if let (Some(idempotency), Some(working_state)) = (&idempotency, &self.working_state) {
+            let jobs = working_state.read_jobs().await;
+            if let Some(existing) = jobs.idempotent_job(&idempotency.key, now) {
+                return Ok(AddJobReply {
+                    job_id: Arc::clone(existing),
+                    entry_id: None,
+                    deduped: true,
+                    status: jobs.job_status(existing),
+                });
+            }
+        }
+
         // Get workflow metadata
         let wflow_meta = self
             .metastore
@@ -69,18 +133,44 @@ impl WflowIngress for PartitionLogIngress {
             .ok_or_eyre(format!("workflow not found: {wflow_key}"))?;
 
         // Append to partition log
+        let idempotency = idempotency.map(|idempotency| JobIdempotencyKey {
+            key: idempotency.key,
+            expires_at: now.checked_add(idempotency.ttl).unwrap_or(Timestamp::MAX),
+        });
         let mut log = self.log.clone();
         let entry_id = log
             .append(&PartitionLogEntry::JobInit(JobInitEvent {
                 args_json: args_json.into(),
                 override_wflow_retry_policy: retry_policy,
                 wflow: wflow_meta,
-                timestamp: Timestamp::now(),
-                job_id,
+                timestamp: now,
+                job_id: Arc::clone(&job_id),
+                idempotency: idempotency.clone(),
             }))
             .await?;
 
-        Ok(entry_id)
+        let (Some(idempotency), Some(working_state)) = (idempotency, &self.working_state) else {
+            return Ok(AddJobReply {
+                job_id,
+                entry_id: Some(entry_id),
+                deduped: false,
+                status: None,
+            });
+        };
+        // a concurrent submission might have claimed the key first,
+        // the reducer has the final word
+        working_state.wait_until_applied(entry_id).await?;
+        let jobs = working_state.read_jobs().await;
+        let resolved = jobs
+            .idempotent_job(&idempotency.key, now)
+            .cloned()
+            .unwrap_or_else(|| Arc::clone(&job_id));
+        Ok(AddJobReply {
+            entry_id: Some(entry_id),
+            deduped: resolved != job_id,
+            status: jobs.job_status(&resolved),
+            job_id: resolved,
+        })
     }
 
     async fn cancel_job(&self, job_id: Arc<str>, reason: String) -> Res<u64> {
@@ -88,7 +178,7 @@ impl WflowIngress for PartitionLogIngress {
         let entry_id = log
             .append(&PartitionLogEntry::JobCancel(JobCancelEvent {
                 job_id,
-                timestamp: Timestamp::now(),
+                timestamp: self.clock.now(),
                 reason: reason.into(),
             }))
             .await?;
@@ -106,7 +196,7 @@ impl WflowIngress for PartitionLogIngress {
             .append(&PartitionLogEntry::JobMessage(JobMessageEvent {
                 job_id,
                 message_id,
-                timestamp: Timestamp::now(),
+                timestamp: self.clock.now(),
                 payload_json: payload_json.into(),
             }))
             .await?;
@@ -5,6 +5,10 @@ use std::path::PathBuf;
 use tokio::sync::{mpsc, oneshot};
 use wflow_core::kvstore::*;
 
+mod redb;
+
+pub use self::redb::{RedbKvFactory, RedbKvStore};
+
 enum KvMsg {
     BootTable {
         table: &'static str,
@@ -55,10 +59,16 @@ pub struct SqliteKvFactory {
 
 impl SqliteKvFactory {
     pub async fn boot(db_path: Option<PathBuf>) -> Res<Self> {
+        Self::boot_keyed(db_path, None).await
+    }
+
+    /// Like [`Self::boot`] but the database is keyed through SQLCipher,
+    /// see [`SqlCtx::url_keyed`].
+    pub async fn boot_keyed(db_path: Option<PathBuf>, key: Option<&str>) -> Res<Self> {
         let sql = match db_path {
             Some(db_path) => {
                 let sqlite_url = format!("sqlite://{}", db_path.display());
-                SqlCtx::url(&sqlite_url).await?
+                SqlCtx::url_keyed(&sqlite_url, key).await?
             }
             None => SqlCtx::memory().await?,
         };
@@ -0,0 +1,391 @@
+use crate::interlude::*;
+
+use redb::ReadableTable;
+use std::path::PathBuf;
+use wflow_core::kvstore::*;
+
+/// Values are stored alongside a version counter that's bumped on
+/// every write. CAS guards compare against the version instead of
+/// the value to avoid ABA.
+///
+/// Deletes leave a tombstone holding the bumped version behind, a
+/// missing row would restart the count and let a stale guard through.
+type VersionedTable = redb::TableDefinition<'static, &'static [u8], (u64, Option<&'static [u8]>)>;
+
+#[derive(Clone)]
+pub struct RedbKvFactory {
+    db: Arc<redb::Database>,
+}
+
+impl RedbKvFactory {
+    /// Open (or create) the database at `db_path`. Uses an in-memory
+    /// backend when no path is provided.
+    pub async fn boot(db_path: Option<PathBuf>) -> Res<Self> {
+        let db = tokio::task::spawn_blocking(move || -> Res<redb::Database> {
+            let db = match db_path {
+                Some(db_path) => redb::Database::create(&db_path)
+                    .wrap_err_with(|| format!("error opening redb at {}", db_path.display()))?,
+                None => redb::Database::builder()
+                    .create_with_backend(redb::backends::InMemoryBackend::new())
+                    .wrap_err("error creating in-memory redb")?,
+            };
+            Ok(db)
+        })
+        .await
+        .wrap_err(ERROR_TOKIO)??;
+        Ok(Self { db: Arc::new(db) })
+    }
+
+    pub async fn open_store(&self, table_name: &'static str) -> Res<RedbKvStore> {
+        let table: VersionedTable = redb::TableDefinition::new(table_name);
+        let db = Arc::clone(&self.db);
+        tokio::task::spawn_blocking(move || -> Res<()> {
+            // opening the table in a write txn creates it if missing
+            let txn = db.begin_write()?;
+            txn.open_table(table)
+                .wrap_err_with(|| format!("failed to create table: {table_name}"))?;
+            txn.commit()?;
+            Ok(())
+        })
+        .await
+        .wrap_err(ERROR_TOKIO)??;
+        Ok(RedbKvStore {
+            table_name,
+            table,
+            db: Arc::clone(&self.db),
+        })
+    }
+}
+
+/// A redb-backed key-value store implementation.
+///
+/// Unlike [`super::SqliteKvStore`], there's no actor in the way. Reads
+/// run concurrently on MVCC snapshots and redb serializes the write
+/// transactions.
+#[derive(Clone)]
+pub struct RedbKvStore {
+    table_name: &'static str,
+    table: VersionedTable,
+    db: Arc<redb::Database>,
+}
+
+impl RedbKvStore {
+    async fn blocking<T, F>(&self, func: F) -> Res<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&redb::Database, VersionedTable) -> Res<T> + Send + 'static,
+    {
+        let db = Arc::clone(&self.db);
+        let table = self.table;
+        let table_name = self.table_name;
+        tokio::task::spawn_blocking(move || func(&db, table))
+            .await
+            .wrap_err(ERROR_TOKIO)?
+            .inspect_err(|err| error!(?err, ?table_name, "redb op failed"))
+    }
+
+    fn read_versioned(
+        db: &redb::Database,
+        table: VersionedTable,
+        key: &[u8],
+    ) -> Res<(Option<Arc<[u8]>>, u64)> {
+        let txn = db.begin_read()?;
+        let table = txn.open_table(table)?;
+        Ok(match table.get(key)? {
+            Some(guard) => {
+                let (version, value) = guard.value();
+                (value.map(Into::into), version)
+            }
+            None => (None, 0),
+        })
+    }
+
+    fn make_cas_guard(&self, key: Arc<[u8]>, value: Option<Arc<[u8]>>, version: u64) -> CasGuard {
+        let snapshot_value = value.clone();
+        let current_cb = move || snapshot_value.clone();
+
+        let store = self.clone();
+        let key_for_cb = Arc::clone(&key);
+        let swap_cb = move |new_value: Arc<[u8]>| -> futures::future::BoxFuture<'static, Res<Result<(), CasError>>> {
+            let store = store.clone();
+            let key = Arc::clone(&key_for_cb);
+            Box::pin(async move {
+                let res = store
+                    .blocking({
+                        let key = Arc::clone(&key);
+                        move |db, table| {
+                            let txn = db.begin_write()?;
+                            let res = {
+                                let mut table = txn.open_table(table)?;
+                                let (current_version, current_value) = table
+                                    .get(key.as_ref())?
+                                    .map(|guard| {
+                                        let (version, value) = guard.value();
+                                        (version, value.map(Arc::<[u8]>::from))
+                                    })
+                                    .unwrap_or((0, None));
+                                if current_version == version {
+                                    table.insert(
+                                        key.as_ref(),
+                                        (current_version + 1, Some(new_value.as_ref())),
+                                    )?;
+                                    Ok(())
+                                } else {
+                                    Err((current_value, current_version))
+                                }
+                            };
+                            if res.is_ok() {
+                                txn.commit()?;
+                            } else {
+                                txn.abort()?;
+                            }
+                            Ok(res)
+                        }
+                    })
+                    .await?;
+                match res {
+                    Ok(()) => Ok(Ok(())),
+                    Err((new_val, new_ver)) => {
+                        let new_guard = store.make_cas_guard(key, new_val, new_ver);
+                        Ok(Err(CasError::CasFailed(new_guard)))
+                    }
+                }
+            })
+        };
+
+        CasGuard::new(current_cb, swap_cb)
+    }
+}
+
+#[async_trait]
+impl KvStore for RedbKvStore {
+    async fn get(&self, key: &[u8]) -> Res<Option<Arc<[u8]>>> {
+        let key = key.to_vec();
+        self.blocking(move |db, table| {
+            let (value, _) = Self::read_versioned(db, table, &key)?;
+            Ok(value)
+        })
+        .await
+    }
+
+    async fn set(&self, key: Arc<[u8]>, value: Arc<[u8]>) -> Res<Option<Arc<[u8]>>> {
+        self.blocking(move |db, table| {
+            let txn = db.begin_write()?;
+            let old = {
+                let mut table = txn.open_table(table)?;
+                let (version, old) = table
+                    .get(key.as_ref())?
+                    .map(|guard| {
+                        let (version, value) = guard.value();
+                        (version, value.map(Arc::<[u8]>::from))
+                    })
+                    .unwrap_or((0, None));
+                table.insert(key.as_ref(), (version + 1, Some(value.as_ref())))?;
+                old
+            };
+            txn.commit()?;
+            Ok(old)
+        })
+        .await
+    }
+
+    async fn del(&self, key: &[u8]) -> Res<Option<Arc<[u8]>>> {
+        let key = key.to_vec();
+        self.blocking(move |db, table| {
+            let txn = db.begin_write()?;
+            let old = {
+                let mut table = txn.open_table(table)?;
+                let current = table.get(key.as_slice())?.and_then(|guard| {
+                    let (version, value) = guard.value();
+                    value.map(|value| (version, Arc::<[u8]>::from(value)))
+                });
+                if let Some((version, _)) = &current {
+                    table.insert(key.as_slice(), (version + 1, None))?;
+                }
+                current.map(|(_, value)| value)
+            };
+            txn.commit()?;
+            Ok(old)
+        })
+        .await
+    }
+
+    async fn increment(&self, key: &[u8], delta: i64) -> Res<i64> {
+        let key = key.to_vec();
+        self.blocking(move |db, table| {
+            let txn = db.begin_write()?;
+            let next = {
+                let mut table = txn.open_table(table)?;
+                let (current_version, current) = table
+                    .get(key.as_slice())?
+                    .map(|guard| {
+                        let (version, value) = guard.value();
+                        (version, value.map(<[u8]>::to_vec))
+                    })
+                    .unwrap_or((0, None));
+                let current_val = match current {
+                    Some(bytes) => {
+                        if bytes.len() != 8 {
+                            eyre::bail!(
+                                "value is not a i64: byte len {len} != 8",
+                                len = bytes.len()
+                            );
+                        }
+                        let mut buf = [0u8; 8];
+                        buf.copy_from_slice(&bytes);
+                        i64::from_le_bytes(buf)
+                    }
+                    None => 0,
+                };
+                let next = current_val
+                    .checked_add(delta)
+                    .ok_or_else(|| ferr!("i64 overflow in increment"))?;
+                table.insert(
+                    key.as_slice(),
+                    (current_version + 1, Some(&next.to_le_bytes()[..])),
+                )?;
+                next
+            };
+            txn.commit()?;
+            Ok(next)
+        })
+        .await
+    }
+
+    async fn new_cas(&self, key: &[u8]) -> Res<CasGuard> {
+        let key: Arc<[u8]> = key.into();
+        let (snapshot_value, snapshot_version) = self
+            .blocking({
+                let key = Arc::clone(&key);
+                move |db, table| Self::read_versioned(db, table, &key)
+            })
+            .await?;
+        Ok(self.make_cas_guard(key, snapshot_value, snapshot_version))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wflow_core::kvstore::tests::{test_kv_store_concurrency, test_kv_store_impl};
+
+    #[tokio::test]
+    async fn test_redb_kvstore() -> Res<()> {
+        let factory = RedbKvFactory::boot(None).await?;
+        let store = factory.open_store("test_kv").await?;
+        let store_dyn = Arc::new(store);
+        test_kv_store_impl(Arc::clone(&store_dyn) as _).await?;
+        test_kv_store_concurrency(store_dyn).await
+    }
+
+    #[tokio::test]
+    async fn test_redb_cas_after_del_and_set_is_stale() -> Res<()> {
+        let factory = RedbKvFactory::boot(None).await?;
+        let store = factory.open_store("test_kv").await?;
+        let key: Arc<[u8]> = b"aba".as_slice().into();
+        let value: Arc<[u8]> = b"one".as_slice().into();
+
+        store.set(Arc::clone(&key), Arc::clone(&value)).await?;
+        let stale = store.new_cas(&key).await?;
+        assert_eq!(store.del(&key).await?, Some(Arc::clone(&value)));
+        assert_eq!(store.get(&key).await?, None);
+        store.set(Arc::clone(&key), Arc::clone(&value)).await?;
+
+        let res = stale.swap(b"two".as_slice().into()).await?;
+        assert!(matches!(res, Err(CasError::CasFailed(_))));
+        assert_eq!(store.get(&key).await?, Some(value));
+
+        // the same goes for a guard taken while the key was missing
+        store.del(&key).await?;
+        let stale = store.new_cas(&key).await?;
+        store
+            .set(Arc::clone(&key), b"three".as_slice().into())
+            .await?;
+        store.del(&key).await?;
+        let res = stale.swap(b"four".as_slice().into()).await?;
+        assert!(matches!(res, Err(CasError::CasFailed(_))));
+        assert_eq!(store.del(&key).await?, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_redb_kvstore_on_disk() -> Res<()> {
+        let temp_dir = tempfile::tempdir()?;
+        let db_path = temp_dir.path().join("kv.redb");
+        {
+            let factory = RedbKvFactory::boot(Some(db_path.clone())).await?;
+            let store = factory.open_store("test_kv").await?;
+            let store_dyn = Arc::new(store);
+            test_kv_store_impl(Arc::clone(&store_dyn) as _).await?;
+            store_dyn
+                .set(b"persisted".as_slice().into(), b"yes".as_slice().into())
+                .await?;
+        }
+        // reopen and make sure writes survived
+        let factory = RedbKvFactory::boot(Some(db_path)).await?;
+        let store = factory.open_store("test_kv").await?;
+        assert_eq!(
+            store.get(b"persisted").await?,
+            Some(Arc::<[u8]>::from(b"yes".as_slice()))
+        );
+        Ok(())
+    }
+
+    /// Rough throughput comparison against [`super::super::SqliteKvStore`].
+    /// Prints numbers instead of asserting on them, akin to the
+    /// effect_chain_perf baselines.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_kvstore_backend_baseline() -> Res<()> {
+        utils_rs::testing::setup_tracing_once();
+
+        const OPS: usize = 2_000;
+
+        async fn run(store: Arc<dyn KvStore + Send + Sync>) -> Res<(u128, u128, u128)> {
+            let t0 = std::time::Instant::now();
+            for ii in 0..OPS {
+                store
+                    .set(
+                        format!("key-{ii}").into_bytes().into(),
+                        ii.to_le_bytes().as_slice().into(),
+                    )
+                    .await?;
+            }
+            let set_ms = t0.elapsed().as_millis();
+
+            let t0 = std::time::Instant::now();
+            for ii in 0..OPS {
+                store.get(format!("key-{ii}").as_bytes()).await?;
+            }
+            let get_ms = t0.elapsed().as_millis();
+
+            let t0 = std::time::Instant::now();
+            for _ in 0..OPS {
+                store.increment(b"counter", 1).await?;
+            }
+            let incr_ms = t0.elapsed().as_millis();
+            Ok((set_ms, get_ms, incr_ms))
+        }
+
+        let temp_dir = tempfile::tempdir()?;
+
+        let sqlite = crate::SqliteKvFactory::boot(Some(temp_dir.path().join("kv.sqlite")))
+            .await?
+            .open_store("bench_kv")
+            .await?;
+        let (set_ms, get_ms, incr_ms) = run(Arc::new(sqlite)).await?;
+        eprintln!(
+            "KVSTORE_BASELINE backend=sqlite ops={OPS} set_ms={set_ms} get_ms={get_ms} increment_ms={incr_ms}"
+        );
+
+        let redb = RedbKvFactory::boot(Some(temp_dir.path().join("kv.redb")))
+            .await?
+            .open_store("bench_kv")
+            .await?;
+        let (set_ms, get_ms, incr_ms) = run(Arc::new(redb)).await?;
+        eprintln!(
+            "KVSTORE_BASELINE backend=redb ops={OPS} set_ms={set_ms} get_ms={get_ms} increment_ms={incr_ms}"
+        );
+
+        Ok(())
+    }
+}
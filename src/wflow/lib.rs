@@ -11,8 +11,8 @@ pub use test::{InitialWorkload, WflowTestContext, WflowTestContextBuilder};
 pub mod ingress;
 pub mod kvstore;
 
-pub use ingress::{PartitionLogIngress, WflowIngress};
-pub use kvstore::{SqliteKvFactory, SqliteKvStore};
+pub use ingress::{AddJobReply, JobIdempotency, PartitionLogIngress, WflowIngress};
+pub use kvstore::{RedbKvFactory, RedbKvStore, SqliteKvFactory, SqliteKvStore};
 pub use wash_plugin_wflow;
 pub use wflow_core;
 pub use wflow_tokio;
@@ -25,25 +25,91 @@ use wflow_core::{gen::types::PartitionId, kvstore::KvStore};
 
 // pub struct Config {}
 
+/// Which [`KvStore`] implementation backs the metastore, logstore and snapstore.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KvBackend {
+    #[default]
+    Sqlite,
+    Redb,
+}
+
+#[derive(Clone)]
+pub enum KvFactory {
+    Sqlite(SqliteKvFactory),
+    Redb(RedbKvFactory),
+}
+
+impl KvFactory {
+    pub async fn boot(backend: KvBackend, db_path: Option<PathBuf>) -> Res<Self> {
+        Self::boot_keyed(backend, db_path, None).await
+    }
+
+    /// `key` encrypts the database through SQLCipher, only the sqlite
+    /// backend supports it.
+    pub async fn boot_keyed(
+        backend: KvBackend,
+        db_path: Option<PathBuf>,
+        key: Option<&str>,
+    ) -> Res<Self> {
+        Ok(match (backend, key) {
+            (KvBackend::Sqlite, key) => {
+                Self::Sqlite(SqliteKvFactory::boot_keyed(db_path, key).await?)
+            }
+            (KvBackend::Redb, None) => Self::Redb(RedbKvFactory::boot(db_path).await?),
+            (KvBackend::Redb, Some(_)) => eyre::bail!("the redb backend can't be encrypted"),
+        })
+    }
+
+    pub async fn open_store(
+        &self,
+        table_name: &'static str,
+    ) -> Res<Arc<dyn KvStore + Send + Sync>> {
+        Ok(match self {
+            Self::Sqlite(factory) => Arc::new(factory.open_store(table_name).await?),
+            Self::Redb(factory) => Arc::new(factory.open_store(table_name).await?),
+        })
+    }
+}
+
 #[derive(Clone)]
 pub struct Ctx {
     pub metastore: Arc<dyn wflow_core::metastore::MetdataStore>,
     pub logstore: Arc<dyn wflow_core::log::LogStore>,
     pub snapstore: Arc<dyn wflow_core::snapstore::SnapStore<Snapshot = Arc<[u8]>>>,
-    pub factory: Option<SqliteKvFactory>,
+    pub factory: Option<KvFactory>,
+    /// Time source for the partition workers
+    pub clock: Arc<dyn wflow_tokio::clock::Clock>,
 }
 
 impl Ctx {
     pub async fn init(db_path: Option<PathBuf>) -> Res<Self> {
-        let factory = SqliteKvFactory::boot(db_path).await?;
-        let metastore_kv = Arc::new(factory.open_store("wflow_metastore").await?);
-        let logstore_kv = Arc::new(factory.open_store("wflow_logstore").await?);
-        let snapstore_kv = Arc::new(factory.open_store("wflow_snapstore").await?);
+        Self::init_with_backend(db_path, KvBackend::default()).await
+    }
+
+    /// Like [`Self::init`] but with the database keyed through SQLCipher,
+    /// see [`sqlx_utils_rs::SqlCtx::url_keyed`].
+    pub async fn init_keyed(db_path: Option<PathBuf>, key: Option<&str>) -> Res<Self> {
+        Self::init_inner(db_path, KvBackend::default(), key).await
+    }
+
+    pub async fn init_with_backend(db_path: Option<PathBuf>, backend: KvBackend) -> Res<Self> {
+        Self::init_inner(db_path, backend, None).await
+    }
+
+    async fn init_inner(
+        db_path: Option<PathBuf>,
+        backend: KvBackend,
+        key: Option<&str>,
+    ) -> Res<Self> {
+        let factory = KvFactory::boot_keyed(backend, db_path, key).await?;
+        let metastore_kv = factory.open_store("wflow_metastore").await?;
+        let logstore_kv = factory.open_store("wflow_logstore").await?;
+        let snapstore_kv = factory.open_store("wflow_snapstore").await?;
 
         // Create the stores
         let metastore = Arc::new(
             wflow_core::kvstore::metastore::KvStoreMetadtaStore::new(
-                metastore_kv as Arc<dyn KvStore + Send + Sync>,
+                metastore_kv,
                 wflow_core::gen::metastore::PartitionsMeta {
                     version: "0".into(),
                     partition_count: 1,
@@ -52,8 +118,7 @@ impl Ctx {
             .await?,
         );
 
-        let logstore =
-            wflow_core::kvstore::log::KvStoreLog::new(Arc::clone(&logstore_kv) as _).await?;
+        let logstore = wflow_core::kvstore::log::KvStoreLog::new(logstore_kv).await?;
         let logstore = Arc::new(logstore);
         let snapstore = Arc::new(wflow_core::kvstore::snapstore::KvSnapStore::new(
             snapstore_kv,
@@ -63,6 +128,7 @@ impl Ctx {
             logstore,
             snapstore,
             factory: Some(factory),
+            clock: Arc::new(wflow_tokio::clock::SystemClock),
         })
     }
 }
@@ -117,7 +183,8 @@ pub async fn start_partition_worker(
         next_entry_id,
         wflow_plugin,
         Arc::new(wflow_tokio::local_native_host::LocalNativeHost {}),
-    );
+    )
+    .with_clock(Arc::clone(&wcx.clock));
 
     let last_applied_entry_id = next_entry_id.saturating_sub(1);
     let active_state = wflow_tokio::partition::state::PartitionWorkingState::new(
@@ -3,11 +3,15 @@ use crate::interlude::*;
 #[cfg(test)]
 mod cancel_job;
 #[cfg(test)]
+mod compensation;
+#[cfg(test)]
 mod effect_chain_perf;
 #[cfg(test)]
 mod fails_once;
 #[cfg(test)]
 mod fails_until_told;
+#[cfg(test)]
+mod idempotent_submit;
 #[cfg(any(test, feature = "test-harness"))]
 #[expect(unused)]
 mod keyvalue_plugin;
@@ -18,6 +22,8 @@ mod recv_message;
 #[cfg(test)]
 mod recv_message_then_effect;
 #[cfg(test)]
+mod replay_determinism;
+#[cfg(test)]
 mod sleep_then_effect;
 #[cfg(test)]
 mod sleep_then_succeed;
@@ -38,6 +44,8 @@ pub struct WflowTestContextBuilder {
     keyvalue_plugin: Option<Arc<keyvalue_plugin::WasiKeyvalue>>,
     initial_workloads: Vec<InitialWorkload>,
     plugins: Vec<Arc<dyn plugin::HostPlugin>>,
+    strict_replay: bool,
+    clock: Arc<dyn wflow_tokio::clock::Clock>,
 }
 
 impl Default for WflowTestContextBuilder {
@@ -57,6 +65,8 @@ impl WflowTestContextBuilder {
             keyvalue_plugin: None,
             initial_workloads: Vec::new(),
             plugins: Vec::new(),
+            strict_replay: false,
+            clock: Arc::new(wflow_tokio::clock::SystemClock),
         }
     }
 
@@ -101,6 +111,21 @@ impl WflowTestContextBuilder {
         self
     }
 
+    /// When set, [`WflowTestContext::stop`] re-runs every completed job from
+    /// its journal and fails if the routine doesn't reproduce the same
+    /// step sequence.
+    pub fn strict_replay(mut self, strict_replay: bool) -> Self {
+        self.strict_replay = strict_replay;
+        self
+    }
+
+    /// Run the partition and the wflow host on the given clock, e.g. a
+    /// [`wflow_tokio::clock::SimClock`] to control when timers fire.
+    pub fn with_clock(mut self, clock: Arc<dyn wflow_tokio::clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub async fn build(mut self) -> Res<WflowTestContext> {
         let temp_dir = self.temp_dir;
 
@@ -125,10 +150,10 @@ impl WflowTestContextBuilder {
         };
 
         let partition_log = wflow_tokio::partition::PartitionLogRef::new(Arc::clone(&logstore));
-        let ingress = Arc::new(crate::ingress::PartitionLogIngress::new(
-            partition_log.clone(),
-            Arc::clone(&metastore),
-        ));
+        let ingress = Arc::new(
+            crate::ingress::PartitionLogIngress::new(partition_log.clone(), Arc::clone(&metastore))
+                .with_clock(Arc::clone(&self.clock)),
+        );
 
         let snapstore = match self.snap_store {
             Some(store) => store,
@@ -139,7 +164,10 @@ impl WflowTestContextBuilder {
             .keyvalue_plugin
             .unwrap_or_else(|| Arc::new(keyvalue_plugin::WasiKeyvalue::new()));
 
-        let wflow_plugin = Arc::new(wash_plugin_wflow::WflowPlugin::new(Arc::clone(&metastore)));
+        let wflow_plugin = Arc::new(
+            wash_plugin_wflow::WflowPlugin::new(Arc::clone(&metastore))
+                .with_clock(Arc::clone(&self.clock)),
+        );
         let runtime_config_plugin = plugin::wasi_config::DynamicConfig::default();
 
         self.plugins.extend_from_slice(&[
@@ -163,6 +191,8 @@ impl WflowTestContextBuilder {
             wflow_plugin,
             worker_handle: None,
             working_state: None,
+            strict_replay: self.strict_replay,
+            clock: self.clock,
         })
     }
 }
@@ -181,6 +211,8 @@ pub struct WflowTestContext {
     wflow_plugin: Arc<wash_plugin_wflow::WflowPlugin>,
     worker_handle: Option<wflow_tokio::partition::TokioPartitionWorkerHandle>,
     working_state: Option<Arc<wflow_tokio::partition::state::PartitionWorkingState>>,
+    strict_replay: bool,
+    clock: Arc<dyn wflow_tokio::clock::Clock>,
 }
 
 /// Workload to register before starting the worker
@@ -207,6 +239,7 @@ impl WflowTestContext {
             logstore: Arc::clone(&self.logstore),
             snapstore: Arc::clone(&self.snapstore),
             factory: None,
+            clock: Arc::clone(&self.clock),
         };
 
         let host = self.pending_host.take().expect("bad builder");
@@ -244,6 +277,14 @@ impl WflowTestContext {
         let (worker_handle, working_state) =
             crate::start_partition_worker(&wcx, Arc::clone(&self.wflow_plugin), 0).await?;
 
+        self.ingress = Arc::new(
+            crate::ingress::PartitionLogIngress::new(
+                self.partition_log.clone(),
+                Arc::clone(&self.metastore),
+            )
+            .with_working_state(Arc::clone(&working_state))
+            .with_clock(Arc::clone(&self.clock)),
+        );
         self.worker_handle = Some(worker_handle);
         self.working_state = Some(working_state);
 
@@ -296,9 +337,27 @@ impl WflowTestContext {
         wflow_key: &str,
         args_json: String,
     ) -> Res<u64> {
+        use crate::WflowIngress;
+        let reply = self
+            .ingress
+            .add_job(job_id, wflow_key, args_json, None, None)
+            .await?;
+        Ok(reply
+            .entry_id
+            .expect("submissions without idempotency key always append"))
+    }
+
+    /// Schedule a workflow job deduped by the given idempotency key
+    pub async fn schedule_job_idempotent(
+        &self,
+        job_id: Arc<str>,
+        wflow_key: &str,
+        args_json: String,
+        idempotency: crate::JobIdempotency,
+    ) -> Res<crate::AddJobReply> {
         use crate::WflowIngress;
         self.ingress
-            .add_job(job_id, wflow_key, args_json, None)
+            .add_job(job_id, wflow_key, args_json, None, Some(idempotency))
             .await
     }
 
@@ -501,6 +560,7 @@ impl WflowTestContext {
                 (r"\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}(?:\.\d+)?Z", "[timestamp]"),
                 (r"\w*Location.*:\d+:\d+", "[location]"),
                 (r"tokio-fxw-p\d+-\d+", "[tokio-fxw]"),
+                (r"[\w/.-]+\.rs:\d+:\d+", "[step-location]"),
             ]
         }, {
             insta::assert_yaml_snapshot!(snapshot_name, log_snapshot);
@@ -519,8 +579,87 @@ impl WflowTestContext {
         Ok(())
     }
 
+    /// Get a value from the keyvalue store (for testing)
+    pub async fn get_keyvalue(&self, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        self.keyvalue_plugin
+            .get_value("workload_123", bucket, key)
+            .await
+    }
+
+    /// Re-run an archived job from its journal, returning the journal
+    /// along with the replay outcome.
+    pub async fn replay_archived_job(
+        &self,
+        job_id: &str,
+    ) -> Res<(
+        wflow_core::partition::state::JobState,
+        wash_plugin_wflow::JobReplayReport,
+    )> {
+        let journal = {
+            let jobs = self.working_state()?.read_jobs().await;
+            jobs.archive
+                .get(job_id)
+                .cloned()
+                .ok_or_else(|| ferr!("job not archived: {job_id}"))?
+        };
+        let metastore::WflowServiceMeta::Wasmcloud(meta) = &journal.wflow.service else {
+            eyre::bail!("job {job_id} is not a wasmcloud wflow");
+        };
+        let report = self
+            .wflow_plugin
+            .replay_job(job_id.into(), journal.clone(), &meta.workload_id)
+            .await?;
+        Ok((journal, report))
+    }
+
+    /// Re-run every successfully completed job from its journal and assert
+    /// the routine consumes the exact same steps to produce the same result.
+    pub async fn assert_replay_determinism(&self) -> Res<()> {
+        use wflow_core::partition::job_events::JobRunResult;
+
+        let archived = {
+            let jobs = self.working_state()?.read_jobs().await;
+            jobs.archive
+                .iter()
+                .map(|(job_id, job)| (Arc::clone(job_id), job.clone()))
+                .collect::<Vec<_>>()
+        };
+        let mut failures = vec![];
+        for (job_id, journal) in archived {
+            let Some(JobRunResult::Success { value_json }) =
+                journal.runs.last().map(|run| run.result.clone())
+            else {
+                continue;
+            };
+            let metastore::WflowServiceMeta::Wasmcloud(meta) = &journal.wflow.service else {
+                continue;
+            };
+            let recorded_steps = journal.steps.len() as u64;
+            let report = self
+                .wflow_plugin
+                .replay_job(Arc::clone(&job_id), journal.clone(), &meta.workload_id)
+                .await?;
+            match &report.result {
+                JobRunResult::Success {
+                    value_json: replay_json,
+                } if *replay_json == value_json && report.replayed_steps == recorded_steps => {}
+                result => failures.push(format!(
+                    "job {job_id}: replayed {replayed} of {recorded_steps} steps with result {result:?}",
+                    replayed = report.replayed_steps
+                )),
+            }
+        }
+        if !failures.is_empty() {
+            eyre::bail!("replay diverged from journal:\n{}", failures.join("\n"));
+        }
+        Ok(())
+    }
+
     /// Cleanup: shutdown all workers
     pub async fn stop(self) -> Res<()> {
+        if self.strict_replay && self.is_started() {
+            self.assert_replay_determinism().await?;
+        }
         if let Some(worker_handle) = self.worker_handle {
             worker_handle.stop().await?;
         }
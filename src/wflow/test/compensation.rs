@@ -0,0 +1,168 @@
+use crate::interlude::*;
+
+use crate::test::{test_wflows_wasm_path, InitialWorkload, WflowTestContext};
+
+/// Replaying a compensated job must walk past the step it gave up on and
+/// serve its compensations from the journal.
+async fn assert_compensation_replays(test_cx: &WflowTestContext, job_id: &str) -> Res<()> {
+    use wflow_core::partition::job_events::{JobError, JobRunResult, StepKind};
+
+    let (journal, report) = test_cx.replay_archived_job(job_id).await?;
+    assert!(journal.is_abandoned_step(1), "{journal:#?}");
+    assert_eq!(
+        journal.recorded_step_key(2).map(|key| key.kind),
+        Some(StepKind::Compensate)
+    );
+    assert_eq!(report.replayed_steps, journal.steps.len() as u64);
+    match &report.result {
+        JobRunResult::WflowErr(JobError::Terminal { error_json }) => {
+            assert!(!error_json.contains("non-determinism"), "{error_json}");
+        }
+        result => panic!("unexpected replay result: {result:?}"),
+    }
+    Ok(())
+}
+
+fn is_compensation_entry(
+    job_id: &str,
+    entry: &wflow_core::partition::log::PartitionLogEntry,
+) -> bool {
+    use wflow_core::partition::job_events::{JobEffectResultDeets, JobRunResult, StepKind};
+    use wflow_core::partition::log::PartitionLogEntry;
+
+    let PartitionLogEntry::JobEffectResult(event) = entry else {
+        return false;
+    };
+    if event.job_id.as_ref() != job_id {
+        return false;
+    }
+    let JobRunResult::StepEffect(effect) = &event.result else {
+        return false;
+    };
+    matches!(effect.deets, JobEffectResultDeets::Success { .. })
+        && effect
+            .key
+            .as_ref()
+            .is_some_and(|key| key.kind == StepKind::Compensate)
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compensation_on_terminal_failure() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["compensated_reservation".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let job_id = "test-compensation-failure-1";
+    let key = "compensation-failure-reservation";
+    test_cx
+        .schedule_job(
+            job_id.into(),
+            "compensated_reservation",
+            serde_json::to_string(&serde_json::json!({ "key": key, "fail": true }))?,
+        )
+        .await?;
+
+    test_cx
+        .wait_until_entry(0, 10, |_entry_id, entry| {
+            is_compensation_entry(job_id, entry)
+        })
+        .await?;
+    test_cx.wait_until_no_active_jobs(10).await?;
+
+    assert_eq!(test_cx.get_keyvalue("default", key).await, None);
+
+    // the routine unwound inline so the job keeps its original error
+    {
+        use wflow_core::partition::job_events::{JobError, JobRunResult};
+        use wflow_core::partition::state::JobStatus;
+
+        let jobs = test_cx.working_state()?.read_jobs().await;
+        assert_eq!(jobs.job_status(job_id), Some(JobStatus::Failed));
+        let job = &jobs.archive[job_id];
+        assert!(!job.compensating, "no extra compensation run expected");
+        match job.runs.last().map(|run| &run.result) {
+            Some(JobRunResult::WflowErr(JobError::Terminal { error_json })) => {
+                assert!(error_json.contains("reservation rejected"), "{error_json}");
+            }
+            result => panic!("unexpected final result: {result:?}"),
+        }
+    }
+    assert_compensation_replays(&test_cx, job_id).await?;
+
+    test_cx.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_compensation_on_cancel() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["compensated_reservation".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let job_id: Arc<str> = "test-compensation-cancel-1".into();
+    let key = "compensation-cancel-reservation";
+    test_cx
+        .schedule_job(
+            Arc::clone(&job_id),
+            "compensated_reservation",
+            serde_json::to_string(&serde_json::json!({
+                "key": key,
+                "wait_for_message": true
+            }))?,
+        )
+        .await?;
+
+    // wait for the job to park on its recv
+    test_cx
+        .wait_until_entry(0, 10, |_entry_id, entry| {
+            use wflow_core::partition::job_events::JobRunResult;
+            use wflow_core::partition::log::PartitionLogEntry;
+
+            let PartitionLogEntry::JobEffectResult(event) = entry else {
+                return false;
+            };
+            event.job_id == job_id && matches!(event.result, JobRunResult::StepWait(_))
+        })
+        .await?;
+    assert_eq!(test_cx.get_keyvalue("default", key).await, Some(vec![1]));
+
+    test_cx
+        .cancel_job(Arc::clone(&job_id), "test requested cancel".to_string())
+        .await?;
+
+    test_cx
+        .wait_until_entry(0, 10, |_entry_id, entry| {
+            is_compensation_entry(&job_id, entry)
+        })
+        .await?;
+    test_cx.wait_until_no_active_jobs(10).await?;
+
+    assert_eq!(test_cx.get_keyvalue("default", key).await, None);
+
+    {
+        use wflow_core::partition::state::JobStatus;
+
+        let jobs = test_cx.working_state()?.read_jobs().await;
+        assert_eq!(jobs.job_status(&job_id), Some(JobStatus::Cancelled));
+    }
+    assert_compensation_replays(&test_cx, &job_id).await?;
+
+    test_cx.stop().await?;
+    Ok(())
+}
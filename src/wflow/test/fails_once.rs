@@ -88,3 +88,42 @@ async fn test_fails_once_sqlite() -> Res<()> {
 
     Ok(())
 }
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_fails_once_redb() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let cx = crate::Ctx::init_with_backend(None, crate::KvBackend::Redb).await?;
+
+    let test_cx = WflowTestContext::builder()
+        .with_metastore(cx.metastore)
+        .with_logstore(cx.logstore)
+        .with_snapstore(cx.snapstore)
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    test_cx
+        .register_workload(&test_wflows_wasm_path()?, vec!["fails_once".to_string()])
+        .await?;
+
+    let job_id: Arc<str> = "test-fails-once-redb-1".into();
+    let args_json = serde_json::to_string(&serde_json::json!({
+        "key": "test-counter"
+    }))?;
+
+    test_cx
+        .schedule_job(Arc::clone(&job_id), "fails_once", args_json.clone())
+        .await?;
+
+    test_cx.wait_until_no_active_jobs(10).await?;
+
+    test_cx
+        .assert_partition_log_snapshot("fails_once_redb_partition_log")
+        .await?;
+
+    test_cx.stop().await?;
+
+    Ok(())
+}
@@ -0,0 +1,114 @@
+use crate::interlude::*;
+
+use crate::test::{test_wflows_wasm_path, InitialWorkload, WflowTestContext};
+use crate::JobIdempotency;
+use wflow_core::partition::state::JobStatus;
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_idempotent_submit_resolves_to_existing_job() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["effect_chain".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let args_json = serde_json::to_string(&serde_json::json!({ "steps": 2 }))?;
+    let idempotency = JobIdempotency {
+        key: "effect-chain-work-1".into(),
+        ttl: Duration::from_secs(60),
+    };
+
+    let first = test_cx
+        .schedule_job_idempotent(
+            "test-idempotent-1".into(),
+            "effect_chain",
+            args_json.clone(),
+            idempotency.clone(),
+        )
+        .await?;
+    assert_eq!(first.job_id.as_ref(), "test-idempotent-1");
+    assert!(!first.deduped);
+    assert!(first.entry_id.is_some());
+
+    let second = test_cx
+        .schedule_job_idempotent(
+            "test-idempotent-2".into(),
+            "effect_chain",
+            args_json.clone(),
+            idempotency.clone(),
+        )
+        .await?;
+    assert_eq!(second.job_id.as_ref(), "test-idempotent-1");
+    assert!(second.deduped);
+
+    test_cx.wait_until_no_active_jobs(10).await?;
+
+    let third = test_cx
+        .schedule_job_idempotent(
+            "test-idempotent-3".into(),
+            "effect_chain",
+            args_json,
+            idempotency,
+        )
+        .await?;
+    assert_eq!(third.job_id.as_ref(), "test-idempotent-1");
+    assert!(third.deduped);
+    assert_eq!(third.entry_id, None);
+    assert_eq!(third.status, Some(JobStatus::Succeeded));
+
+    test_cx.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_idempotent_submit_after_expiry_starts_new_job() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["effect_chain".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let args_json = serde_json::to_string(&serde_json::json!({ "steps": 1 }))?;
+    // a zero ttl expires the claim right away
+    let idempotency = JobIdempotency {
+        key: "effect-chain-expiring".into(),
+        ttl: Duration::ZERO,
+    };
+
+    let first = test_cx
+        .schedule_job_idempotent(
+            "test-idempotent-expiry-1".into(),
+            "effect_chain",
+            args_json.clone(),
+            idempotency.clone(),
+        )
+        .await?;
+    assert!(!first.deduped);
+
+    let second = test_cx
+        .schedule_job_idempotent(
+            "test-idempotent-expiry-2".into(),
+            "effect_chain",
+            args_json,
+            idempotency,
+        )
+        .await?;
+    assert_eq!(second.job_id.as_ref(), "test-idempotent-expiry-2");
+    assert!(!second.deduped);
+
+    test_cx.wait_until_no_active_jobs(10).await?;
+    test_cx.stop().await?;
+    Ok(())
+}
@@ -100,6 +100,17 @@ impl WasiKeyvalue {
             None => Err(anyhow::anyhow!("bucket '{}' does not exist", bucket)),
         }
     }
+
+    /// Read a value from the keyvalue store directly (for testing)
+    pub async fn get_value(&self, workload_id: &str, bucket: &str, key: &str) -> Option<Vec<u8>> {
+        let storage = self.storage.read().await;
+        storage
+            .get(workload_id)?
+            .get(bucket)?
+            .data
+            .get(key)
+            .cloned()
+    }
 }
 
 // Implementation for the store interface
@@ -0,0 +1,80 @@
+use crate::interlude::*;
+
+use crate::test::{test_wflows_wasm_path, InitialWorkload, WflowTestContext};
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_strict_replay_passes_for_deterministic_jobs() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["effect_chain".to_string(), "sleep_then_effect".to_string()],
+        }])
+        .strict_replay(true)
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    test_cx
+        .schedule_job(
+            "replay-effect-chain-1".into(),
+            "effect_chain",
+            serde_json::to_string(&serde_json::json!({ "steps": 4 }))?,
+        )
+        .await?;
+    test_cx
+        .schedule_job(
+            "replay-sleep-then-effect-1".into(),
+            "sleep_then_effect",
+            serde_json::to_string(&serde_json::json!({ "millis": 10_u64 }))?,
+        )
+        .await?;
+    test_cx.wait_until_no_active_jobs(10).await?;
+
+    // strict mode replays both jobs here
+    test_cx.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_replay_detects_step_divergence() -> Res<()> {
+    utils_rs::testing::setup_tracing_once();
+
+    let test_cx = WflowTestContext::builder()
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["replay_divergence".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let key = "replay-divergence-flag";
+    test_cx
+        .schedule_job(
+            "replay-divergence-1".into(),
+            "replay_divergence",
+            serde_json::to_string(&serde_json::json!({ "key": key }))?,
+        )
+        .await?;
+    test_cx.wait_until_no_active_jobs(10).await?;
+    test_cx.assert_replay_determinism().await?;
+
+    // flip the branch the routine takes, its journal still records an effect
+    test_cx.set_keyvalue("default", key, vec![1]).await?;
+    let err = test_cx
+        .assert_replay_determinism()
+        .await
+        .expect_err("replay should diverge");
+    let msg = format!("{err:?}");
+    assert!(
+        msg.contains("non-determinism detected at step 0"),
+        "unexpected error: {msg}"
+    );
+
+    test_cx.stop().await?;
+    Ok(())
+}
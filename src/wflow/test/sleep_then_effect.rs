@@ -52,3 +52,127 @@ async fn test_sleep_then_effect_succeeds() -> Res<()> {
     test_cx.stop().await?;
     Ok(())
 }
+
+fn is_sleep_wait(job_id: &str, entry: &wflow_core::partition::log::PartitionLogEntry) -> bool {
+    use wflow_core::partition::job_events::JobRunResult;
+    use wflow_core::partition::log::PartitionLogEntry;
+    matches!(
+        entry,
+        PartitionLogEntry::JobEffectResult(event)
+            if &*event.job_id == job_id && matches!(event.result, JobRunResult::StepWait(_))
+    )
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sleep_then_effect_fires_on_virtual_time() -> Res<()> {
+    use wflow_core::partition::state::JobStatus;
+    use wflow_tokio::clock::SimClock;
+
+    utils_rs::testing::setup_tracing_once();
+
+    let clock = Arc::new(SimClock::new(Timestamp::now()));
+    let test_cx = WflowTestContext::builder()
+        .with_clock(Arc::clone(&clock) as _)
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["sleep_then_effect".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let job_id: Arc<str> = "test-sleep-then-effect-virtual".into();
+    let args_json = serde_json::to_string(&serde_json::json!({
+        "millis": 60 * 60 * 1000_u64
+    }))?;
+    test_cx
+        .schedule_job(Arc::clone(&job_id), "sleep_then_effect", args_json)
+        .await?;
+    test_cx
+        .wait_until_entry(0, 10, |_entry_id, entry| is_sleep_wait(&job_id, entry))
+        .await?;
+
+    // real time passing doesn't fire the timer
+    tokio::time::sleep(Duration::from_millis(300)).await;
+    let status = test_cx
+        .working_state()?
+        .read_jobs()
+        .await
+        .job_status(&job_id);
+    assert_eq!(status, Some(JobStatus::Active));
+
+    clock.advance(Duration::from_secs(60 * 60));
+    test_cx.wait_until_no_active_jobs(10).await?;
+    let status = test_cx
+        .working_state()?
+        .read_jobs()
+        .await
+        .job_status(&job_id);
+    assert_eq!(status, Some(JobStatus::Succeeded));
+
+    test_cx.stop().await?;
+    Ok(())
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_sleep_then_effect_timer_survives_restart() -> Res<()> {
+    use wflow_core::partition::state::JobStatus;
+    use wflow_tokio::clock::SimClock;
+
+    utils_rs::testing::setup_tracing_once();
+
+    let start_at = Timestamp::now();
+    let test_cx = WflowTestContext::builder()
+        .with_clock(Arc::new(SimClock::new(start_at)))
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["sleep_then_effect".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    let job_id: Arc<str> = "test-sleep-then-effect-restart".into();
+    let args_json = serde_json::to_string(&serde_json::json!({
+        "millis": 60 * 60 * 1000_u64
+    }))?;
+    test_cx
+        .schedule_job(Arc::clone(&job_id), "sleep_then_effect", args_json)
+        .await?;
+    test_cx
+        .wait_until_entry(0, 10, |_entry_id, entry| is_sleep_wait(&job_id, entry))
+        .await?;
+
+    // the partition goes down with the timer pending and comes back after
+    // it was due
+    let logstore = Arc::clone(&test_cx.logstore);
+    test_cx.stop().await?;
+
+    let clock = Arc::new(SimClock::new(
+        start_at.checked_add(Duration::from_secs(2 * 60 * 60))?,
+    ));
+    let test_cx = WflowTestContext::builder()
+        .with_clock(clock)
+        .with_logstore(logstore)
+        .initial_workloads(vec![InitialWorkload {
+            wasm_path: test_wflows_wasm_path()?,
+            wflow_keys: vec!["sleep_then_effect".to_string()],
+        }])
+        .build()
+        .await?
+        .start()
+        .await?;
+
+    test_cx.wait_until_no_active_jobs(10).await?;
+    let status = test_cx
+        .working_state()?
+        .read_jobs()
+        .await
+        .job_status(&job_id);
+    assert_eq!(status, Some(JobStatus::Succeeded));
+
+    test_cx.stop().await?;
+    Ok(())
+}
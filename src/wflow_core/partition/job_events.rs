@@ -10,6 +10,16 @@ pub struct JobInitEvent {
     pub args_json: Arc<str>,
     pub override_wflow_retry_policy: Option<RetryPolicy>,
     pub wflow: WflowMeta,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency: Option<JobIdempotencyKey>,
+}
+
+/// Identifies the logical work a job was submitted for. Resubmitting
+/// under a key that hasn't expired resolves to the job that claimed it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobIdempotencyKey {
+    pub key: Arc<str>,
+    pub expires_at: Timestamp,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -78,6 +88,34 @@ pub enum JobRunWorkerError {
     Other { msg: String },
 }
 
+/// Stable identity of a step.
+///
+/// Recorded alongside every step result so that replays can detect
+/// when a routine no longer requests the same sequence of steps.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct StepKey {
+    pub kind: StepKind,
+    /// Caller provided name or the source location of the step call
+    pub name: Arc<str>,
+}
+
+impl std::fmt::Display for StepKey {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(fmt, "{kind:?}({name})", kind = self.kind, name = self.name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StepKind {
+    Effect,
+    /// An effect that registered an undo step
+    CompensableEffect,
+    /// An undo step ran after the job failed terminally or was cancelled
+    Compensate,
+    Sleep,
+    Recv,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct JobEffectResult {
     pub step_id: u64,
@@ -85,6 +123,9 @@ pub struct JobEffectResult {
     pub start_at: Timestamp,
     pub end_at: Timestamp,
     pub deets: JobEffectResultDeets,
+    /// None for entries written before step keys were recorded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<StepKey>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -99,6 +140,8 @@ pub struct JobWaitResult {
     pub attempt_id: u64,
     pub start_at: Timestamp,
     pub deets: JobWaitResultDeets,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key: Option<StepKey>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
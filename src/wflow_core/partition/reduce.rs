@@ -13,6 +13,23 @@ pub fn reduce_job_init_event(
         info!("duplicate job id, skipping");
         return;
     }
+    if let Some(idempotency) = &event.idempotency {
+        // expiry is judged against the event time to keep replays stable
+        if let Some(job_id) = state.idempotent_job(&idempotency.key, event.timestamp) {
+            info!(existing_job_id = ?job_id, "idempotency key already claimed, skipping");
+            return;
+        }
+        state
+            .idempotency_keys
+            .retain(|_, record| record.expires_at > event.timestamp);
+        state.idempotency_keys.insert(
+            Arc::clone(&idempotency.key),
+            state::IdempotencyRecord {
+                job_id: Arc::clone(&event.job_id),
+                expires_at: idempotency.expires_at,
+            },
+        );
+    }
 
     state.active.insert(
         Arc::clone(&event.job_id),
@@ -21,6 +38,7 @@ pub fn reduce_job_init_event(
             override_wflow_retry_policy: event.override_wflow_retry_policy,
             wflow: event.wflow,
             cancelling: false,
+            compensating: false,
             runs: default(),
             steps: default(),
             pending_messages: default(),
@@ -46,6 +64,10 @@ pub fn reduce_job_cancel_event(
         info!("cancel for unknown or already-archived job, skipping");
         return;
     };
+    if job_state.compensating {
+        info!("cancel for job that's already compensating, skipping");
+        return;
+    }
 
     if let Some(wait_state) = &job_state.active_wait {
         if matches!(
@@ -61,7 +83,8 @@ pub fn reduce_job_cancel_event(
             });
         }
         job_state.active_wait = None;
-        archive_job(state, &event.job_id);
+        job_state.cancelling = true;
+        archive_or_compensate(state, effects, &event.job_id);
         return;
     }
 
@@ -178,6 +201,7 @@ pub fn reduce_job_run_event(
         ref mut steps,
         ref override_wflow_retry_policy,
         ref cancelling,
+        ref compensating,
         ref mut pending_messages,
         ref mut active_wait,
         ..
@@ -194,19 +218,28 @@ pub fn reduce_job_run_event(
     assert!((event.run_id as usize) == runs.len());
     runs.push(event);
     let next_run_id = runs.len() as u64;
+    // a compensation run keeps going even though the job is cancelling
+    let stopping = *cancelling && !*compensating;
 
     let event = runs.last_mut().unwrap();
     match &event.result {
-        job_events::JobRunResult::Success { .. }
-        | job_events::JobRunResult::WorkerErr(_)
+        job_events::JobRunResult::Aborted if stopping => {
+            *active_wait = None;
+            archive_or_compensate(state, effects, &job_id);
+        }
+        job_events::JobRunResult::Success { .. } => {
+            *active_wait = None;
+            archive_job(state, &job_id);
+        }
+        job_events::JobRunResult::WorkerErr(_)
         | job_events::JobRunResult::WflowErr(JobError::Terminal { .. })
         | job_events::JobRunResult::Aborted => {
             *active_wait = None;
-            archive_job(state, &job_id);
+            archive_or_compensate(state, effects, &job_id);
         }
         job_events::JobRunResult::WflowErr(JobError::Transient { retry_policy, .. }) => {
-            if *cancelling {
-                archive_job(state, &job_id);
+            if stopping {
+                archive_or_compensate(state, effects, &job_id);
             } else {
                 *active_wait = None;
                 let retry_policy = retry_policy
@@ -225,7 +258,9 @@ pub fn reduce_job_run_event(
             }
         }
         job_events::JobRunResult::StepEffect(res) => {
-            if steps.len() == res.step_id as usize {
+            // compensations skip the ids of steps the routine abandoned
+            // while unwinding, those are kept as attempt-less placeholders
+            while steps.len() <= res.step_id as usize {
                 steps.push(state::JobStepState::Effect {
                     attempts: default(),
                 });
@@ -238,11 +273,11 @@ pub fn reduce_job_run_event(
             match &res.deets {
                 job_events::JobEffectResultDeets::EffectErr(JobError::Terminal { .. }) => {
                     *active_wait = None;
-                    archive_job(state, &job_id);
+                    archive_or_compensate(state, effects, &job_id);
                 }
                 job_events::JobEffectResultDeets::Success { .. } => {
-                    if *cancelling {
-                        archive_job(state, &job_id);
+                    if stopping {
+                        archive_or_compensate(state, effects, &job_id);
                     } else {
                         effects.push(PartitionEffect {
                             job_id,
@@ -259,8 +294,8 @@ pub fn reduce_job_run_event(
                     retry_policy,
                     ..
                 }) => {
-                    if *cancelling {
-                        archive_job(state, &job_id);
+                    if stopping {
+                        archive_or_compensate(state, effects, &job_id);
                     } else {
                         match retry_policy
                             .as_ref()
@@ -286,7 +321,7 @@ pub fn reduce_job_run_event(
         job_events::JobRunResult::StepWait(wait) => {
             if *cancelling {
                 *active_wait = None;
-                archive_job(state, &job_id);
+                archive_or_compensate(state, effects, &job_id);
                 return;
             }
 
@@ -303,6 +338,7 @@ pub fn reduce_job_run_event(
                 attempt_id: wait.attempt_id,
                 start_at: wait.start_at,
                 deets: wait.deets.clone(),
+                key: wait.key.clone(),
             };
             *active_wait = Some(wait_state.clone());
 
@@ -343,6 +379,32 @@ pub fn reduce_job_run_event(
     }
 }
 
+/// Jobs that stop short of success with compensations still pending get
+/// one more run to undo them before they're archived. The host refuses any
+/// step that's not a compensation during that run.
+///
+/// Routines that already unwound in the failing run have nothing pending
+/// and are archived right away.
+fn archive_or_compensate(
+    state: &mut state::PartitionJobsState,
+    effects: &mut Vec<PartitionEffect>,
+    job_id: &Arc<str>,
+) {
+    let job_state = state.active.get_mut(job_id).unwrap();
+    if job_state.compensating || !job_state.has_pending_compensations() {
+        archive_job(state, job_id);
+        return;
+    }
+    job_state.compensating = true;
+    effects.push(PartitionEffect {
+        job_id: Arc::clone(job_id),
+        deets: effects::PartitionEffectDeets::RunJob(effects::RunJobAttemptDeets {
+            run_id: job_state.runs.len() as u64,
+            preferred_worker_id: None,
+        }),
+    });
+}
+
 fn archive_job(state: &mut state::PartitionJobsState, job_id: &Arc<str>) {
     let job_state = state.active.remove(job_id).unwrap();
     state.archive.insert(Arc::clone(job_id), job_state);
@@ -388,5 +450,6 @@ fn complete_wait_step_success_for_wait_state(
         start_at: wait_state.start_at,
         end_at,
         deets: job_events::JobEffectResultDeets::Success { value_json },
+        key: wait_state.key.clone(),
     });
 }
@@ -10,6 +10,50 @@ use crate::partition::RetryPolicy;
 pub struct PartitionJobsState {
     pub active: HashMap<Arc<str>, JobState>,
     pub archive: HashMap<Arc<str>, JobState>,
+    #[serde(default)]
+    pub idempotency_keys: HashMap<Arc<str>, IdempotencyRecord>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct IdempotencyRecord {
+    pub job_id: Arc<str>,
+    pub expires_at: Timestamp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum JobStatus {
+    Active,
+    Cancelling,
+    Succeeded,
+    Failed,
+    Cancelled,
+}
+
+impl PartitionJobsState {
+    /// The job that claimed the idempotency key, if the claim is still
+    /// live at `now`.
+    pub fn idempotent_job(&self, key: &str, now: Timestamp) -> Option<&Arc<str>> {
+        self.idempotency_keys
+            .get(key)
+            .filter(|record| record.expires_at > now)
+            .map(|record| &record.job_id)
+    }
+
+    pub fn job_status(&self, job_id: &str) -> Option<JobStatus> {
+        if let Some(job) = self.active.get(job_id) {
+            return Some(if job.cancelling {
+                JobStatus::Cancelling
+            } else {
+                JobStatus::Active
+            });
+        }
+        let job = self.archive.get(job_id)?;
+        Some(match job.runs.last().map(|run| &run.result) {
+            Some(JobRunResult::Success { .. }) => JobStatus::Succeeded,
+            _ if job.cancelling => JobStatus::Cancelled,
+            _ => JobStatus::Failed,
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -19,12 +63,82 @@ pub struct JobState {
     pub override_wflow_retry_policy: Option<RetryPolicy>,
     // FIXME: could be cleaner
     pub cancelling: bool,
+    /// Set once a stopped job was given a run to undo its compensable steps
+    #[serde(default)]
+    pub compensating: bool,
     pub runs: Vec<JobRunEvent>,
     pub steps: Vec<JobStepState>,
     pub pending_messages: VecDeque<JobInboxMessage>,
     pub active_wait: Option<JobWaitState>,
 }
 
+impl JobState {
+    /// Whether completed steps registered more compensations than the
+    /// routine has already run.
+    pub fn has_pending_compensations(&self) -> bool {
+        self.completed_steps_of_kind(StepKind::CompensableEffect)
+            > self.completed_steps_of_kind(StepKind::Compensate)
+    }
+
+    fn completed_steps_of_kind(&self, kind: StepKind) -> usize {
+        self.steps
+            .iter()
+            .filter(|step| {
+                let JobStepState::Effect { attempts } = step;
+                attempts.last().is_some_and(|attempt| {
+                    matches!(attempt.deets, JobEffectResultDeets::Success { .. })
+                        && attempt.key.as_ref().is_some_and(|key| key.kind == kind)
+                })
+            })
+            .count()
+    }
+
+    /// Whether the routine gave up on the step before recording any
+    /// attempt, to unwind its compensations past it.
+    pub fn is_abandoned_step(&self, step_id: u64) -> bool {
+        self.steps.get(step_id as usize).is_some_and(|step| {
+            let JobStepState::Effect { attempts } = step;
+            attempts.is_empty()
+        })
+    }
+
+    /// The key recorded for the given step by its latest attempt.
+    pub fn recorded_step_key(&self, step_id: u64) -> Option<&StepKey> {
+        let JobStepState::Effect { attempts } = self.steps.get(step_id as usize)?;
+        attempts.last()?.key.as_ref()
+    }
+
+    /// Check that a step requested by a (re)running routine matches what
+    /// the journal recorded at the same position.
+    ///
+    /// Steps recorded without a key are accepted.
+    pub fn check_step_key(
+        &self,
+        step_id: u64,
+        requested: &StepKey,
+    ) -> Result<(), NonDeterminismError> {
+        match self.recorded_step_key(step_id) {
+            Some(recorded) if recorded != requested => Err(NonDeterminismError {
+                step_id,
+                recorded: recorded.clone(),
+                requested: requested.clone(),
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// A routine requested a different step than the one recorded in its journal.
+#[derive(Debug, Clone, thiserror::Error)]
+#[error(
+    "non-determinism detected at step {step_id}: journal recorded {recorded} but routine requested {requested}"
+)]
+pub struct NonDeterminismError {
+    pub step_id: u64,
+    pub recorded: StepKey,
+    pub requested: StepKey,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum JobStepState {
     Effect { attempts: Vec<JobEffectResult> },
@@ -46,4 +160,6 @@ pub struct JobWaitState {
     pub attempt_id: u64,
     pub start_at: Timestamp,
     pub deets: crate::partition::job_events::JobWaitResultDeets,
+    #[serde(default)]
+    pub key: Option<StepKey>,
 }
@@ -1,6 +1,6 @@
 mod interlude {
     pub use api_utils_rs::prelude::*;
-    pub use std::cell::Cell;
+    pub use std::cell::{Cell, RefCell};
     pub use std::rc::Rc;
 }
 
@@ -46,6 +46,13 @@ pub enum JobErrorX {
 pub struct WflowCtx {
     pub job: types::JobCtx,
     in_step: Rc<Cell<bool>>,
+    compensations: Rc<RefCell<Vec<Compensation>>>,
+}
+
+/// An undo registered by [`WflowCtx::effect_with_compensation`].
+struct Compensation {
+    name: String,
+    undo: Box<dyn FnOnce() -> Result<(), JobErrorX>>,
 }
 
 struct StepGuard {
@@ -91,14 +98,144 @@ impl WflowCtx {
         })
     }
 
+    /// Ask the host for the state of the next step.
+    ///
+    /// The key is checked against the journal and a routine that diverges
+    /// from its recorded step sequence fails terminally.
+    fn next_step(
+        &self,
+        kind: host::StepKind,
+        name: String,
+    ) -> Result<host::StepState, host::NextStepError> {
+        host::next_step(&self.job.job_id, &host::StepKey { kind, name })
+    }
+
+    fn location_name(location: &std::panic::Location<'_>) -> String {
+        format!(
+            "{file}:{line}:{col}",
+            file = location.file(),
+            line = location.line(),
+            col = location.column()
+        )
+    }
+
+    /// Run a side-effecting step, replaying the recorded result if the step
+    /// was already completed.
+    ///
+    /// The step is identified by its call site. Prefer [`Self::effect_named`]
+    /// for long-running routines since edits that move the call around will
+    /// make in-flight jobs fail on replay.
+    #[track_caller]
     pub fn effect<F, O>(&mut self, func: F) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        O: serde::de::DeserializeOwned + Serialize,
+    {
+        let name = Self::location_name(std::panic::Location::caller());
+        self.effect_inner(name, func)
+    }
+
+    /// Like [`Self::effect`] but identified by the given name.
+    pub fn effect_named<F, O>(&mut self, name: &str, func: F) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        O: serde::de::DeserializeOwned + Serialize,
+    {
+        self.effect_inner(name.to_string(), func)
+    }
+
+    /// Run an effect and register an undo step for it.
+    ///
+    /// If the job later fails terminally or gets cancelled, the registered
+    /// undos are run in reverse order as their own journaled steps. The undo
+    /// gets the value produced by `func`, replayed or not. Undos run once:
+    /// their failures are reported in the job's terminal error instead of
+    /// being retried.
+    #[track_caller]
+    pub fn effect_with_compensation<F, U, O>(&mut self, func: F, undo: U) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        U: FnOnce(&O) -> Result<(), JobErrorX> + 'static,
+        O: serde::de::DeserializeOwned + Serialize + Clone + 'static,
+    {
+        let name = Self::location_name(std::panic::Location::caller());
+        self.effect_with_compensation_inner(name, func, undo)
+    }
+
+    /// Like [`Self::effect_with_compensation`] but identified by the given name.
+    pub fn effect_with_compensation_named<F, U, O>(
+        &mut self,
+        name: &str,
+        func: F,
+        undo: U,
+    ) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        U: FnOnce(&O) -> Result<(), JobErrorX> + 'static,
+        O: serde::de::DeserializeOwned + Serialize + Clone + 'static,
+    {
+        self.effect_with_compensation_inner(name.to_string(), func, undo)
+    }
+
+    fn effect_with_compensation_inner<F, U, O>(
+        &mut self,
+        name: String,
+        func: F,
+        undo: U,
+    ) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        U: FnOnce(&O) -> Result<(), JobErrorX> + 'static,
+        O: serde::de::DeserializeOwned + Serialize + Clone + 'static,
+    {
+        let value = self.step_inner(host::StepKind::CompensableEffect, name.clone(), func)?;
+        let undo_value = value.clone();
+        self.compensations.borrow_mut().push(Compensation {
+            name,
+            undo: Box::new(move || undo(&undo_value)),
+        });
+        Ok(value)
+    }
+
+    /// Run the registered undos, latest first.
+    ///
+    /// Returns the errors of the undos that failed.
+    fn compensate(&mut self) -> Vec<String> {
+        let compensations = std::mem::take(&mut *self.compensations.borrow_mut());
+        let mut errors = vec![];
+        for Compensation { name, undo } in compensations.into_iter().rev() {
+            let res = self.step_inner(
+                host::StepKind::Compensate,
+                format!("compensate:{name}"),
+                || undo().map(Json),
+            );
+            if let Err(err) = res {
+                errors.push(format!("{name}: {err}"));
+            }
+        }
+        errors
+    }
+
+    fn effect_inner<F, O>(&mut self, name: String, func: F) -> Result<O, JobErrorX>
+    where
+        F: FnOnce() -> Result<Json<O>, JobErrorX>,
+        O: serde::de::DeserializeOwned + Serialize,
+    {
+        self.step_inner(host::StepKind::Effect, name, func)
+    }
+
+    fn step_inner<F, O>(
+        &mut self,
+        kind: host::StepKind,
+        name: String,
+        func: F,
+    ) -> Result<O, JobErrorX>
     where
         F: FnOnce() -> Result<Json<O>, JobErrorX>,
         O: serde::de::DeserializeOwned + Serialize,
     {
         let _step_guard = self.enter_step("effect")?;
-        let state = host::next_step(&self.job.job_id)
-            .map_err(|err| ferr!("error getting next op: {err}"))?;
+        let state = self.next_step(kind, name).map_err(next_step_error_x)?;
         match state {
             host::StepState::Completed(completed) => {
                 let value: O = serde_json::from_str(&completed.value_json).map_err(|err| {
@@ -124,10 +261,21 @@ impl WflowCtx {
         }
     }
 
+    #[track_caller]
     pub fn sleep(&mut self, duration: Duration) -> Result<(), JobErrorX> {
+        let name = Self::location_name(std::panic::Location::caller());
+        self.sleep_inner(name, duration)
+    }
+
+    pub fn sleep_named(&mut self, name: &str, duration: Duration) -> Result<(), JobErrorX> {
+        self.sleep_inner(name.to_string(), duration)
+    }
+
+    fn sleep_inner(&mut self, name: String, duration: Duration) -> Result<(), JobErrorX> {
         let _step_guard = self.enter_step("sleep")?;
-        let state = host::next_step(&self.job.job_id)
-            .map_err(|err| JobErrorX::Terminal(ferr!("error getting next op: {err}")))?;
+        let state = self
+            .next_step(host::StepKind::Sleep, name)
+            .map_err(|err| JobErrorX::Terminal(next_step_error_report(err)))?;
         match state {
             host::StepState::Completed(_completed) => Ok(()),
             host::StepState::Active(active_op_state) => host::sleep(
@@ -141,13 +289,30 @@ impl WflowCtx {
         }
     }
 
+    #[track_caller]
     pub fn recv<O>(&mut self) -> Result<O, JobErrorX>
+    where
+        O: RecvCodec,
+    {
+        let name = Self::location_name(std::panic::Location::caller());
+        self.recv_inner(name)
+    }
+
+    pub fn recv_named<O>(&mut self, name: &str) -> Result<O, JobErrorX>
+    where
+        O: RecvCodec,
+    {
+        self.recv_inner(name.to_string())
+    }
+
+    fn recv_inner<O>(&mut self, name: String) -> Result<O, JobErrorX>
     where
         O: RecvCodec,
     {
         let _step_guard = self.enter_step("recv")?;
-        let state = host::next_step(&self.job.job_id)
-            .map_err(|err| JobErrorX::Terminal(ferr!("error getting next op: {err}")))?;
+        let state = self
+            .next_step(host::StepKind::Recv, name)
+            .map_err(|err| JobErrorX::Terminal(next_step_error_report(err)))?;
         let value_json = match state {
             host::StepState::Completed(completed) => completed.value_json,
             host::StepState::Active(active_op_state) => {
@@ -160,6 +325,32 @@ impl WflowCtx {
     }
 }
 
+fn next_step_error_report(err: host::NextStepError) -> eyre::Report {
+    match err {
+        host::NextStepError::NonDeterminism(err) => ferr!(
+            "non-determinism detected at step {step_id}: journal recorded {recorded:?}({recorded_name}) but routine requested {requested:?}({requested_name})",
+            step_id = err.step_id,
+            recorded = err.recorded.kind,
+            recorded_name = err.recorded.name,
+            requested = err.requested.kind,
+            requested_name = err.requested.name,
+        ),
+        host::NextStepError::Cancelled => ferr!("job cancelled"),
+        host::NextStepError::Other(err) => ferr!("error getting next op: {err}"),
+    }
+}
+
+/// Non-determinism and cancellation are never recoverable by retrying, other
+/// errors keep the transient treatment `effect` has always given them.
+fn next_step_error_x(err: host::NextStepError) -> JobErrorX {
+    match err {
+        host::NextStepError::NonDeterminism(_) | host::NextStepError::Cancelled => {
+            JobErrorX::Terminal(next_step_error_report(err))
+        }
+        host::NextStepError::Other(_) => JobErrorX::Transient(next_step_error_report(err)),
+    }
+}
+
 /// Helper function to convert JobErrorX to JobError
 pub fn job_error_from_x(err: JobErrorX) -> types::JobError {
     use types::TransientJobError;
@@ -208,13 +399,27 @@ where
     let mut cx = WflowCtx {
         job: args.ctx,
         in_step: Rc::new(Cell::new(false)),
+        compensations: default(),
     };
 
     // Parse args JSON
     let args_json = &args.args_json;
 
-    // Call handler and convert errors
-    let result = handler(&mut cx, &args.wflow_key, args_json).map_err(job_error_from_x);
+    // Call handler, undo its compensable effects on terminal failure
+    // and convert errors
+    let result = match handler(&mut cx, &args.wflow_key, args_json) {
+        Err(JobErrorX::Terminal(err)) if !cx.compensations.borrow().is_empty() => {
+            let errors = cx.compensate();
+            let err = if errors.is_empty() {
+                err
+            } else {
+                err.wrap_err(format!("compensations failed: {}", errors.join("; ")))
+            };
+            Err(JobErrorX::Terminal(err))
+        }
+        res => res,
+    }
+    .map_err(job_error_from_x);
 
     // Serialize result
     match result {
@@ -0,0 +1,94 @@
+use crate::interlude::*;
+
+use futures::future::BoxFuture;
+
+/// Source of time for the partition workers.
+///
+/// Swapped out for [`SimClock`] to run partitions on virtual time.
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Timestamp;
+
+    /// Resolves once the clock reads `at` or later.
+    fn sleep_until(&self, at: Timestamp) -> BoxFuture<'static, ()>;
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Timestamp {
+        Timestamp::now()
+    }
+
+    fn sleep_until(&self, at: Timestamp) -> BoxFuture<'static, ()> {
+        let wait = Duration::try_from(at.duration_since(Timestamp::now())).unwrap_or_default();
+        tokio::time::sleep(wait).boxed()
+    }
+}
+
+/// A clock that only moves when told to.
+#[derive(Debug)]
+pub struct SimClock {
+    now: tokio::sync::watch::Sender<Timestamp>,
+}
+
+impl SimClock {
+    pub fn new(start: Timestamp) -> Self {
+        Self {
+            now: tokio::sync::watch::Sender::new(start),
+        }
+    }
+
+    pub fn advance(&self, by: Duration) {
+        self.now
+            .send_modify(|now| *now = now.checked_add(by).expect("ts overflow"));
+    }
+
+    /// Move the clock forward to `to`. Never moves it backwards.
+    pub fn advance_to(&self, to: Timestamp) {
+        self.now.send_if_modified(|now| {
+            if to > *now {
+                *now = to;
+                true
+            } else {
+                false
+            }
+        });
+    }
+}
+
+impl Clock for SimClock {
+    fn now(&self) -> Timestamp {
+        *self.now.borrow()
+    }
+
+    fn sleep_until(&self, at: Timestamp) -> BoxFuture<'static, ()> {
+        let mut now = self.now.subscribe();
+        async move {
+            // the sender lives as long as the clock, a dropped clock never fires
+            if now.wait_for(|now| *now >= at).await.is_err() {
+                futures::future::pending::<()>().await;
+            }
+        }
+        .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_sim_clock_sleep_waits_for_advance() {
+        let clock = SimClock::new(Timestamp::UNIX_EPOCH);
+        let at = Timestamp::UNIX_EPOCH
+            .checked_add(Duration::from_secs(60))
+            .unwrap();
+        let mut sleep = clock.sleep_until(at);
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance(Duration::from_secs(30));
+        assert!((&mut sleep).now_or_never().is_none());
+        clock.advance_to(at);
+        assert!(sleep.now_or_never().is_some());
+    }
+}
@@ -2,8 +2,10 @@ mod interlude {
     pub use utils_rs::prelude::*;
 }
 
+pub mod clock;
 pub mod local_native_host;
 pub mod partition;
+pub mod sim;
 
 // Re-export types from wflow for convenience (when wflow_tokio is used with wflow)
 pub use wflow_core::snapstore::{PartitionSnapshot, SnapStore};
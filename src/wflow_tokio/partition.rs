@@ -41,6 +41,7 @@ pub struct PartitionCtx {
             + Send,
     >,
     pub local_native_host: Arc<dyn self::service::WflowServiceHost<ExtraArgs = ()> + Sync + Send>,
+    pub clock: Arc<dyn crate::clock::Clock>,
 }
 
 impl PartitionCtx {
@@ -63,9 +64,15 @@ impl PartitionCtx {
             log,
             local_wasmcloud_host,
             local_native_host,
+            clock: Arc::new(crate::clock::SystemClock),
         }
     }
 
+    pub fn with_clock(mut self, clock: Arc<dyn crate::clock::Clock>) -> Self {
+        self.clock = clock;
+        self
+    }
+
     pub fn log_ref(&self) -> PartitionLogRef {
         PartitionLogRef::new(Arc::clone(&self.log))
     }
@@ -146,10 +153,22 @@ impl Drop for TokioPartitionWorkerHandle {
     }
 }
 
+const EFFECT_WORKER_COUNT: usize = 8;
+
 pub async fn start_tokio_worker(
     pcx: PartitionCtx,
     working_state: Arc<state::PartitionWorkingState>,
     snap_store: Arc<dyn SnapStore<Snapshot = Arc<[u8]>>>,
+) -> TokioPartitionWorkerHandle {
+    start_tokio_worker_with_effect_workers(pcx, working_state, snap_store, EFFECT_WORKER_COUNT)
+}
+
+/// [`start_tokio_worker`] with `effect_worker_count` effect workers.
+pub(crate) fn start_tokio_worker_with_effect_workers(
+    pcx: PartitionCtx,
+    working_state: Arc<state::PartitionWorkingState>,
+    snap_store: Arc<dyn SnapStore<Snapshot = Arc<[u8]>>>,
+    effect_worker_count: usize,
 ) -> TokioPartitionWorkerHandle {
     let cancel_token = CancellationToken::new();
     let effect_cancel_tokens: EffectCancelTokens = Arc::new(Mutex::new(HashMap::new()));
@@ -158,7 +177,7 @@ pub async fn start_tokio_worker(
     let mut effect_workers = vec![];
     // Shared channel for effect scheduling
     let (effect_tx, effect_rx) = async_channel::unbounded::<effects::EffectId>();
-    for ii in 0..8 {
+    for ii in 0..effect_worker_count {
         let worker_id: WorkerId = format!("tokio-fxw-p{}-{}", pcx.id, ii).into();
         let (direct_effect_tx, direct_effect_rx) = async_channel::bounded::<effects::EffectId>(64);
         direct_effect_senders.insert(Arc::clone(&worker_id), direct_effect_tx);
@@ -65,9 +65,12 @@ pub fn start_tokio_effect_worker(
                 pcx,
             };
             debug!("starting");
-            let mut timer_tick = tokio::time::interval(Duration::from_millis(100));
-            timer_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
             loop {
+                // re-armed every iteration as handled effects can add timers
+                let next_timer = match worker.next_timer_at() {
+                    Some(fire_at) => worker.pcx.clock.sleep_until(fire_at),
+                    None => futures::future::pending().boxed(),
+                };
                 tokio::select! {
                     biased;
                     _ = cancel_token.cancelled() => {
@@ -86,7 +89,7 @@ pub fn start_tokio_effect_worker(
                         };
                         worker.handle_partition_effects(effect_id).await?;
                     }
-                    _ = timer_tick.tick() => {
+                    _ = next_timer => {
                         worker.fire_due_timers().await?;
                     }
                 };
@@ -170,17 +173,28 @@ impl TokioEffectWorker {
         }
     }
 
+    fn next_timer_at(&self) -> Option<Timestamp> {
+        self.pending_timers
+            .values()
+            .map(|timer| timer.fire_at)
+            .min()
+    }
+
     async fn fire_due_timers(&mut self) -> Res<()> {
         if self.pending_timers.is_empty() {
             return Ok(());
         }
-        let now = Timestamp::now();
-        let due_effect_ids = self
+        let now = self.pcx.clock.now();
+        let mut due_timers = self
             .pending_timers
             .values()
             .filter(|timer| timer.fire_at <= now)
-            .map(|timer| timer.effect_id.clone())
+            .map(|timer| (timer.fire_at, timer.effect_id.clone()))
             .collect::<Vec<_>>();
+        due_timers.sort_by_key(|(fire_at, effect_id)| {
+            (*fire_at, effect_id.entry_id, effect_id.effect_idx)
+        });
+        let due_effect_ids = due_timers.into_iter().map(|(_, effect_id)| effect_id);
 
         for effect_id in due_effect_ids {
             let Some(timer) = self.pending_timers.remove(&effect_id) else {
@@ -249,8 +263,7 @@ impl TokioEffectWorker {
         match deets {
             effects::PartitionEffectDeets::RunJob(run_deets) => {
                 let run_id = run_deets.run_id;
-                let start_at = Timestamp::now();
-                let run_start_instant = std::time::Instant::now();
+                let start_at = self.pcx.clock.now();
                 let run_abort_token = self
                     .effect_cancel_tokens
                     .lock()
@@ -272,8 +285,7 @@ impl TokioEffectWorker {
                     .await;
                 self.job_to_effect_id.lock().await.remove(&job_id);
                 self.effect_cancel_tokens.lock().await.remove(&effect_id);
-                let elapsed = run_start_instant.elapsed();
-                let end_at = start_at.checked_add(elapsed).expect("ts overflow");
+                let end_at = self.pcx.clock.now().max(start_at);
                 self.log
                     .append(&log::PartitionLogEntry::JobEffectResult(
                         job_events::JobRunEvent {
@@ -87,7 +87,7 @@ pub fn start_tokio_partition_reducer(
                 worker_effect_senders,
                 snapstore: snap_store,
                 entries_since_snapshot: 0,
-                last_snapshot_time: Timestamp::now(),
+                last_snapshot_time: pcx.clock.now(),
                 last_snapshotted_entry_id: start_offset.saturating_sub(1),
                 replay_latest_entry_id: latest_entry_id_at_start,
                 replay_seen_effect_sources: default(),
@@ -198,7 +198,7 @@ impl TokioPartitionReducer {
         if self.did_reschedule_after_replay {
             return Ok(());
         }
-        let effects_to_reschedule = {
+        let mut effects_to_reschedule = {
             let effects_map = self.state.read_effects().await;
             effects_map
                 .iter()
@@ -215,6 +215,9 @@ impl TokioPartitionReducer {
                 })
                 .collect::<Vec<_>>()
         };
+        // in log order, the map's order would differ across boots
+        effects_to_reschedule
+            .sort_by_key(|(effect_id, _)| (effect_id.entry_id, effect_id.effect_idx));
         for (effect_id, is_run_job) in effects_to_reschedule {
             if is_run_job {
                 let cancel_token = CancellationToken::new();
@@ -276,9 +279,7 @@ impl TokioPartitionReducer {
             }
         };
 
-        self.state
-            .last_applied_entry_id
-            .store(entry_id, Ordering::SeqCst);
+        self.state.notify_applied(entry_id);
 
         // Check if we should snapshot (entry-based)
         self.entries_since_snapshot += 1;
@@ -312,7 +313,7 @@ impl TokioPartitionReducer {
                 .await
                 .wrap_err("failed to save snapshot")?;
             self.entries_since_snapshot = 0;
-            self.last_snapshot_time = Timestamp::now();
+            self.last_snapshot_time = self.pcx.clock.now();
             self.last_snapshotted_entry_id = entry_id;
         }
         Ok(())
@@ -373,15 +374,26 @@ impl TokioPartitionReducer {
                     )
                 }
                 log::PartitionLogEntry::JobEffectResult(evt) => {
-                    {
+                    let run_effect = {
                         let mut effects = self.state.write_effects().await;
-                        effects.remove(&evt.effect_id);
+                        effects.remove(&evt.effect_id)
+                    };
+                    // the run's effect goes away with its first completion,
+                    // anything after that is a redelivery
+                    if run_effect.is_none() {
+                        warn!(
+                            effect_id = ?evt.effect_id,
+                            job_id = %evt.job_id,
+                            run_id = evt.run_id,
+                            "duplicate effect completion, skipping"
+                        );
+                    } else {
+                        wflow_core::partition::reduce::reduce_job_run_event(
+                            &mut jobs,
+                            &mut self.event_effects,
+                            evt,
+                        )
                     }
-                    wflow_core::partition::reduce::reduce_job_run_event(
-                        &mut jobs,
-                        &mut self.event_effects,
-                        evt,
-                    )
                 }
                 log::PartitionLogEntry::JobCancel(evt) => {
                     wflow_core::partition::reduce::reduce_job_cancel_event(
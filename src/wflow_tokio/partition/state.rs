@@ -23,6 +23,9 @@ pub struct PartitionWorkingState {
     // Change notification channel - sends JobCounts whenever counts change
     change_tx: tokio::sync::watch::Sender<JobCounts>,
     change_rx: tokio::sync::watch::Receiver<JobCounts>,
+    // Latest entry id the reducer applied, for callers waiting on their appends
+    // None until the reducer applies its first entry in this process
+    applied_tx: tokio::sync::watch::Sender<Option<u64>>,
 }
 
 impl PartitionWorkingState {
@@ -37,12 +40,14 @@ impl PartitionWorkingState {
             archive: initial_jobs.archive.len(),
         };
         let (change_tx, change_rx) = tokio::sync::watch::channel(initial_counts);
+        let (applied_tx, _) = tokio::sync::watch::channel(None);
         Self {
             last_applied_entry_id: AtomicU64::new(initial_entry_id),
             jobs: RwLock::new(initial_jobs),
             effects: RwLock::new(initial_effects),
             change_tx,
             change_rx,
+            applied_tx,
         }
     }
 
@@ -77,6 +82,24 @@ impl PartitionWorkingState {
         self.change_rx.clone()
     }
 
+    /// Record that the reducer applied the given entry
+    pub fn notify_applied(&self, entry_id: u64) {
+        self.last_applied_entry_id
+            .store(entry_id, std::sync::atomic::Ordering::SeqCst);
+        self.applied_tx.send_replace(Some(entry_id));
+    }
+
+    /// Wait until the reducer has applied the given entry. Only meant
+    /// for entries appended after the reducer started.
+    pub async fn wait_until_applied(&self, entry_id: u64) -> Res<()> {
+        let mut applied_rx = self.applied_tx.subscribe();
+        applied_rx
+            .wait_for(|applied| applied.is_some_and(|applied| applied >= entry_id))
+            .await
+            .wrap_err(ERROR_CHANNEL)?;
+        Ok(())
+    }
+
     /// Get a read lock on effects state
     pub async fn read_effects(
         &self,
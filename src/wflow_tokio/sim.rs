@@ -0,0 +1,659 @@
+//! Deterministic simulation of a partition.
+//!
+//! Runs the real partition reducer and an effect worker on a [`SimClock`]
+//! with every log append held back until the simulation lets it land.
+//! Between steps the workers are left to run until they've all parked, then
+//! a seeded rng picks which held append lands next or whether virtual time
+//! moves on to the next timer. Faults are rolled from the same rng as appends
+//! land so a seed fully determines a run. Re-run a failing seed to replay it
+//! exactly.
+//!
+//! Must be driven from a current-thread runtime, that's what makes the
+//! workers' progress between steps the same on every run.
+
+use crate::interlude::*;
+
+use std::collections::HashSet;
+
+use tokio_util::sync::CancellationToken;
+use wflow_core::gen::metastore::{PartitionsMeta, WflowMeta, WflowServiceMeta};
+use wflow_core::kvstore::{
+    log::KvStoreLog, metastore::KvStoreMetadtaStore, snapstore::KvSnapStore, KvStore,
+};
+use wflow_core::log::{LogStore, TailLogEntry};
+use wflow_core::partition::log::PartitionLogEntry;
+use wflow_core::partition::{effects, job_events, state};
+use wflow_core::snapstore::SnapStore;
+
+use crate::clock::{Clock, SimClock};
+use crate::partition::service::{RunJobCtx, RunJobReply, WflowServiceHost, WflowServiceSession};
+use crate::partition::state::PartitionWorkingState;
+use crate::partition::{PartitionCtx, TokioPartitionWorkerHandle};
+
+pub type SimHost = Arc<dyn WflowServiceHost<ExtraArgs = ()> + Send + Sync>;
+
+const SIM_PARTITION_ID: u64 = 0;
+/// Consecutive yields without progress after which the workers count as parked
+const SETTLE_YIELDS: usize = 32;
+
+/// Chance, between 0 and 1, of each fault being injected whenever it can be.
+#[derive(Debug, Clone, Default)]
+pub struct SimFaults {
+    /// Crash right after a batch of effects lands in the log, before any of
+    /// them is scheduled
+    pub crash_after_effects_append: f64,
+    /// Land a run's completion in the log twice
+    pub duplicate_completion: f64,
+    /// Lose a fired timer. It only comes back when the partition restarts.
+    pub drop_timer: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    pub seed: u64,
+    pub faults: SimFaults,
+    /// Give up after this many scheduling steps
+    pub max_steps: usize,
+    pub start_at: Timestamp,
+}
+
+impl SimConfig {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            faults: default(),
+            max_steps: 10_000,
+            start_at: Timestamp::UNIX_EPOCH,
+        }
+    }
+
+    pub fn with_faults(mut self, faults: SimFaults) -> Self {
+        self.faults = faults;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SimEvent {
+    /// An entry landed in the log, serialized as it was appended
+    Appended {
+        entry_id: u64,
+        entry: Arc<str>,
+    },
+    AdvancedClock {
+        to: Timestamp,
+    },
+    Crashed {
+        entry_id: u64,
+    },
+    Restarted,
+    DuplicatedCompletion {
+        entry_id: u64,
+        job_id: Arc<str>,
+        run_id: u64,
+    },
+    DroppedTimer {
+        job_id: Arc<str>,
+        wait_id: u64,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SimOutcome {
+    /// No active jobs and nothing left to do
+    Quiescent,
+    /// Active jobs remain but nothing can make progress, even after a restart
+    Stalled,
+    StepLimit,
+}
+
+#[derive(Debug)]
+pub struct SimReport {
+    pub seed: u64,
+    pub outcome: SimOutcome,
+    pub trace: Vec<SimEvent>,
+    pub jobs: state::PartitionJobsState,
+}
+
+/// splitmix64, kept in-tree so seeds replay the same across dependency bumps
+struct SimRng(u64);
+
+impl SimRng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    fn below(&mut self, len: usize) -> usize {
+        (self.next_u64() % len as u64) as usize
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        if probability <= 0.0 {
+            return false;
+        }
+        let sample = (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64;
+        sample < probability
+    }
+}
+
+struct HeldAppend {
+    entry: Arc<[u8]>,
+    reply: tokio::sync::oneshot::Sender<u64>,
+}
+
+/// Log that holds every append until the simulation lands it. Reads go
+/// straight to the backing log.
+struct SimLog {
+    inner: KvStoreLog,
+    held: std::sync::Mutex<Vec<HeldAppend>>,
+}
+
+impl SimLog {
+    fn held_count(&self) -> usize {
+        self.held.lock().expect(ERROR_MUTEX).len()
+    }
+
+    /// Everything held, in an order that doesn't depend on which worker got
+    /// to the log first.
+    fn take_held(&self) -> Vec<HeldAppend> {
+        let mut held = std::mem::take(&mut *self.held.lock().expect(ERROR_MUTEX));
+        held.sort_by(|left, right| left.entry.cmp(&right.entry));
+        held
+    }
+
+    fn put_back(&self, held: Vec<HeldAppend>) {
+        self.held.lock().expect(ERROR_MUTEX).extend(held);
+    }
+}
+
+#[async_trait]
+impl LogStore for SimLog {
+    async fn append(&self, entry: &[u8]) -> Res<u64> {
+        let (reply, reply_rx) = tokio::sync::oneshot::channel();
+        self.held.lock().expect(ERROR_MUTEX).push(HeldAppend {
+            entry: entry.into(),
+            reply,
+        });
+        // only dropped when the partition crashes and this task with it
+        reply_rx.await.wrap_err(ERROR_CHANNEL)
+    }
+
+    fn tail(&'_ self, offset: u64) -> futures::stream::BoxStream<'_, Res<TailLogEntry>> {
+        self.inner.tail(offset)
+    }
+
+    async fn latest_idx(&self) -> Res<u64> {
+        self.inner.latest_idx().await
+    }
+}
+
+/// Stands in for the wasmcloud host, the simulation only runs native wflows.
+struct NoWasmcloudHost;
+
+#[async_trait]
+impl WflowServiceHost for NoWasmcloudHost {
+    type ExtraArgs = wflow_core::metastore::WasmcloudWflowServiceMeta;
+
+    async fn run(
+        &self,
+        _ctx: &RunJobCtx,
+        _job_id: Arc<str>,
+        _journal: state::JobState,
+        _session: Option<Box<dyn WflowServiceSession>>,
+        _cancel_token: CancellationToken,
+        _args: &Self::ExtraArgs,
+    ) -> RunJobReply {
+        RunJobReply {
+            result: Err(job_events::JobRunResult::WorkerErr(
+                job_events::JobRunWorkerError::WflowNotFound,
+            )),
+            session: None,
+        }
+    }
+}
+
+struct SimPartition {
+    state: Arc<PartitionWorkingState>,
+    // dropping the handle aborts the workers wherever they are
+    _worker: TokioPartitionWorkerHandle,
+}
+
+enum SimStep {
+    Land(HeldAppend),
+    AdvanceClock(Timestamp),
+}
+
+pub struct Simulation {
+    config: SimConfig,
+    rng: SimRng,
+    clock: Arc<SimClock>,
+    host: SimHost,
+    log: Arc<SimLog>,
+    metastore: Arc<dyn wflow_core::metastore::MetdataStore>,
+    snapstore: Arc<dyn SnapStore<Snapshot = Arc<[u8]>>>,
+    partition: Option<SimPartition>,
+    dropped_timers: HashSet<(Arc<str>, u64)>,
+    restart_pending: bool,
+    trace: Vec<SimEvent>,
+}
+
+impl Simulation {
+    pub async fn new(config: SimConfig, clock: Arc<SimClock>, host: SimHost) -> Res<Self> {
+        let log = SimLog {
+            inner: KvStoreLog::new(new_in_memory_kv_store()).await?,
+            held: default(),
+        };
+        let metastore = KvStoreMetadtaStore::new(
+            new_in_memory_kv_store(),
+            PartitionsMeta {
+                version: "0".into(),
+                partition_count: 1,
+            },
+        )
+        .await?;
+        Ok(Self {
+            rng: SimRng(config.seed),
+            config,
+            clock,
+            host,
+            log: Arc::new(log),
+            metastore: Arc::new(metastore),
+            snapstore: Arc::new(KvSnapStore::new(new_in_memory_kv_store())),
+            partition: None,
+            dropped_timers: default(),
+            restart_pending: false,
+            trace: default(),
+        })
+    }
+
+    /// Submit a job. Jobs go straight into the log, the partition picks them
+    /// up once [`Self::run`] starts it.
+    pub async fn add_job(
+        &mut self,
+        job_id: impl Into<Arc<str>>,
+        wflow_key: &str,
+        args_json: &str,
+    ) -> Res<()> {
+        self.append_direct(&PartitionLogEntry::JobInit(job_events::JobInitEvent {
+            job_id: job_id.into(),
+            timestamp: self.clock.now(),
+            args_json: args_json.into(),
+            override_wflow_retry_policy: None,
+            wflow: WflowMeta {
+                key: wflow_key.to_string(),
+                service: WflowServiceMeta::LocalNative,
+            },
+            idempotency: None,
+        }))
+        .await
+    }
+
+    pub async fn cancel_job(&mut self, job_id: impl Into<Arc<str>>, reason: &str) -> Res<()> {
+        self.append_direct(&PartitionLogEntry::JobCancel(job_events::JobCancelEvent {
+            job_id: job_id.into(),
+            timestamp: self.clock.now(),
+            reason: reason.into(),
+        }))
+        .await
+    }
+
+    async fn append_direct(&self, entry: &PartitionLogEntry) -> Res<()> {
+        self.log
+            .inner
+            .append(&serde_json::to_vec(entry).expect(ERROR_JSON))
+            .await?;
+        Ok(())
+    }
+
+    pub async fn run(mut self) -> Res<SimReport> {
+        assert_eq!(
+            tokio::runtime::Handle::current().runtime_flavor(),
+            tokio::runtime::RuntimeFlavor::CurrentThread,
+            "simulations are only deterministic on a current-thread runtime"
+        );
+        self.start_partition();
+        let outcome = self.run_steps().await?;
+        let jobs = self.partition_state().read_jobs().await.clone();
+        // stop the workers before the log they hold appends on goes away
+        self.partition = None;
+        Ok(SimReport {
+            seed: self.config.seed,
+            outcome,
+            trace: self.trace,
+            jobs,
+        })
+    }
+
+    async fn run_steps(&mut self) -> Res<SimOutcome> {
+        for _ in 0..self.config.max_steps {
+            self.settle().await;
+
+            let now = self.clock.now();
+            let next_timer = self
+                .partition_state()
+                .read_effects()
+                .await
+                .values()
+                .filter_map(|effect| match &effect.deets {
+                    effects::PartitionEffectDeets::WaitTimer(wait) if wait.fire_at > now => {
+                        Some(wait.fire_at)
+                    }
+                    _ => None,
+                })
+                .min();
+            let mut steps = self
+                .log
+                .take_held()
+                .into_iter()
+                .map(SimStep::Land)
+                .collect::<Vec<_>>();
+            steps.extend(next_timer.map(SimStep::AdvanceClock));
+
+            if steps.is_empty() {
+                if self.partition_state().read_jobs().await.active.is_empty() {
+                    return Ok(SimOutcome::Quiescent);
+                }
+                if self.restart_pending {
+                    // a reboot is the only thing that recovers lost timers
+                    self.restart();
+                    continue;
+                }
+                return Ok(SimOutcome::Stalled);
+            }
+
+            let step = steps.swap_remove(self.rng.below(steps.len()));
+            self.log.put_back(
+                steps
+                    .into_iter()
+                    .filter_map(|step| match step {
+                        SimStep::Land(held) => Some(held),
+                        SimStep::AdvanceClock(_) => None,
+                    })
+                    .collect(),
+            );
+            match step {
+                SimStep::Land(held) => self.land(held).await?,
+                SimStep::AdvanceClock(to) => {
+                    self.clock.advance_to(to);
+                    self.trace.push(SimEvent::AdvancedClock { to });
+                }
+            }
+        }
+        Ok(SimOutcome::StepLimit)
+    }
+
+    /// Let the workers run until they're all parked on the log, a channel
+    /// or the clock.
+    async fn settle(&self) {
+        let mut last_progress = None;
+        let mut idle_yields = 0;
+        while idle_yields < SETTLE_YIELDS {
+            tokio::task::yield_now().await;
+            let progress = (
+                self.log.held_count(),
+                self.partition_state()
+                    .last_applied_entry_id
+                    .load(std::sync::atomic::Ordering::SeqCst),
+            );
+            if last_progress == Some(progress) {
+                idle_yields += 1;
+            } else {
+                last_progress = Some(progress);
+                idle_yields = 0;
+            }
+        }
+    }
+
+    async fn land(&mut self, held: HeldAppend) -> Res<()> {
+        let entry: PartitionLogEntry = serde_json::from_slice(&held.entry).expect(ERROR_JSON);
+        if let PartitionLogEntry::JobTimerFired(evt) = &entry {
+            // a timer is only lost once, otherwise a seed could drop it forever
+            let timer = (Arc::clone(&evt.job_id), evt.wait_id);
+            if !self.dropped_timers.contains(&timer)
+                && self.rng.chance(self.config.faults.drop_timer)
+            {
+                self.trace.push(SimEvent::DroppedTimer {
+                    job_id: Arc::clone(&evt.job_id),
+                    wait_id: evt.wait_id,
+                });
+                self.dropped_timers.insert(timer);
+                self.restart_pending = true;
+                // the worker carries on as if it landed
+                held.reply.send(0).ok();
+                return Ok(());
+            }
+        }
+
+        let entry_id = self.log.inner.append(&held.entry).await?;
+        self.trace.push(SimEvent::Appended {
+            entry_id,
+            entry: String::from_utf8_lossy(&held.entry).into(),
+        });
+        match entry {
+            PartitionLogEntry::JobPartitionEffects(_)
+                if self
+                    .rng
+                    .chance(self.config.faults.crash_after_effects_append) =>
+            {
+                self.trace.push(SimEvent::Crashed { entry_id });
+                // the reducer never hears back about its append
+                drop(held);
+                self.restart();
+                return Ok(());
+            }
+            PartitionLogEntry::JobEffectResult(evt)
+                if self.rng.chance(self.config.faults.duplicate_completion) =>
+            {
+                let duplicate_id = self.log.inner.append(&held.entry).await?;
+                self.trace.push(SimEvent::DuplicatedCompletion {
+                    entry_id: duplicate_id,
+                    job_id: evt.job_id,
+                    run_id: evt.run_id,
+                });
+            }
+            _ => {}
+        }
+        held.reply.send(entry_id).ok();
+        Ok(())
+    }
+
+    /// Boot the partition from the log alone, like a reboot that lost its
+    /// snapshots.
+    fn start_partition(&mut self) {
+        let pcx = PartitionCtx::new(
+            SIM_PARTITION_ID,
+            Arc::clone(&self.metastore),
+            Arc::clone(&self.log) as Arc<dyn LogStore>,
+            0,
+            Arc::new(NoWasmcloudHost),
+            Arc::clone(&self.host),
+        )
+        .with_clock(Arc::clone(&self.clock) as Arc<dyn Clock>);
+        let state = Arc::new(PartitionWorkingState::default());
+        let worker = crate::partition::start_tokio_worker_with_effect_workers(
+            pcx,
+            Arc::clone(&state),
+            Arc::clone(&self.snapstore),
+            1,
+        );
+        self.partition = Some(SimPartition {
+            state,
+            _worker: worker,
+        });
+    }
+
+    fn restart(&mut self) {
+        // abort the old workers before their held appends are dropped
+        self.partition = None;
+        drop(self.log.take_held());
+        self.restart_pending = false;
+        self.trace.push(SimEvent::Restarted);
+        self.start_partition();
+    }
+
+    fn partition_state(&self) -> &PartitionWorkingState {
+        &self
+            .partition
+            .as_ref()
+            .expect("partition not started")
+            .state
+    }
+}
+
+fn new_in_memory_kv_store() -> Arc<dyn KvStore + Send + Sync> {
+    #[expect(clippy::type_complexity)]
+    let store: Arc<DHashMap<Arc<[u8]>, Arc<[u8]>>> = Arc::new(DHashMap::default());
+    Arc::new(store)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `effects` effect steps, sleeps once then succeeds.
+    struct ScriptedHost {
+        clock: Arc<SimClock>,
+        effects: u64,
+        sleep: Duration,
+    }
+
+    #[async_trait]
+    impl WflowServiceHost for ScriptedHost {
+        type ExtraArgs = ();
+
+        async fn run(
+            &self,
+            _ctx: &RunJobCtx,
+            _job_id: Arc<str>,
+            journal: state::JobState,
+            _session: Option<Box<dyn WflowServiceSession>>,
+            _cancel_token: CancellationToken,
+            _args: &Self::ExtraArgs,
+        ) -> RunJobReply {
+            let now = self.clock.now();
+            let step_id = journal.steps.len() as u64;
+            let result = if step_id < self.effects {
+                job_events::JobRunResult::StepEffect(job_events::JobEffectResult {
+                    step_id,
+                    attempt_id: 0,
+                    start_at: now,
+                    end_at: now,
+                    deets: job_events::JobEffectResultDeets::Success {
+                        value_json: "null".into(),
+                    },
+                    key: None,
+                })
+            } else if step_id == self.effects {
+                job_events::JobRunResult::StepWait(job_events::JobWaitResult {
+                    step_id,
+                    attempt_id: 0,
+                    start_at: now,
+                    deets: job_events::JobWaitResultDeets::Timer {
+                        wait_id: step_id,
+                        fire_at: now.checked_add(self.sleep).expect("ts overflow"),
+                    },
+                    key: None,
+                })
+            } else {
+                job_events::JobRunResult::Success {
+                    value_json: "null".into(),
+                }
+            };
+            RunJobReply {
+                result: Ok(result),
+                session: None,
+            }
+        }
+    }
+
+    async fn simulate(config: SimConfig, jobs: usize) -> SimReport {
+        let clock = Arc::new(SimClock::new(config.start_at));
+        let host = Arc::new(ScriptedHost {
+            clock: Arc::clone(&clock),
+            effects: 3,
+            sleep: Duration::from_secs(60),
+        });
+        let mut sim = Simulation::new(config, clock, host).await.unwrap();
+        for ii in 0..jobs {
+            sim.add_job(format!("sim-job-{ii}"), "scripted", "null")
+                .await
+                .unwrap();
+        }
+        sim.run().await.unwrap()
+    }
+
+    fn all_faults() -> SimFaults {
+        SimFaults {
+            crash_after_effects_append: 0.1,
+            duplicate_completion: 0.2,
+            drop_timer: 0.3,
+        }
+    }
+
+    fn assert_all_succeeded(report: &SimReport) {
+        assert_eq!(
+            report.outcome,
+            SimOutcome::Quiescent,
+            "seed {seed} didn't settle",
+            seed = report.seed
+        );
+        for (job_id, job) in &report.jobs.archive {
+            assert!(
+                matches!(
+                    job.runs.last().map(|run| &run.result),
+                    Some(job_events::JobRunResult::Success { .. })
+                ),
+                "seed {seed}: job {job_id} didn't succeed",
+                seed = report.seed
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_sim_replays_seed_exactly() {
+        let config = SimConfig::new(7).with_faults(all_faults());
+        let first = simulate(config.clone(), 3).await;
+        let second = simulate(config, 3).await;
+        assert_all_succeeded(&first);
+        assert_eq!(first.trace, second.trace);
+    }
+
+    #[tokio::test]
+    async fn test_sim_survives_faults_across_seeds() {
+        utils_rs::testing::setup_tracing_once();
+        let mut faults_seen = (false, false, false);
+        for seed in 0..64 {
+            let report = simulate(SimConfig::new(seed).with_faults(all_faults()), 3).await;
+            assert_all_succeeded(&report);
+            assert_eq!(report.jobs.archive.len(), 3, "seed {seed}");
+            for event in &report.trace {
+                match event {
+                    SimEvent::Crashed { .. } => faults_seen.0 = true,
+                    SimEvent::DuplicatedCompletion { .. } => faults_seen.1 = true,
+                    SimEvent::DroppedTimer { .. } => faults_seen.2 = true,
+                    _ => {}
+                }
+            }
+        }
+        assert_eq!(
+            faults_seen,
+            (true, true, true),
+            "not every fault got exercised"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sim_timers_run_on_virtual_time() {
+        let report = simulate(SimConfig::new(1), 1).await;
+        assert_all_succeeded(&report);
+        assert!(report.trace.contains(&SimEvent::AdvancedClock {
+            to: Timestamp::UNIX_EPOCH
+                .checked_add(Duration::from_secs(60))
+                .unwrap()
+        }));
+    }
+}